@@ -0,0 +1,255 @@
+//! Holder distribution of a mint.
+//!
+//! [`analyze`] takes the largest token accounts from
+//! `getTokenLargestAccounts` for the share of supply the top 10 holders own,
+//! the dev wallet's balance for its share, and, when asked to, every token
+//! account of the mint from `getProgramAccounts` for the number of holders
+//! with a balance. Counting is one heavy request that some RPC providers
+//! refuse, so it's only done when needed.
+//!
+//! The liquidity vault, the bonding curve's or the pool's, holds the unsold
+//! supply rather than a holder's and is passed in to be left out. The stats
+//! are cached per mint for [`CACHE_TTL`], the safety checks and `/info`
+//! asking about the same token in a row share one analysis.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{LazyLock, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+};
+use solana_sdk::pubkey::Pubkey;
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::{
+    extension::StateWithExtensions,
+    state::{Account, Mint},
+};
+
+/// Holders counted in the concentration
+pub const TOP_HOLDERS: usize = 10;
+
+/// How long an analysis is reused
+pub const CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Offset of the amount in a token account, after the mint and the owner
+const AMOUNT_OFFSET: usize = 64;
+
+/// Size of an SPL Token account, Token-2022 ones vary with their extensions
+const TOKEN_ACCOUNT_LEN: u64 = 165;
+
+static CACHE: LazyLock<RwLock<HashMap<Pubkey, Cached>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+struct Cached {
+    at: Instant,
+    exclude: Option<Pubkey>,
+    dev: Option<Pubkey>,
+    stats: HolderStats,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HolderStats {
+    pub mint: Pubkey,
+    pub supply: u64,
+    /// Share of supply the [`TOP_HOLDERS`] largest holders own, in percent
+    pub top_holders_pct: f64,
+    /// Token accounts with a balance, `None` unless counted
+    pub holders: Option<u64>,
+    /// Share of supply the dev wallet owns, in percent, `None` without a dev
+    pub dev_pct: Option<f64>,
+}
+
+impl fmt::Display for HolderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "top {} holders own {:.1}%",
+            TOP_HOLDERS, self.top_holders_pct
+        )?;
+        if let Some(holders) = self.holders {
+            write!(f, ", {} holders", holders)?;
+        }
+        if let Some(pct) = self.dev_pct {
+            write!(f, ", dev owns {:.1}%", pct)?;
+        }
+        Ok(())
+    }
+}
+
+fn pct(amount: u64, supply: u64) -> f64 {
+    if supply == 0 {
+        return 0.0;
+    }
+    amount as f64 / supply as f64 * 100.0
+}
+
+/// Sum of the `TOP_HOLDERS` largest of `balances`, address and amount, other
+/// than `exclude`
+fn top_holders(balances: &[(Pubkey, u64)], exclude: Option<&Pubkey>) -> u64 {
+    balances
+        .iter()
+        .filter(|(address, _)| Some(address) != exclude)
+        .take(TOP_HOLDERS)
+        .map(|(_, amount)| amount)
+        .sum()
+}
+
+async fn largest_accounts(client: &RpcClient, mint: &Pubkey) -> Result<Vec<(Pubkey, u64)>> {
+    client
+        .get_token_largest_accounts(mint)
+        .await?
+        .iter()
+        .map(|holder| Ok((holder.address.parse()?, holder.amount.amount.parse()?)))
+        .collect()
+}
+
+/// Token accounts of `mint` with a balance, other than `exclude`
+async fn count_holders(
+    client: &RpcClient,
+    token_program: &Pubkey,
+    mint: &Pubkey,
+    exclude: Option<&Pubkey>,
+) -> Result<u64> {
+    let mut filters = vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+        0,
+        mint.as_ref(),
+    ))];
+    if *token_program == spl_token::ID {
+        filters.push(RpcFilterType::DataSize(TOKEN_ACCOUNT_LEN));
+    }
+    // 只取余额字段，几万个账户也不大
+    let config = RpcProgramAccountsConfig {
+        filters: Some(filters),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            data_slice: Some(UiDataSliceConfig {
+                offset: AMOUNT_OFFSET,
+                length: 8,
+            }),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let accounts = client
+        .get_program_accounts_with_config(token_program, config)
+        .await?;
+    Ok(accounts
+        .iter()
+        .filter(|(address, _)| Some(address) != exclude)
+        .filter(|(_, account)| account.data.get(..8).is_some_and(|amount| amount != [0; 8]))
+        .count() as u64)
+}
+
+/// Balance of `owner`'s associated account of `mint`, 0 without one
+async fn wallet_balance(
+    client: &RpcClient,
+    token_program: &Pubkey,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> Result<u64> {
+    let ata = get_associated_token_address_with_program_id(owner, mint, token_program);
+    let account = client
+        .get_account_with_commitment(&ata, client.commitment())
+        .await?
+        .value;
+    match account {
+        Some(account) => Ok(StateWithExtensions::<Account>::unpack(&account.data)?
+            .base
+            .amount),
+        None => Ok(0),
+    }
+}
+
+/// Analyzes the holders of `mint`, leaving out the liquidity vault `exclude`
+///
+/// `dev` is the creator wallet whose share is reported, the holders are only
+/// counted with `count`. A cached analysis of the same vault and dev is
+/// returned while it's fresh, counted if `count`.
+pub async fn analyze(
+    client: &RpcClient,
+    mint: &Pubkey,
+    exclude: Option<Pubkey>,
+    dev: Option<&Pubkey>,
+    count: bool,
+) -> Result<HolderStats> {
+    if let Some(cached) = CACHE.read().unwrap().get(mint) {
+        if cached.at.elapsed() < CACHE_TTL
+            && cached.exclude == exclude
+            && cached.dev.as_ref() == dev
+            && (!count || cached.stats.holders.is_some())
+        {
+            return Ok(cached.stats.clone());
+        }
+    }
+
+    let (account, largest) = tokio::try_join!(
+        async { Ok::<_, anyhow::Error>(client.get_account(mint).await?) },
+        largest_accounts(client, mint)
+    )?;
+    let token_program = account.owner;
+    let supply = StateWithExtensions::<Mint>::unpack(&account.data)?
+        .base
+        .supply;
+
+    let holders = if count {
+        Some(count_holders(client, &token_program, mint, exclude.as_ref()).await?)
+    } else {
+        None
+    };
+    let dev_pct = match dev {
+        Some(dev) => Some(pct(
+            wallet_balance(client, &token_program, mint, dev).await?,
+            supply,
+        )),
+        None => None,
+    };
+    let stats = HolderStats {
+        mint: *mint,
+        supply,
+        top_holders_pct: pct(top_holders(&largest, exclude.as_ref()), supply),
+        holders,
+        dev_pct,
+    };
+    CACHE.write().unwrap().insert(
+        *mint,
+        Cached {
+            at: Instant::now(),
+            exclude,
+            dev: dev.copied(),
+            stats: stats.clone(),
+        },
+    );
+    Ok(stats)
+}
+
+#[test]
+fn test_top_holders() {
+    let vault = Pubkey::new_unique();
+    let mut balances = vec![(vault, 800)];
+    balances.extend((0..15).map(|i| (Pubkey::new_unique(), 20 - i)));
+
+    // 金库不算持有者，取后面最大的10个
+    assert_eq!(top_holders(&balances, Some(&vault)), (11..=20).sum::<u64>());
+    assert_eq!(top_holders(&balances, None), 800 + (12..=20).sum::<u64>());
+    assert_eq!(pct(155, 1000), 15.5);
+    assert_eq!(pct(1, 0), 0.0);
+
+    let stats = HolderStats {
+        top_holders_pct: 15.5,
+        holders: Some(42),
+        dev_pct: Some(3.0),
+        ..HolderStats::default()
+    };
+    assert_eq!(
+        stats.to_string(),
+        "top 10 holders own 15.5%, 42 holders, dev owns 3.0%"
+    );
+}
@@ -0,0 +1,3 @@
+//! On-chain analysis of tokens, beyond the pass/fail of the safety checks.
+
+pub mod holders;
@@ -0,0 +1,78 @@
+//! Server-sent events API for the monitors' detections.
+//!
+//! `GET /events` streams every `MonitorEvent` as JSON, with the event type as
+//! the SSE event name. Clients only receive events detected after they
+//! connect. `GET /status` reports how many slots each monitor is behind the
+//! node. The server is only started when `EVENTS_ADDR` is set
+//! (e.g. `0.0.0.0:8080`).
+
+use std::{env, net::SocketAddr};
+
+use anyhow::Result;
+use axum::{
+    response::sse::{Event, KeepAlive, Sse},
+    routing::get,
+    Router,
+};
+use futures_util::{stream, Stream};
+use tokio::{net::TcpListener, sync::broadcast::error::RecvError, task::JoinHandle};
+use tracing::{error, info, warn};
+
+use crate::{
+    monitor::{
+        events::{self, MonitorEvent},
+        lag::monitor_lags,
+    },
+    new_client,
+};
+
+fn to_sse_event(event: &MonitorEvent) -> Result<Event, axum::Error> {
+    Event::default().event(event.event_type()).json_data(event)
+}
+
+async fn events_handler() -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let stream = stream::unfold(events::subscribe(), |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((to_sse_event(&event), receiver)),
+                // 客户端太慢，跳过丢失的事件
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("events client lagged, skipped {} events", skipped);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn status_handler() -> String {
+    match monitor_lags(&new_client()).await {
+        Ok(lags) if lags.is_empty() => "no blocks received yet\n".to_string(),
+        Ok(lags) => lags.iter().map(|lag| format!("{}\n", lag)).collect(),
+        Err(e) => format!("failed to get current slot {:?}\n", e),
+    }
+}
+
+/// Serves `/events` and `/status` on `addr` until the task is dropped
+pub async fn serve(addr: SocketAddr) -> Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(addr).await?;
+    let app = Router::new()
+        .route("/events", get(events_handler))
+        .route("/status", get(status_handler));
+    info!("events api listening on {}", addr);
+    Ok(tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            error!("events api stopped {:?}", e);
+        }
+    }))
+}
+
+/// Starts the events API if `EVENTS_ADDR` is set
+pub async fn serve_from_env() -> Result<Option<JoinHandle<()>>> {
+    dotenv::dotenv().ok();
+    match env::var("EVENTS_ADDR") {
+        Ok(addr) => Ok(Some(serve(addr.parse()?).await?)),
+        Err(_) => Ok(None),
+    }
+}
@@ -0,0 +1,135 @@
+//! Historical blocks to replay.
+//!
+//! Blocks are fetched with `getBlock`, in the encoding the monitors subscribe
+//! with, or read back from a dump written while fetching: one [`StoredBlock`]
+//! JSON object per line.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcBlockConfig};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_transaction_status_client_types::{
+    TransactionDetails, UiConfirmedBlock, UiTransactionEncoding,
+};
+use tracing::info;
+
+use crate::rpc::retry::with_retry;
+
+/// `getBlocks` 单次请求的最大范围
+const MAX_SLOT_RANGE: u64 = 500_000;
+
+/// A block with its slot, which the block itself doesn't carry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredBlock {
+    pub slot: u64,
+    pub block: UiConfirmedBlock,
+}
+
+/// Reads the blocks dumped at `path`, in file order
+pub fn read_blocks(path: impl AsRef<Path>) -> Result<impl Iterator<Item = Result<StoredBlock>>> {
+    let path = path.as_ref().to_path_buf();
+    let reader = BufReader::new(
+        File::open(&path).map_err(|e| anyhow!("failed to open {}: {}", path.display(), e))?,
+    );
+    Ok(reader
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(move |(i, line)| {
+            serde_json::from_str(&line?)
+                .map_err(|e| anyhow!("invalid block at {}:{}: {}", path.display(), i + 1, e))
+        }))
+}
+
+/// Appends blocks to a dump readable by [`read_blocks`]
+pub struct BlockWriter {
+    writer: BufWriter<File>,
+}
+
+impl BlockWriter {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn write(&mut self, block: &StoredBlock) -> Result<()> {
+        writeln!(self.writer, "{}", serde_json::to_string(block)?)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// Slots between `start` and `end` inclusive that have a block
+pub async fn block_slots(client: &RpcClient, start: u64, end: u64) -> Result<Vec<u64>> {
+    if end < start {
+        return Err(anyhow!("end slot {} is before start slot {}", end, start));
+    }
+    let mut slots = vec![];
+    let mut from = start;
+    while from <= end {
+        let to = end.min(from + MAX_SLOT_RANGE - 1);
+        slots.extend(with_retry(|| client.get_blocks(from, Some(to))).await?);
+        from = to + 1;
+    }
+    info!("{} blocks between slots {} and {}", slots.len(), start, end);
+    Ok(slots)
+}
+
+/// Fetches the block of `slot` with every transaction
+pub async fn fetch_block(client: &RpcClient, slot: u64) -> Result<StoredBlock> {
+    let config = RpcBlockConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        transaction_details: Some(TransactionDetails::Full),
+        rewards: Some(false),
+        commitment: Some(CommitmentConfig::confirmed()),
+        max_supported_transaction_version: Some(0),
+    };
+    let block = with_retry(|| client.get_block_with_config(slot, config)).await?;
+    Ok(StoredBlock { slot, block })
+}
+
+#[test]
+fn test_block_dump_round_trip() {
+    use solana_sdk::pubkey::Pubkey;
+
+    let path = std::env::temp_dir().join(format!("blocks-{}.jsonl", Pubkey::new_unique()));
+    let block = |slot| StoredBlock {
+        slot,
+        block: UiConfirmedBlock {
+            previous_blockhash: String::new(),
+            blockhash: format!("hash-{}", slot),
+            parent_slot: slot - 1,
+            transactions: Some(vec![]),
+            signatures: None,
+            rewards: None,
+            num_reward_partitions: None,
+            block_time: Some(1_700_000_000),
+            block_height: Some(slot),
+        },
+    };
+    let mut writer = BlockWriter::create(&path).unwrap();
+    writer.write(&block(10)).unwrap();
+    writer.write(&block(12)).unwrap();
+    writer.flush().unwrap();
+
+    let blocks = read_blocks(&path)
+        .unwrap()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(
+        blocks.iter().map(|b| b.slot).collect::<Vec<_>>(),
+        vec![10, 12]
+    );
+    assert_eq!(blocks[1].block.blockhash, "hash-12");
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,526 @@
+//! Replay of historical blocks through the sniper and exits.
+//!
+//! Each block goes through the create monitor's `process_block`, and the
+//! creates passing the `SNIPER_*` filters are bought with simulated fills.
+//! The bonding curves are rebuilt from the Pump.fun buys and sells in the
+//! blocks, the bot's own fills included. After each block the open positions
+//! are valued on their curve and sold once they cross the `EXIT_*`
+//! thresholds, or held to the end without `EXITS_ENABLED`. Fills go through
+//! an in-memory [`Portfolio`], so PnL is computed the same way as live.
+//!
+//! The bot trades at the end of a block, after every transaction in it. A
+//! buy fails if the curve moved more than the sniper's slippage since the
+//! create was detected. The Pump.fun fee is taken on both sides, and every
+//! transaction pays the base fee plus a priority fee. Safety checks need live
+//! accounts and are not applied. Curves created before the first block are
+//! unknown, and trades made through other programs are not seen.
+//!
+//! - `BACKTEST_CURVE_FEE_BPS`: Pump.fun fee, default 100
+//! - `BACKTEST_PRIORITY_FEE_LAMPORTS`: priority fee per transaction, default 0
+//! - `BACKTEST_FILL_DELAY_SLOTS`: slots between a create and its buy,
+//!   default 0
+
+pub mod blocks;
+
+use std::{collections::HashMap, fmt, path::Path};
+
+use anyhow::Result;
+use serde::Serialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{fee::FeeStructure, native_token::lamports_to_sol, pubkey::Pubkey};
+use tracing::{debug, info};
+
+use crate::{
+    monitor::{
+        events::TokenCreateEvent,
+        token_create::{self, CurveTrade},
+    },
+    portfolio::{Fill, Portfolio, Position, Side},
+    pumpfun::accounts::BondingCurveAccount,
+    strategy::{exits::ExitConfig, parse_env, sniper::SniperConfig},
+};
+
+use blocks::{BlockWriter, StoredBlock};
+
+const DEFAULT_CURVE_FEE_BPS: u64 = 100;
+
+#[derive(Debug, Clone)]
+pub struct BacktestConfig {
+    pub sniper: SniperConfig,
+    /// Positions are held to the end without one
+    pub exits: Option<ExitConfig>,
+    /// Pump.fun fee, in basis points
+    pub curve_fee_bps: u64,
+    /// Priority fee per transaction, in lamports
+    pub priority_fee: u64,
+    pub fill_delay_slots: u64,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        Self {
+            sniper: SniperConfig::default(),
+            exits: None,
+            curve_fee_bps: DEFAULT_CURVE_FEE_BPS,
+            priority_fee: 0,
+            fill_delay_slots: 0,
+        }
+    }
+}
+
+impl BacktestConfig {
+    /// Reads the `SNIPER_*`, `EXIT_*` and `BACKTEST_*` variables
+    pub fn from_env() -> Result<Self> {
+        dotenv::dotenv().ok();
+        let default = Self::default();
+        Ok(Self {
+            sniper: SniperConfig::read_env()?,
+            exits: ExitConfig::from_env()?,
+            curve_fee_bps: parse_env("BACKTEST_CURVE_FEE_BPS")?.unwrap_or(default.curve_fee_bps),
+            priority_fee: parse_env("BACKTEST_PRIORITY_FEE_LAMPORTS")?
+                .unwrap_or(default.priority_fee),
+            fill_delay_slots: parse_env("BACKTEST_FILL_DELAY_SLOTS")?
+                .unwrap_or(default.fill_delay_slots),
+        })
+    }
+}
+
+/// A simulated fill
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestTrade {
+    pub slot: u64,
+    pub mint: String,
+    pub side: Side,
+    pub token_amount: u64,
+    /// SOL paid or received, in lamports before network fees
+    pub sol_amount: u64,
+    /// Network fees, in lamports
+    pub fee: u64,
+    /// Profit realized by a sell, in lamports
+    pub realized_pnl: Option<i64>,
+    /// Why the trade was made
+    pub reason: String,
+}
+
+impl fmt::Display for BacktestTrade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "slot {} {:?} {} tokens of {} for {:.4} SOL ({})",
+            self.slot,
+            self.side,
+            self.token_amount,
+            self.mint,
+            lamports_to_sol(self.sol_amount),
+            self.reason
+        )?;
+        if let Some(pnl) = self.realized_pnl {
+            write!(f, ", pnl {:+.4} SOL", pnl as f64 / 1e9)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BacktestReport {
+    pub blocks: u64,
+    pub trades: Vec<BacktestTrade>,
+    /// Buys given up because the curve moved past the slippage
+    pub missed_buys: usize,
+    /// Positions held at the end, with their value on the last curve state
+    pub open: Vec<(Position, u64)>,
+    pub realized_pnl: i64,
+}
+
+impl BacktestReport {
+    /// Value of the open positions over their cost, in lamports
+    pub fn unrealized_pnl(&self) -> i64 {
+        self.open
+            .iter()
+            .map(|(position, value)| *value as i64 - position.cost_basis as i64)
+            .sum()
+    }
+
+    pub fn total_pnl(&self) -> i64 {
+        self.realized_pnl + self.unrealized_pnl()
+    }
+}
+
+impl fmt::Display for BacktestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for trade in &self.trades {
+            writeln!(f, "{}", trade)?;
+        }
+        let sol = |lamports: i64| lamports as f64 / 1e9;
+        write!(
+            f,
+            "{} blocks, {} trades, {} missed buys, {} open positions: realized {:+.4} SOL, unrealized {:+.4} SOL, total {:+.4} SOL",
+            self.blocks,
+            self.trades.len(),
+            self.missed_buys,
+            self.open.len(),
+            sol(self.realized_pnl),
+            sol(self.unrealized_pnl()),
+            sol(self.total_pnl())
+        )
+    }
+}
+
+/// Buys `token_amount` on `curve`, returning the SOL paid before fees
+fn apply_buy(curve: &mut BondingCurveAccount, token_amount: u64) -> Option<u64> {
+    let token_amount = token_amount.min(curve.real_token_reserves);
+    curve.apply_buy(token_amount).ok()
+}
+
+/// Sells `token_amount` on `curve`, returning the SOL received before fees
+fn apply_sell(curve: &mut BondingCurveAccount, token_amount: u64) -> Option<u64> {
+    let sol = curve
+        .get_sell_price(token_amount, 0)
+        .ok()?
+        .min(curve.real_sol_reserves);
+    curve.virtual_token_reserves += token_amount;
+    curve.virtual_sol_reserves -= sol;
+    curve.real_token_reserves += token_amount;
+    curve.real_sol_reserves -= sol;
+    Some(sol)
+}
+
+/// A buy waiting for its fill slot
+struct PendingBuy {
+    mint: Pubkey,
+    detected_slot: u64,
+    /// Tokens quoted when the create was detected
+    quoted: u64,
+}
+
+pub struct Backtest {
+    config: BacktestConfig,
+    curves: HashMap<Pubkey, BondingCurveAccount>,
+    pending: Vec<PendingBuy>,
+    portfolio: Portfolio,
+    /// mint -> 最高估值
+    peaks: HashMap<Pubkey, u64>,
+    trades: Vec<BacktestTrade>,
+    missed_buys: usize,
+    blocks: u64,
+}
+
+impl Backtest {
+    pub fn new(config: BacktestConfig) -> Self {
+        Self {
+            config,
+            curves: HashMap::new(),
+            pending: vec![],
+            portfolio: Portfolio::in_memory(),
+            peaks: HashMap::new(),
+            trades: vec![],
+            missed_buys: 0,
+            blocks: 0,
+        }
+    }
+
+    /// Replays the blocks dumped at `path`
+    pub fn replay_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        for block in blocks::read_blocks(path)? {
+            self.process(block?)?;
+        }
+        Ok(())
+    }
+
+    /// Replays the blocks between `start` and `end` inclusive, also dumping
+    /// them to `save` if set
+    pub async fn replay_rpc(
+        &mut self,
+        client: &RpcClient,
+        start: u64,
+        end: u64,
+        save: Option<&Path>,
+    ) -> Result<()> {
+        let mut writer = save.map(BlockWriter::create).transpose()?;
+        for slot in blocks::block_slots(client, start, end).await? {
+            let block = blocks::fetch_block(client, slot).await?;
+            if let Some(writer) = &mut writer {
+                writer.write(&block)?;
+            }
+            self.process(block)?;
+        }
+        if let Some(writer) = &mut writer {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Replays one block, blocks must come in slot order
+    pub fn process(&mut self, block: StoredBlock) -> Result<()> {
+        let StoredBlock { slot, block } = block;
+        let block_time = block.block_time.unwrap_or_default().max(0) as u64;
+        let trades = token_create::curve_trades(&block);
+        let creates = token_create::process_block(block)?;
+        self.apply(slot, block_time, &trades, creates)
+    }
+
+    fn apply(
+        &mut self,
+        slot: u64,
+        block_time: u64,
+        trades: &[CurveTrade],
+        creates: Vec<TokenCreateEvent>,
+    ) -> Result<()> {
+        self.blocks += 1;
+        for trade in trades {
+            match *trade {
+                CurveTrade::Create { mint, .. } => {
+                    self.curves.insert(mint, BondingCurveAccount::fresh());
+                }
+                CurveTrade::Buy {
+                    mint, token_amount, ..
+                } => {
+                    if let Some(curve) = self.curves.get_mut(&mint) {
+                        apply_buy(curve, token_amount);
+                    }
+                }
+                CurveTrade::Sell {
+                    mint, token_amount, ..
+                } => {
+                    if let Some(curve) = self.curves.get_mut(&mint) {
+                        apply_sell(curve, token_amount);
+                    }
+                }
+            }
+        }
+
+        for event in creates {
+            if let Err(reason) = self.config.sniper.check(&event) {
+                debug!("not sniping {}: {}", event.mint, reason);
+                continue;
+            }
+            let Ok(mint) = event.mint.parse::<Pubkey>() else {
+                continue;
+            };
+            let Some(quoted) = self.curves.get(&mint).and_then(|c| self.buy_quote(c)) else {
+                continue;
+            };
+            self.pending.push(PendingBuy {
+                mint,
+                detected_slot: slot,
+                quoted,
+            });
+        }
+
+        let delay = self.config.fill_delay_slots;
+        let (due, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition::<Vec<_>, _>(|buy| buy.detected_slot + delay <= slot);
+        self.pending = pending;
+        for buy in due {
+            self.fill_buy(slot, block_time, buy)?;
+        }
+        self.check_exits(slot, block_time)
+    }
+
+    /// Network fees of one transaction
+    fn network_fee(&self) -> u64 {
+        FeeStructure::default().lamports_per_signature + self.config.priority_fee
+    }
+
+    /// Tokens the sniper's buy gets on `curve`, the fee taken out of the spend
+    fn buy_quote(&self, curve: &BondingCurveAccount) -> Option<u64> {
+        curve
+            .get_buy_price_with_fee(self.config.sniper.buy_amount, self.config.curve_fee_bps)
+            .ok()
+    }
+
+    fn record(&mut self, slot: u64, block_time: u64, fill: Fill, reason: String) -> Result<()> {
+        let before = self
+            .portfolio
+            .position(&fill.mint)
+            .map_or(0, |p| p.realized_pnl);
+        let mut trade = BacktestTrade {
+            slot,
+            mint: fill.mint.clone(),
+            side: fill.side,
+            token_amount: fill.token_amount,
+            sol_amount: fill.sol_amount,
+            fee: fill.fee,
+            realized_pnl: None,
+            reason,
+        };
+        self.portfolio.record(Fill {
+            timestamp: block_time,
+            ..fill
+        })?;
+        if trade.side == Side::Sell {
+            let after = self
+                .portfolio
+                .position(&trade.mint)
+                .map_or(0, |p| p.realized_pnl);
+            trade.realized_pnl = Some(after - before);
+        }
+        info!("{}", trade);
+        self.trades.push(trade);
+        Ok(())
+    }
+
+    fn fill(&self, mint: &Pubkey, side: Side, token_amount: u64, sol_amount: u64) -> Fill {
+        Fill {
+            mint: mint.to_string(),
+            side,
+            venue: "pumpfun".to_string(),
+            strategy: "backtest".to_string(),
+            token_amount,
+            sol_amount,
+            fee: self.network_fee(),
+            signature: String::new(),
+            timestamp: 0,
+        }
+    }
+
+    fn fill_buy(&mut self, slot: u64, block_time: u64, buy: PendingBuy) -> Result<()> {
+        let Some(tokens) = self.curves.get(&buy.mint).and_then(|c| self.buy_quote(c)) else {
+            self.missed_buys += 1;
+            return Ok(());
+        };
+        let min_out =
+            buy.quoted as u128 * (100 - self.config.sniper.slippage.min(100)) as u128 / 100;
+        if tokens == 0 || (tokens as u128) < min_out {
+            debug!(
+                "missed {}, got {} of {} tokens",
+                buy.mint, tokens, buy.quoted
+            );
+            self.missed_buys += 1;
+            return Ok(());
+        }
+        if let Some(curve) = self.curves.get_mut(&buy.mint) {
+            apply_buy(curve, tokens);
+        }
+        let fill = self.fill(&buy.mint, Side::Buy, tokens, self.config.sniper.buy_amount);
+        self.record(slot, block_time, fill, "snipe".to_string())
+    }
+
+    fn check_exits(&mut self, slot: u64, block_time: u64) -> Result<()> {
+        let Some(exits) = self.config.exits.clone() else {
+            return Ok(());
+        };
+        for position in self.portfolio.open_positions() {
+            let Ok(mint) = position.mint.parse::<Pubkey>() else {
+                continue;
+            };
+            let Some(curve) = self.curves.get_mut(&mint) else {
+                continue;
+            };
+            // 迁移后无法在曲线上卖出
+            let Ok(value) = curve.get_sell_price(position.token_amount, self.config.curve_fee_bps)
+            else {
+                continue;
+            };
+            let peak = self.peaks.entry(mint).or_insert(0);
+            *peak = (*peak).max(value);
+            let Some(reason) = exits.check(position.cost_basis, value, *peak) else {
+                continue;
+            };
+            apply_sell(curve, position.token_amount);
+            self.peaks.remove(&mint);
+            let fill = self.fill(&mint, Side::Sell, position.token_amount, value);
+            self.record(slot, block_time, fill, reason.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Trades so far, the open positions valued on their last curve state
+    pub fn report(&self) -> BacktestReport {
+        let open = self
+            .portfolio
+            .open_positions()
+            .into_iter()
+            .map(|position| {
+                let value = position
+                    .mint
+                    .parse::<Pubkey>()
+                    .ok()
+                    .and_then(|mint| self.curves.get(&mint))
+                    .map_or(0, |curve| {
+                        // 已完成的曲线按最后的储备估值
+                        let curve = BondingCurveAccount {
+                            complete: false,
+                            ..curve.clone()
+                        };
+                        curve
+                            .get_sell_price(position.token_amount, self.config.curve_fee_bps)
+                            .unwrap_or_default()
+                    });
+                (position, value)
+            })
+            .collect();
+        BacktestReport {
+            blocks: self.blocks,
+            trades: self.trades.clone(),
+            missed_buys: self.missed_buys,
+            open,
+            realized_pnl: self.portfolio.realized_pnl(),
+        }
+    }
+}
+
+#[test]
+fn test_backtest_snipe_and_take_profit() {
+    let config = BacktestConfig {
+        sniper: SniperConfig {
+            buy_amount: 1_000_000_000,
+            ..SniperConfig::default()
+        },
+        exits: Some(ExitConfig {
+            take_profit_pct: Some(50.0),
+            ..ExitConfig::default()
+        }),
+        fill_delay_slots: 1,
+        ..BacktestConfig::default()
+    };
+    let mut backtest = Backtest::new(config);
+    let (mint, user) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let create = TokenCreateEvent {
+        signature: String::new(),
+        name: "Moon Cat".to_string(),
+        symbol: "MCAT".to_string(),
+        uri: String::new(),
+        mint: mint.to_string(),
+        bonding_curve: String::new(),
+        associated_bonding_curve: String::new(),
+        creator: user.to_string(),
+        dev_buy: None,
+        dev_alert: false,
+        creator_profile: None,
+        received_at: None,
+    };
+
+    backtest
+        .apply(10, 0, &[CurveTrade::Create { mint, user }], vec![create])
+        .unwrap();
+    assert!(backtest.report().trades.is_empty());
+
+    // 成交前有人小额买入，仍在滑点内
+    let buy = |token_amount| CurveTrade::Buy {
+        mint,
+        user: Pubkey::new_unique(),
+        token_amount,
+        sol_limit: u64::MAX,
+    };
+    backtest
+        .apply(11, 0, &[buy(1_000_000_000_000)], vec![])
+        .unwrap();
+    let report = backtest.report();
+    assert_eq!(report.trades.len(), 1);
+    assert_eq!(report.trades[0].side, Side::Buy);
+    assert_eq!(report.open.len(), 1);
+
+    // 大额买入推高价格，触发止盈
+    backtest
+        .apply(12, 0, &[buy(300_000_000_000_000)], vec![])
+        .unwrap();
+    let report = backtest.report();
+    assert_eq!(report.trades.len(), 2);
+    assert!(report.trades[1].reason.starts_with("take profit"));
+    assert!(report.open.is_empty());
+    assert!(report.realized_pnl > 0);
+    assert_eq!(report.trades[1].realized_pnl, Some(report.realized_pnl));
+    assert_eq!(report.total_pnl(), report.realized_pnl);
+}
@@ -0,0 +1,447 @@
+//! Runtime configuration.
+//!
+//! Connection, wallet and fee settings are loaded into a `BotConfig` from the
+//! TOML file at `BOT_CONFIG` (default `config.toml`, see
+//! `config.example.toml`). Every key can be overridden by the environment
+//! variable of the same name in upper case, e.g. `RPC_URL` or `UNIT_PRICE`.
+//! The file is optional, so a `.env` alone still works. The result is
+//! validated by `init` at startup, instead of panicking deep inside a task.
+//!
+//! Program IDs default to their mainnet values and can be overridden to point
+//! the bot at devnet or a forked validator:
+//!
+//! - `PUMPFUN_PROGRAM_ID`
+//! - `PUMPFUN_MIGRATOR_ID`
+//! - `PUMPFUN_FEE_RECIPIENT`
+//! - `PUMPFUN_EVENT_AUTHORITY`
+//! - `PUMPSWAP_PROGRAM_ID`
+//! - `AMM_PROGRAM_ID`
+//! - `RAYDIUM_CPMM_PROGRAM_ID`
+//! - `ORCA_WHIRLPOOL_PROGRAM_ID`
+//!
+//! Telegram messages are rendered from MarkdownV2 templates (see
+//! `monitor::markdown`), overridable with `CREATE_MESSAGE_TEMPLATE` and
+//! `MIGRATION_MESSAGE_TEMPLATE`. A literal `\n` in the env value is a newline.
+
+use std::{
+    env, fmt, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
+};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use solana_sdk::{bs58, pubkey::Pubkey, signature::Keypair};
+
+use crate::constants::accounts;
+
+static BOT_CONFIG: OnceLock<BotConfig> = OnceLock::new();
+static PROGRAM_IDS: OnceLock<ProgramIds> = OnceLock::new();
+
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// Most compute units a transaction can request
+pub(crate) const MAX_UNIT_LIMIT: u32 = 1_400_000;
+static MESSAGE_TEMPLATES: OnceLock<MessageTemplates> = OnceLock::new();
+
+/// Default token create message
+pub const DEFAULT_CREATE_TEMPLATE: &str = "*🚀 Token Create 🚀*
+```
+name                     : {name}
+symbol                   : {symbol}
+uri                      : {uri}
+mint                     : {mint}
+bondingCurve             : {bonding_curve}
+associatedBondingCurve   : {associated_bonding_curve}
+creator                  : {creator}
+devBuySol                : {dev_buy_sol}
+devBuyTokens             : {dev_buy_tokens}
+devBuyPct                : {dev_buy_pct}
+creatorProfile           : {creator_profile}
+{alert}```";
+
+/// Default token migration message
+pub const DEFAULT_MIGRATION_TEMPLATE: &str = "*🚀 Token Migration 🚀*
+```
+venue:               {venue}
+signature:           {signature}
+coin mint:           {coin_mint}
+pc mint:             {pc_mint}
+pool id:             {pool_id}
+```";
+
+/// Connection, wallet and fee settings
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BotConfig {
+    /// HTTP RPC endpoint
+    pub rpc_url: String,
+    /// Websocket RPC endpoint
+    pub ws_rpc_url: String,
+    /// Base58 secret key of the trading wallet
+    pub pk: Option<String>,
+    /// Priority fee in micro-lamports per compute unit
+    pub unit_price: u64,
+    /// Compute unit limit of swap transactions
+    pub unit_limit: u32,
+    /// Simulates swaps first and sets their compute unit limit to the units
+    /// consumed times this, instead of `unit_limit`
+    pub unit_limit_margin: Option<f64>,
+    /// Looks the output token account up before swapping and creates it only
+    /// if missing, instead of always adding the idempotent create
+    pub ata_precheck: bool,
+    /// gmgn.ai session cookie, for the twitter strategy
+    pub gmgn_cookie: Option<String>,
+    /// Twitter API bearer token, for the twitter strategy
+    pub app_bearer_token: Option<String>,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            rpc_url: String::new(),
+            ws_rpc_url: String::new(),
+            pk: None,
+            unit_price: 20000,
+            unit_limit: 200_000,
+            unit_limit_margin: None,
+            ata_precheck: false,
+            gmgn_cookie: None,
+            app_bearer_token: None,
+        }
+    }
+}
+
+// 不打印密钥
+impl fmt::Debug for BotConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let redacted = |v: &Option<String>| v.as_ref().map(|_| "<redacted>");
+        f.debug_struct("BotConfig")
+            .field("rpc_url", &self.rpc_url)
+            .field("ws_rpc_url", &self.ws_rpc_url)
+            .field("pk", &redacted(&self.pk))
+            .field("unit_price", &self.unit_price)
+            .field("unit_limit", &self.unit_limit)
+            .field("unit_limit_margin", &self.unit_limit_margin)
+            .field("ata_precheck", &self.ata_precheck)
+            .field("gmgn_cookie", &redacted(&self.gmgn_cookie))
+            .field("app_bearer_token", &redacted(&self.app_bearer_token))
+            .finish()
+    }
+}
+
+impl BotConfig {
+    /// Loads the file at `BOT_CONFIG`, then applies the environment overrides
+    pub fn from_env() -> Result<Self> {
+        dotenv::dotenv().ok();
+        let path = env::var("BOT_CONFIG")
+            .map(PathBuf::from)
+            .unwrap_or(PathBuf::from(DEFAULT_CONFIG_PATH));
+        Self::load(&path)
+    }
+
+    /// Loads `path` if it exists, applies the environment overrides and
+    /// validates the result
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut config: Self = match fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text)
+                .map_err(|e| anyhow!("invalid config {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(anyhow!("failed to read {}: {}", path.display(), e)),
+        };
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_env(&mut self) -> Result<()> {
+        override_from_env("RPC_URL", &mut self.rpc_url)?;
+        override_from_env("WS_RPC_URL", &mut self.ws_rpc_url)?;
+        override_option_from_env("PK", &mut self.pk);
+        override_from_env("UNIT_PRICE", &mut self.unit_price)?;
+        override_from_env("UNIT_LIMIT", &mut self.unit_limit)?;
+        override_parsed_option_from_env("UNIT_LIMIT_MARGIN", &mut self.unit_limit_margin)?;
+        override_from_env("ATA_PRECHECK", &mut self.ata_precheck)?;
+        override_option_from_env("GMGN_COOKIE", &mut self.gmgn_cookie);
+        override_option_from_env("APP_BEARER_TOKEN", &mut self.app_bearer_token);
+        Ok(())
+    }
+
+    /// Checks the endpoints, compute budget and wallet key
+    pub fn validate(&self) -> Result<()> {
+        if !(self.rpc_url.starts_with("http://") || self.rpc_url.starts_with("https://")) {
+            return Err(anyhow!(
+                "rpc_url must be an http(s) url, got {:?}",
+                self.rpc_url
+            ));
+        }
+        if !(self.ws_rpc_url.starts_with("ws://") || self.ws_rpc_url.starts_with("wss://")) {
+            return Err(anyhow!(
+                "ws_rpc_url must be a ws(s) url, got {:?}",
+                self.ws_rpc_url
+            ));
+        }
+        if self.unit_limit == 0 || self.unit_limit > MAX_UNIT_LIMIT {
+            return Err(anyhow!(
+                "unit_limit must be between 1 and {}, got {}",
+                MAX_UNIT_LIMIT,
+                self.unit_limit
+            ));
+        }
+        if let Some(margin) = self.unit_limit_margin {
+            if !(margin >= 1.0 && margin.is_finite()) {
+                return Err(anyhow!(
+                    "unit_limit_margin must be at least 1, got {}",
+                    margin
+                ));
+            }
+        }
+        if self.pk.is_some() {
+            self.keypair()?;
+        }
+        Ok(())
+    }
+
+    /// Compute unit limit of a transaction that consumed `units_consumed` in
+    /// simulation, `unit_limit` without a margin
+    pub fn estimated_unit_limit(&self, units_consumed: u64) -> u32 {
+        match self.unit_limit_margin {
+            Some(margin) => (units_consumed as f64 * margin)
+                .ceil()
+                .clamp(1.0, MAX_UNIT_LIMIT as f64) as u32,
+            None => self.unit_limit,
+        }
+    }
+
+    /// Trading wallet
+    pub fn keypair(&self) -> Result<Keypair> {
+        let pk = self.pk.as_ref().ok_or(anyhow!("pk is not set"))?;
+        let bytes = bs58::decode(pk.trim())
+            .into_vec()
+            .map_err(|e| anyhow!("pk is not valid base58: {}", e))?;
+        Keypair::from_bytes(&bytes).map_err(|e| anyhow!("pk is not a valid keypair: {}", e))
+    }
+}
+
+fn override_from_env<T: FromStr>(key: &str, value: &mut T) -> Result<()>
+where
+    T::Err: fmt::Display,
+{
+    if let Ok(v) = env::var(key) {
+        *value = v
+            .trim()
+            .parse()
+            .map_err(|e| anyhow!("invalid {} {:?}: {}", key, v, e))?;
+    }
+    Ok(())
+}
+
+fn override_parsed_option_from_env<T: FromStr>(key: &str, value: &mut Option<T>) -> Result<()>
+where
+    T::Err: fmt::Display,
+{
+    if let Ok(v) = env::var(key) {
+        *value = Some(
+            v.trim()
+                .parse()
+                .map_err(|e| anyhow!("invalid {} {:?}: {}", key, v, e))?,
+        );
+    }
+    Ok(())
+}
+
+fn override_option_from_env(key: &str, value: &mut Option<String>) {
+    if let Ok(v) = env::var(key) {
+        *value = Some(v);
+    }
+}
+
+/// Program and account IDs the bot talks to
+#[derive(Debug, Clone, Copy)]
+pub struct ProgramIds {
+    /// Pump.fun bonding curve program
+    pub pumpfun: Pubkey,
+    /// Account that migrates completed curves to Raydium
+    pub pumpfun_migrator: Pubkey,
+    /// Pump.fun fee recipient
+    pub pumpfun_fee_recipient: Pubkey,
+    /// Pump.fun event authority
+    pub pumpfun_event_authority: Pubkey,
+    /// PumpSwap AMM program
+    pub pumpswap: Pubkey,
+    /// Raydium AMM v4 program
+    pub raydium_amm: Pubkey,
+    /// Raydium CP-Swap program
+    pub raydium_cpmm: Pubkey,
+    /// Orca Whirlpools program
+    pub orca_whirlpool: Pubkey,
+}
+
+impl Default for ProgramIds {
+    fn default() -> Self {
+        Self {
+            pumpfun: accounts::PUMPFUN,
+            pumpfun_migrator: accounts::PUMPFUN_MIGRATOR,
+            pumpfun_fee_recipient: accounts::PUMPFUN_FEE_RECEIPT,
+            pumpfun_event_authority: accounts::EVENT_AUTHORITY,
+            pumpswap: accounts::PUMPSWAP,
+            raydium_amm: accounts::RAYDIUM_AMM,
+            raydium_cpmm: accounts::RAYDIUM_CPMM,
+            orca_whirlpool: accounts::ORCA_WHIRLPOOL,
+        }
+    }
+}
+
+impl ProgramIds {
+    /// Reads the overrides from the environment, falling back to mainnet
+    pub fn from_env() -> Result<Self> {
+        dotenv::dotenv().ok();
+        let default = Self::default();
+        Ok(Self {
+            pumpfun: parse_program_id("PUMPFUN_PROGRAM_ID", default.pumpfun)?,
+            pumpfun_migrator: parse_program_id("PUMPFUN_MIGRATOR_ID", default.pumpfun_migrator)?,
+            pumpfun_fee_recipient: parse_program_id(
+                "PUMPFUN_FEE_RECIPIENT",
+                default.pumpfun_fee_recipient,
+            )?,
+            pumpfun_event_authority: parse_program_id(
+                "PUMPFUN_EVENT_AUTHORITY",
+                default.pumpfun_event_authority,
+            )?,
+            pumpswap: parse_program_id("PUMPSWAP_PROGRAM_ID", default.pumpswap)?,
+            raydium_amm: parse_program_id("AMM_PROGRAM_ID", default.raydium_amm)?,
+            raydium_cpmm: parse_program_id("RAYDIUM_CPMM_PROGRAM_ID", default.raydium_cpmm)?,
+            orca_whirlpool: parse_program_id("ORCA_WHIRLPOOL_PROGRAM_ID", default.orca_whirlpool)?,
+        })
+    }
+}
+
+fn parse_program_id(key: &str, default: Pubkey) -> Result<Pubkey> {
+    match env::var(key) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map_err(|e| anyhow!("invalid {} {:?}: {}", key, value, e)),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Telegram message templates
+#[derive(Debug, Clone)]
+pub struct MessageTemplates {
+    pub create: String,
+    pub migration: String,
+}
+
+impl MessageTemplates {
+    pub fn from_env() -> Self {
+        dotenv::dotenv().ok();
+        let template = |key: &str, default: &str| {
+            env::var(key)
+                .map(|v| v.replace("\\n", "\n"))
+                .unwrap_or(default.to_string())
+        };
+        Self {
+            create: template("CREATE_MESSAGE_TEMPLATE", DEFAULT_CREATE_TEMPLATE),
+            migration: template("MIGRATION_MESSAGE_TEMPLATE", DEFAULT_MIGRATION_TEMPLATE),
+        }
+    }
+}
+
+/// Configured message templates
+pub fn message_templates() -> &'static MessageTemplates {
+    MESSAGE_TEMPLATES.get_or_init(MessageTemplates::from_env)
+}
+
+/// Loads and validates the configuration, call once at startup
+pub fn init() -> Result<&'static BotConfig> {
+    let ids = ProgramIds::from_env()?;
+    PROGRAM_IDS.get_or_init(|| ids);
+    let config = BotConfig::from_env()?;
+    Ok(BOT_CONFIG.get_or_init(|| config))
+}
+
+/// Loaded configuration
+///
+/// Panics on an invalid configuration if `init` wasn't called first.
+pub fn bot_config() -> &'static BotConfig {
+    BOT_CONFIG.get_or_init(|| BotConfig::from_env().unwrap())
+}
+
+/// Configured program IDs
+///
+/// Panics on an invalid override if `init` wasn't called first.
+pub fn program_ids() -> &'static ProgramIds {
+    PROGRAM_IDS.get_or_init(|| ProgramIds::from_env().unwrap())
+}
+
+#[test]
+fn test_parse_program_id_override() {
+    let default = Pubkey::new_unique();
+    let custom = Pubkey::new_unique();
+    assert_eq!(
+        parse_program_id("TEST_UNSET_PROGRAM_ID", default).unwrap(),
+        default
+    );
+
+    env::set_var("TEST_CUSTOM_PROGRAM_ID", custom.to_string());
+    assert_eq!(
+        parse_program_id("TEST_CUSTOM_PROGRAM_ID", default).unwrap(),
+        custom
+    );
+
+    env::set_var("TEST_INVALID_PROGRAM_ID", "not-a-pubkey");
+    assert!(parse_program_id("TEST_INVALID_PROGRAM_ID", default).is_err());
+}
+
+#[test]
+fn test_bot_config_file_and_validation() {
+    let path = env::temp_dir().join(format!("bot_config_test_{}.toml", std::process::id()));
+    fs::write(
+        &path,
+        "rpc_url = \"https://rpc.example\"\nws_rpc_url = \"wss://rpc.example\"\nunit_price = 5\n",
+    )
+    .unwrap();
+    let config = BotConfig::load(&path);
+    fs::write(&path, "rpc_url = \"https://rpc.example\"\nunknown = 1\n").unwrap();
+    let unknown = BotConfig::load(&path);
+    fs::remove_file(&path).unwrap();
+
+    // 环境变量可能覆盖url，只检查文件里独有的值
+    let config = config.unwrap();
+    assert_eq!(config.unit_limit, 200_000);
+    if env::var("UNIT_PRICE").is_err() {
+        assert_eq!(config.unit_price, 5);
+    }
+    assert!(unknown.is_err());
+
+    let keypair = Keypair::new();
+    let mut config = BotConfig {
+        rpc_url: "https://rpc.example".to_string(),
+        ws_rpc_url: "wss://rpc.example".to_string(),
+        pk: Some(keypair.to_base58_string()),
+        ..BotConfig::default()
+    };
+    assert!(config.validate().is_ok());
+    assert_eq!(config.keypair().unwrap().to_bytes(), keypair.to_bytes());
+    assert!(!format!("{:?}", config).contains(&keypair.to_base58_string()));
+
+    config.pk = Some("not-a-key".to_string());
+    assert!(config.validate().is_err());
+    config.pk = None;
+    config.ws_rpc_url = "https://rpc.example".to_string();
+    assert!(config.validate().is_err());
+    config.ws_rpc_url = "wss://rpc.example".to_string();
+    config.unit_limit = MAX_UNIT_LIMIT + 1;
+    assert!(config.validate().is_err());
+    config.unit_limit = 200_000;
+
+    assert_eq!(config.estimated_unit_limit(50_000), 200_000);
+    config.unit_limit_margin = Some(1.2);
+    assert_eq!(config.estimated_unit_limit(50_000), 60_000);
+    assert_eq!(config.estimated_unit_limit(10_000_000), MAX_UNIT_LIMIT);
+    config.unit_limit_margin = Some(0.5);
+    assert!(config.validate().is_err());
+}
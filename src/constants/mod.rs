@@ -9,6 +9,9 @@
 //!
 //! - `seeds`: Contains seed values used for PDA derivation
 //! - `accounts`: Contains important program account addresses
+//! - `curve`: Contains the initial Pump.fun bonding curve parameters
+//! - `jito`: Contains the Jito block engine defaults
+//! - `helius`, `bloxroute`: Contain the Helius Sender and bloXroute defaults
 
 /// Constants used as seeds for deriving PDAs (Program Derived Addresses)
 pub mod seeds {
@@ -39,6 +42,21 @@ pub mod accounts {
 
     pub const PUMPFUN_FEE_RECEIPT: Pubkey = pubkey!("CebN5WGQ4jvEPvsVU4EoHEpgzq1VV7AbicfhtW4xC9iM");
 
+    /// Account that migrates completed Pump.fun curves to Raydium
+    pub const PUMPFUN_MIGRATOR: Pubkey = pubkey!("39azUYFWPz3VHgKCf3VChUwbpURdCHRxjWVowf5jUJjg");
+
+    /// Public key for the PumpSwap AMM program
+    pub const PUMPSWAP: Pubkey = pubkey!("pAMMBay6oceH9fJKBRHGP5D4bD4sWpmSwMn52FMfXEA");
+
+    /// Public key for the Raydium AMM v4 program
+    pub const RAYDIUM_AMM: Pubkey = pubkey!("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8");
+
+    /// Public key for the Raydium CP-Swap program
+    pub const RAYDIUM_CPMM: Pubkey = pubkey!("CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C");
+
+    /// Public key for the Orca Whirlpools program
+    pub const ORCA_WHIRLPOOL: Pubkey = pubkey!("whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc");
+
     /// System Program ID
     pub const SYSTEM_PROGRAM: Pubkey = pubkey!("11111111111111111111111111111111");
 
@@ -55,3 +73,111 @@ pub mod accounts {
     /// Token Mint Authority
     pub const MINT_AUTHORITY: Pubkey = pubkey!("TSLvdd1pWpHVjahSpsvCXUbgwsL3JAcvokwaKt1eokM");
 }
+
+/// Initial state of a freshly created Pump.fun bonding curve
+pub mod curve {
+    /// Initial virtual token reserves
+    pub const INITIAL_VIRTUAL_TOKEN_RESERVES: u64 = 1_073_000_000_000_000;
+
+    /// Initial virtual SOL reserves
+    pub const INITIAL_VIRTUAL_SOL_RESERVES: u64 = 30_000_000_000;
+
+    /// Initial real token reserves available for trading
+    pub const INITIAL_REAL_TOKEN_RESERVES: u64 = 793_100_000_000_000;
+
+    /// Total supply of every Pump.fun token
+    pub const TOKEN_TOTAL_SUPPLY: u64 = 1_000_000_000_000_000;
+
+    /// Decimals of every Pump.fun token
+    pub const TOKEN_DECIMALS: u8 = 6;
+}
+
+/// Jito block engine defaults
+pub mod jito {
+    use solana_sdk::{pubkey, pubkey::Pubkey};
+
+    /// Mainnet block engine JSON-RPC endpoint
+    pub const BLOCK_ENGINE_URL: &str = "https://mainnet.block-engine.jito.wtf/api/v1";
+
+    /// Most transactions the block engine accepts in a bundle
+    pub const MAX_BUNDLE_TRANSACTIONS: usize = 5;
+
+    /// Percentiles of recently landed tips
+    pub const TIP_FLOOR_URL: &str = "https://bundles.jito.wtf/api/v1/bundles/tip_floor";
+
+    /// Mainnet tip payment accounts
+    pub const TIP_ACCOUNTS: [Pubkey; 8] = [
+        pubkey!("96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5"),
+        pubkey!("HFqU5x63VTqvQss8hp11i4wVV8bD44PvwucfZ2bU7gRe"),
+        pubkey!("Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY"),
+        pubkey!("ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49"),
+        pubkey!("DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh"),
+        pubkey!("ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt"),
+        pubkey!("DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL"),
+        pubkey!("3AVi9Tg9Uo68tJfuvoKvqKNWKkC5wPdSSdeBnizKZ6jT"),
+    ];
+}
+
+/// Helius Sender defaults
+pub mod helius {
+    use solana_sdk::{pubkey, pubkey::Pubkey};
+
+    /// Global Sender endpoint, regional ones like `http://ewr-sender.helius-rpc.com/fast`
+    /// are closer to the leaders
+    pub const SENDER_URL: &str = "https://sender.helius-rpc.com/fast";
+
+    /// Accounts Sender accepts tips on
+    pub const TIP_ACCOUNTS: [Pubkey; 10] = [
+        pubkey!("4ACfpUFoaSD9bfPdeu6DBt89gB6ENTeHBXCAi87NhDEE"),
+        pubkey!("D2L6yPZ2FmmmTKPgzaMKdhu6EWZcTpLy1Vhx8uvZe7NZ"),
+        pubkey!("9bnz4RShgq1hAnLnZbP8kbgBg1kEmcJBYQq3gQbmnSta"),
+        pubkey!("5VY91ws6B2hMmBFRsXkoAAdsPHBJwRfBht4DXox3xkwn"),
+        pubkey!("2nyhqdwKcJZR2vcqCyrYsaPVdAnFoJjiksCXJ7hfEYgD"),
+        pubkey!("2q5pghRs6arqVjRvT5gfgWfWcHWmw1ZuCzphgd5KfWGJ"),
+        pubkey!("wyvPkWjVZz1M8fHQnMMCDTQDbkManefNNhweYk5WkcF"),
+        pubkey!("3KCKozbAaF75qEU33jxf1QjEwoBGpznmDPAq4WqVsxm7"),
+        pubkey!("4vieeGHPYPG2MmyPRcYjdiDmmhN3ww7hsFNap8pVN3Ey"),
+        pubkey!("4TQLFNWK8AovT1gFvda5jfw2oJeRMKEmw7aH6MGBJ3or"),
+    ];
+}
+
+/// bloXroute Trader API defaults
+pub mod bloxroute {
+    use solana_sdk::{pubkey, pubkey::Pubkey};
+
+    /// Submit endpoint of the New York region
+    pub const SUBMIT_URL: &str = "https://ny.solana.dex.blxrbdn.com/api/v2/submit";
+
+    /// Account bloXroute accepts tips on
+    pub const TIP_ACCOUNT: Pubkey = pubkey!("HWEoBxYs7ssKuudEjzjmpfJVX7Dvi7wescFsVx2L5yoY");
+}
+
+/// Raydium API defaults
+pub mod raydium {
+    /// v3 API, used to find pools the RPC can't search
+    pub const API_URL: &str = "https://api-v3.raydium.io";
+}
+
+/// GMGN API defaults
+pub mod gmgn {
+    /// Endpoint of the token quotations, needs a session cookie
+    pub const API_URL: &str = "https://gmgn.ai/defi/quotation/v1";
+}
+
+/// Birdeye API defaults
+pub mod birdeye {
+    /// Public API, needs an API key
+    pub const API_URL: &str = "https://public-api.birdeye.so";
+}
+
+/// Discord API defaults
+pub mod discord {
+    /// v10 API, needs a bot token
+    pub const API_URL: &str = "https://discord.com/api/v10";
+}
+
+/// Telegram web defaults
+pub mod telegram {
+    /// Web preview of the latest posts of a public channel, `{url}/{channel}`
+    pub const CHANNEL_PREVIEW_URL: &str = "https://t.me/s";
+}
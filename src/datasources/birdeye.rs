@@ -0,0 +1,168 @@
+//! Birdeye token overview and security endpoints.
+//!
+//! A lookup takes two requests, both authenticated with the API key. Birdeye
+//! doesn't report the mint authority nor honeypots.
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Deserialize};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::constants::birdeye::API_URL;
+
+use super::{lenient_f64, DatasourceConfig, SecurityFlags, Throttle, TokenInfo, TokenInfoSource};
+
+#[derive(Debug, Deserialize)]
+struct Response<T> {
+    success: bool,
+    data: Option<T>,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+impl<T> Response<T> {
+    fn into_data(self) -> Result<T> {
+        match self.data {
+            Some(data) if self.success => Ok(data),
+            _ => Err(anyhow!(
+                "birdeye error: {}",
+                self.message.unwrap_or_default()
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TokenOverview {
+    pub symbol: Option<String>,
+    #[serde(deserialize_with = "lenient_f64")]
+    pub price: Option<f64>,
+    pub holder: Option<u64>,
+    #[serde(deserialize_with = "lenient_f64")]
+    pub liquidity: Option<f64>,
+    #[serde(rename = "v24hUSD", deserialize_with = "lenient_f64")]
+    pub volume_24h_usd: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TokenSecurity {
+    pub freeze_authority: Option<String>,
+    pub freezeable: Option<bool>,
+    /// Fraction of supply of the top 10 holders
+    #[serde(deserialize_with = "lenient_f64")]
+    pub top10_holder_percent: Option<f64>,
+}
+
+/// The overview and security answers as one token info
+pub fn token_info(overview: TokenOverview, security: TokenSecurity) -> TokenInfo {
+    // 没有冻结权限时 freezeable 可能为空
+    let freeze_renounced = match (security.freezeable, &security.freeze_authority) {
+        (Some(freezeable), _) => Some(!freezeable),
+        (None, Some(_)) => Some(false),
+        (None, None) => None,
+    };
+    TokenInfo {
+        symbol: overview.symbol,
+        price_usd: overview.price,
+        holders: overview.holder,
+        liquidity_usd: overview.liquidity,
+        volume_24h_usd: overview.volume_24h_usd,
+        security: SecurityFlags {
+            freeze_renounced,
+            top10_holders_pct: security.top10_holder_percent.map(|rate| rate * 100.0),
+            ..SecurityFlags::default()
+        },
+    }
+}
+
+pub struct BirdeyeClient {
+    http: reqwest::Client,
+    api_key: String,
+    throttle: Throttle,
+}
+
+impl BirdeyeClient {
+    pub fn new(api_key: String, config: &DatasourceConfig) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            api_key,
+            throttle: Throttle::new(config),
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str, mint: &Pubkey) -> Result<T> {
+        let request = self
+            .http
+            .get(format!("{}{}", API_URL, path))
+            .query(&[("address", mint.to_string())])
+            .header("X-API-KEY", &self.api_key)
+            .header("x-chain", "solana");
+        self.throttle
+            .get_json::<Response<T>>(request)
+            .await?
+            .into_data()
+    }
+}
+
+#[async_trait]
+impl TokenInfoSource for BirdeyeClient {
+    fn name(&self) -> &'static str {
+        "birdeye"
+    }
+
+    fn throttle(&self) -> &Throttle {
+        &self.throttle
+    }
+
+    async fn fetch(&self, mint: &Pubkey) -> Result<TokenInfo> {
+        let overview = self.get("/defi/token_overview", mint).await?;
+        let security = self.get("/defi/token_security", mint).await?;
+        Ok(token_info(overview, security))
+    }
+}
+
+#[test]
+fn test_parse_birdeye_token() {
+    let overview: Response<TokenOverview> = serde_json::from_value(serde_json::json!({
+        "success": true,
+        "data": {
+            "address": "mint",
+            "symbol": "CAT",
+            "price": 0.0012,
+            "holder": 321,
+            "liquidity": 15000.5,
+            "v24hUSD": 80000.0,
+        },
+    }))
+    .unwrap();
+    let security: Response<TokenSecurity> = serde_json::from_value(serde_json::json!({
+        "success": true,
+        "data": {
+            "freezeAuthority": null,
+            "freezeable": null,
+            "top10HolderPercent": 0.4,
+        },
+    }))
+    .unwrap();
+    let info = token_info(overview.into_data().unwrap(), security.into_data().unwrap());
+    assert_eq!(info.holders, Some(321));
+    assert_eq!(info.volume_24h_usd, Some(80000.0));
+    assert_eq!(info.security.freeze_renounced, None);
+    assert_eq!(info.security.top10_holders_pct, Some(40.0));
+    assert_eq!(info.security.mint_renounced, None);
+
+    let frozen = TokenSecurity {
+        freeze_authority: Some(Pubkey::new_unique().to_string()),
+        ..TokenSecurity::default()
+    };
+    let info = token_info(TokenOverview::default(), frozen);
+    assert_eq!(info.security.freeze_renounced, Some(false));
+
+    let error: Response<TokenOverview> = serde_json::from_value(
+        serde_json::json!({"success": false, "data": null, "message": "Unauthorized"}),
+    )
+    .unwrap();
+    assert!(error.into_data().is_err());
+}
@@ -0,0 +1,154 @@
+//! GMGN token quotations.
+//!
+//! GMGN has no public API, the quotation endpoint of its web app is called
+//! with the user's session cookie. Ratios come as fractions, sometimes as
+//! strings, and flags as 0 / 1.
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::constants::gmgn::API_URL;
+
+use super::{
+    lenient_bool, lenient_f64, DatasourceConfig, SecurityFlags, Throttle, TokenInfo,
+    TokenInfoSource,
+};
+
+#[derive(Debug, Deserialize)]
+struct Response {
+    code: i64,
+    #[serde(default)]
+    msg: String,
+    data: Option<ResponseData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponseData {
+    token: GmgnToken,
+}
+
+/// Token of the quotation endpoint
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GmgnToken {
+    pub symbol: Option<String>,
+    #[serde(deserialize_with = "lenient_f64")]
+    pub price: Option<f64>,
+    pub holder_count: Option<u64>,
+    #[serde(deserialize_with = "lenient_f64")]
+    pub liquidity: Option<f64>,
+    #[serde(deserialize_with = "lenient_f64")]
+    pub volume_24h: Option<f64>,
+    #[serde(deserialize_with = "lenient_bool")]
+    pub renounced_mint: Option<bool>,
+    #[serde(deserialize_with = "lenient_bool")]
+    pub renounced_freeze_account: Option<bool>,
+    /// Fraction of supply of the top 10 holders
+    #[serde(deserialize_with = "lenient_f64")]
+    pub top_10_holder_rate: Option<f64>,
+    /// Fraction of LP burned
+    #[serde(deserialize_with = "lenient_f64")]
+    pub burn_ratio: Option<f64>,
+    #[serde(deserialize_with = "lenient_bool")]
+    pub is_honeypot: Option<bool>,
+}
+
+impl From<GmgnToken> for TokenInfo {
+    fn from(token: GmgnToken) -> Self {
+        TokenInfo {
+            symbol: token.symbol,
+            price_usd: token.price,
+            holders: token.holder_count,
+            liquidity_usd: token.liquidity,
+            volume_24h_usd: token.volume_24h,
+            security: SecurityFlags {
+                mint_renounced: token.renounced_mint,
+                freeze_renounced: token.renounced_freeze_account,
+                top10_holders_pct: token.top_10_holder_rate.map(|rate| rate * 100.0),
+                lp_burned_pct: token.burn_ratio.map(|ratio| ratio * 100.0),
+                honeypot: token.is_honeypot,
+            },
+        }
+    }
+}
+
+fn parse_response(response: Response) -> Result<TokenInfo> {
+    match response.data {
+        Some(data) if response.code == 0 => Ok(data.token.into()),
+        _ => Err(anyhow!("gmgn error {}: {}", response.code, response.msg)),
+    }
+}
+
+pub struct GmgnClient {
+    http: reqwest::Client,
+    cookie: String,
+    throttle: Throttle,
+}
+
+impl GmgnClient {
+    pub fn new(cookie: String, config: &DatasourceConfig) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            cookie,
+            throttle: Throttle::new(config),
+        }
+    }
+}
+
+#[async_trait]
+impl TokenInfoSource for GmgnClient {
+    fn name(&self) -> &'static str {
+        "gmgn"
+    }
+
+    fn throttle(&self) -> &Throttle {
+        &self.throttle
+    }
+
+    async fn fetch(&self, mint: &Pubkey) -> Result<TokenInfo> {
+        let request = self
+            .http
+            .get(format!("{}/tokens/sol/{}", API_URL, mint))
+            .header("Cookie", &self.cookie);
+        parse_response(self.throttle.get_json(request).await?)
+    }
+}
+
+#[test]
+fn test_parse_gmgn_token() {
+    let response: Response = serde_json::from_value(serde_json::json!({
+        "code": 0,
+        "msg": "success",
+        "data": {"token": {
+            "address": "mint",
+            "symbol": "CAT",
+            "price": 0.0012,
+            "holder_count": 321,
+            "liquidity": "15000.5",
+            "volume_24h": 80000,
+            "renounced_mint": 1,
+            "renounced_freeze_account": true,
+            "top_10_holder_rate": "0.25",
+            "burn_ratio": "1",
+            "is_honeypot": null,
+        }},
+    }))
+    .unwrap();
+    let info = parse_response(response).unwrap();
+    assert_eq!(info.symbol.as_deref(), Some("CAT"));
+    assert_eq!(info.holders, Some(321));
+    assert_eq!(info.liquidity_usd, Some(15000.5));
+    assert_eq!(info.volume_24h_usd, Some(80000.0));
+    assert_eq!(info.security.mint_renounced, Some(true));
+    assert_eq!(info.security.freeze_renounced, Some(true));
+    assert_eq!(info.security.top10_holders_pct, Some(25.0));
+    assert_eq!(info.security.lp_burned_pct, Some(100.0));
+    assert_eq!(info.security.honeypot, None);
+
+    let error: Response =
+        serde_json::from_value(serde_json::json!({"code": 403, "msg": "forbidden", "data": null}))
+            .unwrap();
+    assert!(parse_response(error).is_err());
+}
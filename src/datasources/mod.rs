@@ -0,0 +1,310 @@
+//! Token info from the GMGN and Birdeye APIs.
+//!
+//! [`token_info`] asks every configured source about a mint and merges their
+//! answers into one [`TokenInfo`]: holders, liquidity, volume and security
+//! flags. A field only one source reports is taken from it, the first source
+//! wins otherwise. Answers are cached, and each source stays within its own
+//! request budget, pausing after a 429.
+//!
+//! - `GMGN_COOKIE` (`gmgn_cookie` in the bot config): session cookie, GMGN is
+//!   skipped without it
+//! - `BIRDEYE_API_KEY`: API key, Birdeye is skipped without it
+//! - `DATASOURCES_RATE_LIMIT`: requests per second per source (default 1)
+//! - `DATASOURCES_CACHE_SECS`: seconds an answer is reused (default 30)
+//!
+//! The twitter strategy filters tokens on these answers, and the
+//! [`crate::safety`] checks add them to the on-chain ones when asked to.
+
+pub mod birdeye;
+pub mod gmgn;
+
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::{RequestBuilder, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use solana_sdk::pubkey::Pubkey;
+use tracing::warn;
+
+use crate::{
+    config::bot_config,
+    rpc::limiter::{Limiter, LimiterConfig},
+    strategy::parse_env,
+};
+
+pub use birdeye::BirdeyeClient;
+pub use gmgn::GmgnClient;
+
+const DEFAULT_RATE_LIMIT: f64 = 1.0;
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(30);
+
+static SOURCES: OnceLock<Vec<Arc<dyn TokenInfoSource>>> = OnceLock::new();
+
+/// Security flags of a token, `None` where the source doesn't say
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SecurityFlags {
+    pub mint_renounced: Option<bool>,
+    pub freeze_renounced: Option<bool>,
+    /// Share of supply the top 10 holders own, in percent
+    pub top10_holders_pct: Option<f64>,
+    /// Share of LP burned, in percent
+    pub lp_burned_pct: Option<f64>,
+    pub honeypot: Option<bool>,
+}
+
+/// What a source reports on a token, `None` where it doesn't say
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TokenInfo {
+    pub symbol: Option<String>,
+    pub price_usd: Option<f64>,
+    pub holders: Option<u64>,
+    pub liquidity_usd: Option<f64>,
+    pub volume_24h_usd: Option<f64>,
+    pub security: SecurityFlags,
+}
+
+impl TokenInfo {
+    /// `self`, with the fields it lacks taken from `other`
+    pub fn or(self, other: TokenInfo) -> TokenInfo {
+        TokenInfo {
+            symbol: self.symbol.or(other.symbol),
+            price_usd: self.price_usd.or(other.price_usd),
+            holders: self.holders.or(other.holders),
+            liquidity_usd: self.liquidity_usd.or(other.liquidity_usd),
+            volume_24h_usd: self.volume_24h_usd.or(other.volume_24h_usd),
+            security: SecurityFlags {
+                mint_renounced: self
+                    .security
+                    .mint_renounced
+                    .or(other.security.mint_renounced),
+                freeze_renounced: self
+                    .security
+                    .freeze_renounced
+                    .or(other.security.freeze_renounced),
+                top10_holders_pct: self
+                    .security
+                    .top10_holders_pct
+                    .or(other.security.top10_holders_pct),
+                lp_burned_pct: self.security.lp_burned_pct.or(other.security.lp_burned_pct),
+                honeypot: self.security.honeypot.or(other.security.honeypot),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DatasourceConfig {
+    /// Requests per second per source
+    pub rate: f64,
+    /// How long an answer is reused
+    pub cache_ttl: Duration,
+}
+
+impl Default for DatasourceConfig {
+    fn default() -> Self {
+        Self {
+            rate: DEFAULT_RATE_LIMIT,
+            cache_ttl: DEFAULT_CACHE_TTL,
+        }
+    }
+}
+
+impl DatasourceConfig {
+    pub fn from_env() -> Result<Self> {
+        dotenv::dotenv().ok();
+        let rate = parse_env::<f64>("DATASOURCES_RATE_LIMIT")?.unwrap_or(DEFAULT_RATE_LIMIT);
+        if rate.is_nan() || rate <= 0.0 {
+            return Err(anyhow!(
+                "DATASOURCES_RATE_LIMIT must be positive, got {}",
+                rate
+            ));
+        }
+        Ok(Self {
+            rate,
+            cache_ttl: parse_env("DATASOURCES_CACHE_SECS")?
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_CACHE_TTL),
+        })
+    }
+}
+
+/// Request budget and answer cache of one source
+pub struct Throttle {
+    limiter: Limiter,
+    cache_ttl: Duration,
+    cache: Mutex<HashMap<Pubkey, (Instant, TokenInfo)>>,
+}
+
+impl Throttle {
+    pub fn new(config: &DatasourceConfig) -> Self {
+        Self {
+            limiter: Limiter::new(LimiterConfig {
+                rate: config.rate,
+                burst: config.rate.max(1.0),
+                ..LimiterConfig::default()
+            }),
+            cache_ttl: config.cache_ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn cached(&self, mint: &Pubkey, now: Instant) -> Option<TokenInfo> {
+        let cache = self.cache.lock().unwrap();
+        let (at, info) = cache.get(mint)?;
+        (now.saturating_duration_since(*at) < self.cache_ttl).then(|| info.clone())
+    }
+
+    fn store(&self, mint: Pubkey, info: TokenInfo, now: Instant) {
+        let mut cache = self.cache.lock().unwrap();
+        // 顺便清掉过期的
+        cache.retain(|_, (at, _)| now.saturating_duration_since(*at) < self.cache_ttl);
+        cache.insert(mint, (now, info));
+    }
+
+    /// Sends `request` within the budget and decodes its JSON body
+    pub async fn get_json<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T> {
+        self.limiter.acquire().await;
+        let response = request.send().await?;
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            let pause = self.limiter.rate_limited(Instant::now());
+            return Err(anyhow!("rate limited, pausing for {:?}", pause));
+        }
+        let response = response.error_for_status()?;
+        self.limiter.succeeded();
+        Ok(response.json().await?)
+    }
+}
+
+/// An API reporting on tokens
+#[async_trait]
+pub trait TokenInfoSource: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn throttle(&self) -> &Throttle;
+
+    /// Asks the API about `mint`, bypassing the cache
+    async fn fetch(&self, mint: &Pubkey) -> Result<TokenInfo>;
+
+    /// The cached answer on `mint`, asking the API once it's stale
+    async fn token_info(&self, mint: &Pubkey) -> Result<TokenInfo> {
+        if let Some(info) = self.throttle().cached(mint, Instant::now()) {
+            return Ok(info);
+        }
+        let info = self.fetch(mint).await?;
+        self.throttle().store(*mint, info.clone(), Instant::now());
+        Ok(info)
+    }
+}
+
+/// The sources with credentials configured, none if the config is invalid
+pub fn sources() -> &'static [Arc<dyn TokenInfoSource>] {
+    SOURCES.get_or_init(|| {
+        let config = match DatasourceConfig::from_env() {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("invalid datasources config, not using any {:?}", e);
+                return vec![];
+            }
+        };
+        let mut sources: Vec<Arc<dyn TokenInfoSource>> = vec![];
+        if let Some(cookie) = bot_config().gmgn_cookie.clone() {
+            sources.push(Arc::new(GmgnClient::new(cookie, &config)));
+        }
+        if let Ok(key) = env::var("BIRDEYE_API_KEY") {
+            sources.push(Arc::new(BirdeyeClient::new(key, &config)));
+        }
+        sources
+    })
+}
+
+/// What the configured sources report on `mint`, merged
+///
+/// Sources failing are skipped, it only fails when none answered.
+pub async fn token_info(mint: &Pubkey) -> Result<TokenInfo> {
+    let mut merged: Option<TokenInfo> = None;
+    for source in sources() {
+        match source.token_info(mint).await {
+            Ok(info) => {
+                merged = Some(match merged {
+                    Some(merged) => merged.or(info),
+                    None => info,
+                })
+            }
+            Err(e) => warn!("{} lookup of {} failed {:?}", source.name(), mint, e),
+        }
+    }
+    merged.ok_or_else(|| anyhow!("no datasource answered on {}", mint))
+}
+
+/// A number the API sends as a number or a string
+pub(crate) fn lenient_f64<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<f64>, D::Error> {
+    Ok(match Option::<Value>::deserialize(deserializer)? {
+        Some(Value::Number(n)) => n.as_f64(),
+        Some(Value::String(s)) => s.trim().parse().ok(),
+        _ => None,
+    })
+}
+
+/// A flag the API sends as a bool, a 0 / 1 number or a string
+pub(crate) fn lenient_bool<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<bool>, D::Error> {
+    Ok(match Option::<Value>::deserialize(deserializer)? {
+        Some(Value::Bool(b)) => Some(b),
+        Some(Value::Number(n)) => n.as_f64().map(|n| n != 0.0),
+        Some(Value::String(s)) => match s.trim() {
+            "true" | "1" => Some(true),
+            "false" | "0" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+#[test]
+fn test_token_info_merge_and_cache() {
+    let gmgn = TokenInfo {
+        symbol: Some("CAT".to_string()),
+        holders: Some(120),
+        security: SecurityFlags {
+            mint_renounced: Some(true),
+            ..SecurityFlags::default()
+        },
+        ..TokenInfo::default()
+    };
+    let birdeye = TokenInfo {
+        symbol: Some("cat".to_string()),
+        liquidity_usd: Some(5000.0),
+        security: SecurityFlags {
+            mint_renounced: Some(false),
+            freeze_renounced: Some(true),
+            ..SecurityFlags::default()
+        },
+        ..TokenInfo::default()
+    };
+    let merged = gmgn.or(birdeye);
+    assert_eq!(merged.symbol.as_deref(), Some("CAT"));
+    assert_eq!(merged.holders, Some(120));
+    assert_eq!(merged.liquidity_usd, Some(5000.0));
+    assert_eq!(merged.security.mint_renounced, Some(true));
+    assert_eq!(merged.security.freeze_renounced, Some(true));
+
+    let throttle = Throttle::new(&DatasourceConfig::default());
+    let (mint, now) = (Pubkey::new_unique(), Instant::now());
+    assert_eq!(throttle.cached(&mint, now), None);
+    throttle.store(mint, merged.clone(), now);
+    assert_eq!(
+        throttle.cached(&mint, now + Duration::from_secs(1)),
+        Some(merged)
+    );
+    assert_eq!(throttle.cached(&mint, now + DEFAULT_CACHE_TTL), None);
+}
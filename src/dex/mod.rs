@@ -0,0 +1,96 @@
+//! Venues a mint trades against SOL on, behind one [`Dex`] trait.
+//!
+//! Strategies quote and build swaps through `dyn Dex`, so adding a venue is
+//! one more implementation registered with them, without touching their
+//! code. [`default_venues`] returns the Pump.fun bonding curve, Raydium AMM
+//! v4, PumpSwap and Orca whirlpools.
+//!
+//! Every swap wraps the SOL it spends into the payer's WSOL account where
+//! the venue trades WSOL, and closes that account again at its end, so two
+//! swaps in one transaction never depend on each other's token accounts.
+//!
+//! Orca whirlpools are the concentrated liquidity venue; their quotes assume
+//! the swap stays in the current tick range. Raydium CLMM is not a venue:
+//! this tree has no CLMM pool decoding or swap instructions yet. CP-Swap
+//! pools can't be found from a mint alone, their address also depends on the
+//! fee config.
+
+pub mod orca;
+pub mod pumpfun;
+pub mod pumpswap;
+pub mod raydium;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Keypair};
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+};
+
+use crate::{constants::accounts::TOKEN_PROGRAM, portfolio::Side, wallet::wsol};
+
+const NATIVE_MINT: Pubkey = spl_token::native_mint::ID;
+
+/// A venue trading mints against SOL
+///
+/// Buys spend lamports for tokens, sells tokens for lamports; amounts are
+/// raw.
+#[async_trait]
+pub trait Dex: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Pool trading `mint` against SOL, the bonding curve on Pump.fun
+    async fn pool_for_mint(&self, client: &Arc<RpcClient>, mint: &Pubkey) -> Result<Pubkey>;
+
+    /// Output of swapping exactly `amount_in` of `mint` on `side`, after the
+    /// venue's fees
+    async fn quote(
+        &self,
+        client: &Arc<RpcClient>,
+        mint: &Pubkey,
+        side: Side,
+        amount_in: u64,
+    ) -> Result<u64>;
+
+    /// Instructions swapping `amount_in` on `side` for at least `min_out`
+    ///
+    /// The bonding curve buys exact tokens: `min_out` tokens for at most
+    /// `amount_in` lamports.
+    async fn build_swap_ix(
+        &self,
+        client: &Arc<RpcClient>,
+        payer: &Keypair,
+        mint: &Pubkey,
+        side: Side,
+        amount_in: u64,
+        min_out: u64,
+    ) -> Result<Vec<Instruction>>;
+}
+
+/// Pump.fun, Raydium AMM v4, PumpSwap and Orca whirlpools
+pub fn default_venues() -> Vec<Arc<dyn Dex>> {
+    vec![
+        Arc::new(pumpfun::PumpFun),
+        Arc::new(raydium::RaydiumAmm),
+        Arc::new(pumpswap::PumpSwap),
+        Arc::new(orca::OrcaWhirlpool),
+    ]
+}
+
+/// Creates the payer's token account for `mint` if it doesn't exist yet
+fn create_ata(owner: &Pubkey, mint: &Pubkey) -> Instruction {
+    create_associated_token_account_idempotent(owner, owner, mint, &TOKEN_PROGRAM)
+}
+
+/// Moves `lamports` into the payer's WSOL account
+fn wrap_sol(owner: &Pubkey, lamports: u64) -> Result<Vec<Instruction>> {
+    Ok(wsol::ata(owner, lamports, false)?.setup)
+}
+
+/// Closes the payer's WSOL account, returning its balance as SOL
+fn unwrap_sol(owner: &Pubkey) -> Result<Instruction> {
+    wsol::close_instruction(owner, &get_associated_token_address(owner, &NATIVE_MINT))
+}
@@ -0,0 +1,113 @@
+//! Orca whirlpool pairing the mint with WSOL.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Keypair, signer::Signer};
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::{
+    config::program_ids,
+    orca::{
+        math::quote_exact_in,
+        pools::find_sol_pool,
+        swap::{resolve_a_to_b, swap_instruction},
+    },
+    portfolio::Side,
+};
+
+use super::{create_ata, unwrap_sol, wrap_sol, Dex, NATIVE_MINT};
+
+pub struct OrcaWhirlpool;
+
+/// Input and output mints of swapping `mint` on `side`
+fn swap_mints(mint: &Pubkey, side: Side) -> (&Pubkey, &Pubkey) {
+    match side {
+        Side::Buy => (&NATIVE_MINT, mint),
+        Side::Sell => (mint, &NATIVE_MINT),
+    }
+}
+
+#[async_trait]
+impl Dex for OrcaWhirlpool {
+    fn name(&self) -> &'static str {
+        "orca"
+    }
+
+    async fn pool_for_mint(&self, client: &Arc<RpcClient>, mint: &Pubkey) -> Result<Pubkey> {
+        let (pool_id, _) = find_sol_pool(client.clone(), mint).await?;
+        Ok(pool_id)
+    }
+
+    async fn quote(
+        &self,
+        client: &Arc<RpcClient>,
+        mint: &Pubkey,
+        side: Side,
+        amount_in: u64,
+    ) -> Result<u64> {
+        let (_, pool) = find_sol_pool(client.clone(), mint).await?;
+        let (input_mint, output_mint) = swap_mints(mint, side);
+        let a_to_b = resolve_a_to_b(
+            input_mint,
+            output_mint,
+            &pool.token_mint_a,
+            &pool.token_mint_b,
+        )?;
+        let quote = quote_exact_in(
+            pool.sqrt_price,
+            pool.liquidity,
+            pool.fee_rate,
+            amount_in,
+            a_to_b,
+        )?;
+        Ok(quote.estimated_amount_out)
+    }
+
+    async fn build_swap_ix(
+        &self,
+        client: &Arc<RpcClient>,
+        payer: &Keypair,
+        mint: &Pubkey,
+        side: Side,
+        amount_in: u64,
+        min_out: u64,
+    ) -> Result<Vec<Instruction>> {
+        let owner = payer.pubkey();
+        let (pool_id, pool) = find_sol_pool(client.clone(), mint).await?;
+        let (input_mint, output_mint) = swap_mints(mint, side);
+        let a_to_b = resolve_a_to_b(
+            input_mint,
+            output_mint,
+            &pool.token_mint_a,
+            &pool.token_mint_b,
+        )?;
+        let swap = swap_instruction(
+            &program_ids().orca_whirlpool,
+            &owner,
+            &pool_id,
+            &pool,
+            &get_associated_token_address(&owner, input_mint),
+            &get_associated_token_address(&owner, output_mint),
+            a_to_b,
+            amount_in,
+            min_out,
+        )?;
+        match side {
+            Side::Buy => {
+                let mut instructions = wrap_sol(&owner, amount_in)?;
+                instructions.push(create_ata(&owner, mint));
+                instructions.push(swap);
+                instructions.push(unwrap_sol(&owner)?);
+                Ok(instructions)
+            }
+            Side::Sell => Ok(vec![
+                create_ata(&owner, &NATIVE_MINT),
+                swap,
+                unwrap_sol(&owner)?,
+            ]),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Pump.fun bonding curve, until the curve completes.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Keypair, signer::Signer};
+
+use crate::{
+    portfolio::Side,
+    pumpfun::{
+        instructions::{create_buy_instruction, create_sell_instruction},
+        utils::{get_bonding_curve_account, get_bonding_curve_pda, get_global_account},
+    },
+};
+
+use super::{create_ata, Dex};
+
+pub struct PumpFun;
+
+#[async_trait]
+impl Dex for PumpFun {
+    fn name(&self) -> &'static str {
+        "pumpfun"
+    }
+
+    async fn pool_for_mint(&self, _client: &Arc<RpcClient>, mint: &Pubkey) -> Result<Pubkey> {
+        get_bonding_curve_pda(mint).ok_or(anyhow!("no bonding curve address for {}", mint))
+    }
+
+    async fn quote(
+        &self,
+        client: &Arc<RpcClient>,
+        mint: &Pubkey,
+        side: Side,
+        amount_in: u64,
+    ) -> Result<u64> {
+        let curve = get_bonding_curve_account(client.clone(), mint).await?;
+        let global = get_global_account(client.clone()).await?;
+        // 手续费在花费之外另收
+        match side {
+            Side::Buy => curve.get_buy_price_with_fee(amount_in, global.fee_basis_points),
+            Side::Sell => curve.get_sell_price(amount_in, global.fee_basis_points),
+        }
+        .map_err(|e| anyhow!(e))
+    }
+
+    async fn build_swap_ix(
+        &self,
+        _client: &Arc<RpcClient>,
+        payer: &Keypair,
+        mint: &Pubkey,
+        side: Side,
+        amount_in: u64,
+        min_out: u64,
+    ) -> Result<Vec<Instruction>> {
+        Ok(match side {
+            Side::Buy => vec![
+                create_ata(&payer.pubkey(), mint),
+                create_buy_instruction(payer, mint, min_out, amount_in),
+            ],
+            Side::Sell => vec![create_sell_instruction(payer, mint, amount_in, min_out)],
+        })
+    }
+}
@@ -0,0 +1,93 @@
+//! PumpSwap pool a completed curve migrated to.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Keypair, signer::Signer};
+
+use crate::{
+    portfolio::Side,
+    pumpswap::{
+        instructions::{create_buy_instruction, create_sell_instruction},
+        math::{buy_base_output, sell_quote_output},
+        pools,
+    },
+};
+
+use super::{create_ata, unwrap_sol, wrap_sol, Dex, NATIVE_MINT};
+
+pub struct PumpSwap;
+
+#[async_trait]
+impl Dex for PumpSwap {
+    fn name(&self) -> &'static str {
+        "pumpswap"
+    }
+
+    async fn pool_for_mint(&self, client: &Arc<RpcClient>, mint: &Pubkey) -> Result<Pubkey> {
+        let (pool_id, _) = pools::find_sol_pool(client.clone(), mint).await?;
+        Ok(pool_id)
+    }
+
+    async fn quote(
+        &self,
+        client: &Arc<RpcClient>,
+        mint: &Pubkey,
+        side: Side,
+        amount_in: u64,
+    ) -> Result<u64> {
+        let (_, pool) = pools::find_sol_pool(client.clone(), mint).await?;
+        let config = pools::get_global_config(client).await?;
+        let (base_reserve, quote_reserve) = pools::get_reserves(client, &pool).await?;
+        let quote = match side {
+            Side::Buy => buy_base_output,
+            Side::Sell => sell_quote_output,
+        };
+        Ok(quote(
+            amount_in,
+            base_reserve,
+            quote_reserve,
+            config.lp_fee_basis_points,
+            config.protocol_fee_basis_points,
+        ))
+    }
+
+    async fn build_swap_ix(
+        &self,
+        client: &Arc<RpcClient>,
+        payer: &Keypair,
+        mint: &Pubkey,
+        side: Side,
+        amount_in: u64,
+        min_out: u64,
+    ) -> Result<Vec<Instruction>> {
+        let owner = payer.pubkey();
+        let (pool_id, pool) = pools::find_sol_pool(client.clone(), mint).await?;
+        let config = pools::get_global_config(client).await?;
+        let fee_recipient = &config.protocol_fee_recipients[0];
+        match side {
+            Side::Buy => {
+                let mut instructions = wrap_sol(&owner, amount_in)?;
+                instructions.push(create_ata(&owner, mint));
+                instructions.push(create_buy_instruction(
+                    &pool_id,
+                    &pool,
+                    &owner,
+                    fee_recipient,
+                    min_out,
+                    amount_in,
+                ));
+                // 没花完的wsol也一起取回
+                instructions.push(unwrap_sol(&owner)?);
+                Ok(instructions)
+            }
+            Side::Sell => Ok(vec![
+                create_ata(&owner, &NATIVE_MINT),
+                create_sell_instruction(&pool_id, &pool, &owner, fee_recipient, amount_in, min_out),
+                unwrap_sol(&owner)?,
+            ]),
+        }
+    }
+}
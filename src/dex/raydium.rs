@@ -0,0 +1,138 @@
+//! Raydium AMM v4 pool pairing the mint with WSOL.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Keypair, signer::Signer};
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::{
+    config::program_ids,
+    portfolio::Side,
+    raydium::{
+        getter::get_pool_state,
+        math::{calculate_swap_info, quote_base_in},
+        pools::find_sol_pool,
+        structure::AmmInfo,
+        swap::amm_swap,
+    },
+};
+
+use super::{create_ata, unwrap_sol, wrap_sol, Dex, NATIVE_MINT};
+
+pub struct RaydiumAmm;
+
+impl RaydiumAmm {
+    async fn pool(client: &Arc<RpcClient>, mint: &Pubkey) -> Result<(Pubkey, AmmInfo)> {
+        let pool_id = find_sol_pool(client.clone(), mint).await?;
+        get_pool_state(client.clone(), &pool_id.to_string()).await
+    }
+
+    async fn swap(
+        client: &Arc<RpcClient>,
+        owner: &Pubkey,
+        mint: &Pubkey,
+        input_mint: &Pubkey,
+        amount_in: u64,
+        min_out: u64,
+    ) -> Result<Instruction> {
+        let (pool_id, pool_state) = Self::pool(client, mint).await?;
+        let amm_program = program_ids().raydium_amm;
+        // 和get_swap_tx一样用输入一侧的金库确定方向
+        let input_vault = if *input_mint == pool_state.coin_vault_mint {
+            pool_state.coin_vault
+        } else {
+            pool_state.pc_vault
+        };
+        let output_mint = if *input_mint == NATIVE_MINT {
+            mint
+        } else {
+            &NATIVE_MINT
+        };
+        let swap_info = calculate_swap_info(
+            client.clone(),
+            &pool_state,
+            amm_program,
+            pool_id,
+            input_vault,
+            amount_in,
+            0,
+            true,
+        )
+        .await?;
+        amm_swap(
+            &amm_program,
+            swap_info,
+            owner,
+            &get_associated_token_address(owner, input_mint),
+            &get_associated_token_address(owner, output_mint),
+            amount_in,
+            min_out,
+            true,
+        )
+    }
+}
+
+#[async_trait]
+impl Dex for RaydiumAmm {
+    fn name(&self) -> &'static str {
+        "raydium"
+    }
+
+    async fn pool_for_mint(&self, client: &Arc<RpcClient>, mint: &Pubkey) -> Result<Pubkey> {
+        find_sol_pool(client.clone(), mint).await
+    }
+
+    async fn quote(
+        &self,
+        client: &Arc<RpcClient>,
+        mint: &Pubkey,
+        side: Side,
+        amount_in: u64,
+    ) -> Result<u64> {
+        let (pool_id, pool_state) = Self::pool(client, mint).await?;
+        let input_mint = match side {
+            Side::Buy => &NATIVE_MINT,
+            Side::Sell => mint,
+        };
+        quote_base_in(
+            client.clone(),
+            &pool_state,
+            program_ids().raydium_amm,
+            pool_id,
+            input_mint,
+            amount_in,
+        )
+        .await
+    }
+
+    async fn build_swap_ix(
+        &self,
+        client: &Arc<RpcClient>,
+        payer: &Keypair,
+        mint: &Pubkey,
+        side: Side,
+        amount_in: u64,
+        min_out: u64,
+    ) -> Result<Vec<Instruction>> {
+        let owner = payer.pubkey();
+        match side {
+            Side::Buy => {
+                let mut instructions = wrap_sol(&owner, amount_in)?;
+                instructions.push(create_ata(&owner, mint));
+                instructions.push(
+                    Self::swap(client, &owner, mint, &NATIVE_MINT, amount_in, min_out).await?,
+                );
+                instructions.push(unwrap_sol(&owner)?);
+                Ok(instructions)
+            }
+            Side::Sell => Ok(vec![
+                create_ata(&owner, &NATIVE_MINT),
+                Self::swap(client, &owner, mint, mint, amount_in, min_out).await?,
+                unwrap_sol(&owner)?,
+            ]),
+        }
+    }
+}
@@ -1,90 +1,887 @@
 use anyhow::{anyhow, Result};
-use solana_client::nonblocking::{pubsub_client::PubsubClient, rpc_client::RpcClient};
-use std::{sync::Arc, time::Duration};
-use teloxide::{prelude::Requester, types::ChatId, Bot};
-use tokio::{sync::broadcast, task::JoinSet};
-use tracing::{error, info};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+use spl_associated_token_account::get_associated_token_address;
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{broadcast, broadcast::error::RecvError, mpsc, oneshot},
+    task::JoinSet,
+};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::{error, info, warn};
 use twitter_v2::TwitterApi;
 
 use crate::{
-    monitor::twitter::twitter_monitor::{auth_for_twitter, get_post_content, process_tweet},
-    strategy::Strategy,
+    config::{bot_config, BotConfig},
+    monitor::{
+        events::{self, MonitorEvent},
+        twitter::{
+            filter::TweetFilter,
+            seen::seen_tweets,
+            stream::{self, stream_tweets},
+            twitter_monitor::{auth_for_twitter, process_tweet, TweetBuy},
+            watcher::{accounts_from_env, watch_account, AccountWatch},
+        },
+    },
+    new_client,
+    notify::{self, Notifier},
+    pumpfun::operation::{buy_auto, buy_exact_tokens, ensure_balance, sell_all, sell_percentage},
+    risk, safety,
+    strategy::{bundle_snipe::bundle_buy, parse_env, RiskProfile, Strategy},
+    timeline,
+    tx::{
+        budget::global_guard,
+        sender::{with_sender, Sender},
+        simulate::TxOutcome,
+        tracker::{TrackedTx, TrackerConfig, TxTracker},
+    },
+    wallet::{wsol, Wallets},
 };
 
 pub struct Engine {
-    // tg bot
-    tg_bot: Arc<Bot>,
+    // credentials and fees
+    config: &'static BotConfig,
+    // notifications
+    notifier: Arc<dyn Notifier>,
     // tx client
     http_client: Arc<RpcClient>,
-    // listen client
-    ws_client: Arc<PubsubClient>,
-    // twitter poll interval
-    poll_interval: u64,
+    // trading wallets, loaded only if something trades from them
+    wallets: Option<Arc<Wallets>>,
+    // twitter accounts, each polled by its own watcher
+    x_accounts: Vec<AccountWatch>,
+    // twitter keywords, streamed only
+    x_keywords: Vec<String>,
+    // filtered stream instead of polling
+    twitter_stream: bool,
     // strategy
-    strategy: Strategy,
-    chat_id: ChatId,
+    strategy: RiskProfile,
+    // strategies on the monitor events
+    strategies: StrategyRegistry,
+    // events the strategies run on
+    events: Option<broadcast::Sender<MonitorEvent>>,
+    // other trading tasks
+    tasks: Vec<EngineTask>,
+    // stops every task
+    shutdown: CancellationToken,
+}
+
+/// A task run with the engine's shutdown token and the tracker of its trades
+type EngineTask = Box<
+    dyn FnOnce(CancellationToken, TaskTracker) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send,
+>;
+
+/// Configures an [`Engine`], see [`Engine::builder`]
+pub struct EngineBuilder {
+    config: Option<&'static BotConfig>,
+    notifier: Option<Arc<dyn Notifier>>,
+    http_client: Option<Arc<RpcClient>>,
+    wallets: Option<Arc<Wallets>>,
+    poll_interval: u64,
+    x_accounts: Vec<u64>,
+    x_watches: Vec<AccountWatch>,
+    x_keywords: Vec<String>,
+    twitter_stream: bool,
+    strategy: RiskProfile,
+    strategies: StrategyRegistry,
+    events: Option<broadcast::Sender<MonitorEvent>>,
+    tasks: Vec<EngineTask>,
+    shutdown: CancellationToken,
+}
+
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 60;
+
+impl EngineBuilder {
+    /// Defaults to the global [`bot_config`]
+    pub fn config(mut self, config: &'static BotConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Defaults to [`notify::from_env`]
+    pub fn notifier(mut self, notifier: Arc<dyn Notifier>) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// Defaults to [`new_client`]
+    pub fn http_client(mut self, client: Arc<RpcClient>) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
+    /// Defaults to the wallets of [`Wallets::from_env`], loaded if there are
+    /// strategies or twitter accounts
+    pub fn wallets(mut self, wallets: Arc<Wallets>) -> Self {
+        self.wallets = Some(wallets);
+        self
+    }
+
+    /// Seconds between two polls of the twitter accounts without their own
+    pub fn poll_interval(mut self, secs: u64) -> Self {
+        self.poll_interval = secs;
+        self
+    }
+
+    /// Twitter user ids whose tweets are traded on, with the engine's poll
+    /// interval and risk profile, none by default
+    ///
+    /// The accounts of `TWITTER_ACCOUNTS` are added too, see
+    /// [`crate::monitor::twitter::watcher`].
+    pub fn x_accounts(mut self, accounts: Vec<u64>) -> Self {
+        self.x_accounts = accounts;
+        self
+    }
+
+    /// Adds a twitter account with its own poll interval, request budget and
+    /// risk profile
+    pub fn x_account(mut self, watch: AccountWatch) -> Self {
+        self.x_watches.push(watch);
+        self
+    }
+
+    /// Keywords whose tweets are traded on, none by default
+    ///
+    /// Only the filtered stream matches keywords, polling ignores them.
+    pub fn x_keywords(mut self, keywords: Vec<String>) -> Self {
+        self.x_keywords = keywords;
+        self
+    }
+
+    /// Receives the tweets from the filtered stream instead of polling the
+    /// accounts' timelines, off by default
+    pub fn twitter_stream(mut self, enabled: bool) -> Self {
+        self.twitter_stream = enabled;
+        self
+    }
+
+    pub fn risk_profile(mut self, profile: RiskProfile) -> Self {
+        self.strategy = profile;
+        self
+    }
+
+    /// Adds `strategy`, its actions executed with `config`
+    pub fn strategy(mut self, strategy: impl Strategy + 'static, config: ActionConfig) -> Self {
+        self.strategies.register(strategy, config);
+        self
+    }
+
+    /// Sender of the events the strategies run on, the global bus of
+    /// [`events::subscribe`] by default
+    pub fn events(mut self, events: broadcast::Sender<MonitorEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Adds a trading task, called with the engine's shutdown token and the
+    /// tracker to spawn its trades on
+    ///
+    /// The task should return once the token is cancelled, the engine waits
+    /// for it and its trades before closing the WSOL accounts.
+    pub fn task<F, Fut>(mut self, task: F) -> Self
+    where
+        F: FnOnce(CancellationToken, TaskTracker) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.tasks.push(Box::new(move |shutdown, actions| {
+            Box::pin(task(shutdown, actions))
+        }));
+        self
+    }
+
+    /// Token stopping the engine once cancelled, a new one by default
+    pub fn shutdown(mut self, shutdown: CancellationToken) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    pub fn build(self) -> Result<Engine> {
+        let config = self.config.unwrap_or_else(bot_config);
+        let notifier = match self.notifier {
+            Some(notifier) => notifier,
+            None => notify::from_env()?,
+        };
+        let poll_interval = Duration::from_secs(self.poll_interval);
+        let mut x_accounts = self.x_watches;
+        x_accounts.extend(
+            self.x_accounts
+                .iter()
+                .map(|id| AccountWatch::new(*id, poll_interval, self.strategy)),
+        );
+        x_accounts.extend(accounts_from_env(poll_interval, self.strategy)?);
+        // 同一个账户以最先给出的设置为准
+        let mut user_ids = HashSet::new();
+        x_accounts.retain(|watch| user_ids.insert(watch.user_id));
+        let wallets = match self.wallets {
+            Some(wallets) => Some(wallets),
+            None if !self.strategies.is_empty()
+                || !x_accounts.is_empty()
+                || !self.x_keywords.is_empty() =>
+            {
+                Some(Arc::new(Wallets::from_env(config.keypair()?)?))
+            }
+            None => None,
+        };
+        Ok(Engine {
+            config,
+            notifier,
+            http_client: self.http_client.unwrap_or_else(new_client),
+            wallets,
+            x_accounts,
+            x_keywords: self.x_keywords,
+            twitter_stream: self.twitter_stream,
+            strategy: self.strategy,
+            strategies: self.strategies,
+            events: self.events,
+            tasks: self.tasks,
+            shutdown: self.shutdown,
+        })
+    }
 }
 
 impl Engine {
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder {
+            config: None,
+            notifier: None,
+            http_client: None,
+            wallets: None,
+            poll_interval: DEFAULT_POLL_INTERVAL_SECS,
+            x_accounts: vec![],
+            x_watches: vec![],
+            x_keywords: vec![],
+            twitter_stream: false,
+            strategy: RiskProfile::Medium,
+            strategies: StrategyRegistry::new(),
+            events: None,
+            tasks: vec![],
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    /// Token stopping the engine's tasks once cancelled
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// The trading wallets, `None` if nothing needed them
+    pub fn wallets(&self) -> Option<Arc<Wallets>> {
+        self.wallets.clone()
+    }
+
     // run
-    // twitter account,user_id
-    pub async fn run(self, x_accounts: Vec<u64>, channel_size: usize) -> Result<JoinSet<()>> {
+    //
+    // 取消后停止接收新交易，等待交易任务及其在途交易和通知完成，再关闭钱包的 WSOL 账户
+    pub async fn run(self, channel_size: usize) -> Result<JoinSet<()>> {
         let mut set = JoinSet::new();
+        let shutdown = self.shutdown.clone();
+        // 交易任务和每笔交易都在 actions 上，关闭 WSOL 前等它们结束
+        let actions = TaskTracker::new();
+
+        // 4. notify the results of the trades
+        let notified = CancellationToken::new();
+        let notifications = tokio::spawn(notify_trades(
+            events::subscribe(),
+            self.notifier.clone(),
+            notified.clone(),
+        ));
+
+        // 3. strategies on the monitor events
+        if !self.strategies.is_empty() {
+            let events = match &self.events {
+                Some(events) => events.subscribe(),
+                None => events::subscribe(),
+            };
+            actions.spawn(run_strategies(
+                self.strategies,
+                events,
+                self.http_client.clone(),
+                self.wallets.clone().expect("loaded for the strategies"),
+                shutdown.clone(),
+                actions.clone(),
+            ));
+        }
+        for task in self.tasks {
+            actions.spawn(task(shutdown.clone(), actions.clone()));
+        }
 
         // send tx to process
         let (tx_sender, _) = broadcast::channel(channel_size);
+        let client = self.http_client.clone();
+        let tracker = Arc::new(TxTracker::new(client.clone(), TrackerConfig::from_env()?));
+        let wallets = self.wallets.clone();
 
         // 2. send tx
-        let mut tx_receiver = tx_sender.subscribe();
+        let mut tx_receiver: broadcast::Receiver<TweetBuy> = tx_sender.subscribe();
+        let tx_shutdown = shutdown.clone();
         set.spawn(async move {
-            while let Ok(tx) = tx_receiver.recv().await {
-                // send tx to node
-                match self.http_client.send_transaction(&tx).await {
-                    Ok(sig) => {
-                        info!("a tx send success! {:?}", sig);
-                        // send to tgbot
-                        let _ = self
-                            .tg_bot
-                            .send_message(self.chat_id, format!("new tx send {:?}", sig))
-                            .await;
-                    }
-                    Err(e) => {
-                        error!("failed to send tx {:?}", e);
+            // 没有推特来源时发送端会立即关闭，仍要等到取消才关闭 WSOL 账户
+            let mut closed = false;
+            loop {
+                let tx = tokio::select! {
+                    _ = tx_shutdown.cancelled() => break,
+                    tx = tx_receiver.recv(), if !closed => match tx {
+                        Ok(tx) => tx,
+                        Err(RecvError::Lagged(n)) => {
+                            warn!("tx sender lagged, skipped {} txs", n);
+                            continue;
+                        }
+                        Err(RecvError::Closed) => {
+                            closed = true;
+                            continue;
+                        }
+                    },
+                };
+                let (client, tracker) = (client.clone(), tracker.clone());
+                // 每笔交易单独跟踪，不阻塞后续交易，结果由通知任务发出
+                actions.spawn(async move {
+                    // send tx to node and wait for it to land
+                    match send_tweet_buy(&client, &tracker, tx).await {
+                        Ok(tracked) => info!("tx done {:?}", tracked),
+                        Err(e) => error!("failed to send tx {:?}", e),
                     }
+                });
+            }
+            actions.close();
+            if !actions.is_empty() {
+                info!("waiting for {} trading tasks", actions.len());
+                actions.wait().await;
+            }
+            notified.cancel();
+            if let Err(e) = notifications.await {
+                error!("notifications stopped {:?}", e);
+            }
+            for payer in wallets.iter().flat_map(|wallets| wallets.keypairs()) {
+                if let Err(e) = wsol::close(&client, payer).await {
+                    error!("failed to close WSOL of {} {:?}", payer.pubkey(), e);
                 }
             }
+            info!("engine stopped");
         });
 
         // 1. fetch info from twitter
-        set.spawn(async move {
-            loop {
-                let api = TwitterApi::new(auth_for_twitter());
-                for user in &x_accounts {
-                    match get_post_content(&api, user).await {
-                        Ok(tweet_list) => {
-                            // analyze twitter
-                            for tweet in tweet_list {
-                                // get op by twitter and strategy
-                                if let Some(op) = process_tweet(tweet, &self.strategy).await {
-                                    match tx_sender.send(op) {
-                                        Ok(_) => {
-                                            info!("transaction prepare to send to node");
-                                        }
-                                        Err(e) => {
-                                            error!("send transaction error {:?}", e);
-                                        }
-                                    }
-                                }
-                            }
+        if self.x_accounts.is_empty() && self.x_keywords.is_empty() {
+            return Ok(set);
+        }
+        let auth = auth_for_twitter(self.config)?;
+        let wallets = self.wallets.clone().expect("loaded for the tweets");
+        let (strategy, config) = (self.strategy, self.config);
+        let watches: HashMap<u64, AccountWatch> = self
+            .x_accounts
+            .iter()
+            .map(|watch| (watch.user_id, watch.clone()))
+            .collect();
+        let filter = Arc::new(TweetFilter::from_env()?);
+        let (tweet_sender, mut tweets) = mpsc::channel(channel_size);
+        if self.twitter_stream {
+            let user_ids: Vec<u64> = watches.keys().copied().collect();
+            let rules = stream::rules(&user_ids, &self.x_keywords);
+            let stream = stream_tweets(TwitterApi::new(auth), rules, tweet_sender);
+            let shutdown = shutdown.clone();
+            set.spawn(async move {
+                shutdown.run_until_cancelled(stream).await;
+            });
+        } else {
+            // 每个账户单独轮询，互不阻塞
+            for watch in self.x_accounts {
+                let watcher =
+                    watch_account(TwitterApi::new(auth.clone()), watch, tweet_sender.clone());
+                let shutdown = shutdown.clone();
+                set.spawn(async move {
+                    shutdown.run_until_cancelled(watcher).await;
+                });
+            }
+        }
+        let twitter = async move {
+            // analyze twitter
+            while let Some(tweet) = tweets.recv().await {
+                // 每条推文只处理一次
+                match seen_tweets().first_seen(&tweet) {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(e) => error!("failed to persist tweet {} {:?}", tweet.id, e),
+                }
+                // 按作者账户的配置交易，关键词匹配到的其他作者用默认配置
+                let (profile, buy_sol) = tweet
+                    .author_id
+                    .and_then(|author| watches.get(&author.as_u64()))
+                    .map_or((strategy, None), |watch| (watch.profile, watch.buy_sol));
+                let (filter, tx_sender, wallets) =
+                    (filter.clone(), tx_sender.clone(), wallets.clone());
+                // 每条推文单独处理，一个账户的慢查询不阻塞其他账户
+                tokio::spawn(async move {
+                    // get op by twitter and strategy
+                    let Some(op) =
+                        process_tweet(tweet, &profile, buy_sol, &filter, config, &wallets).await
+                    else {
+                        return;
+                    };
+                    match tx_sender.send(op) {
+                        Ok(_) => {
+                            info!("transaction prepare to send to node");
+                        }
+                        Err(e) => {
+                            error!("send transaction error {:?}", e);
                         }
-                        Err(_) => {}
                     }
+                });
+            }
+        };
+        set.spawn(async move {
+            shutdown.run_until_cancelled(twitter).await;
+        });
+        Ok(set)
+    }
+}
+
+/// Sends the buy of a tweet through `tracker`, after the balance, risk and
+/// budget checks of the other buy paths
+async fn send_tweet_buy(
+    client: &RpcClient,
+    tracker: &TxTracker,
+    buy: TweetBuy,
+) -> Result<TrackedTx> {
+    let new_ata = get_associated_token_address(&buy.payer.pubkey(), &buy.mint);
+    ensure_balance(client, &buy.payer.pubkey(), buy.max_sol_cost, &[new_ata]).await?;
+    risk::check_buy(&buy.mint, buy.lamports)?;
+    let reservation = global_guard().reserve(&buy.mint, buy.lamports)?;
+    let tracked = tracker.send(buy.txn, &buy.payer).await?;
+    reservation.commit();
+    Ok(tracked)
+}
+
+/// Sends the `tx_landed` and `tx_failed` events on `events` to `notifier`,
+/// until `done` is cancelled and the events received before are sent
+async fn notify_trades(
+    mut events: broadcast::Receiver<MonitorEvent>,
+    notifier: Arc<dyn Notifier>,
+    done: CancellationToken,
+) {
+    loop {
+        // 先发完已收到的事件再退出
+        let event = tokio::select! {
+            biased;
+            event = events.recv() => event,
+            _ = done.cancelled() => break,
+        };
+        match event {
+            Ok(event @ (MonitorEvent::TxLanded(_) | MonitorEvent::TxFailed(_))) => {
+                if let Err(e) = notifier.notify(&event).await {
+                    error!("notify error {:?}", e);
                 }
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(n)) => warn!("trade notifications lagged, skipped {} events", n),
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Cancels `shutdown` on SIGINT
+pub async fn cancel_on_ctrl_c(shutdown: CancellationToken) {
+    match tokio::signal::ctrl_c().await {
+        Ok(()) => info!("shutting down"),
+        Err(e) => error!("failed to listen for SIGINT {:?}", e),
+    }
+    shutdown.cancel();
+}
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the automatic strategies are paused
+///
+/// Paused strategies skip new trades, exits of open positions keep running.
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+/// A trade or control requested outside the strategies, e.g. from Telegram
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Buys `mint` with `lamports`
+    Buy {
+        mint: Pubkey,
+        lamports: u64,
+    },
+    /// Buys exactly `token_amount` raw tokens of `mint` on its bonding curve,
+    /// paying at most `max_lamports`
+    BuyTokens {
+        mint: Pubkey,
+        token_amount: u64,
+        max_lamports: u64,
+    },
+    /// Buys `mint` from the first wallets in one Jito bundle, `amounts[i]`
+    /// lamports from the i-th one, see [`crate::strategy::bundle_snipe`]
+    BundleBuy {
+        mint: Pubkey,
+        amounts: Vec<u64>,
+    },
+    /// Sells `pct` percent of the wallet's `mint` balance
+    Sell {
+        mint: Pubkey,
+        pct: f64,
+    },
+    /// Sells the wallet's whole `mint` balance and closes its token account
+    Dump {
+        mint: Pubkey,
+    },
+    Pause,
+    Resume,
+}
+
+impl Action {
+    /// Wallet trading the action, the main one for controls
+    pub fn payer(&self, wallets: &Wallets) -> Arc<Keypair> {
+        match self {
+            Action::Buy { mint, .. } | Action::BuyTokens { mint, .. } => wallets.for_buy(mint),
+            Action::Sell { mint, .. } | Action::Dump { mint } => wallets.for_sell(mint),
+            Action::BundleBuy { .. } | Action::Pause | Action::Resume => wallets.main(),
+        }
+    }
+}
+
+/// An action and where to send its result
+pub struct ActionRequest {
+    pub action: Action,
+    /// The transaction sent, `None` for controls
+    pub reply: oneshot::Sender<Result<Option<TxOutcome>>>,
+}
 
-                // wait
-                tokio::time::sleep(Duration::from_secs(self.poll_interval)).await;
+/// Channel the engine receives actions on
+pub fn action_channel(
+    channel_size: usize,
+) -> (mpsc::Sender<ActionRequest>, mpsc::Receiver<ActionRequest>) {
+    mpsc::channel(channel_size)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ActionConfig {
+    /// Slippage in percent
+    pub slippage: u64,
+    /// Simulates even in live mode, see [`crate::tx::mode`]
+    pub simulate: bool,
+    /// Path the transactions are submitted through
+    pub sender: Sender,
+}
+
+impl ActionConfig {
+    /// Reads `ACTION_SLIPPAGE` (default 10), `ACTION_SIMULATE` and
+    /// `ACTION_SENDER` (default rpc)
+    pub fn from_env() -> Result<Self> {
+        dotenv::dotenv().ok();
+        Ok(Self {
+            slippage: parse_env("ACTION_SLIPPAGE")?.unwrap_or(DEFAULT_ACTION_SLIPPAGE),
+            simulate: parse_env("ACTION_SIMULATE")?.unwrap_or(false),
+            sender: parse_env("ACTION_SENDER")?.unwrap_or_default(),
+        })
+    }
+}
+
+const DEFAULT_ACTION_SLIPPAGE: u64 = 10;
+
+/// Executes `action` with the payer's wallet, submitting through
+/// `config.sender`
+pub async fn execute(
+    action: Action,
+    client: Arc<RpcClient>,
+    payer: &Keypair,
+    config: ActionConfig,
+) -> Result<Option<TxOutcome>> {
+    with_sender(config.sender, execute_action(action, client, payer, config)).await
+}
+
+/// Executes `action` with the wallets trading it, see [`Action::payer`]
+///
+/// Bundle buys are the only actions trading from several wallets.
+pub async fn execute_with_wallets(
+    action: Action,
+    client: Arc<RpcClient>,
+    wallets: &Wallets,
+    config: ActionConfig,
+) -> Result<Option<TxOutcome>> {
+    if let Action::BundleBuy { mint, amounts } = &action {
+        return bundle_buy(
+            client,
+            wallets,
+            mint,
+            amounts,
+            config.slippage,
+            config.simulate,
+        )
+        .await
+        .map(Some);
+    }
+    let payer = action.payer(wallets);
+    execute(action, client, &payer, config).await
+}
+
+async fn execute_action(
+    action: Action,
+    client: Arc<RpcClient>,
+    payer: &Keypair,
+    config: ActionConfig,
+) -> Result<Option<TxOutcome>> {
+    match action {
+        Action::Buy { mint, lamports } => buy_auto(
+            client,
+            payer,
+            &mint,
+            lamports,
+            config.slippage,
+            config.simulate,
+        )
+        .await
+        .map(Some),
+        Action::BuyTokens {
+            mint,
+            token_amount,
+            max_lamports,
+        } => buy_exact_tokens(
+            client,
+            payer,
+            &mint,
+            token_amount,
+            max_lamports,
+            config.slippage,
+            config.simulate,
+        )
+        .await
+        .map(Some),
+        Action::BundleBuy { .. } => Err(anyhow!("bundle buys need the wallets")),
+        Action::Sell { mint, pct } => {
+            sell_percentage(client, payer, &mint, pct, config.slippage, config.simulate)
+                .await
+                .map(Some)
+        }
+        Action::Dump { mint } => sell_all(client, payer, &mint, config.slippage, config.simulate)
+            .await
+            .map(Some),
+        Action::Pause => {
+            PAUSED.store(true, Ordering::Relaxed);
+            info!("strategies paused");
+            Ok(None)
+        }
+        Action::Resume => {
+            PAUSED.store(false, Ordering::Relaxed);
+            info!("strategies resumed");
+            Ok(None)
+        }
+    }
+}
+
+/// Executes the requests on `receiver` until every sender is dropped or
+/// `shutdown` is cancelled, each on `actions`
+pub async fn run_actions(
+    mut receiver: mpsc::Receiver<ActionRequest>,
+    client: Arc<RpcClient>,
+    wallets: Arc<Wallets>,
+    config: ActionConfig,
+    shutdown: CancellationToken,
+    actions: TaskTracker,
+) {
+    while let Some(Some(request)) = shutdown.run_until_cancelled(receiver.recv()).await {
+        let (client, wallets) = (client.clone(), wallets.clone());
+        // 每个请求单独执行，不阻塞后续请求
+        actions.spawn(async move {
+            info!("executing {:?}", request.action);
+            let result = execute_with_wallets(request.action, client, &wallets, config).await;
+            if let Err(e) = &result {
+                error!("action failed {:?}", e);
             }
+            // 请求方已经放弃时忽略
+            let _ = request.reply.send(result);
         });
-        Ok(set)
     }
 }
+
+/// The strategies the engine runs on the monitor's events
+#[derive(Default)]
+pub struct StrategyRegistry {
+    strategies: Vec<(Arc<dyn Strategy>, ActionConfig)>,
+}
+
+impl StrategyRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `strategy`, its actions executed with `config`
+    pub fn register(
+        &mut self,
+        strategy: impl Strategy + 'static,
+        config: ActionConfig,
+    ) -> &mut Self {
+        info!("registered strategy {}", strategy.name());
+        self.strategies.push((Arc::new(strategy), config));
+        self
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.strategies
+            .iter()
+            .map(|(strategy, _)| strategy.name())
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strategies.is_empty()
+    }
+
+    /// Actions of every strategy for `event`, with the strategy returning them
+    pub fn actions(&self, event: &MonitorEvent) -> Vec<(&'static str, Action)> {
+        self.strategies
+            .iter()
+            .flat_map(|(strategy, _)| {
+                strategy
+                    .on_event(event)
+                    .into_iter()
+                    .map(|action| (strategy.name(), action))
+            })
+            .collect()
+    }
+}
+
+/// Executes the actions of the registered strategies for every event on
+/// `events`, until the channel closes or `shutdown` is cancelled
+///
+/// While paused, the strategies' buys are dropped and their other actions
+/// still executed. The actions run on `actions`, which outlives this task so
+/// the caller can wait for the trades in flight before shutting down.
+pub async fn run_strategies(
+    registry: StrategyRegistry,
+    mut events: broadcast::Receiver<MonitorEvent>,
+    client: Arc<RpcClient>,
+    wallets: Arc<Wallets>,
+    shutdown: CancellationToken,
+    actions: TaskTracker,
+) {
+    while let Some(event) = shutdown.run_until_cancelled(events.recv()).await {
+        let event = match event {
+            Ok(event) => event,
+            Err(RecvError::Lagged(n)) => {
+                warn!("strategies lagged, skipped {} events", n);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        let received_at = event.received_at().unwrap_or_else(Instant::now);
+        let creator = match &event {
+            MonitorEvent::Create(event) => event.creator.parse::<Pubkey>().ok(),
+            _ => None,
+        };
+        for (strategy, config) in &registry.strategies {
+            for action in strategy.on_event(&event) {
+                if is_paused()
+                    && matches!(
+                        action,
+                        Action::Buy { .. } | Action::BuyTokens { .. } | Action::BundleBuy { .. }
+                    )
+                {
+                    info!("paused, {} not executing {:?}", strategy.name(), action);
+                    continue;
+                }
+                let config = strategy.action_config().unwrap_or(*config);
+                let strategy = strategy.clone();
+                let (client, wallets) = (client.clone(), wallets.clone());
+                // 每个动作单独执行，不阻塞后续事件
+                actions.spawn(timeline::triggered(
+                    strategy.name(),
+                    received_at,
+                    async move {
+                        if let (
+                            Action::Buy { mint, .. }
+                            | Action::BuyTokens { mint, .. }
+                            | Action::BundleBuy { mint, .. },
+                            Some(safety_config),
+                        ) = (&action, strategy.safety())
+                        {
+                            // 检查失败也不买
+                            match safety::check(
+                                client.clone(),
+                                safety_config,
+                                mint,
+                                creator.as_ref(),
+                            )
+                            .await
+                            {
+                                Ok(report) if safety_config.passes(&report) => {}
+                                Ok(report) => {
+                                    info!("not buying unsafe {}", report);
+                                    return;
+                                }
+                                Err(e) => {
+                                    warn!("not buying {}, safety check failed {:?}", mint, e);
+                                    return;
+                                }
+                            }
+                        }
+                        timeline::mark_decided();
+                        info!("executing {:?}", action);
+                        match execute_with_wallets(action, client, &wallets, config).await {
+                            Ok(outcome) => {
+                                info!("executed {:?}", outcome.as_ref().map(TxOutcome::signatures))
+                            }
+                            Err(e) => error!("strategy action failed {:?}", e),
+                        }
+                    },
+                ));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_registry_collects_actions() {
+    use crate::monitor::events::TxSentEvent;
+
+    struct BuyEverySend(u64);
+
+    impl Strategy for BuyEverySend {
+        fn name(&self) -> &'static str {
+            "buy_every_send"
+        }
+
+        fn on_event(&self, event: &MonitorEvent) -> Vec<Action> {
+            match event {
+                MonitorEvent::TxSent(_) => vec![Action::Buy {
+                    mint: Pubkey::default(),
+                    lamports: self.0,
+                }],
+                _ => vec![],
+            }
+        }
+    }
+
+    let config = ActionConfig {
+        slippage: DEFAULT_ACTION_SLIPPAGE,
+        simulate: true,
+        sender: Sender::Rpc,
+    };
+    let mut registry = StrategyRegistry::new();
+    assert!(registry.is_empty());
+    registry
+        .register(BuyEverySend(1), config)
+        .register(BuyEverySend(2), config);
+    assert_eq!(registry.names(), vec!["buy_every_send"; 2]);
+
+    let event = MonitorEvent::TxSent(TxSentEvent {
+        signature: String::new(),
+    });
+    let lamports: Vec<_> = registry
+        .actions(&event)
+        .into_iter()
+        .map(|(_, action)| match action {
+            Action::Buy { lamports, .. } => lamports,
+            _ => unreachable!(),
+        })
+        .collect();
+    assert_eq!(lamports, vec![1, 2]);
+}
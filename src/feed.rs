@@ -0,0 +1,317 @@
+//! Signed feed of the detected creates and migrations.
+//!
+//! Lets external systems (dashboards, other bots) consume the detections
+//! without Telegram. Every create and migration published on the events
+//! channel is wrapped in a [`FeedMessage`], numbered and timestamped, and its
+//! JSON body signed with HMAC-SHA256 keyed by `FEED_SECRET`, hex encoded:
+//!
+//! - `GET /feed` on `FEED_ADDR` (e.g. `0.0.0.0:8081`) streams them as SSE,
+//!   the SSE id the message's, each `data` a [`SignedMessage`] holding the
+//!   body as a string and its signature
+//! - every `;` separated URL of `FEED_WEBHOOKS` gets them POSTed, the body as
+//!   is with the signature in the [`SIGNATURE_HEADER`] header
+//!
+//! Consumers check the signature over the exact body bytes, see [`verify`],
+//! before parsing it. `FEED_ENABLED=true` starts the feed, `FEED_SECRET` is
+//! then required. The webhook posts are best effort: a failing endpoint is
+//! logged and the message dropped for it.
+
+use std::{
+    env,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+    routing::get,
+    Router,
+};
+use futures_util::{stream, Stream};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use tokio::{
+    net::TcpListener,
+    sync::broadcast::{self, error::RecvError},
+    task::{JoinHandle, JoinSet},
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    monitor::events::{self, MonitorEvent},
+    strategy::parse_env,
+};
+
+/// Header carrying the signature of a webhook post
+pub const SIGNATURE_HEADER: &str = "X-Feed-Signature";
+
+const FEED_CHANNEL_SIZE: usize = 1000;
+
+/// Numbers the messages, from 1
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A detection as sent on the feed
+#[derive(Debug, Clone, Serialize)]
+pub struct FeedMessage {
+    /// Increasing from 1 since the bot started
+    pub id: u64,
+    /// Unix timestamp the message was made at
+    pub timestamp: u64,
+    pub event: MonitorEvent,
+}
+
+/// A message's JSON body and its signature
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SignedMessage {
+    #[serde(skip)]
+    pub id: u64,
+    pub body: String,
+    /// Hex HMAC-SHA256 of `body`
+    pub signature: String,
+}
+
+impl SignedMessage {
+    pub fn new(secret: &[u8], message: &FeedMessage) -> Result<Self> {
+        let body = serde_json::to_string(message)?;
+        Ok(Self {
+            id: message.id,
+            signature: sign(secret, &body),
+            body,
+        })
+    }
+}
+
+fn mac(secret: &[u8]) -> Hmac<Sha256> {
+    Hmac::new_from_slice(secret).expect("HMAC takes keys of any size")
+}
+
+/// Hex HMAC-SHA256 of `body` keyed by `secret`
+pub fn sign(secret: &[u8], body: &str) -> String {
+    let mut mac = mac(secret);
+    mac.update(body.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Whether `signature` is the hex HMAC-SHA256 of `body` keyed by `secret`
+pub fn verify(secret: &[u8], body: &str, signature: &str) -> bool {
+    let bytes: Option<Vec<u8>> = (0..signature.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(signature.get(i..i + 2)?, 16).ok())
+        .collect();
+    let Some(bytes) = bytes else {
+        return false;
+    };
+    let mut mac = mac(secret);
+    mac.update(body.as_bytes());
+    // 常数时间比较
+    mac.verify_slice(&bytes).is_ok()
+}
+
+/// Whether `event` goes on the feed
+pub fn is_fed(event: &MonitorEvent) -> bool {
+    matches!(event, MonitorEvent::Create(_) | MonitorEvent::Migration(_))
+}
+
+#[derive(Debug, Clone)]
+pub struct FeedConfig {
+    pub secret: String,
+    /// Address `/feed` is served on, no SSE if `None`
+    pub addr: Option<SocketAddr>,
+    /// URLs every message is posted to
+    pub webhooks: Vec<String>,
+}
+
+impl FeedConfig {
+    /// The config if `FEED_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("FEED_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        let secret = env::var("FEED_SECRET").map_err(|_| anyhow!("FEED_SECRET is not set"))?;
+        if secret.is_empty() {
+            return Err(anyhow!("FEED_SECRET is empty"));
+        }
+        let webhooks: Vec<String> = env::var("FEED_WEBHOOKS")
+            .unwrap_or_default()
+            .split(';')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(String::from)
+            .collect();
+        let addr = parse_env("FEED_ADDR")?;
+        if addr.is_none() && webhooks.is_empty() {
+            return Err(anyhow!("the feed needs FEED_ADDR or FEED_WEBHOOKS"));
+        }
+        Ok(Some(Self {
+            secret,
+            addr,
+            webhooks,
+        }))
+    }
+}
+
+fn to_sse_event(message: &SignedMessage) -> Result<Event, axum::Error> {
+    Event::default()
+        .id(message.id.to_string())
+        .event("detection")
+        .json_data(message)
+}
+
+async fn feed_handler(
+    State(messages): State<broadcast::Sender<Arc<SignedMessage>>>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let stream = stream::unfold(messages.subscribe(), |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(message) => return Some((to_sse_event(&message), receiver)),
+                // 客户端太慢，跳过丢失的消息，按id能发现缺口
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("feed client lagged, skipped {} messages", skipped);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn post(client: reqwest::Client, url: String, message: Arc<SignedMessage>) -> Result<()> {
+    client
+        .post(&url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, &message.signature)
+        .body(message.body.clone())
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Signs the detections and sends them to the SSE clients and the webhooks,
+/// until the events channel closes
+async fn run(config: FeedConfig, messages: broadcast::Sender<Arc<SignedMessage>>) {
+    let client = reqwest::Client::new();
+    let mut receiver = events::subscribe();
+    let mut posts = JoinSet::new();
+    loop {
+        let event = match receiver.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("feed lagged, skipped {} events", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        if !is_fed(&event) {
+            continue;
+        }
+        let message = FeedMessage {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            event,
+        };
+        let message = match SignedMessage::new(config.secret.as_bytes(), &message) {
+            Ok(message) => Arc::new(message),
+            Err(e) => {
+                error!("failed to sign feed message {} {:?}", message.id, e);
+                continue;
+            }
+        };
+        // 没有SSE客户端时忽略
+        let _ = messages.send(message.clone());
+        // 清理已完成的推送，一个慢的端点不阻塞其他端点
+        while posts.try_join_next().is_some() {}
+        for url in &config.webhooks {
+            let (client, url, message) = (client.clone(), url.clone(), message.clone());
+            posts.spawn(async move {
+                let id = message.id;
+                match post(client, url.clone(), message).await {
+                    Ok(()) => debug!("posted feed message {} to {}", id, url),
+                    Err(e) => warn!("failed to post feed message {} to {} {:?}", id, url, e),
+                }
+            });
+        }
+    }
+}
+
+/// Starts the feed, serving `/feed` if `config.addr` is set
+pub async fn start(config: FeedConfig) -> Result<JoinHandle<()>> {
+    let (messages, _) = broadcast::channel(FEED_CHANNEL_SIZE);
+    if let Some(addr) = config.addr {
+        let listener = TcpListener::bind(addr).await?;
+        let app = Router::new()
+            .route("/feed", get(feed_handler))
+            .with_state(messages.clone());
+        info!("detection feed listening on {}", addr);
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                error!("detection feed stopped {:?}", e);
+            }
+        });
+    }
+    if !config.webhooks.is_empty() {
+        info!("posting detections to {} webhooks", config.webhooks.len());
+    }
+    Ok(tokio::spawn(run(config, messages)))
+}
+
+/// Starts the feed if `FEED_ENABLED=true`
+pub async fn start_from_env() -> Result<Option<JoinHandle<()>>> {
+    match FeedConfig::from_env()? {
+        Some(config) => Ok(Some(start(config).await?)),
+        None => Ok(None),
+    }
+}
+
+#[test]
+fn test_signed_messages() {
+    use crate::monitor::events::{MigrationEvent, MigrationVenue};
+
+    let secret = b"secret";
+    let message = FeedMessage {
+        id: 7,
+        timestamp: 1_700_000_000,
+        event: MonitorEvent::Migration(MigrationEvent {
+            venue: MigrationVenue::Raydium,
+            signature: "sig".to_string(),
+            coin_mint: "coin".to_string(),
+            pc_mint: "pc".to_string(),
+            pool_id: "pool".to_string(),
+            received_at: None,
+        }),
+    };
+    assert!(is_fed(&message.event));
+    let signed = SignedMessage::new(secret, &message).unwrap();
+    assert_eq!(signed.signature.len(), 64);
+    assert!(verify(secret, &signed.body, &signed.signature));
+    // 改动内容、换密钥或者签名不是十六进制都验证失败
+    assert!(!verify(
+        secret,
+        &signed.body.replace("pool", "fake"),
+        &signed.signature
+    ));
+    assert!(!verify(b"other", &signed.body, &signed.signature));
+    assert!(!verify(secret, &signed.body, "zz"));
+
+    let body: serde_json::Value = serde_json::from_str(&signed.body).unwrap();
+    assert_eq!(body["id"], 7);
+    assert_eq!(body["event"]["type"], "migration");
+    let data = serde_json::to_value(&signed).unwrap();
+    assert_eq!(data["body"], signed.body);
+    assert!(data.get("id").is_none());
+}
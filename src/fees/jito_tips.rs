@@ -0,0 +1,251 @@
+//! Jito tips tuned from the tip floor.
+//!
+//! A background task polls Jito's tip floor API, which reports percentiles of
+//! the tips paid by recently landed bundles, and [`jito_tip`] tips the
+//! configured percentile clamped to a floor and a cap. Until the first poll
+//! succeeds, when the last one is too old, or when tuning is off, the fixed
+//! `JITO_TIP_LAMPORTS` (default 100_000) is tipped instead.
+//!
+//! - `JITO_TIP_AUTO`: start the tuner, off by default
+//! - `JITO_TIP_FLOOR_URL`: tip floor endpoint
+//! - `JITO_TIP_PERCENTILE`: `25`, `50`, `75`, `95`, `99` or `ema50` (default 75)
+//! - `JITO_TIP_MIN_LAMPORTS` / `JITO_TIP_MAX_LAMPORTS`: bounds on the tuned tip,
+//!   default 1_000 and 1_000_000
+//! - `JITO_TIP_REFRESH_SECS`: seconds between polls (default 10)
+
+use std::{
+    env,
+    str::FromStr,
+    sync::{Arc, OnceLock, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use solana_sdk::native_token::sol_to_lamports;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::constants::jito::TIP_FLOOR_URL;
+
+/// How often the tip floor is polled
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Tuned tips older than this are not used
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(60);
+
+const DEFAULT_TIP_LAMPORTS: u64 = 100_000;
+/// Jito doesn't accept bundles tipping less than this
+const DEFAULT_MIN_TIP_LAMPORTS: u64 = 1_000;
+const DEFAULT_MAX_TIP_LAMPORTS: u64 = 1_000_000;
+
+static GLOBAL_TUNER: OnceLock<Arc<TipTuner>> = OnceLock::new();
+
+/// Landed tip percentiles, in SOL
+#[derive(Debug, Clone, Deserialize)]
+pub struct TipFloor {
+    pub landed_tips_25th_percentile: f64,
+    pub landed_tips_50th_percentile: f64,
+    pub landed_tips_75th_percentile: f64,
+    pub landed_tips_95th_percentile: f64,
+    pub landed_tips_99th_percentile: f64,
+    pub ema_landed_tips_50th_percentile: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TipPercentile {
+    P25,
+    P50,
+    P75,
+    P95,
+    P99,
+    /// Moving average of the median
+    Ema50,
+}
+
+impl FromStr for TipPercentile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "25" => Ok(Self::P25),
+            "50" => Ok(Self::P50),
+            "75" => Ok(Self::P75),
+            "95" => Ok(Self::P95),
+            "99" => Ok(Self::P99),
+            "ema50" => Ok(Self::Ema50),
+            _ => Err(anyhow!("unknown tip percentile {:?}", s)),
+        }
+    }
+}
+
+impl TipFloor {
+    /// Tip at `percentile`, in SOL
+    pub fn percentile(&self, percentile: TipPercentile) -> f64 {
+        match percentile {
+            TipPercentile::P25 => self.landed_tips_25th_percentile,
+            TipPercentile::P50 => self.landed_tips_50th_percentile,
+            TipPercentile::P75 => self.landed_tips_75th_percentile,
+            TipPercentile::P95 => self.landed_tips_95th_percentile,
+            TipPercentile::P99 => self.landed_tips_99th_percentile,
+            TipPercentile::Ema50 => self.ema_landed_tips_50th_percentile,
+        }
+    }
+}
+
+pub struct TipTuner {
+    client: reqwest::Client,
+    url: String,
+    percentile: TipPercentile,
+    min_tip: u64,
+    max_tip: u64,
+    max_age: Duration,
+    latest: RwLock<Option<(u64, Instant)>>,
+}
+
+impl TipTuner {
+    pub fn new(url: String, percentile: TipPercentile, min_tip: u64, max_tip: u64) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+            percentile,
+            min_tip,
+            max_tip,
+            max_age: DEFAULT_MAX_AGE,
+            latest: RwLock::new(None),
+        }
+    }
+
+    /// Reads `JITO_TIP_FLOOR_URL`, `JITO_TIP_PERCENTILE` and the tip bounds
+    pub fn from_env() -> Result<Self> {
+        dotenv::dotenv().ok();
+        let url = env::var("JITO_TIP_FLOOR_URL").unwrap_or(TIP_FLOOR_URL.to_string());
+        let percentile = match env::var("JITO_TIP_PERCENTILE") {
+            Ok(v) => v.parse()?,
+            Err(_) => TipPercentile::P75,
+        };
+        let lamports = |key: &str, default: u64| -> Result<u64> {
+            match env::var(key) {
+                Ok(v) => v.parse().map_err(|_| anyhow!("invalid {} {:?}", key, v)),
+                Err(_) => Ok(default),
+            }
+        };
+        let min_tip = lamports("JITO_TIP_MIN_LAMPORTS", DEFAULT_MIN_TIP_LAMPORTS)?;
+        let max_tip = lamports("JITO_TIP_MAX_LAMPORTS", DEFAULT_MAX_TIP_LAMPORTS)?;
+        if min_tip > max_tip {
+            return Err(anyhow!(
+                "JITO_TIP_MIN_LAMPORTS {} is above JITO_TIP_MAX_LAMPORTS {}",
+                min_tip,
+                max_tip
+            ));
+        }
+        Ok(Self::new(url, percentile, min_tip, max_tip))
+    }
+
+    /// Tip for `floor`, clamped to the bounds
+    pub fn tip_for(&self, floor: &TipFloor) -> u64 {
+        sol_to_lamports(floor.percentile(self.percentile)).clamp(self.min_tip, self.max_tip)
+    }
+
+    /// Polls the tip floor and stores the tuned tip
+    pub async fn refresh(&self) -> Result<u64> {
+        let floors: Vec<TipFloor> = self
+            .client
+            .get(&self.url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let floor = floors
+            .first()
+            .ok_or_else(|| anyhow!("empty tip floor response"))?;
+        let tip = self.tip_for(floor);
+        *self.latest.write().unwrap() = Some((tip, Instant::now()));
+        Ok(tip)
+    }
+
+    /// Returns the tuned tip if it is younger than the max age
+    pub fn get(&self) -> Option<u64> {
+        self.latest
+            .read()
+            .unwrap()
+            .filter(|(_, fetched_at)| fetched_at.elapsed() <= self.max_age)
+            .map(|(tip, _)| tip)
+    }
+
+    /// Spawns the task polling the tip floor every `interval`
+    pub fn spawn_refresh(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.refresh().await {
+                    warn!("failed to refresh jito tip floor {:?}", e);
+                }
+            }
+        })
+    }
+}
+
+/// Starts the tuner and installs it process wide if `JITO_TIP_AUTO=true`
+pub fn start_from_env() -> Result<Option<JoinHandle<()>>> {
+    dotenv::dotenv().ok();
+    if env::var("JITO_TIP_AUTO").map_or(true, |v| v != "true") {
+        return Ok(None);
+    }
+    let interval = match env::var("JITO_TIP_REFRESH_SECS") {
+        Ok(v) => Duration::from_secs(
+            v.parse()
+                .map_err(|_| anyhow!("invalid JITO_TIP_REFRESH_SECS {:?}", v))?,
+        ),
+        Err(_) => DEFAULT_REFRESH_INTERVAL,
+    };
+    let tuner = Arc::new(TipTuner::from_env()?);
+    GLOBAL_TUNER
+        .set(tuner.clone())
+        .map_err(|_| anyhow!("jito tip tuner already started"))?;
+    info!("tuning jito tips from {}", tuner.url);
+    Ok(Some(tuner.spawn_refresh(interval)))
+}
+
+/// Fixed tip from `JITO_TIP_LAMPORTS`
+fn fixed_tip() -> u64 {
+    env::var("JITO_TIP_LAMPORTS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_TIP_LAMPORTS)
+}
+
+/// Lamports to tip the next bundle
+pub fn jito_tip() -> u64 {
+    GLOBAL_TUNER
+        .get()
+        .and_then(|tuner| tuner.get())
+        .unwrap_or_else(fixed_tip)
+}
+
+#[test]
+fn test_tip_follows_percentile_within_bounds() {
+    let floors: Vec<TipFloor> = serde_json::from_str(
+        r#"[{"time":"2025-01-20T10:00:00Z","landed_tips_25th_percentile":0.000001,"landed_tips_50th_percentile":0.00001,"landed_tips_75th_percentile":0.00005,"landed_tips_95th_percentile":0.0015,"landed_tips_99th_percentile":0.01,"ema_landed_tips_50th_percentile":0.000012}]"#,
+    )
+    .unwrap();
+    let floor = &floors[0];
+
+    let tuner = TipTuner::new(String::new(), TipPercentile::P75, 1_000, 1_000_000);
+    assert_eq!(tuner.tip_for(floor), 50_000);
+    assert_eq!(tuner.get(), None);
+
+    // 低于下限和高于上限时截断
+    let low = TipTuner::new(String::new(), TipPercentile::P25, 1_000, 1_000_000);
+    assert_eq!(low.tip_for(floor), 1_000);
+    let high = TipTuner::new(String::new(), TipPercentile::P99, 1_000, 1_000_000);
+    assert_eq!(high.tip_for(floor), 1_000_000);
+
+    assert_eq!(
+        "ema50".parse::<TipPercentile>().unwrap(),
+        TipPercentile::Ema50
+    );
+    assert!("90".parse::<TipPercentile>().is_err());
+}
@@ -0,0 +1,4 @@
+//! Fees attached to the bot's transactions.
+
+pub mod jito_tips;
+pub mod priority;
@@ -0,0 +1,216 @@
+//! Priority fees estimated from recent blocks.
+//!
+//! With `PRIORITY_FEE_AUTO=true` the compute unit price of
+//! `new_signed_and_send` is the configured percentile of the fees paid in
+//! recent slots by transactions write-locking the same accounts, as reported
+//! by `getRecentPrioritizationFees`. If `HELIUS_PRIORITY_FEE_URL` is set,
+//! Helius' `getPriorityFeeEstimate` is asked first. The configured
+//! `unit_price` is used when estimation is off or fails.
+//!
+//! - `PRIORITY_FEE_PERCENTILE`: percentile of the recent fees (default 75)
+//! - `PRIORITY_FEE_MIN` / `PRIORITY_FEE_MAX`: bounds on the estimate, in
+//!   micro-lamports per compute unit, default 0 and 1_000_000
+//! - `HELIUS_PRIORITY_LEVEL`: Helius priority level (default `High`)
+
+use std::{env, sync::LazyLock};
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use tracing::warn;
+
+use crate::config::bot_config;
+
+const DEFAULT_PERCENTILE: u8 = 75;
+const DEFAULT_MAX_UNIT_PRICE: u64 = 1_000_000;
+const DEFAULT_HELIUS_PRIORITY_LEVEL: &str = "High";
+/// `getRecentPrioritizationFees` accepts at most this many accounts
+const MAX_LOCKED_ACCOUNTS: usize = 128;
+
+static ESTIMATOR: LazyLock<Option<PriorityFeeEstimator>> = LazyLock::new(|| {
+    PriorityFeeEstimator::from_env().unwrap_or_else(|e| {
+        warn!("priority fee estimation disabled {:?}", e);
+        None
+    })
+});
+
+pub struct PriorityFeeEstimator {
+    percentile: u8,
+    min_price: u64,
+    max_price: u64,
+    /// Helius RPC url and priority level
+    helius: Option<(String, String)>,
+    http: reqwest::Client,
+}
+
+impl PriorityFeeEstimator {
+    pub fn new(percentile: u8, min_price: u64, max_price: u64) -> Self {
+        Self {
+            percentile: percentile.min(100),
+            min_price,
+            max_price,
+            helius: None,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Also asks Helius at `url` for `priority_level` estimates
+    pub fn with_helius(mut self, url: String, priority_level: String) -> Self {
+        self.helius = Some((url, priority_level));
+        self
+    }
+
+    /// Reads the `PRIORITY_FEE_*` variables, `None` unless `PRIORITY_FEE_AUTO=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if env::var("PRIORITY_FEE_AUTO").map_or(true, |v| v != "true") {
+            return Ok(None);
+        }
+        fn parse<T: std::str::FromStr>(key: &str, default: T) -> Result<T> {
+            match env::var(key) {
+                Ok(v) => v.parse().map_err(|_| anyhow!("invalid {} {:?}", key, v)),
+                Err(_) => Ok(default),
+            }
+        }
+        let estimator = Self::new(
+            parse("PRIORITY_FEE_PERCENTILE", DEFAULT_PERCENTILE)?,
+            parse("PRIORITY_FEE_MIN", 0)?,
+            parse("PRIORITY_FEE_MAX", DEFAULT_MAX_UNIT_PRICE)?,
+        );
+        Ok(Some(match env::var("HELIUS_PRIORITY_FEE_URL") {
+            Ok(url) => estimator.with_helius(
+                url,
+                env::var("HELIUS_PRIORITY_LEVEL")
+                    .unwrap_or(DEFAULT_HELIUS_PRIORITY_LEVEL.to_string()),
+            ),
+            Err(_) => estimator,
+        }))
+    }
+
+    /// Estimated compute unit price for a transaction write-locking `accounts`
+    pub async fn estimate(&self, client: &RpcClient, accounts: &[Pubkey]) -> Result<u64> {
+        let price = match &self.helius {
+            Some((url, level)) => match self.helius_estimate(url, level, accounts).await {
+                Ok(price) => price,
+                Err(e) => {
+                    warn!("helius priority fee failed, using rpc {:?}", e);
+                    self.rpc_estimate(client, accounts).await?
+                }
+            },
+            None => self.rpc_estimate(client, accounts).await?,
+        };
+        Ok(price.clamp(self.min_price, self.max_price))
+    }
+
+    async fn rpc_estimate(&self, client: &RpcClient, accounts: &[Pubkey]) -> Result<u64> {
+        let accounts = &accounts[..accounts.len().min(MAX_LOCKED_ACCOUNTS)];
+        let mut fees: Vec<u64> = client
+            .get_recent_prioritization_fees(accounts)
+            .await?
+            .into_iter()
+            .map(|fee| fee.prioritization_fee)
+            .collect();
+        percentile(&mut fees, self.percentile)
+            .ok_or_else(|| anyhow!("no recent prioritization fees"))
+    }
+
+    async fn helius_estimate(&self, url: &str, level: &str, accounts: &[Pubkey]) -> Result<u64> {
+        let accounts: Vec<String> = accounts.iter().map(Pubkey::to_string).collect();
+        let response: Value = self
+            .http
+            .post(url)
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "getPriorityFeeEstimate",
+                "params": [{
+                    "accountKeys": accounts,
+                    "options": { "priorityLevel": level },
+                }],
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        response["result"]["priorityFeeEstimate"]
+            .as_f64()
+            .map(|fee| fee.ceil() as u64)
+            .ok_or_else(|| anyhow!("unexpected helius response {}", response))
+    }
+}
+
+/// Value at `pct` percent of `values`, nearest rank
+pub fn percentile(values: &mut [u64], pct: u8) -> Option<u64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    let rank = (values.len() * pct.min(100) as usize).div_ceil(100);
+    Some(values[rank.saturating_sub(1)])
+}
+
+/// Accounts `instructions` write-lock, in order of first use
+pub fn writable_accounts(instructions: &[Instruction]) -> Vec<Pubkey> {
+    let mut accounts = vec![];
+    for meta in instructions.iter().flat_map(|ix| &ix.accounts) {
+        if meta.is_writable && !accounts.contains(&meta.pubkey) {
+            accounts.push(meta.pubkey);
+        }
+    }
+    accounts
+}
+
+/// Compute unit price for `instructions`, estimated if enabled, the
+/// configured `unit_price` otherwise
+pub async fn unit_price(client: &RpcClient, instructions: &[Instruction]) -> u64 {
+    let Some(estimator) = ESTIMATOR.as_ref() else {
+        return bot_config().unit_price;
+    };
+    match estimator
+        .estimate(client, &writable_accounts(instructions))
+        .await
+    {
+        Ok(price) => price,
+        Err(e) => {
+            warn!("failed to estimate priority fee {:?}", e);
+            bot_config().unit_price
+        }
+    }
+}
+
+#[test]
+fn test_percentile_and_writable_accounts() {
+    use solana_sdk::instruction::AccountMeta;
+
+    let mut fees = vec![0, 0, 10, 500, 20, 0, 1000, 30, 40, 50];
+    assert_eq!(percentile(&mut fees, 50), Some(20));
+    assert_eq!(percentile(&mut fees, 75), Some(50));
+    assert_eq!(percentile(&mut fees, 100), Some(1000));
+    assert_eq!(percentile(&mut fees, 0), Some(0));
+    assert_eq!(percentile(&mut [], 75), None);
+
+    let (a, b, c) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let program = Pubkey::new_unique();
+    let instructions = [
+        Instruction::new_with_bytes(
+            program,
+            &[],
+            vec![
+                AccountMeta::new(a, true),
+                AccountMeta::new_readonly(b, false),
+            ],
+        ),
+        Instruction::new_with_bytes(
+            program,
+            &[],
+            vec![AccountMeta::new(c, false), AccountMeta::new(a, false)],
+        ),
+    ];
+    assert_eq!(writable_accounts(&instructions), vec![a, c]);
+}
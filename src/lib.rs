@@ -1,26 +1,93 @@
+pub mod analysis;
+pub mod api;
+pub mod backtest;
+pub mod config;
 mod constants;
-mod engine;
+pub mod datasources;
+pub mod dex;
+pub mod engine;
+pub mod feed;
+pub mod fees;
+pub mod idl;
+pub mod marketdata;
+pub mod math;
+pub mod metrics;
 mod monitor;
-mod pumpfun;
-mod raydium;
-mod strategy;
+pub mod notify;
+pub mod orca;
+pub mod portfolio;
+pub mod pumpfun;
+pub mod pumpswap;
+pub mod quote;
+pub mod raydium;
+pub mod risk;
+pub mod router;
+pub mod rpc;
+pub mod safety;
+pub mod storage;
+pub mod strategy;
+pub mod timeline;
+pub mod tx;
+pub mod wallet;
 
+pub use monitor::bonding_curve;
+pub use monitor::creator;
+pub use monitor::diagnostics;
+pub use monitor::discord;
+pub use monitor::events;
+pub use monitor::lag;
+pub use monitor::pending_swaps;
+pub use monitor::telegram_channels;
 pub use monitor::token_create::listen_pumpfun_create;
 pub use monitor::token_migration::listen_rayidum_migration;
+pub use monitor::wallet_tracker;
+pub use monitor::DEFAULT_CHANNEL_SIZE;
 
+static RPC_CLIENT: std::sync::OnceLock<
+    std::sync::Arc<solana_client::nonblocking::rpc_client::RpcClient>,
+> = std::sync::OnceLock::new();
+
+pub(crate) fn get_rpc_timeout() -> std::time::Duration {
+    std::env::var("RPC_TIMEOUT_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(std::time::Duration::from_millis)
+        .unwrap_or(std::time::Duration::from_secs(10))
+}
+
+pub(crate) fn get_rpc_commitment() -> solana_sdk::commitment_config::CommitmentConfig {
+    std::env::var("RPC_COMMITMENT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(solana_sdk::commitment_config::CommitmentConfig::confirmed())
+}
+
+/// Shared rpc client for the configured `rpc_url`, built once with
+/// `RPC_TIMEOUT_MS` and `RPC_COMMITMENT`
+///
+/// When the multi rpc client was started, the fastest healthy endpoint is
+/// returned instead.
 pub fn new_client() -> std::sync::Arc<solana_client::nonblocking::rpc_client::RpcClient> {
-    dotenv::dotenv().ok();
-    std::sync::Arc::new(solana_client::nonblocking::rpc_client::RpcClient::new(
-        std::env::var("RPC_URL").unwrap(),
-    ))
+    if let Some(multi) = rpc::multi::global_multi_client() {
+        return multi.read_client();
+    }
+    RPC_CLIENT
+        .get_or_init(|| {
+            dotenv::dotenv().ok();
+            std::sync::Arc::new(rpc::limiter::new_rpc_client(
+                config::bot_config().rpc_url.clone(),
+                get_rpc_timeout(),
+                get_rpc_commitment(),
+            ))
+        })
+        .clone()
 }
 
 pub async fn new_ws_client(
 ) -> anyhow::Result<std::sync::Arc<solana_client::nonblocking::pubsub_client::PubsubClient>> {
-    dotenv::dotenv().ok();
     Ok(std::sync::Arc::new(
         solana_client::nonblocking::pubsub_client::PubsubClient::new(
-            std::env::var("WS_RPC_URL").unwrap().as_str(),
+            &config::bot_config().ws_rpc_url,
         )
         .await?,
     ))
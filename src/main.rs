@@ -1,8 +1,764 @@
-use raydium_swap::{listen_pumpfun_create, listen_rayidum_migration, new_ws_client};
+use std::{cell::OnceCell, fs, path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use clap::{Args, Parser, Subcommand};
+use solana_sdk::{native_token::sol_to_lamports, pubkey::Pubkey, signature::read_keypair_file};
+use teloxide::Bot;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::info;
+
+use raydium_swap::{
+    api,
+    backtest::{Backtest, BacktestConfig},
+    bonding_curve,
+    config::{self, BotConfig},
+    discord,
+    engine::{self, Action, ActionConfig, Engine, StrategyRegistry},
+    feed,
+    fees::jito_tips,
+    idl, listen_pumpfun_create, listen_rayidum_migration, metrics, new_client, new_ws_client,
+    notify::{
+        self,
+        telegram::{commands, TelegramNotifier},
+        StdoutNotifier,
+    },
+    pending_swaps,
+    pumpfun::operation,
+    raydium::{
+        open_time,
+        swap::{get_swap_tx, SwapAmount},
+    },
+    router,
+    rpc::{keepalive, limiter, multi},
+    storage::{self, JournalFormat},
+    strategy::{
+        arbitrage::{self, Arbitrage, ArbitrageConfig},
+        exits, migration,
+        params::{self, LiveParams, LiveSniper},
+        signals, sniper,
+    },
+    telegram_channels,
+    tx::{
+        blockhash, budget, leader_tracker,
+        mode::{execution_mode, set_execution_mode, ExecutionMode},
+        sender::{self, Sender},
+        simulate::TxOutcome,
+    },
+    wallet::{self, keystore, Wallets},
+    wallet_tracker, DEFAULT_CHANNEL_SIZE,
+};
+
+#[derive(Parser)]
+#[command(version, about = "Solana trading bot")]
+struct Cli {
+    /// live, simulate or paper, overrides `EXECUTION_MODE`
+    #[arg(long, global = true)]
+    mode: Option<ExecutionMode>,
+    /// Runs the whole bot when omitted
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Runs the monitors and every strategy enabled in the environment
+    Run,
+    /// Runs a single monitor
+    #[command(subcommand)]
+    Monitor(MonitorCommand),
+    /// Swaps an exact input amount through a Raydium pool
+    Swap {
+        #[arg(long)]
+        token_in: Pubkey,
+        #[arg(long)]
+        token_out: Pubkey,
+        /// Input amount in ui units
+        #[arg(long)]
+        amount: f64,
+        #[arg(long)]
+        pool: Pubkey,
+        #[command(flatten)]
+        trade: TradeArgs,
+    },
+    /// Swaps through two Raydium pools via an intermediate token, e.g. into
+    /// USDC via WSOL
+    Route {
+        #[arg(long)]
+        token_in: Pubkey,
+        #[arg(long)]
+        via: Pubkey,
+        #[arg(long)]
+        token_out: Pubkey,
+        /// Input amount in ui units
+        #[arg(long)]
+        amount: f64,
+        #[command(flatten)]
+        trade: TradeArgs,
+    },
+    /// Buys a token on its bonding curve, or its Raydium pool once migrated
+    Buy {
+        #[arg(long)]
+        mint: Pubkey,
+        /// SOL to spend
+        #[arg(long)]
+        sol: f64,
+        #[command(flatten)]
+        trade: TradeArgs,
+    },
+    /// Buys an exact amount of a token on its bonding curve
+    BuyTokens {
+        #[arg(long)]
+        mint: Pubkey,
+        /// Tokens to buy
+        #[arg(
+            long,
+            required_unless_present = "supply_pct",
+            conflicts_with = "supply_pct"
+        )]
+        tokens: Option<f64>,
+        /// Or the share of the supply to buy, in percent
+        #[arg(long)]
+        supply_pct: Option<f64>,
+        /// Most SOL to spend, slippage included
+        #[arg(long)]
+        max_sol: f64,
+        #[command(flatten)]
+        trade: TradeArgs,
+    },
+    /// Buys through a Raydium pool waiting for its open time, in the slot it
+    /// opens
+    OpenBuy {
+        #[arg(long)]
+        pool: Pubkey,
+        /// SOL to spend
+        #[arg(long)]
+        sol: f64,
+        /// Also send the buy as a Jito bundle in each of this many slots
+        /// before the open
+        #[arg(long, default_value_t = 0)]
+        spam_slots: u64,
+        #[command(flatten)]
+        trade: TradeArgs,
+    },
+    /// Sells a share of the wallet's balance of a token
+    Sell {
+        #[arg(long)]
+        mint: Pubkey,
+        /// Percentage of the balance to sell
+        #[arg(long, default_value_t = 100.0)]
+        pct: f64,
+        #[command(flatten)]
+        trade: TradeArgs,
+    },
+    /// Sells the whole balance of a token and closes its account
+    Dump {
+        #[arg(long)]
+        mint: Pubkey,
+        #[command(flatten)]
+        trade: TradeArgs,
+    },
+    /// Buys new Pump.fun tokens passing the `SNIPER_*` filters
+    Snipe {
+        /// SOL spent per buy, overrides `SNIPER_BUY_SOL`
+        #[arg(long)]
+        sol: Option<f64>,
+        /// Slippage in percent, overrides `SNIPER_SLIPPAGE`
+        #[arg(long)]
+        slippage: Option<u64>,
+        /// Only simulate the buys
+        #[arg(long)]
+        simulate: bool,
+    },
+    /// Arbitrages tokens across Pump.fun, Raydium and PumpSwap
+    Arb {
+        /// Mint to watch, repeat for several
+        #[arg(long = "mint", required = true)]
+        mints: Vec<Pubkey>,
+        /// SOL spent on the buy leg
+        #[arg(long, default_value_t = 0.1)]
+        sol: f64,
+        /// Profit after fees required, in SOL
+        #[arg(long, default_value_t = 0.001)]
+        min_profit_sol: f64,
+        /// Slippage on the buy leg in basis points
+        #[arg(long, default_value_t = 50)]
+        slippage_bps: u64,
+        /// Milliseconds between polls
+        #[arg(long, default_value_t = 1000)]
+        poll_ms: u64,
+        /// Send as a Jito bundle
+        #[arg(long)]
+        jito: bool,
+        /// Only simulate the transactions
+        #[arg(long)]
+        simulate: bool,
+        /// Send live round trips without simulating them first
+        #[arg(long)]
+        no_preflight: bool,
+        /// Simulated profit after fees required to send, in SOL, defaults to
+        /// `--min-profit-sol`
+        #[arg(long)]
+        preflight_min_profit_sol: Option<f64>,
+    },
+    /// Replays historical blocks through the sniper and exits with simulated
+    /// fills, see `BACKTEST_*`
+    Backtest {
+        /// Blocks dumped by an earlier `--save`
+        #[arg(long, conflicts_with_all = ["start_slot", "end_slot"])]
+        file: Option<PathBuf>,
+        /// First slot to fetch with `getBlock`
+        #[arg(long, requires = "end_slot")]
+        start_slot: Option<u64>,
+        /// Last slot to fetch, inclusive
+        #[arg(long, requires = "start_slot")]
+        end_slot: Option<u64>,
+        /// Dumps the fetched blocks here for later runs
+        #[arg(long, requires = "start_slot")]
+        save: Option<PathBuf>,
+    },
+    /// Exports the stored fills as a trade journal
+    Export {
+        /// csv or json
+        #[arg(long, default_value_t = JournalFormat::Csv)]
+        format: JournalFormat,
+        /// Only fills from this unix timestamp on
+        #[arg(long, default_value_t = 0)]
+        since: u64,
+        /// Written to stdout when omitted
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Encrypts a keypair file into a keystore with `WALLET_KEYSTORE_PASSWORD`
+    EncryptKey {
+        /// `solana-keygen` keypair file
+        #[arg(long)]
+        keypair: PathBuf,
+        #[arg(long)]
+        out: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum MonitorCommand {
+    /// Pump.fun token creates
+    Create,
+    /// Raydium and PumpSwap migrations
+    Migration,
+    /// Raydium swaps before they land, from `PENDING_SWAPS_WS_URL`
+    PendingSwaps,
+    /// Bonding curve progress of Pump.fun tokens until they complete
+    Curves { mints: Vec<Pubkey> },
+}
+
+#[derive(Args)]
+struct TradeArgs {
+    /// Slippage in percent
+    #[arg(long, default_value_t = 10)]
+    slippage: u64,
+    /// Only simulate the transaction
+    #[arg(long)]
+    simulate: bool,
+    /// Submit through rpc, jito, helius, bloxroute or tpu
+    #[arg(long, default_value_t = Sender::Rpc)]
+    sender: Sender,
+}
+
+impl From<&TradeArgs> for ActionConfig {
+    fn from(trade: &TradeArgs) -> Self {
+        ActionConfig {
+            slippage: trade.slippage,
+            simulate: trade.simulate,
+            sender: trade.sender,
+        }
+    }
+}
 
 #[tokio::main]
-async fn main() {
-    let ws_client = new_ws_client().await.unwrap();
-    let set = listen_pumpfun_create(ws_client, 1000).await.unwrap();
-    set.join_all().await;
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    if let Some(mode) = cli.mode {
+        set_execution_mode(mode)?;
+    }
+    let bot_config = config::init()?;
+    idl::init()?;
+    limiter::init()?;
+    budget::init()?;
+    info!("execution mode {}", execution_mode());
+    multi::start_from_env().await?;
+    keepalive::start_from_env().await?;
+    leader_tracker::start_from_env(new_client()).await?;
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => run(bot_config).await,
+        Command::Monitor(monitor) => {
+            let ws_client = new_ws_client().await?;
+            let notifier = notify::from_env()?;
+            let diagnostics = TelegramNotifier::diagnostics_from_env()?;
+            let mut _curve_watcher = None;
+            let (mut set, _events) = match monitor {
+                MonitorCommand::Create => {
+                    listen_pumpfun_create(ws_client, notifier, DEFAULT_CHANNEL_SIZE).await?
+                }
+                MonitorCommand::Migration => {
+                    listen_rayidum_migration(ws_client, notifier, DEFAULT_CHANNEL_SIZE).await?
+                }
+                // 数量太多，只打印
+                MonitorCommand::PendingSwaps => {
+                    pending_swaps::listen_pending_swaps(
+                        pending_swaps::ws_url_from_env()?,
+                        Arc::new(StdoutNotifier),
+                        DEFAULT_CHANNEL_SIZE,
+                    )
+                    .await?
+                }
+                MonitorCommand::Curves { mints } => {
+                    let (set, watcher) = bonding_curve::listen_bonding_curves(
+                        ws_client,
+                        &mints,
+                        notifier,
+                        DEFAULT_CHANNEL_SIZE,
+                    )?;
+                    let events = watcher.event_sender();
+                    // 监听期间保留watcher
+                    _curve_watcher = Some(watcher);
+                    (set, events)
+                }
+            };
+            if let Some(diagnostics) = diagnostics {
+                set.spawn(diagnostics.forward_diagnostics());
+            }
+            set.join_all().await;
+            Ok(())
+        }
+        Command::Swap {
+            token_in,
+            token_out,
+            amount,
+            pool,
+            trade,
+        } => {
+            start_trading().await?;
+            let token_in = token_in.to_string();
+            let token_out = token_out.to_string();
+            let pool = pool.to_string();
+            let swap = get_swap_tx(
+                new_client(),
+                &token_in,
+                &token_out,
+                amount,
+                &pool,
+                trade.slippage,
+                Arc::new(bot_config.keypair()?),
+                trade.simulate,
+            );
+            let outcome = sender::with_sender(trade.sender, swap).await?;
+            print_outcome(Some(outcome));
+            Ok(())
+        }
+        Command::Route {
+            token_in,
+            via,
+            token_out,
+            amount,
+            trade,
+        } => {
+            start_trading().await?;
+            let swap = router::swap_two_hop(
+                new_client(),
+                Arc::new(bot_config.keypair()?),
+                &token_in,
+                &via,
+                &token_out,
+                SwapAmount::Ui(amount),
+                trade.slippage,
+                trade.simulate,
+            );
+            let outcome = sender::with_sender(trade.sender, swap).await?;
+            print_outcome(Some(outcome));
+            Ok(())
+        }
+        Command::Buy { mint, sol, trade } => {
+            let action = Action::Buy {
+                mint,
+                lamports: sol_to_lamports(sol),
+            };
+            execute(bot_config, action, &trade).await
+        }
+        Command::BuyTokens {
+            mint,
+            tokens,
+            supply_pct,
+            max_sol,
+            trade,
+        } => {
+            let token_amount = match (tokens, supply_pct) {
+                (Some(tokens), _) => operation::raw_tokens(tokens),
+                (None, Some(pct)) => operation::supply_tokens(pct)?,
+                (None, None) => return Err(anyhow!("--tokens or --supply-pct is required")),
+            };
+            let action = Action::BuyTokens {
+                mint,
+                token_amount,
+                max_lamports: sol_to_lamports(max_sol),
+            };
+            execute(bot_config, action, &trade).await
+        }
+        Command::OpenBuy {
+            pool,
+            sol,
+            spam_slots,
+            trade,
+        } => {
+            start_trading().await?;
+            let buy = open_time::buy_at_open(
+                new_client(),
+                Arc::new(bot_config.keypair()?),
+                &pool,
+                sol_to_lamports(sol),
+                trade.slippage,
+                spam_slots,
+                trade.simulate,
+            );
+            let outcome = sender::with_sender(trade.sender, buy).await?;
+            print_outcome(Some(outcome));
+            Ok(())
+        }
+        Command::Sell { mint, pct, trade } => {
+            execute(bot_config, Action::Sell { mint, pct }, &trade).await
+        }
+        Command::Dump { mint, trade } => execute(bot_config, Action::Dump { mint }, &trade).await,
+        Command::Snipe {
+            sol,
+            slippage,
+            simulate,
+        } => {
+            let mut sniper_config = sniper::SniperConfig::read_env()?;
+            if let Some(sol) = sol {
+                sniper_config.buy_amount = sol_to_lamports(sol);
+            }
+            if let Some(slippage) = slippage {
+                sniper_config.slippage = slippage;
+            }
+            sniper_config.simulate |= simulate;
+            start_trading().await?;
+            let ws_client = new_ws_client().await?;
+            let (mut set, events) =
+                listen_pumpfun_create(ws_client, notify::from_env()?, DEFAULT_CHANNEL_SIZE).await?;
+            let mut registry = StrategyRegistry::new();
+            let action_config = sniper_config.action_config();
+            registry.register(sniper_config, action_config);
+            set.spawn(engine::run_strategies(
+                registry,
+                events.subscribe(),
+                new_client(),
+                Arc::new(Wallets::from_env(bot_config.keypair()?)?),
+                CancellationToken::new(),
+                TaskTracker::new(),
+            ));
+            set.join_all().await;
+            Ok(())
+        }
+        Command::Arb {
+            mints,
+            sol,
+            min_profit_sol,
+            slippage_bps,
+            poll_ms,
+            jito,
+            simulate,
+            no_preflight,
+            preflight_min_profit_sol,
+        } => {
+            start_trading().await?;
+            let arbitrage_config = ArbitrageConfig {
+                mints,
+                amount: sol_to_lamports(sol),
+                min_profit: sol_to_lamports(min_profit_sol),
+                slippage_bps,
+                poll_interval: Duration::from_millis(poll_ms),
+                use_jito: jito,
+                simulate,
+                preflight: !no_preflight,
+                preflight_min_profit: sol_to_lamports(
+                    preflight_min_profit_sol.unwrap_or(min_profit_sol),
+                ),
+            };
+            let shutdown = CancellationToken::new();
+            tokio::spawn(engine::cancel_on_ctrl_c(shutdown.clone()));
+            arbitrage::run(
+                Arbitrage::with_default_sources(arbitrage_config),
+                new_client(),
+                Arc::new(bot_config.keypair()?),
+                shutdown,
+            )
+            .await;
+            Ok(())
+        }
+        Command::Backtest {
+            file,
+            start_slot,
+            end_slot,
+            save,
+        } => {
+            let mut backtest = Backtest::new(BacktestConfig::from_env()?);
+            match (file, start_slot, end_slot) {
+                (Some(file), _, _) => backtest.replay_file(file)?,
+                (None, Some(start), Some(end)) => {
+                    backtest
+                        .replay_rpc(&new_client(), start, end, save.as_deref())
+                        .await?
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "backtest needs --file or --start-slot and --end-slot"
+                    ))
+                }
+            }
+            println!("{}", backtest.report());
+            Ok(())
+        }
+        Command::Export { format, since, out } => {
+            let storage = storage::storage().ok_or(anyhow!("storage is not enabled"))?;
+            let journal = storage::write_journal(&storage.fills_since(since)?, format)?;
+            match out {
+                Some(out) => {
+                    fs::write(&out, journal)?;
+                    println!("wrote {}", out.display());
+                }
+                None => print!("{}", journal),
+            }
+            Ok(())
+        }
+        Command::EncryptKey { keypair, out } => {
+            let keypair = read_keypair_file(&keypair)
+                .map_err(|e| anyhow!("failed to read {}: {}", keypair.display(), e))?;
+            let password = std::env::var("WALLET_KEYSTORE_PASSWORD")
+                .map_err(|_| anyhow!("WALLET_KEYSTORE_PASSWORD is not set"))?;
+            let keystore = keystore::encrypt(&keypair, &password, keystore::DEFAULT_ITERATIONS)?;
+            fs::write(&out, serde_json::to_string_pretty(&keystore)?)?;
+            println!("wrote {}", out.display());
+            Ok(())
+        }
+    }
+}
+
+/// Starts the blockhash cache and tip tuning the send paths rely on
+async fn start_trading() -> Result<()> {
+    jito_tips::start_from_env()?;
+    blockhash::start(new_client(), blockhash::DEFAULT_REFRESH_INTERVAL).await?;
+    Ok(())
+}
+
+async fn execute(bot_config: &BotConfig, action: Action, trade: &TradeArgs) -> Result<()> {
+    start_trading().await?;
+    let payer = bot_config.keypair()?;
+    let outcome = engine::execute(action, new_client(), &payer, trade.into()).await?;
+    print_outcome(outcome);
+    Ok(())
+}
+
+fn print_outcome(outcome: Option<TxOutcome>) {
+    match outcome {
+        Some(TxOutcome::Sent(signatures)) => {
+            for signature in signatures {
+                println!("sent {}", signature);
+            }
+        }
+        // 模拟结果已经打印过
+        Some(TxOutcome::Paper(signature)) => println!("paper traded {}", signature),
+        Some(TxOutcome::Simulated(_)) | None => {}
+    }
+}
+
+/// Runs the monitors and every strategy enabled in the environment
+async fn run(bot_config: &'static BotConfig) -> Result<()> {
+    let sniper_config = sniper::SniperConfig::from_env()?;
+    let exit_config = exits::ExitConfig::from_env()?;
+    let migration_config = migration::MigrationConfig::from_env()?;
+    let signal_config = signals::SignalConfig::from_env()?;
+    let discord_config = discord::DiscordConfig::from_env()?;
+    let telegram_channels_config = telegram_channels::TelegramChannelsConfig::from_env()?;
+    let arbitrage_config = ArbitrageConfig::from_env()?;
+    let copy_config = wallet_tracker::WalletTrackerConfig::from_env()?;
+    let commands_config = commands::CommandsConfig::from_env()?;
+    let action_config = ActionConfig::from_env()?;
+    let diagnostics = TelegramNotifier::diagnostics_from_env()?;
+    metrics::serve_from_env().await?;
+    api::serve_from_env().await?;
+    feed::start_from_env().await?;
+    start_trading().await?;
+    let ws_client = new_ws_client().await?;
+    let (mut set, events) =
+        listen_pumpfun_create(ws_client.clone(), notify::from_env()?, DEFAULT_CHANNEL_SIZE).await?;
+    // 各策略共用同一组钱包，只在需要时加载
+    let loaded = OnceCell::new();
+    let wallets = || -> Result<Arc<Wallets>> {
+        if let Some(wallets) = loaded.get() {
+            return Ok(Arc::clone(wallets));
+        }
+        let wallets = Arc::new(Wallets::from_env(bot_config.keypair()?)?);
+        Ok(Arc::clone(loaded.get_or_init(|| wallets)))
+    };
+    if let Some(diagnostics) = diagnostics {
+        set.spawn(diagnostics.forward_diagnostics());
+    }
+    if let Some(storage) = storage::storage() {
+        set.spawn(storage::record_events(storage));
+    }
+    // 狙击和止盈止损的参数可以在运行时修改
+    let mut live_params = LiveParams::from_env();
+    let sniper_config = sniper_config.map(|config| live_params.sniper(config));
+    let exit_config = exit_config.map(|config| live_params.exits(config));
+    set.spawn(params::watch(params::install(live_params)));
+    // 频道里的喊单和链上事件一起交给策略
+    if let Some(discord_config) = discord_config {
+        set.spawn(discord::run(discord_config, events.clone()));
+    }
+    if let Some(telegram_channels_config) = telegram_channels_config {
+        set.spawn(telegram_channels::run(
+            telegram_channels_config,
+            events.clone(),
+        ));
+    }
+    let shutdown = CancellationToken::new();
+    let mut builder = Engine::builder()
+        .config(bot_config)
+        .http_client(new_client())
+        .events(events)
+        .shutdown(shutdown.clone());
+    if let Some(sniper_config) = sniper_config {
+        let action_config = sniper_config.load().action_config();
+        builder = builder.strategy(LiveSniper::new(sniper_config), action_config);
+    }
+    if let Some(signal_config) = signal_config {
+        let action_config = signal_config.action_config();
+        builder = builder.strategy(signal_config, action_config);
+    }
+    if let Some(exit_config) = exit_config {
+        let wallets = wallets()?;
+        builder = builder
+            .task(move |shutdown, _| exits::run(exit_config, new_client(), wallets, shutdown));
+    }
+    if let Some(migration_config) = migration_config {
+        // 迁移只在这里需要，单独监听
+        let (migrations, migration_events) =
+            listen_rayidum_migration(ws_client, notify::from_env()?, DEFAULT_CHANNEL_SIZE).await?;
+        set.spawn(async move {
+            migrations.join_all().await;
+        });
+        let (migration_events, wallets) = (migration_events.subscribe(), wallets()?);
+        builder = builder.task(move |shutdown, actions| {
+            migration::run(
+                migration_config,
+                migration_events,
+                new_client(),
+                wallets,
+                shutdown,
+                actions,
+            )
+        });
+    }
+    if let Some(arbitrage_config) = arbitrage_config {
+        let payer = wallets()?.main();
+        builder = builder.task(move |shutdown, _| {
+            arbitrage::run(
+                Arbitrage::with_default_sources(arbitrage_config),
+                new_client(),
+                payer,
+                shutdown,
+            )
+        });
+    }
+    if let Some(copy_config) = copy_config {
+        let wallets = wallets()?;
+        builder = builder.task(move |shutdown, actions| {
+            wallet_tracker::run(copy_config, new_client(), wallets, shutdown, actions)
+        });
+    }
+    if let Some(commands_config) = commands_config {
+        let (actions, receiver) = engine::action_channel(DEFAULT_CHANNEL_SIZE);
+        let wallets = wallets()?;
+        builder = builder
+            .task(move |shutdown, actions| {
+                engine::run_actions(
+                    receiver,
+                    new_client(),
+                    wallets,
+                    action_config,
+                    shutdown,
+                    actions,
+                )
+            })
+            .task(move |shutdown, _| {
+                commands::run(
+                    commands_config,
+                    Bot::from_env(),
+                    actions,
+                    new_client(),
+                    shutdown,
+                )
+            });
+    }
+    if let Some(wallets) = loaded.get() {
+        builder = builder.wallets(wallets.clone());
+    }
+    let engine = builder.build()?;
+    if let Some(wallets) = engine.wallets().filter(|wallets| wallets.len() > 1) {
+        set.spawn(wallet::track_balances(wallets, new_client()));
+    }
+    tokio::spawn(engine::cancel_on_ctrl_c(shutdown.clone()));
+    // 引擎在取消后等在途交易和通知完成、关闭 WSOL 后才结束，之后再停止监听
+    let trading = engine.run(DEFAULT_CHANNEL_SIZE).await?;
+    shutdown
+        .run_until_cancelled(async { while set.join_next().await.is_some() {} })
+        .await;
+    shutdown.cancel();
+    trading.join_all().await;
+    set.shutdown().await;
+    Ok(())
+}
+
+#[test]
+fn test_cli_parses_subcommands() {
+    use clap::CommandFactory;
+
+    Cli::command().debug_assert();
+    let mint = Pubkey::new_unique();
+    let cli = Cli::parse_from(["bot", "sell", "--mint", &mint.to_string(), "--simulate"]);
+    assert!(matches!(
+        cli.command,
+        Some(Command::Sell { pct, trade, .. }) if pct == 100.0 && trade.simulate
+    ));
+    assert!(Cli::try_parse_from(["bot", "arb"]).is_err());
+    let cli = Cli::parse_from(["bot", "arb", "--mint", &mint.to_string(), "--no-preflight"]);
+    assert!(matches!(
+        cli.command,
+        Some(Command::Arb {
+            no_preflight: true,
+            preflight_min_profit_sol: None,
+            ..
+        })
+    ));
+    let buy_tokens = [
+        "bot",
+        "buy-tokens",
+        "--mint",
+        &mint.to_string(),
+        "--max-sol",
+        "1",
+    ];
+    assert!(Cli::try_parse_from(buy_tokens).is_err());
+    let cli = Cli::parse_from([&buy_tokens[..], &["--supply-pct", "2"][..]].concat());
+    assert!(matches!(
+        cli.command,
+        Some(Command::BuyTokens { tokens: None, supply_pct: Some(pct), .. }) if pct == 2.0
+    ));
+    let cli = Cli::parse_from(["bot", "export", "--format", "json"]);
+    assert!(matches!(
+        cli.command,
+        Some(Command::Export {
+            format: JournalFormat::Json,
+            since: 0,
+            out: None
+        })
+    ));
 }
@@ -0,0 +1,370 @@
+//! Candles of tracked Pump.fun tokens, built from the trades the bot sees.
+//!
+//! With `MARKETDATA_ENABLED=true`, the create listener feeds the bonding
+//! curve trades of every block to [`market_data`], which aggregates those of
+//! the tracked mints into 1s, 15s and 1m candles in memory. Strategies read
+//! [`CandleStore::summary`] for momentum and volume filters, and `/price`
+//! sends a short summary to Telegram. Minutes without trades have no candle.
+//!
+//! - `MARKETDATA_MINTS`: comma separated mints tracked from the start, more
+//!   can be added with [`CandleStore::track`]
+//! - `MARKETDATA_MAX_CANDLES`: candles kept per mint and resolution, default
+//!   900
+//! - `MARKETDATA_PERSIST`: also store the closed 1m candles in the
+//!   [`crate::storage`] database, needs `STORAGE_ENABLED=true`
+
+pub mod trades;
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    env, fmt,
+    str::FromStr,
+    sync::{OnceLock, RwLock},
+};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use solana_sdk::{native_token::lamports_to_sol, pubkey::Pubkey};
+use solana_transaction_status_client_types::UiConfirmedBlock;
+use tracing::{error, warn};
+
+use crate::{storage::storage, strategy::parse_env};
+use trades::{block_trades, Trade};
+
+const DEFAULT_MAX_CANDLES: usize = 900;
+
+static GLOBAL_MARKET_DATA: OnceLock<Option<CandleStore>> = OnceLock::new();
+
+/// Candle width
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum Resolution {
+    #[serde(rename = "1s")]
+    S1,
+    #[serde(rename = "15s")]
+    S15,
+    #[serde(rename = "1m")]
+    M1,
+}
+
+impl Resolution {
+    pub const ALL: [Resolution; 3] = [Resolution::S1, Resolution::S15, Resolution::M1];
+
+    pub fn secs(self) -> u64 {
+        match self {
+            Resolution::S1 => 1,
+            Resolution::S15 => 15,
+            Resolution::M1 => 60,
+        }
+    }
+
+    /// Start of the candle `timestamp` falls in
+    pub fn start(self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.secs()
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resolution::S1 => write!(f, "1s"),
+            Resolution::S15 => write!(f, "15s"),
+            Resolution::M1 => write!(f, "1m"),
+        }
+    }
+}
+
+impl FromStr for Resolution {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Resolution::ALL
+            .into_iter()
+            .find(|resolution| resolution.to_string() == s)
+            .ok_or(anyhow!(
+                "unknown resolution {:?}, expected 1s, 15s or 1m",
+                s
+            ))
+    }
+}
+
+/// Prices in SOL per whole token, volume in lamports
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Candle {
+    /// Unix timestamp the candle starts at
+    pub start: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: u64,
+    pub trades: u32,
+}
+
+impl Candle {
+    fn new(start: u64, trade: &Trade) -> Self {
+        Self {
+            start,
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            close: trade.price,
+            volume: trade.sol_amount,
+            trades: 1,
+        }
+    }
+
+    fn apply(&mut self, trade: &Trade) {
+        self.high = self.high.max(trade.price);
+        self.low = self.low.min(trade.price);
+        self.close = trade.price;
+        self.volume += trade.sol_amount;
+        self.trades += 1;
+    }
+}
+
+/// Price action of a mint over a window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PriceSummary {
+    /// Last traded price
+    pub price: f64,
+    /// Change from the first price of the window, in percent
+    pub change_pct: f64,
+    pub high: f64,
+    pub low: f64,
+    pub volume: u64,
+    pub trades: u32,
+}
+
+impl fmt::Display for PriceSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:+.2}%, volume {:.4} SOL in {} trades",
+            self.change_pct,
+            lamports_to_sol(self.volume),
+            self.trades
+        )
+    }
+}
+
+/// 一个mint各周期的K线
+#[derive(Default)]
+struct Series {
+    candles: HashMap<Resolution, VecDeque<Candle>>,
+}
+
+/// Candles of the tracked mints
+pub struct CandleStore {
+    max_candles: usize,
+    persist: bool,
+    tracked: RwLock<HashSet<Pubkey>>,
+    series: RwLock<HashMap<Pubkey, Series>>,
+}
+
+impl CandleStore {
+    pub fn new(max_candles: usize) -> Self {
+        Self {
+            max_candles: max_candles.max(1),
+            persist: false,
+            tracked: RwLock::new(HashSet::new()),
+            series: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Reads the tracked mints and limits, `None` unless
+    /// `MARKETDATA_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("MARKETDATA_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        let mut store =
+            Self::new(parse_env("MARKETDATA_MAX_CANDLES")?.unwrap_or(DEFAULT_MAX_CANDLES));
+        store.persist = parse_env::<bool>("MARKETDATA_PERSIST")?.unwrap_or(false);
+        if store.persist && storage().is_none() {
+            return Err(anyhow!("MARKETDATA_PERSIST needs STORAGE_ENABLED"));
+        }
+        for mint in env::var("MARKETDATA_MINTS").unwrap_or_default().split(',') {
+            let mint = mint.trim();
+            if !mint.is_empty() {
+                store.track(
+                    mint.parse()
+                        .map_err(|_| anyhow!("invalid MARKETDATA_MINTS entry {:?}", mint))?,
+                );
+            }
+        }
+        Ok(Some(store))
+    }
+
+    /// Starts aggregating the trades of `mint`
+    pub fn track(&self, mint: Pubkey) {
+        self.tracked.write().unwrap().insert(mint);
+    }
+
+    /// Stops aggregating the trades of `mint` and drops its candles
+    pub fn untrack(&self, mint: &Pubkey) {
+        self.tracked.write().unwrap().remove(mint);
+        self.series.write().unwrap().remove(mint);
+    }
+
+    pub fn is_tracked(&self, mint: &Pubkey) -> bool {
+        self.tracked.read().unwrap().contains(mint)
+    }
+
+    /// Adds `trade` to the candles of its mint, false if it isn't tracked
+    ///
+    /// Trades are expected in time order, a late one is added to the latest
+    /// candle.
+    pub fn record(&self, trade: &Trade) -> bool {
+        if !self.is_tracked(&trade.mint) {
+            return false;
+        }
+        let mut closed = None;
+        {
+            let mut series = self.series.write().unwrap();
+            let series = series.entry(trade.mint).or_default();
+            for resolution in Resolution::ALL {
+                let candles = series.candles.entry(resolution).or_default();
+                let start = resolution.start(trade.timestamp);
+                match candles.back_mut() {
+                    Some(last) if last.start >= start => last.apply(trade),
+                    last => {
+                        if resolution == Resolution::M1 {
+                            closed = last.copied();
+                        }
+                        candles.push_back(Candle::new(start, trade));
+                        if candles.len() > self.max_candles {
+                            candles.pop_front();
+                        }
+                    }
+                }
+            }
+        }
+        if let (true, Some(candle), Some(storage)) = (self.persist, closed, storage()) {
+            if let Err(e) = storage.insert_candle(&trade.mint, Resolution::M1, &candle) {
+                error!("failed to store candle of {} {:?}", trade.mint, e);
+            }
+        }
+        true
+    }
+
+    /// Records the tracked trades of `block`
+    pub fn observe_block(&self, block: &UiConfirmedBlock) {
+        for trade in block_trades(block) {
+            self.record(&trade);
+        }
+    }
+
+    /// Candles of `mint` at `resolution`, oldest first
+    pub fn candles(&self, mint: &Pubkey, resolution: Resolution) -> Vec<Candle> {
+        self.series
+            .read()
+            .unwrap()
+            .get(mint)
+            .and_then(|series| series.candles.get(&resolution))
+            .map(|candles| candles.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Price action of `mint` over the `window` seconds up to `now`, from the
+    /// finest candles covering it
+    ///
+    /// `None` without trades in the window.
+    pub fn summary(&self, mint: &Pubkey, window: u64, now: u64) -> Option<PriceSummary> {
+        let resolution = Resolution::ALL
+            .into_iter()
+            .find(|resolution| resolution.secs() * self.max_candles as u64 >= window)
+            .unwrap_or(Resolution::M1);
+        let since = resolution.start(now.saturating_sub(window));
+        let candles: Vec<Candle> = self
+            .candles(mint, resolution)
+            .into_iter()
+            .filter(|candle| candle.start >= since && candle.start <= now)
+            .collect();
+        let (first, last) = (candles.first()?, candles.last()?);
+        Some(PriceSummary {
+            price: last.close,
+            change_pct: (last.close / first.open - 1.0) * 100.0,
+            high: candles.iter().map(|c| c.high).fold(f64::MIN, f64::max),
+            low: candles.iter().map(|c| c.low).fold(f64::MAX, f64::min),
+            volume: candles.iter().map(|c| c.volume).sum(),
+            trades: candles.iter().map(|c| c.trades).sum(),
+        })
+    }
+}
+
+/// Process-wide candles, `None` unless `MARKETDATA_ENABLED=true`
+pub fn market_data() -> Option<&'static CandleStore> {
+    GLOBAL_MARKET_DATA
+        .get_or_init(|| {
+            CandleStore::from_env().unwrap_or_else(|e| {
+                warn!("market data disabled {:?}", e);
+                None
+            })
+        })
+        .as_ref()
+}
+
+/// One bar per value, scaled between their min and max
+pub fn sparkline(values: &[f64]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let min = values.iter().copied().fold(f64::MAX, f64::min);
+    let max = values.iter().copied().fold(f64::MIN, f64::max);
+    values
+        .iter()
+        .map(|value| {
+            if max > min {
+                BARS[((value - min) / (max - min) * 7.0).round() as usize]
+            } else {
+                BARS[3]
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_candles_and_summary() {
+    use crate::portfolio::Side;
+
+    let store = CandleStore::new(900);
+    let mint = Pubkey::new_unique();
+    let trade = |timestamp, price, sol_amount| Trade {
+        mint,
+        timestamp,
+        side: Side::Buy,
+        price,
+        sol_amount,
+        token_amount: 1,
+    };
+    assert!(!store.record(&trade(60, 1.0, 1)));
+    store.track(mint);
+
+    for (timestamp, price) in [(60, 1.0), (61, 1.5), (75, 0.8), (125, 2.0)] {
+        assert!(store.record(&trade(timestamp, price, 10)));
+    }
+    let minutes = store.candles(&mint, Resolution::M1);
+    assert_eq!(minutes.len(), 2);
+    assert_eq!(
+        minutes[0],
+        Candle {
+            start: 60,
+            open: 1.0,
+            high: 1.5,
+            low: 0.8,
+            close: 0.8,
+            volume: 30,
+            trades: 3,
+        }
+    );
+    assert_eq!(store.candles(&mint, Resolution::S15).len(), 3);
+    assert_eq!(store.candles(&mint, Resolution::S1).len(), 4);
+
+    let summary = store.summary(&mint, 60, 125).unwrap();
+    assert_eq!(summary.price, 2.0);
+    assert_eq!((summary.volume, summary.trades), (20, 2));
+    assert!((summary.change_pct - 150.0).abs() < 1e-9);
+    assert!(store.summary(&mint, 60, 1000).is_none());
+
+    assert_eq!("15s".parse::<Resolution>().unwrap(), Resolution::S15);
+    assert_eq!(sparkline(&[1.0, 2.0, 3.0]), "▁▅█");
+}
@@ -0,0 +1,156 @@
+//! Pump.fun trades decoded from the `TradeEvent` the program emits.
+//!
+//! The event is read from the self-CPI Anchor's `emit_cpi!` makes, and from
+//! the `Program data:` logs of older program versions when a transaction has
+//! none. Unlike the instruction data, it carries the exact SOL amount and the
+//! reserves after the trade.
+
+use borsh::BorshDeserialize;
+use solana_sdk::{bs58, native_token::LAMPORTS_PER_SOL, pubkey::Pubkey};
+use solana_transaction_status_client_types::{
+    option_serializer::OptionSerializer, EncodedTransactionWithStatusMeta, UiConfirmedBlock,
+    UiInstruction,
+};
+
+use crate::{constants::curve::TOKEN_DECIMALS, monitor::tx_succeeded, portfolio::Side};
+
+/// Prefix of the instruction data of an `emit_cpi!` event
+const EVENT_IX_TAG: [u8; 8] = [0xe4, 0x45, 0xa5, 0x2e, 0x51, 0xcb, 0x9a, 0x1d];
+
+/// `sha256("event:TradeEvent")[..8]`
+const TRADE_EVENT_DISCRIMINATOR: [u8; 8] = [189, 219, 127, 211, 78, 230, 97, 238];
+
+const PROGRAM_DATA: &str = "Program data: ";
+
+/// Leading fields of the event, later versions append more
+#[derive(Debug, BorshDeserialize)]
+struct TradeEvent {
+    mint: Pubkey,
+    sol_amount: u64,
+    token_amount: u64,
+    is_buy: bool,
+    _user: Pubkey,
+    timestamp: i64,
+    virtual_sol_reserves: u64,
+    virtual_token_reserves: u64,
+}
+
+/// A swap on a bonding curve
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trade {
+    pub mint: Pubkey,
+    /// Unix timestamp of the block
+    pub timestamp: u64,
+    pub side: Side,
+    /// Price of a whole token in SOL after the trade, at the virtual reserves
+    pub price: f64,
+    /// Lamports spent or received
+    pub sol_amount: u64,
+    /// Tokens bought or sold, in raw units
+    pub token_amount: u64,
+}
+
+/// Decodes a `TradeEvent`, from `emit_cpi!` instruction data or a log
+pub fn decode_trade_event(data: &[u8]) -> Option<Trade> {
+    let data = data.strip_prefix(&EVENT_IX_TAG).unwrap_or(data);
+    let mut fields = data.strip_prefix(&TRADE_EVENT_DISCRIMINATOR)?;
+    let event = TradeEvent::deserialize(&mut fields).ok()?;
+    if event.virtual_token_reserves == 0 {
+        return None;
+    }
+    let sol = event.virtual_sol_reserves as f64 / LAMPORTS_PER_SOL as f64;
+    let tokens = event.virtual_token_reserves as f64 / 10f64.powi(TOKEN_DECIMALS as i32);
+    Some(Trade {
+        mint: event.mint,
+        timestamp: event.timestamp.max(0) as u64,
+        side: if event.is_buy { Side::Buy } else { Side::Sell },
+        price: sol / tokens,
+        sol_amount: event.sol_amount,
+        token_amount: event.token_amount,
+    })
+}
+
+fn transaction_trades(tx: &EncodedTransactionWithStatusMeta) -> Vec<Trade> {
+    let Some(meta) = &tx.meta else {
+        return vec![];
+    };
+    let mut trades = vec![];
+    if let OptionSerializer::Some(inner) = &meta.inner_instructions {
+        for ix in inner.iter().flat_map(|inner| &inner.instructions) {
+            if let UiInstruction::Compiled(ix) = ix {
+                if let Ok(data) = bs58::decode(&ix.data).into_vec() {
+                    trades.extend(decode_trade_event(&data));
+                }
+            }
+        }
+    }
+    if !trades.is_empty() {
+        return trades;
+    }
+    // 旧版本只写日志
+    if let OptionSerializer::Some(logs) = &meta.log_messages {
+        for log in logs {
+            let Some(encoded) = log.strip_prefix(PROGRAM_DATA) else {
+                continue;
+            };
+            if let Ok(data) = bs64::decode(encoded.as_bytes()) {
+                trades.extend(decode_trade_event(&data));
+            }
+        }
+    }
+    trades
+}
+
+/// The bonding curve trades of the successful transactions of `block`, in
+/// execution order
+pub fn block_trades(block: &UiConfirmedBlock) -> Vec<Trade> {
+    block
+        .transactions
+        .iter()
+        .flatten()
+        .filter(|tx| tx_succeeded(tx))
+        .flat_map(transaction_trades)
+        .collect()
+}
+
+#[test]
+fn test_decode_trade_event() {
+    use borsh::BorshSerialize;
+
+    #[derive(BorshSerialize)]
+    struct Event {
+        mint: Pubkey,
+        sol_amount: u64,
+        token_amount: u64,
+        is_buy: bool,
+        user: Pubkey,
+        timestamp: i64,
+        virtual_sol_reserves: u64,
+        virtual_token_reserves: u64,
+        real_sol_reserves: u64,
+    }
+    let mint = Pubkey::new_unique();
+    let event = Event {
+        mint,
+        sol_amount: 1_000_000_000,
+        token_amount: 30_000_000_000_000,
+        is_buy: false,
+        user: Pubkey::new_unique(),
+        timestamp: 1_700_000_000,
+        virtual_sol_reserves: 40_000_000_000,
+        virtual_token_reserves: 800_000_000_000_000,
+        real_sol_reserves: 10_000_000_000,
+    };
+    let mut data = TRADE_EVENT_DISCRIMINATOR.to_vec();
+    event.serialize(&mut data).unwrap();
+
+    let trade = decode_trade_event(&data).unwrap();
+    assert_eq!(trade.mint, mint);
+    assert_eq!(trade.side, Side::Sell);
+    assert_eq!(trade.timestamp, 1_700_000_000);
+    assert!((trade.price - 5e-8).abs() < 1e-15);
+    // emit_cpi 的指令数据多一个前缀
+    let cpi = [EVENT_IX_TAG.as_slice(), &data].concat();
+    assert_eq!(decode_trade_event(&cpi), Some(trade));
+    assert!(decode_trade_event(&data[8..]).is_none());
+}
@@ -0,0 +1,3 @@
+//! Math shared by the DEX paths.
+
+pub mod slippage;
@@ -0,0 +1,93 @@
+//! Slippage bounds on swap amounts.
+//!
+//! A [`Slippage`] turns the amount a quote expects into the bound sent with
+//! the swap: the least output accepted by exact-input swaps
+//! ([`Slippage::min_out`]) and the most input paid by exact-output swaps
+//! ([`Slippage::max_in`]). Products are taken in u128, so only a bound that
+//! doesn't fit in u64 fails.
+
+use thiserror::Error;
+
+const BPS_DENOMINATOR: u128 = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SlippageError {
+    #[error("slippage of {bps} bps is over 100%")]
+    OverFullAmount { bps: u64 },
+    #[error("{amount} with slippage overflows u64")]
+    Overflow { amount: u64 },
+}
+
+/// Slippage tolerated on a swap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slippage {
+    /// Percent of the expected amount
+    Percent(u64),
+    /// Basis points of the expected amount
+    Bps(u64),
+    /// Raw units of the token, lamports for SOL
+    Absolute(u64),
+}
+
+impl Slippage {
+    fn bps(self) -> Option<u64> {
+        match self {
+            Slippage::Percent(pct) => Some(pct.saturating_mul(100)),
+            Slippage::Bps(bps) => Some(bps),
+            Slippage::Absolute(_) => None,
+        }
+    }
+
+    /// Least output accepted for an expected `amount`
+    pub fn min_out(self, amount: u64) -> Result<u64, SlippageError> {
+        match self.bps() {
+            Some(bps) if bps as u128 > BPS_DENOMINATOR => {
+                Err(SlippageError::OverFullAmount { bps })
+            }
+            Some(bps) => {
+                Ok((amount as u128 * (BPS_DENOMINATOR - bps as u128) / BPS_DENOMINATOR) as u64)
+            }
+            None => Ok(amount.saturating_sub(self.absolute())),
+        }
+    }
+
+    /// Most input paid for an expected `amount`
+    pub fn max_in(self, amount: u64) -> Result<u64, SlippageError> {
+        let max_in = match self.bps() {
+            Some(bps) => amount as u128 * (BPS_DENOMINATOR + bps as u128) / BPS_DENOMINATOR,
+            None => amount as u128 + self.absolute() as u128,
+        };
+        u64::try_from(max_in).map_err(|_| SlippageError::Overflow { amount })
+    }
+
+    fn absolute(self) -> u64 {
+        match self {
+            Slippage::Absolute(amount) => amount,
+            _ => 0,
+        }
+    }
+}
+
+#[test]
+fn test_slippage_bounds() {
+    assert_eq!(Slippage::Bps(100).min_out(10_000), Ok(9_900));
+    assert_eq!(Slippage::Percent(10).min_out(10_000), Ok(9_000));
+    assert_eq!(Slippage::Percent(10).max_in(10_000), Ok(11_000));
+    assert_eq!(Slippage::Absolute(500).min_out(10_000), Ok(9_500));
+    assert_eq!(Slippage::Absolute(500).min_out(100), Ok(0));
+    assert_eq!(Slippage::Absolute(500).max_in(10_000), Ok(10_500));
+
+    // u64 中间结果会溢出
+    assert_eq!(
+        Slippage::Bps(50).min_out(u64::MAX / 2),
+        Ok(9_177_255_176_670_501_927)
+    );
+    assert_eq!(
+        Slippage::Bps(20_000).min_out(1),
+        Err(SlippageError::OverFullAmount { bps: 20_000 })
+    );
+    assert_eq!(
+        Slippage::Percent(1).max_in(u64::MAX),
+        Err(SlippageError::Overflow { amount: u64::MAX })
+    );
+}
@@ -0,0 +1,303 @@
+//! Prometheus metrics for the running bot.
+//!
+//! The counters and gauges live in the default prometheus registry and are
+//! updated from the monitors and the trade operations. The `/metrics` endpoint
+//! is only served when `METRICS_ADDR` is set (e.g. `0.0.0.0:9100`).
+//!
+//! Trade latencies and PnL are labelled with the strategy that made the trade,
+//! see [`crate::timeline`]. The land rate is
+//! `bot_tx_confirmations_total{outcome="landed"}` over the sum of outcomes.
+//! Slippage is the shortfall from the quoted output, both for simulations and
+//! for confirmed sends, negative when the trade got more than quoted.
+
+use std::{
+    env,
+    net::SocketAddr,
+    sync::LazyLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use axum::{routing::get, Router};
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, TextEncoder,
+};
+use tokio::{net::TcpListener, task::JoinHandle};
+use tracing::{error, info};
+
+/// Blocks received from the websocket, per monitor
+pub static BLOCKS_RECEIVED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "bot_blocks_received_total",
+        "Blocks received per monitor",
+        &["monitor"]
+    )
+    .unwrap()
+});
+
+/// Unix timestamp of the last block received, per monitor
+pub static LAST_BLOCK_TIMESTAMP: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "bot_last_block_timestamp_seconds",
+        "Unix timestamp of the last block received per monitor",
+        &["monitor"]
+    )
+    .unwrap()
+});
+
+/// Last slot received, per monitor
+pub static MONITOR_LAST_SLOT: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "bot_monitor_last_slot",
+        "Last slot received per monitor",
+        &["monitor"]
+    )
+    .unwrap()
+});
+
+/// Slots between the node's current slot and the last one received, per monitor
+pub static MONITOR_LAG_SLOTS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "bot_monitor_lag_slots",
+        "Slots the monitor is behind the node",
+        &["monitor"]
+    )
+    .unwrap()
+});
+
+/// Pump.fun token creations detected
+pub static CREATES_DETECTED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!(
+        "bot_creates_detected_total",
+        "Pump.fun token creates detected"
+    )
+    .unwrap()
+});
+
+/// Raydium swaps seen before they landed
+pub static PENDING_SWAPS_DETECTED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!(
+        "bot_pending_swaps_detected_total",
+        "Raydium swaps seen before they landed"
+    )
+    .unwrap()
+});
+
+/// Account updates of the watched bonding curves
+pub static CURVE_UPDATES: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!(
+        "bot_curve_updates_total",
+        "Account updates of the watched bonding curves"
+    )
+    .unwrap()
+});
+
+/// Raydium migrations detected
+pub static MIGRATIONS_DETECTED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!(
+        "bot_migrations_detected_total",
+        "Raydium migrations detected"
+    )
+    .unwrap()
+});
+
+/// Trades sent to the node, per venue and side
+pub static TRADES_ATTEMPTED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "bot_trades_attempted_total",
+        "Trades attempted per venue and side",
+        &["venue", "side"]
+    )
+    .unwrap()
+});
+
+/// Trades accepted by the node, per venue and side
+pub static TRADES_SUCCEEDED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "bot_trades_succeeded_total",
+        "Trades succeeded per venue and side",
+        &["venue", "side"]
+    )
+    .unwrap()
+});
+
+/// Positions currently held
+pub static OPEN_POSITIONS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!("bot_open_positions", "Positions currently held").unwrap()
+});
+
+/// Buckets of the latency histograms, in seconds
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Seconds from the data triggering a trade arriving to the trade being
+/// accepted by the RPC, per strategy
+pub static DETECTION_TO_SEND_SECONDS: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "bot_detection_to_send_seconds",
+        "Seconds from detection to send per strategy",
+        &["strategy"],
+        LATENCY_BUCKETS.to_vec()
+    )
+    .unwrap()
+});
+
+/// Seconds spent in each stage of a trade (`decide`, `build`, `send`), per
+/// strategy
+pub static TRADE_STAGE_SECONDS: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "bot_trade_stage_seconds",
+        "Seconds per trade stage and strategy",
+        &["strategy", "stage"],
+        LATENCY_BUCKETS.to_vec()
+    )
+    .unwrap()
+});
+
+/// Sent transactions by final outcome: `landed`, `failed` or `expired`
+pub static TX_CONFIRMATIONS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "bot_tx_confirmations_total",
+        "Sent transactions per outcome",
+        &["outcome"]
+    )
+    .unwrap()
+});
+
+/// Shortfall of the output from the quote in basis points, per source
+/// (`simulated` or `landed`)
+pub static SLIPPAGE_BPS: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "bot_slippage_bps",
+        "Output shortfall from the quote in basis points",
+        &["source"],
+        vec![-100.0, -10.0, 0.0, 10.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0]
+    )
+    .unwrap()
+});
+
+/// Realized profit of the positions each strategy opened, in lamports
+pub static REALIZED_PNL_LAMPORTS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "bot_realized_pnl_lamports",
+        "Realized profit per strategy in lamports",
+        &["strategy"]
+    )
+    .unwrap()
+});
+
+/// Rpc calls answered with a 429, per method
+pub static RPC_RATE_LIMITED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "bot_rpc_rate_limited_total",
+        "Rpc calls refused for exceeding the endpoint's rate limit",
+        &["method"]
+    )
+    .unwrap()
+});
+
+/// Account cache lookups by result, `hit` or `miss`
+pub static ACCOUNT_CACHE_LOOKUPS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "bot_account_cache_lookups_total",
+        "Account cache lookups per result",
+        &["result"]
+    )
+    .unwrap()
+});
+
+/// Moving average of the round trip times, per endpoint host
+pub static ENDPOINT_RTT_MICROSECONDS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "bot_endpoint_rtt_microseconds",
+        "Moving average of the round trip time per endpoint host",
+        &["endpoint"]
+    )
+    .unwrap()
+});
+
+/// Records the final outcome of a sent transaction
+pub fn record_confirmation(outcome: &str) {
+    TX_CONFIRMATIONS.with_label_values(&[outcome]).inc();
+}
+
+/// Records `actual` against the `expected` output of a trade
+pub fn record_slippage(source: &str, expected: u64, actual: u64) {
+    if expected == 0 {
+        return;
+    }
+    let bps = (expected as f64 - actual as f64) * 10_000.0 / expected as f64;
+    SLIPPAGE_BPS.with_label_values(&[source]).observe(bps);
+}
+
+/// Records a block received by `monitor`
+pub fn record_block(monitor: &str) {
+    BLOCKS_RECEIVED.with_label_values(&[monitor]).inc();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    LAST_BLOCK_TIMESTAMP.with_label_values(&[monitor]).set(now);
+}
+
+/// Records a trade sent to the node
+pub fn record_trade_attempt(venue: &str, side: &str) {
+    TRADES_ATTEMPTED.with_label_values(&[venue, side]).inc();
+}
+
+/// Records a trade accepted by the node
+pub fn record_trade_success(venue: &str, side: &str) {
+    TRADES_SUCCEEDED.with_label_values(&[venue, side]).inc();
+}
+
+/// Renders every registered metric in the prometheus text format
+pub fn render() -> Result<String> {
+    let mut buffer = vec![];
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
+
+async fn metrics_handler() -> String {
+    render().unwrap_or_else(|e| {
+        error!("failed to encode metrics {:?}", e);
+        String::new()
+    })
+}
+
+/// Serves `/metrics` on `addr` until the task is dropped
+pub async fn serve(addr: SocketAddr) -> Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(addr).await?;
+    let app = Router::new().route("/metrics", get(metrics_handler));
+    info!("metrics listening on {}", addr);
+    Ok(tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            error!("metrics server stopped {:?}", e);
+        }
+    }))
+}
+
+/// Starts the metrics server if `METRICS_ADDR` is set
+pub async fn serve_from_env() -> Result<Option<JoinHandle<()>>> {
+    dotenv::dotenv().ok();
+    match env::var("METRICS_ADDR") {
+        Ok(addr) => Ok(Some(serve(addr.parse()?).await?)),
+        Err(_) => Ok(None),
+    }
+}
+
+#[test]
+fn test_trade_metrics() {
+    record_slippage("test", 1000, 990);
+    record_slippage("test", 0, 990);
+    let slippage = SLIPPAGE_BPS.with_label_values(&["test"]);
+    assert_eq!(slippage.get_sample_count(), 1);
+    assert_eq!(slippage.get_sample_sum(), 100.0);
+
+    record_confirmation("landed");
+    let rendered = render().unwrap();
+    assert!(rendered.contains("bot_slippage_bps_bucket"));
+    assert!(rendered.contains("bot_tx_confirmations_total{outcome=\"landed\"}"));
+}
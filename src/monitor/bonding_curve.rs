@@ -0,0 +1,268 @@
+//! Bonding curves of tracked Pump.fun tokens, pushed instead of polled.
+//!
+//! [`BondingCurveWatcher`] subscribes with `accountSubscribe` to the curve of
+//! every tracked mint at processed commitment. Each update moving the curve to
+//! a new whole percent sold is sent as a [`MonitorEvent::CurveProgress`]; the
+//! update completing it as a [`MonitorEvent::CurveComplete`], after which the
+//! mint is no longer tracked. Migration follows completion within a few slots.
+//!
+//! Subscriptions share the websocket client given to the watcher, and fall
+//! back to their own one on reconnect. Curve events go out on the watcher's
+//! channel only, like the pending swaps.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use borsh::BorshDeserialize;
+use futures_util::StreamExt;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{nonblocking::pubsub_client::PubsubClient, rpc_config::RpcAccountInfoConfig};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use tokio::{
+    sync::broadcast,
+    task::{AbortHandle, JoinSet},
+    time::sleep,
+};
+use tracing::{info, warn};
+
+use crate::{
+    metrics,
+    monitor::{
+        events::{CurveEvent, MonitorEvent},
+        notify_events,
+    },
+    new_ws_client,
+    notify::Notifier,
+    pumpfun::{accounts::BondingCurveAccount, utils::get_bonding_curve_pda},
+};
+
+const MONITOR: &str = "bonding_curve";
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// mint -> 订阅任务
+type Tracked = Arc<Mutex<HashMap<Pubkey, AbortHandle>>>;
+
+/// Watches the bonding curves of a changing set of mints
+pub struct BondingCurveWatcher {
+    ws_client: Arc<PubsubClient>,
+    event_sender: broadcast::Sender<MonitorEvent>,
+    tracked: Tracked,
+}
+
+impl BondingCurveWatcher {
+    pub fn new(ws_client: Arc<PubsubClient>, channel_size: usize) -> Self {
+        Self {
+            ws_client,
+            event_sender: broadcast::channel(channel_size).0,
+            tracked: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Starts watching the curve of `mint`, false if it already was
+    pub fn track(&self, mint: Pubkey) -> Result<bool> {
+        let curve = get_bonding_curve_pda(&mint).ok_or(anyhow!("BondingCurveNotFound"))?;
+        let mut tracked = self.tracked.lock().unwrap();
+        if tracked.contains_key(&mint) {
+            return Ok(false);
+        }
+        let task = tokio::spawn(watch_curve(
+            self.ws_client.clone(),
+            mint,
+            curve,
+            self.event_sender.clone(),
+            self.tracked.clone(),
+        ));
+        tracked.insert(mint, task.abort_handle());
+        Ok(true)
+    }
+
+    /// Stops watching the curve of `mint`, false if it wasn't
+    pub fn untrack(&self, mint: &Pubkey) -> bool {
+        match self.tracked.lock().unwrap().remove(mint) {
+            Some(task) => {
+                task.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Mints watched, completed ones excluded
+    pub fn tracked(&self) -> Vec<Pubkey> {
+        self.tracked.lock().unwrap().keys().copied().collect()
+    }
+
+    /// The sender of the curve events; call `subscribe()` on it to consume
+    /// them
+    pub fn event_sender(&self) -> broadcast::Sender<MonitorEvent> {
+        self.event_sender.clone()
+    }
+}
+
+impl Drop for BondingCurveWatcher {
+    fn drop(&mut self) {
+        for task in self.tracked.lock().unwrap().values() {
+            task.abort();
+        }
+    }
+}
+
+/// Whole percent of the curve sold, the step progress events are sent at
+fn progress_step(curve: &BondingCurveAccount) -> u64 {
+    curve.get_progress_pct().floor() as u64
+}
+
+/// Event for the update of `curve`, `None` if it is still within the step
+/// `last_step` of the previous one
+fn curve_event(
+    mint: &Pubkey,
+    address: &Pubkey,
+    curve: &BondingCurveAccount,
+    slot: u64,
+    last_step: Option<u64>,
+) -> Option<MonitorEvent> {
+    if !curve.complete && last_step == Some(progress_step(curve)) {
+        return None;
+    }
+    let event = CurveEvent {
+        mint: mint.to_string(),
+        bonding_curve: address.to_string(),
+        slot,
+        progress_pct: curve.get_progress_pct(),
+        real_sol_reserves: curve.real_sol_reserves,
+        market_cap: curve.get_market_cap_sol(),
+        received_at: None,
+    };
+    Some(if curve.complete {
+        MonitorEvent::CurveComplete(event)
+    } else {
+        MonitorEvent::CurveProgress(event)
+    })
+}
+
+/// Streams the updates of `curve` into `event_sender` until it completes,
+/// resubscribing on failure
+async fn watch_curve(
+    ws_client: Arc<PubsubClient>,
+    mint: Pubkey,
+    curve: Pubkey,
+    event_sender: broadcast::Sender<MonitorEvent>,
+    tracked: Tracked,
+) {
+    let config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        commitment: Some(CommitmentConfig::processed()),
+        ..RpcAccountInfoConfig::default()
+    };
+    let mut ws_client = Some(ws_client);
+    let mut last_step = None;
+    let mut delay = RECONNECT_DELAY;
+    loop {
+        let client = match ws_client.take() {
+            Some(client) => client,
+            None => match new_ws_client().await {
+                Ok(client) => client,
+                Err(e) => {
+                    warn!("{} failed to reconnect websocket {:?}", MONITOR, e);
+                    sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                    continue;
+                }
+            },
+        };
+
+        match client.account_subscribe(&curve, Some(config.clone())).await {
+            Ok((mut stream, _)) => {
+                delay = RECONNECT_DELAY;
+                info!("{} subscribed to {}", MONITOR, mint);
+                while let Some(update) = stream.next().await {
+                    let received_at = Instant::now();
+                    metrics::CURVE_UPDATES.inc();
+                    let Some(data) = update.value.data.decode() else {
+                        continue;
+                    };
+                    // 新版曲线账户末尾有额外字段
+                    let account = match BondingCurveAccount::deserialize(&mut data.as_slice()) {
+                        Ok(account) => account,
+                        Err(e) => {
+                            warn!("{} failed to decode curve of {} {:?}", MONITOR, mint, e);
+                            continue;
+                        }
+                    };
+                    let event =
+                        curve_event(&mint, &curve, &account, update.context.slot, last_step);
+                    last_step = Some(progress_step(&account));
+                    let Some(mut event) = event else {
+                        continue;
+                    };
+                    if let MonitorEvent::CurveProgress(e) | MonitorEvent::CurveComplete(e) =
+                        &mut event
+                    {
+                        e.received_at = Some(received_at);
+                    }
+                    // 没有接收者时忽略
+                    let _ = event_sender.send(event);
+                    if account.complete {
+                        info!("{} curve of {} complete", MONITOR, mint);
+                        tracked.lock().unwrap().remove(&mint);
+                        return;
+                    }
+                }
+                warn!("{} stream of {} closed, reconnecting", MONITOR, mint);
+            }
+            Err(e) => warn!("{} failed to subscribe to {} {:?}", MONITOR, mint, e),
+        }
+
+        sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+/// Watches the curves of `mints` and notifies `notifier` of their progress
+///
+/// Returns the notifier task and the watcher, to track more mints and
+/// subscribe to its events.
+pub fn listen_bonding_curves(
+    ws_client: Arc<PubsubClient>,
+    mints: &[Pubkey],
+    notifier: Arc<dyn Notifier>,
+    channel_size: usize,
+) -> Result<(JoinSet<()>, BondingCurveWatcher)> {
+    let watcher = BondingCurveWatcher::new(ws_client, channel_size);
+    let mut set: JoinSet<()> = JoinSet::new();
+    set.spawn(notify_events(&watcher.event_sender, notifier));
+    for mint in mints {
+        watcher.track(*mint)?;
+    }
+    Ok((set, watcher))
+}
+
+#[test]
+fn test_curve_events() {
+    let (mint, address) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let mut curve = BondingCurveAccount::fresh();
+    curve
+        .apply_buy(crate::constants::curve::INITIAL_REAL_TOKEN_RESERVES / 4)
+        .unwrap();
+
+    let first = curve_event(&mint, &address, &curve, 1, None).unwrap();
+    assert_eq!(first.event_type(), "curve_progress");
+    // 同一档进度不重复发送
+    assert!(curve_event(&mint, &address, &curve, 2, Some(progress_step(&curve))).is_none());
+    assert!(curve_event(&mint, &address, &curve, 2, Some(progress_step(&curve) - 1)).is_some());
+
+    curve.apply_buy(curve.real_token_reserves).unwrap();
+    match curve_event(&mint, &address, &curve, 3, Some(100)) {
+        Some(MonitorEvent::CurveComplete(event)) => {
+            assert_eq!(event.progress_pct, 100.0);
+            assert_eq!(event.slot, 3);
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+}
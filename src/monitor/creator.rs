@@ -0,0 +1,338 @@
+//! Creator wallet analysis of new Pump.fun tokens.
+//!
+//! With `CREATOR_ANALYSIS_ENABLED=true`, the create listener profiles the
+//! creator of every token before sending its event, and attaches the result
+//! as [`TokenCreateEvent::creator_profile`] for the strategies to filter on:
+//!
+//! - the funder, the fee payer of the creator's oldest transaction, usually
+//!   the exchange or wallet that sent it its first SOL
+//! - its prior launches among its latest transactions, and how many of them
+//!   it sold on the curve
+//! - the other wallets buying the token in the creation block that the
+//!   creator or its funder funded, i.e. bundled snipes
+//!
+//! Only the latest signatures are read, so the funder of an old wallet with a
+//! long history is the payer of the oldest transaction fetched rather than
+//! the first one. Funders are cached for wallets whose history was read in
+//! full.
+//!
+//! - `CREATOR_HISTORY_TXS`: latest creator transactions searched for prior
+//!   launches (default 30)
+//! - `CREATOR_ANALYSIS_TIMEOUT_MS`: longest a create waits for its profile,
+//!   the event goes out without one after (default 2000)
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use futures_util::future::join_all;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient, rpc_client::GetConfirmedSignaturesForAddress2Config,
+};
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status_client_types::{EncodedTransactionWithStatusMeta, UiConfirmedBlock};
+use tokio::time::timeout;
+use tracing::warn;
+
+use crate::{
+    monitor::{
+        events::{CreatorProfile, TokenCreateEvent},
+        token_create::{transaction_curve_trades, CurveTrade},
+        tx_succeeded,
+        wallet_tracker::fetch_transaction,
+    },
+    new_client,
+    strategy::parse_env,
+};
+
+const MONITOR: &str = "creator";
+
+const DEFAULT_HISTORY_TXS: usize = 30;
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(2000);
+
+/// Signatures fetched to find a wallet's oldest transaction
+const FUNDING_HISTORY_LIMIT: usize = 1000;
+/// Other buyers of the creation block checked for bundling
+const MAX_BUNDLE_BUYERS: usize = 8;
+
+/// Who paid for a wallet's oldest transaction, and when
+#[derive(Debug, Clone, Copy, Default)]
+struct Funding {
+    funder: Option<Pubkey>,
+    first_seen: Option<i64>,
+}
+
+/// Profiles the creators of new tokens
+pub struct CreatorAnalyzer {
+    client: Arc<RpcClient>,
+    history_txs: usize,
+    timeout: Duration,
+    fundings: Mutex<HashMap<Pubkey, Funding>>,
+}
+
+impl CreatorAnalyzer {
+    pub fn new(client: Arc<RpcClient>, history_txs: usize, timeout: Duration) -> Self {
+        Self {
+            client,
+            history_txs,
+            timeout,
+            fundings: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reads the `CREATOR_*` variables, `None` unless
+    /// `CREATOR_ANALYSIS_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("CREATOR_ANALYSIS_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        Ok(Some(Self::new(
+            new_client(),
+            parse_env("CREATOR_HISTORY_TXS")?.unwrap_or(DEFAULT_HISTORY_TXS),
+            parse_env("CREATOR_ANALYSIS_TIMEOUT_MS")?
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_TIMEOUT),
+        )))
+    }
+
+    async fn funding(&self, wallet: &Pubkey) -> Result<Funding> {
+        if let Some(funding) = self.fundings.lock().unwrap().get(wallet) {
+            return Ok(*funding);
+        }
+        let config = GetConfirmedSignaturesForAddress2Config {
+            limit: Some(FUNDING_HISTORY_LIMIT),
+            ..GetConfirmedSignaturesForAddress2Config::default()
+        };
+        let signatures = self
+            .client
+            .get_signatures_for_address_with_config(wallet, config)
+            .await?;
+        let Some(oldest) = signatures.last() else {
+            return Ok(Funding::default());
+        };
+        let tx = fetch_transaction(&self.client, &oldest.signature.parse()?).await?;
+        let funding = Funding {
+            funder: fee_payer(&tx).filter(|payer| payer != wallet),
+            first_seen: oldest.block_time,
+        };
+        // 历史没有取全时最早的交易不一定是第一笔，不缓存
+        if signatures.len() < FUNDING_HISTORY_LIMIT {
+            self.fundings.lock().unwrap().insert(*wallet, funding);
+        }
+        Ok(funding)
+    }
+
+    /// Prior launches and rugs of `creator`, `mint` excluded
+    async fn launches(&self, creator: &Pubkey, mint: &Pubkey) -> Result<(u32, u32)> {
+        let config = GetConfirmedSignaturesForAddress2Config {
+            limit: Some(self.history_txs),
+            ..GetConfirmedSignaturesForAddress2Config::default()
+        };
+        let signatures = self
+            .client
+            .get_signatures_for_address_with_config(creator, config)
+            .await?;
+        let fetches = signatures
+            .iter()
+            .filter(|signature| signature.err.is_none())
+            .filter_map(|signature| signature.signature.parse().ok())
+            .map(|signature| async move { fetch_transaction(&self.client, &signature).await.ok() });
+        let txs = join_all(fetches).await;
+        let trades: Vec<CurveTrade> = txs
+            .iter()
+            .flatten()
+            .filter_map(transaction_curve_trades)
+            .flatten()
+            .collect();
+        Ok(count_launches(&trades, creator, mint))
+    }
+
+    /// Profiles the creator of `mint`, `buyers` being the other wallets
+    /// buying it in the creation block
+    pub async fn profile(
+        &self,
+        creator: &Pubkey,
+        mint: &Pubkey,
+        buyers: &[Pubkey],
+    ) -> Result<CreatorProfile> {
+        let (funding, (prior_launches, prior_rugs)) =
+            tokio::try_join!(self.funding(creator), self.launches(creator, mint))?;
+        let buyers: Vec<&Pubkey> = buyers
+            .iter()
+            .filter(|buyer| *buyer != creator)
+            .take(MAX_BUNDLE_BUYERS)
+            .collect();
+        let fundings = join_all(buyers.iter().map(|buyer| self.funding(buyer))).await;
+        let bundled_buyers = buyers
+            .into_iter()
+            .zip(fundings)
+            .filter(|(_, buyer_funding)| {
+                buyer_funding.as_ref().is_ok_and(|buyer_funding| {
+                    is_bundled(creator, funding.funder, buyer_funding.funder)
+                })
+            })
+            .map(|(buyer, _)| buyer.to_string())
+            .collect();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        Ok(CreatorProfile {
+            funder: funding.funder.map(|funder| funder.to_string()),
+            wallet_age_secs: funding
+                .first_seen
+                .map(|first_seen| (now - first_seen).max(0) as u64),
+            prior_launches,
+            prior_rugs,
+            bundled_buyers,
+        })
+    }
+
+    /// Profiles the creators of `events` concurrently and attaches the
+    /// profiles finished within the timeout
+    pub async fn attach(
+        &self,
+        events: &mut [TokenCreateEvent],
+        buyers: &HashMap<Pubkey, Vec<Pubkey>>,
+    ) {
+        let profiles = join_all(events.iter().map(|event| async move {
+            let (Ok(creator), Ok(mint)) = (event.creator.parse(), event.mint.parse()) else {
+                return None;
+            };
+            let buyers = buyers.get(&mint).map(Vec::as_slice).unwrap_or_default();
+            match timeout(self.timeout, self.profile(&creator, &mint, buyers)).await {
+                Ok(Ok(profile)) => Some(profile),
+                Ok(Err(e)) => {
+                    warn!("{} failed to profile {} {:?}", MONITOR, creator, e);
+                    None
+                }
+                Err(_) => {
+                    warn!("{} profiling {} timed out", MONITOR, creator);
+                    None
+                }
+            }
+        }))
+        .await;
+        for (event, profile) in events.iter_mut().zip(profiles) {
+            event.creator_profile = profile;
+        }
+    }
+}
+
+fn fee_payer(tx: &EncodedTransactionWithStatusMeta) -> Option<Pubkey> {
+    tx.transaction
+        .decode()?
+        .message
+        .static_account_keys()
+        .first()
+        .copied()
+}
+
+/// Whether a buyer funded by `buyer_funder` belongs to `creator`
+fn is_bundled(
+    creator: &Pubkey,
+    creator_funder: Option<Pubkey>,
+    buyer_funder: Option<Pubkey>,
+) -> bool {
+    buyer_funder.is_some_and(|funder| funder == *creator || Some(funder) == creator_funder)
+}
+
+/// Tokens `creator` launched in `trades` other than `mint`, and how many of
+/// them it sold
+fn count_launches(trades: &[CurveTrade], creator: &Pubkey, mint: &Pubkey) -> (u32, u32) {
+    let mut launched = HashSet::new();
+    let mut sold = HashSet::new();
+    for trade in trades {
+        match *trade {
+            CurveTrade::Create { mint: launch, user } if user == *creator && launch != *mint => {
+                launched.insert(launch);
+            }
+            CurveTrade::Sell {
+                mint: token, user, ..
+            } if user == *creator => {
+                sold.insert(token);
+            }
+            _ => {}
+        }
+    }
+    (
+        launched.len() as u32,
+        launched.intersection(&sold).count() as u32,
+    )
+}
+
+/// Wallets buying each mint in `block`, in execution order
+pub(crate) fn block_buyers(block: &UiConfirmedBlock) -> HashMap<Pubkey, Vec<Pubkey>> {
+    let mut buyers: HashMap<Pubkey, Vec<Pubkey>> = HashMap::new();
+    for trade in block
+        .transactions
+        .iter()
+        .flatten()
+        .filter(|tx| tx_succeeded(tx))
+        .filter_map(transaction_curve_trades)
+        .flatten()
+    {
+        if let CurveTrade::Buy { mint, user, .. } = trade {
+            let mint_buyers = buyers.entry(mint).or_default();
+            if !mint_buyers.contains(&user) {
+                mint_buyers.push(user);
+            }
+        }
+    }
+    buyers
+}
+
+#[test]
+fn test_count_launches_and_bundles() {
+    let (creator, funder, mint) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let (rugged, held) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let trades = [
+        CurveTrade::Create {
+            mint: rugged,
+            user: creator,
+        },
+        CurveTrade::Create {
+            mint: held,
+            user: creator,
+        },
+        CurveTrade::Create {
+            mint,
+            user: creator,
+        },
+        // 别人创建的代币不计入
+        CurveTrade::Create {
+            mint: Pubkey::new_unique(),
+            user: funder,
+        },
+        CurveTrade::Sell {
+            mint: rugged,
+            user: creator,
+            token_amount: 1,
+            sol_limit: 0,
+        },
+        CurveTrade::Sell {
+            mint: held,
+            user: funder,
+            token_amount: 1,
+            sol_limit: 0,
+        },
+    ];
+    assert_eq!(count_launches(&trades, &creator, &mint), (2, 1));
+
+    assert!(is_bundled(&creator, Some(funder), Some(creator)));
+    assert!(is_bundled(&creator, Some(funder), Some(funder)));
+    assert!(!is_bundled(
+        &creator,
+        Some(funder),
+        Some(Pubkey::new_unique())
+    ));
+    assert!(!is_bundled(&creator, None, None));
+}
@@ -0,0 +1,54 @@
+//! Errors the monitors hit while processing blocks.
+//!
+//! A transaction that can't be decoded is skipped instead of stopping its
+//! monitor, and the error is logged and sent on a process-wide broadcast
+//! channel. The Telegram bot forwards them to its chat when
+//! `TELEGRAM_DIAGNOSTICS_ENABLED=true`.
+
+use std::{fmt, sync::OnceLock};
+
+use tokio::sync::broadcast;
+use tracing::warn;
+
+const DIAGNOSTIC_CHANNEL_SIZE: usize = 100;
+
+static DIAGNOSTICS: OnceLock<broadcast::Sender<Diagnostic>> = OnceLock::new();
+
+/// An error hit by a monitor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub monitor: &'static str,
+    /// The skipped transaction, when the error is about a single one
+    pub signature: Option<String>,
+    pub error: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.signature {
+            Some(signature) => write!(f, "{} skipped {}: {}", self.monitor, signature, self.error),
+            None => write!(f, "{}: {}", self.monitor, self.error),
+        }
+    }
+}
+
+fn sender() -> &'static broadcast::Sender<Diagnostic> {
+    DIAGNOSTICS.get_or_init(|| broadcast::channel(DIAGNOSTIC_CHANNEL_SIZE).0)
+}
+
+/// Logs `error` and sends it to the current subscribers, if any
+pub fn report(monitor: &'static str, signature: Option<String>, error: &anyhow::Error) {
+    let diagnostic = Diagnostic {
+        monitor,
+        signature,
+        error: format!("{:#}", error),
+    };
+    warn!("{}", diagnostic);
+    // 没有订阅者时忽略
+    let _ = sender().send(diagnostic);
+}
+
+/// Receives every diagnostic reported from now on
+pub fn subscribe() -> broadcast::Receiver<Diagnostic> {
+    sender().subscribe()
+}
@@ -0,0 +1,260 @@
+//! Contract addresses called in Discord channels.
+//!
+//! Polls the latest messages of the watched channels through the REST API and
+//! publishes each one naming a mint as a [`SignalEvent`], the mint found the
+//! same way as in tweets, see [`super::find_mint`]. The bot has to be a member
+//! of the servers allowed to read the channels' history. It is off unless
+//! `DISCORD_SIGNALS_ENABLED=true`.
+//!
+//! - `DISCORD_BOT_TOKEN`: token of the bot reading the channels
+//! - `DISCORD_CHANNELS`: comma separated channel ids
+//! - `DISCORD_POLL_SECS`: seconds between two polls (default 5)
+//!
+//! Messages posted before the bot started are skipped.
+
+use std::{
+    collections::HashMap,
+    env,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tokio::{sync::broadcast, time::sleep};
+use tracing::{info, warn};
+
+use crate::{constants::discord::API_URL, strategy::parse_env};
+
+use super::{
+    events::{self, MonitorEvent, SignalEvent, SignalSource},
+    find_mint,
+};
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Most messages the API returns at once
+const MAX_MESSAGES: u32 = 100;
+
+#[derive(Debug, Clone)]
+pub struct DiscordConfig {
+    pub token: String,
+    pub channels: Vec<u64>,
+    pub poll_interval: Duration,
+}
+
+impl DiscordConfig {
+    /// Reads the `DISCORD_*` variables, `None` unless
+    /// `DISCORD_SIGNALS_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("DISCORD_SIGNALS_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        let token =
+            env::var("DISCORD_BOT_TOKEN").map_err(|_| anyhow!("DISCORD_BOT_TOKEN is not set"))?;
+        let channels = env::var("DISCORD_CHANNELS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|channel| !channel.is_empty())
+            .map(|channel| {
+                channel
+                    .parse()
+                    .map_err(|_| anyhow!("invalid DISCORD_CHANNELS id {:?}", channel))
+            })
+            .collect::<Result<Vec<u64>>>()?;
+        if channels.is_empty() {
+            return Err(anyhow!("DISCORD_CHANNELS is empty"));
+        }
+        Ok(Some(Self {
+            token,
+            channels,
+            poll_interval: parse_env("DISCORD_POLL_SECS")?
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_POLL_INTERVAL),
+        }))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiscordUser {
+    pub username: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DiscordEmbed {
+    pub title: Option<String>,
+    pub description: Option<String>,
+}
+
+/// A message of the channel messages endpoint
+#[derive(Debug, Deserialize)]
+pub struct DiscordMessage {
+    pub id: String,
+    #[serde(default)]
+    pub content: String,
+    pub author: DiscordUser,
+    /// Call bots often post in embeds
+    #[serde(default)]
+    pub embeds: Vec<DiscordEmbed>,
+}
+
+impl DiscordMessage {
+    /// Snowflake id, growing with the time posted
+    fn snowflake(&self) -> u64 {
+        self.id.parse().unwrap_or_default()
+    }
+
+    /// The content and embeds, one per line
+    pub fn text(&self) -> String {
+        let embeds = self
+            .embeds
+            .iter()
+            .flat_map(|embed| [&embed.title, &embed.description])
+            .flatten();
+        std::iter::once(&self.content)
+            .chain(embeds)
+            .filter(|text| !text.is_empty())
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RateLimited {
+    /// Seconds to wait
+    retry_after: f64,
+}
+
+/// The signals of `messages` posted in `channel`, oldest first
+pub fn signals(
+    channel: u64,
+    messages: &[DiscordMessage],
+    received_at: Instant,
+) -> Vec<SignalEvent> {
+    let mut messages: Vec<&DiscordMessage> = messages.iter().collect();
+    messages.sort_by_key(|message| message.snowflake());
+    messages
+        .into_iter()
+        .filter_map(|message| {
+            let text = message.text();
+            let mint = find_mint(&text)?;
+            Some(SignalEvent {
+                source: SignalSource::Discord,
+                channel: channel.to_string(),
+                message_id: message.id.clone(),
+                author: message.author.username.clone(),
+                text,
+                mint: mint.to_string(),
+                received_at: Some(received_at),
+            })
+        })
+        .collect()
+}
+
+struct DiscordClient {
+    http: reqwest::Client,
+    token: String,
+}
+
+impl DiscordClient {
+    /// The latest `limit` messages of `channel`, only those after the message
+    /// `after` if given
+    async fn messages(
+        &self,
+        channel: u64,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<DiscordMessage>> {
+        let mut request = self
+            .http
+            .get(format!("{}/channels/{}/messages", API_URL, channel))
+            .header("Authorization", format!("Bot {}", self.token))
+            .query(&[("limit", limit.to_string())]);
+        if let Some(after) = after {
+            request = request.query(&[("after", after)]);
+        }
+        let response = request.send().await?;
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            let wait = response.json::<RateLimited>().await?.retry_after;
+            sleep(Duration::from_secs_f64(wait.max(0.0))).await;
+            return Err(anyhow!("rate limited for {}s", wait));
+        }
+        Ok(response.error_for_status()?.json().await?)
+    }
+}
+
+/// Publishes the calls of the watched channels forever, also sending them on
+/// `events_out`
+pub async fn run(config: DiscordConfig, events_out: broadcast::Sender<MonitorEvent>) {
+    let client = DiscordClient {
+        http: reqwest::Client::new(),
+        token: config.token.clone(),
+    };
+    // 每个频道最新的消息
+    let mut cursors: HashMap<u64, String> = HashMap::new();
+    info!("watching {} discord channels", config.channels.len());
+    loop {
+        for &channel in &config.channels {
+            let after = cursors.get(&channel).cloned();
+            // 第一次只取最新一条作为起点
+            let limit = if after.is_some() { MAX_MESSAGES } else { 1 };
+            let messages = match client.messages(channel, after.as_deref(), limit).await {
+                Ok(messages) => messages,
+                Err(e) => {
+                    warn!("failed to poll discord channel {} {:?}", channel, e);
+                    continue;
+                }
+            };
+            if let Some(newest) = messages.iter().max_by_key(|message| message.snowflake()) {
+                cursors.insert(channel, newest.id.clone());
+            }
+            if after.is_none() {
+                cursors.entry(channel).or_insert_with(|| "0".to_string());
+                continue;
+            }
+            for signal in signals(channel, &messages, Instant::now()) {
+                info!("discord call of {} in {}", signal.mint, channel);
+                let event = MonitorEvent::Signal(signal);
+                events::publish(event.clone());
+                // 没有接收者时忽略
+                let _ = events_out.send(event);
+            }
+        }
+        sleep(config.poll_interval).await;
+    }
+}
+
+#[test]
+fn test_discord_signals() {
+    use solana_sdk::pubkey::Pubkey;
+
+    let mint = Pubkey::new_unique();
+    let messages: Vec<DiscordMessage> = serde_json::from_value(serde_json::json!([
+        {
+            "id": "300",
+            "content": "",
+            "author": {"username": "callbot"},
+            "embeds": [{"title": "New call", "description": format!("CA: {}", mint)}],
+        },
+        {"id": "200", "content": "gm", "author": {"username": "alice"}},
+        {
+            "id": "100",
+            "content": format!("aping {} now", mint),
+            "author": {"username": "bob"},
+        },
+    ]))
+    .unwrap();
+    assert_eq!(messages[0].text(), format!("New call\nCA: {}", mint));
+
+    let calls = signals(42, &messages, Instant::now());
+    assert_eq!(calls.len(), 2);
+    // 按发布时间排序
+    assert_eq!(calls[0].author, "bob");
+    assert_eq!(calls[1].message_id, "300");
+    assert_eq!(calls[1].mint, mint.to_string());
+    assert_eq!(calls[1].channel, "42");
+}
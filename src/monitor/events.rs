@@ -0,0 +1,364 @@
+//! Typed detections published by the monitors.
+//!
+//! Every create/migration the listeners detect is also sent on a process-wide
+//! broadcast channel, so consumers other than Telegram (e.g. the events API)
+//! can subscribe. Subscribers only see events sent after they subscribed.
+
+use std::{fmt, str::FromStr, sync::OnceLock, time::Instant};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::portfolio::Side;
+
+const EVENT_CHANNEL_SIZE: usize = 1000;
+
+static EVENTS: OnceLock<broadcast::Sender<MonitorEvent>> = OnceLock::new();
+
+/// The creator's buy in the create transaction
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct DevBuy {
+    /// Tokens bought, in raw units
+    pub token_amount: u64,
+    /// SOL spent on a fresh curve, in lamports before fees
+    pub sol_cost: u64,
+    /// Share of the total supply bought
+    pub supply_pct: f64,
+}
+
+/// What the creator's wallet history says about a new token
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CreatorProfile {
+    /// Fee payer of the creator's oldest transaction seen, `None` when the
+    /// creator paid it
+    pub funder: Option<String>,
+    /// Age of the creator's oldest transaction seen, in seconds
+    pub wallet_age_secs: Option<u64>,
+    /// Pump.fun tokens the creator launched before, among its recent
+    /// transactions
+    pub prior_launches: u32,
+    /// Prior launches the creator also sold on the curve
+    pub prior_rugs: u32,
+    /// Other buyers of the token in the creation block funded by the creator
+    /// or by the creator's funder
+    pub bundled_buyers: Vec<String>,
+}
+
+impl fmt::Display for CreatorProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} prior launches, {} rugs, {} bundled buyers",
+            self.prior_launches,
+            self.prior_rugs,
+            self.bundled_buyers.len()
+        )?;
+        if let Some(funder) = &self.funder {
+            write!(f, ", funded by {}", funder)?;
+        }
+        Ok(())
+    }
+}
+
+/// A Pump.fun token create
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenCreateEvent {
+    pub signature: String,
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub mint: String,
+    pub bonding_curve: String,
+    pub associated_bonding_curve: String,
+    pub creator: String,
+    pub dev_buy: Option<DevBuy>,
+    /// Dev bought more than the configured share of supply
+    pub dev_alert: bool,
+    /// Set when `CREATOR_ANALYSIS_ENABLED=true` and the analysis finished in
+    /// time, see [`crate::monitor::creator`]
+    pub creator_profile: Option<CreatorProfile>,
+    /// When the block of the create arrived
+    #[serde(skip)]
+    pub received_at: Option<Instant>,
+}
+
+/// AMM a completed curve migrated to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationVenue {
+    Raydium,
+    PumpSwap,
+}
+
+impl fmt::Display for MigrationVenue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationVenue::Raydium => write!(f, "Raydium"),
+            MigrationVenue::PumpSwap => write!(f, "PumpSwap"),
+        }
+    }
+}
+
+/// A pool initialized by a migration
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationEvent {
+    pub venue: MigrationVenue,
+    pub signature: String,
+    pub coin_mint: String,
+    pub pc_mint: String,
+    pub pool_id: String,
+    /// When the block of the migration arrived
+    #[serde(skip)]
+    pub received_at: Option<Instant>,
+}
+
+/// A transaction sent by the engine
+#[derive(Debug, Clone, Serialize)]
+pub struct TxSentEvent {
+    pub signature: String,
+}
+
+/// A tracked transaction confirmed without error
+#[derive(Debug, Clone, Serialize)]
+pub struct TxLandedEvent {
+    pub signature: String,
+    pub slot: u64,
+    /// Times the transaction was sent, rebroadcasts included
+    pub attempts: u32,
+}
+
+/// A tracked transaction that failed on chain or never landed
+#[derive(Debug, Clone, Serialize)]
+pub struct TxFailedEvent {
+    /// The last signature sent
+    pub signature: String,
+    pub error: String,
+    pub attempts: u32,
+}
+
+/// A sell refused because the wallet's token account is frozen
+#[derive(Debug, Clone, Serialize)]
+pub struct FrozenEvent {
+    pub mint: String,
+    /// Wallet holding the tokens
+    pub owner: String,
+    /// The frozen token account
+    pub account: String,
+    /// Freeze authority of the mint, `None` once revoked
+    pub freeze_authority: Option<String>,
+}
+
+/// Amounts of a Raydium AMM swap instruction, in raw units
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SwapAmounts {
+    /// `swap_base_in`, exact input
+    BaseIn {
+        amount_in: u64,
+        minimum_amount_out: u64,
+    },
+    /// `swap_base_out`, exact output
+    BaseOut { max_amount_in: u64, amount_out: u64 },
+}
+
+/// A Raydium AMM swap seen before it landed
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingSwapEvent {
+    pub signature: String,
+    /// Slot the feed saw the transaction in
+    pub slot: u64,
+    /// AMM account of the pool
+    pub pool: String,
+    pub user: String,
+    pub user_source: String,
+    pub user_destination: String,
+    pub amounts: SwapAmounts,
+    /// When the feed delivered the transaction
+    #[serde(skip)]
+    pub received_at: Option<Instant>,
+}
+
+/// A buy or sell instruction on a Pump.fun bonding curve
+#[derive(Debug, Clone, Serialize)]
+pub struct PumpTradeEvent {
+    pub signature: String,
+    pub mint: String,
+    pub trader: String,
+    pub side: Side,
+    /// Tokens bought or sold, in raw units
+    pub token_amount: u64,
+    /// Most lamports a buy spends, or least a sell receives
+    pub sol_limit: u64,
+    /// Unix timestamp of the block, if known
+    pub block_time: Option<i64>,
+    /// When the block of the trade arrived
+    #[serde(skip)]
+    pub received_at: Option<Instant>,
+}
+
+/// Bonding curve state of a tracked Pump.fun token
+#[derive(Debug, Clone, Serialize)]
+pub struct CurveEvent {
+    pub mint: String,
+    pub bonding_curve: String,
+    /// Slot of the account update
+    pub slot: u64,
+    /// Share of the curve's tokens sold, see
+    /// [`crate::pumpfun::accounts::BondingCurveAccount::get_progress_pct`]
+    pub progress_pct: f64,
+    /// SOL in the curve, in lamports
+    pub real_sol_reserves: u64,
+    /// Market cap at the curve price, in lamports
+    pub market_cap: u64,
+    /// When the account update arrived
+    #[serde(skip)]
+    pub received_at: Option<Instant>,
+}
+
+/// Where a call was posted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignalSource {
+    Discord,
+    Telegram,
+}
+
+impl fmt::Display for SignalSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignalSource::Discord => write!(f, "Discord"),
+            SignalSource::Telegram => write!(f, "Telegram"),
+        }
+    }
+}
+
+impl FromStr for SignalSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "discord" => Ok(SignalSource::Discord),
+            "telegram" => Ok(SignalSource::Telegram),
+            _ => Err(anyhow::anyhow!("unknown signal source {:?}", s)),
+        }
+    }
+}
+
+/// A message naming a contract address in a watched channel
+#[derive(Debug, Clone, Serialize)]
+pub struct SignalEvent {
+    pub source: SignalSource,
+    /// Channel id or name
+    pub channel: String,
+    /// Id of the message in the channel
+    pub message_id: String,
+    pub author: String,
+    pub text: String,
+    /// First mint named in the message
+    pub mint: String,
+    /// When the message was fetched
+    #[serde(skip)]
+    pub received_at: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MonitorEvent {
+    Create(TokenCreateEvent),
+    Migration(MigrationEvent),
+    TxSent(TxSentEvent),
+    TxLanded(TxLandedEvent),
+    TxFailed(TxFailedEvent),
+    PendingSwap(PendingSwapEvent),
+    CurveProgress(CurveEvent),
+    /// The curve sold out, migration is imminent
+    CurveComplete(CurveEvent),
+    PumpTrade(PumpTradeEvent),
+    Signal(SignalEvent),
+    Frozen(FrozenEvent),
+}
+
+/// Serialized `type` of every event
+pub const EVENT_TYPES: [&str; 11] = [
+    "create",
+    "migration",
+    "tx_sent",
+    "tx_landed",
+    "tx_failed",
+    "pending_swap",
+    "curve_progress",
+    "curve_complete",
+    "pump_trade",
+    "signal",
+    "frozen",
+];
+
+impl MonitorEvent {
+    /// The serialized `type` of the event, one of [`EVENT_TYPES`]
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            MonitorEvent::Create(_) => "create",
+            MonitorEvent::Migration(_) => "migration",
+            MonitorEvent::TxSent(_) => "tx_sent",
+            MonitorEvent::TxLanded(_) => "tx_landed",
+            MonitorEvent::TxFailed(_) => "tx_failed",
+            MonitorEvent::PendingSwap(_) => "pending_swap",
+            MonitorEvent::CurveProgress(_) => "curve_progress",
+            MonitorEvent::CurveComplete(_) => "curve_complete",
+            MonitorEvent::PumpTrade(_) => "pump_trade",
+            MonitorEvent::Signal(_) => "signal",
+            MonitorEvent::Frozen(_) => "frozen",
+        }
+    }
+
+    /// When the block, update or message behind a detection arrived, `None`
+    /// for the others
+    pub fn received_at(&self) -> Option<Instant> {
+        match self {
+            MonitorEvent::Create(event) => event.received_at,
+            MonitorEvent::Migration(event) => event.received_at,
+            MonitorEvent::PendingSwap(event) => event.received_at,
+            MonitorEvent::CurveProgress(event) | MonitorEvent::CurveComplete(event) => {
+                event.received_at
+            }
+            MonitorEvent::PumpTrade(event) => event.received_at,
+            MonitorEvent::Signal(event) => event.received_at,
+            _ => None,
+        }
+    }
+}
+
+fn sender() -> &'static broadcast::Sender<MonitorEvent> {
+    EVENTS.get_or_init(|| broadcast::channel(EVENT_CHANNEL_SIZE).0)
+}
+
+/// Sends `event` to the current subscribers, if any
+pub fn publish(event: MonitorEvent) {
+    // 没有订阅者时忽略
+    let _ = sender().send(event);
+}
+
+/// Receives every event published from now on
+pub fn subscribe() -> broadcast::Receiver<MonitorEvent> {
+    sender().subscribe()
+}
+
+#[tokio::test]
+async fn test_subscribers_receive_tagged_events() {
+    let mut receiver = subscribe();
+    publish(MonitorEvent::Migration(MigrationEvent {
+        venue: MigrationVenue::PumpSwap,
+        signature: "sig".to_string(),
+        coin_mint: "coin".to_string(),
+        pc_mint: "pc".to_string(),
+        pool_id: "pool".to_string(),
+        received_at: None,
+    }));
+
+    let event = receiver.recv().await.unwrap();
+    let json = serde_json::to_value(&event).unwrap();
+    assert_eq!(json["type"], "migration");
+    assert_eq!(json["type"], event.event_type());
+    assert_eq!(json["pool_id"], "pool");
+    assert_eq!(json["venue"], "pump_swap");
+}
@@ -0,0 +1,288 @@
+//! Yellowstone gRPC (Geyser) block source.
+//!
+//! `block_subscribe` sends every whole block as JSON over the websocket and
+//! skips blocks when the node falls behind. With `GEYSER_ENABLED=true` the
+//! create and migration listeners subscribe to the Yellowstone endpoint at
+//! `GEYSER_ENDPOINT` instead, authenticated with `GEYSER_X_TOKEN` if set, for
+//! the confirmed transactions of the programs they decode and the block meta
+//! of every slot.
+//!
+//! The transactions of a slot are collected until its block meta arrives,
+//! then sent on the listener's block channel as one [`UiConfirmedBlock`],
+//! encoded the way `block_subscribe` encodes it, so the block processors run
+//! unchanged. Such a block only holds the transactions mentioning the
+//! programs; slots without any aren't sent.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    time::Instant,
+};
+
+use anyhow::{anyhow, Result};
+use futures_util::{SinkExt, StreamExt};
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::{
+    BlockEncodingOptions, ConfirmedBlock, TransactionDetails, TransactionWithStatusMeta,
+    UiConfirmedBlock, UiTransactionEncoding,
+};
+use tokio::{sync::broadcast, time::sleep};
+use tracing::{info, warn};
+use yellowstone_grpc_client::{ClientTlsConfig, GeyserGrpcClient, Interceptor};
+use yellowstone_grpc_proto::{
+    convert_from::create_tx_with_meta,
+    prelude::{
+        subscribe_update::UpdateOneof, CommitmentLevel, SubscribeRequest,
+        SubscribeRequestFilterBlocksMeta, SubscribeRequestFilterTransactions, SubscribeRequestPing,
+        SubscribeUpdateBlockMeta,
+    },
+};
+
+use crate::{metrics, strategy::parse_env};
+
+use super::{diagnostics, lag, MAX_RECONNECT_DELAY, RECONNECT_DELAY};
+
+#[derive(Debug, Clone)]
+pub struct GeyserConfig {
+    pub endpoint: String,
+    pub x_token: Option<String>,
+}
+
+impl GeyserConfig {
+    /// The config if `GEYSER_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("GEYSER_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        let endpoint =
+            env::var("GEYSER_ENDPOINT").map_err(|_| anyhow!("GEYSER_ENDPOINT is not set"))?;
+        let x_token = env::var("GEYSER_X_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty());
+        Ok(Some(Self { endpoint, x_token }))
+    }
+}
+
+/// Confirmed successful transactions mentioning `programs`, and every block
+/// meta
+fn subscribe_request(programs: &[Pubkey]) -> SubscribeRequest {
+    SubscribeRequest {
+        transactions: HashMap::from([(
+            "programs".to_string(),
+            SubscribeRequestFilterTransactions {
+                vote: Some(false),
+                // 处理器本来就跳过失败的交易
+                failed: Some(false),
+                account_include: programs.iter().map(Pubkey::to_string).collect(),
+                ..Default::default()
+            },
+        )]),
+        blocks_meta: HashMap::from([(
+            "blocks".to_string(),
+            SubscribeRequestFilterBlocksMeta::default(),
+        )]),
+        commitment: Some(CommitmentLevel::Confirmed as i32),
+        ..Default::default()
+    }
+}
+
+/// Transactions per slot, until the slot's block meta arrives
+#[derive(Default)]
+struct PendingBlocks {
+    slots: BTreeMap<u64, Vec<(u64, TransactionWithStatusMeta)>>,
+}
+
+impl PendingBlocks {
+    /// Adds the transaction at `index` in the block of `slot`
+    fn push(&mut self, slot: u64, index: u64, tx: TransactionWithStatusMeta) {
+        self.slots.entry(slot).or_default().push((index, tx));
+    }
+
+    /// The block `meta` completes, its transactions in block order, if any
+    ///
+    /// Earlier slots still pending never got their meta, a fork the cluster
+    /// abandoned, and are dropped.
+    fn complete(&mut self, meta: SubscribeUpdateBlockMeta) -> Option<ConfirmedBlock> {
+        let later = self.slots.split_off(&(meta.slot + 1));
+        let mut slots = std::mem::replace(&mut self.slots, later);
+        let mut transactions = slots.remove(&meta.slot)?;
+        for slot in slots.keys() {
+            warn!("geyser dropped slot {} without block meta", slot);
+        }
+        transactions.sort_by_key(|(index, _)| *index);
+        Some(ConfirmedBlock {
+            previous_blockhash: meta.parent_blockhash,
+            blockhash: meta.blockhash,
+            parent_slot: meta.parent_slot,
+            transactions: transactions.into_iter().map(|(_, tx)| tx).collect(),
+            rewards: vec![],
+            num_partitions: None,
+            block_time: meta.block_time.map(|time| time.timestamp),
+            block_height: meta.block_height.map(|height| height.block_height),
+        })
+    }
+}
+
+/// Encodes `block` as `block_subscribe` does for the monitors
+fn encode(block: ConfirmedBlock) -> Result<UiConfirmedBlock> {
+    Ok(block.encode_with_options(
+        UiTransactionEncoding::Binary,
+        BlockEncodingOptions {
+            transaction_details: TransactionDetails::Full,
+            show_rewards: false,
+            max_supported_transaction_version: Some(0),
+        },
+    )?)
+}
+
+async fn connect(config: &GeyserConfig) -> Result<GeyserGrpcClient<impl Interceptor>> {
+    let mut builder = GeyserGrpcClient::build_from_shared(config.endpoint.clone())?
+        .x_token(config.x_token.clone())?;
+    if config.endpoint.starts_with("https") {
+        builder = builder.tls_config(ClientTlsConfig::new().with_native_roots())?;
+    }
+    Ok(builder.connect().await?)
+}
+
+/// Streams the blocks of `programs`' transactions into `block_sender`
+/// forever, with the instant each one completed
+///
+/// Like [`super::stream_blocks`], a failed or ended subscription is retried
+/// with exponential backoff and `block_sender` reused.
+pub(crate) async fn stream_blocks(
+    config: GeyserConfig,
+    programs: Vec<Pubkey>,
+    monitor: &'static str,
+    block_sender: broadcast::Sender<(Instant, UiConfirmedBlock)>,
+) {
+    let last_slot = lag::last_slot(monitor);
+    let request = subscribe_request(&programs);
+    let mut delay = RECONNECT_DELAY;
+    loop {
+        let mut client = match connect(&config).await {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("{} failed to connect to geyser {:?}", monitor, e);
+                sleep(delay).await;
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                continue;
+            }
+        };
+
+        match client.subscribe_with_request(Some(request.clone())).await {
+            Ok((mut sink, mut stream)) => {
+                info!("{} subscribed to geyser {}", monitor, config.endpoint);
+                delay = RECONNECT_DELAY;
+                let mut pending = PendingBlocks::default();
+                while let Some(update) = stream.next().await {
+                    let update = match update {
+                        Ok(update) => update,
+                        Err(e) => {
+                            warn!("{} geyser stream failed {:?}", monitor, e);
+                            break;
+                        }
+                    };
+                    match update.update_oneof {
+                        Some(UpdateOneof::Transaction(update)) => {
+                            let Some(info) = update.transaction else {
+                                continue;
+                            };
+                            let index = info.index;
+                            match create_tx_with_meta(info) {
+                                Ok(tx) => pending.push(update.slot, index, tx),
+                                Err(e) => diagnostics::report(monitor, None, &anyhow!(e)),
+                            }
+                        }
+                        Some(UpdateOneof::BlockMeta(meta)) => {
+                            let received_at = Instant::now();
+                            metrics::record_block(monitor);
+                            lag::record_slot(&last_slot, monitor, meta.slot);
+                            let Some(block) = pending.complete(meta) else {
+                                continue;
+                            };
+                            match encode(block) {
+                                // 没有接收者时忽略
+                                Ok(block) => {
+                                    let _ = block_sender.send((received_at, block));
+                                }
+                                Err(e) => diagnostics::report(monitor, None, &e),
+                            }
+                        }
+                        Some(UpdateOneof::Ping(_)) => {
+                            // 有的负载均衡器要客户端也发消息才不断开，带上原来的过滤条件
+                            let ping = SubscribeRequest {
+                                ping: Some(SubscribeRequestPing { id: 1 }),
+                                ..request.clone()
+                            };
+                            if let Err(e) = sink.send(ping).await {
+                                warn!("{} failed to ping geyser {:?}", monitor, e);
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                warn!("{} geyser stream closed, reconnecting", monitor);
+            }
+            Err(e) => warn!("{} failed to subscribe to geyser {:?}", monitor, e),
+        }
+
+        sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+#[test]
+fn test_pending_blocks_complete_in_block_order() {
+    use solana_sdk::{signature::Signature, transaction::Transaction};
+    use yellowstone_grpc_proto::prelude::UnixTimestamp;
+
+    let tx = |signature: Signature| {
+        let mut tx = Transaction::new_with_payer(&[], Some(&Pubkey::new_unique()));
+        tx.signatures = vec![signature];
+        TransactionWithStatusMeta::MissingMetadata(tx)
+    };
+    let (first, second) = (Signature::new_unique(), Signature::new_unique());
+    let mut pending = PendingBlocks::default();
+    pending.push(9, 0, tx(Signature::new_unique()));
+    pending.push(10, 5, tx(second));
+    pending.push(10, 2, tx(first));
+    pending.push(11, 0, tx(Signature::new_unique()));
+
+    let block = pending
+        .complete(SubscribeUpdateBlockMeta {
+            slot: 10,
+            blockhash: "hash".to_string(),
+            parent_slot: 9,
+            block_time: Some(UnixTimestamp {
+                timestamp: 1_700_000_000,
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+    // 9没等到block meta被丢弃，11还在等
+    assert_eq!(pending.slots.keys().copied().collect::<Vec<_>>(), [11]);
+    assert!(pending
+        .complete(SubscribeUpdateBlockMeta {
+            slot: 10,
+            ..Default::default()
+        })
+        .is_none());
+
+    let block = encode(block).unwrap();
+    assert_eq!(block.blockhash, "hash");
+    assert_eq!(block.parent_slot, 9);
+    assert_eq!(block.block_time, Some(1_700_000_000));
+    let signatures: Vec<_> = block
+        .transactions
+        .unwrap()
+        .iter()
+        .map(|tx| tx.transaction.decode().unwrap().signatures[0])
+        .collect();
+    assert_eq!(signatures, [first, second]);
+
+    let request = subscribe_request(&[Pubkey::new_unique()]);
+    assert_eq!(request.commitment, Some(CommitmentLevel::Confirmed as i32));
+    assert_eq!(request.transactions["programs"].account_include.len(), 1);
+}
@@ -0,0 +1,119 @@
+//! Last slot seen per monitor, to tell how far behind real time a feed is.
+//!
+//! The lag is the node's current slot minus the last slot a monitor received.
+//! A warning is logged when it exceeds `MONITOR_LAG_WARN_SLOTS` (default 20).
+
+use std::{
+    collections::BTreeMap,
+    env, fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock, Mutex,
+    },
+};
+
+use anyhow::Result;
+use serde::Serialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use tracing::warn;
+
+use crate::metrics;
+
+const DEFAULT_LAG_WARN_SLOTS: u64 = 20;
+
+static LAST_SLOTS: LazyLock<Mutex<BTreeMap<&'static str, Arc<AtomicU64>>>> =
+    LazyLock::new(Default::default);
+
+fn get_lag_warn_slots() -> u64 {
+    env::var("MONITOR_LAG_WARN_SLOTS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_LAG_WARN_SLOTS)
+}
+
+/// Last slot received by `monitor`, 0 until its first block
+pub fn last_slot(monitor: &'static str) -> Arc<AtomicU64> {
+    LAST_SLOTS
+        .lock()
+        .unwrap()
+        .entry(monitor)
+        .or_default()
+        .clone()
+}
+
+/// Records that `monitor` received the block at `slot`
+pub fn record_slot(last_slot: &AtomicU64, monitor: &str, slot: u64) {
+    // 重连后可能收到旧的slot
+    last_slot.fetch_max(slot, Ordering::Relaxed);
+    metrics::MONITOR_LAST_SLOT
+        .with_label_values(&[monitor])
+        .set(slot as i64);
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct MonitorLag {
+    pub monitor: &'static str,
+    pub last_slot: u64,
+    /// Slots between the node's current slot and `last_slot`
+    pub lag: u64,
+}
+
+impl fmt::Display for MonitorLag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} monitor lag: {} slots (last slot {})",
+            self.monitor, self.lag, self.last_slot
+        )
+    }
+}
+
+/// Lag of every monitor that has received a block, against `current_slot`
+pub fn lags_at(current_slot: u64) -> Vec<MonitorLag> {
+    LAST_SLOTS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(monitor, last_slot)| (*monitor, last_slot.load(Ordering::Relaxed)))
+        .filter(|(_, last_slot)| *last_slot > 0)
+        .map(|(monitor, last_slot)| MonitorLag {
+            monitor,
+            last_slot,
+            lag: current_slot.saturating_sub(last_slot),
+        })
+        .collect()
+}
+
+/// Lag of every monitor against the node's current slot
+pub async fn monitor_lags(client: &RpcClient) -> Result<Vec<MonitorLag>> {
+    let lags = lags_at(client.get_slot().await?);
+    let threshold = get_lag_warn_slots();
+    for lag in &lags {
+        metrics::MONITOR_LAG_SLOTS
+            .with_label_values(&[lag.monitor])
+            .set(lag.lag as i64);
+        if lag.lag > threshold {
+            warn!("{}, over {} slots", lag, threshold);
+        }
+    }
+    Ok(lags)
+}
+
+#[test]
+fn test_lags_at_current_slot() {
+    let slot = last_slot("test_lag");
+    assert!(lags_at(100).iter().all(|lag| lag.monitor != "test_lag"));
+
+    record_slot(&slot, "test_lag", 90);
+    record_slot(&slot, "test_lag", 85);
+    let lag = lags_at(100)
+        .into_iter()
+        .find(|lag| lag.monitor == "test_lag")
+        .unwrap();
+    assert_eq!(lag.last_slot, 90);
+    assert_eq!(lag.lag, 10);
+    assert_eq!(
+        lag.to_string(),
+        "test_lag monitor lag: 10 slots (last slot 90)"
+    );
+}
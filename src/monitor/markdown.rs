@@ -0,0 +1,106 @@
+//! Telegram MarkdownV2 escaping and message templates.
+//!
+//! Templates are plain MarkdownV2 with `{field}` placeholders. Field values
+//! are escaped for where they land: inside a code span/block only `` ` `` and
+//! `\` are escaped, everywhere else every reserved character is. Unknown
+//! placeholders are left as they are.
+
+/// Characters MarkdownV2 reserves outside code entities
+const RESERVED: &[char] = &[
+    '_', '*', '[', ']', '(', ')', '~', '`', '>', '#', '+', '-', '=', '|', '{', '}', '.', '!', '\\',
+];
+
+/// Escapes `text` for use as plain MarkdownV2 text
+pub fn escape_markdown_v2(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if RESERVED.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escapes `text` for use inside a MarkdownV2 code span or block
+pub fn escape_markdown_v2_code(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == '`' || c == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Fills the `{field}` placeholders of `template` with escaped values
+pub fn render(template: &str, fields: &[(&str, String)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut in_code = false;
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // ``` 是三次切换，结果同样是进入/离开代码块
+            '`' => {
+                in_code = !in_code;
+                out.push(c);
+            }
+            // 模板中已转义的字符原样输出
+            '\\' => {
+                out.push(c);
+                if let Some(next) = chars.next() {
+                    out.push(next);
+                }
+            }
+            '{' => {
+                let mut key = String::new();
+                let mut closed = false;
+                while let Some(&next) = chars.peek() {
+                    chars.next();
+                    if next == '}' {
+                        closed = true;
+                        break;
+                    }
+                    key.push(next);
+                }
+                match fields.iter().find(|(name, _)| *name == key) {
+                    Some((_, value)) if closed => out.push_str(&if in_code {
+                        escape_markdown_v2_code(value)
+                    } else {
+                        escape_markdown_v2(value)
+                    }),
+                    _ => {
+                        out.push('{');
+                        out.push_str(&key);
+                        if closed {
+                            out.push('}');
+                        }
+                    }
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[test]
+fn test_escape_every_reserved_character() {
+    let name = "_*[]()~`>#+-=|{}.!\\";
+    assert_eq!(
+        escape_markdown_v2(name),
+        "\\_\\*\\[\\]\\(\\)\\~\\`\\>\\#\\+\\-\\=\\|\\{\\}\\.\\!\\\\"
+    );
+    assert_eq!(escape_markdown_v2_code(name), "_*[]()~\\`>#+-=|{}.!\\\\");
+    assert_eq!(escape_markdown_v2("PEPE 2.0 (fun)"), "PEPE 2\\.0 \\(fun\\)");
+}
+
+#[test]
+fn test_render_escapes_by_context() {
+    let fields = [("name", "a_b`c".to_string())];
+    assert_eq!(
+        render("*{name}*\n```\n{name}\n```{missing}", &fields),
+        "*a\\_b\\`c*\n```\na_b\\`c\n```{missing}"
+    );
+}
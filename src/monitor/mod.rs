@@ -1,3 +1,201 @@
+//! Block monitors.
+//!
+//! Each listener owns two broadcast channels that live as long as the
+//! listener: raw blocks from the websocket, and the typed events detected in
+//! them. The websocket is resubscribed on failure without recreating either
+//! channel, so receivers obtained from the returned event sender keep working
+//! across reconnects.
+//!
+//! A receiver that falls more than `channel_size` messages behind gets
+//! `RecvError::Lagged(n)` once, loses the `n` oldest messages, then resumes
+//! with the oldest one still buffered. Slow consumers should log and carry on
+//! rather than treat it as fatal.
+//!
+//! The create and migration listeners read their blocks from the websocket's
+//! `block_subscribe`, or from a Yellowstone gRPC endpoint with
+//! `GEYSER_ENABLED=true`, see [`geyser`].
+
+pub mod bonding_curve;
+pub mod creator;
+pub mod diagnostics;
+pub mod discord;
+pub mod events;
+pub mod geyser;
+pub mod lag;
+pub mod markdown;
+pub mod pending_swaps;
+pub mod telegram_channels;
 pub mod token_create;
 pub mod token_migration;
 pub mod twitter;
+pub mod wallet_tracker;
+
+use std::{
+    future::Future,
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
+};
+
+use futures_util::StreamExt;
+use regex::Regex;
+use solana_client::{
+    nonblocking::pubsub_client::PubsubClient,
+    rpc_config::{RpcBlockSubscribeConfig, RpcBlockSubscribeFilter},
+};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use solana_transaction_status_client_types::{
+    EncodedTransactionWithStatusMeta, TransactionDetails, UiConfirmedBlock, UiTransactionEncoding,
+};
+use tokio::{sync::broadcast, time::sleep};
+use tracing::warn;
+
+use crate::{metrics, new_ws_client, notify::Notifier};
+use events::MonitorEvent;
+
+/// Default capacity of the block and event channels
+pub const DEFAULT_CHANNEL_SIZE: usize = 1024;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// First base58 string of `text` that is a valid pubkey, the contract address
+/// of a call
+pub fn find_mint(text: &str) -> Option<Pubkey> {
+    static ADDRESS: OnceLock<Regex> = OnceLock::new();
+    ADDRESS
+        .get_or_init(|| Regex::new(r"[1-9A-HJ-NP-Za-km-z]{32,44}").unwrap())
+        .find_iter(text)
+        .find_map(|m| m.as_str().parse().ok())
+}
+
+/// Whether `tx` executed successfully, failed transactions still carry logs
+pub fn tx_succeeded(tx: &EncodedTransactionWithStatusMeta) -> bool {
+    tx.meta.as_ref().is_some_and(|meta| meta.err.is_none())
+}
+
+/// Streams confirmed blocks into `block_sender` forever, with the instant
+/// each one arrived
+///
+/// When the subscription fails or ends, a new websocket client is created and
+/// the subscription retried with exponential backoff. `block_sender` is reused,
+/// so its receivers never notice the reconnect.
+pub(crate) async fn stream_blocks(
+    ws_client: Arc<PubsubClient>,
+    monitor: &'static str,
+    block_sender: broadcast::Sender<(Instant, UiConfirmedBlock)>,
+) {
+    let last_slot = lag::last_slot(monitor);
+    let mut ws_client = Some(ws_client);
+    let mut delay = RECONNECT_DELAY;
+    loop {
+        let client = match ws_client.take() {
+            Some(client) => client,
+            None => match new_ws_client().await {
+                Ok(client) => client,
+                Err(e) => {
+                    warn!("{} failed to reconnect websocket {:?}", monitor, e);
+                    sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                    continue;
+                }
+            },
+        };
+
+        match client
+            .block_subscribe(
+                // 只关注migrator
+                // RpcBlockSubscribeFilter::MentionsAccountOrProgram(program_ids().pumpfun_migrator.to_string()),
+                RpcBlockSubscribeFilter::All,
+                // 区块信息配置
+                Some(RpcBlockSubscribeConfig {
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    encoding: Some(UiTransactionEncoding::Binary),
+                    transaction_details: Some(TransactionDetails::Full),
+                    show_rewards: Some(false),
+                    max_supported_transaction_version: Some(0),
+                }),
+            )
+            .await
+        {
+            Ok((mut stream, _)) => {
+                delay = RECONNECT_DELAY;
+                // 发送block
+                while let Some(new_block) = stream.next().await {
+                    let received_at = Instant::now();
+                    metrics::record_block(monitor);
+                    lag::record_slot(&last_slot, monitor, new_block.value.slot);
+                    if let Some(block) = new_block.value.block {
+                        // 没有接收者时忽略
+                        let _ = block_sender.send((received_at, block));
+                    }
+                }
+                warn!("{} block stream closed, reconnecting", monitor);
+            }
+            Err(e) => warn!("{} failed to subscribe blocks {:?}", monitor, e),
+        }
+
+        sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+/// Forwards every event on `event_sender` to `notifier`, skipping over lag
+pub(crate) fn notify_events(
+    event_sender: &broadcast::Sender<MonitorEvent>,
+    notifier: Arc<dyn Notifier>,
+) -> impl Future<Output = ()> + Send {
+    let mut receiver = event_sender.subscribe();
+    async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    if let Err(e) = notifier.notify(&event).await {
+                        eprintln!("notify error {:?}", e);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("notifier lagged, skipped {} events", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_notify_events_survives_lag() {
+    use std::sync::Mutex;
+
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use events::TxSentEvent;
+
+    #[derive(Default)]
+    struct Recording(Mutex<Vec<String>>);
+
+    #[async_trait]
+    impl Notifier for Recording {
+        async fn notify(&self, event: &MonitorEvent) -> Result<()> {
+            if let MonitorEvent::TxSent(event) = event {
+                self.0.lock().unwrap().push(event.signature.clone());
+            }
+            Ok(())
+        }
+    }
+
+    let (event_sender, _) = broadcast::channel(2);
+    let recording = Arc::new(Recording::default());
+    let consumer = notify_events(&event_sender, recording.clone());
+    for i in 0..5 {
+        event_sender
+            .send(MonitorEvent::TxSent(TxSentEvent {
+                signature: i.to_string(),
+            }))
+            .unwrap();
+    }
+    drop(event_sender);
+
+    // 落后的接收者跳过最旧的消息后继续
+    consumer.await;
+    assert_eq!(*recording.0.lock().unwrap(), vec!["3", "4"]);
+}
@@ -0,0 +1,254 @@
+//! Raydium swaps seen before they land.
+//!
+//! Subscribes with `transactionSubscribe` to the Geyser-enhanced websocket at
+//! `PENDING_SWAPS_WS_URL`, as served by Helius and other Geyser providers, for
+//! the transactions mentioning the Raydium AMM at processed commitment, the
+//! earliest these feeds serve. Every `swap_base_in`/`swap_base_out` in them is
+//! sent as a [`MonitorEvent::PendingSwap`]. [`decode_swaps`] only needs the
+//! transaction, so other feeds of unconfirmed transactions, like a ShredStream
+//! proxy, can reuse it.
+//!
+//! Only top-level instructions are decoded, swaps routed through aggregators
+//! are missed, and so are swaps whose accounts come from address lookup
+//! tables. Pending swaps go out on the listener's channel only, they are far
+//! too many to publish to [`super::events`] for the notifiers and storage.
+
+use std::{
+    env,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use solana_sdk::{pubkey::Pubkey, transaction::VersionedTransaction};
+use tokio::{sync::broadcast, task::JoinSet, time::sleep};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{info, warn};
+
+use crate::{
+    config::program_ids,
+    metrics,
+    monitor::{
+        events::{MonitorEvent, PendingSwapEvent, SwapAmounts},
+        notify_events,
+    },
+    notify::Notifier,
+    raydium::swap_instructions::AmmInstruction,
+};
+
+const MONITOR: &str = "pending_swaps";
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Accounts of a swap without the optional target orders
+const MIN_SWAP_ACCOUNTS: usize = 17;
+
+/// The Geyser websocket, `PENDING_SWAPS_WS_URL`
+pub fn ws_url_from_env() -> Result<String> {
+    dotenv::dotenv().ok();
+    env::var("PENDING_SWAPS_WS_URL").map_err(|_| anyhow!("PENDING_SWAPS_WS_URL not set"))
+}
+
+/// Raydium AMM swaps of `program` in `tx`, seen in `slot`
+pub fn decode_swaps(
+    tx: &VersionedTransaction,
+    slot: u64,
+    program: &Pubkey,
+) -> Vec<PendingSwapEvent> {
+    let keys = tx.message.static_account_keys();
+    let signature = tx
+        .signatures
+        .first()
+        .map(|s| s.to_string())
+        .unwrap_or_default();
+    let mut swaps = vec![];
+    for instruction in tx.message.instructions() {
+        if keys.get(instruction.program_id_index as usize) != Some(program) {
+            continue;
+        }
+        let amounts = match AmmInstruction::unpack(&instruction.data) {
+            Ok(AmmInstruction::SwapBaseIn(swap)) => SwapAmounts::BaseIn {
+                amount_in: swap.amount_in,
+                minimum_amount_out: swap.minimum_amount_out,
+            },
+            Ok(AmmInstruction::SwapBaseOut(swap)) => SwapAmounts::BaseOut {
+                max_amount_in: swap.max_amount_in,
+                amount_out: swap.amount_out,
+            },
+            Ok(_) | Err(_) => continue,
+        };
+        // 查找表里的账户无法解析
+        let accounts: Option<Vec<Pubkey>> = instruction
+            .accounts
+            .iter()
+            .map(|index| keys.get(*index as usize).copied())
+            .collect();
+        let Some(accounts) = accounts.filter(|a| a.len() >= MIN_SWAP_ACCOUNTS) else {
+            continue;
+        };
+        // 用户账户总在最后
+        let n = accounts.len();
+        swaps.push(PendingSwapEvent {
+            signature: signature.clone(),
+            slot,
+            pool: accounts[1].to_string(),
+            user: accounts[n - 1].to_string(),
+            user_source: accounts[n - 3].to_string(),
+            user_destination: accounts[n - 2].to_string(),
+            amounts,
+            received_at: None,
+        });
+    }
+    swaps
+}
+
+/// Transaction and slot of a `transactionNotification`, `None` for the other
+/// messages
+fn parse_notification(message: &str) -> Result<Option<(VersionedTransaction, u64)>> {
+    let message: Value = serde_json::from_str(message)?;
+    if message["method"] != "transactionNotification" {
+        return Ok(None);
+    }
+    let result = &message["params"]["result"];
+    let encoded = result["transaction"]["transaction"][0]
+        .as_str()
+        .ok_or(anyhow!("notification without transaction {}", message))?;
+    let tx = bincode::deserialize(&bs64::decode(encoded.as_bytes())?)?;
+    Ok(Some((tx, result["slot"].as_u64().unwrap_or_default())))
+}
+
+/// Streams the swaps of `url` into `event_sender` until the subscription
+/// fails or ends
+async fn stream_swaps(
+    url: &str,
+    program: &Pubkey,
+    event_sender: &broadcast::Sender<MonitorEvent>,
+) -> Result<()> {
+    let (mut ws, _) = connect_async(url).await?;
+    let subscribe = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "transactionSubscribe",
+        "params": [
+            {"vote": false, "failed": false, "accountInclude": [program.to_string()]},
+            {
+                "commitment": "processed",
+                "encoding": "base64",
+                "transactionDetails": "full",
+                "maxSupportedTransactionVersion": 0
+            }
+        ]
+    });
+    ws.send(Message::Text(subscribe.to_string())).await?;
+    info!("{} subscribed to {}", MONITOR, program);
+
+    while let Some(message) = ws.next().await {
+        let text = match message? {
+            Message::Text(text) => text,
+            Message::Ping(data) => {
+                ws.send(Message::Pong(data)).await?;
+                continue;
+            }
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let received_at = Instant::now();
+        let (tx, slot) = match parse_notification(&text) {
+            Ok(Some(notification)) => notification,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("{} failed to decode notification {:?}", MONITOR, e);
+                continue;
+            }
+        };
+        for mut swap in decode_swaps(&tx, slot, program) {
+            metrics::PENDING_SWAPS_DETECTED.inc();
+            swap.received_at = Some(received_at);
+            // 没有接收者时忽略
+            let _ = event_sender.send(MonitorEvent::PendingSwap(swap));
+        }
+    }
+    Ok(())
+}
+
+/// Listens for pending Raydium swaps on the Geyser websocket at `url` and
+/// notifies `notifier` of each one
+///
+/// Returns the listener tasks and the event sender, which stays valid across
+/// reconnects; call `subscribe()` on it to add more consumers.
+pub async fn listen_pending_swaps(
+    url: String,
+    notifier: Arc<dyn Notifier>,
+    channel_size: usize,
+) -> Result<(JoinSet<()>, broadcast::Sender<MonitorEvent>)> {
+    let mut set: JoinSet<()> = JoinSet::new();
+    let (event_sender, _) = broadcast::channel(channel_size);
+
+    set.spawn(notify_events(&event_sender, notifier));
+
+    // 断线自动重连
+    let events_out = event_sender.clone();
+    let program = program_ids().raydium_amm;
+    set.spawn(async move {
+        let mut delay = RECONNECT_DELAY;
+        loop {
+            let started = Instant::now();
+            match stream_swaps(&url, &program, &events_out).await {
+                Ok(()) => warn!("{} stream closed, reconnecting", MONITOR),
+                Err(e) => warn!("{} stream failed {:?}", MONITOR, e),
+            }
+            if started.elapsed() > MAX_RECONNECT_DELAY {
+                delay = RECONNECT_DELAY;
+            }
+            sleep(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    });
+
+    Ok((set, event_sender))
+}
+
+#[test]
+fn test_decode_swaps() {
+    use solana_sdk::{
+        instruction::{AccountMeta, Instruction},
+        message::{Message as TxMessage, VersionedMessage},
+        signature::Keypair,
+        signer::Signer,
+    };
+
+    let program = Pubkey::new_unique();
+    let user = Keypair::new();
+    let mut accounts: Vec<_> = (0..16)
+        .map(|_| AccountMeta::new(Pubkey::new_unique(), false))
+        .collect();
+    accounts.push(AccountMeta::new_readonly(user.pubkey(), true));
+    let pool = accounts[1].pubkey;
+    let source = accounts[14].pubkey;
+
+    let mut data = vec![9];
+    data.extend_from_slice(&1_000u64.to_le_bytes());
+    data.extend_from_slice(&900u64.to_le_bytes());
+    let swap = Instruction::new_with_bytes(program, &data, accounts.clone());
+    // 其他指令被忽略
+    let other = Instruction::new_with_bytes(program, &[1, 2], accounts);
+    let message = TxMessage::new(&[other, swap], Some(&user.pubkey()));
+    let tx = VersionedTransaction::try_new(VersionedMessage::Legacy(message), &[&user]).unwrap();
+
+    let swaps = decode_swaps(&tx, 7, &program);
+    assert_eq!(swaps.len(), 1);
+    assert_eq!(swaps[0].pool, pool.to_string());
+    assert_eq!(swaps[0].user, user.pubkey().to_string());
+    assert_eq!(swaps[0].user_source, source.to_string());
+    assert_eq!(
+        swaps[0].amounts,
+        SwapAmounts::BaseIn {
+            amount_in: 1_000,
+            minimum_amount_out: 900
+        }
+    );
+    assert!(decode_swaps(&tx, 7, &Pubkey::new_unique()).is_empty());
+}
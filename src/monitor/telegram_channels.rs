@@ -0,0 +1,225 @@
+//! Contract addresses called in public Telegram channels.
+//!
+//! The bot API only sees channels the bot was added to, and its updates are
+//! already consumed by the command bot, see [`crate::notify::telegram`]. So
+//! the channels' web preview (`t.me/s/<channel>`) is scraped instead, and
+//! every new post naming a mint is published as a [`SignalEvent`], the mint
+//! found the same way as in tweets, see [`super::find_mint`]. Only public
+//! channels have a preview. It is off unless `TELEGRAM_CHANNELS_ENABLED=true`.
+//!
+//! - `TELEGRAM_CHANNELS`: comma separated channel usernames, e.g.
+//!   `solcalls,@gemsdaily`
+//! - `TELEGRAM_CHANNELS_POLL_SECS`: seconds between two polls (default 10)
+//!
+//! Posts made before the bot started are skipped.
+
+use std::{
+    collections::HashMap,
+    env,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use tokio::{sync::broadcast, time::sleep};
+use tracing::{info, warn};
+
+use crate::{constants::telegram::CHANNEL_PREVIEW_URL, strategy::parse_env};
+
+use super::{
+    events::{self, MonitorEvent, SignalEvent, SignalSource},
+    find_mint,
+};
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct TelegramChannelsConfig {
+    /// Usernames, without the `@`
+    pub channels: Vec<String>,
+    pub poll_interval: Duration,
+}
+
+impl TelegramChannelsConfig {
+    /// Reads the `TELEGRAM_CHANNELS*` variables, `None` unless
+    /// `TELEGRAM_CHANNELS_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("TELEGRAM_CHANNELS_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        let channels: Vec<String> = env::var("TELEGRAM_CHANNELS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|channel| channel.trim().trim_start_matches('@').to_string())
+            .filter(|channel| !channel.is_empty())
+            .collect();
+        if channels.is_empty() {
+            return Err(anyhow!("TELEGRAM_CHANNELS is empty"));
+        }
+        Ok(Some(Self {
+            channels,
+            poll_interval: parse_env("TELEGRAM_CHANNELS_POLL_SECS")?
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_POLL_INTERVAL),
+        }))
+    }
+}
+
+/// A post of a channel's web preview
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelPost {
+    pub id: u64,
+    pub text: String,
+}
+
+/// `html` without its tags, line breaks kept and entities decoded
+fn strip_html(html: &str) -> String {
+    static BREAK: OnceLock<Regex> = OnceLock::new();
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    let text = BREAK
+        .get_or_init(|| Regex::new(r"(?i)<br\s*/?>").unwrap())
+        .replace_all(html, "\n");
+    let text = TAG
+        .get_or_init(|| Regex::new(r"<[^>]*>").unwrap())
+        .replace_all(&text, "");
+    // &amp; 最后替换，避免重复解码
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// The posts of a channel's web preview page, oldest first
+pub fn parse_posts(html: &str) -> Vec<ChannelPost> {
+    static TEXT: OnceLock<Regex> = OnceLock::new();
+    let text_div = TEXT.get_or_init(|| {
+        Regex::new(r#"(?s)class="tgme_widget_message_text[^"]*"[^>]*>(.*?)</div>"#).unwrap()
+    });
+    // 每条消息以 data-post="频道/编号" 开头
+    let mut posts: Vec<ChannelPost> = html
+        .split(r#"data-post=""#)
+        .skip(1)
+        .filter_map(|post| {
+            let (path, body) = post.split_once('"')?;
+            let id = path.rsplit('/').next()?.parse().ok()?;
+            // 只有图片的消息没有文字
+            let text = text_div
+                .captures(body)
+                .map(|captures| strip_html(&captures[1]))
+                .unwrap_or_default();
+            Some(ChannelPost { id, text })
+        })
+        .collect();
+    posts.sort_by_key(|post| post.id);
+    posts
+}
+
+/// The signals of the `posts` of `channel` newer than post `after`
+pub fn signals(
+    channel: &str,
+    posts: &[ChannelPost],
+    after: u64,
+    received_at: Instant,
+) -> Vec<SignalEvent> {
+    posts
+        .iter()
+        .filter(|post| post.id > after)
+        .filter_map(|post| {
+            let mint = find_mint(&post.text)?;
+            Some(SignalEvent {
+                source: SignalSource::Telegram,
+                channel: channel.to_string(),
+                message_id: post.id.to_string(),
+                author: channel.to_string(),
+                text: post.text.clone(),
+                mint: mint.to_string(),
+                received_at: Some(received_at),
+            })
+        })
+        .collect()
+}
+
+async fn fetch_posts(http: &reqwest::Client, channel: &str) -> Result<Vec<ChannelPost>> {
+    let html = http
+        .get(format!("{}/{}", CHANNEL_PREVIEW_URL, channel))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    Ok(parse_posts(&html))
+}
+
+/// Publishes the calls of the watched channels forever, also sending them on
+/// `events_out`
+pub async fn run(config: TelegramChannelsConfig, events_out: broadcast::Sender<MonitorEvent>) {
+    let http = reqwest::Client::new();
+    // 每个频道最新的消息编号
+    let mut cursors: HashMap<String, u64> = HashMap::new();
+    info!("watching {} telegram channels", config.channels.len());
+    loop {
+        for channel in &config.channels {
+            let posts = match fetch_posts(&http, channel).await {
+                Ok(posts) => posts,
+                Err(e) => {
+                    warn!("failed to poll telegram channel {} {:?}", channel, e);
+                    continue;
+                }
+            };
+            let newest = posts.last().map_or(0, |post| post.id);
+            let Some(&after) = cursors.get(channel) else {
+                // 第一次只记录起点
+                cursors.insert(channel.clone(), newest);
+                continue;
+            };
+            // 预览页被删帖后编号可能变小
+            cursors.insert(channel.clone(), newest.max(after));
+            for signal in signals(channel, &posts, after, Instant::now()) {
+                info!("telegram call of {} in {}", signal.mint, channel);
+                let event = MonitorEvent::Signal(signal);
+                events::publish(event.clone());
+                // 没有接收者时忽略
+                let _ = events_out.send(event);
+            }
+        }
+        sleep(config.poll_interval).await;
+    }
+}
+
+#[test]
+fn test_telegram_channel_posts() {
+    use solana_sdk::pubkey::Pubkey;
+
+    let mint = Pubkey::new_unique();
+    let html = format!(
+        r#"<section class="tgme_channel_history js-message_history">
+<div class="tgme_widget_message_wrap"><div class="tgme_widget_message js-widget_message" data-post="solcalls/12" data-view="x">
+<div class="tgme_widget_message_text js-message_text" dir="auto">New gem &amp; more<br/>CA: <a href="https://pump.fun/{mint}">{mint}</a></div>
+</div></div>
+<div class="tgme_widget_message_wrap"><div class="tgme_widget_message js-widget_message" data-post="solcalls/11">
+<div class="tgme_widget_message_text js-message_text" dir="auto">gm</div>
+</div></div>
+<div class="tgme_widget_message_wrap"><div class="tgme_widget_message js-widget_message" data-post="solcalls/13">
+<a class="tgme_widget_message_photo_wrap"></a>
+</div></div>
+</section>"#
+    );
+    let posts = parse_posts(&html);
+    assert_eq!(
+        posts.iter().map(|post| post.id).collect::<Vec<_>>(),
+        [11, 12, 13]
+    );
+    assert_eq!(posts[1].text, format!("New gem & more\nCA: {}", mint));
+    assert_eq!(posts[2].text, "");
+
+    let calls = signals("solcalls", &posts, 11, Instant::now());
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].mint, mint.to_string());
+    assert_eq!(calls[0].message_id, "12");
+    // 已处理过的消息不再发出
+    assert!(signals("solcalls", &posts, 12, Instant::now()).is_empty());
+}
@@ -1,167 +1,513 @@
 use anyhow::{anyhow, Result};
-use futures_util::StreamExt;
-use solana_client::{
-    nonblocking::pubsub_client::PubsubClient,
-    rpc_config::{RpcBlockSubscribeConfig, RpcBlockSubscribeFilter},
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status_client_types::{
+    option_serializer::OptionSerializer, EncodedTransactionWithStatusMeta, UiConfirmedBlock,
 };
-use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
-use solana_transaction_status_client_types::UiConfirmedBlock;
 use std::str;
-use std::sync::Arc;
-use teloxide::{
-    payloads::SendMessageSetters,
-    prelude::Requester,
-    types::{ChatId, ParseMode},
-    Bot,
+use std::{env, sync::Arc};
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    task::JoinSet,
 };
-use tokio::{sync::broadcast, task::JoinSet};
-const CHATID: i64 = 1233301525;
-
-const PUMPFUNPROGRAM: Pubkey =
-    Pubkey::from_str_const("6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P");
-
-const CREATEDISCRIMINATOR: u64 = u64::from_le_bytes([24, 30, 200, 40, 5, 28, 7, 119]);
-const IX_DEF: [(&str, &str); 3] = [("name", "string"), ("symbol", "string"), ("uri", "string")];
-
-fn decode_create_instruction(ix_data: &[u8], accounts: Vec<String>) -> Result<String> {
-    let mut args = Vec::new(); // 使用 Vec 保持顺序
-    let mut offset = 8; // Skip 8-byte discriminator
-
-    for (name, arg_type) in IX_DEF {
-        match arg_type {
-            "string" => {
-                let length = u32::from_le_bytes(ix_data[offset..offset + 4].try_into()?) as usize;
-                offset += 4;
-                let value = str::from_utf8(&ix_data[offset..offset + length])?.to_string();
-                offset += length;
-                args.push((name.to_string(), value)); // 按顺序插入
-            }
-            "publicKey" => {
-                let value = bs64::encode(&ix_data[offset..offset + 32]);
-                offset += 32;
-                args.push((name.to_string(), value)); // 按顺序插入
-            }
-            _ => return Err(anyhow!("Unsupported type: {:?}", arg_type).into()),
+
+use crate::{
+    config::program_ids,
+    constants::curve::TOKEN_TOTAL_SUPPLY,
+    idl::{self, DecodedInstruction, IdlValue},
+    marketdata::market_data,
+    metrics,
+    monitor::{
+        creator::{block_buyers, CreatorAnalyzer},
+        diagnostics,
+        events::{self, DevBuy, MonitorEvent, PumpTradeEvent, TokenCreateEvent},
+        geyser::{self, GeyserConfig},
+        notify_events, stream_blocks, tx_succeeded,
+    },
+    notify::Notifier,
+    portfolio::Side,
+    pumpfun::accounts::BondingCurveAccount,
+    strategy::parse_env,
+};
+
+const DEFAULT_DEV_BUY_ALERT_PCT: f64 = 10.0;
+
+const MONITOR: &str = "token_create";
+
+/// Percentage of supply above which a dev buy is flagged
+fn get_dev_buy_alert_pct() -> f64 {
+    env::var("DEV_BUY_ALERT_PCT")
+        .ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .unwrap_or(DEFAULT_DEV_BUY_ALERT_PCT)
+}
+
+/// Decodes a buy instruction executed against a freshly created curve
+fn decode_dev_buy(buy: &DecodedInstruction) -> Option<DevBuy> {
+    let token_amount = buy.arg("amount")?.as_u64()?;
+    let sol_cost = BondingCurveAccount::fresh()
+        .get_buy_sol_cost(token_amount)
+        .ok()?;
+    Some(DevBuy {
+        token_amount,
+        sol_cost,
+        supply_pct: token_amount as f64 / TOKEN_TOTAL_SUPPLY as f64 * 100.0,
+    })
+}
+
+fn decode_create_instruction(
+    create: &DecodedInstruction,
+    accounts: &[String],
+    signature: String,
+    dev_buy: Option<DevBuy>,
+) -> Result<TokenCreateEvent> {
+    let arg = |name: &str| {
+        create
+            .arg(name)
+            .and_then(IdlValue::as_str)
+            .map(str::to_string)
+            .unwrap_or_default()
+    };
+    let account = |name: &str| {
+        create
+            .account(name, accounts)
+            .cloned()
+            .ok_or_else(|| anyhow!("create instruction without {} account", name))
+    };
+
+    let dev_alert = dev_buy.is_some_and(|b| b.supply_pct > get_dev_buy_alert_pct());
+
+    Ok(TokenCreateEvent {
+        signature,
+        name: arg("name"),
+        symbol: arg("symbol"),
+        uri: arg("uri"),
+        mint: account("mint")?,
+        bonding_curve: account("bonding_curve")?,
+        associated_bonding_curve: account("associated_bonding_curve")?,
+        creator: account("user")?,
+        dev_buy,
+        dev_alert,
+        creator_profile: None,
+        received_at: None,
+    })
+}
+
+/// Account keys of `tx`, including those loaded from lookup tables
+fn account_keys(
+    tx: &EncodedTransactionWithStatusMeta,
+    static_keys: &[Pubkey],
+) -> Result<Vec<Pubkey>> {
+    let mut keys = static_keys.to_vec();
+    if let Some(OptionSerializer::Some(loaded)) =
+        tx.meta.as_ref().map(|meta| &meta.loaded_addresses)
+    {
+        for key in loaded.writable.iter().chain(&loaded.readonly) {
+            keys.push(key.parse()?);
         }
     }
+    Ok(keys)
+}
 
-    // Add accounts in the correct order
-    args.push(("mint".to_string(), accounts[0].clone()));
-    args.push(("bondingCurve".to_string(), accounts[2].clone()));
-    args.push(("associatedBondingCurve".to_string(), accounts[3].clone()));
-    args.push(("user".to_string(), accounts[7].clone()));
-
-    // Format as a beautiful Markdown string
-    let mut markdown = String::new();
-    markdown.push_str("**🚀 Token Create 🚀**\n");
-    markdown.push_str("```\n");
-    for (key, value) in args {
-        markdown.push_str(&format!("{:25}: {}\n", key, value)); // 对齐输出
+/// Decodes the creates of a successful transaction
+fn process_transaction(
+    encoded: &EncodedTransactionWithStatusMeta,
+) -> Result<Vec<TokenCreateEvent>> {
+    let pumpfun_program = program_ids().pumpfun;
+    let tx = encoded
+        .transaction
+        .decode()
+        .ok_or_else(|| anyhow!("failed to decode transaction"))?;
+    let signature = tx
+        .signatures
+        .first()
+        .ok_or_else(|| anyhow!("transaction without signature"))?
+        .to_string();
+    let account_keys = account_keys(encoded, tx.message.static_account_keys())?;
+    let mut creates = vec![];
+    let mut buys = vec![];
+    for instruction in tx.message.instructions() {
+        let Some(program) = account_keys.get(instruction.program_id_index as usize) else {
+            return Err(anyhow!(
+                "program index {} out of range",
+                instruction.program_id_index
+            ));
+        };
+        if !program.eq(&pumpfun_program) {
+            continue;
+        }
+        let Some(decoded) = idl::pumpfun().decode(&instruction.data)? else {
+            continue;
+        };
+        // 相关账户收集
+        let accounts = || {
+            instruction
+                .accounts
+                .iter()
+                .map(|idx| {
+                    account_keys
+                        .get(*idx as usize)
+                        .map(|key| key.to_string())
+                        .ok_or_else(|| anyhow!("account index {} out of range", idx))
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        match decoded.name() {
+            "create" => creates.push((decoded, accounts()?)),
+            "buy" => buys.push((decoded, accounts()?)),
+            _ => {}
+        }
+    }
+
+    let mut result = vec![];
+    for (create, accounts) in creates {
+        // 同一笔交易中 creator 对该 mint 的买入
+        let dev_buy = buys
+            .iter()
+            .find(|(buy, buy_accounts)| {
+                let same = |name| {
+                    buy.account(name, buy_accounts)
+                        .is_some_and(|key| create.account(name, &accounts) == Some(key))
+                };
+                same("mint") && same("user")
+            })
+            .and_then(|(buy, _)| decode_dev_buy(buy));
+        // 处理指令
+        result.push(decode_create_instruction(
+            &create,
+            &accounts,
+            signature.clone(),
+            dev_buy,
+        )?);
+    }
+    Ok(result)
+}
+
+/// A top-level bonding curve instruction of a successful transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CurveTrade {
+    Create {
+        mint: Pubkey,
+        user: Pubkey,
+    },
+    /// Buys exactly `token_amount` raw tokens for at most `sol_limit`
+    /// lamports
+    Buy {
+        mint: Pubkey,
+        user: Pubkey,
+        token_amount: u64,
+        sol_limit: u64,
+    },
+    /// Sells exactly `token_amount` raw tokens for at least `sol_limit`
+    /// lamports
+    Sell {
+        mint: Pubkey,
+        user: Pubkey,
+        token_amount: u64,
+        sol_limit: u64,
+    },
+}
+
+pub(crate) fn transaction_curve_trades(
+    encoded: &EncodedTransactionWithStatusMeta,
+) -> Option<Vec<CurveTrade>> {
+    let pumpfun_program = program_ids().pumpfun;
+    let tx = encoded.transaction.decode()?;
+    let account_keys = account_keys(encoded, tx.message.static_account_keys()).ok()?;
+    let mut trades = vec![];
+    for instruction in tx.message.instructions() {
+        if account_keys.get(instruction.program_id_index as usize) != Some(&pumpfun_program) {
+            continue;
+        }
+        let Ok(Some(decoded)) = idl::pumpfun().decode(&instruction.data) else {
+            continue;
+        };
+        let account = |name: &str| {
+            account_keys
+                .get(*decoded.account(name, &instruction.accounts)? as usize)
+                .copied()
+        };
+        let argument = |name: &str| decoded.arg(name)?.as_u64();
+        let trade = match decoded.name() {
+            "create" => CurveTrade::Create {
+                mint: account("mint")?,
+                user: account("user")?,
+            },
+            "buy" => CurveTrade::Buy {
+                mint: account("mint")?,
+                user: account("user")?,
+                token_amount: argument("amount")?,
+                sol_limit: argument("max_sol_cost")?,
+            },
+            "sell" => CurveTrade::Sell {
+                mint: account("mint")?,
+                user: account("user")?,
+                token_amount: argument("amount")?,
+                sol_limit: argument("min_sol_output")?,
+            },
+            _ => continue,
+        };
+        trades.push(trade);
     }
-    markdown.push_str("```");
+    Some(trades)
+}
 
-    Ok(markdown)
+/// The bonding curve instructions of `block`, in execution order
+///
+/// Trades made through other programs (CPI) are not seen, and malformed
+/// transactions are skipped since [`process_block`] already reports them.
+pub(crate) fn curve_trades(block: &UiConfirmedBlock) -> Vec<CurveTrade> {
+    block
+        .transactions
+        .iter()
+        .flatten()
+        .filter(|tx| tx_succeeded(tx))
+        .filter_map(transaction_curve_trades)
+        .flatten()
+        .collect()
 }
 
-pub fn process_block(block: UiConfirmedBlock) -> Vec<String> {
+/// Decodes the bonding curve buys and sells of `block`, in execution order
+///
+/// Like [`curve_trades`] only top-level instructions are seen.
+pub fn process_block_trades(block: &UiConfirmedBlock) -> Vec<PumpTradeEvent> {
     let mut result = vec![];
-    for tx in block.transactions.unwrap() {
-        let tx = tx.transaction.decode().unwrap();
-        let instructions = tx.message.instructions();
-        let account_keys = tx.message.static_account_keys();
-        for instruction in instructions {
-            if account_keys[instruction.program_id_index as usize].eq(&PUMPFUNPROGRAM) {
-                let slice = &instruction.data[..8];
-                // 创建一个固定长度的数组
-                let mut array = [0u8; 8];
-                // 将切片内容复制到数组中
-                array.copy_from_slice(slice);
-                let discriminator = u64::from_le_bytes(array);
-                if discriminator == CREATEDISCRIMINATOR {
-                    // 相关账户收集
-                    let accounts = instruction
-                        .accounts
-                        .iter()
-                        .map(|idx| account_keys[*idx as usize].to_string())
-                        .collect::<Vec<_>>();
-                    // 处理指令
-
-                    decode_create_instruction(&instruction.data, accounts)
-                        .map(|v| result.push(v))
-                        .unwrap();
-                }
-            }
+    for tx in block.transactions.iter().flatten() {
+        if !tx_succeeded(tx) {
+            continue;
+        }
+        let Some(trades) = transaction_curve_trades(tx) else {
+            continue;
+        };
+        let signature = tx
+            .transaction
+            .decode()
+            .and_then(|tx| tx.signatures.first().map(|s| s.to_string()))
+            .unwrap_or_default();
+        for trade in trades {
+            let (side, mint, trader, token_amount, sol_limit) = match trade {
+                CurveTrade::Buy {
+                    mint,
+                    user,
+                    token_amount,
+                    sol_limit,
+                } => (Side::Buy, mint, user, token_amount, sol_limit),
+                CurveTrade::Sell {
+                    mint,
+                    user,
+                    token_amount,
+                    sol_limit,
+                } => (Side::Sell, mint, user, token_amount, sol_limit),
+                CurveTrade::Create { .. } => continue,
+            };
+            result.push(PumpTradeEvent {
+                signature: signature.clone(),
+                mint: mint.to_string(),
+                trader: trader.to_string(),
+                side,
+                token_amount,
+                sol_limit,
+                block_time: block.block_time,
+                received_at: None,
+            });
         }
     }
     result
 }
 
+/// Decodes the creates of `block`
+///
+/// Malformed transactions are skipped and reported to [`diagnostics`], the
+/// block only fails as a whole when it carries no transactions.
+pub fn process_block(block: UiConfirmedBlock) -> Result<Vec<TokenCreateEvent>> {
+    let transactions = block
+        .transactions
+        .ok_or_else(|| anyhow!("block {} without transactions", block.blockhash))?;
+    let mut result = vec![];
+    for tx in transactions {
+        // 跳过执行失败的交易
+        if !tx_succeeded(&tx) {
+            continue;
+        }
+        match process_transaction(&tx) {
+            Ok(events) => result.extend(events),
+            Err(e) => {
+                let signature = tx
+                    .transaction
+                    .decode()
+                    .and_then(|tx| tx.signatures.first().map(|s| s.to_string()));
+                diagnostics::report(MONITOR, signature, &e);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Listens for Pump.fun creates and notifies `notifier` of each one
+///
+/// With `CREATOR_ANALYSIS_ENABLED=true` each create waits for its creator's
+/// profile, see [`crate::monitor::creator`], before it is sent. With
+/// `PUMP_TRADE_EVENTS_ENABLED=true` the curve buys and sells of each block
+/// are sent too, after its creates; they are many, so route them away from
+/// chat notifiers with `NOTIFY_<BACKEND>_EVENTS` or `TELEGRAM_ROUTES`.
+///
+/// With `GEYSER_ENABLED=true` the blocks come from Yellowstone gRPC instead
+/// of `ws_client`, see [`geyser`].
+///
+/// Returns the listener tasks and the event sender, which stays valid across
+/// websocket reconnects; call `subscribe()` on it to add more consumers.
 pub async fn listen_pumpfun_create(
     ws_client: Arc<PubsubClient>,
+    notifier: Arc<dyn Notifier>,
     channel_size: usize,
-) -> Result<JoinSet<()>> {
+) -> Result<(JoinSet<()>, broadcast::Sender<MonitorEvent>)> {
+    let analyzer = CreatorAnalyzer::from_env()?;
+    let geyser_config = GeyserConfig::from_env()?;
+    dotenv::dotenv().ok();
+    let trade_events = parse_env::<bool>("PUMP_TRADE_EVENTS_ENABLED")?.unwrap_or(false);
     let mut set: JoinSet<()> = JoinSet::new();
     let (block_sender, _) = broadcast::channel(channel_size);
-    let bot = Arc::new(Bot::from_env());
+    let (event_sender, _) = broadcast::channel(channel_size);
 
-    // 处理log的线程
+    // 通知的线程
+    set.spawn(notify_events(&event_sender, notifier));
+
+    // 处理block的线程
     let mut block_receiver = block_sender.subscribe();
+    let events_out = event_sender.clone();
     set.spawn(async move {
-        while let Ok(block) = block_receiver.recv().await {
-            let result = process_block(block);
-            for res in result {
-                // 发送到tgbot
-                match bot
-                    .send_message(ChatId(CHATID), res)
-                    .parse_mode(ParseMode::MarkdownV2)
-                    .await
-                {
-                    Ok(_) => {}
-                    Err(e) => {
-                        eprintln!("send to bot error {:?}", e);
-                    }
+        loop {
+            let (received_at, block) = match block_receiver.recv().await {
+                Ok(block) => block,
+                Err(RecvError::Lagged(n)) => {
+                    eprintln!("{} lagged, skipped {} blocks", MONITOR, n);
+                    continue;
                 }
+                Err(RecvError::Closed) => break,
+            };
+            if let Some(market_data) = market_data() {
+                market_data.observe_block(&block);
             }
-        }
-    });
-
-    // 发出block的线程
-    set.spawn(async move {
-        let (mut stream, _) = ws_client
-            .block_subscribe(
-                // 只关注migrator
-                // RpcBlockSubscribeFilter::MentionsAccountOrProgram(PUMPFUNMIGRATOR.to_string()),
-                RpcBlockSubscribeFilter::All,
-                // 区块信息配置
-                Some(RpcBlockSubscribeConfig {
-                    commitment: Some(CommitmentConfig::confirmed()),
-                    encoding: Some(
-                        solana_transaction_status_client_types::UiTransactionEncoding::Binary,
-                    ),
-                    transaction_details: Some(
-                        solana_transaction_status_client_types::TransactionDetails::Full,
-                    ),
-                    show_rewards: Some(false),
-                    max_supported_transaction_version: Some(0),
-                }),
-            )
-            .await
-            .map_err(|e| anyhow!("failed to get stream {:?}", e))
-            .unwrap();
-
-        // 发送block
-        while let Some(new_block) = stream.next().await {
-            if let Some(block) = new_block.value.block {
-                match block_sender.send(block) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        eprintln!("send block error {:?}", e);
-                    }
+            let buyers = analyzer.as_ref().map(|_| block_buyers(&block));
+            let trades = if trade_events {
+                process_block_trades(&block)
+            } else {
+                vec![]
+            };
+            let mut result = match process_block(block) {
+                Ok(result) => result,
+                Err(e) => {
+                    diagnostics::report(MONITOR, None, &e);
+                    continue;
                 }
+            };
+            metrics::CREATES_DETECTED.inc_by(result.len() as u64);
+            if let (Some(analyzer), Some(buyers)) = (&analyzer, &buyers) {
+                analyzer.attach(&mut result, buyers).await;
+            }
+            for mut event in result {
+                event.received_at = Some(received_at);
+                let event = MonitorEvent::Create(event);
+                events::publish(event.clone());
+                // 没有接收者时忽略
+                let _ = events_out.send(event);
+            }
+            for mut trade in trades {
+                trade.received_at = Some(received_at);
+                let event = MonitorEvent::PumpTrade(trade);
+                events::publish(event.clone());
+                let _ = events_out.send(event);
             }
         }
     });
 
+    // 发出block的线程，断线自动重连
+    match geyser_config {
+        Some(config) => set.spawn(geyser::stream_blocks(
+            config,
+            vec![program_ids().pumpfun],
+            MONITOR,
+            block_sender,
+        )),
+        None => set.spawn(stream_blocks(ws_client, MONITOR, block_sender)),
+    };
+
     // 返回set到主线程
-    Ok(set)
+    Ok((set, event_sender))
+}
+
+#[test]
+fn test_decode_dev_buy() {
+    let mut data = idl::pumpfun()
+        .instruction("buy")
+        .unwrap()
+        .discriminator
+        .to_vec();
+    data.extend_from_slice(&100_000_000_000_000u64.to_le_bytes());
+    data.extend_from_slice(&5_000_000_000u64.to_le_bytes());
+
+    let buy = idl::pumpfun().decode(&data).unwrap().unwrap();
+    let dev_buy = decode_dev_buy(&buy).unwrap();
+    assert_eq!(dev_buy.token_amount, 100_000_000_000_000);
+    assert!((dev_buy.supply_pct - 10.0).abs() < f64::EPSILON);
+    assert!(dev_buy.sol_cost > 3_000_000_000 && dev_buy.sol_cost < 3_200_000_000);
+    assert!(idl::pumpfun().decode(&data[..12]).is_err());
+}
+
+#[test]
+fn test_process_block_trades() {
+    use solana_sdk::{
+        instruction::{AccountMeta, Instruction},
+        transaction::{Transaction, VersionedTransaction},
+    };
+
+    let (mint, trader) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let mut data = idl::pumpfun()
+        .instruction("sell")
+        .unwrap()
+        .discriminator
+        .to_vec();
+    data.extend_from_slice(&1_000u64.to_le_bytes());
+    data.extend_from_slice(&50u64.to_le_bytes());
+    let mut keys: Vec<Pubkey> = (0..7).map(|_| Pubkey::new_unique()).collect();
+    keys[2] = mint;
+    keys[6] = trader;
+    let accounts = keys
+        .into_iter()
+        .map(|pubkey| AccountMeta::new(pubkey, false))
+        .collect();
+    let sell = Instruction::new_with_bytes(program_ids().pumpfun, &data, accounts);
+    let tx = Transaction::new_with_payer(&[sell], Some(&trader));
+    let encoded = bs64::encode(&bincode::serialize(&VersionedTransaction::from(tx)).unwrap());
+
+    let tx: EncodedTransactionWithStatusMeta = serde_json::from_value(serde_json::json!({
+        "transaction": [encoded, "base64"],
+        "meta": {
+            "err": null,
+            "status": {"Ok": null},
+            "fee": 5000,
+            "preBalances": [],
+            "postBalances": [],
+        },
+    }))
+    .unwrap();
+    let block = UiConfirmedBlock {
+        previous_blockhash: String::new(),
+        blockhash: String::new(),
+        parent_slot: 0,
+        transactions: Some(vec![tx]),
+        signatures: None,
+        rewards: None,
+        num_reward_partitions: None,
+        block_time: Some(1_700_000_000),
+        block_height: None,
+    };
+
+    let trades = process_block_trades(&block);
+    assert_eq!(trades.len(), 1);
+    assert_eq!(trades[0].side, Side::Sell);
+    assert_eq!(trades[0].mint, mint.to_string());
+    assert_eq!(trades[0].trader, trader.to_string());
+    assert_eq!((trades[0].token_amount, trades[0].sol_limit), (1_000, 50));
+    assert_eq!(trades[0].block_time, Some(1_700_000_000));
 }
@@ -1,35 +1,59 @@
 use std::sync::Arc;
 
-use crate::pumpfun::utils::get_bonding_curve_account;
+use crate::{
+    config::program_ids,
+    metrics,
+    monitor::{
+        diagnostics,
+        events::{self, MigrationEvent, MigrationVenue, MonitorEvent},
+        geyser::{self, GeyserConfig},
+        notify_events, stream_blocks, tx_succeeded,
+    },
+    notify::Notifier,
+    pumpfun::utils::get_bonding_curve_account,
+    pumpswap::instructions::CREATE_POOL_DISCRIMINATOR,
+    raydium::pools,
+};
 use anyhow::{anyhow, Result};
-use futures_util::StreamExt;
-use solana_client::{
-    nonblocking::{pubsub_client::PubsubClient, rpc_client::RpcClient},
-    rpc_config::{RpcBlockSubscribeConfig, RpcBlockSubscribeFilter},
+use solana_client::nonblocking::{pubsub_client::PubsubClient, rpc_client::RpcClient};
+use solana_sdk::{bs58, pubkey::Pubkey, signature::Signature, transaction::VersionedTransaction};
+use solana_transaction_status_client_types::{
+    option_serializer::OptionSerializer, EncodedTransactionWithStatusMeta, UiConfirmedBlock,
+    UiInstruction,
 };
-use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
-use solana_transaction_status_client_types::{EncodedTransactionWithStatusMeta, UiConfirmedBlock};
-use teloxide::{
-    payloads::SendMessageSetters,
-    prelude::Requester,
-    types::{ChatId, ParseMode},
-    Bot,
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    task::JoinSet,
 };
-use tokio::{sync::broadcast, task::JoinSet};
 
-const CHATID: i64 = 1233301525;
-const PUMPFUNMIGRATOR: &str = "39azUYFWPz3VHgKCf3VChUwbpURdCHRxjWVowf5jUJjg";
+const MONITOR: &str = "token_migration";
 
 /// 检查mint代币的状态
+///
+/// 需要持续关注时用 [`super::bonding_curve`] 订阅，不要轮询
 pub async fn check_token_status(client: Arc<RpcClient>, mint: &str) -> Result<bool> {
     let mint = Pubkey::from_str_const(mint);
     let bonding_curve = get_bonding_curve_account(client, &mint).await?;
     Ok(bonding_curve.complete)
 }
 
-pub fn process_initialize2_transaction(tx: &EncodedTransactionWithStatusMeta) -> Option<String> {
-    let decode_tx = tx.transaction.decode().unwrap();
-    let signature = decode_tx.signatures[0];
+/// Decodes `tx` and its first signature
+fn decode(tx: &EncodedTransactionWithStatusMeta) -> Result<(VersionedTransaction, Signature)> {
+    let decoded = tx
+        .transaction
+        .decode()
+        .ok_or_else(|| anyhow!("failed to decode transaction"))?;
+    let signature = *decoded
+        .signatures
+        .first()
+        .ok_or_else(|| anyhow!("transaction without signature"))?;
+    Ok((decoded, signature))
+}
+
+pub fn process_initialize2_transaction(
+    tx: &EncodedTransactionWithStatusMeta,
+) -> Result<Option<MigrationEvent>> {
+    let (decode_tx, signature) = decode(tx)?;
     let account_keys = decode_tx.message.static_account_keys();
     if account_keys.len() > 19 {
         let coin_token = account_keys[18];
@@ -41,107 +65,312 @@ pub fn process_initialize2_transaction(tx: &EncodedTransactionWithStatusMeta) ->
         println!("pc_token address {:?}", pc_token);
         println!("Liquidity address {:?}", liquidity_address);
         println!("==============================================================================================");
-        return Some(format!(
-            "**🚀 Token Migration 🚀**\n\
-            ```\n\
-            signature:           {}\n\
-            coin_token address:  {:?}\n\
-            pc_token address:    {:?}\n\
-            Liquidity address:   {:?}\n\
-            ```",
-            signature.to_string(),
-            coin_token,
-            pc_token,
-            liquidity_address
-        ));
+        // 记录迁移后的池子，之后卖出时使用
+        let native_mint = spl_token::native_mint::ID;
+        if pc_token == native_mint {
+            pools::record_pool(coin_token, liquidity_address);
+        } else if coin_token == native_mint {
+            pools::record_pool(pc_token, liquidity_address);
+        }
+        Ok(Some(MigrationEvent {
+            venue: MigrationVenue::Raydium,
+            signature: signature.to_string(),
+            coin_mint: coin_token.to_string(),
+            pc_mint: pc_token.to_string(),
+            pool_id: liquidity_address.to_string(),
+            received_at: None,
+        }))
     } else {
-        None
+        Ok(None)
     }
 }
 
-pub fn process_block(block: UiConfirmedBlock) -> Vec<String> {
-    let mut result = vec![];
-    for tx in block.transactions.unwrap() {
-        let logs = tx.meta.as_ref().unwrap().log_messages.clone().unwrap();
-        for log in logs {
-            if log.contains("Program log: initialize2: InitializeInstruction2") {
-                println!("Found initialize2 instruction!");
-                let res = process_initialize2_transaction(&tx);
-                if res.is_some() {
-                    result.push(res.unwrap());
+/// Finds a PumpSwap `create_pool`, called directly or through a CPI
+pub fn process_pumpswap_create_pool(
+    tx: &EncodedTransactionWithStatusMeta,
+) -> Result<Option<MigrationEvent>> {
+    let (decoded, signature) = decode(tx)?;
+    let meta = tx
+        .meta
+        .as_ref()
+        .ok_or_else(|| anyhow!("transaction without meta"))?;
+
+    // 包含地址查找表加载的账户
+    let mut keys = decoded.message.static_account_keys().to_vec();
+    if let OptionSerializer::Some(loaded) = &meta.loaded_addresses {
+        for key in loaded.writable.iter().chain(&loaded.readonly) {
+            keys.push(key.parse()?);
+        }
+    }
+    let key = |index: u8| keys.get(index as usize).copied();
+
+    let mut instructions: Vec<(u8, Vec<u8>, Vec<u8>)> = decoded
+        .message
+        .instructions()
+        .iter()
+        .map(|ix| (ix.program_id_index, ix.accounts.clone(), ix.data.clone()))
+        .collect();
+    if let OptionSerializer::Some(inner) = &meta.inner_instructions {
+        for ix in inner.iter().flat_map(|inner| &inner.instructions) {
+            if let UiInstruction::Compiled(ix) = ix {
+                if let Ok(data) = bs58::decode(&ix.data).into_vec() {
+                    instructions.push((ix.program_id_index, ix.accounts.clone(), data));
                 }
             }
         }
     }
-    result
+
+    let pumpswap = program_ids().pumpswap;
+    Ok(instructions
+        .into_iter()
+        .find_map(|(program, accounts, data)| {
+            if key(program)? != pumpswap
+                || !data.starts_with(&CREATE_POOL_DISCRIMINATOR)
+                || accounts.len() < 5
+            {
+                return None;
+            }
+            // pool, global_config, creator, base_mint, quote_mint, ...
+            Some(MigrationEvent {
+                venue: MigrationVenue::PumpSwap,
+                signature: signature.to_string(),
+                coin_mint: key(accounts[3])?.to_string(),
+                pc_mint: key(accounts[4])?.to_string(),
+                pool_id: key(accounts[0])?.to_string(),
+                received_at: None,
+            })
+        }))
+}
+
+/// Finds the migrations of a successful transaction
+fn process_transaction(
+    tx: &EncodedTransactionWithStatusMeta,
+    pumpswap_invoke: &str,
+) -> Result<Vec<MigrationEvent>> {
+    let meta = tx
+        .meta
+        .as_ref()
+        .ok_or_else(|| anyhow!("transaction without meta"))?;
+    let logs: Vec<String> = Option::from(meta.log_messages.clone()).unwrap_or_default();
+    let mut result = vec![];
+    if logs.iter().any(|log| log.starts_with(pumpswap_invoke)) {
+        result.extend(process_pumpswap_create_pool(tx)?);
+    }
+    if logs
+        .iter()
+        .any(|log| log.contains("Program log: initialize2: InitializeInstruction2"))
+    {
+        println!("Found initialize2 instruction!");
+        result.extend(process_initialize2_transaction(tx)?);
+    }
+    Ok(result)
+}
+
+/// Finds the migrations of `block`
+///
+/// Malformed transactions are skipped and reported to [`diagnostics`], the
+/// block only fails as a whole when it carries no transactions.
+pub fn process_block(block: UiConfirmedBlock) -> Result<Vec<MigrationEvent>> {
+    let transactions = block
+        .transactions
+        .ok_or_else(|| anyhow!("block {} without transactions", block.blockhash))?;
+    let mut result = vec![];
+    let pumpswap_invoke = format!("Program {} invoke", program_ids().pumpswap);
+    for tx in transactions {
+        // 跳过执行失败的交易
+        if !tx_succeeded(&tx) {
+            continue;
+        }
+        match process_transaction(&tx, &pumpswap_invoke) {
+            Ok(events) => result.extend(events),
+            Err(e) => {
+                let signature = decode(&tx).ok().map(|(_, signature)| signature.to_string());
+                diagnostics::report(MONITOR, signature, &e);
+            }
+        }
+    }
+    Ok(result)
 }
 
+/// Listens for Raydium and PumpSwap migrations and notifies `notifier` of each one
+///
+/// With `GEYSER_ENABLED=true` the blocks come from Yellowstone gRPC instead
+/// of `ws_client`, see [`geyser`].
+///
+/// Returns the listener tasks and the event sender, which stays valid across
+/// websocket reconnects; call `subscribe()` on it to add more consumers.
 pub async fn listen_rayidum_migration(
     ws_client: Arc<PubsubClient>,
+    notifier: Arc<dyn Notifier>,
     channel_size: usize,
-) -> Result<JoinSet<()>> {
+) -> Result<(JoinSet<()>, broadcast::Sender<MonitorEvent>)> {
+    let geyser_config = GeyserConfig::from_env()?;
     let mut set: JoinSet<()> = JoinSet::new();
     let (block_sender, _) = broadcast::channel(channel_size);
-    let bot = Arc::new(Bot::from_env());
+    let (event_sender, _) = broadcast::channel(channel_size);
+
+    // 通知的线程
+    set.spawn(notify_events(&event_sender, notifier));
 
-    // 处理log的线程
+    // 处理block的线程
     let mut block_receiver = block_sender.subscribe();
+    let events_out = event_sender.clone();
     set.spawn(async move {
-        while let Ok(block) = block_receiver.recv().await {
-            let result = process_block(block);
-            for res in result {
-                // 发送到tgbot
-                match bot
-                    .send_message(ChatId(CHATID), res)
-                    .parse_mode(ParseMode::MarkdownV2)
-                    .await
-                {
-                    Ok(_) => {}
-                    Err(e) => {
-                        eprintln!("send to bot error {:?}", e);
-                    }
+        loop {
+            let (received_at, block) = match block_receiver.recv().await {
+                Ok(block) => block,
+                Err(RecvError::Lagged(n)) => {
+                    eprintln!("{} lagged, skipped {} blocks", MONITOR, n);
+                    continue;
                 }
-            }
-        }
-    });
-
-    // 发出block的线程
-    set.spawn(async move {
-        let (mut stream, _) = ws_client
-            .block_subscribe(
-                // 只关注migrator
-                // RpcBlockSubscribeFilter::MentionsAccountOrProgram(PUMPFUNMIGRATOR.to_string()),
-                RpcBlockSubscribeFilter::All,
-                // 区块信息配置
-                Some(RpcBlockSubscribeConfig {
-                    commitment: Some(CommitmentConfig::confirmed()),
-                    encoding: Some(
-                        solana_transaction_status_client_types::UiTransactionEncoding::Binary,
-                    ),
-                    transaction_details: Some(
-                        solana_transaction_status_client_types::TransactionDetails::Full,
-                    ),
-                    show_rewards: Some(false),
-                    max_supported_transaction_version: Some(0),
-                }),
-            )
-            .await
-            .map_err(|e| anyhow!("failed to get stream {:?}", e))
-            .unwrap();
-
-        // 发送block
-        while let Some(new_block) = stream.next().await {
-            if let Some(block) = new_block.value.block {
-                match block_sender.send(block) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        eprintln!("send block error")
-                    }
+                Err(RecvError::Closed) => break,
+            };
+            let result = match process_block(block) {
+                Ok(result) => result,
+                Err(e) => {
+                    diagnostics::report(MONITOR, None, &e);
+                    continue;
                 }
+            };
+            metrics::MIGRATIONS_DETECTED.inc_by(result.len() as u64);
+            for mut event in result {
+                event.received_at = Some(received_at);
+                let event = MonitorEvent::Migration(event);
+                events::publish(event.clone());
+                // 没有接收者时忽略
+                let _ = events_out.send(event);
             }
         }
     });
 
+    // 发出block的线程，断线自动重连
+    match geyser_config {
+        Some(config) => set.spawn(geyser::stream_blocks(
+            config,
+            vec![program_ids().raydium_amm, program_ids().pumpswap],
+            MONITOR,
+            block_sender,
+        )),
+        None => set.spawn(stream_blocks(ws_client, MONITOR, block_sender)),
+    };
+
     // 返回set到主线程
-    Ok(set)
+    Ok((set, event_sender))
+}
+
+#[test]
+fn test_process_block_skips_failed_transactions() {
+    let failed: EncodedTransactionWithStatusMeta = serde_json::from_value(serde_json::json!({
+        "transaction": ["", "base64"],
+        "meta": {
+            "err": {"InstructionError": [0, {"Custom": 1}]},
+            "status": {"Err": {"InstructionError": [0, {"Custom": 1}]}},
+            "fee": 5000,
+            "preBalances": [],
+            "postBalances": [],
+            "logMessages": ["Program log: initialize2: InitializeInstruction2 { nonce: 254 }"],
+        },
+    }))
+    .unwrap();
+    let block = UiConfirmedBlock {
+        previous_blockhash: String::new(),
+        blockhash: String::new(),
+        parent_slot: 0,
+        transactions: Some(vec![failed]),
+        signatures: None,
+        rewards: None,
+        num_reward_partitions: None,
+        block_time: None,
+        block_height: None,
+    };
+
+    assert!(process_block(block).unwrap().is_empty());
+}
+
+#[test]
+fn test_process_block_detects_pumpswap_create_pool() {
+    use solana_sdk::{
+        instruction::{AccountMeta, Instruction},
+        transaction::Transaction,
+    };
+
+    let pool = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+    let mut data = CREATE_POOL_DISCRIMINATOR.to_vec();
+    data.extend_from_slice(&[0; 18]);
+    let accounts = [pool, Pubkey::new_unique(), Pubkey::new_unique(), mint]
+        .into_iter()
+        .chain([spl_token::native_mint::ID])
+        .map(|pubkey| AccountMeta::new(pubkey, false))
+        .collect();
+    let create_pool = Instruction::new_with_bytes(program_ids().pumpswap, &data, accounts);
+    let tx = Transaction::new_with_payer(&[create_pool], Some(&Pubkey::new_unique()));
+    let encoded = bs64::encode(&bincode::serialize(&VersionedTransaction::from(tx)).unwrap());
+
+    let tx: EncodedTransactionWithStatusMeta = serde_json::from_value(serde_json::json!({
+        "transaction": [encoded, "base64"],
+        "meta": {
+            "err": null,
+            "status": {"Ok": null},
+            "fee": 5000,
+            "preBalances": [],
+            "postBalances": [],
+            "logMessages": [format!("Program {} invoke [1]", program_ids().pumpswap)],
+        },
+    }))
+    .unwrap();
+    let block = UiConfirmedBlock {
+        previous_blockhash: String::new(),
+        blockhash: String::new(),
+        parent_slot: 0,
+        transactions: Some(vec![tx]),
+        signatures: None,
+        rewards: None,
+        num_reward_partitions: None,
+        block_time: None,
+        block_height: None,
+    };
+
+    let events = process_block(block).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].venue, MigrationVenue::PumpSwap);
+    assert_eq!(events[0].coin_mint, mint.to_string());
+    assert_eq!(events[0].pc_mint, spl_token::native_mint::ID.to_string());
+    assert_eq!(events[0].pool_id, pool.to_string());
+}
+
+#[test]
+fn test_process_block_reports_malformed_transactions() {
+    let malformed: EncodedTransactionWithStatusMeta = serde_json::from_value(serde_json::json!({
+        "transaction": ["AAAA", "base64"],
+        "meta": {
+            "err": null,
+            "status": {"Ok": null},
+            "fee": 5000,
+            "preBalances": [],
+            "postBalances": [],
+            "logMessages": ["Program log: initialize2: InitializeInstruction2 { nonce: 254 }"],
+        },
+    }))
+    .unwrap();
+    let mut block = UiConfirmedBlock {
+        previous_blockhash: String::new(),
+        blockhash: String::new(),
+        parent_slot: 0,
+        transactions: Some(vec![malformed]),
+        signatures: None,
+        rewards: None,
+        num_reward_partitions: None,
+        block_time: None,
+        block_height: None,
+    };
+
+    let mut reports = diagnostics::subscribe();
+    assert!(process_block(block.clone()).unwrap().is_empty());
+    let report = reports.try_recv().unwrap();
+    assert_eq!(report.monitor, MONITOR);
+    assert_eq!(report.signature, None);
+
+    block.transactions = None;
+    assert!(process_block(block).is_err());
 }
@@ -0,0 +1,274 @@
+//! Filters deciding which tweets may trigger a buy.
+//!
+//! A tweet naming a mint is only traded on if it passes every filter
+//! configured, none by default:
+//!
+//! - `TWEET_REQUIRED_KEYWORDS`: comma separated, the tweet must contain one
+//! - `TWEET_BLOCKED_KEYWORDS`: comma separated, the tweet must contain none
+//! - `TWEET_MIN_FOLLOWERS`: followers its author needs
+//! - `TWEET_MIN_ACCOUNT_AGE_DAYS`: days since its author signed up
+//! - `TWEET_SCORER_URL`: endpoint scoring the tweet, e.g. a sentiment model
+//!   or an LLM, see [`HttpScorer`]
+//! - `TWEET_MIN_SCORE`: score the tweet needs (default 0)
+//!
+//! Keywords match case-insensitively. Authors are looked up once and cached,
+//! and a tweet whose author or score can't be fetched is skipped.
+
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use time::OffsetDateTime;
+use tracing::info;
+use twitter_v2::{query::UserField, Tweet, TwitterApi};
+
+use crate::{config::BotConfig, strategy::parse_env};
+
+use super::twitter_monitor::auth_for_twitter;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Scores a tweet, higher being more bullish
+#[async_trait]
+pub trait TweetScorer: Send + Sync {
+    async fn score(&self, tweet: &Tweet) -> Result<f64>;
+}
+
+/// Scores tweets by posting `{"text", "author_id"}` to an endpoint answering
+/// `{"score": <number>}`
+pub struct HttpScorer {
+    http: reqwest::Client,
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScoreResponse {
+    score: f64,
+}
+
+impl HttpScorer {
+    pub fn new(url: String) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url,
+        }
+    }
+}
+
+#[async_trait]
+impl TweetScorer for HttpScorer {
+    async fn score(&self, tweet: &Tweet) -> Result<f64> {
+        let response: ScoreResponse = self
+            .http
+            .post(&self.url)
+            .json(&json!({
+                "text": tweet.text,
+                "author_id": tweet.author_id.map(|id| id.to_string()),
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response.score)
+    }
+}
+
+/// What the author filters need to know of a user
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Author {
+    pub followers: u64,
+    pub created_at: Option<OffsetDateTime>,
+}
+
+#[derive(Default)]
+pub struct TweetFilter {
+    /// Lowercase, the tweet must contain one if any
+    pub required_keywords: Vec<String>,
+    /// Lowercase, the tweet must contain none
+    pub blocked_keywords: Vec<String>,
+    pub min_followers: Option<u64>,
+    pub min_account_age: Option<Duration>,
+    pub scorer: Option<Arc<dyn TweetScorer>>,
+    pub min_score: f64,
+    authors: Mutex<HashMap<u64, Author>>,
+}
+
+fn keywords(name: &str) -> Vec<String> {
+    env::var(name)
+        .map(|list| {
+            list.split(',')
+                .map(|keyword| keyword.trim().to_lowercase())
+                .filter(|keyword| !keyword.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl TweetFilter {
+    /// Reads the `TWEET_*` filter variables
+    pub fn from_env() -> Result<Self> {
+        dotenv::dotenv().ok();
+        Ok(Self {
+            required_keywords: keywords("TWEET_REQUIRED_KEYWORDS"),
+            blocked_keywords: keywords("TWEET_BLOCKED_KEYWORDS"),
+            min_followers: parse_env("TWEET_MIN_FOLLOWERS")?,
+            min_account_age: parse_env::<u64>("TWEET_MIN_ACCOUNT_AGE_DAYS")?
+                .map(|days| Duration::from_secs(days * SECS_PER_DAY)),
+            scorer: env::var("TWEET_SCORER_URL")
+                .ok()
+                .map(|url| Arc::new(HttpScorer::new(url)) as Arc<dyn TweetScorer>),
+            min_score: parse_env("TWEET_MIN_SCORE")?.unwrap_or(0.0),
+            authors: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Why the text of `text` is skipped, `None` if it passes
+    pub fn text_rejection(&self, text: &str) -> Option<String> {
+        let text = text.to_lowercase();
+        if !self.required_keywords.is_empty()
+            && !self
+                .required_keywords
+                .iter()
+                .any(|keyword| text.contains(keyword.as_str()))
+        {
+            return Some("no required keyword".to_string());
+        }
+        self.blocked_keywords
+            .iter()
+            .find(|keyword| text.contains(keyword.as_str()))
+            .map(|keyword| format!("blocked keyword {:?}", keyword))
+    }
+
+    fn needs_author(&self) -> bool {
+        self.min_followers.is_some() || self.min_account_age.is_some()
+    }
+
+    /// Why tweets of `author` are skipped at `now`, `None` if they pass
+    pub fn author_rejection(&self, author: &Author, now: OffsetDateTime) -> Option<String> {
+        if let Some(min) = self.min_followers {
+            if author.followers < min {
+                return Some(format!("{} followers < {}", author.followers, min));
+            }
+        }
+        if let Some(min) = self.min_account_age {
+            // 没有注册时间按新账号处理
+            let age = author
+                .created_at
+                .map(|created_at| (now - created_at).whole_seconds().max(0) as u64)
+                .unwrap_or(0);
+            if age < min.as_secs() {
+                return Some(format!(
+                    "account {} days old < {}",
+                    age / SECS_PER_DAY,
+                    min.as_secs() / SECS_PER_DAY
+                ));
+            }
+        }
+        None
+    }
+
+    /// The author `id`, asking twitter the first time
+    async fn author(&self, id: u64, config: &BotConfig) -> Result<Author> {
+        if let Some(author) = self.authors.lock().unwrap().get(&id) {
+            return Ok(*author);
+        }
+        let api = TwitterApi::new(auth_for_twitter(config)?);
+        let user = api
+            .get_user(id)
+            .user_fields([UserField::PublicMetrics, UserField::CreatedAt])
+            .send()
+            .await?
+            .into_data()
+            .ok_or_else(|| anyhow!("user {} not found", id))?;
+        let author = Author {
+            followers: user
+                .public_metrics
+                .map(|metrics| metrics.followers_count as u64)
+                .unwrap_or(0),
+            created_at: user.created_at,
+        };
+        self.authors.lock().unwrap().insert(id, author);
+        Ok(author)
+    }
+
+    /// Why `tweet` is skipped, `None` if it passes every filter
+    pub async fn rejection(&self, tweet: &Tweet, config: &BotConfig) -> Option<String> {
+        if let Some(reason) = self.text_rejection(&tweet.text) {
+            return Some(reason);
+        }
+        if self.needs_author() {
+            let Some(id) = tweet.author_id else {
+                return Some("unknown author".to_string());
+            };
+            let author = match self.author(id.as_u64(), config).await {
+                Ok(author) => author,
+                Err(e) => return Some(format!("author lookup failed: {}", e)),
+            };
+            if let Some(reason) = self.author_rejection(&author, OffsetDateTime::now_utc()) {
+                return Some(reason);
+            }
+        }
+        if let Some(scorer) = &self.scorer {
+            match scorer.score(tweet).await {
+                Ok(score) if score >= self.min_score => {
+                    info!("tweet {} scored {}", tweet.id, score)
+                }
+                Ok(score) => return Some(format!("score {} < {}", score, self.min_score)),
+                Err(e) => return Some(format!("scoring failed: {}", e)),
+            }
+        }
+        None
+    }
+}
+
+#[tokio::test]
+async fn test_tweet_filters() {
+    struct Fixed(f64);
+
+    #[async_trait]
+    impl TweetScorer for Fixed {
+        async fn score(&self, _tweet: &Tweet) -> Result<f64> {
+            Ok(self.0)
+        }
+    }
+
+    let filter = TweetFilter {
+        required_keywords: vec!["ca".to_string(), "launch".to_string()],
+        blocked_keywords: vec!["rug".to_string()],
+        min_followers: Some(1_000),
+        min_account_age: Some(Duration::from_secs(30 * SECS_PER_DAY)),
+        ..TweetFilter::default()
+    };
+    assert_eq!(filter.text_rejection("Launching now"), None);
+    assert!(filter.text_rejection("new coin").is_some());
+    assert!(filter.text_rejection("CA: xyz, not a RUG").is_some());
+
+    let now = OffsetDateTime::now_utc();
+    let author = |followers, days: i64| Author {
+        followers,
+        created_at: Some(now - time::Duration::days(days)),
+    };
+    assert_eq!(filter.author_rejection(&author(5_000, 365), now), None);
+    assert!(filter.author_rejection(&author(10, 365), now).is_some());
+    assert!(filter.author_rejection(&author(5_000, 2), now).is_some());
+
+    let tweet: Tweet = serde_json::from_value(json!({"id": "1", "text": "CA: mint"})).unwrap();
+    let config = BotConfig::default();
+    // 没有作者信息时跳过
+    assert!(filter.rejection(&tweet, &config).await.is_some());
+    let scored = |score| TweetFilter {
+        scorer: Some(Arc::new(Fixed(score))),
+        min_score: 0.5,
+        ..TweetFilter::default()
+    };
+    assert_eq!(scored(0.8).rejection(&tweet, &config).await, None);
+    assert!(scored(0.1).rejection(&tweet, &config).await.is_some());
+}
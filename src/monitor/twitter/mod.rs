@@ -1 +1,5 @@
+pub mod filter;
+pub mod seen;
+pub mod stream;
 pub mod twitter_monitor;
+pub mod watcher;
@@ -0,0 +1,198 @@
+//! Tweets the twitter strategy already processed.
+//!
+//! Timelines are polled from each account's `since_id`, the newest tweet of it
+//! processed, and every processed tweet id is remembered, so a tweet polled
+//! twice or received from both the stream and a poll triggers one trade. Both
+//! survive restarts: they're kept in the SQLite [`storage`] with
+//! `STORAGE_ENABLED=true`, in `TWITTER_STATE_PATH` (default
+//! `twitter_state.json`) otherwise. Only the newest [`MAX_SEEN`] ids are
+//! remembered, older tweets are below every `since_id` anyway.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use twitter_v2::Tweet;
+
+use crate::storage::{self, Storage};
+
+const DEFAULT_STATE_PATH: &str = "twitter_state.json";
+
+/// Processed tweet ids remembered
+pub const MAX_SEEN: usize = 10_000;
+
+static GLOBAL_SEEN: OnceLock<SeenTweets> = OnceLock::new();
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    /// Newest processed tweet of each user
+    since_ids: HashMap<u64, u64>,
+    /// Processed tweet ids, the oldest evicted first
+    seen: BTreeSet<u64>,
+}
+
+impl State {
+    /// Records tweet `id`, `false` if it was already
+    fn insert(&mut self, id: u64, author: Option<u64>) -> bool {
+        if let Some(author) = author {
+            let since_id = self.since_ids.entry(author).or_default();
+            *since_id = (*since_id).max(id);
+        }
+        if !self.seen.insert(id) {
+            return false;
+        }
+        while self.seen.len() > MAX_SEEN {
+            self.seen.pop_first();
+        }
+        true
+    }
+}
+
+enum SeenStore {
+    Memory,
+    File(PathBuf),
+    Storage(&'static Storage),
+}
+
+pub struct SeenTweets {
+    store: SeenStore,
+    state: Mutex<State>,
+}
+
+impl SeenTweets {
+    /// Forgets everything on restart
+    pub fn in_memory() -> Self {
+        Self {
+            store: SeenStore::Memory,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Loads the state saved at `path` and saves updates to it
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let state = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(path)?)
+                .map_err(|e| anyhow!("invalid twitter state {}: {}", path.display(), e))?
+        } else {
+            State::default()
+        };
+        Ok(Self {
+            store: SeenStore::File(path.to_path_buf()),
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Loads the state kept in `storage` and inserts updates into it
+    pub fn from_storage(storage: &'static Storage) -> Result<Self> {
+        let state = State {
+            since_ids: storage.tweet_cursors()?.into_iter().collect(),
+            seen: storage.seen_tweets(MAX_SEEN)?.into_iter().collect(),
+        };
+        Ok(Self {
+            store: SeenStore::Storage(storage),
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Uses the [`storage`] if enabled, `TWITTER_STATE_PATH` otherwise
+    pub fn from_env() -> Result<Self> {
+        dotenv::dotenv().ok();
+        if let Some(storage) = storage::storage() {
+            return Self::from_storage(storage);
+        }
+        let path =
+            env::var("TWITTER_STATE_PATH").unwrap_or_else(|_| DEFAULT_STATE_PATH.to_string());
+        Self::open(path)
+    }
+
+    /// Newest processed tweet of `user`
+    pub fn since_id(&self, user: u64) -> Option<u64> {
+        self.state.lock().unwrap().since_ids.get(&user).copied()
+    }
+
+    /// Records `tweet` as processed and persists it, `false` if it already
+    /// was
+    pub fn first_seen(&self, tweet: &Tweet) -> Result<bool> {
+        let id = tweet.id.as_u64();
+        let author = tweet.author_id.map(|author| author.as_u64());
+        let mut state = self.state.lock().unwrap();
+        // 持有锁，保证存储和内存一致
+        if !state.insert(id, author) {
+            return Ok(false);
+        }
+        match &self.store {
+            SeenStore::Memory => {}
+            SeenStore::File(path) => {
+                // 先写临时文件再替换，避免写到一半
+                let tmp = path.with_extension("tmp");
+                fs::write(&tmp, serde_json::to_string(&*state)?)?;
+                fs::rename(&tmp, path)?;
+            }
+            SeenStore::Storage(storage) => {
+                storage.insert_seen_tweet(id, author)?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Process-wide processed tweets, in memory if their state can't be loaded
+pub fn seen_tweets() -> &'static SeenTweets {
+    GLOBAL_SEEN.get_or_init(|| {
+        SeenTweets::from_env().unwrap_or_else(|e| {
+            error!("failed to load twitter state, keeping it in memory {:?}", e);
+            SeenTweets::in_memory()
+        })
+    })
+}
+
+#[test]
+fn test_seen_tweets_persist() {
+    use twitter_v2::id::NumericId;
+
+    let tweet = |id: u64, author: u64| {
+        let mut tweet: Tweet = serde_json::from_value(serde_json::json!({
+            "id": id.to_string(),
+            "text": "",
+        }))
+        .unwrap();
+        tweet.author_id = Some(NumericId::new(author));
+        tweet
+    };
+    let path = env::temp_dir().join(format!("twitter_state_{}.json", std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let seen = SeenTweets::open(&path).unwrap();
+    assert!(seen.first_seen(&tweet(20, 1)).unwrap());
+    assert!(seen.first_seen(&tweet(10, 1)).unwrap());
+    assert!(!seen.first_seen(&tweet(20, 1)).unwrap());
+    assert_eq!(seen.since_id(1), Some(20));
+    assert_eq!(seen.since_id(2), None);
+
+    // 重启后仍然记得
+    let seen = SeenTweets::open(&path).unwrap();
+    assert!(!seen.first_seen(&tweet(10, 1)).unwrap());
+    assert_eq!(seen.since_id(1), Some(20));
+    fs::remove_file(&path).unwrap();
+
+    let storage: &'static Storage = Box::leak(Box::new(Storage::in_memory().unwrap()));
+    let seen = SeenTweets::from_storage(storage).unwrap();
+    assert!(seen.first_seen(&tweet(30, 2)).unwrap());
+    let seen = SeenTweets::from_storage(storage).unwrap();
+    assert!(!seen.first_seen(&tweet(30, 2)).unwrap());
+    assert_eq!(seen.since_id(2), Some(30));
+
+    let mut state = State::default();
+    for id in 0..=MAX_SEEN as u64 {
+        state.insert(id, None);
+    }
+    assert_eq!(state.seen.len(), MAX_SEEN);
+    assert!(!state.seen.contains(&0));
+}
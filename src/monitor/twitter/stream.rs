@@ -0,0 +1,172 @@
+//! Tweets of the tracked accounts and keywords from the filtered stream.
+//!
+//! Rather than polling every timeline, one rule per account (`from:<id>`) and
+//! per keyword is kept on the app's filtered stream, and matching tweets are
+//! pushed as they're posted. The bot tags its rules so [`sync_rules`] leaves
+//! the app's other rules alone: on every (re)connect the missing ones are
+//! added and the stale ones deleted. The stream is reopened with a backoff
+//! when it fails or drops.
+
+use std::{collections::HashSet, time::Duration};
+
+use anyhow::Result;
+use futures_util::StreamExt;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+use twitter_v2::{
+    authorization::BearerToken, data::StreamRule, id::NumericId, query::TweetField, Tweet,
+    TwitterApi,
+};
+
+/// Tag of the rules the bot manages
+pub const RULE_TAG: &str = "raydium_swap";
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(120);
+
+/// Stream rules matching the tweets of `accounts` and those containing one of
+/// `keywords`
+pub fn rules(accounts: &[u64], keywords: &[String]) -> Vec<String> {
+    let accounts = accounts.iter().map(|id| format!("from:{}", id));
+    let keywords = keywords
+        .iter()
+        .map(|keyword| keyword.trim())
+        .filter(|keyword| !keyword.is_empty())
+        .map(|keyword| {
+            // 含空格的关键词按短语匹配
+            if keyword.contains(char::is_whitespace) {
+                format!("\"{}\"", keyword.replace('"', ""))
+            } else {
+                keyword.to_string()
+            }
+        });
+    let mut seen = HashSet::new();
+    accounts
+        .chain(keywords)
+        .filter(|rule| seen.insert(rule.clone()))
+        .collect()
+}
+
+/// The rules of `wanted` missing from `existing`, and the ids of the bot's
+/// rules of `existing` no longer wanted
+pub fn diff_rules(wanted: &[String], existing: &[StreamRule]) -> (Vec<String>, Vec<NumericId>) {
+    let ours: Vec<&StreamRule> = existing
+        .iter()
+        .filter(|rule| rule.tag.as_deref() == Some(RULE_TAG))
+        .collect();
+    let to_add = wanted
+        .iter()
+        .filter(|value| !ours.iter().any(|rule| &rule.value == *value))
+        .cloned()
+        .collect();
+    let to_delete = ours
+        .iter()
+        .filter(|rule| !wanted.contains(&rule.value))
+        .map(|rule| rule.id)
+        .collect();
+    (to_add, to_delete)
+}
+
+/// Makes the bot's rules on the stream `wanted`
+pub async fn sync_rules(api: &TwitterApi<BearerToken>, wanted: &[String]) -> Result<()> {
+    let existing = api
+        .get_tweets_search_stream_rules()
+        .send()
+        .await?
+        .into_data()
+        .unwrap_or_default();
+    let (to_add, to_delete) = diff_rules(wanted, &existing);
+    if !to_delete.is_empty() {
+        api.post_tweets_search_stream_rule()
+            .delete_ids(to_delete.clone())
+            .send()
+            .await?;
+    }
+    if !to_add.is_empty() {
+        let mut request = api.post_tweets_search_stream_rule();
+        for value in &to_add {
+            request.add_tagged(value, RULE_TAG);
+        }
+        request.send().await?;
+    }
+    info!(
+        "stream rules synced, {} added, {} deleted",
+        to_add.len(),
+        to_delete.len()
+    );
+    Ok(())
+}
+
+/// Syncs `rules` and sends the matching tweets to `tweets` until it closes,
+/// reconnecting whenever the stream fails or ends
+pub async fn stream_tweets(
+    api: TwitterApi<BearerToken>,
+    rules: Vec<String>,
+    tweets: mpsc::Sender<Tweet>,
+) {
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        match stream_once(&api, &rules, &tweets, &mut backoff).await {
+            Ok(true) => return,
+            Ok(false) => warn!("twitter stream ended, reconnecting in {:?}", backoff),
+            Err(e) => warn!(
+                "twitter stream failed, reconnecting in {:?} {:?}",
+                backoff, e
+            ),
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Reads one connection of the stream, `true` once `tweets` closed
+async fn stream_once(
+    api: &TwitterApi<BearerToken>,
+    rules: &[String],
+    tweets: &mpsc::Sender<Tweet>,
+    backoff: &mut Duration,
+) -> Result<bool> {
+    sync_rules(api, rules).await?;
+    let mut stream = api
+        .get_tweets_search_stream()
+        .tweet_fields([TweetField::AuthorId, TweetField::CreatedAt])
+        .stream()
+        .await?;
+    info!("twitter stream connected");
+    while let Some(payload) = stream.next().await {
+        let Some(tweet) = payload?.into_data() else {
+            continue;
+        };
+        // 收到数据说明连接正常，重置退避
+        *backoff = MIN_BACKOFF;
+        if tweets.send(tweet).await.is_err() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[test]
+fn test_stream_rules_diff() {
+    let wanted = rules(
+        &[12, 34, 12],
+        &["pump".to_string(), " new coin ".to_string(), String::new()],
+    );
+    assert_eq!(wanted, ["from:12", "from:34", "pump", "\"new coin\""]);
+
+    let rule = |id: u64, value: &str, tag: Option<&str>| StreamRule {
+        id: NumericId::new(id),
+        value: value.to_string(),
+        tag: tag.map(str::to_string),
+    };
+    let existing = [
+        rule(1, "from:12", Some(RULE_TAG)),
+        rule(2, "from:56", Some(RULE_TAG)),
+        // 不是本程序的规则，不删除
+        rule(3, "from:78", None),
+        rule(4, "pump", Some("other")),
+    ];
+    let (to_add, to_delete) = diff_rules(&wanted, &existing);
+    assert_eq!(to_add, ["from:34", "pump", "\"new coin\""]);
+    assert_eq!(to_delete, [NumericId::new(2)]);
+}
@@ -1,54 +1,250 @@
-use std::{env, time::Duration};
+use std::sync::Arc;
 
-use anyhow::Result;
-use regex::Regex;
-use solana_sdk::transaction::Transaction;
+use anyhow::{anyhow, Result};
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction, native_token::sol_to_lamports, pubkey::Pubkey,
+    signature::Keypair, signer::Signer, transaction::Transaction,
+};
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
 use time::OffsetDateTime;
 use twitter_v2::{
     authorization::BearerToken, id::IntoNumericId, query::TweetField, Authorization, Tweet,
     TwitterApi,
 };
 
-use crate::strategy::Strategy;
+use tracing::{info, warn};
+
+use crate::{
+    config::BotConfig,
+    constants::accounts::TOKEN_PROGRAM,
+    datasources::{self, TokenInfo},
+    math::slippage::Slippage,
+    monitor::find_mint,
+    new_client,
+    pumpfun::{
+        instructions::create_buy_instruction,
+        utils::{get_bonding_curve_account, get_global_account},
+    },
+    strategy::RiskProfile,
+    wallet::Wallets,
+};
+
+use super::filter::TweetFilter;
+
+/// Slippage of the tweet buys, in percent
+const SLIPPAGE: u64 = 10;
+
+/// Unsigned buy of a token a tweet named, with what the engine checks
+/// before sending it
+#[derive(Debug, Clone)]
+pub struct TweetBuy {
+    pub mint: Pubkey,
+    /// SOL spent, in lamports including the fee
+    pub lamports: u64,
+    /// Most SOL the buy may cost with slippage, in lamports
+    pub max_sol_cost: u64,
+    /// Wallet paying for and signing the buy
+    pub payer: Arc<Keypair>,
+    pub txn: Transaction,
+}
+
+/// What a token must look like for a risk profile to buy it
+#[derive(Debug, Clone, Copy)]
+struct ProfileLimits {
+    min_liquidity_usd: f64,
+    min_holders: u64,
+    max_top10_holders_pct: f64,
+    /// Needs the mint and freeze authorities reported revoked
+    renounced: bool,
+    buy_sol: f64,
+}
+
+fn limits(profile: &RiskProfile) -> ProfileLimits {
+    match profile {
+        RiskProfile::Conservative => ProfileLimits {
+            min_liquidity_usd: 20_000.0,
+            min_holders: 200,
+            max_top10_holders_pct: 30.0,
+            renounced: true,
+            buy_sol: 0.05,
+        },
+        RiskProfile::Medium => ProfileLimits {
+            min_liquidity_usd: 5_000.0,
+            min_holders: 50,
+            max_top10_holders_pct: 50.0,
+            renounced: true,
+            buy_sol: 0.1,
+        },
+        RiskProfile::Radical => ProfileLimits {
+            min_liquidity_usd: 0.0,
+            min_holders: 0,
+            max_top10_holders_pct: 100.0,
+            renounced: false,
+            buy_sol: 0.2,
+        },
+    }
+}
+
+/// Why `info` doesn't suit `profile`, `None` if it does
+///
+/// Fields the sources didn't report don't count against the token.
+pub fn rejection(profile: &RiskProfile, info: &TokenInfo) -> Option<String> {
+    let limits = limits(profile);
+    let security = &info.security;
+    if security.honeypot == Some(true) {
+        return Some("flagged as honeypot".to_string());
+    }
+    if limits.renounced
+        && (security.mint_renounced == Some(false) || security.freeze_renounced == Some(false))
+    {
+        return Some("authorities not revoked".to_string());
+    }
+    if let Some(liquidity) = info.liquidity_usd.filter(|l| *l < limits.min_liquidity_usd) {
+        return Some(format!("liquidity of ${:.0}", liquidity));
+    }
+    if let Some(holders) = info.holders.filter(|h| *h < limits.min_holders) {
+        return Some(format!("{} holders", holders));
+    }
+    if let Some(pct) = security
+        .top10_holders_pct
+        .filter(|pct| *pct > limits.max_top10_holders_pct)
+    {
+        return Some(format!("top 10 holders own {:.1}%", pct));
+    }
+    None
+}
 
 // 获取用户tweet
+/// Tweets of user `id`, only those after `since_id` if given
 pub async fn get_post_content<A: Authorization>(
     api: &TwitterApi<A>,
     id: impl IntoNumericId,
+    since_id: Option<u64>,
 ) -> Result<Vec<Tweet>> {
-    let res = api
-        .get_user_tweets(id)
+    let mut request = api.get_user_tweets(id);
+    request
         .end_time(OffsetDateTime::now_utc())
-        .tweet_fields([TweetField::AuthorId, TweetField::CreatedAt])
-        .send()
-        .await?
-        .into_data()
-        .expect("get user post error {:?}");
-    Ok(res)
+        .tweet_fields([TweetField::AuthorId, TweetField::CreatedAt]);
+    if let Some(since_id) = since_id {
+        request.since_id(since_id);
+    }
+    // 没有新推文时没有 data
+    Ok(request.send().await?.into_data().unwrap_or_default())
 }
 
-pub fn auth_for_twitter() -> BearerToken {
-    BearerToken::new(std::env::var("APP_BEARER_TOKEN").unwrap())
+pub fn auth_for_twitter(config: &BotConfig) -> Result<BearerToken> {
+    let token = config
+        .app_bearer_token
+        .as_ref()
+        .ok_or(anyhow!("app_bearer_token is not set"))?;
+    Ok(BearerToken::new(token))
 }
 
-pub async fn process_tweet(tweet: Tweet, strategy: &Strategy) -> Option<Transaction> {
+/// Buy of the token a tweet names, `None` if it names none or it's skipped
+///
+/// `buy_sol` overrides the profile's buy size. The buy is paid by the
+/// wallet [`Wallets::for_buy`] picks.
+pub async fn process_tweet(
+    tweet: Tweet,
+    strategy: &RiskProfile,
+    buy_sol: Option<f64>,
+    filter: &TweetFilter,
+    config: &BotConfig,
+    wallets: &Wallets,
+) -> Option<TweetBuy> {
     // fetch the coin name,mint address and gmgn info
-    let re = Regex::new(r"[1-9A-HJ-NP-Za-km-z]{32,44}").unwrap();
-    if let Some(captures) = re.find(&tweet.text) {
-        let mint_address = captures.as_str().to_string();
-        // fetch from gmgn,and create a tx
-        fetch_coin_info_and_creat_tx(mint_address, env::var("GMGN_COOKIE").unwrap(), strategy).await
-    } else {
+    let mint = find_mint(&tweet.text)?;
+    if let Some(reason) = filter.rejection(&tweet, config).await {
+        info!("skipping tweet {} on {}: {}", tweet.id, mint, reason);
         return None;
     }
+    let payer = wallets.for_buy(&mint);
+    match fetch_coin_info_and_creat_tx(mint, strategy, buy_sol, config, payer).await {
+        Ok(buy) => buy,
+        Err(e) => {
+            warn!("not buying {} from tweet {:?}", mint, e);
+            None
+        }
+    }
 }
 
+/// Unsigned buy of `mint` on its bonding curve if the datasources report it
+/// suits `strategy`, `None` if it doesn't
+///
+/// Buys with `buy_sol`, or the profile's size if `None`, from `payer`.
+///
+/// The engine signs it with a fresh blockhash when sending it.
 pub async fn fetch_coin_info_and_creat_tx(
-    mint_address: String,
-    cookie: String,
-    strategy: &Strategy,
-) -> Option<Transaction> {
+    mint: Pubkey,
+    strategy: &RiskProfile,
+    buy_sol: Option<f64>,
+    config: &BotConfig,
+    payer: Arc<Keypair>,
+) -> Result<Option<TweetBuy>> {
     // 1. analyze is potenial
+    let info = datasources::token_info(&mint).await?;
+    if let Some(reason) = rejection(strategy, &info) {
+        info!("{:?} profile skips {}: {}", strategy, mint, reason);
+        return Ok(None);
+    }
+
     // 2. create a transaction with strategy
-    Some(Transaction::default())
+    let lamports = sol_to_lamports(buy_sol.unwrap_or(limits(strategy).buy_sol));
+    let curve = get_bonding_curve_account(new_client(), &mint).await?;
+    let global = get_global_account(new_client()).await?;
+    let token_amount = curve
+        .get_buy_price_with_fee(lamports, global.fee_basis_points)
+        .map_err(|e| anyhow!(e))?;
+    let max_sol_cost = Slippage::Percent(SLIPPAGE).max_in(lamports)?;
+    let instructions = [
+        ComputeBudgetInstruction::set_compute_unit_limit(config.unit_limit),
+        ComputeBudgetInstruction::set_compute_unit_price(config.unit_price),
+        create_associated_token_account_idempotent(
+            &payer.pubkey(),
+            &payer.pubkey(),
+            &mint,
+            &TOKEN_PROGRAM,
+        ),
+        create_buy_instruction(&payer, &mint, token_amount, max_sol_cost),
+    ];
+    let txn = Transaction::new_with_payer(&instructions, Some(&payer.pubkey()));
+    Ok(Some(TweetBuy {
+        mint,
+        lamports,
+        max_sol_cost,
+        payer,
+        txn,
+    }))
+}
+
+#[test]
+fn test_risk_profile_rejections() {
+    use crate::datasources::SecurityFlags;
+
+    let info = TokenInfo {
+        liquidity_usd: Some(8_000.0),
+        holders: Some(100),
+        security: SecurityFlags {
+            mint_renounced: Some(true),
+            top10_holders_pct: Some(40.0),
+            ..SecurityFlags::default()
+        },
+        ..TokenInfo::default()
+    };
+    assert!(rejection(&RiskProfile::Conservative, &info).is_some());
+    assert_eq!(rejection(&RiskProfile::Medium, &info), None);
+
+    let honeypot = TokenInfo {
+        security: SecurityFlags {
+            honeypot: Some(true),
+            ..SecurityFlags::default()
+        },
+        ..TokenInfo::default()
+    };
+    assert!(rejection(&RiskProfile::Radical, &honeypot).is_some());
+    // 没有报告的字段不算
+    assert_eq!(
+        rejection(&RiskProfile::Conservative, &TokenInfo::default()),
+        None
+    );
 }
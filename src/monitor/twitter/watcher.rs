@@ -0,0 +1,224 @@
+//! Per-account watchers of the Twitter timelines.
+//!
+//! Every polled account gets its own task, polling on the account's own
+//! interval within its own request budget, so a slow or rate limited
+//! timeline doesn't hold the others back. The account's tweets are traded
+//! with its own [`RiskProfile`] and buy size, e.g. a bigger one for the
+//! accounts whose calls are trusted more.
+//!
+//! `TWITTER_ACCOUNTS` lists the accounts, `;` separated, each a user id
+//! followed by optional `,<key>=<value>` settings:
+//!
+//! - `poll_secs`: seconds between two polls (default the engine's)
+//! - `requests`: most requests per 15 minutes, past it the account waits for
+//!   the next window (default unlimited)
+//! - `profile`: `conservative`, `medium` or `radical` (default the engine's)
+//! - `buy_sol`: SOL per buy, instead of the profile's
+//!
+//! e.g. `TWITTER_ACCOUNTS=44196397,poll_secs=15,profile=radical,buy_sol=0.5;12345`
+
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use tokio::{sync::mpsc, time::MissedTickBehavior};
+use tracing::warn;
+use twitter_v2::{authorization::BearerToken, Tweet, TwitterApi};
+
+use crate::strategy::{params::parse_value, RiskProfile};
+
+use super::{seen::seen_tweets, twitter_monitor::get_post_content};
+
+/// Window the request budgets are counted over, Twitter's rate limit window
+pub const RATE_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// A watched account and how its tweets are traded
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountWatch {
+    pub user_id: u64,
+    pub poll_interval: Duration,
+    /// Most requests per [`RATE_WINDOW`], unlimited if `None`
+    pub requests: Option<u32>,
+    pub profile: RiskProfile,
+    /// SOL per buy, the profile's if `None`
+    pub buy_sol: Option<f64>,
+}
+
+impl AccountWatch {
+    pub fn new(user_id: u64, poll_interval: Duration, profile: RiskProfile) -> Self {
+        Self {
+            user_id,
+            poll_interval,
+            requests: None,
+            profile,
+            buy_sol: None,
+        }
+    }
+
+    /// Sets the setting `key` to `value`
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "poll_secs" => self.poll_interval = Duration::from_secs(parse_value(key, value)?),
+            "requests" => self.requests = Some(parse_value(key, value)?),
+            "profile" => self.profile = parse_value(key, value)?,
+            "buy_sol" => self.buy_sol = Some(parse_value(key, value)?),
+            _ => return Err(anyhow!("unknown account setting {:?}", key)),
+        }
+        Ok(())
+    }
+}
+
+/// Parses the `;` separated `<user id>[,<key>=<value>...]` entries of
+/// `TWITTER_ACCOUNTS`, the settings left out taking the defaults given
+pub fn parse_accounts(
+    accounts: &str,
+    poll_interval: Duration,
+    profile: RiskProfile,
+) -> Result<Vec<AccountWatch>> {
+    accounts
+        .split(';')
+        .map(str::trim)
+        .filter(|account| !account.is_empty())
+        .map(|account| {
+            let mut parts = account.split(',').map(str::trim);
+            let user_id = parts.next().unwrap_or_default();
+            let user_id = user_id
+                .parse()
+                .map_err(|_| anyhow!("invalid user id {:?}", user_id))?;
+            let mut watch = AccountWatch::new(user_id, poll_interval, profile);
+            for setting in parts.filter(|setting| !setting.is_empty()) {
+                let (key, value) = setting
+                    .split_once('=')
+                    .ok_or(anyhow!("{:?} is not <key>=<value>", setting))?;
+                watch.set(key.trim(), value)?;
+            }
+            Ok(watch)
+        })
+        .collect()
+}
+
+/// The accounts of `TWITTER_ACCOUNTS`, none if unset
+pub fn accounts_from_env(
+    poll_interval: Duration,
+    profile: RiskProfile,
+) -> Result<Vec<AccountWatch>> {
+    dotenv::dotenv().ok();
+    match env::var("TWITTER_ACCOUNTS") {
+        Ok(accounts) => parse_accounts(&accounts, poll_interval, profile)
+            .map_err(|e| anyhow!("invalid TWITTER_ACCOUNTS: {}", e)),
+        Err(_) => Ok(vec![]),
+    }
+}
+
+/// Requests of one account in the current window
+#[derive(Debug)]
+pub struct RequestBudget {
+    max: Option<u32>,
+    window_start: Instant,
+    used: u32,
+}
+
+impl RequestBudget {
+    pub fn new(max: Option<u32>) -> Self {
+        Self {
+            max,
+            window_start: Instant::now(),
+            used: 0,
+        }
+    }
+
+    /// Takes a request from the budget at `now`, or returns how long until
+    /// the next window
+    fn take(&mut self, now: Instant) -> Option<Duration> {
+        if now.duration_since(self.window_start) >= RATE_WINDOW {
+            self.window_start = now;
+            self.used = 0;
+        }
+        if self.max.is_some_and(|max| self.used >= max) {
+            return Some(RATE_WINDOW - now.duration_since(self.window_start));
+        }
+        self.used += 1;
+        None
+    }
+
+    /// Waits until the budget has a request left, and takes it
+    pub async fn acquire(&mut self) {
+        while let Some(wait) = self.take(Instant::now()) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Polls the timeline of `watch` and sends its new tweets to `tweets` until
+/// the receiver is dropped
+pub async fn watch_account(
+    api: TwitterApi<BearerToken>,
+    watch: AccountWatch,
+    tweets: mpsc::Sender<Tweet>,
+) {
+    let mut budget = RequestBudget::new(watch.requests);
+    let mut ticker = tokio::time::interval(watch.poll_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        budget.acquire().await;
+        let since_id = seen_tweets().since_id(watch.user_id);
+        match get_post_content(&api, watch.user_id, since_id).await {
+            Ok(tweet_list) => {
+                for tweet in tweet_list {
+                    if tweets.send(tweet).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(e) => warn!("failed to poll the tweets of {} {:?}", watch.user_id, e),
+        }
+    }
+}
+
+#[test]
+fn test_parse_accounts() {
+    let poll = Duration::from_secs(60);
+    let accounts = parse_accounts(
+        "44196397, poll_secs=15, profile=radical, buy_sol=0.5, requests=100; 12345",
+        poll,
+        RiskProfile::Medium,
+    )
+    .unwrap();
+    assert_eq!(
+        accounts,
+        vec![
+            AccountWatch {
+                user_id: 44196397,
+                poll_interval: Duration::from_secs(15),
+                requests: Some(100),
+                profile: RiskProfile::Radical,
+                buy_sol: Some(0.5),
+            },
+            AccountWatch::new(12345, poll, RiskProfile::Medium),
+        ]
+    );
+    assert!(parse_accounts("abc", poll, RiskProfile::Medium).is_err());
+    assert!(parse_accounts("1,speed=2", poll, RiskProfile::Medium).is_err());
+    assert!(parse_accounts("1,profile=yolo", poll, RiskProfile::Medium).is_err());
+}
+
+#[test]
+fn test_request_budget() {
+    let mut budget = RequestBudget::new(Some(2));
+    let start = budget.window_start;
+    assert_eq!(budget.take(start), None);
+    assert_eq!(budget.take(start), None);
+    // 用完后等到下一个窗口
+    let later = start + Duration::from_secs(60);
+    assert_eq!(
+        budget.take(later),
+        Some(RATE_WINDOW - Duration::from_secs(60))
+    );
+    assert_eq!(budget.take(start + RATE_WINDOW), None);
+
+    let mut unlimited = RequestBudget::new(None);
+    assert!((0..1000).all(|_| unlimited.take(start).is_none()));
+}
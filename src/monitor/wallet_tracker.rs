@@ -0,0 +1,552 @@
+//! Copy trading of tracked wallets.
+//!
+//! Every tracked wallet gets its own logs subscription. Each successful
+//! transaction mentioning the wallet is fetched and decoded from the wallet's
+//! SOL and token balance changes; a Pump.fun or Raydium swap is then replayed
+//! after the wallet's delay. Buys spend the wallet's ratio of the SOL it
+//! spent, sells sell the same share of our position as the wallet sold of its
+//! holdings. It is off unless `COPY_TRADE_ENABLED=true`.
+//!
+//! - `COPY_WALLETS`: comma separated `wallet[:ratio[:delay_ms]]`
+//! - `COPY_RATIO`: default share of a wallet's buy to copy (default 0.1)
+//! - `COPY_DELAY_MS`: default delay before replaying (default 0)
+//! - `COPY_MAX_SOL`: most SOL spent per copied buy (default 0.5)
+//! - `COPY_SLIPPAGE`: slippage in percent (default 10)
+//! - `COPY_SIMULATE`: only simulate the trades
+//!
+//! With `SAFETY_CHECKS_ENABLED=true` buys of tokens failing the
+//! [`crate::safety`] checks aren't copied, sells always are.
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use futures_util::StreamExt;
+use solana_client::{
+    nonblocking::{pubsub_client::PubsubClient, rpc_client::RpcClient},
+    rpc_config::{RpcTransactionConfig, RpcTransactionLogsConfig, RpcTransactionLogsFilter},
+};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    native_token::{lamports_to_sol, sol_to_lamports},
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+};
+use solana_transaction_status_client_types::{
+    option_serializer::OptionSerializer, EncodedTransactionWithStatusMeta, UiTransactionEncoding,
+    UiTransactionTokenBalance,
+};
+use tokio::{sync::mpsc, task::JoinSet, time::sleep};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::{error, info, warn};
+
+use crate::{
+    config::program_ids,
+    engine::is_paused,
+    monitor::tx_succeeded,
+    new_ws_client,
+    portfolio::{portfolio, Side},
+    pumpfun::operation::{buy, sell_auto},
+    raydium::{pools::find_sol_pool, swap::get_swap_tx},
+    safety::{self, SafetyConfig},
+    strategy::parse_env,
+    timeline,
+    tx::simulate::TxOutcome,
+    wallet::Wallets,
+};
+
+const DEFAULT_RATIO: f64 = 0.1;
+const DEFAULT_MAX_SOL: f64 = 0.5;
+const DEFAULT_SLIPPAGE: u64 = 10;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// A wallet to copy and how
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackedWallet {
+    pub wallet: Pubkey,
+    /// Share of the wallet's buys to spend
+    pub ratio: f64,
+    /// Wait before replaying a trade
+    pub delay: Duration,
+}
+
+impl TrackedWallet {
+    /// Parses `wallet[:ratio[:delay_ms]]`, falling back to the defaults
+    fn parse(s: &str, ratio: f64, delay: Duration) -> Result<Self> {
+        let mut parts = s.split(':').map(str::trim);
+        let wallet = parts
+            .next()
+            .unwrap_or_default()
+            .parse()
+            .map_err(|e| anyhow!("invalid COPY_WALLETS wallet {:?}: {}", s, e))?;
+        let ratio = match parts.next() {
+            Some(ratio) => ratio
+                .parse()
+                .map_err(|_| anyhow!("invalid COPY_WALLETS ratio {:?}", s))?,
+            None => ratio,
+        };
+        let delay = match parts.next() {
+            Some(delay) => Duration::from_millis(
+                delay
+                    .parse()
+                    .map_err(|_| anyhow!("invalid COPY_WALLETS delay {:?}", s))?,
+            ),
+            None => delay,
+        };
+        Ok(Self {
+            wallet,
+            ratio,
+            delay,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WalletTrackerConfig {
+    pub wallets: Vec<TrackedWallet>,
+    /// Most lamports spent per copied buy
+    pub max_buy: u64,
+    /// Slippage in percent
+    pub slippage: u64,
+    pub simulate: bool,
+    /// Skips buys of tokens failing the safety checks when set
+    pub safety: Option<SafetyConfig>,
+}
+
+impl WalletTrackerConfig {
+    /// Reads the `COPY_*` variables, `None` unless `COPY_TRADE_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("COPY_TRADE_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        let ratio = parse_env("COPY_RATIO")?.unwrap_or(DEFAULT_RATIO);
+        let delay = Duration::from_millis(parse_env("COPY_DELAY_MS")?.unwrap_or(0));
+        let wallets = parse_env::<String>("COPY_WALLETS")?
+            .unwrap_or_default()
+            .split(',')
+            .filter(|wallet| !wallet.trim().is_empty())
+            .map(|wallet| TrackedWallet::parse(wallet, ratio, delay))
+            .collect::<Result<Vec<_>>>()?;
+        if wallets.is_empty() {
+            return Err(anyhow!("COPY_TRADE_ENABLED needs COPY_WALLETS"));
+        }
+        Ok(Some(Self {
+            wallets,
+            max_buy: sol_to_lamports(parse_env("COPY_MAX_SOL")?.unwrap_or(DEFAULT_MAX_SOL)),
+            slippage: parse_env("COPY_SLIPPAGE")?.unwrap_or(DEFAULT_SLIPPAGE),
+            simulate: parse_env("COPY_SIMULATE")?.unwrap_or(false),
+            safety: SafetyConfig::from_env()?,
+        }))
+    }
+}
+
+/// A swap made by a tracked wallet
+#[derive(Debug, Clone, PartialEq)]
+pub struct WalletSwap {
+    pub wallet: Pubkey,
+    pub signature: String,
+    pub venue: &'static str,
+    pub side: Side,
+    pub mint: Pubkey,
+    /// Tokens bought or sold, in raw units
+    pub token_amount: u64,
+    /// Tokens the wallet held before the swap
+    pub token_balance_before: u64,
+    /// Lamports spent or received, net of the transaction fee
+    pub sol_amount: u64,
+}
+
+/// Sum of `wallet`'s balances per mint
+fn token_balances(
+    wallet: &str,
+    balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>,
+) -> HashMap<String, u64> {
+    let mut result = HashMap::new();
+    if let OptionSerializer::Some(balances) = balances {
+        for balance in balances {
+            if balance.owner.as_ref() != OptionSerializer::Some(&wallet.to_string()) {
+                continue;
+            }
+            let amount = balance.ui_token_amount.amount.parse().unwrap_or(0);
+            *result.entry(balance.mint.clone()).or_insert(0) += amount;
+        }
+    }
+    result
+}
+
+/// Decodes the Pump.fun or Raydium swap `wallet` made in `tx`, if any
+pub fn decode_swap(wallet: &Pubkey, tx: &EncodedTransactionWithStatusMeta) -> Option<WalletSwap> {
+    if !tx_succeeded(tx) {
+        return None;
+    }
+    let decoded = tx.transaction.decode()?;
+    let meta = tx.meta.as_ref()?;
+
+    // 包含地址查找表加载的账户
+    let mut keys = decoded.message.static_account_keys().to_vec();
+    if let OptionSerializer::Some(loaded) = &meta.loaded_addresses {
+        for key in loaded.writable.iter().chain(&loaded.readonly) {
+            keys.push(key.parse().ok()?);
+        }
+    }
+    let ids = program_ids();
+    let venue = if keys.contains(&ids.pumpfun) {
+        "pumpfun"
+    } else if keys.contains(&ids.raydium_amm) {
+        "raydium"
+    } else {
+        return None;
+    };
+
+    // 钱包sol变化，付费者加回手续费
+    let index = keys.iter().position(|key| key == wallet)?;
+    let mut sol_delta =
+        *meta.post_balances.get(index)? as i128 - *meta.pre_balances.get(index)? as i128;
+    if index == 0 {
+        sol_delta += meta.fee as i128;
+    }
+
+    let wallet = wallet.to_string();
+    let pre = token_balances(&wallet, &meta.pre_token_balances);
+    let post = token_balances(&wallet, &meta.post_token_balances);
+    let delta = |mint: &String| {
+        post.get(mint).copied().unwrap_or(0) as i128 - pre.get(mint).copied().unwrap_or(0) as i128
+    };
+    // 保留的wsol账户也算作sol
+    let native_mint = spl_token::native_mint::ID.to_string();
+    sol_delta += delta(&native_mint);
+
+    let (mint, token_delta) = pre
+        .keys()
+        .chain(post.keys())
+        .filter(|mint| **mint != native_mint)
+        .map(|mint| (mint, delta(mint)))
+        .find(|(_, delta)| *delta != 0)?;
+    let side = match (token_delta > 0, sol_delta < 0) {
+        (true, true) => Side::Buy,
+        (false, false) => Side::Sell,
+        _ => return None,
+    };
+    Some(WalletSwap {
+        wallet: wallet.parse().ok()?,
+        signature: decoded.signatures[0].to_string(),
+        venue,
+        side,
+        mint: mint.parse().ok()?,
+        token_amount: token_delta.unsigned_abs() as u64,
+        token_balance_before: pre.get(mint).copied().unwrap_or(0),
+        sol_amount: sol_delta.unsigned_abs() as u64,
+    })
+}
+
+/// Streams the signatures of successful transactions mentioning `wallet`
+/// into `sender` forever, resubscribing with backoff
+async fn stream_signatures(
+    ws_client: Arc<PubsubClient>,
+    wallet: usize,
+    address: Pubkey,
+    sender: mpsc::Sender<(usize, Signature)>,
+) {
+    let mut ws_client = Some(ws_client);
+    let mut delay = RECONNECT_DELAY;
+    loop {
+        let client = match ws_client.take() {
+            Some(client) => client,
+            None => match new_ws_client().await {
+                Ok(client) => client,
+                Err(e) => {
+                    warn!("wallet_tracker failed to reconnect websocket {:?}", e);
+                    sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                    continue;
+                }
+            },
+        };
+
+        match client
+            .logs_subscribe(
+                RpcTransactionLogsFilter::Mentions(vec![address.to_string()]),
+                RpcTransactionLogsConfig {
+                    commitment: Some(CommitmentConfig::confirmed()),
+                },
+            )
+            .await
+        {
+            Ok((mut stream, _)) => {
+                delay = RECONNECT_DELAY;
+                while let Some(logs) = stream.next().await {
+                    if logs.value.err.is_some() {
+                        continue;
+                    }
+                    let Ok(signature) = logs.value.signature.parse() else {
+                        continue;
+                    };
+                    if sender.send((wallet, signature)).await.is_err() {
+                        return;
+                    }
+                }
+                warn!("wallet_tracker logs of {} closed, reconnecting", address);
+            }
+            Err(e) => warn!("wallet_tracker failed to subscribe {} {:?}", address, e),
+        }
+
+        sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+pub(crate) async fn fetch_transaction(
+    client: &RpcClient,
+    signature: &Signature,
+) -> Result<EncodedTransactionWithStatusMeta> {
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        commitment: Some(CommitmentConfig::confirmed()),
+        max_supported_transaction_version: Some(0),
+    };
+    Ok(client
+        .get_transaction_with_config(signature, config)
+        .await?
+        .transaction)
+}
+
+/// Buys through the venue the wallet used
+async fn replay_buy(
+    client: Arc<RpcClient>,
+    payer: Arc<Keypair>,
+    swap: &WalletSwap,
+    lamports: u64,
+    config: &WalletTrackerConfig,
+) -> Result<TxOutcome> {
+    if swap.venue == "pumpfun" {
+        return buy(
+            client,
+            &payer,
+            &swap.mint,
+            lamports,
+            config.slippage,
+            config.simulate,
+        )
+        .await;
+    }
+    let pool_id = find_sol_pool(client.clone(), &swap.mint).await?;
+    get_swap_tx(
+        client,
+        &spl_token::native_mint::ID.to_string(),
+        &swap.mint.to_string(),
+        lamports_to_sol(lamports),
+        &pool_id.to_string(),
+        config.slippage,
+        payer,
+        config.simulate,
+    )
+    .await
+}
+
+/// Replays `swap` scaled to `wallet`'s settings
+pub async fn replay(
+    client: Arc<RpcClient>,
+    wallets: &Wallets,
+    wallet: &TrackedWallet,
+    swap: &WalletSwap,
+    config: &WalletTrackerConfig,
+) -> Result<Option<TxOutcome>> {
+    match swap.side {
+        Side::Buy => {
+            let lamports = ((swap.sol_amount as f64 * wallet.ratio) as u64).min(config.max_buy);
+            if lamports == 0 {
+                return Ok(None);
+            }
+            if let Some(safety_config) = &config.safety {
+                let report = safety::check(client.clone(), safety_config, &swap.mint, None).await?;
+                if !safety_config.passes(&report) {
+                    info!("not copying buy of unsafe {}", report);
+                    return Ok(None);
+                }
+            }
+            replay_buy(client, wallets.for_buy(&swap.mint), swap, lamports, config)
+                .await
+                .map(Some)
+        }
+        Side::Sell => {
+            // 按钱包卖出的比例卖出持仓
+            let Some(position) = portfolio()
+                .position(&swap.mint.to_string())
+                .filter(|position| position.is_open())
+            else {
+                return Ok(None);
+            };
+            let share = if swap.token_balance_before == 0 {
+                1.0
+            } else {
+                (swap.token_amount as f64 / swap.token_balance_before as f64).min(1.0)
+            };
+            let amount = (position.token_amount as f64 * share) as u64;
+            if amount == 0 {
+                return Ok(None);
+            }
+            sell_auto(
+                client,
+                &wallets.for_sell(&swap.mint),
+                &swap.mint,
+                amount,
+                config.slippage,
+                config.simulate,
+            )
+            .await
+            .map(Some)
+        }
+    }
+}
+
+/// Copies the swaps of the configured wallets until the subscriptions end or
+/// `shutdown` is cancelled, each copy on `actions`
+pub async fn run(
+    config: WalletTrackerConfig,
+    client: Arc<RpcClient>,
+    wallets: Arc<Wallets>,
+    shutdown: CancellationToken,
+    actions: TaskTracker,
+) {
+    let config = Arc::new(config);
+    let (sender, mut receiver) = mpsc::channel(crate::DEFAULT_CHANNEL_SIZE);
+    let mut set = JoinSet::new();
+    for (index, wallet) in config.wallets.iter().enumerate() {
+        let ws_client = match new_ws_client().await {
+            Ok(ws_client) => ws_client,
+            Err(e) => {
+                error!("wallet_tracker failed to connect {:?}", e);
+                return;
+            }
+        };
+        set.spawn(stream_signatures(
+            ws_client,
+            index,
+            wallet.wallet,
+            sender.clone(),
+        ));
+    }
+    drop(sender);
+
+    while let Some(Some((index, signature))) = shutdown.run_until_cancelled(receiver.recv()).await {
+        let (config, client, wallets) = (config.clone(), client.clone(), wallets.clone());
+        let shutdown = shutdown.clone();
+        let received_at = Instant::now();
+        // 每笔交易单独处理，不阻塞后续交易
+        actions.spawn(timeline::triggered("copy", received_at, async move {
+            let wallet = &config.wallets[index];
+            let tx = match fetch_transaction(&client, &signature).await {
+                Ok(tx) => tx,
+                Err(e) => {
+                    warn!("failed to fetch {} {:?}", signature, e);
+                    return;
+                }
+            };
+            let Some(swap) = decode_swap(&wallet.wallet, &tx) else {
+                return;
+            };
+            info!(
+                "{} {:?} {} of {} on {} for {} lamports",
+                wallet.wallet, swap.side, swap.token_amount, swap.mint, swap.venue, swap.sol_amount
+            );
+            // 延迟期间停止时不再跟单
+            if shutdown
+                .run_until_cancelled(sleep(wallet.delay))
+                .await
+                .is_none()
+            {
+                return;
+            }
+            if is_paused() {
+                info!("paused, not copying {}", swap.signature);
+                return;
+            }
+            timeline::mark_decided();
+            match replay(client, &wallets, wallet, &swap, &config).await {
+                Ok(Some(outcome)) => info!(
+                    "copied {:?} of {} {:?}",
+                    swap.side,
+                    swap.mint,
+                    outcome.signatures()
+                ),
+                Ok(None) => info!("nothing to copy for {}", swap.signature),
+                Err(e) => error!("failed to copy {} {:?}", swap.signature, e),
+            }
+        }));
+    }
+    set.shutdown().await;
+}
+
+#[test]
+fn test_decode_swap_from_balance_changes() {
+    use solana_sdk::{
+        instruction::{AccountMeta, Instruction},
+        transaction::{Transaction, VersionedTransaction},
+    };
+
+    let wallet = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+    let ata = Pubkey::new_unique();
+    let buy = Instruction::new_with_bytes(
+        program_ids().pumpfun,
+        &[0; 24],
+        vec![AccountMeta::new(ata, false)],
+    );
+    let tx = Transaction::new_with_payer(&[buy], Some(&wallet));
+    let encoded = bs64::encode(&bincode::serialize(&VersionedTransaction::from(tx)).unwrap());
+    let token_balance = |amount: &str| {
+        serde_json::json!([{
+            "accountIndex": 1,
+            "mint": mint.to_string(),
+            "uiTokenAmount": {"uiAmount": null, "decimals": 6, "amount": amount, "uiAmountString": ""},
+            "owner": wallet.to_string(),
+        }])
+    };
+    let tx = |pre: u64, post: u64, pre_tokens: &str, post_tokens: &str| {
+        serde_json::from_value::<EncodedTransactionWithStatusMeta>(serde_json::json!({
+            "transaction": [encoded, "base64"],
+            "meta": {
+                "err": null,
+                "status": {"Ok": null},
+                "fee": 5000,
+                "preBalances": [pre, 0, 1],
+                "postBalances": [post, 0, 1],
+                "preTokenBalances": token_balance(pre_tokens),
+                "postTokenBalances": token_balance(post_tokens),
+            },
+        }))
+        .unwrap()
+    };
+
+    // 花费1 SOL加手续费买入
+    let swap = decode_swap(&wallet, &tx(3_000_000_000, 1_999_995_000, "0", "500")).unwrap();
+    assert_eq!(swap.venue, "pumpfun");
+    assert_eq!(swap.side, Side::Buy);
+    assert_eq!(swap.mint, mint);
+    assert_eq!(swap.token_amount, 500);
+    assert_eq!(swap.sol_amount, 1_000_000_000);
+
+    // 卖出一半持仓
+    let swap = decode_swap(&wallet, &tx(1_000_000_000, 1_499_995_000, "500", "250")).unwrap();
+    assert_eq!(swap.side, Side::Sell);
+    assert_eq!(swap.token_amount, 250);
+    assert_eq!(swap.token_balance_before, 500);
+    assert_eq!(swap.sol_amount, 500_000_000);
+
+    // 其他钱包的交易不算
+    assert!(decode_swap(&Pubkey::new_unique(), &tx(0, 0, "0", "500")).is_none());
+    assert_eq!(
+        TrackedWallet::parse(&format!("{}:0.5:200", wallet), 0.1, Duration::ZERO).unwrap(),
+        TrackedWallet {
+            wallet,
+            ratio: 0.5,
+            delay: Duration::from_millis(200),
+        }
+    );
+}
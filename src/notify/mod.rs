@@ -0,0 +1,286 @@
+//! Notification backends for the monitors' detections.
+//!
+//! `NOTIFIERS` selects the active backends as a comma separated list of
+//! `telegram`, `discord`, `slack`, `webhook` and `stdout`. Without it Telegram
+//! is used when `TELOXIDE_TOKEN` is set, stdout otherwise, so the monitors run
+//! without a bot token.
+//!
+//! - `TELEGRAM_CHAT_ID`: chat the Telegram bot posts to, see
+//!   [`telegram`] for routing event types to several chats
+//! - `DISCORD_WEBHOOK_URL` / `SLACK_WEBHOOK_URL`: webhooks messages are posted to
+//! - `WEBHOOK_URL`: endpoint events are posted to as JSON
+//! - `NOTIFY_<BACKEND>_EVENTS`: comma separated event types a backend gets,
+//!   among `create`, `migration`, `tx_sent` and `frozen`, e.g.
+//!   `NOTIFY_DISCORD_EVENTS=migration`. Backends get every event by default.
+
+pub mod discord;
+pub mod slack;
+pub mod telegram;
+pub mod webhook;
+
+use std::{collections::HashSet, env, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use solana_sdk::native_token::lamports_to_sol;
+use tracing::error;
+
+use crate::monitor::events::{MonitorEvent, EVENT_TYPES};
+
+pub use discord::DiscordNotifier;
+pub use slack::SlackNotifier;
+pub use telegram::TelegramNotifier;
+pub use webhook::WebhookNotifier;
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, event: &MonitorEvent) -> Result<()>;
+}
+
+/// Prints every event as JSON
+pub struct StdoutNotifier;
+
+#[async_trait]
+impl Notifier for StdoutNotifier {
+    async fn notify(&self, event: &MonitorEvent) -> Result<()> {
+        println!("{}", serde_json::to_string(event)?);
+        Ok(())
+    }
+}
+
+/// Formats `event` as a plain text message
+pub fn plain_text(event: &MonitorEvent) -> String {
+    match event {
+        MonitorEvent::Create(event) => {
+            let mut text = format!(
+                "New token {} ({})\nmint: {}\ncreator: {}",
+                event.name, event.symbol, event.mint, event.creator
+            );
+            if let Some(dev_buy) = event.dev_buy {
+                text.push_str(&format!(
+                    "\ndev buy: {:.4} SOL ({:.2}% of supply)",
+                    lamports_to_sol(dev_buy.sol_cost),
+                    dev_buy.supply_pct
+                ));
+            }
+            if let Some(creator) = &event.creator_profile {
+                text.push_str(&format!("\ncreator profile: {}", creator));
+            }
+            if event.dev_alert {
+                text.push_str("\n⚠️ HIGH DEV ALLOCATION");
+            }
+            text
+        }
+        MonitorEvent::Migration(event) => format!(
+            "{} migration\ncoin: {}\npc: {}\npool: {}\nsignature: {}",
+            event.venue, event.coin_mint, event.pc_mint, event.pool_id, event.signature
+        ),
+        MonitorEvent::TxSent(event) => format!("new tx send {}", event.signature),
+        MonitorEvent::TxLanded(event) => format!(
+            "tx landed {} in slot {} after {} attempts",
+            event.signature, event.slot, event.attempts
+        ),
+        MonitorEvent::TxFailed(event) => format!(
+            "tx failed {} after {} attempts: {}",
+            event.signature, event.attempts, event.error
+        ),
+        MonitorEvent::PendingSwap(event) => format!(
+            "pending swap {:?}\npool: {}\nuser: {}\nsignature: {}",
+            event.amounts, event.pool, event.user, event.signature
+        ),
+        MonitorEvent::CurveProgress(event) => format!(
+            "curve {:.1}% sold\nmint: {}\nreserves: {:.4} SOL",
+            event.progress_pct,
+            event.mint,
+            lamports_to_sol(event.real_sol_reserves)
+        ),
+        MonitorEvent::CurveComplete(event) => format!(
+            "curve complete, migration imminent\nmint: {}\nreserves: {:.4} SOL",
+            event.mint,
+            lamports_to_sol(event.real_sol_reserves)
+        ),
+        MonitorEvent::PumpTrade(event) => format!(
+            "curve {:?} of {} tokens\nmint: {}\ntrader: {}\nsignature: {}",
+            event.side, event.token_amount, event.mint, event.trader, event.signature
+        ),
+        MonitorEvent::Signal(event) => format!(
+            "{} call in {} by {}\nmint: {}\n{}",
+            event.source, event.channel, event.author, event.mint, event.text
+        ),
+        MonitorEvent::Frozen(event) => {
+            let mut text = format!(
+                "⚠️ FROZEN, can't sell\nmint: {}\nwallet: {}\naccount: {}",
+                event.mint, event.owner, event.account
+            );
+            if let Some(authority) = &event.freeze_authority {
+                text.push_str(&format!("\nfreeze authority: {}", authority));
+            }
+            text
+        }
+    }
+}
+
+/// Forwards to a backend only the event types routed to it
+pub struct RoutedNotifier {
+    notifier: Arc<dyn Notifier>,
+    event_types: HashSet<String>,
+}
+
+impl RoutedNotifier {
+    pub fn new(notifier: Arc<dyn Notifier>, event_types: HashSet<String>) -> Self {
+        Self {
+            notifier,
+            event_types,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for RoutedNotifier {
+    async fn notify(&self, event: &MonitorEvent) -> Result<()> {
+        if !self.event_types.contains(event.event_type()) {
+            return Ok(());
+        }
+        self.notifier.notify(event).await
+    }
+}
+
+/// Parses the comma separated event types of `NOTIFY_<BACKEND>_EVENTS`
+fn parse_event_types(types: &str) -> Result<HashSet<String>> {
+    types
+        .split(',')
+        .map(str::trim)
+        .filter(|event_type| !event_type.is_empty())
+        .map(|event_type| {
+            if EVENT_TYPES.contains(&event_type) {
+                Ok(event_type.to_string())
+            } else {
+                Err(anyhow!("unknown event type {:?}", event_type))
+            }
+        })
+        .collect()
+}
+
+/// Fans events out to several notifiers
+pub struct MultiNotifier {
+    notifiers: Vec<Arc<dyn Notifier>>,
+}
+
+impl MultiNotifier {
+    pub fn new(notifiers: Vec<Arc<dyn Notifier>>) -> Self {
+        Self { notifiers }
+    }
+}
+
+#[async_trait]
+impl Notifier for MultiNotifier {
+    /// Notifies every backend, failing if any of them failed
+    async fn notify(&self, event: &MonitorEvent) -> Result<()> {
+        let mut failed = 0;
+        for notifier in &self.notifiers {
+            if let Err(e) = notifier.notify(event).await {
+                error!("notify error {:?}", e);
+                failed += 1;
+            }
+        }
+        if failed > 0 {
+            return Err(anyhow!(
+                "{} of {} notifiers failed",
+                failed,
+                self.notifiers.len()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Builds the notifiers selected by `NOTIFIERS`
+pub fn from_env() -> Result<Arc<dyn Notifier>> {
+    dotenv::dotenv().ok();
+    let names = env::var("NOTIFIERS").unwrap_or_else(|_| {
+        if env::var("TELOXIDE_TOKEN").is_ok() {
+            "telegram".to_string()
+        } else {
+            "stdout".to_string()
+        }
+    });
+
+    let mut notifiers: Vec<Arc<dyn Notifier>> = vec![];
+    for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        let notifier: Arc<dyn Notifier> = match name {
+            "telegram" => Arc::new(TelegramNotifier::from_env()?),
+            "discord" => Arc::new(DiscordNotifier::from_env()?),
+            "slack" => Arc::new(SlackNotifier::from_env()?),
+            "webhook" => Arc::new(WebhookNotifier::from_env()?),
+            "stdout" => Arc::new(StdoutNotifier),
+            _ => return Err(anyhow!("unknown notifier {:?}", name)),
+        };
+        let key = format!("NOTIFY_{}_EVENTS", name.to_uppercase());
+        notifiers.push(match env::var(&key) {
+            Ok(types) => Arc::new(RoutedNotifier::new(
+                notifier,
+                parse_event_types(&types).map_err(|e| anyhow!("invalid {}: {}", key, e))?,
+            )),
+            Err(_) => notifier,
+        });
+    }
+
+    Ok(match notifiers.len() {
+        1 => notifiers.remove(0),
+        _ => Arc::new(MultiNotifier::new(notifiers)),
+    })
+}
+
+#[tokio::test]
+async fn test_multi_notifier_reports_failures() {
+    use crate::monitor::events::TxSentEvent;
+
+    struct Failing;
+
+    #[async_trait]
+    impl Notifier for Failing {
+        async fn notify(&self, _event: &MonitorEvent) -> Result<()> {
+            Err(anyhow!("down"))
+        }
+    }
+
+    let event = MonitorEvent::TxSent(TxSentEvent {
+        signature: "sig".to_string(),
+    });
+    let ok = MultiNotifier::new(vec![Arc::new(StdoutNotifier)]);
+    assert!(ok.notify(&event).await.is_ok());
+
+    let failing = MultiNotifier::new(vec![Arc::new(Failing), Arc::new(StdoutNotifier)]);
+    assert!(failing.notify(&event).await.is_err());
+}
+
+#[tokio::test]
+async fn test_routed_notifier_filters_event_types() {
+    use std::sync::Mutex;
+
+    use crate::monitor::events::TxSentEvent;
+
+    #[derive(Default)]
+    struct Recording(Mutex<Vec<&'static str>>);
+
+    #[async_trait]
+    impl Notifier for Recording {
+        async fn notify(&self, event: &MonitorEvent) -> Result<()> {
+            self.0.lock().unwrap().push(event.event_type());
+            Ok(())
+        }
+    }
+
+    let recording = Arc::new(Recording::default());
+    let routed = RoutedNotifier::new(
+        recording.clone(),
+        parse_event_types("migration, tx_sent").unwrap(),
+    );
+    let event = MonitorEvent::TxSent(TxSentEvent {
+        signature: "sig".to_string(),
+    });
+    routed.notify(&event).await.unwrap();
+    assert_eq!(*recording.0.lock().unwrap(), vec!["tx_sent"]);
+    assert_eq!(plain_text(&event), "new tx send sig");
+    assert!(parse_event_types("create,swap").is_err());
+}
@@ -0,0 +1,42 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::json;
+use std::env;
+
+use crate::monitor::events::MonitorEvent;
+
+use super::{plain_text, Notifier};
+
+/// Posts every event as a message to the Slack incoming webhook `SLACK_WEBHOOK_URL`
+pub struct SlackNotifier {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl SlackNotifier {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let url =
+            env::var("SLACK_WEBHOOK_URL").map_err(|_| anyhow!("SLACK_WEBHOOK_URL is not set"))?;
+        Ok(Self::new(url))
+    }
+}
+
+#[async_trait]
+impl Notifier for SlackNotifier {
+    async fn notify(&self, event: &MonitorEvent) -> Result<()> {
+        self.client
+            .post(&self.url)
+            .json(&json!({ "text": plain_text(event) }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
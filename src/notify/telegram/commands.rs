@@ -0,0 +1,418 @@
+//! Remote control through Telegram commands.
+//!
+//! Commands are only answered in the allowed chats, others are ignored.
+//! Trades and pause/resume go through the engine's action channel; it is off
+//! unless `TELEGRAM_COMMANDS_ENABLED=true`.
+//!
+//! - `TELEGRAM_ALLOWED_CHATS`: comma separated chat ids, defaults to
+//!   `TELEGRAM_CHAT_ID`
+//!
+//! `/buy <mint> <sol>`, `/sell <mint> <pct>`, `/positions`, `/stats`,
+//! `/price <mint>`, `/info <mint>`, `/pause`, `/resume`, `/config`,
+//! `/export [csv|json]` and `/set <strategy>.<param> <value>` are understood,
+//! `/help` lists them. See [`crate::strategy::params`] for the parameters
+//! `/set` changes.
+
+use std::{
+    collections::HashSet,
+    env,
+    fmt::Write,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    native_token::{lamports_to_sol, sol_to_lamports},
+    pubkey::Pubkey,
+};
+use teloxide::{
+    dispatching::{HandlerExt, UpdateFilterExt},
+    dptree,
+    prelude::{Dispatcher, Requester},
+    types::{ChatId, InputFile, Message, Update},
+    utils::command::BotCommands,
+    Bot, RequestError,
+};
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+use crate::{
+    analysis::holders,
+    config::bot_config,
+    engine::{is_paused, Action, ActionRequest},
+    fees::jito_tips::jito_tip,
+    marketdata::{market_data, sparkline, Resolution},
+    portfolio::{portfolio, quote::pnl_report},
+    safety::liquidity_vault,
+    storage::{storage, write_journal, JournalFormat},
+    strategy::{params::live_params, parse_env},
+    tx::simulate::TxOutcome,
+};
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// Windows `/price` summarizes
+const PRICE_WINDOWS: [(&str, u64); 3] = [("1m", 60), ("5m", 5 * 60), ("15m", 15 * 60)];
+
+/// 1m closes in the `/price` sparkline
+const SPARKLINE_CANDLES: usize = 15;
+
+#[derive(BotCommands, Debug, Clone, PartialEq)]
+#[command(rename_rule = "lowercase", description = "Commands:")]
+pub enum Command {
+    #[command(description = "list the commands")]
+    Help,
+    #[command(description = "<mint> <sol>: buy a token", parse_with = "split")]
+    Buy { mint: Pubkey, sol: f64 },
+    #[command(
+        description = "<mint> <pct>: sell a share of the balance",
+        parse_with = "split"
+    )]
+    Sell { mint: Pubkey, pct: f64 },
+    #[command(description = "<mint>: sell the whole balance and close the account")]
+    Dump { mint: Pubkey },
+    #[command(description = "open positions and their pnl")]
+    Positions,
+    #[command(description = "trading and detection stats")]
+    Stats,
+    #[command(description = "<mint>: recent price action of a tracked token")]
+    Price { mint: Pubkey },
+    #[command(description = "<mint>: holder distribution of a token")]
+    Info { mint: Pubkey },
+    #[command(description = "pause the automatic strategies")]
+    Pause,
+    #[command(description = "resume the automatic strategies")]
+    Resume,
+    #[command(description = "show the running config")]
+    Config,
+    #[command(
+        description = "<strategy>.<param> <value>: change a strategy parameter",
+        parse_with = "split"
+    )]
+    Set { key: String, value: String },
+    #[command(description = "[csv|json]: trade journal of the stored fills")]
+    Export { format: String },
+}
+
+impl Command {
+    /// The engine action of the command, if it has one
+    fn action(&self) -> Option<Action> {
+        match self {
+            Command::Buy { mint, sol } => Some(Action::Buy {
+                mint: *mint,
+                lamports: sol_to_lamports(*sol),
+            }),
+            Command::Sell { mint, pct } => Some(Action::Sell {
+                mint: *mint,
+                pct: *pct,
+            }),
+            Command::Dump { mint } => Some(Action::Dump { mint: *mint }),
+            Command::Pause => Some(Action::Pause),
+            Command::Resume => Some(Action::Resume),
+            Command::Help
+            | Command::Positions
+            | Command::Stats
+            | Command::Price { .. }
+            | Command::Info { .. }
+            | Command::Config
+            | Command::Set { .. }
+            | Command::Export { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandsConfig {
+    pub allowed_chats: HashSet<ChatId>,
+}
+
+/// Parses comma separated chat ids
+fn parse_chats(chats: &str) -> Result<HashSet<ChatId>> {
+    chats
+        .split(',')
+        .map(str::trim)
+        .filter(|chat| !chat.is_empty())
+        .map(|chat| {
+            chat.parse()
+                .map(ChatId)
+                .map_err(|_| anyhow!("invalid chat id {:?}", chat))
+        })
+        .collect()
+}
+
+impl CommandsConfig {
+    /// Reads `TELEGRAM_ALLOWED_CHATS`, `None` unless `TELEGRAM_COMMANDS_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("TELEGRAM_COMMANDS_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        let chats = env::var("TELEGRAM_ALLOWED_CHATS")
+            .or_else(|_| env::var("TELEGRAM_CHAT_ID"))
+            .unwrap_or_default();
+        let allowed_chats = parse_chats(&chats)?;
+        if allowed_chats.is_empty() {
+            return Err(anyhow!(
+                "TELEGRAM_COMMANDS_ENABLED needs TELEGRAM_ALLOWED_CHATS"
+            ));
+        }
+        Ok(Some(Self { allowed_chats }))
+    }
+}
+
+fn format_outcome(outcome: Option<TxOutcome>) -> String {
+    match outcome {
+        Some(TxOutcome::Sent(signatures)) => {
+            let signatures: Vec<String> = signatures.iter().map(|s| s.to_string()).collect();
+            format!("sent {}", signatures.join(", "))
+        }
+        Some(TxOutcome::Simulated(summary)) => format!("simulated: {}", summary),
+        Some(TxOutcome::Paper(signature)) => format!("paper traded {}", signature),
+        None => "done".to_string(),
+    }
+}
+
+async fn positions_text(client: Arc<RpcClient>) -> String {
+    let report = pnl_report(client, portfolio()).await;
+    if report.is_empty() {
+        return "no open positions".to_string();
+    }
+    let mut text = String::new();
+    for (position, pnl) in report {
+        let _ = match pnl {
+            Ok(pnl) => writeln!(text, "{}", pnl),
+            Err(e) => writeln!(text, "{}: failed to quote {}", position.mint, e),
+        };
+    }
+    let _ = write!(
+        text,
+        "realized {:+.4} SOL",
+        portfolio().realized_pnl() as f64 / 1e9
+    );
+    text
+}
+
+fn stats_text() -> String {
+    let Some(storage) = storage() else {
+        return "storage is not enabled".to_string();
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let mut text = String::new();
+    for (period, since) in [("24h", now.saturating_sub(DAY_SECS)), ("all time", 0)] {
+        let _ = match storage.stats(since) {
+            Ok(stats) => writeln!(text, "{}: {}", period, stats),
+            Err(e) => writeln!(text, "{}: failed to query {}", period, e),
+        };
+    }
+    let mut pnl: Vec<_> = portfolio().realized_pnl_by_strategy().into_iter().collect();
+    pnl.sort();
+    for (strategy, pnl) in pnl {
+        let _ = writeln!(text, "{} realized {:+.4} SOL", strategy, pnl as f64 / 1e9);
+    }
+    text.trim_end().to_string()
+}
+
+fn price_text(mint: &Pubkey) -> String {
+    let Some(market_data) = market_data() else {
+        return "market data is not enabled".to_string();
+    };
+    // 请求的代币开始记录，下次查询可用
+    if !market_data.is_tracked(mint) {
+        market_data.track(*mint);
+        return format!("now tracking {}, no trades yet", mint);
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let Some(latest) = market_data.summary(mint, PRICE_WINDOWS[0].1, now) else {
+        return format!("no trades of {} in the last minute", mint);
+    };
+    let mut text = format!("{}\nprice {:.10} SOL", mint, latest.price);
+    for (period, window) in PRICE_WINDOWS {
+        if let Some(summary) = market_data.summary(mint, window, now) {
+            let _ = write!(text, "\n{}: {}", period, summary);
+        }
+    }
+    let closes: Vec<f64> = market_data
+        .candles(mint, Resolution::M1)
+        .iter()
+        .rev()
+        .take(SPARKLINE_CANDLES)
+        .rev()
+        .map(|candle| candle.close)
+        .collect();
+    if closes.len() > 1 {
+        let _ = write!(text, "\n{}", sparkline(&closes));
+    }
+    text
+}
+
+async fn info_text(client: Arc<RpcClient>, mint: &Pubkey) -> String {
+    // 记录过创建事件的代币才知道开发者
+    let creator = storage()
+        .and_then(|storage| storage.creator(&mint.to_string()).ok().flatten())
+        .and_then(|creator| creator.parse::<Pubkey>().ok());
+    let vault = match liquidity_vault(&client, mint).await {
+        Ok(vault) => vault,
+        Err(e) => return format!("failed to find the liquidity of {}: {}", mint, e),
+    };
+    match holders::analyze(&client, mint, vault, creator.as_ref(), true).await {
+        Ok(stats) => {
+            let mut text = format!("{}\n{}", mint, stats);
+            if let Some(creator) = creator {
+                let _ = write!(text, "\ncreator {}", creator);
+            }
+            text
+        }
+        Err(e) => format!("failed to analyze the holders of {}: {}", mint, e),
+    }
+}
+
+fn set_text(key: &str, value: &str) -> String {
+    let Some(params) = live_params() else {
+        return "no strategy parameters to change".to_string();
+    };
+    match params.set(key, value) {
+        Ok(()) => format!("{} = {}", key, value),
+        Err(e) => format!("failed: {}", e),
+    }
+}
+
+fn config_text() -> String {
+    format!(
+        "{:?}\npaused: {}\njito tip: {:.6} SOL",
+        bot_config(),
+        is_paused(),
+        lamports_to_sol(jito_tip())
+    )
+}
+
+/// The trade journal as a file named after its format
+fn journal_file(format: &str) -> Result<InputFile> {
+    let format: JournalFormat = match format.trim() {
+        "" => JournalFormat::default(),
+        format => format.parse()?,
+    };
+    let storage = storage().ok_or(anyhow!("storage is not enabled"))?;
+    let journal = write_journal(&storage.fills()?, format)?;
+    Ok(InputFile::memory(journal.into_bytes()).file_name(format!("trades.{}", format)))
+}
+
+async fn answer(
+    bot: Bot,
+    msg: Message,
+    command: Command,
+    actions: mpsc::Sender<ActionRequest>,
+    client: Arc<RpcClient>,
+) -> Result<(), RequestError> {
+    if let Command::Export { format } = &command {
+        match journal_file(format) {
+            Ok(file) => bot.send_document(msg.chat.id, file).await?,
+            Err(e) => {
+                bot.send_message(msg.chat.id, format!("failed: {}", e))
+                    .await?
+            }
+        };
+        return Ok(());
+    }
+    let text = match command.action() {
+        Some(action) => {
+            let (reply, result) = oneshot::channel();
+            if actions.send(ActionRequest { action, reply }).await.is_err() {
+                "engine is not running".to_string()
+            } else {
+                match result.await {
+                    Ok(Ok(outcome)) => format_outcome(outcome),
+                    Ok(Err(e)) => format!("failed: {}", e),
+                    Err(_) => "engine dropped the request".to_string(),
+                }
+            }
+        }
+        None => match command {
+            Command::Positions => positions_text(client).await,
+            Command::Stats => stats_text(),
+            Command::Price { mint } => price_text(&mint),
+            Command::Info { mint } => info_text(client, &mint).await,
+            Command::Config => config_text(),
+            Command::Set { key, value } => set_text(&key, &value),
+            _ => Command::descriptions().to_string(),
+        },
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+/// Answers commands from the allowed chats until the bot is stopped or
+/// `shutdown` is cancelled
+pub async fn run(
+    config: CommandsConfig,
+    bot: Bot,
+    actions: mpsc::Sender<ActionRequest>,
+    client: Arc<RpcClient>,
+    shutdown: CancellationToken,
+) {
+    let allowed_chats = Arc::new(config.allowed_chats);
+    let handler = Update::filter_message()
+        .filter(move |msg: Message| {
+            let allowed = allowed_chats.contains(&msg.chat.id);
+            if !allowed {
+                warn!("ignoring message from chat {}", msg.chat.id);
+            }
+            allowed
+        })
+        .filter_command::<Command>()
+        .endpoint(answer);
+    let mut dispatcher = Dispatcher::builder(bot, handler)
+        .dependencies(dptree::deps![actions, client])
+        .build();
+    shutdown.run_until_cancelled(dispatcher.dispatch()).await;
+}
+
+#[test]
+fn test_parse_commands() {
+    let mint = Pubkey::new_unique();
+    assert_eq!(
+        Command::parse(&format!("/buy {} 0.5", mint), "bot").unwrap(),
+        Command::Buy { mint, sol: 0.5 }
+    );
+    let sell = Command::parse(&format!("/sell {} 50", mint), "bot").unwrap();
+    assert_eq!(sell.action(), Some(Action::Sell { mint, pct: 50.0 }));
+    let dump = Command::parse(&format!("/dump {}", mint), "bot").unwrap();
+    assert_eq!(dump.action(), Some(Action::Dump { mint }));
+    let price = Command::parse(&format!("/price {}", mint), "bot").unwrap();
+    assert_eq!(price.action(), None);
+    let info = Command::parse(&format!("/info {}", mint), "bot").unwrap();
+    assert_eq!(info, Command::Info { mint });
+    assert_eq!(info.action(), None);
+    assert!(Command::parse("/buy notamint 1", "bot").is_err());
+    assert_eq!(
+        Command::parse("/export", "bot").unwrap(),
+        Command::Export {
+            format: String::new()
+        }
+    );
+    assert_eq!(
+        Command::parse("/set sniper.buy_sol 0.05", "bot").unwrap(),
+        Command::Set {
+            key: "sniper.buy_sol".to_string(),
+            value: "0.05".to_string(),
+        }
+    );
+    assert_eq!(
+        Command::parse("/pause", "bot").unwrap().action(),
+        Some(Action::Pause)
+    );
+
+    assert_eq!(
+        parse_chats("1, -100200").unwrap(),
+        HashSet::from([ChatId(1), ChatId(-100200)])
+    );
+    assert!(parse_chats("abc").is_err());
+}
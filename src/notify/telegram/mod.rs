@@ -0,0 +1,305 @@
+//! Telegram notifications.
+//!
+//! Events go to `TELEGRAM_CHAT_ID`, or are routed per event type with
+//! `TELEGRAM_ROUTES`: `;` separated `<chat id>=<event types>` entries, the
+//! event types comma separated, e.g.
+//! `TELEGRAM_ROUTES=-1001=create;-1002=migration;42=tx_landed,tx_failed`. A
+//! chat without `=` gets every event. Monitor diagnostics always go to
+//! `TELEGRAM_CHAT_ID`.
+
+pub mod commands;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use std::{
+    collections::HashSet,
+    env,
+    future::Future,
+    time::{Duration, Instant},
+};
+use teloxide::{
+    payloads::SendMessageSetters,
+    prelude::Requester,
+    types::{ChatId, ParseMode},
+    Bot,
+};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+use crate::{
+    config::message_templates,
+    constants::curve::TOKEN_DECIMALS,
+    monitor::{
+        diagnostics,
+        events::{MigrationEvent, MonitorEvent, TokenCreateEvent},
+        markdown,
+    },
+    strategy::parse_env,
+};
+
+use super::{parse_event_types, plain_text, Notifier};
+
+/// Shortest time between two diagnostic messages
+const DIAGNOSTIC_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A chat and the event types it gets, every type if `None`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatRoute {
+    pub chat_id: ChatId,
+    pub event_types: Option<HashSet<String>>,
+}
+
+impl ChatRoute {
+    fn accepts(&self, event_type: &str) -> bool {
+        self.event_types
+            .as_ref()
+            .is_none_or(|event_types| event_types.contains(event_type))
+    }
+}
+
+/// Parses the `;` separated `<chat id>=<event types>` entries of
+/// `TELEGRAM_ROUTES`
+fn parse_routes(routes: &str) -> Result<Vec<ChatRoute>> {
+    routes
+        .split(';')
+        .map(str::trim)
+        .filter(|route| !route.is_empty())
+        .map(|route| {
+            let (chat, event_types) = match route.split_once('=') {
+                Some((chat, event_types)) => (chat, Some(parse_event_types(event_types)?)),
+                None => (route, None),
+            };
+            let chat_id = chat
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("invalid chat id {:?}", chat))?;
+            Ok(ChatRoute {
+                chat_id: ChatId(chat_id),
+                event_types,
+            })
+        })
+        .collect()
+}
+
+/// `TELEGRAM_CHAT_ID`
+fn chat_id_from_env() -> Result<ChatId> {
+    let chat_id =
+        env::var("TELEGRAM_CHAT_ID").map_err(|_| anyhow!("TELEGRAM_CHAT_ID is not set"))?;
+    let chat_id = chat_id
+        .parse()
+        .map_err(|_| anyhow!("invalid TELEGRAM_CHAT_ID {:?}", chat_id))?;
+    Ok(ChatId(chat_id))
+}
+
+/// Sends events to Telegram chats as MarkdownV2 messages
+pub struct TelegramNotifier {
+    bot: Bot,
+    routes: Vec<ChatRoute>,
+}
+
+impl TelegramNotifier {
+    /// Sends every event to `chat_id`
+    pub fn new(bot: Bot, chat_id: ChatId) -> Self {
+        Self::with_routes(
+            bot,
+            vec![ChatRoute {
+                chat_id,
+                event_types: None,
+            }],
+        )
+    }
+
+    pub fn with_routes(bot: Bot, routes: Vec<ChatRoute>) -> Self {
+        Self { bot, routes }
+    }
+
+    /// Uses `TELOXIDE_TOKEN`, and `TELEGRAM_ROUTES` or else `TELEGRAM_CHAT_ID`
+    pub fn from_env() -> Result<Self> {
+        let routes = match env::var("TELEGRAM_ROUTES") {
+            Ok(routes) => {
+                parse_routes(&routes).map_err(|e| anyhow!("invalid TELEGRAM_ROUTES: {}", e))?
+            }
+            Err(_) => return Ok(Self::new(Bot::from_env(), chat_id_from_env()?)),
+        };
+        if routes.is_empty() {
+            return Err(anyhow!("TELEGRAM_ROUTES has no chats"));
+        }
+        Ok(Self::with_routes(Bot::from_env(), routes))
+    }
+
+    /// Notifier for the monitor diagnostics, `None` unless
+    /// `TELEGRAM_DIAGNOSTICS_ENABLED=true`
+    pub fn diagnostics_from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("TELEGRAM_DIAGNOSTICS_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        Ok(Some(Self::new(Bot::from_env(), chat_id_from_env()?)))
+    }
+
+    /// Chats `event_type` is routed to
+    fn chats(&self, event_type: &str) -> Vec<ChatId> {
+        self.routes
+            .iter()
+            .filter(|route| route.accepts(event_type))
+            .map(|route| route.chat_id)
+            .collect()
+    }
+
+    /// Sends the monitor diagnostics to the chat, at most one per interval
+    ///
+    /// Subscribes right away, so nothing reported after the call is missed.
+    pub fn forward_diagnostics(self) -> impl Future<Output = ()> + Send {
+        let mut receiver = diagnostics::subscribe();
+        async move {
+            let mut last_sent: Option<Instant> = None;
+            let mut suppressed = 0;
+            loop {
+                let diagnostic = match receiver.recv().await {
+                    Ok(diagnostic) => diagnostic,
+                    Err(RecvError::Lagged(n)) => {
+                        suppressed += n;
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                // 限流，避免一个坏区块刷屏
+                if last_sent.is_some_and(|sent| sent.elapsed() < DIAGNOSTIC_INTERVAL) {
+                    suppressed += 1;
+                    continue;
+                }
+                let mut text = format!("⚠️ {}", diagnostic);
+                if suppressed > 0 {
+                    text.push_str(&format!("\n({} more since the last report)", suppressed));
+                }
+                suppressed = 0;
+                last_sent = Some(Instant::now());
+                for route in &self.routes {
+                    if let Err(e) = self.bot.send_message(route.chat_id, text.clone()).await {
+                        warn!("failed to send diagnostic {:?}", e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for TelegramNotifier {
+    /// Sends `event` to every chat it is routed to, failing if any send failed
+    async fn notify(&self, event: &MonitorEvent) -> Result<()> {
+        let chats = self.chats(event.event_type());
+        if chats.is_empty() {
+            return Ok(());
+        }
+        let text = match event {
+            MonitorEvent::Create(event) => format_create_event(event),
+            MonitorEvent::Migration(event) => format_migration_event(event),
+            event @ (MonitorEvent::TxSent(_)
+            | MonitorEvent::TxLanded(_)
+            | MonitorEvent::TxFailed(_)
+            | MonitorEvent::PendingSwap(_)
+            | MonitorEvent::CurveProgress(_)
+            | MonitorEvent::CurveComplete(_)
+            | MonitorEvent::PumpTrade(_)
+            | MonitorEvent::Signal(_)
+            | MonitorEvent::Frozen(_)) => markdown::escape_markdown_v2(&plain_text(event)),
+        };
+        let mut failed = 0;
+        for chat_id in &chats {
+            if let Err(e) = self
+                .bot
+                .send_message(*chat_id, text.clone())
+                .parse_mode(ParseMode::MarkdownV2)
+                .await
+            {
+                warn!("failed to notify chat {} {:?}", chat_id, e);
+                failed += 1;
+            }
+        }
+        if failed > 0 {
+            return Err(anyhow!("{} of {} chats failed", failed, chats.len()));
+        }
+        Ok(())
+    }
+}
+
+fn format_create_event(event: &TokenCreateEvent) -> String {
+    // dev 首次买入
+    let dev_buy = event.dev_buy;
+    let dev_buy_pct = dev_buy.map_or(0.0, |b| b.supply_pct);
+    let dev_buy_sol = dev_buy.map_or(0, |b| b.sol_cost);
+    let dev_buy_tokens = dev_buy.map_or(0, |b| b.token_amount);
+    let alert = if event.dev_alert {
+        "⚠️ HIGH DEV ALLOCATION\n"
+    } else {
+        ""
+    };
+
+    let fields = [
+        ("signature", event.signature.clone()),
+        ("name", event.name.clone()),
+        ("symbol", event.symbol.clone()),
+        ("uri", event.uri.clone()),
+        ("mint", event.mint.clone()),
+        ("bonding_curve", event.bonding_curve.clone()),
+        (
+            "associated_bonding_curve",
+            event.associated_bonding_curve.clone(),
+        ),
+        ("creator", event.creator.clone()),
+        // 旧模板里的占位符名
+        ("user", event.creator.clone()),
+        (
+            "dev_buy_sol",
+            format!("{:.4}", dev_buy_sol as f64 / LAMPORTS_PER_SOL as f64),
+        ),
+        (
+            "dev_buy_tokens",
+            format!(
+                "{:.0}",
+                dev_buy_tokens as f64 / 10f64.powi(TOKEN_DECIMALS as i32)
+            ),
+        ),
+        ("dev_buy_pct", format!("{:.2}%", dev_buy_pct)),
+        (
+            "creator_profile",
+            event
+                .creator_profile
+                .as_ref()
+                .map_or("unknown".to_string(), |creator| creator.to_string()),
+        ),
+        ("alert", alert.to_string()),
+    ];
+    markdown::render(&message_templates().create, &fields)
+}
+
+fn format_migration_event(event: &MigrationEvent) -> String {
+    let fields = [
+        ("venue", event.venue.to_string()),
+        ("signature", event.signature.clone()),
+        ("coin_mint", event.coin_mint.clone()),
+        ("pc_mint", event.pc_mint.clone()),
+        ("pool_id", event.pool_id.clone()),
+        // 旧模板里的占位符名
+        ("coin_token", event.coin_mint.clone()),
+        ("pc_token", event.pc_mint.clone()),
+        ("liquidity_address", event.pool_id.clone()),
+    ];
+    markdown::render(&message_templates().migration, &fields)
+}
+
+#[test]
+fn test_parse_routes() {
+    let routes = parse_routes("-1001=create; -1002=migration;42=tx_landed,tx_failed;7").unwrap();
+    assert_eq!(routes.len(), 4);
+    let notifier = TelegramNotifier::with_routes(Bot::new("token"), routes);
+    assert_eq!(notifier.chats("create"), vec![ChatId(-1001), ChatId(7)]);
+    assert_eq!(notifier.chats("tx_failed"), vec![ChatId(42), ChatId(7)]);
+    assert_eq!(notifier.chats("pending_swap"), vec![ChatId(7)]);
+
+    assert!(parse_routes("chat=create").is_err());
+    assert!(parse_routes("1=swap").is_err());
+    assert!(parse_routes(" ; ").unwrap().is_empty());
+}
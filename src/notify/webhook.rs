@@ -0,0 +1,40 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::env;
+
+use crate::monitor::events::MonitorEvent;
+
+use super::Notifier;
+
+/// Posts every event as JSON to `WEBHOOK_URL`
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let url = env::var("WEBHOOK_URL").map_err(|_| anyhow!("WEBHOOK_URL is not set"))?;
+        Ok(Self::new(url))
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, event: &MonitorEvent) -> Result<()> {
+        self.client
+            .post(&self.url)
+            .json(event)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
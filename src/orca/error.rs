@@ -0,0 +1,21 @@
+use solana_sdk::pubkey::Pubkey;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum OrcaError {
+    #[error("{pubkey} is not a whirlpool account")]
+    InvalidWhirlpool { pubkey: Pubkey },
+    #[error("{token_in} -> {token_out} doesn't match whirlpool mints a {mint_a} / b {mint_b}")]
+    PoolMintMismatch {
+        token_in: Pubkey,
+        token_out: Pubkey,
+        mint_a: Pubkey,
+        mint_b: Pubkey,
+    },
+    #[error("no whirlpool pairs {mint_a} with {mint_b}")]
+    PoolNotFound { mint_a: Pubkey, mint_b: Pubkey },
+    #[error("whirlpool has no liquidity at the current price")]
+    NoLiquidity,
+    #[error("swap moves the sqrt price to {sqrt_price}, outside the supported range")]
+    PriceOutOfRange { sqrt_price: u128 },
+}
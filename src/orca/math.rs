@@ -0,0 +1,102 @@
+//! Exact input quotes on the whirlpool sqrt price curve
+
+use anyhow::Result;
+use num_bigint::BigUint;
+
+use crate::orca::error::OrcaError;
+
+/// Lowest sqrt price the program accepts, Q64.64
+pub const MIN_SQRT_PRICE: u128 = 4_295_048_016;
+/// Highest sqrt price the program accepts, Q64.64
+pub const MAX_SQRT_PRICE: u128 = 79_226_673_515_401_279_992_447_579_055;
+
+const FEE_RATE_DENOMINATOR: u64 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapQuote {
+    pub amount_in: u64,
+    /// Part of `amount_in` taken as fee
+    pub fee: u64,
+    pub estimated_amount_out: u64,
+    pub sqrt_price_after: u128,
+}
+
+fn to_u128(value: BigUint) -> Result<u128> {
+    u128::try_from(value).map_err(|e| e.into())
+}
+
+/// Quotes swapping exactly `amount_in` against the current liquidity
+///
+/// Assumes the swap stays within the current tick range. That holds for the
+/// sizes the bot trades; a swap that crosses ticks fills at a different price
+/// and is bounded by the slippage threshold instead.
+pub fn quote_exact_in(
+    sqrt_price: u128,
+    liquidity: u128,
+    fee_rate: u16,
+    amount_in: u64,
+    a_to_b: bool,
+) -> Result<SwapQuote> {
+    if liquidity == 0 {
+        return Err(OrcaError::NoLiquidity.into());
+    }
+    // 手续费向上取整
+    let fee = (amount_in as u128 * fee_rate as u128).div_ceil(FEE_RATE_DENOMINATOR as u128) as u64;
+    let amount = BigUint::from(amount_in - fee);
+
+    let q64 = BigUint::from(1u8) << 64;
+    let l = BigUint::from(liquidity);
+    let sqrt = BigUint::from(sqrt_price);
+    let (sqrt_price_after, amount_out) = if a_to_b {
+        // 价格下降: next = L * sqrt / (L + amount * sqrt)，向上取整
+        let numerator: BigUint = &l * &sqrt * &q64;
+        let denominator: BigUint = &l * &q64 + &amount * &sqrt;
+        let next = to_u128((numerator + &denominator - 1u8) / denominator)?;
+        let out = &l * BigUint::from(sqrt_price - next) / &q64;
+        (next, out)
+    } else {
+        // 价格上升: next = sqrt + amount / L，向下取整
+        let next = sqrt_price
+            .checked_add(to_u128(&amount * &q64 / &l)?)
+            .ok_or(OrcaError::PriceOutOfRange {
+                sqrt_price: u128::MAX,
+            })?;
+        let out = &l * &q64 * BigUint::from(next - sqrt_price) / (BigUint::from(next) * &sqrt);
+        (next, out)
+    };
+    if !(MIN_SQRT_PRICE..=MAX_SQRT_PRICE).contains(&sqrt_price_after) {
+        return Err(OrcaError::PriceOutOfRange {
+            sqrt_price: sqrt_price_after,
+        }
+        .into());
+    }
+
+    Ok(SwapQuote {
+        amount_in,
+        fee,
+        estimated_amount_out: u64::try_from(amount_out)?,
+        sqrt_price_after,
+    })
+}
+
+#[test]
+fn test_quote_exact_in_matches_constant_product() {
+    // 价格为1，流动性1e12，相当于两边各1e12的恒定乘积池
+    let sqrt_price = 1u128 << 64;
+    let liquidity = 1_000_000_000_000;
+
+    let a_to_b = quote_exact_in(sqrt_price, liquidity, 3000, 1_000_000_000, true).unwrap();
+    assert_eq!(a_to_b.fee, 3_000_000);
+    // x * y = k: out = y * in / (x + in)
+    let expected = liquidity * 997_000_000 / (liquidity + 997_000_000);
+    assert!(a_to_b.estimated_amount_out as u128 <= expected);
+    assert!(a_to_b.estimated_amount_out as u128 + 1 >= expected);
+    assert!(a_to_b.sqrt_price_after < sqrt_price);
+
+    let b_to_a = quote_exact_in(sqrt_price, liquidity, 3000, 1_000_000_000, false).unwrap();
+    assert!(b_to_a.estimated_amount_out as u128 <= expected);
+    assert!(b_to_a.estimated_amount_out as u128 + 1 >= expected);
+    assert!(b_to_a.sqrt_price_after > sqrt_price);
+
+    assert!(quote_exact_in(sqrt_price, 0, 3000, 1, true).is_err());
+}
@@ -0,0 +1,6 @@
+pub mod error;
+pub mod math;
+pub mod pools;
+pub mod state;
+pub mod swap;
+pub mod swap_instructions;
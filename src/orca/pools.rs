@@ -0,0 +1,130 @@
+//! Whirlpools pairing a mint with WSOL.
+//!
+//! A mint can have a whirlpool per tick spacing; the one with the most
+//! liquidity at the current price is used and its address cached, its state
+//! is fetched again on every lookup.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use anyhow::Result;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    config::program_ids,
+    orca::{
+        error::OrcaError,
+        state::{Whirlpool, WHIRLPOOL_LEN},
+        swap::get_whirlpool,
+    },
+};
+
+/// Offset of `token_mint_a` in a whirlpool account
+const TOKEN_MINT_A_OFFSET: usize = 101;
+/// Offset of `token_mint_b` in a whirlpool account
+const TOKEN_MINT_B_OFFSET: usize = 181;
+
+/// mint -> WSOL whirlpool
+static KNOWN_POOLS: LazyLock<RwLock<HashMap<Pubkey, Pubkey>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Program account filters matching whirlpools of `mint_a` / `mint_b`
+fn pool_filters(mint_a: &Pubkey, mint_b: &Pubkey) -> Vec<RpcFilterType> {
+    vec![
+        RpcFilterType::DataSize(WHIRLPOOL_LEN as u64),
+        RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            TOKEN_MINT_A_OFFSET,
+            mint_a.as_ref(),
+        )),
+        RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            TOKEN_MINT_B_OFFSET,
+            mint_b.as_ref(),
+        )),
+    ]
+}
+
+/// Most liquid whirlpool pairing `mint_a` with `mint_b`
+pub async fn find_pool_by_mints(
+    client: &RpcClient,
+    mint_a: &Pubkey,
+    mint_b: &Pubkey,
+) -> Result<(Pubkey, Whirlpool)> {
+    // 池子的mint a总是按字节序较小的那个
+    let (mint_a, mint_b) = if mint_a < mint_b {
+        (mint_a, mint_b)
+    } else {
+        (mint_b, mint_a)
+    };
+    let config = RpcProgramAccountsConfig {
+        filters: Some(pool_filters(mint_a, mint_b)),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let pools = client
+        .get_program_accounts_with_config(&program_ids().orca_whirlpool, config)
+        .await?;
+    pools
+        .iter()
+        .filter_map(|(pubkey, account)| {
+            Whirlpool::unpack(pubkey, &account.data)
+                .ok()
+                .map(|pool| (*pubkey, pool))
+        })
+        .max_by_key(|(_, pool)| pool.liquidity)
+        .ok_or_else(|| {
+            OrcaError::PoolNotFound {
+                mint_a: *mint_a,
+                mint_b: *mint_b,
+            }
+            .into()
+        })
+}
+
+/// Finds the whirlpool pairing `mint` with WSOL, with its current state
+pub async fn find_sol_pool(client: Arc<RpcClient>, mint: &Pubkey) -> Result<(Pubkey, Whirlpool)> {
+    let known = KNOWN_POOLS.read().unwrap().get(mint).copied();
+    if let Some(pool_id) = known {
+        let pool = get_whirlpool(client, &pool_id).await?;
+        return Ok((pool_id, pool));
+    }
+    let (pool_id, pool) = find_pool_by_mints(&client, mint, &spl_token::native_mint::ID).await?;
+    KNOWN_POOLS.write().unwrap().insert(*mint, pool_id);
+    Ok((pool_id, pool))
+}
+
+#[test]
+fn test_pool_filters_match_whirlpool_layout() {
+    use crate::orca::state::WHIRLPOOL_DISCRIMINATOR;
+
+    let mint_a = Pubkey::new_unique();
+    let mint_b = Pubkey::new_unique();
+    let mut data = WHIRLPOOL_DISCRIMINATOR.to_vec();
+    data.resize(WHIRLPOOL_LEN, 0);
+    data[TOKEN_MINT_A_OFFSET..][..32].copy_from_slice(mint_a.as_ref());
+    data[TOKEN_MINT_B_OFFSET..][..32].copy_from_slice(mint_b.as_ref());
+
+    let pool = Whirlpool::unpack(&Pubkey::new_unique(), &data).unwrap();
+    assert_eq!(pool.token_mint_a, mint_a);
+    assert_eq!(pool.token_mint_b, mint_b);
+
+    let matches = |filters: Vec<RpcFilterType>| {
+        filters.iter().all(|filter| match filter {
+            RpcFilterType::DataSize(size) => *size == data.len() as u64,
+            RpcFilterType::Memcmp(memcmp) => memcmp.bytes_match(&data),
+            _ => false,
+        })
+    };
+    assert!(matches(pool_filters(&mint_a, &mint_b)));
+    assert!(!matches(pool_filters(&mint_b, &mint_a)));
+}
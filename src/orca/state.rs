@@ -0,0 +1,140 @@
+//! Whirlpool account layout and the PDAs a swap needs
+
+use anyhow::Result;
+use borsh::BorshDeserialize;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::orca::error::OrcaError;
+
+/// Anchor discriminator of `Whirlpool` accounts
+pub const WHIRLPOOL_DISCRIMINATOR: [u8; 8] = [63, 149, 209, 12, 225, 128, 99, 9];
+
+/// Size of a `Whirlpool` account
+pub const WHIRLPOOL_LEN: usize = 653;
+
+/// Ticks per tick array account
+pub const TICK_ARRAY_SIZE: i32 = 88;
+
+#[derive(Debug, Clone, Copy, BorshDeserialize)]
+pub struct WhirlpoolRewardInfo {
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub emissions_per_second_x64: u128,
+    pub growth_global_x64: u128,
+}
+
+/// A concentrated liquidity pool
+#[derive(Debug, Clone, BorshDeserialize)]
+pub struct Whirlpool {
+    pub discriminator: [u8; 8],
+    pub whirlpools_config: Pubkey,
+    pub whirlpool_bump: [u8; 1],
+    pub tick_spacing: u16,
+    pub tick_spacing_seed: [u8; 2],
+    /// Fee in hundredths of a basis point
+    pub fee_rate: u16,
+    pub protocol_fee_rate: u16,
+    /// Liquidity in the current tick range
+    pub liquidity: u128,
+    /// Square root of the price of a in b, Q64.64
+    pub sqrt_price: u128,
+    pub tick_current_index: i32,
+    pub protocol_fee_owed_a: u64,
+    pub protocol_fee_owed_b: u64,
+    pub token_mint_a: Pubkey,
+    pub token_vault_a: Pubkey,
+    pub fee_growth_global_a: u128,
+    pub token_mint_b: Pubkey,
+    pub token_vault_b: Pubkey,
+    pub fee_growth_global_b: u128,
+    pub reward_last_updated_timestamp: u64,
+    pub reward_infos: [WhirlpoolRewardInfo; 3],
+}
+
+impl Whirlpool {
+    /// Decodes the account data of `pubkey`, checking the discriminator
+    pub fn unpack(pubkey: &Pubkey, data: &[u8]) -> Result<Self> {
+        if data.len() < WHIRLPOOL_LEN || data[..8] != WHIRLPOOL_DISCRIMINATOR {
+            return Err(OrcaError::InvalidWhirlpool { pubkey: *pubkey }.into());
+        }
+        Ok(Self::deserialize(&mut &data[..WHIRLPOOL_LEN])?)
+    }
+}
+
+/// First tick of the tick array containing `tick`
+pub fn tick_array_start_index(tick: i32, tick_spacing: u16) -> i32 {
+    let ticks_in_array = TICK_ARRAY_SIZE * tick_spacing as i32;
+    tick.div_euclid(ticks_in_array) * ticks_in_array
+}
+
+/// The three tick arrays a swap in direction `a_to_b` may traverse
+pub fn tick_array_pdas(
+    program_id: &Pubkey,
+    whirlpool_pubkey: &Pubkey,
+    whirlpool: &Whirlpool,
+    a_to_b: bool,
+) -> [Pubkey; 3] {
+    let tick_spacing = whirlpool.tick_spacing;
+    let ticks_in_array = TICK_ARRAY_SIZE * tick_spacing as i32;
+    // b到a时价格上升，当前tick可能正好在下一个数组的边界
+    let shift = if a_to_b { 0 } else { tick_spacing as i32 };
+    let start = tick_array_start_index(whirlpool.tick_current_index + shift, tick_spacing);
+    let step = if a_to_b {
+        -ticks_in_array
+    } else {
+        ticks_in_array
+    };
+    [0, 1, 2].map(|i| {
+        let start_index = (start + i * step).to_string();
+        Pubkey::find_program_address(
+            &[
+                b"tick_array",
+                whirlpool_pubkey.as_ref(),
+                start_index.as_bytes(),
+            ],
+            program_id,
+        )
+        .0
+    })
+}
+
+/// Oracle account of `whirlpool_pubkey`
+pub fn oracle_pda(program_id: &Pubkey, whirlpool_pubkey: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"oracle", whirlpool_pubkey.as_ref()], program_id).0
+}
+
+#[test]
+fn test_unpack_whirlpool_layout() {
+    let mint_a = Pubkey::new_unique();
+    let mut data = WHIRLPOOL_DISCRIMINATOR.to_vec();
+    data.extend_from_slice(&[0; 32 + 1]);
+    data.extend_from_slice(&64u16.to_le_bytes());
+    data.extend_from_slice(&[0; 2]);
+    data.extend_from_slice(&3000u16.to_le_bytes());
+    data.extend_from_slice(&[0; 2]);
+    data.extend_from_slice(&7u128.to_le_bytes());
+    data.extend_from_slice(&(1u128 << 64).to_le_bytes());
+    data.extend_from_slice(&(-100i32).to_le_bytes());
+    data.extend_from_slice(&[0; 16]);
+    data.extend_from_slice(mint_a.as_ref());
+    data.resize(WHIRLPOOL_LEN, 0);
+
+    let whirlpool = Whirlpool::unpack(&Pubkey::new_unique(), &data).unwrap();
+    assert_eq!(whirlpool.tick_spacing, 64);
+    assert_eq!(whirlpool.fee_rate, 3000);
+    assert_eq!(whirlpool.liquidity, 7);
+    assert_eq!(whirlpool.sqrt_price, 1 << 64);
+    assert_eq!(whirlpool.tick_current_index, -100);
+    assert_eq!(whirlpool.token_mint_a, mint_a);
+
+    data[0] = 0;
+    assert!(Whirlpool::unpack(&Pubkey::new_unique(), &data).is_err());
+    assert_eq!(
+        solana_sdk::hash::hash(b"account:Whirlpool").to_bytes()[..8],
+        WHIRLPOOL_DISCRIMINATOR
+    );
+
+    assert_eq!(tick_array_start_index(-100, 64), -5632);
+    assert_eq!(tick_array_start_index(5632, 64), 5632);
+}
@@ -0,0 +1,236 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Keypair, signer::Signer};
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::ui_amount_to_amount;
+
+use crate::{
+    config::program_ids,
+    math::slippage::Slippage,
+    orca::{
+        error::OrcaError,
+        math::{quote_exact_in, MAX_SQRT_PRICE, MIN_SQRT_PRICE},
+        state::{oracle_pda, tick_array_pdas, Whirlpool},
+        swap_instructions::{self, SwapAccounts, SwapArgs},
+    },
+    raydium::{
+        getter,
+        tx::{new_signed_and_send, trade_side},
+    },
+    risk,
+    tx::{
+        budget::global_guard,
+        mode::ExecutionMode,
+        simulate::{ExpectedOutput, OutputAccount, TxOutcome},
+    },
+    wallet::{self, wsol},
+};
+
+/// Fetches and decodes the whirlpool at `pool_id`
+pub async fn get_whirlpool(client: Arc<RpcClient>, pool_id: &Pubkey) -> Result<Whirlpool> {
+    let account = client.get_account(pool_id).await?;
+    Whirlpool::unpack(pool_id, &account.data)
+}
+
+/// Whether swapping `token_in` for `token_out` is a to b
+pub fn resolve_a_to_b(
+    token_in: &Pubkey,
+    token_out: &Pubkey,
+    mint_a: &Pubkey,
+    mint_b: &Pubkey,
+) -> Result<bool> {
+    if token_in == mint_a && token_out == mint_b {
+        Ok(true)
+    } else if token_in == mint_b && token_out == mint_a {
+        Ok(false)
+    } else {
+        Err(OrcaError::PoolMintMismatch {
+            token_in: *token_in,
+            token_out: *token_out,
+            mint_a: *mint_a,
+            mint_b: *mint_b,
+        }
+        .into())
+    }
+}
+
+/// Exact input swap of `amount` on `whirlpool` from the owner's `in_account`
+/// to `out_account`, for at least `min_out`
+#[allow(clippy::too_many_arguments)]
+pub fn swap_instruction(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    pool_id: &Pubkey,
+    whirlpool: &Whirlpool,
+    in_account: &Pubkey,
+    out_account: &Pubkey,
+    a_to_b: bool,
+    amount: u64,
+    min_out: u64,
+) -> Result<Instruction> {
+    let (token_owner_account_a, token_owner_account_b) = if a_to_b {
+        (*in_account, *out_account)
+    } else {
+        (*out_account, *in_account)
+    };
+    let accounts = SwapAccounts {
+        whirlpool: *pool_id,
+        token_owner_account_a,
+        token_vault_a: whirlpool.token_vault_a,
+        token_owner_account_b,
+        token_vault_b: whirlpool.token_vault_b,
+        tick_arrays: tick_array_pdas(program_id, pool_id, whirlpool, a_to_b),
+        oracle: oracle_pda(program_id, pool_id),
+    };
+    swap_instructions::swap(
+        program_id,
+        owner,
+        &accounts,
+        SwapArgs {
+            amount,
+            other_amount_threshold: min_out,
+            sqrt_price_limit: if a_to_b {
+                MIN_SQRT_PRICE
+            } else {
+                MAX_SQRT_PRICE
+            },
+            amount_specified_is_input: true,
+            a_to_b,
+        },
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn get_swap_tx(
+    client: Arc<RpcClient>,
+    token_in: &str,
+    token_out: &str,
+    amount_in: f64,
+    pool_id: &str,
+    slippage: u64,
+    keypair: Arc<Keypair>,
+    is_simulate: bool,
+) -> Result<TxOutcome> {
+    // 滑点
+    let slippage_bps = slippage * 100;
+    let owner = keypair.pubkey();
+
+    let token_in = token_in.parse::<Pubkey>()?;
+    let token_out = token_out.parse::<Pubkey>()?;
+    let pool_id = pool_id.parse::<Pubkey>()?;
+    let native_mint = spl_token::native_mint::ID;
+    let program_id = program_ids().orca_whirlpool;
+
+    // 获取池子状态
+    let whirlpool = get_whirlpool(client.clone(), &pool_id).await?;
+    let a_to_b = resolve_a_to_b(
+        &token_in,
+        &token_out,
+        &whirlpool.token_mint_a,
+        &whirlpool.token_mint_b,
+    )?;
+
+    // 计算出输入数量的准确数值
+    let in_decimals = if token_in == native_mint {
+        spl_token::native_mint::DECIMALS
+    } else {
+        getter::get_mint_info(client.clone(), keypair.clone(), &token_in)
+            .await?
+            .decimals
+    };
+    let amount_specified = ui_amount_to_amount(amount_in, in_decimals);
+
+    // 用sol买入时检查风控、预算和冷却
+    let reservation = if !ExecutionMode::resolve(is_simulate).simulates() && token_in == native_mint
+    {
+        risk::check_buy(&token_out, amount_specified)?;
+        Some(global_guard().reserve(&token_out, amount_specified)?)
+    } else {
+        None
+    };
+
+    let quote = quote_exact_in(
+        whirlpool.sqrt_price,
+        whirlpool.liquidity,
+        whirlpool.fee_rate,
+        amount_specified,
+        a_to_b,
+    )?;
+    let other_amount_threshold = Slippage::Bps(slippage_bps).min_out(quote.estimated_amount_out)?;
+
+    let mut instructions = vec![];
+    let mut in_account = get_associated_token_address(&owner, &token_in);
+    let mut out_account = get_associated_token_address(&owner, &token_out);
+
+    // 输出代币不是sol时，需要其ATA账户
+    if token_out != native_mint {
+        instructions.extend(wallet::create_ata(&client, &owner, &token_out, &spl_token::ID).await);
+    }
+
+    // 输入或输出是sol时，用wsol账户
+    let mut wsol_account = None;
+    if token_in == native_mint || token_out == native_mint {
+        let lamports = if token_in == native_mint {
+            amount_specified
+        } else {
+            0
+        };
+        let wsol = wsol::prepare(&client, &owner, lamports).await?;
+        instructions.extend(wsol.setup.iter().cloned());
+        if token_in == native_mint {
+            in_account = wsol.address;
+        } else {
+            out_account = wsol.address;
+        }
+        wsol_account = Some(wsol);
+    }
+
+    instructions.push(swap_instruction(
+        &program_id,
+        &owner,
+        &pool_id,
+        &whirlpool,
+        &in_account,
+        &out_account,
+        a_to_b,
+        amount_specified,
+        other_amount_threshold,
+    )?);
+    if let Some(wsol) = &wsol_account {
+        instructions.extend(wsol.cleanup.iter().cloned());
+    }
+
+    let expected = ExpectedOutput {
+        expected_out: quote.estimated_amount_out,
+        min_out: other_amount_threshold,
+        account: match &wsol_account {
+            Some(wsol) if token_out == native_mint => wsol.output(&owner),
+            _ => OutputAccount::Token(out_account),
+        },
+    };
+    let outcome = new_signed_and_send(
+        client,
+        keypair,
+        instructions,
+        is_simulate,
+        Some(expected),
+        "orca",
+        trade_side(&token_in, &token_out),
+    )
+    .await?;
+    if let Some(reservation) = reservation {
+        reservation.commit();
+    }
+    Ok(outcome)
+}
+
+#[test]
+fn test_resolve_a_to_b_either_ordering() {
+    let mint_a = Pubkey::new_unique();
+    let mint_b = Pubkey::new_unique();
+    assert!(resolve_a_to_b(&mint_a, &mint_b, &mint_a, &mint_b).unwrap());
+    assert!(!resolve_a_to_b(&mint_b, &mint_a, &mint_a, &mint_b).unwrap());
+    assert!(resolve_a_to_b(&mint_a, &Pubkey::new_unique(), &mint_a, &mint_b).is_err());
+}
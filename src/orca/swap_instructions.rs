@@ -0,0 +1,103 @@
+//! Whirlpool swap instruction
+
+use anyhow::Result;
+use borsh::BorshSerialize;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+
+/// Anchor discriminator of the `swap` instruction
+pub const SWAP_DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+
+#[derive(Debug, Clone, Copy, BorshSerialize)]
+pub struct SwapArgs {
+    /// Input amount if `amount_specified_is_input`, otherwise output amount
+    pub amount: u64,
+    /// Minimum output, or maximum input when `amount` is the output
+    pub other_amount_threshold: u64,
+    /// Sqrt price the swap may not move past, Q64.64
+    pub sqrt_price_limit: u128,
+    pub amount_specified_is_input: bool,
+    /// Swapping token a for token b
+    pub a_to_b: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SwapAccounts {
+    pub whirlpool: Pubkey,
+    pub token_owner_account_a: Pubkey,
+    pub token_vault_a: Pubkey,
+    pub token_owner_account_b: Pubkey,
+    pub token_vault_b: Pubkey,
+    pub tick_arrays: [Pubkey; 3],
+    pub oracle: Pubkey,
+}
+
+/// Creates a whirlpool `swap` instruction signed by `authority`
+pub fn swap(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    accounts: &SwapAccounts,
+    args: SwapArgs,
+) -> Result<Instruction> {
+    let mut data = SWAP_DISCRIMINATOR.to_vec();
+    args.serialize(&mut data)?;
+
+    let accounts = vec![
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(accounts.whirlpool, false),
+        AccountMeta::new(accounts.token_owner_account_a, false),
+        AccountMeta::new(accounts.token_vault_a, false),
+        AccountMeta::new(accounts.token_owner_account_b, false),
+        AccountMeta::new(accounts.token_vault_b, false),
+        AccountMeta::new(accounts.tick_arrays[0], false),
+        AccountMeta::new(accounts.tick_arrays[1], false),
+        AccountMeta::new(accounts.tick_arrays[2], false),
+        AccountMeta::new(accounts.oracle, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+#[test]
+fn test_swap_instruction_data() {
+    assert_eq!(
+        solana_sdk::hash::hash(b"global:swap").to_bytes()[..8],
+        SWAP_DISCRIMINATOR
+    );
+
+    let accounts = SwapAccounts {
+        whirlpool: Pubkey::new_unique(),
+        token_owner_account_a: Pubkey::new_unique(),
+        token_vault_a: Pubkey::new_unique(),
+        token_owner_account_b: Pubkey::new_unique(),
+        token_vault_b: Pubkey::new_unique(),
+        tick_arrays: [Pubkey::new_unique(); 3],
+        oracle: Pubkey::new_unique(),
+    };
+    let ix = swap(
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        &accounts,
+        SwapArgs {
+            amount: 1,
+            other_amount_threshold: 2,
+            sqrt_price_limit: 3,
+            amount_specified_is_input: true,
+            a_to_b: false,
+        },
+    )
+    .unwrap();
+    // 判别符 + u64 + u64 + u128 + bool + bool
+    assert_eq!(ix.data.len(), 8 + 8 + 8 + 16 + 1 + 1);
+    assert_eq!(ix.data[8..16], 1u64.to_le_bytes());
+    assert_eq!(ix.data[40..], [1, 0]);
+    assert_eq!(ix.accounts.len(), 11);
+    assert!(ix.accounts[1].is_signer);
+}
@@ -0,0 +1,456 @@
+//! Positions and PnL of the bot's own trades.
+//!
+//! Every buy and sell sent through `pumpfun::operation` or `raydium::swap` is
+//! recorded as a [`Fill`] in the process-wide [`Portfolio`]. Fills are
+//! appended as JSON lines to `PORTFOLIO_PATH` (default `portfolio.jsonl`) and
+//! replayed on startup, so positions survive restarts. With
+//! `STORAGE_ENABLED=true` they go to the SQLite [`storage`] instead. Simulations
+//! are not recorded. In paper mode, see [`crate::tx::mode`], the fills go to
+//! `PAPER_PORTFOLIO_PATH` (default `paper_portfolio.jsonl`) and never to the
+//! storage, so paper trades can't mix with live ones.
+//!
+//! Amounts are the ones quoted when the trade was sent, not what landed on
+//! chain. A position's cost is the average of its buys, and a sell realizes
+//! its proceeds minus the cost of the tokens sold, credited to the strategy
+//! that opened the position. Unrealized PnL needs live quotes, see [`quote`].
+
+pub mod quote;
+
+use std::{
+    collections::HashMap,
+    env,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{OnceLock, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{fee::FeeStructure, pubkey::Pubkey};
+use tracing::error;
+
+use crate::{
+    metrics,
+    storage::{self, Storage},
+    timeline,
+    tx::{
+        mode::{execution_mode, ExecutionMode},
+        simulate::TxOutcome,
+    },
+};
+
+const DEFAULT_PORTFOLIO_PATH: &str = "portfolio.jsonl";
+const DEFAULT_PAPER_PORTFOLIO_PATH: &str = "paper_portfolio.jsonl";
+
+static GLOBAL_PORTFOLIO: OnceLock<Portfolio> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+/// A trade sent by the bot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fill {
+    pub mint: String,
+    pub side: Side,
+    pub venue: String,
+    /// Strategy that made the trade, see [`timeline::strategy`]
+    #[serde(default = "unknown_strategy")]
+    pub strategy: String,
+    /// Tokens bought or sold, in raw units
+    pub token_amount: u64,
+    /// SOL paid or received, in lamports before fees
+    pub sol_amount: u64,
+    /// Network fees, in lamports
+    pub fee: u64,
+    pub signature: String,
+    /// Unix timestamp in seconds
+    pub timestamp: u64,
+}
+
+/// Strategy of fills recorded before fills carried one
+fn unknown_strategy() -> String {
+    "unknown".to_string()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Position {
+    pub mint: String,
+    /// Strategy of the first buy, credited with the position's PnL
+    pub strategy: String,
+    /// Tokens held, in raw units
+    pub token_amount: u64,
+    /// Cost of the tokens held including fees, in lamports
+    pub cost_basis: u64,
+    /// Profit of the sells so far, in lamports
+    pub realized_pnl: i64,
+    /// Fees paid over the position's lifetime, in lamports
+    pub fees: u64,
+    /// Raydium pool the token trades in since its curve migrated, see
+    /// [`Portfolio::mark_migrated`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool: Option<String>,
+}
+
+impl Position {
+    pub fn is_open(&self) -> bool {
+        self.token_amount > 0
+    }
+
+    /// Average lamports paid per raw token unit
+    pub fn entry_price(&self) -> Option<f64> {
+        self.is_open()
+            .then(|| self.cost_basis as f64 / self.token_amount as f64)
+    }
+
+    /// Applies `fill`, returning the profit it realized
+    fn apply(&mut self, fill: &Fill) -> i64 {
+        self.fees += fill.fee;
+        if self.strategy.is_empty() {
+            self.strategy = fill.strategy.clone();
+        }
+        match fill.side {
+            Side::Buy => {
+                self.token_amount += fill.token_amount;
+                self.cost_basis += fill.sol_amount + fill.fee;
+                0
+            }
+            Side::Sell => {
+                // 卖出超过持仓的部分没有成本（例如在bot之外买入的）
+                let sold = fill.token_amount.min(self.token_amount);
+                let cost = if self.token_amount == 0 {
+                    0
+                } else {
+                    (self.cost_basis as u128 * sold as u128 / self.token_amount as u128) as u64
+                };
+                self.token_amount -= sold;
+                self.cost_basis -= cost;
+                let realized = fill.sol_amount as i64 - fill.fee as i64 - cost as i64;
+                self.realized_pnl += realized;
+                realized
+            }
+        }
+    }
+}
+
+/// Where fills are persisted
+enum FillStore {
+    Memory,
+    /// JSON lines appended to a file
+    File(PathBuf),
+    Storage(&'static Storage),
+}
+
+/// Profit a sell realized
+#[derive(Debug, Clone)]
+struct Realization {
+    strategy: String,
+    /// Unix timestamp in seconds
+    timestamp: u64,
+    pnl: i64,
+}
+
+pub struct Portfolio {
+    store: FillStore,
+    positions: RwLock<HashMap<String, Position>>,
+    realizations: RwLock<Vec<Realization>>,
+}
+
+impl Portfolio {
+    pub fn in_memory() -> Self {
+        Self {
+            store: FillStore::Memory,
+            positions: RwLock::new(HashMap::new()),
+            realizations: RwLock::new(vec![]),
+        }
+    }
+
+    fn replay(store: FillStore, fills: impl IntoIterator<Item = Fill>) -> Self {
+        let mut positions: HashMap<String, Position> = HashMap::new();
+        let mut realizations = vec![];
+        for fill in fills {
+            let position = positions
+                .entry(fill.mint.clone())
+                .or_insert_with(|| Position {
+                    mint: fill.mint.clone(),
+                    ..Position::default()
+                });
+            let pnl = position.apply(&fill);
+            if fill.side == Side::Sell {
+                realizations.push(Realization {
+                    strategy: position.strategy.clone(),
+                    timestamp: fill.timestamp,
+                    pnl,
+                });
+            }
+        }
+        let portfolio = Self {
+            store,
+            positions: RwLock::new(positions),
+            realizations: RwLock::new(realizations),
+        };
+        portfolio.update_metrics();
+        portfolio
+    }
+
+    /// Replays the fills stored at `path` and appends new ones to it
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut fills = vec![];
+        if path.exists() {
+            let reader = BufReader::new(File::open(path)?);
+            for (i, line) in reader.lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let fill: Fill = serde_json::from_str(&line)
+                    .map_err(|e| anyhow!("invalid fill at {}:{}: {}", path.display(), i + 1, e))?;
+                fills.push(fill);
+            }
+        }
+        Ok(Self::replay(FillStore::File(path.to_path_buf()), fills))
+    }
+
+    /// Replays the fills in `storage` and inserts new ones into it
+    pub fn from_storage(storage: &'static Storage) -> Result<Self> {
+        Ok(Self::replay(FillStore::Storage(storage), storage.fills()?))
+    }
+
+    /// Uses `PAPER_PORTFOLIO_PATH` in paper mode, else the [`storage`] if
+    /// enabled, `PORTFOLIO_PATH` otherwise
+    pub fn from_env() -> Result<Self> {
+        dotenv::dotenv().ok();
+        if execution_mode() == ExecutionMode::Paper {
+            return Self::open(
+                env::var("PAPER_PORTFOLIO_PATH")
+                    .unwrap_or_else(|_| DEFAULT_PAPER_PORTFOLIO_PATH.to_string()),
+            );
+        }
+        if let Some(storage) = storage::storage() {
+            return Self::from_storage(storage);
+        }
+        let path =
+            env::var("PORTFOLIO_PATH").unwrap_or_else(|_| DEFAULT_PORTFOLIO_PATH.to_string());
+        Self::open(path)
+    }
+
+    /// Applies `fill` to its position and persists it
+    pub fn record(&self, fill: Fill) -> Result<()> {
+        let mut positions = self.positions.write().unwrap();
+        // 持有写锁，保证存储中的顺序和内存一致
+        match &self.store {
+            FillStore::Memory => {}
+            FillStore::File(path) => {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", serde_json::to_string(&fill)?)?;
+            }
+            FillStore::Storage(storage) => storage.insert_fill(&fill)?,
+        }
+        let position = positions
+            .entry(fill.mint.clone())
+            .or_insert_with(|| Position {
+                mint: fill.mint.clone(),
+                ..Position::default()
+            });
+        let pnl = position.apply(&fill);
+        if fill.side == Side::Sell {
+            self.realizations.write().unwrap().push(Realization {
+                strategy: position.strategy.clone(),
+                timestamp: fill.timestamp,
+                pnl,
+            });
+        }
+        drop(positions);
+        self.update_metrics();
+        Ok(())
+    }
+
+    /// Marks the open position in `mint` as migrated to `pool`, returning
+    /// whether there was one
+    ///
+    /// The mark isn't persisted, after a restart migrated positions are quoted
+    /// through the pool found for their mint instead.
+    pub fn mark_migrated(&self, mint: &str, pool: &Pubkey) -> bool {
+        match self.positions.write().unwrap().get_mut(mint) {
+            Some(position) if position.is_open() => {
+                position.pool = Some(pool.to_string());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn position(&self, mint: &str) -> Option<Position> {
+        self.positions.read().unwrap().get(mint).cloned()
+    }
+
+    /// Every position traded, including closed ones
+    pub fn positions(&self) -> Vec<Position> {
+        self.positions.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn open_positions(&self) -> Vec<Position> {
+        self.positions
+            .read()
+            .unwrap()
+            .values()
+            .filter(|position| position.is_open())
+            .cloned()
+            .collect()
+    }
+
+    /// Realized profit over every position, in lamports
+    pub fn realized_pnl(&self) -> i64 {
+        self.positions
+            .read()
+            .unwrap()
+            .values()
+            .map(|position| position.realized_pnl)
+            .sum()
+    }
+
+    /// Realized profit per strategy, in lamports
+    pub fn realized_pnl_by_strategy(&self) -> HashMap<String, i64> {
+        let mut pnl = HashMap::new();
+        for position in self.positions.read().unwrap().values() {
+            *pnl.entry(position.strategy.clone()).or_default() += position.realized_pnl;
+        }
+        pnl
+    }
+
+    /// Profit `strategy` realized on sells since the unix timestamp `since`,
+    /// in lamports
+    pub fn realized_pnl_since(&self, strategy: &str, since: u64) -> i64 {
+        self.realizations
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|r| r.strategy == strategy && r.timestamp >= since)
+            .map(|r| r.pnl)
+            .sum()
+    }
+
+    fn update_metrics(&self) {
+        let open = self
+            .positions
+            .read()
+            .unwrap()
+            .values()
+            .filter(|position| position.is_open())
+            .count();
+        metrics::OPEN_POSITIONS.set(open as i64);
+        for (strategy, pnl) in self.realized_pnl_by_strategy() {
+            metrics::REALIZED_PNL_LAMPORTS
+                .with_label_values(&[&strategy])
+                .set(pnl);
+        }
+    }
+}
+
+/// Process-wide portfolio, in memory only if its fills can't be read
+pub fn portfolio() -> &'static Portfolio {
+    GLOBAL_PORTFOLIO.get_or_init(|| {
+        Portfolio::from_env().unwrap_or_else(|e| {
+            error!("failed to load portfolio, not persisting fills {:?}", e);
+            Portfolio::in_memory()
+        })
+    })
+}
+
+/// Records a trade in the global portfolio if `outcome` was sent or paper
+/// traded
+///
+/// The fee is the base signature fee of each transaction sent. Failing to
+/// persist is only logged, since the trade is already on its way.
+pub fn record_trade(
+    venue: &str,
+    side: Side,
+    mint: &Pubkey,
+    token_amount: u64,
+    sol_amount: u64,
+    outcome: &TxOutcome,
+) {
+    // 纸面交易按未发送交易的签名记录
+    let signatures = match outcome {
+        TxOutcome::Paper(signature) => std::slice::from_ref(signature),
+        outcome => outcome.signatures(),
+    };
+    let Some(signature) = signatures.last() else {
+        return;
+    };
+    let fill = Fill {
+        mint: mint.to_string(),
+        side,
+        venue: venue.to_string(),
+        strategy: timeline::strategy().to_string(),
+        token_amount,
+        sol_amount,
+        fee: FeeStructure::default().lamports_per_signature * signatures.len() as u64,
+        signature: signature.to_string(),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+    };
+    if let Err(e) = portfolio().record(fill) {
+        error!("failed to record {:?} of {} {:?}", side, mint, e);
+    }
+}
+
+#[test]
+fn test_portfolio_average_cost_and_replay() {
+    let path = env::temp_dir().join(format!("portfolio-{}.jsonl", Pubkey::new_unique()));
+    let fill = |side, token_amount, sol_amount| Fill {
+        mint: "mint".to_string(),
+        side,
+        venue: "pumpfun".to_string(),
+        strategy: "sniper".to_string(),
+        token_amount,
+        sol_amount,
+        fee: 10,
+        signature: String::new(),
+        timestamp: 0,
+    };
+
+    let portfolio = Portfolio::open(&path).unwrap();
+    portfolio.record(fill(Side::Buy, 1000, 990)).unwrap();
+    portfolio.record(fill(Side::Buy, 1000, 1990)).unwrap();
+    // 平均成本 1.5 / token
+    portfolio.record(fill(Side::Sell, 500, 1010)).unwrap();
+
+    let position = portfolio.position("mint").unwrap();
+    assert_eq!(position.token_amount, 1500);
+    assert_eq!(position.cost_basis, 2250);
+    assert_eq!(position.realized_pnl, 1010 - 10 - 750);
+    assert_eq!(position.fees, 30);
+    assert_eq!(position.entry_price(), Some(1.5));
+
+    // 重启后从文件恢复
+    let replayed = Portfolio::open(&path).unwrap();
+    assert_eq!(replayed.position("mint"), Some(position));
+    assert_eq!(replayed.open_positions().len(), 1);
+
+    replayed.record(fill(Side::Sell, 2000, 3000)).unwrap();
+    let closed = replayed.position("mint").unwrap();
+    assert!(!closed.is_open());
+    assert_eq!(closed.cost_basis, 0);
+    assert_eq!(replayed.realized_pnl(), 250 + 3000 - 10 - 2250);
+    assert_eq!(
+        replayed.realized_pnl_by_strategy()["sniper"],
+        replayed.realized_pnl()
+    );
+    assert_eq!(
+        replayed.realized_pnl_since("sniper", 0),
+        replayed.realized_pnl()
+    );
+    assert_eq!(replayed.realized_pnl_since("sniper", 1), 0);
+    assert!(replayed.open_positions().is_empty());
+
+    std::fs::remove_file(&path).unwrap();
+}
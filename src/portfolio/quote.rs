@@ -0,0 +1,213 @@
+//! Live value of open positions.
+//!
+//! A position is valued at what selling all of it would return right now: on
+//! the bonding curve while it's running, in the WSOL PumpSwap pool once the
+//! curve is complete, or in the WSOL Raydium pool of the tokens migrated
+//! there. Positions marked migrated are quoted in their pool, PumpSwap or
+//! Raydium by the pool account's owner, without looking at the curve.
+
+use std::{fmt, sync::Arc};
+
+use anyhow::Result;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{native_token::lamports_to_sol, pubkey::Pubkey};
+use tracing::debug;
+
+use crate::{
+    config::program_ids,
+    pumpfun::utils::{get_bonding_curve_account, get_global_account},
+    pumpswap::{self, accounts::Pool, math::sell_quote_output},
+    raydium::{getter::get_pool_state, math::quote_base_in, pools::find_sol_pool},
+};
+
+use super::{Portfolio, Position};
+
+#[derive(Debug, Clone)]
+pub struct PositionPnl {
+    pub position: Position,
+    /// Where the position was quoted
+    pub venue: &'static str,
+    /// SOL selling the whole position would return, in lamports
+    pub value: u64,
+    /// `value` minus the cost basis, in lamports
+    pub unrealized_pnl: i64,
+}
+
+impl PositionPnl {
+    pub fn new(position: Position, venue: &'static str, value: u64) -> Self {
+        let unrealized_pnl = value as i64 - position.cost_basis as i64;
+        Self {
+            position,
+            venue,
+            value,
+            unrealized_pnl,
+        }
+    }
+
+    /// Realized plus unrealized profit, in lamports
+    pub fn total_pnl(&self) -> i64 {
+        self.position.realized_pnl + self.unrealized_pnl
+    }
+}
+
+fn signed_sol(lamports: i64) -> String {
+    let sol = lamports_to_sol(lamports.unsigned_abs());
+    if lamports < 0 {
+        format!("-{:.4}", sol)
+    } else {
+        format!("+{:.4}", sol)
+    }
+}
+
+impl fmt::Display for PositionPnl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}): cost {:.4} SOL, value {:.4} SOL, unrealized {} SOL, realized {} SOL",
+            self.position.mint,
+            self.venue,
+            lamports_to_sol(self.position.cost_basis),
+            lamports_to_sol(self.value),
+            signed_sol(self.unrealized_pnl),
+            signed_sol(self.position.realized_pnl),
+        )
+    }
+}
+
+/// Lamports selling `token_amount` of `mint` would return, and the venue quoted
+pub async fn sell_quote(
+    client: Arc<RpcClient>,
+    mint: &Pubkey,
+    token_amount: u64,
+) -> Result<(&'static str, u64)> {
+    // 没有bonding curve的代币直接查raydium
+    if let Ok(curve) = get_bonding_curve_account(client.clone(), mint).await {
+        if !curve.complete {
+            let global = get_global_account(client).await?;
+            let value = curve
+                .get_sell_price(token_amount, global.fee_basis_points)
+                .map_err(|e| anyhow::anyhow!(e))?;
+            return Ok(("pumpfun", value));
+        }
+    }
+
+    // 现在默认迁移到pumpswap，找不到再查raydium
+    match pumpswap::pools::find_sol_pool(client.clone(), mint).await {
+        Ok((pool_id, pool)) => {
+            let value = pumpswap_quote(&client, &pool_id, &pool, token_amount).await?;
+            Ok(("pumpswap", value))
+        }
+        Err(e) => {
+            debug!("no pumpswap pool for {} {:?}", mint, e);
+            let pool_id = find_sol_pool(client.clone(), mint).await?;
+            let value = pool_quote(client, &pool_id, mint, token_amount).await?;
+            Ok(("raydium", value))
+        }
+    }
+}
+
+/// Lamports selling `token_amount` in the PumpSwap pool `pool_id` would
+/// return
+pub async fn pumpswap_quote(
+    client: &RpcClient,
+    pool_id: &Pubkey,
+    pool: &Pool,
+    token_amount: u64,
+) -> Result<u64> {
+    let (config, (base_reserve, quote_reserve)) = tokio::try_join!(
+        pumpswap::pools::get_global_config(client),
+        pumpswap::pools::get_reserves(client, pool)
+    )?;
+    debug!(
+        "pumpswap pool {} reserves {} / {}",
+        pool_id, base_reserve, quote_reserve
+    );
+    Ok(sell_quote_output(
+        token_amount,
+        base_reserve,
+        quote_reserve,
+        config.lp_fee_basis_points,
+        config.protocol_fee_basis_points,
+    ))
+}
+
+/// Lamports selling `token_amount` of `mint` in the Raydium pool `pool_id`
+/// would return
+pub async fn pool_quote(
+    client: Arc<RpcClient>,
+    pool_id: &Pubkey,
+    mint: &Pubkey,
+    token_amount: u64,
+) -> Result<u64> {
+    let (pool_id, pool_state) = get_pool_state(client.clone(), &pool_id.to_string()).await?;
+    quote_base_in(
+        client,
+        &pool_state,
+        program_ids().raydium_amm,
+        pool_id,
+        mint,
+        token_amount,
+    )
+    .await
+}
+
+/// Lamports selling the whole `position` would return, and the venue quoted
+pub async fn position_value(
+    client: Arc<RpcClient>,
+    position: &Position,
+) -> Result<(&'static str, u64)> {
+    let mint: Pubkey = position.mint.parse()?;
+    let Some(pool) = &position.pool else {
+        return sell_quote(client, &mint, position.token_amount).await;
+    };
+    let pool_id: Pubkey = pool.parse()?;
+    let account = client.get_account(&pool_id).await?;
+    if account.owner == program_ids().pumpswap {
+        let pool = Pool::unpack(&pool_id, &account.data)?;
+        let value = pumpswap_quote(&client, &pool_id, &pool, position.token_amount).await?;
+        return Ok(("pumpswap", value));
+    }
+    let value = pool_quote(client, &pool_id, &mint, position.token_amount).await?;
+    Ok(("raydium", value))
+}
+
+pub async fn position_pnl(client: Arc<RpcClient>, position: Position) -> Result<PositionPnl> {
+    let (venue, value) = position_value(client, &position).await?;
+    Ok(PositionPnl::new(position, venue, value))
+}
+
+/// Quotes every open position of `portfolio`
+///
+/// Positions that can't be quoted are returned with the error instead of
+/// failing the whole report.
+pub async fn pnl_report(
+    client: Arc<RpcClient>,
+    portfolio: &Portfolio,
+) -> Vec<(Position, Result<PositionPnl>)> {
+    let mut report = vec![];
+    for position in portfolio.open_positions() {
+        let pnl = position_pnl(client.clone(), position.clone()).await;
+        report.push((position, pnl));
+    }
+    report
+}
+
+#[test]
+fn test_position_pnl_display() {
+    let position = Position {
+        mint: "mint".to_string(),
+        strategy: "sniper".to_string(),
+        token_amount: 1000,
+        cost_basis: 2_000_000_000,
+        realized_pnl: -500_000_000,
+        fees: 10_000,
+        pool: None,
+    };
+    let pnl = PositionPnl::new(position, "pumpfun", 2_500_000_000);
+    assert_eq!(pnl.unrealized_pnl, 500_000_000);
+    assert_eq!(pnl.total_pnl(), 0);
+    assert_eq!(
+        pnl.to_string(),
+        "mint (pumpfun): cost 2.0000 SOL, value 2.5000 SOL, unrealized +0.5000 SOL, realized -0.5000 SOL"
+    );
+}
@@ -1,10 +1,15 @@
 use std::f32::consts::E;
 
 use borsh::{BorshDeserialize, BorshSerialize};
-use solana_sdk::pubkey::Pubkey;
+use solana_sdk::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey};
 
 use anyhow::Result;
 
+use crate::constants::curve::{
+    INITIAL_REAL_TOKEN_RESERVES, INITIAL_VIRTUAL_SOL_RESERVES, INITIAL_VIRTUAL_TOKEN_RESERVES,
+    TOKEN_DECIMALS, TOKEN_TOTAL_SUPPLY,
+};
+
 #[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
 /// Unique identifier for the bonding curve
 pub struct BondingCurveAccount {
@@ -54,6 +59,34 @@ impl BondingCurveAccount {
         }
     }
 
+    /// Curve of a freshly created token, before any buy
+    pub fn fresh() -> Self {
+        Self::new(
+            0,
+            INITIAL_VIRTUAL_TOKEN_RESERVES,
+            INITIAL_VIRTUAL_SOL_RESERVES,
+            INITIAL_REAL_TOKEN_RESERVES,
+            0,
+            TOKEN_TOTAL_SUPPLY,
+            false,
+        )
+    }
+
+    /// Buys `amount` tokens on the curve, updating its reserves
+    ///
+    /// # Returns
+    /// * `Ok(u64)` - Amount of SOL paid, before fees
+    /// * `Err(&str)` - Error message if curve is complete or lacks the tokens
+    pub fn apply_buy(&mut self, amount: u64) -> Result<u64, &'static str> {
+        let sol = self.get_buy_sol_cost(amount)?;
+        self.virtual_token_reserves -= amount;
+        self.virtual_sol_reserves += sol;
+        self.real_token_reserves -= amount;
+        self.real_sol_reserves += sol;
+        self.complete = self.real_token_reserves == 0;
+        Ok(sol)
+    }
+
     /// Calculates the amount of tokens received for a given SOL amount
     ///
     /// # Arguments
@@ -92,6 +125,51 @@ impl BondingCurveAccount {
         })
     }
 
+    /// Calculates the amount of tokens received for SOL paying the fee too
+    ///
+    /// # Arguments
+    /// * `amount` - Amount of SOL to spend, fee included
+    /// * `fee_basis_points` - Fee in basis points (1/100th of a percent)
+    ///
+    /// # Returns
+    /// * `Ok(u64)` - Amount of tokens that would be received
+    /// * `Err(&str)` - Error message if curve is complete
+    pub fn get_buy_price_with_fee(
+        &self,
+        amount: u64,
+        fee_basis_points: u64,
+    ) -> Result<u64, &'static str> {
+        self.get_buy_price(amount - fee_included(amount, fee_basis_points))
+    }
+
+    /// Calculates the amount of SOL needed to buy an exact amount of tokens
+    ///
+    /// # Arguments
+    /// * `amount` - Amount of tokens to buy
+    ///
+    /// # Returns
+    /// * `Ok(u64)` - Amount of SOL required, before fees
+    /// * `Err(&str)` - Error message if curve is complete or lacks the tokens
+    pub fn get_buy_sol_cost(&self, amount: u64) -> Result<u64, &'static str> {
+        if self.complete {
+            return Err("Curve is complete");
+        }
+
+        if amount == 0 {
+            return Ok(0);
+        }
+
+        if amount > self.real_token_reserves {
+            return Err("Not enough tokens in curve");
+        }
+
+        // Solve (sol + x) * (token - amount) = sol * token for x, rounding up
+        let n: u128 = (amount as u128) * (self.virtual_sol_reserves as u128);
+        let d: u128 = (self.virtual_token_reserves as u128) - (amount as u128);
+
+        Ok((n / d + 1) as u64)
+    }
+
     /// Calculates the amount of SOL received for selling tokens
     ///
     /// # Arguments
@@ -121,7 +199,47 @@ impl BondingCurveAccount {
         Ok((n - a) as u64)
     }
 
-    /// Calculates the current market cap in SOL
+    /// Current price of a whole token in SOL, at the virtual reserves
+    pub fn get_price_sol(&self) -> f64 {
+        if self.virtual_token_reserves == 0 {
+            return 0.0;
+        }
+        let sol = self.virtual_sol_reserves as f64 / LAMPORTS_PER_SOL as f64;
+        let tokens = self.virtual_token_reserves as f64 / 10f64.powi(TOKEN_DECIMALS as i32);
+        sol / tokens
+    }
+
+    /// Share of the curve's tokens already sold, in percent
+    pub fn get_progress_pct(&self) -> f64 {
+        if self.complete {
+            return 100.0;
+        }
+        let sold = INITIAL_REAL_TOKEN_RESERVES.saturating_sub(self.real_token_reserves);
+        sold as f64 / INITIAL_REAL_TOKEN_RESERVES as f64 * 100.0
+    }
+
+    /// Price impact of buying with `amount` lamports, in basis points
+    ///
+    /// How much fewer tokens the buy gets than at the current price, fees
+    /// excluded.
+    ///
+    /// # Returns
+    /// * `Ok(u64)` - Price impact in basis points
+    /// * `Err(&str)` - Error message if curve is complete
+    pub fn get_price_impact_bps(&self, amount: u64) -> Result<u64, &'static str> {
+        let tokens = self.get_buy_price(amount)?;
+        if amount == 0 || self.virtual_sol_reserves == 0 {
+            return Ok(0);
+        }
+        let spot = (amount as u128) * (self.virtual_token_reserves as u128)
+            / (self.virtual_sol_reserves as u128);
+        if spot == 0 {
+            return Ok(0);
+        }
+        Ok((spot.saturating_sub(tokens as u128) * 10000 / spot) as u64)
+    }
+
+    /// Calculates the current market cap, in lamports
     pub fn get_market_cap_sol(&self) -> u64 {
         if self.virtual_token_reserves == 0 {
             return 0;
@@ -179,6 +297,18 @@ impl BondingCurveAccount {
     }
 }
 
+/// Fee the program charges on a trade of `sol_amount` lamports, on top of a
+/// buy's cost or out of a sell's proceeds
+pub fn trade_fee(sol_amount: u64, fee_basis_points: u64) -> u64 {
+    (sol_amount as u128 * fee_basis_points as u128 / 10000) as u64
+}
+
+/// Part of `amount` lamports going to the fee when they pay for both a buy
+/// and its fee
+pub fn fee_included(amount: u64, fee_basis_points: u64) -> u64 {
+    (amount as u128 * fee_basis_points as u128 / (10000 + fee_basis_points) as u128) as u64
+}
+
 /**全局账户是 Solana 程序中的一个账户，用于存储程序的全局配置和状态。
 
 在 Pump.fun 程序中，全局账户用于管理以下内容：
@@ -254,10 +384,15 @@ impl GlobalAccount {
         }
     }
 
+    /// Fee charged on a trade of `sol_amount` lamports
+    pub fn trade_fee(&self, sol_amount: u64) -> u64 {
+        trade_fee(sol_amount, self.fee_basis_points)
+    }
+
     /// Calculates the initial amount of tokens received for a given SOL amount
     ///
     /// # Arguments
-    /// * `amount` - Amount of SOL to spend
+    /// * `amount` - Amount of SOL to spend, before fees
     ///
     /// # Returns
     /// Amount of tokens that would be received
@@ -279,3 +414,67 @@ impl GlobalAccount {
         }
     }
 }
+
+#[test]
+fn test_buy_sol_cost_round_trips_buy_price() {
+    let curve = BondingCurveAccount::new(
+        0,
+        1_073_000_000_000_000,
+        30_000_000_000,
+        793_100_000_000_000,
+        0,
+        1_000_000_000_000_000,
+        false,
+    );
+    let token_amount = 35_000_000_000_000;
+    let sol_cost = curve.get_buy_sol_cost(token_amount).unwrap();
+    assert!(curve.get_buy_price(sol_cost).unwrap() >= token_amount);
+    assert!(curve.get_buy_price(sol_cost - 2).unwrap() < token_amount);
+    assert!(curve
+        .get_buy_sol_cost(curve.real_token_reserves + 1)
+        .is_err());
+}
+
+#[test]
+fn test_curve_price_progress_and_impact() {
+    let mut curve = BondingCurveAccount::fresh();
+    // 初始价格约 0.000000028 SOL，市值约 28 SOL
+    assert!((curve.get_price_sol() - 2.796e-8).abs() < 1e-10);
+    assert_eq!(curve.get_market_cap_sol() / LAMPORTS_PER_SOL, 27);
+    assert_eq!(curve.get_progress_pct(), 0.0);
+
+    let small = curve.get_price_impact_bps(LAMPORTS_PER_SOL / 100).unwrap();
+    let large = curve.get_price_impact_bps(5 * LAMPORTS_PER_SOL).unwrap();
+    assert!(small < 10 && large > 1000, "{} {}", small, large);
+
+    let price = curve.get_price_sol();
+    curve.apply_buy(INITIAL_REAL_TOKEN_RESERVES / 4).unwrap();
+    assert!((curve.get_progress_pct() - 25.0).abs() < 1e-9);
+    assert!(curve.get_price_sol() > price);
+
+    curve.apply_buy(curve.real_token_reserves).unwrap();
+    assert!(curve.is_complete());
+    assert_eq!(curve.get_progress_pct(), 100.0);
+    assert!(curve.get_price_impact_bps(1).is_err());
+}
+
+#[test]
+fn test_buy_price_with_fee() {
+    let curve = BondingCurveAccount::fresh();
+    let amount = LAMPORTS_PER_SOL;
+    // 1% 手续费
+    let fee = fee_included(amount, 100);
+    assert_eq!(fee, 9_900_990);
+    assert!(fee + trade_fee(amount - fee, 100) <= amount);
+    let tokens = curve.get_buy_price_with_fee(amount, 100).unwrap();
+    assert_eq!(tokens, curve.get_buy_price(amount - fee).unwrap());
+    assert!(tokens < curve.get_buy_price(amount).unwrap());
+    assert_eq!(
+        curve.get_buy_price_with_fee(amount, 0),
+        curve.get_buy_price(amount)
+    );
+
+    // 买入花费加手续费不超过给出的金额
+    let cost = curve.get_buy_sol_cost(tokens).unwrap();
+    assert!(cost + trade_fee(cost, 100) <= amount + 1);
+}
@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PumpfunError {
+    #[error("buying {token_amount} tokens needs {required_sol} lamports, above the {max_sol} cap")]
+    MaxSolExceeded {
+        token_amount: u64,
+        required_sol: u64,
+        max_sol: u64,
+    },
+    #[error("payer needs {required} lamports but has {balance}, short by {shortfall}")]
+    InsufficientBalance {
+        required: u64,
+        balance: u64,
+        shortfall: u64,
+    },
+    #[error("selling {amount} tokens but only {balance} are held")]
+    InsufficientTokens { amount: u64, balance: u64 },
+}
@@ -7,9 +7,11 @@ use solana_sdk::{
 };
 use spl_associated_token_account::get_associated_token_address;
 
-use crate::constants;
+use crate::{config::program_ids, constants};
 
-use super::utils::{get_bonding_curve_pda, get_global_pda};
+use super::utils::{
+    get_bonding_curve_pda, get_global_pda, get_metadata_pda, get_mint_authority_pda,
+};
 
 #[derive(BorshSerialize, BorshDeserialize)]
 struct BuyArgs {
@@ -24,24 +26,17 @@ struct SellArgs {
 #[derive(BorshSerialize, BorshDeserialize)]
 struct CreateArgs {
     /// Name of the token
-    pub name: String,
+    name: String,
     /// Token symbol (e.g. "BTC")
-    pub symbol: String,
-    /// Description of the token
-    pub description: String,
-    /// Path to the token's image file
-    pub file: String,
-    /// Optional Twitter handle
-    pub twitter: Option<String>,
-    /// Optional Telegram group
-    pub telegram: Option<String>,
-    /// Optional website URL
-    pub website: Option<String>,
+    symbol: String,
+    /// Metadata uri returned by the upload
+    uri: String,
 }
 
 // 指令的标识符
-const BUY_INSTRUCTION_DISCRIMINATOR: u8 = 102;
-const SELL_INSTRUCTION_DISCRIMINATOR: u8 = 51;
+const BUY_INSTRUCTION_DISCRIMINATOR: [u8; 8] = [102, 6, 61, 18, 1, 218, 235, 234];
+const SELL_INSTRUCTION_DISCRIMINATOR: [u8; 8] = [51, 230, 133, 164, 1, 127, 131, 173];
+const CREATE_INSTRUCTION_DISCRIMINATOR: [u8; 8] = [24, 30, 200, 40, 5, 28, 7, 119];
 
 pub fn create_buy_instruction(
     payer: &Keypair,
@@ -54,7 +49,7 @@ pub fn create_buy_instruction(
     // 准备账户列表
     let accounts = vec![
         AccountMeta::new(get_global_pda(), false),
-        AccountMeta::new(constants::accounts::PUMPFUN_FEE_RECEIPT, false),
+        AccountMeta::new(program_ids().pumpfun_fee_recipient, false),
         AccountMeta::new(*mint, false),
         AccountMeta::new(bonding_curve, false),
         AccountMeta::new(get_associated_token_address(&bonding_curve, mint), false),
@@ -63,8 +58,8 @@ pub fn create_buy_instruction(
         AccountMeta::new_readonly(constants::accounts::SYSTEM_PROGRAM, false),
         AccountMeta::new_readonly(constants::accounts::TOKEN_PROGRAM, false),
         AccountMeta::new_readonly(constants::accounts::RENT, false),
-        AccountMeta::new_readonly(constants::accounts::EVENT_AUTHORITY, false),
-        AccountMeta::new_readonly(constants::accounts::PUMPFUN, false),
+        AccountMeta::new_readonly(program_ids().pumpfun_event_authority, false),
+        AccountMeta::new_readonly(program_ids().pumpfun, false),
     ];
 
     // 准备指令参数
@@ -74,12 +69,12 @@ pub fn create_buy_instruction(
     };
 
     // 序列化指令数据
-    let mut data = vec![BUY_INSTRUCTION_DISCRIMINATOR];
+    let mut data = BUY_INSTRUCTION_DISCRIMINATOR.to_vec();
     args.serialize(&mut data).unwrap();
 
     // 返回 Instruction
     Instruction {
-        program_id: constants::accounts::PUMPFUN,
+        program_id: program_ids().pumpfun,
         accounts,
         data,
     }
@@ -95,7 +90,7 @@ pub fn create_sell_instruction(
 
     let accounts = vec![
         AccountMeta::new(get_global_pda(), false), //gloabl
-        AccountMeta::new(constants::accounts::PUMPFUN_FEE_RECEIPT, false), // fee receipient
+        AccountMeta::new(program_ids().pumpfun_fee_recipient, false), // fee receipient
         AccountMeta::new(*mint, false),            // mint
         AccountMeta::new(bonding_curve, false),    // bonding curve
         AccountMeta::new(get_associated_token_address(&bonding_curve, mint), false), // associated bonding curve
@@ -104,8 +99,8 @@ pub fn create_sell_instruction(
         AccountMeta::new_readonly(constants::accounts::SYSTEM_PROGRAM, false), // system program
         AccountMeta::new_readonly(constants::accounts::TOKEN_PROGRAM, false), // associated token program
         AccountMeta::new_readonly(constants::accounts::RENT, false),          // token program
-        AccountMeta::new_readonly(constants::accounts::EVENT_AUTHORITY, false), // event authority
-        AccountMeta::new_readonly(constants::accounts::PUMPFUN, false),       // pump fun program
+        AccountMeta::new_readonly(program_ids().pumpfun_event_authority, false), // event authority
+        AccountMeta::new_readonly(program_ids().pumpfun, false),              // pump fun program
     ];
 
     let args = SellArgs {
@@ -113,13 +108,79 @@ pub fn create_sell_instruction(
         min_sol_output,
     };
 
-    let mut data = vec![SELL_INSTRUCTION_DISCRIMINATOR];
+    let mut data = SELL_INSTRUCTION_DISCRIMINATOR.to_vec();
+    args.serialize(&mut data).unwrap();
+    Instruction {
+        program_id: program_ids().pumpfun,
+        accounts,
+        data,
+    }
+}
+
+/// Creates `mint` on a fresh bonding curve, with `payer` as the creator
+///
+/// `mint` must sign the transaction too.
+pub fn create_token_instruction(
+    payer: &Keypair,
+    mint: &Pubkey,
+    name: String,
+    symbol: String,
+    uri: String,
+) -> Instruction {
+    let bonding_curve: Pubkey = get_bonding_curve_pda(mint).unwrap();
+
+    let accounts = vec![
+        AccountMeta::new(*mint, true),
+        AccountMeta::new_readonly(get_mint_authority_pda(), false),
+        AccountMeta::new(bonding_curve, false),
+        AccountMeta::new(get_associated_token_address(&bonding_curve, mint), false),
+        AccountMeta::new_readonly(get_global_pda(), false),
+        AccountMeta::new_readonly(constants::accounts::MPL_TOKEN_METADATA, false),
+        AccountMeta::new(get_metadata_pda(mint), false),
+        AccountMeta::new(payer.pubkey(), true),
+        AccountMeta::new_readonly(constants::accounts::SYSTEM_PROGRAM, false),
+        AccountMeta::new_readonly(constants::accounts::TOKEN_PROGRAM, false),
+        AccountMeta::new_readonly(constants::accounts::ASSOCIATED_TOKEN_PROGRAM, false),
+        AccountMeta::new_readonly(constants::accounts::RENT, false),
+        AccountMeta::new_readonly(program_ids().pumpfun_event_authority, false),
+        AccountMeta::new_readonly(program_ids().pumpfun, false),
+    ];
+
+    let args = CreateArgs { name, symbol, uri };
+    let mut data = CREATE_INSTRUCTION_DISCRIMINATOR.to_vec();
     args.serialize(&mut data).unwrap();
     Instruction {
-        program_id: constants::accounts::PUMPFUN,
+        program_id: program_ids().pumpfun,
         accounts,
         data,
     }
 }
 
-// pub fn create_token_instruction(payer: &Keypair, mint: &Pubkey) -> Instruction {}
+#[test]
+fn test_instruction_discriminators_and_create_layout() {
+    let discriminator =
+        |name: &str| solana_sdk::hash::hash(name.as_bytes()).to_bytes()[..8].to_vec();
+    assert_eq!(discriminator("global:buy"), BUY_INSTRUCTION_DISCRIMINATOR);
+    assert_eq!(discriminator("global:sell"), SELL_INSTRUCTION_DISCRIMINATOR);
+    assert_eq!(
+        discriminator("global:create"),
+        CREATE_INSTRUCTION_DISCRIMINATOR
+    );
+
+    let payer = Keypair::new();
+    let mint = Pubkey::new_unique();
+    let ix = create_token_instruction(
+        &payer,
+        &mint,
+        "Moon Cat".to_string(),
+        "MCAT".to_string(),
+        "https://ipfs.io/ipfs/x".to_string(),
+    );
+    // 和监听器解析create时用的账户位置一致
+    assert_eq!(ix.accounts[0].pubkey, mint);
+    assert!(ix.accounts[0].is_signer);
+    assert_eq!(ix.accounts[2].pubkey, get_bonding_curve_pda(&mint).unwrap());
+    assert_eq!(ix.accounts[7].pubkey, payer.pubkey());
+    assert_eq!(ix.data[8..12], 8u32.to_le_bytes());
+    assert_eq!(&ix.data[12..20], b"Moon Cat");
+}
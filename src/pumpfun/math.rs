@@ -1,23 +1,54 @@
+use std::sync::Arc;
+
 use anyhow::{anyhow, Result};
-pub fn amount_with_slippage(amount: u64, slippage_bps: u64, is_buy: bool) -> Result<u64> {
-    let amount = amount;
-    println!("real amount {:?}", amount);
-    let ten_thounsand = 10000u64;
-    let slippage_bps = slippage_bps;
-    // buy false
-    let amount_with_slippage = if is_buy {
-        amount
-            .checked_mul(slippage_bps.checked_add(ten_thounsand).unwrap())
-            .unwrap()
-            .checked_div(ten_thounsand)
-            .unwrap()
-    } else {
-        amount
-            .checked_mul(ten_thounsand.checked_sub(slippage_bps).unwrap())
-            .unwrap()
-            .checked_div(ten_thounsand)
-            .unwrap()
-    };
-    u64::try_from(amount_with_slippage)
-        .map_err(|_| anyhow!("failed to read keypair from {}", amount_with_slippage))
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    math::slippage::Slippage,
+    pumpfun::{
+        accounts::{fee_included, BondingCurveAccount, GlobalAccount},
+        utils::{get_bonding_curve_account, get_global_account},
+    },
+    quote::{FeeBreakdown, Quote},
+};
+
+/// Quotes buying with `amount_sol` lamports on the bonding curve
+///
+/// The fee is taken out of `amount_sol`, the rest is swapped at the virtual
+/// reserves.
+pub fn quote_buy(
+    curve: &BondingCurveAccount,
+    global: &GlobalAccount,
+    amount_sol: u64,
+    slippage_bps: u64,
+) -> Result<Quote> {
+    let protocol_fee = fee_included(amount_sol, global.fee_basis_points);
+    let expected_out = curve
+        .get_buy_price_with_fee(amount_sol, global.fee_basis_points)
+        .map_err(|e| anyhow!(e))?;
+    let min_out = Slippage::Bps(slippage_bps).min_out(expected_out)?;
+    Ok(Quote::new(
+        amount_sol,
+        expected_out,
+        min_out,
+        curve.virtual_sol_reserves,
+        curve.virtual_token_reserves,
+        FeeBreakdown {
+            lp_fee: 0,
+            protocol_fee,
+        },
+    ))
+}
+
+/// Quotes buying `mint` with `amount_sol` lamports, without sending anything
+pub async fn quote(
+    client: Arc<RpcClient>,
+    mint: &Pubkey,
+    amount_sol: u64,
+    slippage_bps: u64,
+) -> Result<Quote> {
+    let curve = get_bonding_curve_account(client.clone(), mint).await?;
+    let global = get_global_account(client).await?;
+    quote_buy(&curve, &global, amount_sol, slippage_bps)
 }
@@ -1,5 +1,8 @@
 pub mod accounts;
+pub mod error;
 pub mod instructions;
 pub mod math;
 pub mod operation;
 pub mod utils;
+
+pub use math::quote;
@@ -1,24 +1,55 @@
 use anyhow::{anyhow, Result};
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::{
-    pubkey::Pubkey,
-    signature::{Keypair, Signature},
-    signer::Signer,
+    compute_budget::ComputeBudgetInstruction, fee::FeeStructure, hash::Hash,
+    instruction::Instruction, native_token::lamports_to_sol, packet::PACKET_DATA_SIZE,
+    program_pack::Pack, pubkey::Pubkey, signature::Keypair, signer::Signer,
     transaction::Transaction,
 };
 use spl_associated_token_account::{
-    get_associated_token_address, instruction::create_associated_token_account,
+    get_associated_token_address,
+    instruction::{create_associated_token_account, create_associated_token_account_idempotent},
 };
-use std::{env, sync::Arc};
+use std::sync::Arc;
+use tracing::{debug, info};
 
 use crate::{
-    constants::accounts::TOKEN_PROGRAM,
-    new_client,
+    config::bot_config,
+    constants::{
+        accounts::TOKEN_PROGRAM,
+        curve::{TOKEN_DECIMALS, TOKEN_TOTAL_SUPPLY},
+        jito::MAX_BUNDLE_TRANSACTIONS,
+    },
+    dex::{pumpswap::PumpSwap, Dex},
+    fees::priority,
+    math::slippage::Slippage,
+    metrics, new_client,
+    portfolio::{record_trade, Side},
     pumpfun::{
-        instructions::{create_buy_instruction, create_sell_instruction},
-        math::amount_with_slippage,
-        utils::{get_bonding_curve_account, get_global_account},
+        accounts::fee_included,
+        error::PumpfunError,
+        instructions::{create_buy_instruction, create_sell_instruction, create_token_instruction},
+        utils::{
+            create_token_meta_data, get_bonding_curve_account, get_global_account,
+            CreateTokenMetadata,
+        },
+    },
+    pumpswap,
+    raydium::{
+        self,
+        pools::find_sol_pool,
+        swap::{get_swap_tx, swap_exact_in, SwapAmount},
+        tx::paper,
     },
+    risk, timeline,
+    tx::{
+        blockhash::recent_blockhash,
+        budget::global_guard,
+        mode::ExecutionMode,
+        sender::{self, send_jito_transactions, Sender},
+        simulate::{simulate, ExpectedOutput, OutputAccount, TxOutcome},
+    },
+    wallet::{create_ata, frozen::check_sellable},
 };
 
 pub async fn buy(
@@ -28,61 +59,346 @@ pub async fn buy(
     amount_sol: u64,
     slippage: u64,
     is_simulate: bool,
-) -> Result<Vec<Signature>> {
+) -> Result<TxOutcome> {
     let mut instructions = vec![];
-    // 计算数量
+    // 计算数量，amount_sol 包含手续费
     let bonding_curve_account = get_bonding_curve_account(client.clone(), mint).await?;
-    let buy_amount = bonding_curve_account.get_buy_price(amount_sol).unwrap();
-
-    // 滑点
-    let buy_amount_with_slippage = amount_with_slippage(buy_amount, slippage * 100, true)?;
+    let global_account = get_global_account(client.clone()).await?;
+    let buy_amount = bonding_curve_account
+        .get_buy_price_with_fee(amount_sol, global_account.fee_basis_points)
+        .map_err(|e| anyhow!(e))?;
 
-    // 获取关联账户
-    let mint_ata = get_associated_token_address(&payer.pubkey(), &mint);
-    println!("mint_ata {:?}", mint_ata);
+    // 滑点，最多花费的sol
+    let max_sol_cost = Slippage::Percent(slippage).max_in(amount_sol)?;
 
-    // 获取不到关联账户，需要创建
-    if client.get_account(&mint_ata).await.is_err() {
-        instructions.push(create_associated_token_account(
-            &payer.pubkey(),
-            &payer.pubkey(),
-            &mint,
-            &TOKEN_PROGRAM,
-        ));
+    // 关联账户，默认幂等创建
+    if let Some(create) = create_ata(&client, &payer.pubkey(), mint, &TOKEN_PROGRAM).await {
+        instructions.push(create);
     }
 
+    // 余额检查
+    let ata = get_associated_token_address(&payer.pubkey(), mint);
+    ensure_balance(&client, &payer.pubkey(), max_sol_cost, &[ata]).await?;
+
+    // 风控、预算和冷却检查
+    let reservation = if !ExecutionMode::resolve(is_simulate).simulates() {
+        risk::check_buy(mint, amount_sol)?;
+        Some(global_guard().reserve(mint, amount_sol)?)
+    } else {
+        None
+    };
+
     // buy指令
     instructions.push(create_buy_instruction(
         payer,
         mint,
         buy_amount,
-        buy_amount_with_slippage,
+        max_sol_cost,
     ));
-    let recent_blockhash = client.get_latest_blockhash().await.unwrap();
+    let expected = ExpectedOutput {
+        expected_out: buy_amount,
+        min_out: buy_amount,
+        account: OutputAccount::Token(get_associated_token_address(&payer.pubkey(), mint)),
+    };
+    let outcome =
+        send_or_simulate(client, payer, &instructions, is_simulate, "buy", expected).await?;
+    if let Some(reservation) = reservation {
+        reservation.commit();
+    }
+    record_trade("pumpfun", Side::Buy, mint, buy_amount, amount_sol, &outcome);
+    Ok(outcome)
+}
 
-    // 创建交易
-    let txn = Transaction::new_signed_with_payer(
-        &instructions,
-        Some(&payer.pubkey()),
-        &[payer],
-        recent_blockhash,
+/// Buys exactly `token_amount` tokens, paying at most `max_sol` lamports
+pub async fn buy_exact_tokens(
+    client: Arc<RpcClient>,
+    payer: &Keypair,
+    mint: &Pubkey,
+    token_amount: u64,
+    max_sol: u64,
+    slippage: u64,
+    is_simulate: bool,
+) -> Result<TxOutcome> {
+    let mut instructions = vec![];
+    let bonding_curve_account = get_bonding_curve_account(client.clone(), mint).await?;
+    let global_account = get_global_account(client.clone()).await?;
+
+    // 买到指定数量代币需要的sol，包含手续费
+    let sol_cost = bonding_curve_account
+        .get_buy_sol_cost(token_amount)
+        .map_err(|e| anyhow!(e))?;
+    let required_sol = sol_cost + global_account.trade_fee(sol_cost);
+    if required_sol > max_sol {
+        return Err(PumpfunError::MaxSolExceeded {
+            token_amount,
+            required_sol,
+            max_sol,
+        }
+        .into());
+    }
+
+    // 滑点，不超过上限
+    let max_sol_cost = Slippage::Percent(slippage)
+        .max_in(required_sol)?
+        .min(max_sol);
+
+    if let Some(create) = create_ata(&client, &payer.pubkey(), mint, &TOKEN_PROGRAM).await {
+        instructions.push(create);
+    }
+
+    let ata = get_associated_token_address(&payer.pubkey(), mint);
+    ensure_balance(&client, &payer.pubkey(), max_sol_cost, &[ata]).await?;
+
+    let reservation = if !ExecutionMode::resolve(is_simulate).simulates() {
+        risk::check_buy(mint, max_sol_cost)?;
+        Some(global_guard().reserve(mint, max_sol_cost)?)
+    } else {
+        None
+    };
+
+    instructions.push(create_buy_instruction(
+        payer,
+        mint,
+        token_amount,
+        max_sol_cost,
+    ));
+
+    let expected = ExpectedOutput {
+        expected_out: token_amount,
+        min_out: token_amount,
+        account: OutputAccount::Token(get_associated_token_address(&payer.pubkey(), mint)),
+    };
+    let outcome =
+        send_or_simulate(client, payer, &instructions, is_simulate, "buy", expected).await?;
+    if let Some(reservation) = reservation {
+        reservation.commit();
+    }
+    record_trade(
+        "pumpfun",
+        Side::Buy,
+        mint,
+        token_amount,
+        required_sol,
+        &outcome,
     );
+    Ok(outcome)
+}
+
+/// Buys each mint of `buys` with its lamports, all or none of them
+///
+/// The buys and their token account creations are packed into one
+/// transaction sent like [`send_or_simulate_signed`] when they fit, else into
+/// a Jito bundle of up to [`MAX_BUNDLE_TRANSACTIONS`], which lands whole or
+/// not at all too.
+pub async fn buy_many(
+    client: Arc<RpcClient>,
+    payer: &Keypair,
+    buys: &[(Pubkey, u64)],
+    slippage: u64,
+    is_simulate: bool,
+) -> Result<TxOutcome> {
+    if buys.is_empty() {
+        return Err(anyhow!("nothing to buy"));
+    }
+    if let Some((i, (mint, _))) = buys
+        .iter()
+        .enumerate()
+        .find(|(i, (mint, _))| buys[..*i].iter().any(|(other, _)| other == mint))
+    {
+        return Err(anyhow!("{} bought twice, at {}", mint, i));
+    }
+    let global_account = get_global_account(client.clone()).await?;
+
+    let mut groups = vec![];
+    let mut token_amounts = vec![];
+    let mut max_sol_total = 0;
+    for &(mint, amount_sol) in buys {
+        let curve = get_bonding_curve_account(client.clone(), &mint).await?;
+        let token_amount = curve
+            .get_buy_price_with_fee(amount_sol, global_account.fee_basis_points)
+            .map_err(|e| anyhow!("can't buy {} for {} lamports: {}", mint, amount_sol, e))?;
+        let max_sol_cost = Slippage::Percent(slippage).max_in(amount_sol)?;
+        max_sol_total += max_sol_cost;
+        token_amounts.push(token_amount);
+        // 每个代币的账户创建和买入放在同一笔交易
+        groups.push(vec![
+            create_associated_token_account_idempotent(
+                &payer.pubkey(),
+                &payer.pubkey(),
+                &mint,
+                &TOKEN_PROGRAM,
+            ),
+            create_buy_instruction(payer, &mint, token_amount, max_sol_cost),
+        ]);
+    }
 
-    if is_simulate {
-        let simulate_result = client.simulate_transaction(&txn).await?;
-        if let Some(logs) = simulate_result.value.logs {
-            for log in logs {
-                println!("{}", log);
+    let new_atas: Vec<Pubkey> = buys
+        .iter()
+        .map(|(mint, _)| get_associated_token_address(&payer.pubkey(), mint))
+        .collect();
+    ensure_balance(&client, &payer.pubkey(), max_sol_total, &new_atas).await?;
+
+    // 先检查全部代币再预留，失败时已预留的随之释放
+    let mode = ExecutionMode::resolve(is_simulate);
+    let reservations = if !mode.simulates() {
+        for (mint, amount_sol) in buys {
+            risk::check_buy(mint, *amount_sol)?;
+        }
+        buys.iter()
+            .map(|(mint, amount_sol)| global_guard().reserve(mint, *amount_sol))
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        vec![]
+    };
+
+    let blockhash = recent_blockhash(&client).await?;
+    // 加上当前发送方的优先费和小费仍放得进一笔时按单笔发送
+    let sender = sender::current();
+    let mut with_fees = groups.clone();
+    if sender.pays_priority_fee() {
+        with_fees[0].insert(0, ComputeBudgetInstruction::set_compute_unit_price(0));
+    }
+    let tip = sender.tip(&payer.pubkey());
+    if pack_transactions(payer, &with_fees, tip, blockhash)?.len() == 1 {
+        let outcome = send_or_simulate_signed(
+            client,
+            payer,
+            &[payer],
+            &groups.concat(),
+            is_simulate,
+            "buy",
+            None,
+        )
+        .await?;
+        for reservation in reservations {
+            reservation.commit();
+        }
+        for ((mint, amount_sol), token_amount) in buys.iter().zip(token_amounts) {
+            record_trade(
+                "pumpfun",
+                Side::Buy,
+                mint,
+                token_amount,
+                *amount_sol,
+                &outcome,
+            );
+        }
+        return Ok(outcome);
+    }
+
+    // 放不进一笔交易，改用bundle，最后一笔付小费
+    timeline::mark_built();
+    let tip = Sender::Jito.tip(&payer.pubkey());
+    let transactions = pack_transactions(payer, &groups, tip, blockhash)?;
+    if transactions.len() > MAX_BUNDLE_TRANSACTIONS {
+        return Err(anyhow!(
+            "{} buys need {} transactions, a bundle holds {}",
+            buys.len(),
+            transactions.len(),
+            MAX_BUNDLE_TRANSACTIONS
+        ));
+    }
+
+    let outcome = match mode {
+        ExecutionMode::Simulate => {
+            // 逐笔模拟
+            let mut summary = None;
+            for txn in &transactions {
+                summary = Some(simulate(&client, txn, None).await?);
             }
+            TxOutcome::Simulated(summary.expect("buys have transactions"))
         }
-        return match simulate_result.value.err {
-            Some(err) => Err(anyhow!("{}", err)),
-            None => Ok(vec![]),
+        ExecutionMode::Paper => paper(&transactions[0]),
+        ExecutionMode::Live => {
+            metrics::record_trade_attempt("pumpfun", "buy");
+            let bundle_id = send_jito_transactions(&transactions).await?;
+            info!(
+                "bundle id: {}, {} buys in {} transactions",
+                bundle_id,
+                buys.len(),
+                transactions.len()
+            );
+            timeline::mark_sent();
+            metrics::record_trade_success("pumpfun", "buy");
+            TxOutcome::Sent(transactions.iter().map(|txn| txn.signatures[0]).collect())
+        }
+    };
+    for reservation in reservations {
+        reservation.commit();
+    }
+    for ((mint, amount_sol), token_amount) in buys.iter().zip(token_amounts) {
+        // 每笔买入按所在的交易记录
+        let txn = transactions
+            .iter()
+            .find(|txn| txn.message.account_keys.contains(mint))
+            .expect("every buy is packed");
+        let fill_outcome = match &outcome {
+            TxOutcome::Sent(_) => TxOutcome::Sent(vec![txn.signatures[0]]),
+            TxOutcome::Paper(_) => TxOutcome::Paper(txn.signatures[0]),
+            simulated => simulated.clone(),
         };
-    } else {
-        let res = client.send_transaction(&txn).await?;
-        Ok(vec![res])
+        record_trade(
+            "pumpfun",
+            Side::Buy,
+            mint,
+            token_amount,
+            *amount_sol,
+            &fill_outcome,
+        );
     }
+    Ok(outcome)
+}
+
+/// Signs the instruction `groups` into as few transactions as fit in a
+/// packet, never splitting a group, `tip` going last in the last one
+fn pack_transactions(
+    payer: &Keypair,
+    groups: &[Vec<Instruction>],
+    tip: Option<Instruction>,
+    recent_blockhash: Hash,
+) -> Result<Vec<Transaction>> {
+    let sign = |instructions: &[Instruction]| {
+        Transaction::new_signed_with_payer(
+            instructions,
+            Some(&payer.pubkey()),
+            &[payer],
+            recent_blockhash,
+        )
+    };
+    let fits = |instructions: &[Instruction]| -> Result<bool> {
+        Ok(bincode::serialized_size(&sign(instructions))? as usize <= PACKET_DATA_SIZE)
+    };
+
+    let mut packed: Vec<Vec<Instruction>> = vec![];
+    let mut current: Vec<Instruction> = vec![];
+    for group in groups {
+        let mut candidate = current.clone();
+        candidate.extend(group.iter().cloned());
+        if fits(&candidate)? {
+            current = candidate;
+            continue;
+        }
+        if current.is_empty() || !fits(group)? {
+            return Err(anyhow!(
+                "{} instructions don't fit in a transaction",
+                group.len()
+            ));
+        }
+        packed.push(std::mem::replace(&mut current, group.clone()));
+    }
+    if let Some(tip) = tip {
+        current.push(tip.clone());
+        if !fits(&current)? {
+            // 小费单独一笔
+            current.pop();
+            packed.push(std::mem::replace(&mut current, vec![tip]));
+        }
+    }
+    packed.push(current);
+    Ok(packed
+        .iter()
+        .map(|instructions| sign(instructions))
+        .collect())
 }
 
 pub async fn sell(
@@ -92,14 +408,42 @@ pub async fn sell(
     amount_token: u64,
     slippage: u64,
     is_simulate: bool,
-) -> Result<Vec<Signature>> {
+) -> Result<TxOutcome> {
+    sell_tokens(
+        client,
+        payer,
+        mint,
+        amount_token,
+        slippage,
+        is_simulate,
+        false,
+    )
+    .await
+}
+
+/// Sells `amount_token` raw tokens on the bonding curve, closing the token
+/// account afterwards if `close_ata`
+async fn sell_tokens(
+    client: Arc<RpcClient>,
+    payer: &Keypair,
+    mint: &Pubkey,
+    amount_token: u64,
+    slippage: u64,
+    is_simulate: bool,
+    close_ata: bool,
+) -> Result<TxOutcome> {
     // 获取当前账户余额
     let payer_pub_key = &payer.pubkey();
+    check_sellable(&client, payer_pub_key, mint).await?;
     let ata = get_associated_token_address(payer_pub_key, mint);
-    let token_balance = client.get_token_account_balance(&ata).await?;
-    let token_balance_u64 = token_balance.ui_amount.unwrap() as u64;
-
-    assert!(token_balance_u64 >= amount_token);
+    let token_balance = raw_token_balance(&client, &ata).await?;
+    if token_balance < amount_token {
+        return Err(PumpfunError::InsufficientTokens {
+            amount: amount_token,
+            balance: token_balance,
+        }
+        .into());
+    }
 
     // bonding curve
     let bonding_curve = get_bonding_curve_account(client.clone(), mint).await?;
@@ -108,48 +452,533 @@ pub async fn sell(
 
     let sol_output = bonding_curve
         .get_sell_price(amount_token, global_account.fee_basis_points)
-        .unwrap();
-    let min_sol_output = amount_with_slippage(sol_output, slippage * 100, false).unwrap();
+        .map_err(|e| anyhow!(e))?;
+    let min_sol_output = Slippage::Percent(slippage).min_out(sol_output)?;
 
     // 创建sell指令
-    let mut instructions = vec![];
-    instructions.push(create_sell_instruction(
+    let mut instructions = vec![create_sell_instruction(
         payer,
         mint,
-        sol_output,
+        amount_token,
         min_sol_output,
-    ));
-    let recent_blockhash = client.get_latest_blockhash().await.unwrap();
+    )];
+    // 卖出全部余额后关闭账户，取回租金
+    if close_ata {
+        instructions.push(spl_token::instruction::close_account(
+            &TOKEN_PROGRAM,
+            &ata,
+            payer_pub_key,
+            payer_pub_key,
+            &[payer_pub_key],
+        )?);
+    }
+    let expected = ExpectedOutput {
+        expected_out: sol_output,
+        min_out: min_sol_output,
+        account: OutputAccount::Lamports(payer.pubkey()),
+    };
+    let outcome =
+        send_or_simulate(client, payer, &instructions, is_simulate, "sell", expected).await?;
+    record_trade(
+        "pumpfun",
+        Side::Sell,
+        mint,
+        amount_token,
+        sol_output,
+        &outcome,
+    );
+    Ok(outcome)
+}
+
+/// Sells `amount_token` raw tokens on the bonding curve, or through the
+/// migrated PumpSwap or Raydium pool once the curve is complete
+pub async fn sell_auto(
+    client: Arc<RpcClient>,
+    payer: &Keypair,
+    mint: &Pubkey,
+    amount_token: u64,
+    slippage: u64,
+    is_simulate: bool,
+) -> Result<TxOutcome> {
+    let bonding_curve = get_bonding_curve_account(client.clone(), mint).await?;
+    if !bonding_curve.complete {
+        return sell(client, payer, mint, amount_token, slippage, is_simulate).await;
+    }
+
+    // 已迁移，先找pumpswap池子，再找raydium
+    check_sellable(&client, &payer.pubkey(), mint).await?;
+    if has_pumpswap_pool(&client, mint).await {
+        return pumpswap_swap(
+            client,
+            payer,
+            mint,
+            Side::Sell,
+            amount_token,
+            slippage,
+            is_simulate,
+            false,
+        )
+        .await;
+    }
+    let pool_id = find_sol_pool(client.clone(), mint).await?;
+    info!(
+        "{} migrated, selling through raydium pool {}",
+        mint, pool_id
+    );
+    swap_exact_in(
+        client,
+        &mint.to_string(),
+        &spl_token::native_mint::ID.to_string(),
+        SwapAmount::Raw(amount_token),
+        &pool_id.to_string(),
+        slippage,
+        Arc::new(payer.insecure_clone()),
+        is_simulate,
+        false,
+    )
+    .await
+}
+
+/// Sells the wallet's whole raw balance of `mint`, on the bonding curve or
+/// through the migrated PumpSwap or Raydium pool, and closes its token
+/// account to reclaim the rent
+pub async fn sell_all(
+    client: Arc<RpcClient>,
+    payer: &Keypair,
+    mint: &Pubkey,
+    slippage: u64,
+    is_simulate: bool,
+) -> Result<TxOutcome> {
+    let bonding_curve = get_bonding_curve_account(client.clone(), mint).await?;
+    if bonding_curve.complete && has_pumpswap_pool(&client, mint).await {
+        check_sellable(&client, &payer.pubkey(), mint).await?;
+        let ata = get_associated_token_address(&payer.pubkey(), mint);
+        let balance = raw_token_balance(&client, &ata).await?;
+        if balance == 0 {
+            return Err(anyhow!("no {} to sell", mint));
+        }
+        return pumpswap_swap(
+            client,
+            payer,
+            mint,
+            Side::Sell,
+            balance,
+            slippage,
+            is_simulate,
+            true,
+        )
+        .await;
+    }
+    if bonding_curve.complete {
+        let pool_id = find_sol_pool(client.clone(), mint).await?;
+        return raydium::swap::sell_all(
+            client,
+            Arc::new(payer.insecure_clone()),
+            mint,
+            &pool_id.to_string(),
+            slippage,
+            is_simulate,
+        )
+        .await;
+    }
+    let ata = get_associated_token_address(&payer.pubkey(), mint);
+    let balance = raw_token_balance(&client, &ata).await?;
+    if balance == 0 {
+        return Err(anyhow!("no {} to sell", mint));
+    }
+    sell_tokens(client, payer, mint, balance, slippage, is_simulate, true).await
+}
+
+/// Whether a completed curve's `mint` migrated to PumpSwap, rather than
+/// Raydium
+async fn has_pumpswap_pool(client: &Arc<RpcClient>, mint: &Pubkey) -> bool {
+    match pumpswap::pools::find_sol_pool(client.clone(), mint).await {
+        Ok(_) => true,
+        Err(e) => {
+            debug!("no pumpswap pool for {} {:?}", mint, e);
+            false
+        }
+    }
+}
+
+/// Swaps `amount_in` in the PumpSwap pool of `mint`, lamports on a buy and
+/// raw tokens on a sell, closing the token account after a sell with
+/// `close_ata`
+#[allow(clippy::too_many_arguments)]
+async fn pumpswap_swap(
+    client: Arc<RpcClient>,
+    payer: &Keypair,
+    mint: &Pubkey,
+    side: Side,
+    amount_in: u64,
+    slippage: u64,
+    is_simulate: bool,
+    close_ata: bool,
+) -> Result<TxOutcome> {
+    let owner = payer.pubkey();
+    let expected_out = PumpSwap.quote(&client, mint, side, amount_in).await?;
+    let min_out = Slippage::Percent(slippage).min_out(expected_out)?;
+    let reservation = if side == Side::Buy && !ExecutionMode::resolve(is_simulate).simulates() {
+        risk::check_buy(mint, amount_in)?;
+        Some(global_guard().reserve(mint, amount_in)?)
+    } else {
+        None
+    };
+    let mut instructions = PumpSwap
+        .build_swap_ix(&client, payer, mint, side, amount_in, min_out)
+        .await?;
+    let ata = get_associated_token_address(&owner, mint);
+    if close_ata {
+        instructions.push(spl_token::instruction::close_account(
+            &TOKEN_PROGRAM,
+            &ata,
+            &owner,
+            &owner,
+            &[&owner],
+        )?);
+    }
+    let (account, side_name, token_amount, sol_amount) = match side {
+        Side::Buy => (OutputAccount::Token(ata), "buy", expected_out, amount_in),
+        Side::Sell => (
+            OutputAccount::Lamports(owner),
+            "sell",
+            amount_in,
+            expected_out,
+        ),
+    };
+    info!(
+        "{} {} through pumpswap, {} in, at least {} out",
+        side_name, mint, amount_in, min_out
+    );
+    let expected = ExpectedOutput {
+        expected_out,
+        min_out,
+        account,
+    };
+    let outcome = send_or_simulate(
+        client,
+        payer,
+        &instructions,
+        is_simulate,
+        side_name,
+        expected,
+    )
+    .await?;
+    if let Some(reservation) = reservation {
+        reservation.commit();
+    }
+    record_trade("pumpswap", side, mint, token_amount, sol_amount, &outcome);
+    Ok(outcome)
+}
+
+/// Raw balance of the token account `ata`
+///
+/// `ui_amount` is scaled by the decimals and loses precision, so the raw
+/// `amount` is parsed instead.
+async fn raw_token_balance(client: &RpcClient, ata: &Pubkey) -> Result<u64> {
+    Ok(client
+        .get_token_account_balance(ata)
+        .await?
+        .amount
+        .parse()?)
+}
+
+/// Raw tokens making `pct` percent of `balance`, rounded down
+pub fn percentage_of(balance: u64, pct: f64) -> Result<u64> {
+    if !(pct > 0.0 && pct <= 100.0) {
+        return Err(anyhow!("sell percentage {} not in (0, 100]", pct));
+    }
+    // 换成基点整数计算，避免大余额的浮点误差
+    let bps = (pct * 100.0).round() as u128;
+    Ok((balance as u128 * bps / 10000) as u64)
+}
+
+/// Raw tokens making `pct` percent of a Pump.fun token's supply
+pub fn supply_tokens(pct: f64) -> Result<u64> {
+    percentage_of(TOKEN_TOTAL_SUPPLY, pct)
+        .map_err(|_| anyhow!("supply percentage {} not in (0, 100]", pct))
+}
+
+/// Raw tokens of the UI `amount` of a Pump.fun token
+pub fn raw_tokens(amount: f64) -> u64 {
+    spl_token::ui_amount_to_amount(amount, TOKEN_DECIMALS)
+}
+
+/// Sells `pct` percent of the wallet's raw balance of `mint`, on the bonding
+/// curve or through the migrated PumpSwap or Raydium pool; all of it, closing
+/// the token account, at 100
+pub async fn sell_percentage(
+    client: Arc<RpcClient>,
+    payer: &Keypair,
+    mint: &Pubkey,
+    pct: f64,
+    slippage: u64,
+    is_simulate: bool,
+) -> Result<TxOutcome> {
+    let ata = get_associated_token_address(&payer.pubkey(), mint);
+    let balance = raw_token_balance(&client, &ata).await?;
+    let amount = percentage_of(balance, pct)?;
+    if amount == 0 {
+        return Err(anyhow!("no {} to sell", mint));
+    }
+    if amount == balance {
+        return sell_all(client, payer, mint, slippage, is_simulate).await;
+    }
+    sell_auto(client, payer, mint, amount, slippage, is_simulate).await
+}
+
+/// Buys `mint` with `amount_sol` lamports on the bonding curve, through the
+/// migrated PumpSwap or Raydium pool once the curve is complete, or through
+/// the Raydium pool for non Pump.fun tokens
+pub async fn buy_auto(
+    client: Arc<RpcClient>,
+    payer: &Keypair,
+    mint: &Pubkey,
+    amount_sol: u64,
+    slippage: u64,
+    is_simulate: bool,
+) -> Result<TxOutcome> {
+    if let Ok(bonding_curve) = get_bonding_curve_account(client.clone(), mint).await {
+        if !bonding_curve.complete {
+            return buy(client, payer, mint, amount_sol, slippage, is_simulate).await;
+        }
+        if has_pumpswap_pool(&client, mint).await {
+            return pumpswap_swap(
+                client,
+                payer,
+                mint,
+                Side::Buy,
+                amount_sol,
+                slippage,
+                is_simulate,
+                false,
+            )
+            .await;
+        }
+    }
+
+    let pool_id = find_sol_pool(client.clone(), mint).await?;
+    get_swap_tx(
+        client,
+        &spl_token::native_mint::ID.to_string(),
+        &mint.to_string(),
+        lamports_to_sol(amount_sol),
+        &pool_id.to_string(),
+        slippage,
+        Arc::new(payer.insecure_clone()),
+        is_simulate,
+    )
+    .await
+}
+
+/// Uploads `metadata` and creates its token, bundling a dev buy of
+/// `dev_buy_sol` lamports in the same transaction unless it's 0
+///
+/// Returns the new mint along with the outcome.
+pub async fn create_and_buy(
+    client: Arc<RpcClient>,
+    payer: &Keypair,
+    metadata: CreateTokenMetadata,
+    dev_buy_sol: u64,
+    slippage: u64,
+    is_simulate: bool,
+) -> Result<(Pubkey, TxOutcome)> {
+    let mint = Keypair::new();
+    let (name, symbol) = (metadata.name.clone(), metadata.symbol.clone());
+    let uri = create_token_meta_data(metadata).await?;
+    let mut instructions = vec![create_token_instruction(
+        payer,
+        &mint.pubkey(),
+        name,
+        symbol,
+        uri,
+    )];
+
+    let mut expected = None;
+    let mut buy_amount = 0;
+    let mut reservation = None;
+    if dev_buy_sol > 0 {
+        // 新的曲线，按初始储备计算，dev_buy_sol 包含手续费
+        let global_account = get_global_account(client.clone()).await?;
+        buy_amount = global_account.get_initial_buy_price(
+            dev_buy_sol - fee_included(dev_buy_sol, global_account.fee_basis_points),
+        );
+        let max_sol_cost = Slippage::Percent(slippage).max_in(dev_buy_sol)?;
+        let new_ata = get_associated_token_address(&payer.pubkey(), &mint.pubkey());
+        ensure_balance(&client, &payer.pubkey(), max_sol_cost, &[new_ata]).await?;
+        if !ExecutionMode::resolve(is_simulate).simulates() {
+            risk::check_buy(&mint.pubkey(), dev_buy_sol)?;
+            reservation = Some(global_guard().reserve(&mint.pubkey(), dev_buy_sol)?);
+        }
+        instructions.push(create_associated_token_account(
+            &payer.pubkey(),
+            &payer.pubkey(),
+            &mint.pubkey(),
+            &TOKEN_PROGRAM,
+        ));
+        instructions.push(create_buy_instruction(
+            payer,
+            &mint.pubkey(),
+            buy_amount,
+            max_sol_cost,
+        ));
+        expected = Some(ExpectedOutput {
+            expected_out: buy_amount,
+            min_out: buy_amount,
+            account: OutputAccount::Token(get_associated_token_address(
+                &payer.pubkey(),
+                &mint.pubkey(),
+            )),
+        });
+    }
+
+    let outcome = send_or_simulate_signed(
+        client,
+        payer,
+        &[payer, &mint],
+        &instructions,
+        is_simulate,
+        "create",
+        expected,
+    )
+    .await?;
+    if let Some(reservation) = reservation {
+        reservation.commit();
+    }
+    if dev_buy_sol > 0 {
+        record_trade(
+            "pumpfun",
+            Side::Buy,
+            &mint.pubkey(),
+            buy_amount,
+            dev_buy_sol,
+            &outcome,
+        );
+    }
+    Ok((mint.pubkey(), outcome))
+}
+
+/// Fails with `InsufficientBalance` unless the payer can cover `spend`, the
+/// fees of sending through the task's [`sender::current`] and the rent of
+/// those of `new_atas` that don't exist yet
+pub(crate) async fn ensure_balance(
+    client: &RpcClient,
+    payer: &Pubkey,
+    spend: u64,
+    new_atas: &[Pubkey],
+) -> Result<()> {
+    // 只算payer一个签名的费用
+    let sender = sender::current();
+    let mut fee = FeeStructure::default().lamports_per_signature;
+    if sender.pays_priority_fee() {
+        let unit_price = priority::unit_price(client, &[]).await as u128;
+        let unit_limit = bot_config().unit_limit as u128;
+        fee += (unit_price * unit_limit).div_ceil(1_000_000) as u64;
+    }
+    fee += sender.tip_lamports().unwrap_or(0);
+    let missing = if new_atas.is_empty() {
+        0
+    } else {
+        client
+            .get_multiple_accounts(new_atas)
+            .await?
+            .iter()
+            .filter(|account| account.is_none())
+            .count() as u64
+    };
+    let rent = if missing > 0 {
+        missing
+            * client
+                .get_minimum_balance_for_rent_exemption(spl_token::state::Account::LEN)
+                .await?
+    } else {
+        0
+    };
+    let required = spend + fee + rent;
+    let balance = client.get_balance(payer).await?;
+    if balance < required {
+        return Err(PumpfunError::InsufficientBalance {
+            required,
+            balance,
+            shortfall: required - balance,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+async fn send_or_simulate(
+    client: Arc<RpcClient>,
+    payer: &Keypair,
+    instructions: &[Instruction],
+    is_simulate: bool,
+    side: &str,
+    expected: ExpectedOutput,
+) -> Result<TxOutcome> {
+    send_or_simulate_signed(
+        client,
+        payer,
+        &[payer],
+        instructions,
+        is_simulate,
+        side,
+        Some(expected),
+    )
+    .await
+}
+
+/// Like [`send_or_simulate`], with every signer the instructions need
+///
+/// Live sends go through the task's [`sender::current`], with the priority
+/// fee from [`priority::unit_price`] and the tip the sender requires.
+async fn send_or_simulate_signed(
+    client: Arc<RpcClient>,
+    payer: &Keypair,
+    signers: &[&Keypair],
+    instructions: &[Instruction],
+    is_simulate: bool,
+    side: &str,
+    expected: Option<ExpectedOutput>,
+) -> Result<TxOutcome> {
+    timeline::mark_built();
+    let sender = sender::current();
+    let mut instructions = instructions.to_vec();
+    if sender.pays_priority_fee() {
+        let unit_price = priority::unit_price(&client, &instructions).await;
+        let add_priority_fee = ComputeBudgetInstruction::set_compute_unit_price(unit_price);
+        instructions.insert(0, add_priority_fee);
+    }
+    instructions.extend(sender.tip(&payer.pubkey()));
+    let recent_blockhash = recent_blockhash(&client).await?;
 
     // 创建交易
     let txn = Transaction::new_signed_with_payer(
         &instructions,
         Some(&payer.pubkey()),
-        &[payer],
+        signers,
         recent_blockhash,
     );
 
-    if is_simulate {
-        let simulate_result = client.simulate_transaction(&txn).await?;
-        if let Some(logs) = simulate_result.value.logs {
-            for log in logs {
-                println!("{}", log);
-            }
+    match ExecutionMode::resolve(is_simulate) {
+        ExecutionMode::Simulate => {
+            let summary = simulate(&client, &txn, expected).await?;
+            Ok(TxOutcome::Simulated(summary))
+        }
+        ExecutionMode::Paper => Ok(paper(&txn)),
+        ExecutionMode::Live => {
+            metrics::record_trade_attempt("pumpfun", side);
+            let sig = sender.submit(&client, &txn).await?;
+            metrics::record_trade_success("pumpfun", side);
+            info!("{} signature: {:?}", sender, sig);
+            Ok(TxOutcome::Sent(vec![sig]))
         }
-        return match simulate_result.value.err {
-            Some(err) => Err(anyhow!("{}", err)),
-            None => Ok(vec![]),
-        };
-    } else {
-        let res = client.send_transaction(&txn).await?;
-        Ok(vec![res])
     }
 }
 
 #[tokio::test]
 async fn test_buy() {
     dotenv::dotenv().ok();
-    let keypair = Keypair::from_base58_string(&env::var("PK").unwrap());
+    let keypair = crate::config::bot_config().keypair().unwrap();
     let mint = Pubkey::from_str_const("8vbjWGXKhrKfVMCXpLrUGyUUHKNfmvRiuT2Dn2h1pump");
 
     let client = new_client();
@@ -159,9 +988,61 @@ async fn test_buy() {
 #[tokio::test]
 async fn test_sell() {
     dotenv::dotenv().ok();
-    let keypair = Keypair::from_base58_string(&env::var("PK").unwrap());
+    let keypair = crate::config::bot_config().keypair().unwrap();
     let mint = Pubkey::from_str_const("8vbjWGXKhrKfVMCXpLrUGyUUHKNfmvRiuT2Dn2h1pump");
 
     let client = new_client();
     sell(client, &keypair, &mint, 1, 2, true).await.unwrap();
 }
+
+#[test]
+fn test_sell_percentage_amounts() {
+    // 6 位小数的代币，原始数量不截断
+    let balance = 123_456_789_012_345;
+    assert_eq!(percentage_of(balance, 100.0).unwrap(), balance);
+    assert_eq!(percentage_of(balance, 50.0).unwrap(), 61_728_394_506_172);
+    assert_eq!(percentage_of(balance, 0.01).unwrap(), 12_345_678_901);
+    assert_eq!(percentage_of(1, 50.0).unwrap(), 0);
+    assert!(percentage_of(balance, 0.0).is_err());
+    assert!(percentage_of(balance, 150.0).is_err());
+    // 总供应量的1%
+    assert_eq!(supply_tokens(1.0).unwrap(), 10_000_000_000_000);
+    assert_eq!(raw_tokens(1.5), 1_500_000);
+}
+
+#[test]
+fn test_pack_buys() {
+    let payer = Keypair::new();
+    let tip_account = Pubkey::new_unique();
+    let tip = solana_sdk::system_instruction::transfer(&payer.pubkey(), &tip_account, 1_000);
+    let groups: Vec<Vec<Instruction>> = (0..6)
+        .map(|_| {
+            let mint = Pubkey::new_unique();
+            vec![
+                create_associated_token_account_idempotent(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM,
+                ),
+                create_buy_instruction(&payer, &mint, 1_000, 1_000),
+            ]
+        })
+        .collect();
+
+    let single = pack_transactions(&payer, &groups[..1], None, Hash::default()).unwrap();
+    assert_eq!(single.len(), 1);
+    assert_eq!(single[0].message.instructions.len(), 2);
+
+    let txns = pack_transactions(&payer, &groups, Some(tip), Hash::default()).unwrap();
+    assert!(txns.len() > 1);
+    for txn in &txns {
+        txn.verify().unwrap();
+        assert!(bincode::serialized_size(txn).unwrap() as usize <= PACKET_DATA_SIZE);
+    }
+    // 不拆开同一个代币的指令，小费在最后
+    let instructions: usize = txns.iter().map(|txn| txn.message.instructions.len()).sum();
+    assert_eq!(instructions, groups.len() * 2 + 1);
+    let tips = |txn: &Transaction| txn.message.account_keys.contains(&tip_account);
+    assert!(tips(&txns[txns.len() - 1]) && !tips(&txns[0]));
+}
@@ -3,16 +3,28 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use reqwest::multipart::{Form, Part};
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::pubkey::Pubkey;
-use std::{fs::File, io::Read, sync::Arc};
-
-use crate::constants;
+use std::{
+    fs::File,
+    io::Read,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::program_ids,
+    constants,
+    rpc::{
+        cache::{account_cache, get_accounts, AccountKind},
+        retry::with_retry,
+    },
+};
 
 use super::accounts::{BondingCurveAccount, GlobalAccount};
 
 /// 获取bonding curve
 pub fn get_bonding_curve_pda(mint: &Pubkey) -> Option<Pubkey> {
     let seeds: &[&[u8]; 2] = &[constants::seeds::BONDING_CURVE_SEED, mint.as_ref()];
-    let program_id: &Pubkey = &constants::accounts::PUMPFUN;
+    let program_id: &Pubkey = &program_ids().pumpfun;
     let pda: Option<(Pubkey, u8)> = Pubkey::try_find_program_address(seeds, program_id);
     pda.map(|pubkey| pubkey.0)
 }
@@ -24,45 +36,96 @@ pub async fn get_bonding_curve_account(
 ) -> Result<BondingCurveAccount> {
     let bonding_curve_pda = get_bonding_curve_pda(mint).ok_or(anyhow!("BondingCurveNotFound"))?;
 
-    let account = client
-        .get_account(&bonding_curve_pda)
-        .await
-        .map_err(|_keypair| anyhow!("SolanaClientError"))?;
+    if account_cache().is_some() {
+        let accounts =
+            get_accounts(client, &[(bonding_curve_pda, AccountKind::BondingCurve)]).await?;
+        return accounts
+            .into_iter()
+            .flatten()
+            .find_map(|account| account.bonding_curve().cloned())
+            .ok_or(anyhow!("BondingCurveNotFound"));
+    }
+
+    // 账户不存在时不重试
+    let account =
+        with_retry(|| client.get_account_with_commitment(&bonding_curve_pda, client.commitment()))
+            .await
+            .map_err(|e| anyhow!("SolanaClientError {:?}", e))?
+            .value
+            .ok_or(anyhow!("BondingCurveNotFound"))?;
 
     BondingCurveAccount::try_from_slice(&account.data).map_err(|_| anyhow!("BorshError"))
 }
 
+/// 获取mint authority地址
+pub fn get_mint_authority_pda() -> Pubkey {
+    let seeds: &[&[u8]; 1] = &[constants::seeds::MINT_AUTHORITY_SEED];
+    Pubkey::find_program_address(seeds, &program_ids().pumpfun).0
+}
+
+/// 获取代币metadata地址
+pub fn get_metadata_pda(mint: &Pubkey) -> Pubkey {
+    let metadata_program = constants::accounts::MPL_TOKEN_METADATA;
+    let seeds: &[&[u8]; 3] = &[
+        constants::seeds::METADATA_SEED,
+        metadata_program.as_ref(),
+        mint.as_ref(),
+    ];
+    Pubkey::find_program_address(seeds, &metadata_program).0
+}
+
 /// 获取global program地址
 pub fn get_global_pda() -> Pubkey {
     let seeds: &[&[u8]; 1] = &[constants::seeds::GLOBAL_SEED];
-    let program_id: &Pubkey = &constants::accounts::PUMPFUN;
+    let program_id: &Pubkey = &program_ids().pumpfun;
     Pubkey::find_program_address(seeds, program_id).0
 }
 
-/// 获取global program的账户封装
+/// How long a fetched global account is reused, its fee parameters rarely
+/// change
+const GLOBAL_ACCOUNT_TTL: Duration = Duration::from_secs(60);
+
+static GLOBAL_ACCOUNT: Mutex<Option<(Instant, GlobalAccount)>> = Mutex::new(None);
+
+/// 获取global program的账户封装，缓存 [`GLOBAL_ACCOUNT_TTL`]
 pub async fn get_global_account(client: Arc<RpcClient>) -> Result<GlobalAccount> {
+    if let Some((fetched_at, global)) = &*GLOBAL_ACCOUNT.lock().unwrap() {
+        if fetched_at.elapsed() < GLOBAL_ACCOUNT_TTL {
+            return Ok(global.clone());
+        }
+    }
+    let global = fetch_global_account(client).await?;
+    *GLOBAL_ACCOUNT.lock().unwrap() = Some((Instant::now(), global.clone()));
+    Ok(global)
+}
+
+async fn fetch_global_account(client: Arc<RpcClient>) -> Result<GlobalAccount> {
     let global: Pubkey = get_global_pda();
 
-    let account = client
-        .get_account(&global)
+    let account = with_retry(|| client.get_account_with_commitment(&global, client.commitment()))
         .await
-        .map_err(|_| anyhow!("SolanaClientError"))?;
+        .map_err(|e| anyhow!("SolanaClientError {:?}", e))?
+        .value
+        .ok_or(anyhow!("GlobalAccountNotFound"))?;
 
-    GlobalAccount::try_from_slice(&account.data).map_err(|e| anyhow!("BorshError"))
+    GlobalAccount::try_from_slice(&account.data).map_err(|_| anyhow!("BorshError"))
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct CreateTokenMetadata {
-    name: String,
-    symbol: String,
-    description: String,
-    twitter: Option<String>,
-    telegram: Option<String>,
-    website: Option<String>,
-    show_name: bool,
-    file: String,
+    pub name: String,
+    /// Token symbol (e.g. "BTC")
+    pub symbol: String,
+    pub description: String,
+    pub twitter: Option<String>,
+    pub telegram: Option<String>,
+    pub website: Option<String>,
+    pub show_name: bool,
+    /// Path to the token's png image
+    pub file: String,
 }
 
+/// Uploads `create_meta_data` and its image to Pump.fun's IPFS, returning the metadata uri
 pub async fn create_token_meta_data(create_meta_data: CreateTokenMetadata) -> Result<String> {
     let mut file = File::open(create_meta_data.file)?;
     let mut file_content = Vec::new();
@@ -97,9 +160,14 @@ pub async fn create_token_meta_data(create_meta_data: CreateTokenMetadata) -> Re
         .multipart(form)
         .send()
         .await?;
-    let metadata_response_json = metadata_response.text().await?;
-    println!("Metadata URI: {}", metadata_response_json);
-    Ok(metadata_response_json)
+    let metadata_response_json: serde_json::Value =
+        metadata_response.error_for_status()?.json().await?;
+    let uri = metadata_response_json["metadataUri"]
+        .as_str()
+        .ok_or_else(|| anyhow!("no metadataUri in {}", metadata_response_json))?
+        .to_string();
+    println!("Metadata URI: {}", uri);
+    Ok(uri)
 }
 
 #[tokio::test]
@@ -0,0 +1,107 @@
+use anyhow::Result;
+use borsh::BorshDeserialize;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{config::program_ids, pumpswap::error::PumpSwapError};
+
+/// Anchor discriminator of `Pool` accounts
+pub const POOL_DISCRIMINATOR: [u8; 8] = [241, 154, 109, 4, 17, 177, 109, 188];
+
+/// Anchor discriminator of the `GlobalConfig` account
+pub const GLOBAL_CONFIG_DISCRIMINATOR: [u8; 8] = [149, 8, 156, 202, 160, 252, 176, 217];
+
+/// A PumpSwap pool
+///
+/// Newer pools carry extra trailing fields, only this prefix is decoded.
+#[derive(Debug, Clone, BorshDeserialize)]
+pub struct Pool {
+    pub discriminator: [u8; 8],
+    pub pool_bump: u8,
+    pub index: u16,
+    pub creator: Pubkey,
+    /// The migrated token
+    pub base_mint: Pubkey,
+    /// WSOL for migrated Pump.fun tokens
+    pub quote_mint: Pubkey,
+    pub lp_mint: Pubkey,
+    pub pool_base_token_account: Pubkey,
+    pub pool_quote_token_account: Pubkey,
+    pub lp_supply: u64,
+}
+
+/// Fee configuration shared by every pool
+#[derive(Debug, Clone, BorshDeserialize)]
+pub struct GlobalConfig {
+    pub discriminator: [u8; 8],
+    pub admin: Pubkey,
+    /// Fee paid to liquidity providers, in basis points
+    pub lp_fee_basis_points: u64,
+    /// Fee paid to the protocol, in basis points
+    pub protocol_fee_basis_points: u64,
+    pub disable_flags: u8,
+    pub protocol_fee_recipients: [Pubkey; 8],
+}
+
+fn unpack<T: BorshDeserialize>(
+    kind: &'static str,
+    discriminator: [u8; 8],
+    pubkey: &Pubkey,
+    data: &[u8],
+) -> Result<T> {
+    if data.len() < 8 || data[..8] != discriminator {
+        return Err(PumpSwapError::InvalidAccount {
+            kind,
+            pubkey: *pubkey,
+        }
+        .into());
+    }
+    Ok(T::deserialize(&mut &data[..])?)
+}
+
+impl Pool {
+    pub fn unpack(pubkey: &Pubkey, data: &[u8]) -> Result<Self> {
+        unpack("pool", POOL_DISCRIMINATOR, pubkey, data)
+    }
+}
+
+impl GlobalConfig {
+    pub fn unpack(pubkey: &Pubkey, data: &[u8]) -> Result<Self> {
+        unpack("global config", GLOBAL_CONFIG_DISCRIMINATOR, pubkey, data)
+    }
+}
+
+/// The `GlobalConfig` account
+pub fn get_global_config_pda() -> Pubkey {
+    Pubkey::find_program_address(&[b"global_config"], &program_ids().pumpswap).0
+}
+
+/// Authority the program emits its events through
+pub fn get_event_authority_pda() -> Pubkey {
+    Pubkey::find_program_address(&[b"__event_authority"], &program_ids().pumpswap).0
+}
+
+#[test]
+fn test_unpack_pool_ignores_trailing_fields() {
+    let base_mint = Pubkey::new_unique();
+    let mut data = POOL_DISCRIMINATOR.to_vec();
+    data.push(255);
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(Pubkey::new_unique().as_ref());
+    data.extend_from_slice(base_mint.as_ref());
+    data.extend_from_slice(spl_token::native_mint::ID.as_ref());
+    data.extend_from_slice(&[0; 32 * 3]);
+    data.extend_from_slice(&42u64.to_le_bytes());
+    // 新版池子多出的字段
+    data.extend_from_slice(&[7; 32]);
+
+    let pool = Pool::unpack(&Pubkey::new_unique(), &data).unwrap();
+    assert_eq!(pool.base_mint, base_mint);
+    assert_eq!(pool.quote_mint, spl_token::native_mint::ID);
+    assert_eq!(pool.lp_supply, 42);
+
+    assert!(GlobalConfig::unpack(&Pubkey::new_unique(), &data).is_err());
+    assert_eq!(
+        solana_sdk::hash::hash(b"account:Pool").to_bytes()[..8],
+        POOL_DISCRIMINATOR
+    );
+}
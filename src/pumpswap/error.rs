@@ -0,0 +1,13 @@
+use solana_sdk::pubkey::Pubkey;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PumpSwapError {
+    #[error("{pubkey} is not a {kind} account")]
+    InvalidAccount { kind: &'static str, pubkey: Pubkey },
+    #[error("pool holds {base_reserve} base tokens, can't buy {base_amount_out}")]
+    InsufficientLiquidity {
+        base_amount_out: u64,
+        base_reserve: u64,
+    },
+}
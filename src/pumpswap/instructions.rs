@@ -0,0 +1,122 @@
+use borsh::BorshSerialize;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::{
+    config::program_ids,
+    constants::accounts::{ASSOCIATED_TOKEN_PROGRAM, SYSTEM_PROGRAM, TOKEN_PROGRAM},
+    pumpswap::accounts::{get_event_authority_pda, get_global_config_pda, Pool},
+};
+
+// 指令的标识符
+pub const BUY_DISCRIMINATOR: [u8; 8] = [102, 6, 61, 18, 1, 218, 235, 234];
+pub const SELL_DISCRIMINATOR: [u8; 8] = [51, 230, 133, 164, 1, 127, 131, 173];
+pub const CREATE_POOL_DISCRIMINATOR: [u8; 8] = [233, 146, 209, 142, 207, 104, 64, 188];
+
+#[derive(BorshSerialize)]
+struct BuyArgs {
+    base_amount_out: u64,
+    max_quote_amount_in: u64,
+}
+
+#[derive(BorshSerialize)]
+struct SellArgs {
+    base_amount_in: u64,
+    min_quote_amount_out: u64,
+}
+
+/// Accounts shared by `buy` and `sell`
+fn trade_accounts(
+    pool_pubkey: &Pubkey,
+    pool: &Pool,
+    user: &Pubkey,
+    protocol_fee_recipient: &Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*pool_pubkey, false),
+        AccountMeta::new(*user, true),
+        AccountMeta::new_readonly(get_global_config_pda(), false),
+        AccountMeta::new_readonly(pool.base_mint, false),
+        AccountMeta::new_readonly(pool.quote_mint, false),
+        AccountMeta::new(get_associated_token_address(user, &pool.base_mint), false),
+        AccountMeta::new(get_associated_token_address(user, &pool.quote_mint), false),
+        AccountMeta::new(pool.pool_base_token_account, false),
+        AccountMeta::new(pool.pool_quote_token_account, false),
+        AccountMeta::new_readonly(*protocol_fee_recipient, false),
+        AccountMeta::new(
+            get_associated_token_address(protocol_fee_recipient, &pool.quote_mint),
+            false,
+        ),
+        AccountMeta::new_readonly(TOKEN_PROGRAM, false),
+        AccountMeta::new_readonly(TOKEN_PROGRAM, false),
+        AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
+        AccountMeta::new_readonly(ASSOCIATED_TOKEN_PROGRAM, false),
+        AccountMeta::new_readonly(get_event_authority_pda(), false),
+        AccountMeta::new_readonly(program_ids().pumpswap, false),
+    ]
+}
+
+/// Buys exactly `base_amount_out` tokens, spending at most `max_quote_amount_in`
+///
+/// The user's quote account must already hold the WSOL to spend.
+pub fn create_buy_instruction(
+    pool_pubkey: &Pubkey,
+    pool: &Pool,
+    user: &Pubkey,
+    protocol_fee_recipient: &Pubkey,
+    base_amount_out: u64,
+    max_quote_amount_in: u64,
+) -> Instruction {
+    let mut data = BUY_DISCRIMINATOR.to_vec();
+    BuyArgs {
+        base_amount_out,
+        max_quote_amount_in,
+    }
+    .serialize(&mut data)
+    .unwrap();
+    Instruction {
+        program_id: program_ids().pumpswap,
+        accounts: trade_accounts(pool_pubkey, pool, user, protocol_fee_recipient),
+        data,
+    }
+}
+
+/// Sells `base_amount_in` tokens for at least `min_quote_amount_out`
+pub fn create_sell_instruction(
+    pool_pubkey: &Pubkey,
+    pool: &Pool,
+    user: &Pubkey,
+    protocol_fee_recipient: &Pubkey,
+    base_amount_in: u64,
+    min_quote_amount_out: u64,
+) -> Instruction {
+    let mut data = SELL_DISCRIMINATOR.to_vec();
+    SellArgs {
+        base_amount_in,
+        min_quote_amount_out,
+    }
+    .serialize(&mut data)
+    .unwrap();
+    Instruction {
+        program_id: program_ids().pumpswap,
+        accounts: trade_accounts(pool_pubkey, pool, user, protocol_fee_recipient),
+        data,
+    }
+}
+
+#[test]
+fn test_instruction_discriminators() {
+    for (name, discriminator) in [
+        ("global:buy", BUY_DISCRIMINATOR),
+        ("global:sell", SELL_DISCRIMINATOR),
+        ("global:create_pool", CREATE_POOL_DISCRIMINATOR),
+    ] {
+        assert_eq!(
+            solana_sdk::hash::hash(name.as_bytes()).to_bytes()[..8],
+            discriminator
+        );
+    }
+}
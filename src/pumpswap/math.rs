@@ -0,0 +1,83 @@
+use anyhow::Result;
+
+use crate::pumpswap::error::PumpSwapError;
+
+fn fee(amount: u128, basis_points: u64) -> u128 {
+    (amount * basis_points as u128).div_ceil(10000)
+}
+
+/// Quote tokens, fees included, needed to buy exactly `base_amount_out`
+pub fn buy_quote_input(
+    base_amount_out: u64,
+    base_reserve: u64,
+    quote_reserve: u64,
+    lp_fee_bps: u64,
+    protocol_fee_bps: u64,
+) -> Result<u64> {
+    if base_amount_out >= base_reserve {
+        return Err(PumpSwapError::InsufficientLiquidity {
+            base_amount_out,
+            base_reserve,
+        }
+        .into());
+    }
+    // (x - out) * (y + in) = x * y，向上取整
+    let quote_in = (quote_reserve as u128 * base_amount_out as u128)
+        .div_ceil((base_reserve - base_amount_out) as u128);
+    let total = quote_in + fee(quote_in, lp_fee_bps) + fee(quote_in, protocol_fee_bps);
+    Ok(u64::try_from(total)?)
+}
+
+/// Base tokens bought by spending `quote_amount_in`, fees included
+pub fn buy_base_output(
+    quote_amount_in: u64,
+    base_reserve: u64,
+    quote_reserve: u64,
+    lp_fee_bps: u64,
+    protocol_fee_bps: u64,
+) -> u64 {
+    // 扣除手续费后实际进入池子的数量
+    let effective =
+        quote_amount_in as u128 * 10000 / (10000 + lp_fee_bps + protocol_fee_bps) as u128;
+    (base_reserve as u128 * effective / (quote_reserve as u128 + effective)) as u64
+}
+
+/// Quote tokens received, after fees, for selling `base_amount_in`
+pub fn sell_quote_output(
+    base_amount_in: u64,
+    base_reserve: u64,
+    quote_reserve: u64,
+    lp_fee_bps: u64,
+    protocol_fee_bps: u64,
+) -> u64 {
+    let quote_out = quote_reserve as u128 * base_amount_in as u128
+        / (base_reserve as u128 + base_amount_in as u128);
+    quote_out
+        .saturating_sub(fee(quote_out, lp_fee_bps))
+        .saturating_sub(fee(quote_out, protocol_fee_bps)) as u64
+}
+
+#[test]
+fn test_buy_and_sell_quotes() {
+    let base_reserve = 200_000_000_000_000;
+    let quote_reserve = 80_000_000_000;
+
+    let base_out = buy_base_output(1_000_000_000, base_reserve, quote_reserve, 20, 5);
+    let quote_in = buy_quote_input(base_out, base_reserve, quote_reserve, 20, 5).unwrap();
+    // 反算的花费和输入只差取整误差
+    assert!(quote_in.abs_diff(1_000_000_000) <= 2);
+
+    // 不计手续费时买入再卖出拿不回更多
+    let cost = buy_quote_input(base_out, base_reserve, quote_reserve, 0, 0).unwrap();
+    let sold = sell_quote_output(
+        base_out,
+        base_reserve - base_out,
+        quote_reserve + cost,
+        0,
+        0,
+    );
+    assert!(sold <= cost);
+    assert!(sell_quote_output(base_out, base_reserve, quote_reserve, 20, 5) < sold);
+
+    assert!(buy_quote_input(base_reserve, base_reserve, quote_reserve, 20, 5).is_err());
+}
@@ -0,0 +1,11 @@
+//! PumpSwap, the AMM Pump.fun migrates completed curves to.
+//!
+//! Pools are constant product over the pool's base (token) and quote (WSOL)
+//! token accounts. LP and protocol fees are charged on the quote side, on top
+//! of the input for buys and out of the output for sells.
+
+pub mod accounts;
+pub mod error;
+pub mod instructions;
+pub mod math;
+pub mod pools;
@@ -0,0 +1,130 @@
+//! PumpSwap pool lookup and state.
+//!
+//! Pools of migrated tokens are found by searching the program accounts for
+//! a pool with the mint as base and WSOL as quote, and cached afterwards.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use anyhow::{anyhow, Result};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+};
+use solana_sdk::{program_pack::Pack, pubkey::Pubkey};
+
+use crate::{
+    config::program_ids,
+    pumpswap::accounts::{get_global_config_pda, GlobalConfig, Pool, POOL_DISCRIMINATOR},
+};
+
+/// Offset of `Pool::base_mint`, after the discriminator, bump, index and creator
+const BASE_MINT_OFFSET: usize = 8 + 1 + 2 + 32;
+const QUOTE_MINT_OFFSET: usize = BASE_MINT_OFFSET + 32;
+
+/// mint -> WSOL pool
+static KNOWN_POOLS: LazyLock<RwLock<HashMap<Pubkey, Pubkey>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Records the WSOL pool of `mint`
+pub fn record_pool(mint: Pubkey, pool: Pubkey) {
+    KNOWN_POOLS.write().unwrap().insert(mint, pool);
+}
+
+fn pool_filters(base_mint: &Pubkey, quote_mint: &Pubkey) -> Vec<RpcFilterType> {
+    vec![
+        RpcFilterType::Memcmp(Memcmp::new_base58_encoded(0, &POOL_DISCRIMINATOR)),
+        RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            BASE_MINT_OFFSET,
+            base_mint.as_ref(),
+        )),
+        RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            QUOTE_MINT_OFFSET,
+            quote_mint.as_ref(),
+        )),
+    ]
+}
+
+/// Fetches and decodes the pool at `pool_id`
+pub async fn get_pool(client: &RpcClient, pool_id: &Pubkey) -> Result<Pool> {
+    let account = client.get_account(pool_id).await?;
+    Pool::unpack(pool_id, &account.data)
+}
+
+/// Finds the PumpSwap pool pairing `mint` with WSOL
+pub async fn find_sol_pool(client: Arc<RpcClient>, mint: &Pubkey) -> Result<(Pubkey, Pool)> {
+    let known = KNOWN_POOLS.read().unwrap().get(mint).copied();
+    if let Some(pool_id) = known {
+        return Ok((pool_id, get_pool(&client, &pool_id).await?));
+    }
+
+    let config = RpcProgramAccountsConfig {
+        filters: Some(pool_filters(mint, &spl_token::native_mint::ID)),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let pools = client
+        .get_program_accounts_with_config(&program_ids().pumpswap, config)
+        .await?;
+    let (pool_id, account) = pools
+        .first()
+        .ok_or_else(|| anyhow!("PumpSwapPoolNotFound {}", mint))?;
+    let pool = Pool::unpack(pool_id, &account.data)?;
+    record_pool(*mint, *pool_id);
+    Ok((*pool_id, pool))
+}
+
+pub async fn get_global_config(client: &RpcClient) -> Result<GlobalConfig> {
+    let pubkey = get_global_config_pda();
+    let account = client.get_account(&pubkey).await?;
+    GlobalConfig::unpack(&pubkey, &account.data)
+}
+
+/// Balances of the pool's base and quote token accounts
+pub async fn get_reserves(client: &RpcClient, pool: &Pool) -> Result<(u64, u64)> {
+    let accounts = client
+        .get_multiple_accounts(&[pool.pool_base_token_account, pool.pool_quote_token_account])
+        .await?;
+    let amount = |i: usize| -> Result<u64> {
+        let account = accounts
+            .get(i)
+            .and_then(Option::as_ref)
+            .ok_or_else(|| anyhow!("missing pool token account"))?;
+        Ok(spl_token::state::Account::unpack(&account.data)?.amount)
+    };
+    Ok((amount(0)?, amount(1)?))
+}
+
+#[test]
+fn test_pool_filter_offsets() {
+    let base_mint = Pubkey::new_unique();
+    let mut data = POOL_DISCRIMINATOR.to_vec();
+    data.push(255);
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(Pubkey::new_unique().as_ref());
+    data.extend_from_slice(base_mint.as_ref());
+    data.extend_from_slice(spl_token::native_mint::ID.as_ref());
+    data.extend_from_slice(&[0; 32 * 3 + 8]);
+
+    let matches = |filters: Vec<RpcFilterType>| {
+        filters.iter().all(|filter| match filter {
+            RpcFilterType::Memcmp(memcmp) => memcmp.bytes_match(&data),
+            _ => false,
+        })
+    };
+    assert!(matches(pool_filters(
+        &base_mint,
+        &spl_token::native_mint::ID
+    )));
+    assert!(!matches(pool_filters(
+        &spl_token::native_mint::ID,
+        &base_mint
+    )));
+}
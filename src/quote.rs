@@ -0,0 +1,104 @@
+//! Quotes of a swap, without building or sending a transaction.
+//!
+//! [`crate::raydium::quote`] and [`crate::pumpfun::quote`] read the pool or
+//! bonding curve once and price the swap at those reserves, for dry runs and
+//! backtests.
+
+use std::fmt;
+
+/// Fees a swap pays, in units of the input token
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FeeBreakdown {
+    /// Left in the pool for liquidity providers
+    pub lp_fee: u64,
+    /// Taken by the protocol
+    pub protocol_fee: u64,
+}
+
+impl FeeBreakdown {
+    pub fn total(&self) -> u64 {
+        self.lp_fee + self.protocol_fee
+    }
+}
+
+/// Expected result of swapping `amount_in`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quote {
+    pub amount_in: u64,
+    /// Output at the current reserves, after fees
+    pub expected_out: u64,
+    /// Smallest output accepted after slippage
+    pub min_out: u64,
+    /// How much less the swap returns than the spot price would, in percent,
+    /// fees excluded
+    pub price_impact_pct: f64,
+    pub fees: FeeBreakdown,
+}
+
+impl Quote {
+    /// Prices the swap of `amount_in`, the fees deducted first, against the
+    /// spot price of `input_reserve` / `output_reserve`
+    pub fn new(
+        amount_in: u64,
+        expected_out: u64,
+        min_out: u64,
+        input_reserve: u64,
+        output_reserve: u64,
+        fees: FeeBreakdown,
+    ) -> Self {
+        let swapped = amount_in.saturating_sub(fees.total()) as f64;
+        let spot_out = swapped * output_reserve as f64 / input_reserve as f64;
+        let price_impact_pct = if spot_out > 0.0 {
+            ((1.0 - expected_out as f64 / spot_out) * 100.0).max(0.0)
+        } else {
+            0.0
+        };
+        Self {
+            amount_in,
+            expected_out,
+            min_out,
+            price_impact_pct,
+            fees,
+        }
+    }
+}
+
+impl fmt::Display for Quote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} in -> {} out (min {}), impact {:.2}%, fees {} lp + {} protocol",
+            self.amount_in,
+            self.expected_out,
+            self.min_out,
+            self.price_impact_pct,
+            self.fees.lp_fee,
+            self.fees.protocol_fee
+        )
+    }
+}
+
+#[test]
+fn test_quote_price_impact() {
+    let fees = FeeBreakdown {
+        lp_fee: 22,
+        protocol_fee: 3,
+    };
+    // 1:2 的池子，扣费后按恒定乘积计算
+    let (input_reserve, output_reserve) = (1_000_000, 2_000_000);
+    let swapped = 10_000 - fees.total();
+    let out = output_reserve * swapped / (input_reserve + swapped);
+    let quote = Quote::new(
+        10_000,
+        out,
+        out * 99 / 100,
+        input_reserve,
+        output_reserve,
+        fees,
+    );
+    assert!((quote.price_impact_pct - 0.99).abs() < 0.01);
+    assert_eq!(quote.fees.total(), 25);
+
+    let no_impact = Quote::new(100, 200, 200, 1, 2, FeeBreakdown::default());
+    assert_eq!(no_impact.price_impact_pct, 0.0);
+}
@@ -0,0 +1,63 @@
+//! CP-Swap constant product quotes
+//!
+//! The trade fee is taken from the input before it enters the curve, rounding
+//! in the pool's favour as the program does.
+
+use anyhow::Result;
+
+use crate::raydium::error::RaydiumError;
+
+/// Denominator of the `AmmConfig` fee rates
+pub const FEE_RATE_DENOMINATOR: u64 = 1_000_000;
+
+fn trade_fee(amount: u128, trade_fee_rate: u64) -> u128 {
+    (amount * trade_fee_rate as u128).div_ceil(FEE_RATE_DENOMINATOR as u128)
+}
+
+/// Output of swapping exactly `amount_in`, after the trade fee
+pub fn swap_base_input(
+    amount_in: u64,
+    input_reserve: u64,
+    output_reserve: u64,
+    trade_fee_rate: u64,
+) -> u64 {
+    let amount_in = amount_in as u128;
+    let effective = amount_in - trade_fee(amount_in, trade_fee_rate);
+    (output_reserve as u128 * effective / (input_reserve as u128 + effective)) as u64
+}
+
+/// Input, trade fee included, needed to receive exactly `amount_out`
+pub fn swap_base_output(
+    amount_out: u64,
+    input_reserve: u64,
+    output_reserve: u64,
+    trade_fee_rate: u64,
+) -> Result<u64> {
+    if amount_out >= output_reserve {
+        return Err(RaydiumError::InsufficientLiquidity {
+            amount_out,
+            reserve: output_reserve,
+        }
+        .into());
+    }
+    // (x + in) * (y - out) = x * y，向上取整
+    let effective = (input_reserve as u128 * amount_out as u128)
+        .div_ceil((output_reserve - amount_out) as u128);
+    let amount_in = (effective * FEE_RATE_DENOMINATOR as u128)
+        .div_ceil((FEE_RATE_DENOMINATOR - trade_fee_rate) as u128);
+    Ok(u64::try_from(amount_in)?)
+}
+
+#[test]
+fn test_swap_quotes_round_trip() {
+    let (sol_reserve, token_reserve) = (80_000_000_000, 200_000_000_000_000);
+    // 0.25% 手续费
+    let rate = 2500;
+
+    let out = swap_base_input(1_000_000_000, sol_reserve, token_reserve, rate);
+    let amount_in = swap_base_output(out, sol_reserve, token_reserve, rate).unwrap();
+    assert!(amount_in <= 1_000_000_000 && 1_000_000_000 - amount_in <= 2);
+    assert!(out < swap_base_input(1_000_000_000, sol_reserve, token_reserve, 0));
+
+    assert!(swap_base_output(token_reserve, sol_reserve, token_reserve, rate).is_err());
+}
@@ -0,0 +1,11 @@
+//! Raydium CP-Swap, the constant product program replacing AMM v4 for new pools.
+//!
+//! Pools pair two mints of either token program, sorted by address into
+//! token 0 and token 1, and charge the trade fee of the `AmmConfig` they were
+//! created with. `raydium::swap::swap_exact_in` routes pools owned by the
+//! program here.
+
+pub mod math;
+pub mod state;
+pub mod swap;
+pub mod swap_instructions;
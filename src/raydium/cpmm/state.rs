@@ -0,0 +1,186 @@
+//! CP-Swap account layouts and the PDAs a swap needs
+
+use anyhow::Result;
+use borsh::BorshDeserialize;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::raydium::error::RaydiumError;
+
+/// Anchor discriminator of `PoolState` accounts
+pub const POOL_STATE_DISCRIMINATOR: [u8; 8] = [247, 237, 227, 245, 215, 195, 222, 70];
+
+/// Anchor discriminator of `AmmConfig` accounts
+pub const AMM_CONFIG_DISCRIMINATOR: [u8; 8] = [218, 244, 33, 104, 203, 203, 43, 111];
+
+/// A CP-Swap pool
+///
+/// Only the prefix up to `recent_epoch` is decoded, the padding is ignored.
+#[derive(Debug, Clone, BorshDeserialize)]
+pub struct PoolState {
+    pub discriminator: [u8; 8],
+    /// Fee configuration of the pool
+    pub amm_config: Pubkey,
+    pub pool_creator: Pubkey,
+    pub token_0_vault: Pubkey,
+    pub token_1_vault: Pubkey,
+    pub lp_mint: Pubkey,
+    pub token_0_mint: Pubkey,
+    pub token_1_mint: Pubkey,
+    /// Token program of each mint, spl-token or Token-2022
+    pub token_0_program: Pubkey,
+    pub token_1_program: Pubkey,
+    pub observation_key: Pubkey,
+    pub auth_bump: u8,
+    pub status: u8,
+    pub lp_mint_decimals: u8,
+    pub mint_0_decimals: u8,
+    pub mint_1_decimals: u8,
+    pub lp_supply: u64,
+    /// Fees still held by the vaults but not part of the reserves
+    pub protocol_fees_token_0: u64,
+    pub protocol_fees_token_1: u64,
+    pub fund_fees_token_0: u64,
+    pub fund_fees_token_1: u64,
+    /// Unix time the pool opens for swaps
+    pub open_time: u64,
+    pub recent_epoch: u64,
+}
+
+/// Fee configuration shared by the pools created with it
+#[derive(Debug, Clone, BorshDeserialize)]
+pub struct AmmConfig {
+    pub discriminator: [u8; 8],
+    pub bump: u8,
+    pub disable_create_pool: bool,
+    pub index: u16,
+    /// Fee on the input, over [`super::math::FEE_RATE_DENOMINATOR`]
+    pub trade_fee_rate: u64,
+    /// Share of the trade fee going to the protocol
+    pub protocol_fee_rate: u64,
+    /// Share of the trade fee going to the fund
+    pub fund_fee_rate: u64,
+    pub create_pool_fee: u64,
+    pub protocol_owner: Pubkey,
+    pub fund_owner: Pubkey,
+}
+
+fn unpack<T: BorshDeserialize>(
+    kind: &'static str,
+    discriminator: [u8; 8],
+    pubkey: &Pubkey,
+    data: &[u8],
+) -> Result<T> {
+    if data.len() < 8 || data[..8] != discriminator {
+        return Err(RaydiumError::InvalidCpmmAccount {
+            kind,
+            pubkey: *pubkey,
+        }
+        .into());
+    }
+    Ok(T::deserialize(&mut &data[..])?)
+}
+
+impl PoolState {
+    pub fn unpack(pubkey: &Pubkey, data: &[u8]) -> Result<Self> {
+        unpack("pool state", POOL_STATE_DISCRIMINATOR, pubkey, data)
+    }
+
+    /// Amounts of the vaults that belong to liquidity providers
+    pub fn reserves(&self, vault_0_amount: u64, vault_1_amount: u64) -> (u64, u64) {
+        (
+            vault_0_amount
+                .saturating_sub(self.protocol_fees_token_0)
+                .saturating_sub(self.fund_fees_token_0),
+            vault_1_amount
+                .saturating_sub(self.protocol_fees_token_1)
+                .saturating_sub(self.fund_fees_token_1),
+        )
+    }
+}
+
+impl AmmConfig {
+    pub fn unpack(pubkey: &Pubkey, data: &[u8]) -> Result<Self> {
+        unpack("amm config", AMM_CONFIG_DISCRIMINATOR, pubkey, data)
+    }
+}
+
+/// Authority owning every pool's vaults and LP mint
+pub fn authority_pda(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"vault_and_lp_mint_auth_seed"], program_id).0
+}
+
+/// The pool of `mint_a` and `mint_b` created with `amm_config`, in either order
+pub fn pool_pda(
+    program_id: &Pubkey,
+    amm_config: &Pubkey,
+    mint_a: &Pubkey,
+    mint_b: &Pubkey,
+) -> Pubkey {
+    // 池子里 token 0 的地址总是较小的一个
+    let (mint_0, mint_1) = if mint_a < mint_b {
+        (mint_a, mint_b)
+    } else {
+        (mint_b, mint_a)
+    };
+    Pubkey::find_program_address(
+        &[
+            b"pool",
+            amm_config.as_ref(),
+            mint_0.as_ref(),
+            mint_1.as_ref(),
+        ],
+        program_id,
+    )
+    .0
+}
+
+/// Vault of `pool` holding `mint`
+pub fn vault_pda(program_id: &Pubkey, pool: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"pool_vault", pool.as_ref(), mint.as_ref()], program_id).0
+}
+
+/// Price oracle of `pool`
+pub fn observation_pda(program_id: &Pubkey, pool: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"observation", pool.as_ref()], program_id).0
+}
+
+#[test]
+fn test_unpack_pool_state_layout() {
+    let mint_0 = Pubkey::new_unique();
+    let mut data = POOL_STATE_DISCRIMINATOR.to_vec();
+    data.extend_from_slice(&[0; 32 * 5]);
+    data.extend_from_slice(mint_0.as_ref());
+    data.extend_from_slice(&[0; 32 * 4]);
+    data.extend_from_slice(&[255, 1, 9, 6, 9]);
+    data.extend_from_slice(&1_000u64.to_le_bytes());
+    data.extend_from_slice(&10u64.to_le_bytes());
+    data.extend_from_slice(&20u64.to_le_bytes());
+    data.extend_from_slice(&1u64.to_le_bytes());
+    data.extend_from_slice(&2u64.to_le_bytes());
+    data.extend_from_slice(&[0; 16]);
+    // 末尾的padding
+    data.extend_from_slice(&[0; 8 * 31]);
+
+    let pool = PoolState::unpack(&Pubkey::new_unique(), &data).unwrap();
+    assert_eq!(pool.token_0_mint, mint_0);
+    assert_eq!(pool.mint_0_decimals, 6);
+    assert_eq!(pool.lp_supply, 1_000);
+    assert_eq!(pool.reserves(100, 100), (89, 78));
+
+    assert!(AmmConfig::unpack(&Pubkey::new_unique(), &data).is_err());
+    assert_eq!(
+        solana_sdk::hash::hash(b"account:PoolState").to_bytes()[..8],
+        POOL_STATE_DISCRIMINATOR
+    );
+    assert_eq!(
+        solana_sdk::hash::hash(b"account:AmmConfig").to_bytes()[..8],
+        AMM_CONFIG_DISCRIMINATOR
+    );
+
+    let (program_id, config) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let mint_1 = Pubkey::new_unique();
+    assert_eq!(
+        pool_pda(&program_id, &config, &mint_0, &mint_1),
+        pool_pda(&program_id, &config, &mint_1, &mint_0)
+    );
+}
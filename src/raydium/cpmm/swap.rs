@@ -0,0 +1,272 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
+};
+use spl_token_2022::{extension::StateWithExtensions, state::Account};
+
+use crate::{
+    config::program_ids,
+    math::slippage::Slippage,
+    portfolio::{record_trade, Side},
+    raydium::{
+        cpmm::{
+            math::swap_base_input,
+            state::{authority_pda, AmmConfig, PoolState},
+            swap_instructions::{self, SwapAccounts},
+        },
+        error::RaydiumError,
+        structure::SwapDirection,
+        swap::{resolve_swap_direction, SwapAmount},
+        tx::{new_signed_and_send, trade_side},
+    },
+    risk,
+    tx::{
+        budget::global_guard,
+        mode::ExecutionMode,
+        simulate::{ExpectedOutput, OutputAccount, TxOutcome},
+    },
+    wallet::wsol,
+};
+
+/// Fetches and decodes the pool at `pool_id`
+pub async fn get_pool(client: &RpcClient, pool_id: &Pubkey) -> Result<PoolState> {
+    let account = client.get_account(pool_id).await?;
+    PoolState::unpack(pool_id, &account.data)
+}
+
+/// Fetches and decodes the fee configuration at `amm_config`
+pub async fn get_amm_config(client: &RpcClient, amm_config: &Pubkey) -> Result<AmmConfig> {
+    let account = client.get_account(amm_config).await?;
+    AmmConfig::unpack(amm_config, &account.data)
+}
+
+/// Token 0 and token 1 reserves of `pool`, owed fees excluded
+pub async fn get_reserves(client: &RpcClient, pool: &PoolState) -> Result<(u64, u64)> {
+    let vaults = [pool.token_0_vault, pool.token_1_vault];
+    let accounts = client.get_multiple_accounts(&vaults).await?;
+    let mut amounts = [0; 2];
+    for ((amount, vault), account) in amounts.iter_mut().zip(&vaults).zip(accounts) {
+        let account = account.ok_or(RaydiumError::MissingAccount {
+            name: "cpmm vault",
+            pubkey: *vault,
+        })?;
+        *amount = StateWithExtensions::<Account>::unpack(&account.data)
+            .map_err(|_| RaydiumError::InvalidTokenAccount {
+                name: "cpmm vault",
+                pubkey: *vault,
+            })?
+            .base
+            .amount;
+    }
+    Ok(pool.reserves(amounts[0], amounts[1]))
+}
+
+/// Output of swapping exactly `amount_in` of `token_in` through `pool`
+pub async fn quote_base_in(
+    client: &RpcClient,
+    pool: &PoolState,
+    token_in: &Pubkey,
+    amount_in: u64,
+) -> Result<u64> {
+    let token_out = if *token_in == pool.token_0_mint {
+        pool.token_1_mint
+    } else {
+        pool.token_0_mint
+    };
+    let direction =
+        resolve_swap_direction(token_in, &token_out, &pool.token_0_mint, &pool.token_1_mint)?;
+    let config = get_amm_config(client, &pool.amm_config).await?;
+    let (reserve_0, reserve_1) = get_reserves(client, pool).await?;
+    let (input_reserve, output_reserve) = match direction {
+        SwapDirection::Buy => (reserve_0, reserve_1),
+        SwapDirection::Sell => (reserve_1, reserve_0),
+    };
+    Ok(swap_base_input(
+        amount_in,
+        input_reserve,
+        output_reserve,
+        config.trade_fee_rate,
+    ))
+}
+
+/// Swaps exactly `amount_in` through a CP-Swap pool, like [`crate::raydium::swap::swap_exact_in`]
+///
+/// SOL is wrapped into, and unwrapped from, the payer's WSOL account.
+#[allow(clippy::too_many_arguments)]
+pub async fn swap_exact_in(
+    client: Arc<RpcClient>,
+    token_in: &str,
+    token_out: &str,
+    amount_in: SwapAmount,
+    pool_id: &str,
+    slippage: u64,
+    keypair: Arc<Keypair>,
+    is_simulate: bool,
+    close_input: bool,
+) -> Result<TxOutcome> {
+    // 滑点
+    let slippage_bps = slippage * 100;
+    let owner = keypair.pubkey();
+
+    let token_in = token_in.parse::<Pubkey>()?;
+    let token_out = token_out.parse::<Pubkey>()?;
+    let pool_id = pool_id.parse::<Pubkey>()?;
+    let native_mint = spl_token::native_mint::ID;
+    let program_id = program_ids().raydium_cpmm;
+
+    // 获取池子状态，token 0 换 token 1 记为 Buy
+    let pool = get_pool(&client, &pool_id).await?;
+    let direction = resolve_swap_direction(
+        &token_in,
+        &token_out,
+        &pool.token_0_mint,
+        &pool.token_1_mint,
+    )?;
+    let (in_decimals, in_program, in_vault, out_program, out_vault) = match direction {
+        SwapDirection::Buy => (
+            pool.mint_0_decimals,
+            pool.token_0_program,
+            pool.token_0_vault,
+            pool.token_1_program,
+            pool.token_1_vault,
+        ),
+        SwapDirection::Sell => (
+            pool.mint_1_decimals,
+            pool.token_1_program,
+            pool.token_1_vault,
+            pool.token_0_program,
+            pool.token_0_vault,
+        ),
+    };
+    let amount_specified = amount_in.to_raw(in_decimals);
+
+    // 用sol买入时检查风控、预算和冷却
+    let reservation = if !ExecutionMode::resolve(is_simulate).simulates() && token_in == native_mint
+    {
+        risk::check_buy(&token_out, amount_specified)?;
+        Some(global_guard().reserve(&token_out, amount_specified)?)
+    } else {
+        None
+    };
+
+    let expected_out = quote_base_in(&client, &pool, &token_in, amount_specified).await?;
+    let other_amount_threshold = Slippage::Bps(slippage_bps).min_out(expected_out)?;
+
+    let mut in_account =
+        get_associated_token_address_with_program_id(&owner, &token_in, &in_program);
+    let mut out_account =
+        get_associated_token_address_with_program_id(&owner, &token_out, &out_program);
+    let mut instructions = vec![];
+    // 输出代币不是sol时，需要其ATA账户
+    if token_out != native_mint {
+        instructions.push(create_associated_token_account_idempotent(
+            &owner,
+            &owner,
+            &token_out,
+            &out_program,
+        ));
+    }
+    // 输入或输出是sol时，用wsol账户
+    let mut wsol_account = None;
+    if token_in == native_mint || token_out == native_mint {
+        let lamports = if token_in == native_mint {
+            amount_specified
+        } else {
+            0
+        };
+        let wsol = wsol::prepare(&client, &owner, lamports).await?;
+        instructions.extend(wsol.setup.iter().cloned());
+        if token_in == native_mint {
+            in_account = wsol.address;
+        } else {
+            out_account = wsol.address;
+        }
+        wsol_account = Some(wsol);
+    }
+
+    let accounts = SwapAccounts {
+        authority: authority_pda(&program_id),
+        amm_config: pool.amm_config,
+        pool_state: pool_id,
+        input_token_account: in_account,
+        output_token_account: out_account,
+        input_vault: in_vault,
+        output_vault: out_vault,
+        input_token_program: in_program,
+        output_token_program: out_program,
+        input_token_mint: token_in,
+        output_token_mint: token_out,
+        observation_state: pool.observation_key,
+    };
+    instructions.push(swap_instructions::swap_base_input(
+        &program_id,
+        &owner,
+        &accounts,
+        amount_specified,
+        other_amount_threshold,
+    )?);
+
+    // 关闭临时wsol账户取回sol
+    if let Some(wsol) = &wsol_account {
+        instructions.extend(wsol.cleanup.iter().cloned());
+    }
+    // 卖出全部余额后关闭账户，取回租金
+    if close_input && token_in != native_mint {
+        instructions.push(spl_token_2022::instruction::close_account(
+            &in_program,
+            &in_account,
+            &owner,
+            &owner,
+            &[&owner],
+        )?);
+    }
+
+    let expected = ExpectedOutput {
+        expected_out,
+        min_out: other_amount_threshold,
+        account: match &wsol_account {
+            Some(wsol) if token_out == native_mint => wsol.output(&owner),
+            _ => OutputAccount::Token(out_account),
+        },
+    };
+    let outcome = new_signed_and_send(
+        client,
+        keypair,
+        instructions,
+        is_simulate,
+        Some(expected),
+        "raydium_cpmm",
+        trade_side(&token_in, &token_out),
+    )
+    .await?;
+    if let Some(reservation) = reservation {
+        reservation.commit();
+    }
+
+    // 只记录和sol之间的交易
+    if token_in == native_mint {
+        record_trade(
+            "raydium_cpmm",
+            Side::Buy,
+            &token_out,
+            expected_out,
+            amount_specified,
+            &outcome,
+        );
+    } else if token_out == native_mint {
+        record_trade(
+            "raydium_cpmm",
+            Side::Sell,
+            &token_in,
+            amount_specified,
+            expected_out,
+            &outcome,
+        );
+    }
+    Ok(outcome)
+}
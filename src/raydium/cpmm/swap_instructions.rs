@@ -0,0 +1,139 @@
+//! CP-Swap swap instructions
+
+use anyhow::Result;
+use borsh::BorshSerialize;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+
+/// Anchor discriminator of the `swap_base_input` instruction
+pub const SWAP_BASE_INPUT_DISCRIMINATOR: [u8; 8] = [143, 190, 90, 218, 196, 30, 51, 222];
+
+/// Anchor discriminator of the `swap_base_output` instruction
+pub const SWAP_BASE_OUTPUT_DISCRIMINATOR: [u8; 8] = [55, 217, 98, 86, 163, 74, 180, 173];
+
+#[derive(BorshSerialize)]
+struct SwapBaseInputArgs {
+    amount_in: u64,
+    minimum_amount_out: u64,
+}
+
+#[derive(BorshSerialize)]
+struct SwapBaseOutputArgs {
+    max_amount_in: u64,
+    amount_out: u64,
+}
+
+/// Accounts of a swap, oriented from the input to the output token
+#[derive(Debug, Clone, Copy)]
+pub struct SwapAccounts {
+    pub authority: Pubkey,
+    pub amm_config: Pubkey,
+    pub pool_state: Pubkey,
+    pub input_token_account: Pubkey,
+    pub output_token_account: Pubkey,
+    pub input_vault: Pubkey,
+    pub output_vault: Pubkey,
+    pub input_token_program: Pubkey,
+    pub output_token_program: Pubkey,
+    pub input_token_mint: Pubkey,
+    pub output_token_mint: Pubkey,
+    pub observation_state: Pubkey,
+}
+
+fn swap(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    accounts: &SwapAccounts,
+    data: Vec<u8>,
+) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new_readonly(*payer, true),
+        AccountMeta::new_readonly(accounts.authority, false),
+        AccountMeta::new_readonly(accounts.amm_config, false),
+        AccountMeta::new(accounts.pool_state, false),
+        AccountMeta::new(accounts.input_token_account, false),
+        AccountMeta::new(accounts.output_token_account, false),
+        AccountMeta::new(accounts.input_vault, false),
+        AccountMeta::new(accounts.output_vault, false),
+        AccountMeta::new_readonly(accounts.input_token_program, false),
+        AccountMeta::new_readonly(accounts.output_token_program, false),
+        AccountMeta::new_readonly(accounts.input_token_mint, false),
+        AccountMeta::new_readonly(accounts.output_token_mint, false),
+        AccountMeta::new(accounts.observation_state, false),
+    ];
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    }
+}
+
+/// Swaps exactly `amount_in`, receiving at least `minimum_amount_out`
+pub fn swap_base_input(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    accounts: &SwapAccounts,
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Result<Instruction> {
+    let mut data = SWAP_BASE_INPUT_DISCRIMINATOR.to_vec();
+    SwapBaseInputArgs {
+        amount_in,
+        minimum_amount_out,
+    }
+    .serialize(&mut data)?;
+    Ok(swap(program_id, payer, accounts, data))
+}
+
+/// Receives exactly `amount_out`, spending at most `max_amount_in`
+pub fn swap_base_output(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    accounts: &SwapAccounts,
+    max_amount_in: u64,
+    amount_out: u64,
+) -> Result<Instruction> {
+    let mut data = SWAP_BASE_OUTPUT_DISCRIMINATOR.to_vec();
+    SwapBaseOutputArgs {
+        max_amount_in,
+        amount_out,
+    }
+    .serialize(&mut data)?;
+    Ok(swap(program_id, payer, accounts, data))
+}
+
+#[test]
+fn test_swap_instruction_data() {
+    assert_eq!(
+        solana_sdk::hash::hash(b"global:swap_base_input").to_bytes()[..8],
+        SWAP_BASE_INPUT_DISCRIMINATOR
+    );
+    assert_eq!(
+        solana_sdk::hash::hash(b"global:swap_base_output").to_bytes()[..8],
+        SWAP_BASE_OUTPUT_DISCRIMINATOR
+    );
+
+    let accounts = SwapAccounts {
+        authority: Pubkey::new_unique(),
+        amm_config: Pubkey::new_unique(),
+        pool_state: Pubkey::new_unique(),
+        input_token_account: Pubkey::new_unique(),
+        output_token_account: Pubkey::new_unique(),
+        input_vault: Pubkey::new_unique(),
+        output_vault: Pubkey::new_unique(),
+        input_token_program: spl_token::ID,
+        output_token_program: spl_token::ID,
+        input_token_mint: Pubkey::new_unique(),
+        output_token_mint: Pubkey::new_unique(),
+        observation_state: Pubkey::new_unique(),
+    };
+    let payer = Pubkey::new_unique();
+    let ix = swap_base_input(&Pubkey::new_unique(), &payer, &accounts, 1, 2).unwrap();
+    assert_eq!(ix.data[8..16], 1u64.to_le_bytes());
+    assert_eq!(ix.data[16..], 2u64.to_le_bytes());
+    assert_eq!(ix.accounts.len(), 13);
+    assert!(ix.accounts[0].is_signer && ix.accounts[0].pubkey == payer);
+    assert!(ix.accounts[12].is_writable);
+}
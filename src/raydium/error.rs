@@ -0,0 +1,56 @@
+use solana_sdk::pubkey::Pubkey;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RaydiumError {
+    #[error("{token_in} -> {token_out} doesn't match pool mints coin {coin_mint} / pc {pc_mint}")]
+    PoolMintMismatch {
+        token_in: Pubkey,
+        token_out: Pubkey,
+        coin_mint: Pubkey,
+        pc_mint: Pubkey,
+    },
+    #[error("{name} account {pubkey} not found")]
+    MissingAccount { name: &'static str, pubkey: Pubkey },
+    #[error("{name} account {pubkey} is owned by {owner}, not the token program")]
+    NotTokenAccount {
+        name: &'static str,
+        pubkey: Pubkey,
+        owner: Pubkey,
+    },
+    #[error("{name} account {pubkey} is not a valid token account")]
+    InvalidTokenAccount { name: &'static str, pubkey: Pubkey },
+    #[error("{pubkey} is not a CP-Swap {kind} account")]
+    InvalidCpmmAccount { kind: &'static str, pubkey: Pubkey },
+    #[error("can't take {amount_out} out of a reserve of {reserve}")]
+    InsufficientLiquidity { amount_out: u64, reserve: u64 },
+    #[error("pool {pool} has no liquidity to deposit against")]
+    EmptyPool { pool: Pubkey },
+    #[error("{pubkey} is not a valid OpenBook market account")]
+    InvalidMarketAccount { pubkey: Pubkey },
+    #[error("no Raydium AMM pool pairs {mint_a} with {mint_b}")]
+    PoolNotFound { mint_a: Pubkey, mint_b: Pubkey },
+    #[error("transaction is {size} bytes, over the {limit} byte packet limit; use a v0 transaction with an address lookup table")]
+    TransactionTooLarge { size: usize, limit: usize },
+}
+
+/// Why a swap through an AMM v4 pool can't be quoted or built
+#[derive(Debug, Error)]
+pub enum SwapError {
+    #[error("pool {pool} doesn't allow swaps, status {status}")]
+    PoolDisabled { pool: Pubkey, status: u64 },
+    #[error("{name} account {pubkey} not found")]
+    AccountMissing { name: &'static str, pubkey: Pubkey },
+    #[error("input mint {mint} doesn't match pool mints coin {coin_mint} / pc {pc_mint}")]
+    MintMismatch {
+        mint: Pubkey,
+        coin_mint: Pubkey,
+        pc_mint: Pubkey,
+    },
+    #[error("can't swap {amount} against reserves of {pc_reserve} pc / {coin_reserve} coin")]
+    InsufficientLiquidity {
+        amount: u64,
+        pc_reserve: u64,
+        coin_reserve: u64,
+    },
+}
@@ -1,17 +1,25 @@
-use std::{str::FromStr, sync::Arc};
+use std::{env, str::FromStr, sync::Arc};
 
-use crate::new_client;
+use crate::{
+    config::program_ids, constants::raydium::API_URL, rpc::retry::with_retry, strategy::parse_env,
+};
 
-use super::structure::AmmInfo;
+use super::{error::RaydiumError, pools::pool_filters, structure::AmmInfo};
 
-use anyhow::Result;
-use solana_client::nonblocking::rpc_client::RpcClient;
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+};
 use solana_sdk::{program_pack::Pack, pubkey::Pubkey, signature::Keypair};
 use spl_token::state::{Account, Mint};
 use spl_token_client::{
     client::{ProgramClient, ProgramRpcClient, ProgramRpcClientSendTransaction},
     token::{TokenError, TokenResult},
 };
+use tracing::warn;
 
 pub async fn get_multiple_accounts(
     client: Arc<RpcClient>,
@@ -91,13 +99,14 @@ pub async fn get_pool_state(client: Arc<RpcClient>, pool_id: &str) -> Result<(Pu
 
 // 获取账户信息
 pub async fn get_account(client: Arc<RpcClient>, addr: &Pubkey) -> Result<Option<Vec<u8>>> {
-    if let Some(account) = client
-        .get_account_with_commitment(
+    if let Some(account) = with_retry(|| {
+        client.get_account_with_commitment(
             addr,
             solana_sdk::commitment_config::CommitmentConfig::processed(),
         )
-        .await?
-        .value
+    })
+    .await?
+    .value
     {
         let account_data = account.data;
         Ok(Some(account_data))
@@ -106,9 +115,159 @@ pub async fn get_account(client: Arc<RpcClient>, addr: &Pubkey) -> Result<Option
     }
 }
 
+/// Finds the Raydium AMM pool pairing `mint_a` with `mint_b`, in either vault
+/// ordering
+///
+/// Searches the AMM program accounts, which needs an RPC serving
+/// `getProgramAccounts`, and picks the pool with the deepest vaults. If the
+/// search fails or finds nothing and `RAYDIUM_API_FALLBACK=true`, the pool is
+/// looked up on the Raydium API at `RAYDIUM_API_URL` (default [`API_URL`]),
+/// the most liquid first.
+pub async fn find_pool_by_mints(
+    client: Arc<RpcClient>,
+    mint_a: &Pubkey,
+    mint_b: &Pubkey,
+) -> Result<Pubkey> {
+    let program = program_ids().raydium_amm;
+    let mut pools = vec![];
+    for (coin_mint, pc_mint) in [(mint_a, mint_b), (mint_b, mint_a)] {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(pool_filters(coin_mint, pc_mint)),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                ..RpcAccountInfoConfig::default()
+            },
+            ..RpcProgramAccountsConfig::default()
+        };
+        // 不支持gPA的节点会拒绝或超时，改用API
+        match client
+            .get_program_accounts_with_config(&program, config)
+            .await
+        {
+            Ok(found) => pools.extend(found),
+            Err(e) => warn!(
+                "getProgramAccounts for {} / {} failed {:?}",
+                coin_mint, pc_mint, e
+            ),
+        }
+    }
+    if let Some(pool) = deepest_pool(&client, &pools).await? {
+        return Ok(pool);
+    }
+
+    dotenv::dotenv().ok();
+    if parse_env::<bool>("RAYDIUM_API_FALLBACK")?.unwrap_or(false) {
+        let url = env::var("RAYDIUM_API_URL").unwrap_or(API_URL.to_string());
+        if let Some(pool) = find_api_pool(&url, mint_a, mint_b, &program).await? {
+            return Ok(pool);
+        }
+    }
+    Err(RaydiumError::PoolNotFound {
+        mint_a: *mint_a,
+        mint_b: *mint_b,
+    }
+    .into())
+}
+
+/// Pool of `pools` with the largest product of its vault balances
+async fn deepest_pool(
+    client: &RpcClient,
+    pools: &[(Pubkey, solana_sdk::account::Account)],
+) -> Result<Option<Pubkey>> {
+    if pools.len() < 2 {
+        return Ok(pools.first().map(|(pool, _)| *pool));
+    }
+    let vaults: Vec<Pubkey> = pools
+        .iter()
+        .map(|(_, account)| AmmInfo::load_from_bytes(&account.data))
+        .collect::<Result<Vec<_>>>()?
+        .iter()
+        .flat_map(|amm| [amm.coin_vault, amm.pc_vault])
+        .collect();
+    let balances: Vec<u64> = with_retry(|| client.get_multiple_accounts(&vaults))
+        .await?
+        .iter()
+        .map(|vault| {
+            vault
+                .as_ref()
+                .and_then(|vault| Account::unpack(&vault.data).ok())
+                .map_or(0, |vault| vault.amount)
+        })
+        .collect();
+    let depths = balances
+        .chunks(2)
+        .map(|vaults| vaults[0] as u128 * vaults[1] as u128);
+    Ok(pools
+        .iter()
+        .zip(depths)
+        .max_by_key(|(_, depth)| *depth)
+        .map(|((pool, _), _)| *pool))
+}
+
+/// Most liquid pool of `program` pairing `mint_a` with `mint_b` listed by the
+/// Raydium API at `url`
+async fn find_api_pool(
+    url: &str,
+    mint_a: &Pubkey,
+    mint_b: &Pubkey,
+    program: &Pubkey,
+) -> Result<Option<Pubkey>> {
+    let response: Value = reqwest::Client::new()
+        .get(format!("{}/pools/info/mint", url.trim_end_matches('/')))
+        .query(&[
+            ("mint1", mint_a.to_string()),
+            ("mint2", mint_b.to_string()),
+            ("poolType", "standard".to_string()),
+            ("poolSortField", "liquidity".to_string()),
+            ("sortType", "desc".to_string()),
+            ("pageSize", "100".to_string()),
+            ("page", "1".to_string()),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    api_pools(&response, program).map(|pools| pools.first().copied())
+}
+
+/// Pools of `program` in a `/pools/info/mint` response, in the API's order
+fn api_pools(response: &Value, program: &Pubkey) -> Result<Vec<Pubkey>> {
+    if response["success"] != true {
+        return Err(anyhow!("raydium api error {}", response));
+    }
+    let pools = response["data"]["data"]
+        .as_array()
+        .ok_or(anyhow!("raydium api response without pools {}", response))?;
+    // CPMM和CLMM池子也会返回
+    Ok(pools
+        .iter()
+        .filter(|pool| pool["programId"].as_str() == Some(&program.to_string()))
+        .filter_map(|pool| pool["id"].as_str()?.parse().ok())
+        .collect())
+}
+
+#[test]
+fn test_api_pools() {
+    let program = Pubkey::new_unique();
+    let (amm, cpmm) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let response = serde_json::json!({
+        "success": true,
+        "data": {
+            "count": 2,
+            "data": [
+                {"programId": Pubkey::new_unique().to_string(), "id": cpmm.to_string()},
+                {"programId": program.to_string(), "id": amm.to_string()},
+            ]
+        }
+    });
+    assert_eq!(api_pools(&response, &program).unwrap(), vec![amm]);
+    assert!(api_pools(&serde_json::json!({"success": false}), &program).is_err());
+}
+
 #[tokio::test]
 async fn test_get_pool_state() -> Result<()> {
-    let client = new_client();
+    let client = crate::new_client();
     get_pool_state(client, "3gfdqZ2DqFwYufzy1G49evXcXkmtWfUj4tfmg8zUg6zB").await?;
     Ok(())
 }
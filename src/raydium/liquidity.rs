@@ -0,0 +1,416 @@
+//! Adding and removing liquidity on AMM v4 pools.
+//!
+//! [`PoolLiquidity`] holds a pool's reserves, without the pnl it still owes,
+//! and its LP supply, and quotes deposits and withdrawals the way the program
+//! computes them: a deposit takes the other side at the pool ratio rounded up
+//! and mints LP pro rata to the base side rounded down, a withdrawal pays
+//! both sides pro rata rounded down. [`deposit`] and [`withdraw`] build the
+//! instructions from a quote's [`DepositQuote::limits`] or
+//! [`WithdrawQuote::limits`], with the pool's OpenBook market accounts
+//! ([`get_market_keys`](crate::raydium::market::get_market_keys)).
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+
+use crate::{
+    math::slippage::Slippage,
+    raydium::{
+        error::RaydiumError,
+        getter::get_multiple_accounts,
+        market::{load_orderbook, MarketKeys, Orderbook},
+        math::{calc_total_without_take_pnl, unpack_token_account},
+        structure::{AmmInfo, AmmKeys},
+        swap_instructions::{AmmInstruction, DepositInstruction, WithdrawInstruction},
+    },
+};
+
+/// Side a deposit is based on, the other is taken at the pool ratio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaseSide {
+    Coin,
+    Pc,
+}
+
+impl BaseSide {
+    pub fn into_u64(self) -> u64 {
+        match self {
+            BaseSide::Coin => 0,
+            BaseSide::Pc => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolLiquidity {
+    pub pool: Pubkey,
+    pub coin_amount: u64,
+    pub pc_amount: u64,
+    pub lp_supply: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepositQuote {
+    pub base_side: BaseSide,
+    pub coin_amount: u64,
+    pub pc_amount: u64,
+    /// LP token minted
+    pub lp_amount: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WithdrawQuote {
+    /// LP token burnt
+    pub lp_amount: u64,
+    pub coin_amount: u64,
+    pub pc_amount: u64,
+}
+
+impl PoolLiquidity {
+    /// Liquidity of `pool` at the given vault balances, plus what its open
+    /// orders hold if its orderbook is enabled
+    pub fn new(
+        pool: Pubkey,
+        coin_vault_amount: u64,
+        pc_vault_amount: u64,
+        orderbook: Option<&Orderbook>,
+        amm: &AmmInfo,
+    ) -> Result<Self> {
+        let (pc_amount, coin_amount) =
+            calc_total_without_take_pnl(pc_vault_amount, coin_vault_amount, orderbook, amm)?;
+        Ok(Self {
+            pool,
+            coin_amount,
+            pc_amount,
+            lp_supply: amm.lp_amount,
+        })
+    }
+
+    /// Deposit of exactly `amount` on `base_side`
+    pub fn deposit(&self, base_side: BaseSide, amount: u64) -> Result<DepositQuote> {
+        if self.coin_amount == 0 || self.pc_amount == 0 || self.lp_supply == 0 {
+            return Err(RaydiumError::EmptyPool { pool: self.pool }.into());
+        }
+        let (base_total, other_total) = match base_side {
+            BaseSide::Coin => (self.coin_amount, self.pc_amount),
+            BaseSide::Pc => (self.pc_amount, self.coin_amount),
+        };
+        // 另一侧向上取整，LP 向下取整，和合约一致
+        let other = mul_div(amount, other_total, base_total, true)?;
+        let lp_amount = mul_div(amount, self.lp_supply, base_total, false)?;
+        let (coin_amount, pc_amount) = match base_side {
+            BaseSide::Coin => (amount, other),
+            BaseSide::Pc => (other, amount),
+        };
+        Ok(DepositQuote {
+            base_side,
+            coin_amount,
+            pc_amount,
+            lp_amount,
+        })
+    }
+
+    /// Withdrawal burning `lp_amount`
+    pub fn withdraw(&self, lp_amount: u64) -> Result<WithdrawQuote> {
+        if lp_amount > self.lp_supply {
+            return Err(RaydiumError::InsufficientLiquidity {
+                amount_out: lp_amount,
+                reserve: self.lp_supply,
+            }
+            .into());
+        }
+        if self.lp_supply == 0 {
+            return Err(RaydiumError::EmptyPool { pool: self.pool }.into());
+        }
+        Ok(WithdrawQuote {
+            lp_amount,
+            coin_amount: mul_div(lp_amount, self.coin_amount, self.lp_supply, false)?,
+            pc_amount: mul_div(lp_amount, self.pc_amount, self.lp_supply, false)?,
+        })
+    }
+}
+
+impl DepositQuote {
+    /// Instruction amounts for this deposit, the other side allowed to move
+    /// by `slippage` either way
+    pub fn limits(&self, slippage: Slippage) -> Result<DepositInstruction> {
+        // 基准侧按精确数量扣除，只有另一侧会变
+        let (max_coin_amount, max_pc_amount, other) = match self.base_side {
+            BaseSide::Coin => (
+                self.coin_amount,
+                slippage.max_in(self.pc_amount)?,
+                self.pc_amount,
+            ),
+            BaseSide::Pc => (
+                slippage.max_in(self.coin_amount)?,
+                self.pc_amount,
+                self.coin_amount,
+            ),
+        };
+        Ok(DepositInstruction {
+            max_coin_amount,
+            max_pc_amount,
+            base_side: self.base_side.into_u64(),
+            other_amount_min: Some(slippage.min_out(other)?),
+        })
+    }
+}
+
+impl WithdrawQuote {
+    /// Instruction amounts for this withdrawal, each side allowed to fall
+    /// short by `slippage`
+    pub fn limits(&self, slippage: Slippage) -> Result<WithdrawInstruction> {
+        Ok(WithdrawInstruction {
+            amount: self.lp_amount,
+            min_coin_amount: Some(slippage.min_out(self.coin_amount)?),
+            min_pc_amount: Some(slippage.min_out(self.pc_amount)?),
+        })
+    }
+}
+
+/// `a * b / denominator` in u128
+fn mul_div(a: u64, b: u64, denominator: u64, round_up: bool) -> Result<u64> {
+    let product = a as u128 * b as u128;
+    let quotient = if round_up {
+        product.div_ceil(denominator as u128)
+    } else {
+        product / denominator as u128
+    };
+    u64::try_from(quotient).map_err(|_| anyhow!("{} * {} / {} overflows u64", a, b, denominator))
+}
+
+/// Liquidity of `pool_id` at its current balances
+pub async fn get_pool_liquidity(
+    client: Arc<RpcClient>,
+    pool_id: Pubkey,
+    amm: &AmmInfo,
+) -> Result<PoolLiquidity> {
+    let vaults = [amm.coin_vault, amm.pc_vault];
+    let accounts = get_multiple_accounts(client.clone(), &vaults).await?;
+    let [coin_vault, pc_vault] = accounts.as_slice() else {
+        return Err(anyhow!("expected 2 accounts, got {}", accounts.len()));
+    };
+    let coin_vault = unpack_token_account("amm coin vault", &vaults[0], coin_vault)?;
+    let pc_vault = unpack_token_account("amm pc vault", &vaults[1], pc_vault)?;
+    let orderbook = load_orderbook(client, amm).await?;
+    PoolLiquidity::new(
+        pool_id,
+        coin_vault.amount,
+        pc_vault.amount,
+        orderbook.as_ref(),
+        amm,
+    )
+}
+
+/// Creates a 'deposit' instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn deposit(
+    amm_program: &Pubkey,
+    amm_keys: &AmmKeys,
+    market_keys: &MarketKeys,
+    user_coin_token: &Pubkey,
+    user_pc_token: &Pubkey,
+    user_lp_token: &Pubkey,
+    user_owner: &Pubkey,
+    amounts: DepositInstruction,
+) -> Result<Instruction> {
+    let data = AmmInstruction::Deposit(amounts).pack()?;
+
+    let accounts = vec![
+        // spl token
+        AccountMeta::new_readonly(spl_token::id(), false),
+        // amm
+        AccountMeta::new(amm_keys.amm_pool, false),
+        AccountMeta::new_readonly(amm_keys.amm_authority, false),
+        AccountMeta::new_readonly(amm_keys.amm_open_order, false),
+        AccountMeta::new(amm_keys.amm_target, false),
+        AccountMeta::new(amm_keys.amm_lp_mint, false),
+        AccountMeta::new(amm_keys.amm_coin_vault, false),
+        AccountMeta::new(amm_keys.amm_pc_vault, false),
+        // market
+        AccountMeta::new_readonly(market_keys.market, false),
+        // user
+        AccountMeta::new(*user_coin_token, false),
+        AccountMeta::new(*user_pc_token, false),
+        AccountMeta::new(*user_lp_token, false),
+        AccountMeta::new_readonly(*user_owner, true),
+        // market event queue
+        AccountMeta::new_readonly(market_keys.event_queue, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *amm_program,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'withdraw' instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn withdraw(
+    amm_program: &Pubkey,
+    amm_keys: &AmmKeys,
+    market_keys: &MarketKeys,
+    user_lp_token: &Pubkey,
+    user_coin_token: &Pubkey,
+    user_pc_token: &Pubkey,
+    user_owner: &Pubkey,
+    amounts: WithdrawInstruction,
+) -> Result<Instruction> {
+    let data = AmmInstruction::Withdraw(amounts).pack()?;
+
+    let accounts = vec![
+        // spl token
+        AccountMeta::new_readonly(spl_token::id(), false),
+        // amm
+        AccountMeta::new(amm_keys.amm_pool, false),
+        AccountMeta::new_readonly(amm_keys.amm_authority, false),
+        AccountMeta::new(amm_keys.amm_open_order, false),
+        AccountMeta::new(amm_keys.amm_target, false),
+        AccountMeta::new(amm_keys.amm_lp_mint, false),
+        AccountMeta::new(amm_keys.amm_coin_vault, false),
+        AccountMeta::new(amm_keys.amm_pc_vault, false),
+        // market
+        AccountMeta::new_readonly(market_keys.market_program, false),
+        AccountMeta::new(market_keys.market, false),
+        AccountMeta::new(market_keys.coin_vault, false),
+        AccountMeta::new(market_keys.pc_vault, false),
+        AccountMeta::new_readonly(market_keys.vault_signer, false),
+        // user
+        AccountMeta::new(*user_lp_token, false),
+        AccountMeta::new(*user_coin_token, false),
+        AccountMeta::new(*user_pc_token, false),
+        AccountMeta::new_readonly(*user_owner, true),
+        // market event queue and orderbook
+        AccountMeta::new(market_keys.event_queue, false),
+        AccountMeta::new(market_keys.bids, false),
+        AccountMeta::new(market_keys.asks, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *amm_program,
+        accounts,
+        data,
+    })
+}
+
+#[test]
+fn test_deposit_and_withdraw() {
+    let mut amm = AmmInfo {
+        lp_amount: 1_000_000,
+        ..AmmInfo::default()
+    };
+    amm.state_data.need_take_pnl_coin = 1000;
+    let pool = Pubkey::new_unique();
+    let liquidity = PoolLiquidity::new(pool, 2_001_000, 3_000_000, None, &amm).unwrap();
+    assert_eq!(
+        (liquidity.coin_amount, liquidity.pc_amount),
+        (2_000_000, 3_000_000)
+    );
+
+    let quote = liquidity.deposit(BaseSide::Coin, 1001).unwrap();
+    // 1001 * 1.5 向上取整，LP 向下取整
+    assert_eq!(
+        (quote.coin_amount, quote.pc_amount, quote.lp_amount),
+        (1001, 1502, 500)
+    );
+    let quote = liquidity.deposit(BaseSide::Pc, 3000).unwrap();
+    assert_eq!(
+        (quote.coin_amount, quote.pc_amount, quote.lp_amount),
+        (2000, 3000, 1000)
+    );
+    let limits = quote.limits(Slippage::Percent(1)).unwrap();
+    assert_eq!(
+        limits,
+        DepositInstruction {
+            max_coin_amount: 2020,
+            max_pc_amount: 3000,
+            base_side: 1,
+            other_amount_min: Some(1980),
+        }
+    );
+    let empty = PoolLiquidity {
+        lp_supply: 0,
+        ..liquidity
+    };
+    assert!(empty.deposit(BaseSide::Coin, 1000).is_err());
+
+    let quote = liquidity.withdraw(1000).unwrap();
+    assert_eq!((quote.coin_amount, quote.pc_amount), (2000, 3000));
+    assert!(liquidity.withdraw(1_000_001).is_err());
+    let withdraw_limits = quote.limits(Slippage::Bps(50)).unwrap();
+    assert_eq!(withdraw_limits.min_coin_amount, Some(1990));
+
+    for instruction in [
+        AmmInstruction::Deposit(limits),
+        AmmInstruction::Withdraw(withdraw_limits),
+        AmmInstruction::Withdraw(WithdrawInstruction {
+            amount: 1,
+            ..WithdrawInstruction::default()
+        }),
+    ] {
+        let data = instruction.pack().unwrap();
+        assert_eq!(AmmInstruction::unpack(&data).unwrap(), instruction);
+    }
+
+    let (market_program, market, bids) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let market_keys = MarketKeys {
+        market_program,
+        market,
+        coin_vault: Pubkey::new_unique(),
+        pc_vault: Pubkey::new_unique(),
+        vault_signer: Pubkey::new_unique(),
+        event_queue: Pubkey::new_unique(),
+        bids,
+        asks: Pubkey::new_unique(),
+    };
+    let amm_keys = AmmKeys {
+        amm_pool: pool,
+        amm_coin_mint: Pubkey::new_unique(),
+        amm_pc_mint: Pubkey::new_unique(),
+        amm_authority: Pubkey::new_unique(),
+        amm_target: Pubkey::new_unique(),
+        amm_coin_vault: Pubkey::new_unique(),
+        amm_pc_vault: Pubkey::new_unique(),
+        amm_lp_mint: Pubkey::new_unique(),
+        amm_open_order: Pubkey::new_unique(),
+        market_program,
+        market,
+        nonce: 0,
+    };
+    let (program, user) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let ix = deposit(
+        &program,
+        &amm_keys,
+        &market_keys,
+        &user,
+        &user,
+        &user,
+        &user,
+        limits,
+    )
+    .unwrap();
+    assert_eq!(ix.accounts.len(), 14);
+    let ix = withdraw(
+        &program,
+        &amm_keys,
+        &market_keys,
+        &user,
+        &user,
+        &user,
+        &user,
+        withdraw_limits,
+    )
+    .unwrap();
+    assert_eq!(ix.accounts.len(), 20);
+    assert_eq!(ix.accounts[18].pubkey, bids);
+}
@@ -0,0 +1,189 @@
+//! OpenBook market accounts of AMM v4 pools.
+//!
+//! A pool with its orderbook enabled keeps part of its reserves in its
+//! OpenBook open orders account, and its swaps pass the real market accounts
+//! where other pools pad them. [`load_orderbook`] reads both for such pools.
+//! Deposits and withdrawals always pass the market accounts, see
+//! [`get_market_keys`].
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use arrayref::array_ref;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::raydium::{
+    error::RaydiumError,
+    getter::get_multiple_accounts,
+    structure::{AmmInfo, AmmStatus},
+};
+
+/// market 账户中各字段的偏移，前 5 字节是 "serum" 填充
+const MARKET_VAULT_SIGNER_NONCE: usize = 45;
+const MARKET_COIN_VAULT: usize = 117;
+const MARKET_PC_VAULT: usize = 165;
+const MARKET_EVENT_QUEUE: usize = 253;
+const MARKET_BIDS: usize = 285;
+const MARKET_ASKS: usize = 317;
+/// open orders 账户中的偏移
+const OPEN_ORDERS_COIN_TOTAL: usize = 85;
+const OPEN_ORDERS_PC_TOTAL: usize = 101;
+
+/// OpenBook market accounts a swap, deposit or withdrawal passes through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarketKeys {
+    pub market_program: Pubkey,
+    pub market: Pubkey,
+    pub coin_vault: Pubkey,
+    pub pc_vault: Pubkey,
+    pub vault_signer: Pubkey,
+    pub event_queue: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+}
+
+impl MarketKeys {
+    /// Reads the keys out of the data of the `market` account
+    pub fn unpack(market_program: &Pubkey, market: &Pubkey, data: &[u8]) -> Result<Self> {
+        let invalid = || RaydiumError::InvalidMarketAccount { pubkey: *market };
+        if data.len() < MARKET_ASKS + 32 {
+            return Err(invalid().into());
+        }
+        let key = |offset| Pubkey::new_from_array(*array_ref![data, offset, 32]);
+        let nonce = array_ref![data, MARKET_VAULT_SIGNER_NONCE, 8];
+        let vault_signer =
+            Pubkey::create_program_address(&[market.as_ref(), nonce], market_program)
+                .map_err(|_| invalid())?;
+        Ok(Self {
+            market_program: *market_program,
+            market: *market,
+            coin_vault: key(MARKET_COIN_VAULT),
+            pc_vault: key(MARKET_PC_VAULT),
+            vault_signer,
+            event_queue: key(MARKET_EVENT_QUEUE),
+            bids: key(MARKET_BIDS),
+            asks: key(MARKET_ASKS),
+        })
+    }
+}
+
+/// Pool tokens held by its open orders account, free or locked in orders
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpenOrders {
+    pub native_coin_total: u64,
+    pub native_pc_total: u64,
+}
+
+impl OpenOrders {
+    pub fn unpack(open_orders: &Pubkey, data: &[u8]) -> Result<Self> {
+        if data.len() < OPEN_ORDERS_PC_TOTAL + 8 {
+            return Err(RaydiumError::InvalidMarketAccount {
+                pubkey: *open_orders,
+            }
+            .into());
+        }
+        let amount = |offset| u64::from_le_bytes(*array_ref![data, offset, 8]);
+        Ok(Self {
+            native_coin_total: amount(OPEN_ORDERS_COIN_TOTAL),
+            native_pc_total: amount(OPEN_ORDERS_PC_TOTAL),
+        })
+    }
+}
+
+/// Market side of a pool with its orderbook enabled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Orderbook {
+    pub market: MarketKeys,
+    pub open_orders: OpenOrders,
+}
+
+/// Market keys of the pool `amm`
+pub async fn get_market_keys(client: Arc<RpcClient>, amm: &AmmInfo) -> Result<MarketKeys> {
+    let (market_program, market) = (amm.market_program, amm.market);
+    let account = client.get_account(&market).await?;
+    if account.owner != market_program {
+        return Err(RaydiumError::InvalidMarketAccount { pubkey: market }.into());
+    }
+    MarketKeys::unpack(&market_program, &market, &account.data)
+}
+
+/// Market keys and open orders of the pool `amm`, `None` unless its
+/// orderbook is enabled
+pub async fn load_orderbook(client: Arc<RpcClient>, amm: &AmmInfo) -> Result<Option<Orderbook>> {
+    if !AmmStatus::from_u64(amm.status).orderbook_permission() {
+        return Ok(None);
+    }
+    let (market_program, market, open_orders) = (amm.market_program, amm.market, amm.open_orders);
+    let accounts = get_multiple_accounts(client, &[market, open_orders]).await?;
+    let [market_account, open_orders_account] = accounts.as_slice() else {
+        return Err(anyhow!("expected 2 accounts, got {}", accounts.len()));
+    };
+    let market_account = market_account
+        .as_ref()
+        .filter(|account| account.owner == market_program)
+        .ok_or(RaydiumError::InvalidMarketAccount { pubkey: market })?;
+    let open_orders_account = open_orders_account
+        .as_ref()
+        .ok_or(RaydiumError::MissingAccount {
+            name: "amm open orders",
+            pubkey: open_orders,
+        })?;
+    Ok(Some(Orderbook {
+        market: MarketKeys::unpack(&market_program, &market, &market_account.data)?,
+        open_orders: OpenOrders::unpack(&open_orders, &open_orders_account.data)?,
+    }))
+}
+
+#[test]
+fn test_unpack_market_accounts() {
+    use crate::raydium::math::calc_total_without_take_pnl;
+
+    // 构造一个 market 账户，nonce 要能推导出 vault signer
+    let (market_program, market) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let mut data = vec![0u8; 388];
+    let nonce = (0u64..)
+        .find(|nonce| {
+            Pubkey::create_program_address(
+                &[market.as_ref(), &nonce.to_le_bytes()],
+                &market_program,
+            )
+            .is_ok()
+        })
+        .unwrap();
+    data[MARKET_VAULT_SIGNER_NONCE..][..8].copy_from_slice(&nonce.to_le_bytes());
+    let bids = Pubkey::new_unique();
+    data[MARKET_BIDS..][..32].copy_from_slice(bids.as_ref());
+    let keys = MarketKeys::unpack(&market_program, &market, &data).unwrap();
+    assert_eq!(keys.bids, bids);
+    assert_eq!(keys.asks, Pubkey::default());
+    assert!(MarketKeys::unpack(&market_program, &market, &data[..300]).is_err());
+
+    let open_orders = Pubkey::new_unique();
+    let mut data = vec![0u8; 3228];
+    data[OPEN_ORDERS_COIN_TOTAL..][..8].copy_from_slice(&5u64.to_le_bytes());
+    data[OPEN_ORDERS_PC_TOTAL..][..8].copy_from_slice(&7u64.to_le_bytes());
+    assert_eq!(
+        OpenOrders::unpack(&open_orders, &data).unwrap(),
+        OpenOrders {
+            native_coin_total: 5,
+            native_pc_total: 7,
+        }
+    );
+    assert!(OpenOrders::unpack(&open_orders, &data[..100]).is_err());
+
+    // 开启订单簿时储备包含open orders里的部分
+    let orderbook = Orderbook {
+        market: keys,
+        open_orders: OpenOrders::unpack(&open_orders, &data).unwrap(),
+    };
+    let amm = AmmInfo::default();
+    assert_eq!(
+        calc_total_without_take_pnl(100, 200, Some(&orderbook), &amm).unwrap(),
+        (107, 205)
+    );
+    assert_eq!(
+        calc_total_without_take_pnl(100, 200, None, &amm).unwrap(),
+        (100, 200)
+    );
+}
@@ -3,14 +3,21 @@ use std::sync::Arc;
 use super::structure::{AmmInfo, AmmKeys, AmmSwapInfoResult};
 
 use crate::raydium::swap_instructions::AmmInstruction::{SwapBaseIn, SwapBaseOut};
-use crate::raydium::{
-    getter::get_multiple_accounts,
-    structure::{AmmStatus, SwapDirection},
+use crate::{
+    config::program_ids,
+    math::slippage::Slippage,
+    quote::{FeeBreakdown, Quote},
+    raydium::{
+        error::{RaydiumError, SwapError},
+        getter::{get_multiple_accounts, get_pool_state},
+        market::{load_orderbook, MarketKeys, Orderbook},
+        structure::{AmmStatus, Fees, SwapDirection},
+    },
+    rpc::cache::{get_accounts, AccountKind},
 };
 use anyhow::{anyhow, Result};
-use arrayref::array_ref;
 use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_sdk::{program_pack::Pack, pubkey::Pubkey};
+use solana_sdk::{account::Account as SolanaAccount, program_pack::Pack, pubkey::Pubkey};
 use spl_token::state::Account;
 
 pub const AUTHORITY_AMM: &'static [u8] = b"amm authority";
@@ -25,35 +32,62 @@ pub async fn calculate_swap_info(
     slippage_bps: u64,
     base_in: bool,
 ) -> Result<AmmSwapInfoResult> {
+    let status = amm_state.status;
+    if !AmmStatus::from_u64(status).swap_permission() {
+        return Err(SwapError::PoolDisabled {
+            pool: pool_id,
+            status,
+        }
+        .into());
+    }
     // load amm keys
     let amm_keys = load_amm_keys(amm_state, &amm_program, &pool_id)?;
-    let load_pubkeys = vec![
-        pool_id,
-        amm_keys.amm_pc_vault,
-        amm_keys.amm_coin_vault,
-        user_input_token,
+    // 开启账户缓存时同一个池子的重复报价不再重新读取
+    let load_keys = [
+        ("amm pool", pool_id, AccountKind::Amm),
+        ("amm pc vault", amm_keys.amm_pc_vault, AccountKind::Token),
+        (
+            "amm coin vault",
+            amm_keys.amm_coin_vault,
+            AccountKind::Token,
+        ),
+        ("user input token", user_input_token, AccountKind::Token),
     ];
-
-    let rsps = get_multiple_accounts(rpc_client.clone(), &load_pubkeys).await?;
-    let accounts = array_ref![rsps, 0, 4];
-    let [amm_account, amm_pc_vault_account, amm_coin_vault_account, user_input_token_account] =
-        accounts;
-    let amm_pc_vault = Account::unpack(&amm_pc_vault_account.as_ref().unwrap().data).unwrap();
-    let amm_coin_vault = Account::unpack(&amm_coin_vault_account.as_ref().unwrap().data).unwrap();
-    let user_input_token_info =
-        Account::unpack(&user_input_token_account.as_ref().unwrap().data).unwrap();
-    assert_eq!(
-        AmmStatus::from_u64(amm_state.status).orderbook_permission(),
-        false
-    );
-
-    let (amm_pool_pc_vault_amount, amm_pool_coin_vault_amount) =
-        calc_total_without_take_pnl_no_orderbook(
-            amm_pc_vault.amount,
-            amm_coin_vault.amount,
-            &amm_state,
-        )
-        .unwrap();
+    let keys: Vec<_> = load_keys
+        .iter()
+        .map(|(_, pubkey, kind)| (*pubkey, *kind))
+        .collect();
+    let accounts = get_accounts(rpc_client.clone(), &keys).await?;
+    if accounts.len() != load_keys.len() {
+        return Err(anyhow!(
+            "expected {} accounts, got {}",
+            load_keys.len(),
+            accounts.len()
+        ));
+    }
+    let mut token_accounts = vec![];
+    for ((name, pubkey, _), account) in load_keys.into_iter().zip(&accounts) {
+        let Some(account) = account else {
+            return Err(SwapError::AccountMissing { name, pubkey }.into());
+        };
+        if let Some(token) = account.token() {
+            token_accounts.push(*token);
+        }
+    }
+    let [amm_pc_vault, amm_coin_vault, user_input_token_info] = token_accounts[..] else {
+        return Err(anyhow!(
+            "expected 3 token accounts, got {}",
+            token_accounts.len()
+        ));
+    };
+    // 开启订单簿的池子有部分储备在open orders里，swap也要带上市场账户
+    let orderbook = load_orderbook(rpc_client, amm_state).await?;
+    let (amm_pool_pc_vault_amount, amm_pool_coin_vault_amount) = calc_total_without_take_pnl(
+        amm_pc_vault.amount,
+        amm_coin_vault.amount,
+        orderbook.as_ref(),
+        amm_state,
+    )?;
 
     let (swap_direction, input_mint, output_mint) =
         if user_input_token_info.mint == amm_keys.amm_coin_mint {
@@ -69,9 +103,23 @@ pub async fn calculate_swap_info(
                 amm_keys.amm_coin_mint,
             )
         } else {
-            panic!("input tokens not match pool vaults");
+            return Err(SwapError::MintMismatch {
+                mint: user_input_token_info.mint,
+                coin_mint: amm_keys.amm_coin_mint,
+                pc_mint: amm_keys.amm_pc_mint,
+            }
+            .into());
         };
 
+    let expected_other_amount = swap_exact_amount(
+        amm_pool_pc_vault_amount,
+        amm_pool_coin_vault_amount,
+        amm_state.fees.swap_fee_numerator,
+        amm_state.fees.swap_fee_denominator,
+        swap_direction.clone(),
+        amount_specified,
+        base_in,
+    )?;
     let other_amount_threshold = swap_with_slippage(
         amm_pool_pc_vault_amount,
         amm_pool_coin_vault_amount,
@@ -83,8 +131,52 @@ pub async fn calculate_swap_info(
         slippage_bps,
     )?;
 
-    Ok(AmmSwapInfoResult {
-        pool_id,
+    Ok(swap_accounts(
+        &amm_keys,
+        orderbook.as_ref().map(|orderbook| &orderbook.market),
+        input_mint,
+        output_mint,
+        amount_specified,
+        other_amount_threshold,
+        expected_other_amount,
+    ))
+}
+
+/// Accounts and amounts of a swap through the pool of `amm_keys`, the market
+/// accounts padded unless the pool's orderbook is enabled
+pub(crate) fn swap_accounts(
+    amm_keys: &AmmKeys,
+    market: Option<&MarketKeys>,
+    input_mint: Pubkey,
+    output_mint: Pubkey,
+    amount_specified: u64,
+    other_amount_threshold: u64,
+    expected_other_amount: u64,
+) -> AmmSwapInfoResult {
+    if let Some(market) = market {
+        return AmmSwapInfoResult {
+            pool_id: amm_keys.amm_pool,
+            amm_authority: amm_keys.amm_authority,
+            amm_open_orders: amm_keys.amm_open_order,
+            amm_coin_vault: amm_keys.amm_coin_vault,
+            amm_pc_vault: amm_keys.amm_pc_vault,
+            input_mint,
+            output_mint,
+            market_program: market.market_program,
+            market: market.market,
+            market_coin_vault: market.coin_vault,
+            market_pc_vault: market.pc_vault,
+            market_vault_signer: market.vault_signer,
+            market_event_queue: market.event_queue,
+            market_bids: market.bids,
+            market_asks: market.asks,
+            amount_specified,
+            other_amount_threshold,
+            expected_other_amount,
+        };
+    }
+    AmmSwapInfoResult {
+        pool_id: amm_keys.amm_pool,
         amm_authority: amm_keys.amm_authority,
         amm_open_orders: amm_keys.amm_open_order,
         amm_coin_vault: amm_keys.amm_coin_vault,
@@ -101,6 +193,179 @@ pub async fn calculate_swap_info(
         market_asks: amm_keys.amm_open_order,   // padding readwrite account
         amount_specified,
         other_amount_threshold,
+        expected_other_amount,
+    }
+}
+
+/// Output of swapping exactly `amount_in` of `input_mint` at the pool's current
+/// vault balances, after the swap fee
+pub async fn quote_base_in(
+    rpc_client: Arc<RpcClient>,
+    amm_state: &AmmInfo,
+    amm_program: Pubkey,
+    pool_id: Pubkey,
+    input_mint: &Pubkey,
+    amount_in: u64,
+) -> Result<u64> {
+    let amm_keys = load_amm_keys(amm_state, &amm_program, &pool_id)?;
+    let reserves = load_reserves(rpc_client, amm_state, &amm_keys).await?;
+
+    let swap_direction = if *input_mint == amm_keys.amm_coin_mint {
+        SwapDirection::Buy
+    } else if *input_mint == amm_keys.amm_pc_mint {
+        SwapDirection::Sell
+    } else {
+        return Err(SwapError::MintMismatch {
+            mint: *input_mint,
+            coin_mint: amm_keys.amm_coin_mint,
+            pc_mint: amm_keys.amm_pc_mint,
+        }
+        .into());
+    };
+
+    swap_exact_amount(
+        reserves.pc_amount,
+        reserves.coin_amount,
+        amm_state.fees.swap_fee_numerator,
+        amm_state.fees.swap_fee_denominator,
+        swap_direction,
+        amount_in,
+        true,
+    )
+}
+
+/// Pc and coin reserves of a pool
+pub(crate) struct Reserves {
+    pub pc_amount: u64,
+    pub coin_amount: u64,
+    /// Market accounts its swaps pass, if its orderbook is enabled
+    pub market: Option<MarketKeys>,
+}
+
+/// Pc and coin amounts of the pool's vaults and open orders, pnl not yet
+/// taken excluded
+pub(crate) async fn load_reserves(
+    rpc_client: Arc<RpcClient>,
+    amm_state: &AmmInfo,
+    amm_keys: &AmmKeys,
+) -> Result<Reserves> {
+    let load_pubkeys = [amm_keys.amm_pc_vault, amm_keys.amm_coin_vault];
+    let rsps = get_multiple_accounts(rpc_client.clone(), &load_pubkeys).await?;
+    if rsps.len() != load_pubkeys.len() {
+        return Err(anyhow!(
+            "expected {} accounts, got {}",
+            load_pubkeys.len(),
+            rsps.len()
+        ));
+    }
+    let amm_pc_vault = unpack_token_account("amm pc vault", &load_pubkeys[0], &rsps[0])?;
+    let amm_coin_vault = unpack_token_account("amm coin vault", &load_pubkeys[1], &rsps[1])?;
+    let orderbook = load_orderbook(rpc_client, amm_state).await?;
+    let (pc_amount, coin_amount) = calc_total_without_take_pnl(
+        amm_pc_vault.amount,
+        amm_coin_vault.amount,
+        orderbook.as_ref(),
+        amm_state,
+    )?;
+    Ok(Reserves {
+        pc_amount,
+        coin_amount,
+        market: orderbook.map(|orderbook| orderbook.market),
+    })
+}
+
+/// Quotes swapping exactly `amount_in` through the AMM v4 pool `pool_id`
+///
+/// `Buy` swaps the coin mint into the pc mint, `Sell` the reverse.
+pub async fn quote(
+    rpc_client: Arc<RpcClient>,
+    pool_id: &Pubkey,
+    amount_in: u64,
+    direction: SwapDirection,
+    slippage_bps: u64,
+) -> Result<Quote> {
+    let (pool_id, amm_state) = get_pool_state(rpc_client.clone(), &pool_id.to_string()).await?;
+    let amm_keys = load_amm_keys(&amm_state, &program_ids().raydium_amm, &pool_id)?;
+    let reserves = load_reserves(rpc_client, &amm_state, &amm_keys).await?;
+    quote_reserves(
+        reserves.pc_amount,
+        reserves.coin_amount,
+        &amm_state.fees,
+        direction,
+        amount_in,
+        slippage_bps,
+    )
+}
+
+/// Quotes swapping exactly `amount_in` at the given reserves
+pub fn quote_reserves(
+    pc_vault_amount: u64,
+    coin_vault_amount: u64,
+    fees: &Fees,
+    direction: SwapDirection,
+    amount_in: u64,
+    slippage_bps: u64,
+) -> Result<Quote> {
+    let expected_out = swap_exact_amount(
+        pc_vault_amount,
+        coin_vault_amount,
+        fees.swap_fee_numerator,
+        fees.swap_fee_denominator,
+        direction.clone(),
+        amount_in,
+        true,
+    )?;
+    let min_out = Slippage::Bps(slippage_bps).min_out(expected_out)?;
+
+    // 交易费中pnl比例的部分归协议
+    let swap_fee = (amount_in as u128 * fees.swap_fee_numerator as u128
+        / fees.swap_fee_denominator as u128) as u64;
+    let protocol_fee = if fees.pnl_denominator == 0 {
+        0
+    } else {
+        (swap_fee as u128 * fees.pnl_numerator as u128 / fees.pnl_denominator as u128) as u64
+    };
+    let (input_reserve, output_reserve) = match direction {
+        SwapDirection::Buy => (coin_vault_amount, pc_vault_amount),
+        SwapDirection::Sell => (pc_vault_amount, coin_vault_amount),
+    };
+    Ok(Quote::new(
+        amount_in,
+        expected_out,
+        min_out,
+        input_reserve,
+        output_reserve,
+        FeeBreakdown {
+            lp_fee: swap_fee - protocol_fee,
+            protocol_fee,
+        },
+    ))
+}
+
+/// Unpacks an SPL token account, naming it in the error if it's missing or invalid
+pub(crate) fn unpack_token_account(
+    name: &'static str,
+    pubkey: &Pubkey,
+    account: &Option<SolanaAccount>,
+) -> Result<Account> {
+    let account = account.as_ref().ok_or(RaydiumError::MissingAccount {
+        name,
+        pubkey: *pubkey,
+    })?;
+    if account.owner != spl_token::ID {
+        return Err(RaydiumError::NotTokenAccount {
+            name,
+            pubkey: *pubkey,
+            owner: account.owner,
+        }
+        .into());
+    }
+    Account::unpack(&account.data).map_err(|_| {
+        RaydiumError::InvalidTokenAccount {
+            name,
+            pubkey: *pubkey,
+        }
+        .into()
     })
 }
 
@@ -121,6 +386,27 @@ pub fn load_amm_keys(amm: &AmmInfo, amm_program: &Pubkey, amm_pool: &Pubkey) ->
     })
 }
 
+/// Pc and coin amounts of a pool, its open orders' added if its orderbook is
+/// enabled
+pub fn calc_total_without_take_pnl(
+    pc_vault_amount: u64,
+    coin_vault_amount: u64,
+    orderbook: Option<&Orderbook>,
+    amm: &AmmInfo,
+) -> Result<(u64, u64)> {
+    let Some(orderbook) = orderbook else {
+        return calc_total_without_take_pnl_no_orderbook(pc_vault_amount, coin_vault_amount, amm);
+    };
+    let open_orders = orderbook.open_orders;
+    let pc_amount = pc_vault_amount
+        .checked_add(open_orders.native_pc_total)
+        .ok_or(anyhow!("CheckedAddOverflow"))?;
+    let coin_amount = coin_vault_amount
+        .checked_add(open_orders.native_coin_total)
+        .ok_or(anyhow!("CheckedAddOverflow"))?;
+    calc_total_without_take_pnl_no_orderbook(pc_amount, coin_amount, amm)
+}
+
 pub fn calc_total_without_take_pnl_no_orderbook<'a>(
     pc_amount: u64,
     coin_amount: u64,
@@ -154,12 +440,13 @@ pub fn swap_with_slippage(
         amount_specified,
         swap_base_in,
     )?;
+    let slippage = Slippage::Bps(slippage_bps);
     let other_amount_threshold = if swap_base_in {
         // min out
-        amount_with_slippage(other_amount_threshold, slippage_bps, false)?
+        slippage.min_out(other_amount_threshold)?
     } else {
         // max in
-        amount_with_slippage(other_amount_threshold, slippage_bps, true)?
+        slippage.max_in(other_amount_threshold)?
     };
     Ok(other_amount_threshold)
 }
@@ -169,28 +456,6 @@ pub fn authority_id(program_id: &Pubkey, amm_seed: &[u8], nonce: u8) -> Result<P
         .map_err(|_| anyhow!("InvalidProgramAddress"))
 }
 
-pub fn amount_with_slippage(amount: u64, slippage_bps: u64, up_towards: bool) -> Result<u64> {
-    let amount = amount;
-    println!("real amount {:?}", amount);
-    let ten_thounsand = 10000u64;
-    let slippage_bps = slippage_bps;
-    let amount_with_slippage = if up_towards {
-        amount
-            .checked_mul(slippage_bps.checked_add(ten_thounsand).unwrap())
-            .unwrap()
-            .checked_div(ten_thounsand)
-            .unwrap()
-    } else {
-        amount
-            .checked_mul(ten_thounsand.checked_sub(slippage_bps).unwrap())
-            .unwrap()
-            .checked_div(ten_thounsand)
-            .unwrap()
-    };
-    u64::try_from(amount_with_slippage)
-        .map_err(|_| anyhow!("failed to read keypair from {}", amount_with_slippage))
-}
-
 fn swap_exact_amount(
     pc_vault_amount: u64,
     coin_vault_amount: u64,
@@ -200,52 +465,55 @@ fn swap_exact_amount(
     amount_specified: u64,
     swap_base_in: bool,
 ) -> Result<u64> {
+    if swap_fee_numerator >= swap_fee_denominator {
+        return Err(anyhow!(
+            "invalid swap fee {}/{}",
+            swap_fee_numerator,
+            swap_fee_denominator
+        ));
+    }
+    let insufficient = || SwapError::InsufficientLiquidity {
+        amount: amount_specified,
+        pc_reserve: pc_vault_amount,
+        coin_reserve: coin_vault_amount,
+    };
     let other_amount_threshold = if swap_base_in {
-        let swap_fee = u128::from(amount_specified)
-            .checked_mul(swap_fee_numerator.into())
-            .unwrap()
-            .checked_div(swap_fee_denominator.into())
-            .unwrap();
-
-        let swap_in_after_deduct_fee = u128::from(amount_specified).checked_sub(swap_fee).unwrap();
-        let swap_amount_out = swap_token_amount_base_in(
+        // 费率小于1，扣费后不会下溢
+        let swap_fee = u128::from(amount_specified) * u128::from(swap_fee_numerator)
+            / u128::from(swap_fee_denominator);
+        let swap_in_after_deduct_fee = u128::from(amount_specified) - swap_fee;
+        swap_token_amount_base_in(
             swap_in_after_deduct_fee,
             pc_vault_amount.into(),
             coin_vault_amount.into(),
             swap_direction,
-        ) as u64;
-        swap_amount_out
+        )
+        .ok_or_else(insufficient)?
     } else {
         let swap_in_before_add_fee = swap_token_amount_base_out(
             amount_specified.into(),
             pc_vault_amount.into(),
             coin_vault_amount.into(),
             swap_direction,
-        );
-        let swap_in_after_add_fee = swap_in_before_add_fee
+        )
+        .ok_or_else(insufficient)?;
+        swap_in_before_add_fee
             .checked_mul(swap_fee_denominator.into())
-            .unwrap()
-            .checked_div(
-                (swap_fee_denominator
-                    .checked_sub(swap_fee_numerator)
-                    .unwrap())
-                .into(),
-            )
-            .unwrap() as u64;
-
-        swap_in_after_add_fee
+            .ok_or_else(insufficient)?
+            / u128::from(swap_fee_denominator - swap_fee_numerator)
     };
 
-    Ok(other_amount_threshold)
+    Ok(u64::try_from(other_amount_threshold).map_err(|_| insufficient())?)
 }
 
+/// Output of swapping `amount_in` after fees, `None` if either reserve is
+/// too small
 pub fn swap_token_amount_base_in(
     amount_in: u128,
     total_pc_without_take_pnl: u128,
     total_coin_without_take_pnl: u128,
     swap_direction: SwapDirection,
-) -> u128 {
-    let amount_out;
+) -> Option<u128> {
     match swap_direction {
         SwapDirection::Buy => {
             // (x + delta_x) * (y + delta_y) = x * y
@@ -253,12 +521,10 @@ pub fn swap_token_amount_base_in(
             // => amount_out = pc - coin * pc / (coin + amount_in)
             // => amount_out = ((pc * coin + pc * amount_in) - coin * pc) / (coin + amount_in)
             // => amount_out =  pc * amount_in / (coin + amount_in)
-            let denominator = total_coin_without_take_pnl.checked_add(amount_in).unwrap();
-            amount_out = total_pc_without_take_pnl
-                .checked_mul(amount_in)
-                .unwrap()
+            let denominator = total_coin_without_take_pnl.checked_add(amount_in)?;
+            total_pc_without_take_pnl
+                .checked_mul(amount_in)?
                 .checked_div(denominator)
-                .unwrap();
         }
         SwapDirection::Sell => {
             // (x + delta_x) * (y + delta_y) = x * y
@@ -266,24 +532,22 @@ pub fn swap_token_amount_base_in(
             // => amount_out = coin - coin * pc / (pc + amount_in)
             // => amount_out = (coin * pc + coin * amount_in - coin * pc) / (pc + amount_in)
             // => amount_out = coin * amount_in / (pc + amount_in)
-            let denominator = total_pc_without_take_pnl.checked_add(amount_in).unwrap();
-            amount_out = total_coin_without_take_pnl
-                .checked_mul(amount_in)
-                .unwrap()
+            let denominator = total_pc_without_take_pnl.checked_add(amount_in)?;
+            total_coin_without_take_pnl
+                .checked_mul(amount_in)?
                 .checked_div(denominator)
-                .unwrap();
         }
     }
-    return amount_out;
 }
 
+/// Input needed for `amount_out` before fees, `None` if the output reserve
+/// doesn't cover it
 pub fn swap_token_amount_base_out(
     amount_out: u128,
     total_pc_without_take_pnl: u128,
     total_coin_without_take_pnl: u128,
     swap_direction: SwapDirection,
-) -> u128 {
-    let amount_in;
+) -> Option<u128> {
     match swap_direction {
         SwapDirection::Buy => {
             // (x + delta_x) * (y + delta_y) = x * y
@@ -291,12 +555,10 @@ pub fn swap_token_amount_base_out(
             // => amount_in = coin * pc / (pc - amount_out) - coin
             // => amount_in = (coin * pc - pc * coin + amount_out * coin) / (pc - amount_out)
             // => amount_in = (amount_out * coin) / (pc - amount_out)
-            let denominator = total_pc_without_take_pnl.checked_sub(amount_out).unwrap();
-            amount_in = total_coin_without_take_pnl
-                .checked_mul(amount_out)
-                .unwrap()
+            let denominator = total_pc_without_take_pnl.checked_sub(amount_out)?;
+            total_coin_without_take_pnl
+                .checked_mul(amount_out)?
                 .checked_div(denominator)
-                .unwrap();
         }
         SwapDirection::Sell => {
             // (x + delta_x) * (y + delta_y) = x * y
@@ -308,13 +570,85 @@ pub fn swap_token_amount_base_out(
             // => amount_in = coin * pc / (coin - amount_out) - pc
             // => amount_in = (coin * pc - pc * coin + pc * amount_out) / (coin - amount_out)
             // => amount_in = (pc * amount_out) / (coin - amount_out)
-            let denominator = total_coin_without_take_pnl.checked_sub(amount_out).unwrap();
-            amount_in = total_pc_without_take_pnl
-                .checked_mul(amount_out)
-                .unwrap()
+            let denominator = total_coin_without_take_pnl.checked_sub(amount_out)?;
+            total_pc_without_take_pnl
+                .checked_mul(amount_out)?
                 .checked_div(denominator)
-                .unwrap();
         }
     }
-    return amount_in;
+}
+
+#[test]
+fn test_unpack_token_account_rejects_bad_vaults() {
+    let vault = Pubkey::new_unique();
+
+    // 缺失的 vault 账户
+    let err = unpack_token_account("amm pc vault", &vault, &None).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RaydiumError>(),
+        Some(RaydiumError::MissingAccount {
+            name: "amm pc vault",
+            ..
+        })
+    ));
+
+    // 不属于 token program
+    let foreign = SolanaAccount::new(1, Account::LEN, &Pubkey::new_unique());
+    let err = unpack_token_account("amm pc vault", &vault, &Some(foreign)).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RaydiumError>(),
+        Some(RaydiumError::NotTokenAccount { .. })
+    ));
+
+    // 未初始化的数据
+    let uninitialized = SolanaAccount::new(1, Account::LEN, &spl_token::ID);
+    let err = unpack_token_account("amm pc vault", &vault, &Some(uninitialized)).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RaydiumError>(),
+        Some(RaydiumError::InvalidTokenAccount { .. })
+    ));
+
+    let mut data = vec![0; Account::LEN];
+    Account::pack(
+        Account {
+            mint: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            amount: 42,
+            state: spl_token::state::AccountState::Initialized,
+            ..Account::default()
+        },
+        &mut data,
+    )
+    .unwrap();
+    let mut valid = SolanaAccount::new(1, 0, &spl_token::ID);
+    valid.data = data;
+    assert_eq!(
+        unpack_token_account("amm pc vault", &vault, &Some(valid))
+            .unwrap()
+            .amount,
+        42
+    );
+}
+
+#[test]
+fn test_swap_exact_amount_errors() {
+    // 正常报价
+    assert_eq!(
+        swap_exact_amount(1000, 1000, 25, 10000, SwapDirection::Buy, 100, true).unwrap(),
+        90
+    );
+    // 输出超过储备
+    let err =
+        swap_exact_amount(1000, 1000, 25, 10000, SwapDirection::Buy, 1000, false).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SwapError>(),
+        Some(SwapError::InsufficientLiquidity { amount: 1000, .. })
+    ));
+    // 空池子
+    let err = swap_exact_amount(0, 0, 25, 10000, SwapDirection::Sell, 0, true).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SwapError>(),
+        Some(SwapError::InsufficientLiquidity { .. })
+    ));
+    assert!(swap_exact_amount(1000, 1000, 0, 0, SwapDirection::Sell, 10, true).is_err());
 }
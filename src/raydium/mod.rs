@@ -1,6 +1,14 @@
+pub mod cpmm;
+pub mod error;
 pub mod getter;
+pub mod liquidity;
+pub mod market;
 pub mod math;
+pub mod open_time;
+pub mod pools;
 pub mod structure;
-mod swap;
+pub mod swap;
 pub mod swap_instructions;
 pub mod tx;
+
+pub use math::quote;
@@ -0,0 +1,307 @@
+//! Buys at a pool's open time.
+//!
+//! An AMM v4 pool can be created `WaitingTrade` with a `pool_open_time` in
+//! the future, and swaps through it fail until the cluster clock reaches it.
+//! The [`OpenSchedule`] of such a pool turns the open time into the slot it
+//! is expected in, from the `Clock` sysvar, whose timestamp is the one the
+//! program compares against, and the nominal slot duration.
+//!
+//! [`buy_at_open`] prepares the buy a few seconds before the open, signed and
+//! with its minimum output from the pool's initial reserves, waits for the
+//! open slot and sends it then through the task's sender. With `spam_slots`
+//! the transaction tips Jito instead and is also sent as a bundle in each of
+//! the slots before the open: a bundle whose transaction fails is dropped
+//! without paying anything, so the early ones just don't land and the first
+//! slot the pool is open in gets the buy.
+
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    clock::{Clock, DEFAULT_MS_PER_SLOT},
+    compute_budget::ComputeBudgetInstruction,
+    pubkey::Pubkey,
+    signature::Keypair,
+    signer::Signer,
+    sysvar,
+};
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+};
+use tracing::{debug, info};
+
+use crate::{
+    config::{bot_config, program_ids},
+    fees::priority,
+    portfolio::{record_trade, Side},
+    risk,
+    tx::{
+        blockhash::recent_blockhash,
+        budget::global_guard,
+        mode::ExecutionMode,
+        sender::{self, send_jito_bundle, Sender},
+        simulate::{simulate, ExpectedOutput, OutputAccount, TxOutcome},
+    },
+    wallet::wsol,
+};
+
+use super::{
+    getter::get_pool_state,
+    math::calculate_swap_info,
+    structure::{AmmInfo, AmmStatus},
+    swap::amm_swap,
+    tx::{build_transaction, paper},
+};
+
+/// Slots before the open, and before the spam, the buy is prepared in,
+/// about 8 seconds, well within the blockhash's lifetime
+const PREPARE_SLOTS: u64 = 20;
+
+/// How often the slot is polled close to the open
+const SLOT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// When a pool waiting for its open time is expected to open
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenSchedule {
+    pub pool_id: Pubkey,
+    /// Unix timestamp the program lets swaps through from
+    pub open_time: i64,
+    /// Slot whose clock is expected to first reach `open_time`
+    pub open_slot: u64,
+}
+
+impl OpenSchedule {
+    /// `None` unless `pool` is `WaitingTrade` with an open time after `clock`
+    pub fn new(pool_id: Pubkey, pool: &AmmInfo, clock: &Clock) -> Option<Self> {
+        let status = pool.status;
+        let open_time = pool.state_data.pool_open_time as i64;
+        if status != AmmStatus::WaitingTrade.into_u64() || open_time <= clock.unix_timestamp {
+            return None;
+        }
+        Some(Self {
+            pool_id,
+            open_time,
+            open_slot: expected_slot(clock, open_time),
+        })
+    }
+
+    /// The schedule of the pool `pool_id`, `None` if it's already open
+    pub async fn fetch(client: &Arc<RpcClient>, pool_id: &Pubkey) -> Result<Option<Self>> {
+        let (pool_id, pool) = get_pool_state(client.clone(), &pool_id.to_string()).await?;
+        let clock = get_clock(client).await?;
+        Ok(Self::new(pool_id, &pool, &clock))
+    }
+
+    /// Wall clock time of the open
+    pub fn opens_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.open_time.max(0) as u64)
+    }
+}
+
+/// Slot the cluster clock is expected to reach `unix_timestamp` in, from the
+/// current `clock`
+pub fn expected_slot(clock: &Clock, unix_timestamp: i64) -> u64 {
+    let ms = (unix_timestamp - clock.unix_timestamp).max(0) as u64 * 1000;
+    clock.slot + ms.div_ceil(DEFAULT_MS_PER_SLOT)
+}
+
+/// The cluster's `Clock` sysvar
+pub async fn get_clock(client: &RpcClient) -> Result<Clock> {
+    let account = client.get_account(&sysvar::clock::ID).await?;
+    Ok(bincode::deserialize(&account.data)?)
+}
+
+/// Waits until the cluster reaches `slot`
+pub async fn wait_for_slot(client: &RpcClient, slot: u64) -> Result<()> {
+    loop {
+        let current = client.get_slot().await?;
+        if current >= slot {
+            return Ok(());
+        }
+        // 离得远时按槽位时长睡一半，接近时短轮询
+        let remaining = Duration::from_millis((slot - current) * DEFAULT_MS_PER_SLOT / 2);
+        tokio::time::sleep(remaining.max(SLOT_POLL_INTERVAL)).await;
+    }
+}
+
+/// Buys the token of the SOL pool `pool_id` with `lamports` as soon as the
+/// pool opens, see the module docs
+///
+/// `slippage` is in percent, like the other swaps.
+#[allow(clippy::too_many_arguments)]
+pub async fn buy_at_open(
+    client: Arc<RpcClient>,
+    keypair: Arc<Keypair>,
+    pool_id: &Pubkey,
+    lamports: u64,
+    slippage: u64,
+    spam_slots: u64,
+    is_simulate: bool,
+) -> Result<TxOutcome> {
+    let schedule = OpenSchedule::fetch(&client, pool_id)
+        .await?
+        .ok_or(anyhow!("pool {} isn't waiting for its open time", pool_id))?;
+    info!(
+        "pool {} opens at {} (~slot {})",
+        pool_id, schedule.open_time, schedule.open_slot
+    );
+    let first_slot = schedule.open_slot.saturating_sub(spam_slots);
+    wait_for_slot(&client, first_slot.saturating_sub(PREPARE_SLOTS)).await?;
+
+    let mode = ExecutionMode::resolve(is_simulate);
+    let sender = if spam_slots > 0 {
+        Sender::Jito
+    } else {
+        sender::current()
+    };
+    let owner = keypair.pubkey();
+    let native_mint = spl_token::native_mint::ID;
+    let (pool_id, pool_state) = get_pool_state(client.clone(), &pool_id.to_string()).await?;
+    let (mint, input_vault) = if pool_state.coin_vault_mint == native_mint {
+        (pool_state.pc_vault_mint, pool_state.coin_vault)
+    } else if pool_state.pc_vault_mint == native_mint {
+        (pool_state.coin_vault_mint, pool_state.pc_vault)
+    } else {
+        return Err(anyhow!("pool {} doesn't trade against SOL", pool_id));
+    };
+    let reservation = if !mode.simulates() {
+        risk::check_buy(&mint, lamports)?;
+        Some(global_guard().reserve(&mint, lamports)?)
+    } else {
+        None
+    };
+
+    // 开盘前按初始储备报价
+    let amm_program = program_ids().raydium_amm;
+    let swap_info = calculate_swap_info(
+        client.clone(),
+        &pool_state,
+        amm_program,
+        pool_id,
+        input_vault,
+        lamports,
+        slippage * 100,
+        true,
+    )
+    .await?;
+    let expected_out = swap_info.expected_other_amount;
+    let min_out = swap_info.other_amount_threshold;
+    let out_ata = get_associated_token_address(&owner, &mint);
+    let wsol = wsol::ata(&owner, lamports, false)?;
+
+    let mut instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(
+        bot_config().unit_limit,
+    )];
+    instructions.extend(wsol.setup.iter().cloned());
+    instructions.push(create_associated_token_account_idempotent(
+        &owner,
+        &owner,
+        &mint,
+        &spl_token::ID,
+    ));
+    instructions.push(amm_swap(
+        &amm_program,
+        swap_info,
+        &owner,
+        &wsol.address,
+        &out_ata,
+        lamports,
+        min_out,
+        true,
+    )?);
+    instructions.extend(wsol.cleanup.iter().cloned());
+    if sender.pays_priority_fee() {
+        let unit_price = priority::unit_price(&client, &instructions).await;
+        instructions.insert(
+            1,
+            ComputeBudgetInstruction::set_compute_unit_price(unit_price),
+        );
+    }
+    instructions.extend(sender.tip(&owner));
+    let txn = build_transaction(&keypair, &instructions, recent_blockhash(&client).await?)?;
+    info!(
+        "prepared buy of {} for {} lamports, at least {} out",
+        mint, lamports, min_out
+    );
+
+    if mode == ExecutionMode::Live && spam_slots > 0 {
+        let mut last_sent = None;
+        loop {
+            let slot = client.get_slot().await?;
+            if slot >= schedule.open_slot {
+                break;
+            }
+            if slot >= first_slot && last_sent != Some(slot) {
+                // 开盘前的bundle会失败被丢弃，不花费任何费用
+                if let Err(e) = send_jito_bundle(&txn).await {
+                    debug!("early bundle in slot {} failed {:?}", slot, e);
+                }
+                last_sent = Some(slot);
+            }
+            tokio::time::sleep(SLOT_POLL_INTERVAL).await;
+        }
+    } else {
+        wait_for_slot(&client, schedule.open_slot).await?;
+    }
+
+    let outcome = match mode {
+        ExecutionMode::Simulate => {
+            let expected = ExpectedOutput {
+                expected_out,
+                min_out,
+                account: OutputAccount::Token(out_ata),
+            };
+            TxOutcome::Simulated(simulate(&client, &txn, Some(expected)).await?)
+        }
+        ExecutionMode::Paper => paper(&txn),
+        ExecutionMode::Live => {
+            let sig = sender.submit(&client, &txn).await?;
+            info!("open buy {} signature: {:?}", sender, sig);
+            TxOutcome::Sent(vec![sig])
+        }
+    };
+    if let Some(reservation) = reservation {
+        reservation.commit();
+    }
+    record_trade(
+        "raydium",
+        Side::Buy,
+        &mint,
+        expected_out,
+        lamports,
+        &outcome,
+    );
+    Ok(outcome)
+}
+
+#[test]
+fn test_open_schedule() {
+    let clock = Clock {
+        slot: 1000,
+        unix_timestamp: 1_700_000_000,
+        ..Clock::default()
+    };
+    // 10秒后，按每槽400毫秒为25个槽位
+    assert_eq!(expected_slot(&clock, 1_700_000_010), 1025);
+    assert_eq!(expected_slot(&clock, 1_699_999_990), 1000);
+
+    let pool_id = Pubkey::new_unique();
+    let mut pool = AmmInfo {
+        status: AmmStatus::WaitingTrade.into_u64(),
+        ..AmmInfo::default()
+    };
+    pool.state_data.pool_open_time = 1_700_000_060;
+    let schedule = OpenSchedule::new(pool_id, &pool, &clock).unwrap();
+    assert_eq!(schedule.open_slot, 1150);
+
+    // 已经开盘或者不在等待状态的池子没有计划
+    pool.state_data.pool_open_time = 1_699_000_000;
+    assert!(OpenSchedule::new(pool_id, &pool, &clock).is_none());
+    pool.state_data.pool_open_time = 1_700_000_060;
+    pool.status = AmmStatus::SwapOnly.into_u64();
+    assert!(OpenSchedule::new(pool_id, &pool, &clock).is_none());
+}
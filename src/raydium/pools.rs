@@ -0,0 +1,82 @@
+//! Raydium pools of migrated Pump.fun tokens.
+//!
+//! Pools seen by the migration monitor are recorded here. Tokens migrated
+//! before the bot started are found with [`find_pool_by_mints`] and cached
+//! afterwards.
+
+use std::{
+    collections::HashMap,
+    mem::{offset_of, size_of},
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use anyhow::Result;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_filter::{Memcmp, RpcFilterType},
+};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::raydium::{getter::find_pool_by_mints, structure::AmmInfo};
+
+/// mint -> WSOL pool
+static KNOWN_POOLS: LazyLock<RwLock<HashMap<Pubkey, Pubkey>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Records the WSOL pool of `mint`
+pub fn record_pool(mint: Pubkey, pool: Pubkey) {
+    KNOWN_POOLS.write().unwrap().insert(mint, pool);
+}
+
+/// Pool recorded for `mint`, if any
+pub fn known_pool(mint: &Pubkey) -> Option<Pubkey> {
+    KNOWN_POOLS.read().unwrap().get(mint).copied()
+}
+
+/// Program account filters matching pools with `coin_mint` / `pc_mint`
+pub(crate) fn pool_filters(coin_mint: &Pubkey, pc_mint: &Pubkey) -> Vec<RpcFilterType> {
+    vec![
+        RpcFilterType::DataSize(size_of::<AmmInfo>() as u64),
+        RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            offset_of!(AmmInfo, coin_vault_mint),
+            coin_mint.as_ref(),
+        )),
+        RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            offset_of!(AmmInfo, pc_vault_mint),
+            pc_mint.as_ref(),
+        )),
+    ]
+}
+
+/// Finds the Raydium pool pairing `mint` with WSOL
+pub async fn find_sol_pool(client: Arc<RpcClient>, mint: &Pubkey) -> Result<Pubkey> {
+    if let Some(pool) = known_pool(mint) {
+        return Ok(pool);
+    }
+    let pool = find_pool_by_mints(client, mint, &spl_token::native_mint::ID).await?;
+    record_pool(*mint, pool);
+    Ok(pool)
+}
+
+#[test]
+fn test_pool_filters_match_amm_layout() {
+    let coin_mint = Pubkey::new_unique();
+    let pc_mint = Pubkey::new_unique();
+    let mut data = vec![0u8; size_of::<AmmInfo>()];
+    data[offset_of!(AmmInfo, coin_vault_mint)..][..32].copy_from_slice(coin_mint.as_ref());
+    data[offset_of!(AmmInfo, pc_vault_mint)..][..32].copy_from_slice(pc_mint.as_ref());
+
+    let amm = AmmInfo::load_from_bytes(&data).unwrap();
+    assert_eq!({ amm.coin_vault_mint }, coin_mint);
+    assert_eq!({ amm.pc_vault_mint }, pc_mint);
+
+    let matches = |filters: Vec<RpcFilterType>| {
+        filters.iter().all(|filter| match filter {
+            RpcFilterType::DataSize(size) => *size == data.len() as u64,
+            RpcFilterType::Memcmp(memcmp) => memcmp.bytes_match(&data),
+            _ => false,
+        })
+    };
+    assert!(matches(pool_filters(&coin_mint, &pc_mint)));
+    assert!(!matches(pool_filters(&pc_mint, &coin_mint)));
+}
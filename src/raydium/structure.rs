@@ -129,6 +129,8 @@ pub struct AmmSwapInfoResult {
     pub market_asks: Pubkey,
     pub amount_specified: u64,
     pub other_amount_threshold: u64,
+    /// other amount before slippage is applied
+    pub expected_other_amount: u64,
 }
 
 #[derive(Clone, Copy, Debug)]
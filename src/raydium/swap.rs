@@ -1,27 +1,84 @@
-use std::{env, sync::Arc};
+use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_sdk::{
-    instruction::Instruction, program_pack::Pack, pubkey::Pubkey, signature::Keypair,
-    signer::Signer, system_instruction,
-};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Keypair, signer::Signer};
 use spl_associated_token_account::{
-    get_associated_token_address, instruction::create_associated_token_account,
+    get_associated_token_address, get_associated_token_address_with_program_id,
 };
-use spl_token::{amount_to_ui_amount, state::Account, ui_amount_to_amount};
+use spl_token::ui_amount_to_amount;
 
 use crate::{
+    config::program_ids,
     new_client,
-    raydium::{getter, math::calculate_swap_info, swap_instructions, tx::new_signed_and_send},
+    portfolio::{record_trade, Side},
+    raydium::{
+        cpmm,
+        error::RaydiumError,
+        getter,
+        math::calculate_swap_info,
+        swap_instructions,
+        tx::{new_signed_and_send, trade_side},
+    },
+    risk,
+    tx::{
+        budget::global_guard,
+        mode::ExecutionMode,
+        simulate::{ExpectedOutput, OutputAccount, TxOutcome},
+    },
+    wallet::{self, wsol},
 };
 
 use super::{
     getter::get_pool_state,
     structure::{AmmSwapInfoResult, SwapDirection},
 };
-pub const AMM_PROGRAM: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
 
+/// Resolves the pool side of `token_in`, whichever vault each mint sits in
+///
+/// `Buy` swaps the coin vault mint into the pc vault mint, `Sell` the reverse.
+pub fn resolve_swap_direction(
+    token_in: &Pubkey,
+    token_out: &Pubkey,
+    coin_mint: &Pubkey,
+    pc_mint: &Pubkey,
+) -> Result<SwapDirection> {
+    if token_in == coin_mint && token_out == pc_mint {
+        Ok(SwapDirection::Buy)
+    } else if token_in == pc_mint && token_out == coin_mint {
+        Ok(SwapDirection::Sell)
+    } else {
+        Err(RaydiumError::PoolMintMismatch {
+            token_in: *token_in,
+            token_out: *token_out,
+            coin_mint: *coin_mint,
+            pc_mint: *pc_mint,
+        }
+        .into())
+    }
+}
+
+/// Input amount of a swap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SwapAmount {
+    /// In UI units, scaled by the input mint's decimals
+    Ui(f64),
+    /// In raw units, exact
+    Raw(u64),
+}
+
+impl SwapAmount {
+    /// The amount in raw units of a mint with `decimals`
+    pub fn to_raw(self, decimals: u8) -> u64 {
+        match self {
+            SwapAmount::Ui(amount) => ui_amount_to_amount(amount, decimals),
+            SwapAmount::Raw(amount) => amount,
+        }
+    }
+}
+
+/// Swaps exactly `amount_in` of `token_in` through the AMM v4 or CP-Swap pool `pool_id`
+#[allow(clippy::too_many_arguments)]
 pub async fn get_swap_tx(
     client: Arc<RpcClient>,
     token_in: &str,
@@ -30,14 +87,63 @@ pub async fn get_swap_tx(
     pool_id: &str,
     slippage: u64,
     keypair: Arc<Keypair>,
-) -> Result<()> {
+    is_simulate: bool,
+) -> Result<TxOutcome> {
+    swap_exact_in(
+        client,
+        token_in,
+        token_out,
+        SwapAmount::Ui(amount_in),
+        pool_id,
+        slippage,
+        keypair,
+        is_simulate,
+        false,
+    )
+    .await
+}
+
+/// Swaps exactly `amount_in` of `token_in` through the AMM v4 or CP-Swap pool
+/// `pool_id`, closing the wallet's `token_in` account afterwards if
+/// `close_input`
+///
+/// Closing only succeeds when the swap spends the whole balance.
+#[allow(clippy::too_many_arguments)]
+pub async fn swap_exact_in(
+    client: Arc<RpcClient>,
+    token_in: &str,
+    token_out: &str,
+    amount_in: SwapAmount,
+    pool_id: &str,
+    slippage: u64,
+    keypair: Arc<Keypair>,
+    is_simulate: bool,
+    close_input: bool,
+) -> Result<TxOutcome> {
+    // CP-Swap的池子
+    let pool_account = client.get_account(&pool_id.parse::<Pubkey>()?).await?;
+    if pool_account.owner == program_ids().raydium_cpmm {
+        return cpmm::swap::swap_exact_in(
+            client,
+            token_in,
+            token_out,
+            amount_in,
+            pool_id,
+            slippage,
+            keypair,
+            is_simulate,
+            close_input,
+        )
+        .await;
+    }
+
     // 滑点
     let slippage_bps = slippage * 100;
     // 用户pubkey
     let owner = keypair.pubkey();
 
-    let token_in = Pubkey::from_str_const(token_in);
-    let token_out = Pubkey::from_str_const(token_out);
+    let token_in = token_in.parse::<Pubkey>()?;
+    let token_out = token_out.parse::<Pubkey>()?;
 
     // 原生程序
     let program_id = spl_token::ID;
@@ -52,66 +158,51 @@ pub async fn get_swap_tx(
     let coin_vault = pool_state.coin_vault;
     let pc_vault = pool_state.pc_vault;
 
-    // swap方向
-    let (user_input_token, swap_direction) = if token_in.eq(&coin_mint) {
-        // 使用sol购买代币
-        assert_eq!(token_out, pc_mint);
-        (coin_vault, SwapDirection::Buy)
-    } else {
-        // 使用代币购买sol
-        assert_eq!(token_out, coin_mint);
-        (pc_vault, SwapDirection::Sell)
+    // swap方向，与sol在哪一侧无关
+    let swap_direction = resolve_swap_direction(&token_in, &token_out, &coin_mint, &pc_mint)?;
+    let user_input_token = match swap_direction {
+        SwapDirection::Buy => coin_vault,
+        SwapDirection::Sell => pc_vault,
     };
 
-    // swap base in
-    let swap_base_in = token_in == native_mint;
+    // amount_in 总是精确输入数量
+    let swap_base_in = true;
 
     // 获取ata地址
     let in_ata = get_associated_token_address(&owner, &token_in);
     let out_ata = get_associated_token_address(&owner, &token_out);
 
-    let mut create_instruction = None;
+    // 输出代币不是sol时，需要其ATA账户
+    let create_instruction = if token_out != native_mint {
+        wallet::create_ata(&client, &owner, &token_out, &program_id).await
+    } else {
+        None
+    };
 
     // 计算出输入数量的准确数值
-    let (amount_specified, _) = match swap_direction {
-        SwapDirection::Buy => {
-            // 获取输出代币的ATA地址的账户信息
-            match getter::get_account_info(client.clone(), keypair.clone(), &token_out, &out_ata)
-                .await
-            {
-                Ok(_) => {}
-                Err(_) => {
-                    // 获取账户失败，创建ata账户
-                    create_instruction = Some(create_associated_token_account(
-                        &owner,
-                        &owner,
-                        &token_out,
-                        &program_id,
-                    ));
-                }
-            };
-            (
-                ui_amount_to_amount(amount_in, spl_token::native_mint::DECIMALS),
-                (amount_in, spl_token::native_mint::DECIMALS),
-            )
-        }
-        SwapDirection::Sell => {
-            // 卖出
-            let in_mint = getter::get_mint_info(client.clone(), keypair.clone(), &token_in).await?;
-            // println!("in_mint {:?}", in_mint);
-            let amount = ui_amount_to_amount(amount_in, in_mint.decimals);
-            (
-                amount,
-                (
-                    amount_to_ui_amount(amount, in_mint.decimals),
-                    in_mint.decimals,
-                ),
-            )
+    let amount_specified = match amount_in {
+        SwapAmount::Raw(amount) => amount,
+        SwapAmount::Ui(_) if token_in == native_mint => {
+            amount_in.to_raw(spl_token::native_mint::DECIMALS)
         }
+        SwapAmount::Ui(_) => amount_in.to_raw(
+            getter::get_mint_info(client.clone(), keypair.clone(), &token_in)
+                .await?
+                .decimals,
+        ),
+    };
+
+    // 用sol买入时检查风控、预算和冷却
+    let reservation = if !ExecutionMode::resolve(is_simulate).simulates() && token_in == native_mint
+    {
+        risk::check_buy(&token_out, amount_specified)?;
+        Some(global_guard().reserve(&token_out, amount_specified)?)
+    } else {
+        None
     };
 
     // amm program
-    let amm_program = Pubkey::from_str_const(AMM_PROGRAM);
+    let amm_program = program_ids().raydium_amm;
 
     // 模拟swap后的结果
     let swap_info_result = calculate_swap_info(
@@ -126,52 +217,23 @@ pub async fn get_swap_tx(
     )
     .await?;
     let other_amount_threshold = swap_info_result.other_amount_threshold;
+    let expected_other_amount = swap_info_result.expected_other_amount;
     // println!("other number {:?}", swap_info_result.other_amount_threshold);
 
     let mut instructions = vec![];
     // 可能需要wsol账户
     let mut wsol_account = None;
-    // 如果输入输出是sol，需要创建wsol账户
+    // 如果输入输出是sol，需要wsol账户
     if token_in == native_mint || token_out == native_mint {
-        // 账户计算
-        let seed = &format!("{}", Keypair::new().pubkey())[..32];
-        let wsol_pubkey = Pubkey::create_with_seed(&owner, seed, &spl_token::id())?;
-        wsol_account = Some(wsol_pubkey);
-
-        // LAMPORTS_PER_SOL / 100 // 0.01 SOL as rent
-
-        // 获取租金
-        let rent = client
-            .clone()
-            .get_minimum_balance_for_rent_exemption(Account::LEN)
-            .await?;
-        // 计算要转入wsol账户的sol数量
-        let total_amount = if token_in == native_mint {
-            rent + amount_specified
+        // 输入是sol时转入输入数量
+        let lamports = if token_in == native_mint {
+            amount_specified
         } else {
-            rent
+            0
         };
-        // println!("total_amount {:?}", total_amount);
-        // 创建wsol账户
-        // 此处为临时的
-        instructions.push(system_instruction::create_account_with_seed(
-            &owner,
-            &wsol_pubkey,
-            &owner,
-            seed,
-            total_amount,
-            Account::LEN as u64, // 165, // Token account size
-            &spl_token::id(),
-        ));
-
-        // initialize account
-        // 初始化账户
-        instructions.push(spl_token::instruction::initialize_account(
-            &spl_token::id(),
-            &wsol_pubkey,
-            &native_mint,
-            &owner,
-        )?);
+        let wsol = wsol::prepare(&client, &owner, lamports).await?;
+        instructions.extend(wsol.setup.iter().cloned());
+        wsol_account = Some(wsol);
     }
 
     // 创建指令
@@ -180,30 +242,17 @@ pub async fn get_swap_tx(
     }
 
     if amount_specified > 0 {
-        let mut close_wsol_account_instruction = None;
-        // replace native mint with tmp wsol account
+        // replace native mint with the wsol account
         let mut final_in_ata = in_ata;
         let mut final_out_ata = out_ata;
-
-        // 如果是和sol相关，之后需要关闭wsol账户
-        if let Some(wsol_account) = wsol_account {
-            match swap_direction {
-                SwapDirection::Buy => {
-                    // buy，token_in的ata是wsol的
-                    final_in_ata = wsol_account;
-                }
-                SwapDirection::Sell => {
-                    // sell，token_out的ata是wsol的
-                    final_out_ata = wsol_account;
-                }
+        if let Some(wsol) = &wsol_account {
+            if token_in == native_mint {
+                // 输入是sol，token_in的ata是wsol的
+                final_in_ata = wsol.address;
+            } else {
+                // 输出是sol，token_out的ata是wsol的
+                final_out_ata = wsol.address;
             }
-            close_wsol_account_instruction = Some(spl_token::instruction::close_account(
-                &program_id,
-                &wsol_account,
-                &owner,
-                &owner,
-                &vec![&owner],
-            )?);
         }
 
         // swap指令
@@ -219,19 +268,110 @@ pub async fn get_swap_tx(
         )?;
         println!(
             "amount_specified: {}, other_amount_threshold: {}, wsol_account: {:?}",
-            amount_specified, other_amount_threshold, wsol_account
+            amount_specified,
+            other_amount_threshold,
+            wsol_account.as_ref().map(|wsol| wsol.address)
         );
         instructions.push(build_swap_instruction);
-        // close wsol account
-        if let Some(close_wsol_account_instruction) = close_wsol_account_instruction {
-            instructions.push(close_wsol_account_instruction);
+        // close temporary wsol account
+        if let Some(wsol) = &wsol_account {
+            instructions.extend(wsol.cleanup.iter().cloned());
+        }
+        // 卖出全部余额后关闭账户，取回租金
+        if close_input && token_in != native_mint {
+            instructions.push(spl_token::instruction::close_account(
+                &program_id,
+                &in_ata,
+                &owner,
+                &owner,
+                &[&owner],
+            )?);
         }
     }
-    new_signed_and_send(client.clone(), keypair.clone(), instructions, true).await?;
-    Ok(())
+    // 模拟时对比预期输出，只有base in时阈值是最小输出
+    let expected = swap_base_in.then(|| ExpectedOutput {
+        expected_out: expected_other_amount,
+        min_out: other_amount_threshold,
+        account: match &wsol_account {
+            Some(wsol) if token_out == native_mint => wsol.output(&owner),
+            _ => OutputAccount::Token(out_ata),
+        },
+    });
+    let outcome = new_signed_and_send(
+        client.clone(),
+        keypair.clone(),
+        instructions,
+        is_simulate,
+        expected,
+        "raydium",
+        trade_side(&token_in, &token_out),
+    )
+    .await?;
+    if let Some(reservation) = reservation {
+        reservation.commit();
+    }
+
+    // 只记录和sol之间的交易
+    if token_in == native_mint {
+        record_trade(
+            "raydium",
+            Side::Buy,
+            &token_out,
+            expected_other_amount,
+            amount_specified,
+            &outcome,
+        );
+    } else if token_out == native_mint {
+        record_trade(
+            "raydium",
+            Side::Sell,
+            &token_in,
+            amount_specified,
+            expected_other_amount,
+            &outcome,
+        );
+    }
+    Ok(outcome)
 }
 
-fn amm_swap(
+/// Sells the wallet's whole raw balance of `mint` for SOL through `pool_id`
+/// and closes its token account, reclaiming the rent
+pub async fn sell_all(
+    client: Arc<RpcClient>,
+    keypair: Arc<Keypair>,
+    mint: &Pubkey,
+    pool_id: &str,
+    slippage: u64,
+    is_simulate: bool,
+) -> Result<TxOutcome> {
+    wallet::frozen::check_sellable(&client, &keypair.pubkey(), mint).await?;
+    let token_program = client.get_account(mint).await?.owner;
+    let ata = get_associated_token_address_with_program_id(&keypair.pubkey(), mint, &token_program);
+    // 原始数量，ui_amount 转换会截断
+    let balance: u64 = client
+        .get_token_account_balance(&ata)
+        .await?
+        .amount
+        .parse()?;
+    if balance == 0 {
+        return Err(anyhow!("no {} to sell", mint));
+    }
+    swap_exact_in(
+        client,
+        &mint.to_string(),
+        &spl_token::native_mint::ID.to_string(),
+        SwapAmount::Raw(balance),
+        pool_id,
+        slippage,
+        keypair,
+        is_simulate,
+        true,
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn amm_swap(
     amm_program: &Pubkey,
     result: AmmSwapInfoResult,
     user_owner: &Pubkey,
@@ -306,7 +446,7 @@ async fn test_get_swap_tx_in_raydium() -> Result<()> {
     let slippage = 1;
 
     // 模拟用户密钥对
-    let keypair = Arc::new(Keypair::from_base58_string(&env::var("PK").unwrap()));
+    let keypair = Arc::new(crate::config::bot_config().keypair().unwrap());
 
     // 调用函数
     let result = get_swap_tx(
@@ -317,9 +457,43 @@ async fn test_get_swap_tx_in_raydium() -> Result<()> {
         pool_id,
         slippage,
         keypair,
+        true,
     )
     .await
     .unwrap();
 
     Ok(())
 }
+
+#[test]
+fn test_resolve_swap_direction_either_ordering() {
+    let sol = spl_token::native_mint::ID;
+    let token = Pubkey::new_unique();
+
+    // sol 在 coin 一侧
+    assert!(matches!(
+        resolve_swap_direction(&sol, &token, &sol, &token),
+        Ok(SwapDirection::Buy)
+    ));
+    assert!(matches!(
+        resolve_swap_direction(&token, &sol, &sol, &token),
+        Ok(SwapDirection::Sell)
+    ));
+
+    // sol 在 pc 一侧
+    assert!(matches!(
+        resolve_swap_direction(&sol, &token, &token, &sol),
+        Ok(SwapDirection::Sell)
+    ));
+    assert!(matches!(
+        resolve_swap_direction(&token, &sol, &token, &sol),
+        Ok(SwapDirection::Buy)
+    ));
+
+    // 不属于该池子
+    let err = resolve_swap_direction(&sol, &Pubkey::new_unique(), &token, &sol).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<RaydiumError>(),
+        Some(RaydiumError::PoolMintMismatch { .. })
+    ));
+}
@@ -28,6 +28,31 @@ pub struct SwapInstructionBaseOut {
     pub amount_out: u64,
 }
 
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DepositInstruction {
+    /// Most COIN token to deposit
+    pub max_coin_amount: u64,
+    /// Most PC token to deposit
+    pub max_pc_amount: u64,
+    /// Side the deposit is based on, 0 for COIN and 1 for PC, the other is
+    /// taken at the pool ratio
+    pub base_side: u64,
+    /// Least of the other side to deposit, prevents excessive slippage
+    pub other_amount_min: Option<u64>,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WithdrawInstruction {
+    /// LP token to burn
+    pub amount: u64,
+    /// Least COIN token to receive
+    pub min_coin_amount: Option<u64>,
+    /// Least PC token to receive
+    pub min_pc_amount: Option<u64>,
+}
+
 /// Instructions supported by the AmmInfo program.
 #[repr(C)]
 #[derive(Clone, Debug, PartialEq)]
@@ -75,6 +100,48 @@ pub enum AmmInstruction {
     ///   16. `[writable]` User destination token Account.
     ///   17. `[signer]` User wallet Account
     SwapBaseOut(SwapInstructionBaseOut),
+
+    /// Deposit COIN and PC for LP token at the pool ratio
+    ///
+    ///   0. `[]` Spl Token program id
+    ///   1. `[writable]` AMM Account
+    ///   2. `[]` $authority derived from `create_program_address(&[AUTHORITY_AMM, &[nonce]])`.
+    ///   3. `[]` AMM open orders Account
+    ///   4. `[writable]` AMM target orders Account
+    ///   5. `[writable]` AMM lp mint Account
+    ///   6. `[writable]` AMM coin vault Account
+    ///   7. `[writable]` AMM pc vault Account
+    ///   8. `[]` Market Account. Market program is the owner.
+    ///   9. `[writable]` User coin token Account
+    ///   10. `[writable]` User pc token Account
+    ///   11. `[writable]` User lp token Account
+    ///   12. `[signer]` User wallet Account
+    ///   13. `[]` Market event queue Account
+    Deposit(DepositInstruction),
+
+    /// Burn LP token for COIN and PC at the pool ratio
+    ///
+    ///   0. `[]` Spl Token program id
+    ///   1. `[writable]` AMM Account
+    ///   2. `[]` $authority derived from `create_program_address(&[AUTHORITY_AMM, &[nonce]])`.
+    ///   3. `[writable]` AMM open orders Account
+    ///   4. `[writable]` AMM target orders Account
+    ///   5. `[writable]` AMM lp mint Account
+    ///   6. `[writable]` AMM coin vault Account
+    ///   7. `[writable]` AMM pc vault Account
+    ///   8. `[]` Market program id
+    ///   9. `[writable]` Market Account. Market program is the owner.
+    ///   10. `[writable]` Market coin vault Account
+    ///   11. `[writable]` Market pc vault Account
+    ///   12. '[]` Market vault signer Account
+    ///   13. `[writable]` User lp token Account
+    ///   14. `[writable]` User coin token Account
+    ///   15. `[writable]` User pc token Account
+    ///   16. `[signer]` User wallet Account
+    ///   17. `[writable]` Market event queue Account
+    ///   18. `[writable]` Market bids Account
+    ///   19. `[writable]` Market asks Account
+    Withdraw(WithdrawInstruction),
 }
 
 impl AmmInstruction {
@@ -102,6 +169,36 @@ impl AmmInstruction {
                 })
             }
 
+            3 => {
+                let (max_coin_amount, rest) = Self::unpack_u64(rest)?;
+                let (max_pc_amount, rest) = Self::unpack_u64(rest)?;
+                let (base_side, rest) = Self::unpack_u64(rest)?;
+                let other_amount_min = Self::unpack_u64(rest).ok().map(|(amount, _)| amount);
+                Self::Deposit(DepositInstruction {
+                    max_coin_amount,
+                    max_pc_amount,
+                    base_side,
+                    other_amount_min,
+                })
+            }
+
+            4 => {
+                let (amount, rest) = Self::unpack_u64(rest)?;
+                // 最小输出是可选的，要么都有要么都没有
+                let (min_coin_amount, min_pc_amount) = match Self::unpack_u64(rest) {
+                    Ok((min_coin_amount, rest)) => {
+                        let (min_pc_amount, _rest) = Self::unpack_u64(rest)?;
+                        (Some(min_coin_amount), Some(min_pc_amount))
+                    }
+                    Err(_) => (None, None),
+                };
+                Self::Withdraw(WithdrawInstruction {
+                    amount,
+                    min_coin_amount,
+                    min_pc_amount,
+                })
+            }
+
             _ => return Err(ProgramError::InvalidInstructionData.into()),
         })
     }
@@ -169,7 +266,36 @@ impl AmmInstruction {
                 buf.extend_from_slice(&max_amount_in.to_le_bytes());
                 buf.extend_from_slice(&amount_out.to_le_bytes());
             }
-            _ => todo!(),
+
+            Self::Deposit(DepositInstruction {
+                max_coin_amount,
+                max_pc_amount,
+                base_side,
+                other_amount_min,
+            }) => {
+                buf.push(3);
+                buf.extend_from_slice(&max_coin_amount.to_le_bytes());
+                buf.extend_from_slice(&max_pc_amount.to_le_bytes());
+                buf.extend_from_slice(&base_side.to_le_bytes());
+                if let Some(other_amount_min) = other_amount_min {
+                    buf.extend_from_slice(&other_amount_min.to_le_bytes());
+                }
+            }
+
+            Self::Withdraw(WithdrawInstruction {
+                amount,
+                min_coin_amount,
+                min_pc_amount,
+            }) => {
+                buf.push(4);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                if let (Some(min_coin_amount), Some(min_pc_amount)) =
+                    (min_coin_amount, min_pc_amount)
+                {
+                    buf.extend_from_slice(&min_coin_amount.to_le_bytes());
+                    buf.extend_from_slice(&min_pc_amount.to_le_bytes());
+                }
+            }
         }
         Ok(buf)
     }
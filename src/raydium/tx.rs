@@ -1,78 +1,329 @@
-use std::{env, sync::Arc, time::Instant};
+use std::{sync::Arc, time::Instant};
 
-use anyhow::{anyhow, Result};
-use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcSendTransactionConfig};
+use anyhow::Result;
+use solana_client::{
+    client_error::ClientError,
+    nonblocking::rpc_client::RpcClient,
+    rpc_client::SerializableTransaction,
+    rpc_config::{RpcSendTransactionConfig, RpcSimulateTransactionConfig},
+};
 use solana_sdk::{
     commitment_config::CommitmentConfig,
+    compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
     instruction::Instruction,
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
     signature::{Keypair, Signature},
     signer::Signer,
     transaction::Transaction,
 };
-use std::str::FromStr;
-use tracing::info;
-
-fn get_unit_price() -> u64 {
-    env::var("UNIT_PRICE")
-        .ok()
-        .and_then(|v| u64::from_str(&v).ok())
-        .unwrap_or(20000)
+use spl_associated_token_account::ID as ASSOCIATED_TOKEN_PROGRAM;
+use tracing::{info, warn};
+
+use crate::{
+    config::{bot_config, MAX_UNIT_LIMIT},
+    fees::priority,
+    metrics,
+    raydium::error::RaydiumError,
+    rpc::multi::{self, global_multi_client},
+    timeline,
+    tx::{
+        blockhash::recent_blockhash,
+        mode::{ensure_live, ExecutionMode},
+        nonce,
+        sender::{self, send_jito_bundle, Sender},
+        simulate::{landed_output, simulate, ExpectedOutput, TxOutcome},
+    },
+};
+
+/// Signs `instructions` into a legacy transaction that fits in one packet
+///
+/// Fails with `TransactionTooLarge` instead of the node's opaque
+/// serialization error when it doesn't.
+pub fn build_transaction(
+    keypair: &Keypair,
+    instructions: &[Instruction],
+    recent_blockhash: Hash,
+) -> Result<Transaction> {
+    let txn = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&keypair.pubkey()),
+        &[keypair],
+        recent_blockhash,
+    );
+    let size = bincode::serialized_size(&txn)? as usize;
+    if size > PACKET_DATA_SIZE {
+        return Err(RaydiumError::TransactionTooLarge {
+            size,
+            limit: PACKET_DATA_SIZE,
+        }
+        .into());
+    }
+    Ok(txn)
+}
+
+/// Moves the ATA creations out of `instructions`, returning them
+fn split_ata_creation(instructions: &mut Vec<Instruction>) -> Vec<Instruction> {
+    let (ata, rest) = instructions
+        .drain(..)
+        .partition(|ix| ix.program_id == ASSOCIATED_TOKEN_PROGRAM);
+    *instructions = rest;
+    ata
 }
 
-fn get_unit_limit() -> u32 {
-    env::var("UNIT_LIMIT")
-        .ok()
-        .and_then(|v| u32::from_str(&v).ok())
-        .unwrap_or(200_000)
+/// Whether `e` is a [`RaydiumError::TransactionTooLarge`] from
+/// [`build_transaction`]
+fn is_too_large(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<RaydiumError>(),
+        Some(RaydiumError::TransactionTooLarge { .. })
+    )
 }
 
+/// Signs `instructions` against the wallet's nonce account when
+/// `use_nonce`, else against `recent_blockhash`
+async fn sign(
+    client: &RpcClient,
+    keypair: &Keypair,
+    instructions: &[Instruction],
+    recent_blockhash: Hash,
+    use_nonce: bool,
+) -> Result<Transaction> {
+    if use_nonce {
+        return nonce::sign_with_nonce(client, keypair, nonce::DEFAULT_SEED, instructions).await;
+    }
+    build_transaction(keypair, instructions, recent_blockhash)
+}
+
+/// Compute unit limit of `instructions` paid by `payer`
+///
+/// With `unit_limit_margin` set, the instructions are simulated first and the
+/// limit is the units consumed times the margin, which costs one more RPC
+/// call. Falls back to `unit_limit` without a margin or if the simulation
+/// fails.
+async fn estimate_unit_limit(
+    client: &RpcClient,
+    payer: &Pubkey,
+    instructions: &[Instruction],
+) -> u32 {
+    let config = bot_config();
+    if config.unit_limit_margin.is_none() {
+        return config.unit_limit;
+    }
+    let mut simulated = vec![ComputeBudgetInstruction::set_compute_unit_limit(
+        MAX_UNIT_LIMIT,
+    )];
+    simulated.extend_from_slice(instructions);
+    let txn = Transaction::new_with_payer(&simulated, Some(payer));
+    // 不签名，由节点替换区块哈希
+    let sim_config = RpcSimulateTransactionConfig {
+        sig_verify: false,
+        replace_recent_blockhash: true,
+        commitment: Some(client.commitment()),
+        ..RpcSimulateTransactionConfig::default()
+    };
+    match client
+        .simulate_transaction_with_config(&txn, sim_config)
+        .await
+    {
+        Ok(response) => match (response.value.err, response.value.units_consumed) {
+            (None, Some(units)) => {
+                let limit = config.estimated_unit_limit(units);
+                info!(
+                    "simulation consumed {} compute units, limit {}",
+                    units, limit
+                );
+                limit
+            }
+            (Some(err), _) => {
+                warn!("compute unit simulation failed, using unit_limit: {}", err);
+                config.unit_limit
+            }
+            (None, None) => config.unit_limit,
+        },
+        Err(e) => {
+            warn!("compute unit simulation failed, using unit_limit {:?}", e);
+            config.unit_limit
+        }
+    }
+}
+
+/// Sends `instructions` with the compute unit limit from
+/// [`estimate_unit_limit`] and the priority fee from [`priority::unit_price`]
+/// through the task's [`sender::current`], with the tip it requires
+///
+/// If the transaction is over the packet limit and creates token accounts,
+/// the account creation is sent first as its own transaction. Simulations
+/// can't be split, since the swap depends on the accounts existing.
+///
+/// With durable nonces enabled, the transaction is signed against the
+/// wallet's nonce account, see [`nonce`]. Outside of live mode nothing is
+/// sent, see [`crate::tx::mode`]. Live sends are counted in the trade
+/// metrics under `venue` and `side`, see [`trade_side`].
+#[allow(clippy::too_many_arguments)]
 pub async fn new_signed_and_send(
     client: Arc<RpcClient>,
     keypair: Arc<Keypair>,
     mut instructions: Vec<Instruction>,
     is_simulate: bool,
-) -> Result<Vec<String>> {
-    let unit_limit = get_unit_limit();
-    let unit_price = get_unit_price();
-    // If not using Jito, manually set the compute unit price and limit
-    let modify_compute_units =
-        solana_sdk::compute_budget::ComputeBudgetInstruction::set_compute_unit_limit(unit_limit);
-    let add_priority_fee =
-        solana_sdk::compute_budget::ComputeBudgetInstruction::set_compute_unit_price(unit_price);
+    expected: Option<ExpectedOutput>,
+    venue: &str,
+    side: &str,
+) -> Result<TxOutcome> {
+    timeline::mark_built();
+    let mode = ExecutionMode::resolve(is_simulate);
+    let sender = sender::current();
+    let unit_limit = estimate_unit_limit(&client, &keypair.pubkey(), &instructions).await;
+    let modify_compute_units = ComputeBudgetInstruction::set_compute_unit_limit(unit_limit);
     instructions.insert(0, modify_compute_units);
-    instructions.insert(1, add_priority_fee);
+    if sender.pays_priority_fee() {
+        let unit_price = priority::unit_price(&client, &instructions).await;
+        let add_priority_fee = ComputeBudgetInstruction::set_compute_unit_price(unit_price);
+        instructions.insert(1, add_priority_fee);
+    }
+    instructions.extend(sender.tip(&keypair.pubkey()));
     // send init tx
-    let recent_blockhash = client.get_latest_blockhash().await?;
-    let txn = Transaction::new_signed_with_payer(
+    let recent_blockhash = recent_blockhash(&client).await?;
+    // 模拟不消耗nonce
+    let use_nonce = nonce::enabled() && mode == ExecutionMode::Live;
+    let _nonce_guard = if use_nonce {
+        Some(nonce::lock(&keypair.pubkey()).await)
+    } else {
+        None
+    };
+    let mut txs = vec![];
+    let start_time = Instant::now();
+    let txn = match sign(
+        &client,
+        &keypair,
         &instructions,
-        Some(&keypair.pubkey()),
-        &vec![&*keypair],
         recent_blockhash,
-    );
+        use_nonce,
+    )
+    .await
+    {
+        Ok(txn) => txn,
+        Err(e) if mode != ExecutionMode::Live || !is_too_large(&e) => return Err(e),
+        Err(e) => {
+            let ata = split_ata_creation(&mut instructions);
+            if ata.is_empty() {
+                return Err(e);
+            }
+            // 超过大小限制，先单独创建ATA
+            let ata_txn = build_transaction(&keypair, &ata, recent_blockhash)?;
+            let txn = sign(
+                &client,
+                &keypair,
+                &instructions,
+                recent_blockhash,
+                use_nonce,
+            )
+            .await?;
+            let sig = send_txn(&client, &ata_txn, true).await?;
+            info!("ata signature: {:?}", sig);
+            txs.push(sig);
+            txn
+        }
+    };
+
+    match mode {
+        ExecutionMode::Simulate => {
+            let summary = simulate(&client, &txn, expected).await?;
+            return Ok(TxOutcome::Simulated(summary));
+        }
+        ExecutionMode::Paper => return Ok(paper(&txn)),
+        ExecutionMode::Live => {}
+    }
 
-    if is_simulate {
-        let simulate_result = client.simulate_transaction(&txn).await?;
-        if let Some(logs) = simulate_result.value.logs {
-            for log in logs {
-                println!("{}", log);
+    metrics::record_trade_attempt(venue, side);
+    let sig = sender.submit(&client, &txn).await?;
+    metrics::record_trade_success(venue, side);
+    info!("{} signature: {:?}", sender, sig);
+    txs.push(sig);
+    if let Some(expected) = expected {
+        // 落地后的实际输出，不阻塞返回
+        tokio::spawn(async move {
+            match landed_output(&client, &sig, &expected.account).await {
+                Ok(Some(actual)) => {
+                    metrics::record_slippage("landed", expected.expected_out, actual)
+                }
+                Ok(None) => {}
+                Err(e) => warn!("failed to read the output of {} {:?}", sig, e),
             }
+        });
+    }
+
+    info!("tx elapsed: {:?}", start_time.elapsed());
+
+    Ok(TxOutcome::Sent(txs))
+}
+
+/// Sends `instructions` as a Jito bundle, tipping
+/// [`crate::fees::jito_tips::jito_tip`], without waiting for it to land
+///
+/// The block engine is configured in [`sender`]. The tip replaces the
+/// priority fee, so only the compute unit limit is set.
+#[allow(clippy::too_many_arguments)]
+pub async fn send_bundle(
+    client: Arc<RpcClient>,
+    keypair: Arc<Keypair>,
+    mut instructions: Vec<Instruction>,
+    is_simulate: bool,
+    expected: Option<ExpectedOutput>,
+    venue: &str,
+    side: &str,
+) -> Result<TxOutcome> {
+    timeline::mark_built();
+    let mode = ExecutionMode::resolve(is_simulate);
+    let unit_limit = estimate_unit_limit(&client, &keypair.pubkey(), &instructions).await;
+    let modify_compute_units = ComputeBudgetInstruction::set_compute_unit_limit(unit_limit);
+    instructions.insert(0, modify_compute_units);
+    // 小费放在最后，交易失败时不付小费
+    instructions.extend(Sender::Jito.tip(&keypair.pubkey()));
+    let recent_blockhash = recent_blockhash(&client).await?;
+    let txn = build_transaction(&keypair, &instructions, recent_blockhash)?;
+
+    match mode {
+        ExecutionMode::Simulate => {
+            let summary = simulate(&client, &txn, expected).await?;
+            return Ok(TxOutcome::Simulated(summary));
         }
-        return match simulate_result.value.err {
-            Some(err) => Err(anyhow!("{}", err)),
-            None => Ok(vec![]),
-        };
+        ExecutionMode::Paper => return Ok(paper(&txn)),
+        ExecutionMode::Live => {}
     }
 
     let start_time = Instant::now();
-    let mut txs = vec![];
+    metrics::record_trade_attempt(venue, side);
+    let bundle_id = send_jito_bundle(&txn).await?;
+    timeline::mark_sent();
+    metrics::record_trade_success(venue, side);
+    info!(
+        "bundle id: {}, signature: {:?}",
+        bundle_id, txn.signatures[0]
+    );
+    info!("bundle elapsed: {:?}", start_time.elapsed());
 
-    let sig = send_txn(&client, &txn, true).await?;
-    info!("signature: {:?}", sig);
-    txs.push(sig.to_string());
+    Ok(TxOutcome::Sent(vec![txn.signatures[0]]))
+}
 
-    info!("tx elapsed: {:?}", start_time.elapsed());
+/// Trade metrics side of a swap from `token_in` to `token_out`, `buy` or
+/// `sell` against SOL and `swap` between two tokens
+pub fn trade_side(token_in: &Pubkey, token_out: &Pubkey) -> &'static str {
+    let native_mint = spl_token::native_mint::ID;
+    if *token_in == native_mint {
+        "buy"
+    } else if *token_out == native_mint {
+        "sell"
+    } else {
+        "swap"
+    }
+}
 
-    Ok(txs)
+/// Outcome of `txn` in paper mode, which is never sent
+pub(crate) fn paper(txn: &Transaction) -> TxOutcome {
+    info!("paper trade, not sending {:?}", txn.signatures[0]);
+    TxOutcome::Paper(txn.signatures[0])
 }
 
 pub async fn send_txn(
@@ -80,14 +331,88 @@ pub async fn send_txn(
     txn: &Transaction,
     skip_preflight: bool,
 ) -> Result<Signature> {
-    Ok(client
-        .send_and_confirm_transaction_with_spinner_and_config(
-            txn,
-            CommitmentConfig::confirmed(),
-            RpcSendTransactionConfig {
-                skip_preflight,
-                ..RpcSendTransactionConfig::default()
-            },
-        )
-        .await?)
+    ensure_live()?;
+    let config = RpcSendTransactionConfig {
+        skip_preflight,
+        ..RpcSendTransactionConfig::default()
+    };
+    let result = async {
+        // 多节点时同时发送，再从读节点确认
+        let sig = multi::send_transaction(client, txn, config).await?;
+        timeline::mark_sent();
+        if global_multi_client().is_some() {
+            client
+                .poll_for_signature_with_commitment(&sig, CommitmentConfig::confirmed())
+                .await?;
+        } else {
+            // 同 send_and_confirm_transaction_with_spinner
+            let blockhash = if txn.uses_durable_nonce() {
+                client
+                    .get_latest_blockhash_with_commitment(CommitmentConfig::processed())
+                    .await?
+                    .0
+            } else {
+                txn.message.recent_blockhash
+            };
+            client
+                .confirm_transaction_with_spinner(&sig, &blockhash, CommitmentConfig::confirmed())
+                .await?;
+        }
+        Ok::<_, ClientError>(sig)
+    }
+    .await;
+    metrics::record_confirmation(if result.is_ok() { "landed" } else { "failed" });
+    Ok(result?)
+}
+
+#[test]
+fn test_build_transaction_rejects_oversized() {
+    use solana_sdk::instruction::AccountMeta;
+
+    let keypair = Keypair::new();
+    let program = Pubkey::new_unique();
+    let accounts = (0..40)
+        .map(|_| AccountMeta::new(Pubkey::new_unique(), false))
+        .collect();
+    let oversized = vec![Instruction::new_with_bytes(program, &[0; 64], accounts)];
+
+    let err = build_transaction(&keypair, &oversized, Hash::default()).unwrap_err();
+    assert!(is_too_large(&err));
+    assert!(!is_too_large(&anyhow::anyhow!("nonce account not found")));
+    match err.downcast_ref::<RaydiumError>() {
+        Some(RaydiumError::TransactionTooLarge { size, limit }) => {
+            assert!(size > limit);
+            assert_eq!(*limit, PACKET_DATA_SIZE);
+        }
+        other => panic!("unexpected error {:?}", other),
+    }
+
+    let small = vec![Instruction::new_with_bytes(program, &[0; 64], vec![])];
+    assert!(build_transaction(&keypair, &small, Hash::default()).is_ok());
+}
+
+#[test]
+fn test_split_ata_creation() {
+    use spl_associated_token_account::instruction::create_associated_token_account;
+
+    let payer = Pubkey::new_unique();
+    let swap = Instruction::new_with_bytes(Pubkey::new_unique(), &[9], vec![]);
+    let mut instructions = vec![
+        create_associated_token_account(&payer, &payer, &Pubkey::new_unique(), &spl_token::ID),
+        swap.clone(),
+    ];
+
+    let ata = split_ata_creation(&mut instructions);
+    assert_eq!(ata.len(), 1);
+    assert_eq!(instructions, vec![swap]);
+}
+
+#[test]
+fn test_trade_side() {
+    let native_mint = spl_token::native_mint::ID;
+    let mint = Pubkey::new_unique();
+
+    assert_eq!(trade_side(&native_mint, &mint), "buy");
+    assert_eq!(trade_side(&mint, &native_mint), "sell");
+    assert_eq!(trade_side(&mint, &Pubkey::new_unique()), "swap");
 }
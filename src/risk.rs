@@ -0,0 +1,329 @@
+//! Risk limits on the strategies' buys.
+//!
+//! Every buy path checks [`check_buy`] before sending, with the limits of the
+//! strategy making the trade (see [`timeline::strategy`]) against the
+//! process-wide [`portfolio`]:
+//!
+//! - `max_sol_per_trade`: SOL spent by a single buy
+//! - `max_open_positions`: open positions of the strategy, buys adding to one
+//!   of them are still allowed
+//! - `max_daily_loss_sol`: loss the strategy realized since midnight UTC,
+//!   after which it stops buying for the day
+//! - `max_mint_exposure_sol`: cost basis of a mint's position after the buy,
+//!   whichever strategy holds it
+//!
+//! The limits are read from the TOML file at `RISK_CONFIG` (default
+//! `risk.toml`, see `risk.example.toml`): a `[default]` table for every
+//! strategy, and `[strategies.<name>]` tables overriding it per strategy.
+//! Manual trades only get the limits of `[strategies.manual]`. The file is
+//! reloaded when it changes, checked at most every [`RELOAD_INTERVAL`]; an
+//! invalid edit is logged and the previous limits kept. Without the file
+//! nothing is limited. Simulations are not checked.
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use solana_sdk::{native_token::sol_to_lamports, pubkey::Pubkey};
+use thiserror::Error;
+use tracing::{error, info, warn};
+
+use crate::{
+    portfolio::{portfolio, Portfolio},
+    timeline,
+};
+
+const DEFAULT_RISK_CONFIG_PATH: &str = "risk.toml";
+
+/// How often the file is checked for changes
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+const SECS_PER_DAY: u64 = 86_400;
+
+static RISK_CONFIG: LazyLock<Mutex<WatchedConfig>> = LazyLock::new(|| {
+    dotenv::dotenv().ok();
+    let path = env::var("RISK_CONFIG")
+        .map(PathBuf::from)
+        .unwrap_or(PathBuf::from(DEFAULT_RISK_CONFIG_PATH));
+    Mutex::new(WatchedConfig::new(path))
+});
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RiskError {
+    #[error("{strategy} buy of {lamports} lamports is over its limit of {max}")]
+    TradeTooLarge {
+        strategy: String,
+        lamports: u64,
+        max: u64,
+    },
+    #[error("{strategy} already has {open} open positions")]
+    TooManyPositions { strategy: String, open: usize },
+    #[error("{strategy} lost {loss} lamports today, its limit is {max}")]
+    DailyLossReached {
+        strategy: String,
+        loss: u64,
+        max: u64,
+    },
+    #[error("{mint} exposure would be {exposure} lamports, over the limit of {max}")]
+    MintExposure {
+        mint: Pubkey,
+        exposure: u64,
+        max: u64,
+    },
+}
+
+/// Limits of a strategy, unset ones don't apply
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RiskLimits {
+    pub max_sol_per_trade: Option<f64>,
+    pub max_open_positions: Option<usize>,
+    pub max_daily_loss_sol: Option<f64>,
+    pub max_mint_exposure_sol: Option<f64>,
+}
+
+impl RiskLimits {
+    /// These limits, falling back to `default` for the unset ones
+    fn or(&self, default: &RiskLimits) -> RiskLimits {
+        RiskLimits {
+            max_sol_per_trade: self.max_sol_per_trade.or(default.max_sol_per_trade),
+            max_open_positions: self.max_open_positions.or(default.max_open_positions),
+            max_daily_loss_sol: self.max_daily_loss_sol.or(default.max_daily_loss_sol),
+            max_mint_exposure_sol: self.max_mint_exposure_sol.or(default.max_mint_exposure_sol),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RiskConfig {
+    /// Limits of every strategy but manual trades
+    pub default: RiskLimits,
+    /// Overrides per strategy name
+    pub strategies: HashMap<String, RiskLimits>,
+}
+
+impl RiskConfig {
+    /// Loads `path`, no limits if it doesn't exist
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text)
+                .map_err(|e| anyhow!("invalid risk config {}: {}", path.display(), e)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(anyhow!("failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Limits applying to `strategy`
+    pub fn limits(&self, strategy: &str) -> RiskLimits {
+        let own = self.strategies.get(strategy).cloned().unwrap_or_default();
+        if strategy == timeline::MANUAL {
+            own
+        } else {
+            own.or(&self.default)
+        }
+    }
+
+    /// Checks a buy of `mint` for `lamports` by `strategy` at the unix
+    /// timestamp `now`
+    pub fn check(
+        &self,
+        strategy: &str,
+        mint: &Pubkey,
+        lamports: u64,
+        portfolio: &Portfolio,
+        now: u64,
+    ) -> Result<(), RiskError> {
+        let limits = self.limits(strategy);
+
+        if let Some(max) = limits.max_sol_per_trade.map(sol_to_lamports) {
+            if lamports > max {
+                return Err(RiskError::TradeTooLarge {
+                    strategy: strategy.to_string(),
+                    lamports,
+                    max,
+                });
+            }
+        }
+
+        let position = portfolio.position(&mint.to_string());
+        if let Some(max) = limits.max_open_positions {
+            let open = portfolio
+                .open_positions()
+                .iter()
+                .filter(|position| position.strategy == strategy)
+                .count();
+            // 加仓不算新仓位
+            let adds = position.as_ref().is_some_and(|p| p.is_open());
+            if !adds && open >= max {
+                return Err(RiskError::TooManyPositions {
+                    strategy: strategy.to_string(),
+                    open,
+                });
+            }
+        }
+
+        if let Some(max) = limits.max_daily_loss_sol.map(sol_to_lamports) {
+            let today = now - now % SECS_PER_DAY;
+            let pnl = portfolio.realized_pnl_since(strategy, today);
+            let loss = pnl.min(0).unsigned_abs();
+            if loss >= max {
+                return Err(RiskError::DailyLossReached {
+                    strategy: strategy.to_string(),
+                    loss,
+                    max,
+                });
+            }
+        }
+
+        if let Some(max) = limits.max_mint_exposure_sol.map(sol_to_lamports) {
+            let held = position.map_or(0, |p| p.cost_basis);
+            let exposure = held.saturating_add(lamports);
+            if exposure > max {
+                return Err(RiskError::MintExposure {
+                    mint: *mint,
+                    exposure,
+                    max,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The config file and the limits last loaded from it
+struct WatchedConfig {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    checked_at: Option<Instant>,
+    config: Arc<RiskConfig>,
+}
+
+impl WatchedConfig {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            modified: None,
+            checked_at: None,
+            config: Arc::new(RiskConfig::default()),
+        }
+    }
+
+    /// The limits, reloaded first if the file changed
+    fn current(&mut self) -> Arc<RiskConfig> {
+        if self
+            .checked_at
+            .is_some_and(|at| at.elapsed() < RELOAD_INTERVAL)
+        {
+            return self.config.clone();
+        }
+        let first = self.checked_at.is_none();
+        self.checked_at = Some(Instant::now());
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        if first || modified != self.modified {
+            // 出错时也记下修改时间，不重复报错
+            self.modified = modified;
+            match RiskConfig::load(&self.path) {
+                Ok(config) => {
+                    info!("risk limits loaded from {}", self.path.display());
+                    self.config = Arc::new(config);
+                }
+                Err(e) => error!("keeping the previous risk limits {:?}", e),
+            }
+        }
+        self.config.clone()
+    }
+}
+
+/// The current limits of every strategy
+pub fn risk_config() -> Arc<RiskConfig> {
+    RISK_CONFIG.lock().unwrap().current()
+}
+
+/// Checks a buy of `mint` for `lamports` by the current task's strategy
+pub fn check_buy(mint: &Pubkey, lamports: u64) -> Result<(), RiskError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    risk_config()
+        .check(timeline::strategy(), mint, lamports, portfolio(), now)
+        .inspect_err(|e| warn!("buy rejected by risk limits: {}", e))
+}
+
+#[test]
+fn test_risk_limits() {
+    use crate::portfolio::{Fill, Side};
+
+    let config: RiskConfig = toml::from_str(
+        r#"
+        [default]
+        max_sol_per_trade = 1.0
+        max_open_positions = 1
+        max_daily_loss_sol = 0.5
+
+        [strategies.sniper]
+        max_sol_per_trade = 0.1
+        max_mint_exposure_sol = 0.15
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.limits("sniper").max_open_positions, Some(1));
+    assert_eq!(config.limits(timeline::MANUAL), RiskLimits::default());
+
+    let portfolio = Portfolio::in_memory();
+    let (held, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let day = 10 * SECS_PER_DAY;
+    let fill = |mint: &Pubkey, side, sol_amount, timestamp| Fill {
+        mint: mint.to_string(),
+        side,
+        venue: "pumpfun".to_string(),
+        strategy: "sniper".to_string(),
+        token_amount: 1000,
+        sol_amount,
+        fee: 0,
+        signature: String::new(),
+        timestamp,
+    };
+    portfolio
+        .record(fill(&held, Side::Buy, 100_000_000, day))
+        .unwrap();
+
+    let check = |mint, lamports, now| config.check("sniper", mint, lamports, &portfolio, now);
+    assert!(matches!(
+        check(&other, 200_000_000, day),
+        Err(RiskError::TradeTooLarge { .. })
+    ));
+    assert!(matches!(
+        check(&other, 1, day),
+        Err(RiskError::TooManyPositions { open: 1, .. })
+    ));
+    assert!(matches!(
+        check(&held, 60_000_000, day),
+        Err(RiskError::MintExposure { .. })
+    ));
+    check(&held, 50_000_000, day).unwrap();
+
+    // 亏损计入当天，次日重置
+    portfolio
+        .record(fill(&other, Side::Buy, 800_000_000, day))
+        .unwrap();
+    portfolio
+        .record(fill(&other, Side::Sell, 100_000_000, day + 60))
+        .unwrap();
+    assert!(matches!(
+        check(&held, 1, day + 120),
+        Err(RiskError::DailyLossReached {
+            loss: 700_000_000,
+            ..
+        })
+    ));
+    check(&held, 1, day + SECS_PER_DAY).unwrap();
+}
@@ -0,0 +1,410 @@
+//! Two-hop swaps through an intermediate token.
+//!
+//! [`swap_two_hop`] swaps exactly `amount_in` of the input mint into the
+//! intermediate one and that into the output mint, e.g. TOKEN -> WSOL -> USDC,
+//! across two Raydium AMM v4 pools in one transaction, so a position can exit
+//! into a stable without a second send.
+//!
+//! The second hop spends the first hop's minimum out, the most it is sure to
+//! receive, and its own minimum out is quoted on that amount. Whatever the
+//! first hop returns above its minimum stays in the wallet: as SOL when WSOL
+//! is the intermediate and the WSOL account is temporary, as the
+//! intermediate token otherwise.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+};
+use tracing::info;
+
+use crate::{
+    config::program_ids,
+    portfolio::{record_trade, Side},
+    quote::Quote,
+    raydium::{
+        getter::{self, find_pool_by_mints, get_pool_state},
+        market::MarketKeys,
+        math::{load_amm_keys, load_reserves, quote_reserves, swap_accounts},
+        structure::{AmmInfo, AmmKeys},
+        swap::{amm_swap, resolve_swap_direction, SwapAmount},
+        tx::{new_signed_and_send, trade_side},
+    },
+    risk,
+    tx::{
+        budget::global_guard,
+        mode::ExecutionMode,
+        simulate::{ExpectedOutput, OutputAccount, TxOutcome},
+    },
+    wallet::wsol,
+};
+
+/// One swap of a route
+#[derive(Debug, Clone)]
+pub struct Hop {
+    pub pool: Pubkey,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub quote: Quote,
+}
+
+/// Quoted swap through two pools
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub hops: [Hop; 2],
+}
+
+impl Route {
+    pub fn amount_in(&self) -> u64 {
+        self.hops[0].quote.amount_in
+    }
+
+    /// Output of the second hop at the current reserves
+    pub fn expected_out(&self) -> u64 {
+        self.hops[1].quote.expected_out
+    }
+
+    /// Smallest output the transaction accepts
+    pub fn min_out(&self) -> u64 {
+        self.hops[1].quote.min_out
+    }
+}
+
+/// A pool with its keys and reserves, read once per route
+struct RoutePool {
+    keys: AmmKeys,
+    state: AmmInfo,
+    pc_reserve: u64,
+    coin_reserve: u64,
+    market: Option<MarketKeys>,
+}
+
+impl RoutePool {
+    async fn load(client: Arc<RpcClient>, pool: &Pubkey) -> Result<Self> {
+        let (pool, state) = get_pool_state(client.clone(), &pool.to_string()).await?;
+        let keys = load_amm_keys(&state, &program_ids().raydium_amm, &pool)?;
+        let reserves = load_reserves(client, &state, &keys).await?;
+        Ok(Self {
+            keys,
+            state,
+            pc_reserve: reserves.pc_amount,
+            coin_reserve: reserves.coin_amount,
+            market: reserves.market,
+        })
+    }
+
+    /// Quotes swapping exactly `amount_in` of `input_mint` for `output_mint`
+    fn quote(
+        &self,
+        input_mint: &Pubkey,
+        output_mint: &Pubkey,
+        amount_in: u64,
+        slippage_bps: u64,
+    ) -> Result<Hop> {
+        let direction = resolve_swap_direction(
+            input_mint,
+            output_mint,
+            &self.keys.amm_coin_mint,
+            &self.keys.amm_pc_mint,
+        )?;
+        let quote = quote_reserves(
+            self.pc_reserve,
+            self.coin_reserve,
+            &self.state.fees,
+            direction,
+            amount_in,
+            slippage_bps,
+        )?;
+        Ok(Hop {
+            pool: self.keys.amm_pool,
+            input_mint: *input_mint,
+            output_mint: *output_mint,
+            quote,
+        })
+    }
+}
+
+/// Chains `first` into the hop `second` quotes, which spends the first hop's
+/// minimum out
+fn compose(first: Hop, second: impl FnOnce(u64) -> Result<Hop>) -> Result<Route> {
+    if first.quote.min_out == 0 {
+        return Err(anyhow!(
+            "first hop through {} returns nothing for {}",
+            first.pool,
+            first.quote.amount_in
+        ));
+    }
+    let second = second(first.quote.min_out)?;
+    if second.input_mint != first.output_mint {
+        return Err(anyhow!(
+            "second hop spends {}, the first returns {}",
+            second.input_mint,
+            first.output_mint
+        ));
+    }
+    Ok(Route {
+        hops: [first, second],
+    })
+}
+
+/// Finds the AMM v4 pools of `input_mint` -> `via` -> `output_mint`
+pub async fn find_route_pools(
+    client: Arc<RpcClient>,
+    input_mint: &Pubkey,
+    via: &Pubkey,
+    output_mint: &Pubkey,
+) -> Result<[Pubkey; 2]> {
+    Ok([
+        find_pool_by_mints(client.clone(), input_mint, via).await?,
+        find_pool_by_mints(client, via, output_mint).await?,
+    ])
+}
+
+/// Quotes swapping exactly `amount_in` of `input_mint` through `pools`, the
+/// first pairing it with `via`, the second `via` with `output_mint`
+///
+/// Each hop's minimum out allows `slippage_bps` off its own quote.
+pub async fn quote_route(
+    client: Arc<RpcClient>,
+    pools: &[Pubkey; 2],
+    input_mint: &Pubkey,
+    via: &Pubkey,
+    output_mint: &Pubkey,
+    amount_in: u64,
+    slippage_bps: u64,
+) -> Result<Route> {
+    let pools = load_pools(client, pools).await?;
+    quote_pools(
+        &pools,
+        input_mint,
+        via,
+        output_mint,
+        amount_in,
+        slippage_bps,
+    )
+}
+
+async fn load_pools(client: Arc<RpcClient>, pools: &[Pubkey; 2]) -> Result<[RoutePool; 2]> {
+    let (first, second) = tokio::try_join!(
+        RoutePool::load(client.clone(), &pools[0]),
+        RoutePool::load(client, &pools[1]),
+    )?;
+    Ok([first, second])
+}
+
+fn quote_pools(
+    pools: &[RoutePool; 2],
+    input_mint: &Pubkey,
+    via: &Pubkey,
+    output_mint: &Pubkey,
+    amount_in: u64,
+    slippage_bps: u64,
+) -> Result<Route> {
+    compose(
+        pools[0].quote(input_mint, via, amount_in, slippage_bps)?,
+        |amount| pools[1].quote(via, output_mint, amount, slippage_bps),
+    )
+}
+
+/// Swaps exactly `amount_in` of `input_mint` into `output_mint` through `via`,
+/// in one transaction
+///
+/// The pools are found with [`find_pool_by_mints`]. `slippage` is in percent,
+/// per hop.
+#[allow(clippy::too_many_arguments)]
+pub async fn swap_two_hop(
+    client: Arc<RpcClient>,
+    keypair: Arc<Keypair>,
+    input_mint: &Pubkey,
+    via: &Pubkey,
+    output_mint: &Pubkey,
+    amount_in: SwapAmount,
+    slippage: u64,
+    is_simulate: bool,
+) -> Result<TxOutcome> {
+    let owner = keypair.pubkey();
+    let native_mint = spl_token::native_mint::ID;
+    let amount_in = match amount_in {
+        SwapAmount::Raw(amount) => amount,
+        SwapAmount::Ui(_) if *input_mint == native_mint => {
+            amount_in.to_raw(spl_token::native_mint::DECIMALS)
+        }
+        SwapAmount::Ui(_) => amount_in.to_raw(
+            getter::get_mint_info(client.clone(), keypair.clone(), input_mint)
+                .await?
+                .decimals,
+        ),
+    };
+
+    // 用sol买入时检查风控、预算和冷却
+    let reservation =
+        if !ExecutionMode::resolve(is_simulate).simulates() && *input_mint == native_mint {
+            risk::check_buy(output_mint, amount_in)?;
+            Some(global_guard().reserve(output_mint, amount_in)?)
+        } else {
+            None
+        };
+
+    let pools = find_route_pools(client.clone(), input_mint, via, output_mint).await?;
+    let pools = load_pools(client.clone(), &pools).await?;
+    let route = quote_pools(
+        &pools,
+        input_mint,
+        via,
+        output_mint,
+        amount_in,
+        slippage * 100,
+    )?;
+    info!(
+        "route {} -> {} -> {}: {} in, {} out (min {})",
+        input_mint,
+        via,
+        output_mint,
+        route.amount_in(),
+        route.expected_out(),
+        route.min_out()
+    );
+
+    let mut instructions = vec![];
+    // 路径中的sol都用同一个wsol账户
+    let mints = [*input_mint, *via, *output_mint];
+    let wsol_account = if mints.contains(&native_mint) {
+        let lamports = if *input_mint == native_mint {
+            amount_in
+        } else {
+            0
+        };
+        let wsol = wsol::prepare(&client, &owner, lamports).await?;
+        instructions.extend(wsol.setup.iter().cloned());
+        Some(wsol)
+    } else {
+        None
+    };
+    let account = |mint: &Pubkey| match &wsol_account {
+        Some(wsol) if *mint == native_mint => wsol.address,
+        _ => get_associated_token_address(&owner, mint),
+    };
+    for mint in [via, output_mint] {
+        if *mint != native_mint {
+            instructions.push(create_associated_token_account_idempotent(
+                &owner,
+                &owner,
+                mint,
+                &spl_token::ID,
+            ));
+        }
+    }
+
+    let amm_program = program_ids().raydium_amm;
+    for (hop, pool) in route.hops.iter().zip(&pools) {
+        let info = swap_accounts(
+            &pool.keys,
+            pool.market.as_ref(),
+            hop.input_mint,
+            hop.output_mint,
+            hop.quote.amount_in,
+            hop.quote.min_out,
+            hop.quote.expected_out,
+        );
+        instructions.push(amm_swap(
+            &amm_program,
+            info,
+            &owner,
+            &account(&hop.input_mint),
+            &account(&hop.output_mint),
+            hop.quote.amount_in,
+            hop.quote.min_out,
+            true,
+        )?);
+    }
+    if let Some(wsol) = &wsol_account {
+        instructions.extend(wsol.cleanup.iter().cloned());
+    }
+
+    let expected = ExpectedOutput {
+        expected_out: route.expected_out(),
+        min_out: route.min_out(),
+        account: match &wsol_account {
+            Some(wsol) if *output_mint == native_mint => wsol.output(&owner),
+            _ => OutputAccount::Token(account(output_mint)),
+        },
+    };
+    let outcome = new_signed_and_send(
+        client,
+        keypair,
+        instructions,
+        is_simulate,
+        Some(expected),
+        "raydium",
+        trade_side(input_mint, output_mint),
+    )
+    .await?;
+    if let Some(reservation) = reservation {
+        reservation.commit();
+    }
+
+    // 只记录和sol之间的交易
+    if *input_mint == native_mint {
+        record_trade(
+            "raydium",
+            Side::Buy,
+            output_mint,
+            route.expected_out(),
+            amount_in,
+            &outcome,
+        );
+    } else if *output_mint == native_mint {
+        record_trade(
+            "raydium",
+            Side::Sell,
+            input_mint,
+            amount_in,
+            route.expected_out(),
+            &outcome,
+        );
+    }
+    Ok(outcome)
+}
+
+#[test]
+fn test_compose_propagates_min_out() {
+    use crate::raydium::structure::{Fees, SwapDirection};
+
+    let fees = Fees {
+        swap_fee_numerator: 25,
+        swap_fee_denominator: 10_000,
+        ..Fees::default()
+    };
+    let (token, sol, usdc) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let hop = |input_mint, output_mint, amount, pc, coin| -> Result<Hop> {
+        Ok(Hop {
+            pool: Pubkey::new_unique(),
+            input_mint,
+            output_mint,
+            quote: quote_reserves(pc, coin, &fees, SwapDirection::Buy, amount, 100)?,
+        })
+    };
+
+    let first = hop(token, sol, 1_000_000, 50_000_000, 100_000_000_000).unwrap();
+    let first_min = first.quote.min_out;
+    let route = compose(first, |amount| {
+        hop(sol, usdc, amount, 1_000_000_000, 5_000_000_000)
+    })
+    .unwrap();
+    assert_eq!(route.amount_in(), 1_000_000);
+    assert_eq!(route.hops[1].quote.amount_in, first_min);
+    assert!(route.min_out() < route.expected_out());
+
+    let err = compose(route.hops[0].clone(), |amount| {
+        hop(usdc, sol, amount, 1_000, 1_000)
+    })
+    .unwrap_err();
+    assert!(err.to_string().contains("second hop spends"));
+}
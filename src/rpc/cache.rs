@@ -0,0 +1,437 @@
+//! Warm cache of decoded pool, vault and bonding curve accounts.
+//!
+//! With `ACCOUNT_CACHE_ENABLED=true`, [`get_accounts`] serves the accounts
+//! quotes read over and over from memory. A miss is fetched once, decoded,
+//! stored with the slot it was read at, and subscribed to with
+//! `accountSubscribe`; each notification replaces the entry unless it's from
+//! an older slot than what's cached, so a fetch racing an update never
+//! overwrites it. An entry whose subscription drops is evicted, as it can't
+//! be trusted anymore, and the least recently used one when the cache is
+//! full.
+//!
+//! - `ACCOUNT_CACHE_MAX_ENTRIES`: accounts kept and subscribed to, default
+//!   1000
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex, OnceLock},
+    time::Instant,
+};
+
+use anyhow::{anyhow, Result};
+use borsh::BorshDeserialize;
+use futures_util::StreamExt;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    nonblocking::{pubsub_client::PubsubClient, rpc_client::RpcClient},
+    rpc_config::RpcAccountInfoConfig,
+};
+use solana_sdk::{account::Account, program_pack::Pack, pubkey::Pubkey};
+use spl_token::state::Account as TokenAccount;
+use tokio::task::AbortHandle;
+use tracing::{debug, warn};
+
+use crate::{
+    metrics, new_client, new_ws_client,
+    pumpfun::accounts::BondingCurveAccount,
+    raydium::{error::RaydiumError, structure::AmmInfo},
+    rpc::retry::with_retry,
+    strategy::parse_env,
+};
+
+const DEFAULT_MAX_ENTRIES: usize = 1000;
+
+static GLOBAL_ACCOUNT_CACHE: OnceLock<Option<AccountCache>> = OnceLock::new();
+
+/// How the data of a cached account is decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountKind {
+    Amm,
+    BondingCurve,
+    Token,
+}
+
+impl fmt::Display for AccountKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountKind::Amm => write!(f, "amm pool"),
+            AccountKind::BondingCurve => write!(f, "bonding curve"),
+            AccountKind::Token => write!(f, "token"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CachedAccount {
+    Amm(Box<AmmInfo>),
+    BondingCurve(BondingCurveAccount),
+    Token(TokenAccount),
+}
+
+impl CachedAccount {
+    pub fn decode(kind: AccountKind, pubkey: &Pubkey, account: &Account) -> Result<Self> {
+        Ok(match kind {
+            AccountKind::Amm => CachedAccount::Amm(Box::new(
+                bytemuck::try_pod_read_unaligned(&account.data)
+                    .map_err(|_| anyhow!("{} is not an amm v4 pool account", pubkey))?,
+            )),
+            // 新版曲线账户末尾有额外字段
+            AccountKind::BondingCurve => CachedAccount::BondingCurve(
+                BondingCurveAccount::deserialize(&mut account.data.as_slice())?,
+            ),
+            AccountKind::Token => {
+                if account.owner != spl_token::ID {
+                    return Err(RaydiumError::NotTokenAccount {
+                        name: "token",
+                        pubkey: *pubkey,
+                        owner: account.owner,
+                    }
+                    .into());
+                }
+                CachedAccount::Token(TokenAccount::unpack(&account.data).map_err(|_| {
+                    RaydiumError::InvalidTokenAccount {
+                        name: "token",
+                        pubkey: *pubkey,
+                    }
+                })?)
+            }
+        })
+    }
+
+    pub fn amm(&self) -> Option<&AmmInfo> {
+        match self {
+            CachedAccount::Amm(amm) => Some(amm),
+            _ => None,
+        }
+    }
+
+    pub fn bonding_curve(&self) -> Option<&BondingCurveAccount> {
+        match self {
+            CachedAccount::BondingCurve(curve) => Some(curve),
+            _ => None,
+        }
+    }
+
+    pub fn token(&self) -> Option<&TokenAccount> {
+        match self {
+            CachedAccount::Token(token) => Some(token),
+            _ => None,
+        }
+    }
+}
+
+struct Entry {
+    kind: AccountKind,
+    /// `None` 表示账户不存在
+    account: Option<CachedAccount>,
+    slot: u64,
+    last_used: Instant,
+    subscription: Option<AbortHandle>,
+}
+
+/// Decoded accounts kept fresh by websocket notifications
+pub struct AccountCache {
+    max_entries: usize,
+    entries: Mutex<HashMap<Pubkey, Entry>>,
+    /// 所有订阅共用，断开后重连
+    ws_client: tokio::sync::Mutex<Option<Arc<PubsubClient>>>,
+}
+
+impl AccountCache {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            max_entries: max_entries.max(1),
+            entries: Mutex::new(HashMap::new()),
+            ws_client: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// `None` unless `ACCOUNT_CACHE_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("ACCOUNT_CACHE_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        Ok(Some(Self::new(
+            parse_env("ACCOUNT_CACHE_MAX_ENTRIES")?.unwrap_or(DEFAULT_MAX_ENTRIES),
+        )))
+    }
+
+    /// Cached account of `pubkey`, `Some(None)` if it's cached as missing
+    pub fn get(&self, pubkey: &Pubkey, kind: AccountKind) -> Option<Option<CachedAccount>> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(pubkey).filter(|entry| entry.kind == kind)?;
+        entry.last_used = Instant::now();
+        Some(entry.account.clone())
+    }
+
+    /// Stores `account` as read at `slot`, unless a newer read is cached;
+    /// returns whether the account wasn't cached yet
+    fn store(
+        &self,
+        pubkey: Pubkey,
+        kind: AccountKind,
+        account: Option<CachedAccount>,
+        slot: u64,
+    ) -> bool {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.get_mut(&pubkey) {
+            if entry.kind == kind {
+                if slot >= entry.slot {
+                    entry.account = account;
+                    entry.slot = slot;
+                }
+                return false;
+            }
+            // 类型不同时按新类型重新缓存
+            if let Some(subscription) = entries.remove(&pubkey).and_then(|e| e.subscription) {
+                subscription.abort();
+            }
+        }
+        if entries.len() >= self.max_entries {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(pubkey, _)| *pubkey);
+            if let Some(subscription) = oldest
+                .and_then(|pubkey| entries.remove(&pubkey))
+                .and_then(|entry| entry.subscription)
+            {
+                subscription.abort();
+            }
+        }
+        entries.insert(
+            pubkey,
+            Entry {
+                kind,
+                account,
+                slot,
+                last_used: Instant::now(),
+                subscription: None,
+            },
+        );
+        true
+    }
+
+    /// Drops the entry of `pubkey`
+    pub fn evict(&self, pubkey: &Pubkey) {
+        if let Some(subscription) = self
+            .entries
+            .lock()
+            .unwrap()
+            .remove(pubkey)
+            .and_then(|entry| entry.subscription)
+        {
+            subscription.abort();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Accounts of `keys`, fetching and subscribing to those not cached
+    pub async fn get_multiple(
+        &'static self,
+        client: Arc<RpcClient>,
+        keys: &[(Pubkey, AccountKind)],
+    ) -> Result<Vec<Option<CachedAccount>>> {
+        let mut result = vec![None; keys.len()];
+        let mut missing = vec![];
+        for (i, (pubkey, kind)) in keys.iter().enumerate() {
+            match self.get(pubkey, *kind) {
+                Some(account) => result[i] = account,
+                None => missing.push(i),
+            }
+        }
+        metrics::ACCOUNT_CACHE_LOOKUPS
+            .with_label_values(&["hit"])
+            .inc_by((keys.len() - missing.len()) as u64);
+        if missing.is_empty() {
+            return Ok(result);
+        }
+        metrics::ACCOUNT_CACHE_LOOKUPS
+            .with_label_values(&["miss"])
+            .inc_by(missing.len() as u64);
+
+        let pubkeys: Vec<Pubkey> = missing.iter().map(|&i| keys[i].0).collect();
+        let (slot, accounts) = fetch(&client, &pubkeys).await?;
+        for (i, account) in missing.into_iter().zip(accounts) {
+            let (pubkey, kind) = keys[i];
+            let account = account
+                .map(|account| CachedAccount::decode(kind, &pubkey, &account))
+                .transpose()?;
+            if self.store(pubkey, kind, account.clone(), slot) {
+                let subscription = tokio::spawn(self.watch(pubkey, kind)).abort_handle();
+                match self.entries.lock().unwrap().get_mut(&pubkey) {
+                    Some(entry) => entry.subscription = Some(subscription),
+                    // 已被挤出缓存
+                    None => subscription.abort(),
+                }
+            }
+            result[i] = account;
+        }
+        Ok(result)
+    }
+
+    async fn ws_client(&self) -> Result<Arc<PubsubClient>> {
+        let mut ws_client = self.ws_client.lock().await;
+        if let Some(client) = ws_client.as_ref() {
+            return Ok(client.clone());
+        }
+        let client = new_ws_client().await?;
+        *ws_client = Some(client.clone());
+        Ok(client)
+    }
+
+    /// Applies the notifications of `pubkey` until its subscription drops,
+    /// then evicts it
+    async fn watch(&'static self, pubkey: Pubkey, kind: AccountKind) {
+        let result: Result<()> = async {
+            let ws_client = self.ws_client().await?;
+            let config = RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                commitment: Some(new_client().commitment()),
+                ..RpcAccountInfoConfig::default()
+            };
+            let (mut stream, _) = ws_client.account_subscribe(&pubkey, Some(config)).await?;
+            // 订阅前的更新不会推送，重新读取一次
+            let (slot, account) = fetch(&new_client(), &[pubkey]).await?;
+            self.apply(pubkey, kind, account.into_iter().flatten().next(), slot);
+            while let Some(update) = stream.next().await {
+                self.apply(pubkey, kind, update.value.decode(), update.context.slot);
+            }
+            Ok(())
+        }
+        .await;
+        match result {
+            Ok(()) => debug!("account cache stream of {} closed", pubkey),
+            Err(e) => warn!("account cache failed to watch {} {:?}", pubkey, e),
+        }
+        // 连接可能已断开，下次订阅时重连
+        self.ws_client.lock().await.take();
+        self.entries.lock().unwrap().remove(&pubkey);
+    }
+
+    /// Applies an update of `pubkey` if it's still cached; closed or
+    /// undecodable accounts are cached as missing
+    fn apply(&self, pubkey: Pubkey, kind: AccountKind, account: Option<Account>, slot: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        let Some(entry) = entries.get_mut(&pubkey).filter(|entry| entry.kind == kind) else {
+            return;
+        };
+        if slot >= entry.slot {
+            entry.account =
+                account.and_then(|account| CachedAccount::decode(kind, &pubkey, &account).ok());
+            entry.slot = slot;
+        }
+    }
+}
+
+/// Reads `pubkeys` with the slot of the read
+async fn fetch(client: &RpcClient, pubkeys: &[Pubkey]) -> Result<(u64, Vec<Option<Account>>)> {
+    let response =
+        with_retry(|| client.get_multiple_accounts_with_commitment(pubkeys, client.commitment()))
+            .await?;
+    Ok((response.context.slot, response.value))
+}
+
+/// Process-wide account cache, `None` unless `ACCOUNT_CACHE_ENABLED=true`
+pub fn account_cache() -> Option<&'static AccountCache> {
+    GLOBAL_ACCOUNT_CACHE
+        .get_or_init(|| {
+            AccountCache::from_env().unwrap_or_else(|e| {
+                warn!("account cache disabled {:?}", e);
+                None
+            })
+        })
+        .as_ref()
+}
+
+/// Decoded accounts of `keys`, through the cache when it's enabled
+pub async fn get_accounts(
+    client: Arc<RpcClient>,
+    keys: &[(Pubkey, AccountKind)],
+) -> Result<Vec<Option<CachedAccount>>> {
+    if let Some(cache) = account_cache() {
+        return cache.get_multiple(client, keys).await;
+    }
+    let pubkeys: Vec<Pubkey> = keys.iter().map(|(pubkey, _)| *pubkey).collect();
+    let (_, accounts) = fetch(&client, &pubkeys).await?;
+    keys.iter()
+        .zip(accounts)
+        .map(|((pubkey, kind), account)| {
+            account
+                .map(|account| CachedAccount::decode(*kind, pubkey, &account))
+                .transpose()
+        })
+        .collect()
+}
+
+#[test]
+fn test_account_cache_slots() {
+    let cache = AccountCache::new(2);
+    let (pool, vault, curve) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let token = |amount| {
+        Some(CachedAccount::Token(TokenAccount {
+            amount,
+            ..Default::default()
+        }))
+    };
+    let amount = |cache: &AccountCache| {
+        cache
+            .get(&vault, AccountKind::Token)
+            .flatten()
+            .and_then(|account| account.token().map(|token| token.amount))
+    };
+
+    assert!(cache.store(vault, AccountKind::Token, token(1), 10));
+    assert_eq!(amount(&cache), Some(1));
+    // 旧 slot 的读取不覆盖新的通知
+    assert!(!cache.store(vault, AccountKind::Token, token(2), 9));
+    assert_eq!(amount(&cache), Some(1));
+    cache.store(vault, AccountKind::Token, token(3), 11);
+    assert_eq!(amount(&cache), Some(3));
+    assert!(cache.get(&vault, AccountKind::Amm).is_none());
+
+    // 账户关闭后缓存为不存在，已挤出的账户不会被通知重新加入
+    cache.apply(vault, AccountKind::Token, None, 12);
+    assert!(matches!(cache.get(&vault, AccountKind::Token), Some(None)));
+    cache.apply(curve, AccountKind::BondingCurve, None, 12);
+    assert!(cache.get(&curve, AccountKind::BondingCurve).is_none());
+
+    // 满了以后挤出最久未用的
+    cache.store(pool, AccountKind::Amm, None, 12);
+    cache.get(&vault, AccountKind::Token);
+    cache.store(curve, AccountKind::BondingCurve, None, 12);
+    assert_eq!(cache.len(), 2);
+    assert!(cache.get(&pool, AccountKind::Amm).is_none());
+    assert!(cache.get(&vault, AccountKind::Token).is_some());
+    cache.evict(&vault);
+    assert!(cache.get(&vault, AccountKind::Token).is_none());
+
+    let mut data = vec![0u8; TokenAccount::LEN];
+    TokenAccount {
+        amount: 5,
+        state: spl_token::state::AccountState::Initialized,
+        ..Default::default()
+    }
+    .pack_into_slice(&mut data);
+    let account = Account {
+        data,
+        owner: spl_token::ID,
+        ..Default::default()
+    };
+    let decoded = CachedAccount::decode(AccountKind::Token, &vault, &account).unwrap();
+    assert_eq!(decoded.token().unwrap().amount, 5);
+    assert!(CachedAccount::decode(AccountKind::Amm, &pool, &account).is_err());
+}
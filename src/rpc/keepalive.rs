@@ -0,0 +1,234 @@
+//! Warm connections to the RPC and Jito endpoints.
+//!
+//! A request on a connection the pool closed for idling first pays the TCP
+//! and TLS handshakes, which is the latency a trade can least afford. With
+//! `RPC_KEEPALIVE_ENABLED=true` every RPC endpoint and the Jito block engine
+//! get a lightweight request at startup, before any trade, and again every
+//! `RPC_KEEPALIVE_SECS` (default 10), well within the idle timeouts: the
+//! RPC nodes `getHealth`, Jito `getTipAccounts`. The requests go through the
+//! same clients as the trades, so they keep the trades' connections open,
+//! over HTTP/2 where the endpoint negotiates it.
+//!
+//! Each round trip is recorded into the endpoint's [`RttStats`], which the
+//! [`MultiClient`](super::multi::MultiClient) health checks record into too
+//! and route the reads by. The smoothed round trip times are exported as
+//! `bot_endpoint_rtt_microseconds`, labelled with the endpoint's host only
+//! so api keys in the urls don't end up in the metrics.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use futures_util::future::join_all;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::{
+    metrics, new_client,
+    rpc::multi::{global_multi_client, Endpoint},
+    strategy::parse_env,
+    tx,
+};
+
+pub const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Round trip times of one endpoint
+pub struct RttStats {
+    /// Host the metrics are labelled with
+    label: String,
+    /// Moving average, `u64::MAX` before the first sample
+    smoothed_us: AtomicU64,
+    last_us: AtomicU64,
+    failures: AtomicU64,
+}
+
+impl RttStats {
+    pub fn new(url: &str) -> Self {
+        let label = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or("unknown".to_string());
+        Self {
+            label,
+            smoothed_us: AtomicU64::new(u64::MAX),
+            last_us: AtomicU64::new(u64::MAX),
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    /// Records a round trip that took `rtt`
+    pub fn record(&self, rtt: Duration) {
+        let sample = rtt.as_micros() as u64;
+        self.last_us.store(sample, Ordering::Relaxed);
+        // 和TCP的SRTT一样，新样本占1/8
+        let smoothed = match self.smoothed_us.load(Ordering::Relaxed) {
+            u64::MAX => sample,
+            smoothed => (smoothed * 7 + sample) / 8,
+        };
+        self.smoothed_us.store(smoothed, Ordering::Relaxed);
+        metrics::ENDPOINT_RTT_MICROSECONDS
+            .with_label_values(&[&self.label])
+            .set(smoothed as i64);
+    }
+
+    /// Records a request that failed
+    pub fn record_failure(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Moving average of the round trips, `u64::MAX` before the first one,
+    /// so unmeasured endpoints sort last
+    pub fn smoothed_us(&self) -> u64 {
+        self.smoothed_us.load(Ordering::Relaxed)
+    }
+
+    pub fn smoothed(&self) -> Option<Duration> {
+        match self.smoothed_us() {
+            u64::MAX => None,
+            us => Some(Duration::from_micros(us)),
+        }
+    }
+
+    pub fn last(&self) -> Option<Duration> {
+        match self.last_us.load(Ordering::Relaxed) {
+            u64::MAX => None,
+            us => Some(Duration::from_micros(us)),
+        }
+    }
+
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+}
+
+/// An endpoint kept warm
+enum Target {
+    /// RPC node of the multi client, its stats live with the endpoint
+    Endpoint(&'static Endpoint),
+    /// Single RPC client of `rpc_url`
+    Rpc(Arc<RpcClient>, RttStats),
+    Jito(RttStats),
+}
+
+impl Target {
+    fn name(&self) -> String {
+        match self {
+            Target::Endpoint(endpoint) => endpoint.client.url(),
+            Target::Rpc(client, _) => client.url(),
+            Target::Jito(_) => "jito block engine".to_string(),
+        }
+    }
+
+    /// Sends the lightweight request and records its round trip
+    async fn ping(&self) -> Result<()> {
+        let start = Instant::now();
+        let (result, stats): (Result<()>, &RttStats) = match self {
+            Target::Endpoint(endpoint) => (
+                endpoint.client.get_health().await.map_err(Into::into),
+                endpoint.rtt(),
+            ),
+            Target::Rpc(client, stats) => (client.get_health().await.map_err(Into::into), stats),
+            Target::Jito(stats) => (tx::sender::ping_jito().await, stats),
+        };
+        match &result {
+            Ok(()) => stats.record(start.elapsed()),
+            Err(_) => stats.record_failure(),
+        }
+        result
+    }
+}
+
+pub struct Keepalive {
+    targets: Vec<Target>,
+}
+
+impl Keepalive {
+    /// Every RPC endpoint in use and the Jito block engine
+    pub fn new() -> Self {
+        let mut targets: Vec<Target> = match global_multi_client() {
+            Some(multi) => multi.endpoints().iter().map(Target::Endpoint).collect(),
+            None => {
+                let client = new_client();
+                let stats = RttStats::new(&client.url());
+                vec![Target::Rpc(client, stats)]
+            }
+        };
+        targets.push(Target::Jito(RttStats::new(&tx::sender::jito_url())));
+        Self { targets }
+    }
+
+    /// Pings every endpoint at once
+    pub async fn ping_all(&self) {
+        let results = join_all(self.targets.iter().map(Target::ping)).await;
+        for (target, result) in self.targets.iter().zip(results) {
+            if let Err(e) = result {
+                warn!("keepalive of {} failed {:?}", target.name(), e);
+            }
+        }
+    }
+
+    /// Spawns the task pinging the endpoints every `interval`
+    pub fn spawn(self, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // 第一次已经在启动时发过
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                self.ping_all().await;
+            }
+        })
+    }
+}
+
+impl Default for Keepalive {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Warms the connections and keeps them alive if
+/// `RPC_KEEPALIVE_ENABLED=true`
+///
+/// Call after the multi client was started, so its endpoints are warmed.
+pub async fn start_from_env() -> Result<Option<JoinHandle<()>>> {
+    dotenv::dotenv().ok();
+    if !parse_env::<bool>("RPC_KEEPALIVE_ENABLED")?.unwrap_or(false) {
+        return Ok(None);
+    }
+    let interval = parse_env("RPC_KEEPALIVE_SECS")?
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_KEEPALIVE_INTERVAL);
+    let keepalive = Keepalive::new();
+    keepalive.ping_all().await;
+    info!(
+        "keeping {} endpoints warm every {:?}",
+        keepalive.targets.len(),
+        interval
+    );
+    Ok(Some(keepalive.spawn(interval)))
+}
+
+#[test]
+fn test_rtt_stats() {
+    let stats = RttStats::new("https://mainnet.example.com/?api-key=secret");
+    assert_eq!(stats.label, "mainnet.example.com");
+    assert_eq!(stats.smoothed(), None);
+
+    stats.record(Duration::from_millis(80));
+    assert_eq!(stats.smoothed(), Some(Duration::from_millis(80)));
+    // 一次慢请求只移动均值的1/8
+    stats.record(Duration::from_millis(160));
+    assert_eq!(stats.smoothed(), Some(Duration::from_millis(90)));
+    assert_eq!(stats.last(), Some(Duration::from_millis(160)));
+
+    stats.record_failure();
+    assert_eq!(stats.failures(), 1);
+    assert_eq!(RttStats::new("succeeds").label, "unknown");
+}
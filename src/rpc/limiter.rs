@@ -0,0 +1,356 @@
+//! Request budget for rpc endpoints.
+//!
+//! Clients built by [`new_rpc_client`] send through a [`LimitedSender`] that
+//! shares one [`Limiter`] per endpoint url, so the monitors, strategies and
+//! health checks all draw from the same budget. A token bucket caps the
+//! request rate, per method semaphores cap the calls in flight, and a 429
+//! answer pauses the whole endpoint with a doubling backoff before the call
+//! is retried.
+//!
+//! - `RPC_LIMITER_ENABLED`: send through the limiter (default false)
+//! - `RPC_RATE_LIMIT`: requests per second per endpoint (default 10)
+//! - `RPC_RATE_BURST`: requests sent at once after idling (default the rate)
+//! - `RPC_METHOD_CONCURRENCY`: comma separated `<method>=<max>` caps on calls
+//!   in flight, e.g. `getProgramAccounts=1,getMultipleAccounts=4`
+//! - `RPC_BACKOFF_MAX_MS`: longest pause after repeated 429s (default 10000)
+//! - `RPC_RATE_LIMITED_RETRIES`: retries of a rate limited call (default 3)
+
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, LazyLock, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use solana_client::{
+    client_error::{reqwest::StatusCode, ClientError, ClientErrorKind, Result as ClientResult},
+    nonblocking::rpc_client::RpcClient,
+    rpc_request::{RpcError, RpcRequest},
+    rpc_sender::{RpcSender, RpcTransportStats},
+};
+use solana_rpc_client::{http_sender::HttpSender, rpc_client::RpcClientConfig};
+use solana_sdk::commitment_config::CommitmentConfig;
+use tokio::sync::Semaphore;
+use tracing::warn;
+
+use crate::{metrics, strategy::parse_env};
+
+pub const DEFAULT_RATE_LIMIT: f64 = 10.0;
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+pub const DEFAULT_RATE_LIMITED_RETRIES: u32 = 3;
+
+/// Pause after the first 429, doubled on each one that follows
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+
+static CONFIG: OnceLock<Option<LimiterConfig>> = OnceLock::new();
+static LIMITERS: LazyLock<Mutex<HashMap<String, Arc<Limiter>>>> = LazyLock::new(Default::default);
+
+#[derive(Debug, Clone)]
+pub struct LimiterConfig {
+    /// Requests per second
+    pub rate: f64,
+    /// Requests sent at once after idling
+    pub burst: f64,
+    /// Calls in flight allowed per method, unlisted methods are uncapped
+    pub method_concurrency: HashMap<String, usize>,
+    pub max_backoff: Duration,
+    pub rate_limited_retries: u32,
+}
+
+impl Default for LimiterConfig {
+    fn default() -> Self {
+        Self {
+            rate: DEFAULT_RATE_LIMIT,
+            burst: DEFAULT_RATE_LIMIT,
+            method_concurrency: HashMap::new(),
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            rate_limited_retries: DEFAULT_RATE_LIMITED_RETRIES,
+        }
+    }
+}
+
+impl LimiterConfig {
+    /// `None` unless `RPC_LIMITER_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("RPC_LIMITER_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        let rate = parse_env::<f64>("RPC_RATE_LIMIT")?.unwrap_or(DEFAULT_RATE_LIMIT);
+        if rate.is_nan() || rate <= 0.0 {
+            return Err(anyhow!("RPC_RATE_LIMIT must be positive, got {}", rate));
+        }
+        let method_concurrency = match env::var("RPC_METHOD_CONCURRENCY") {
+            Ok(caps) => parse_method_concurrency(&caps)
+                .map_err(|e| anyhow!("invalid RPC_METHOD_CONCURRENCY: {}", e))?,
+            Err(_) => HashMap::new(),
+        };
+        Ok(Some(Self {
+            rate,
+            burst: parse_env::<f64>("RPC_RATE_BURST")?.unwrap_or(rate).max(1.0),
+            method_concurrency,
+            max_backoff: parse_env::<u64>("RPC_BACKOFF_MAX_MS")?
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_MAX_BACKOFF),
+            rate_limited_retries: parse_env("RPC_RATE_LIMITED_RETRIES")?
+                .unwrap_or(DEFAULT_RATE_LIMITED_RETRIES),
+        }))
+    }
+}
+
+/// Parses `<method>=<max>` pairs
+fn parse_method_concurrency(caps: &str) -> Result<HashMap<String, usize>> {
+    caps.split(',')
+        .map(str::trim)
+        .filter(|cap| !cap.is_empty())
+        .map(|cap| {
+            let (method, max) = cap
+                .split_once('=')
+                .ok_or_else(|| anyhow!("expected <method>=<max>, got {:?}", cap))?;
+            let max: usize = max
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("invalid max {:?} for {}", max, method))?;
+            if max == 0 {
+                return Err(anyhow!("max for {} must be positive", method));
+            }
+            Ok((method.trim().to_string(), max))
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// 可能为负，表示已预约的请求
+    tokens: f64,
+    updated: Instant,
+    paused_until: Option<Instant>,
+    backoff: Duration,
+}
+
+/// Budget of one endpoint
+#[derive(Debug)]
+pub struct Limiter {
+    config: LimiterConfig,
+    bucket: Mutex<Bucket>,
+    semaphores: HashMap<String, Arc<Semaphore>>,
+}
+
+impl Limiter {
+    pub fn new(config: LimiterConfig) -> Self {
+        let semaphores = config
+            .method_concurrency
+            .iter()
+            .map(|(method, max)| (method.clone(), Arc::new(Semaphore::new(*max))))
+            .collect();
+        Self {
+            bucket: Mutex::new(Bucket {
+                tokens: config.burst,
+                updated: Instant::now(),
+                paused_until: None,
+                backoff: Duration::ZERO,
+            }),
+            config,
+            semaphores,
+        }
+    }
+
+    /// Takes a token, returning how long to wait before sending
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.config.rate).min(self.config.burst);
+        bucket.updated = now;
+        bucket.tokens -= 1.0;
+        let debt = Duration::from_secs_f64((-bucket.tokens).max(0.0) / self.config.rate);
+        let pause = bucket
+            .paused_until
+            .map(|until| until.saturating_duration_since(now))
+            .unwrap_or_default();
+        debt.max(pause)
+    }
+
+    /// Waits until the budget allows one more request
+    pub async fn acquire(&self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Pauses the endpoint after a 429, returning the pause
+    pub(crate) fn rate_limited(&self, now: Instant) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.backoff = if bucket.backoff.is_zero() {
+            BASE_BACKOFF
+        } else {
+            (bucket.backoff * 2).min(self.config.max_backoff)
+        };
+        bucket.paused_until = Some(now + bucket.backoff);
+        bucket.backoff
+    }
+
+    pub(crate) fn succeeded(&self) {
+        self.bucket.lock().unwrap().backoff = Duration::ZERO;
+    }
+}
+
+/// Whether the endpoint refused `err` for exceeding its rate limit
+pub fn is_rate_limited(err: &ClientError) -> bool {
+    match err.kind() {
+        ClientErrorKind::Reqwest(e) => e.status() == Some(StatusCode::TOO_MANY_REQUESTS),
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => *code == 429,
+        _ => false,
+    }
+}
+
+/// Sends through `inner` within the budget of `limiter`
+pub struct LimitedSender<S> {
+    inner: S,
+    limiter: Arc<Limiter>,
+}
+
+impl<S> LimitedSender<S> {
+    pub fn new(inner: S, limiter: Arc<Limiter>) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+#[async_trait]
+impl<S: RpcSender + Send + Sync> RpcSender for LimitedSender<S> {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> ClientResult<serde_json::Value> {
+        let method = request.to_string();
+        let _permit = match self.limiter.semaphores.get(&method) {
+            // semaphore 从不关闭
+            Some(semaphore) => Some(semaphore.acquire().await.unwrap()),
+            None => None,
+        };
+        let mut retries = 0;
+        loop {
+            self.limiter.acquire().await;
+            match self.inner.send(request, params.clone()).await {
+                Err(e)
+                    if is_rate_limited(&e)
+                        && retries < self.limiter.config.rate_limited_retries =>
+                {
+                    retries += 1;
+                    let pause = self.limiter.rate_limited(Instant::now());
+                    metrics::RPC_RATE_LIMITED
+                        .with_label_values(&[&method])
+                        .inc();
+                    warn!(
+                        "{} rate limited {} by {}, pausing {:?}",
+                        method,
+                        retries,
+                        self.inner.url(),
+                        pause
+                    );
+                }
+                res => {
+                    if res.is_ok() {
+                        self.limiter.succeeded();
+                    }
+                    return res;
+                }
+            }
+        }
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.inner.get_transport_stats()
+    }
+
+    fn url(&self) -> String {
+        self.inner.url()
+    }
+}
+
+/// Reads the limiter config, failing if it's invalid
+pub fn init() -> Result<()> {
+    let config = LimiterConfig::from_env()?;
+    CONFIG.get_or_init(|| config);
+    Ok(())
+}
+
+/// Limiter shared by every client of `url`, `None` when disabled
+///
+/// Panics on an invalid config if `init` wasn't called first.
+pub fn limiter(url: &str) -> Option<Arc<Limiter>> {
+    let config = CONFIG
+        .get_or_init(|| LimiterConfig::from_env().unwrap())
+        .as_ref()?;
+    let mut limiters = LIMITERS.lock().unwrap();
+    Some(
+        limiters
+            .entry(url.to_string())
+            .or_insert_with(|| Arc::new(Limiter::new(config.clone())))
+            .clone(),
+    )
+}
+
+/// Http client of `url`, within the endpoint's budget when the limiter is
+/// enabled
+pub fn new_rpc_client(url: String, timeout: Duration, commitment: CommitmentConfig) -> RpcClient {
+    let sender = HttpSender::new_with_timeout(url.clone(), timeout);
+    let config = RpcClientConfig::with_commitment(commitment);
+    match limiter(&url) {
+        Some(limiter) => RpcClient::new_sender(LimitedSender::new(sender, limiter), config),
+        None => RpcClient::new_sender(sender, config),
+    }
+}
+
+#[tokio::test]
+async fn test_limiter_budget() {
+    use solana_rpc_client::mock_sender::MockSender;
+
+    assert_eq!(
+        parse_method_concurrency("getProgramAccounts=1, getMultipleAccounts=4").unwrap(),
+        HashMap::from([
+            ("getProgramAccounts".to_string(), 1),
+            ("getMultipleAccounts".to_string(), 4),
+        ])
+    );
+    assert!(parse_method_concurrency("getProgramAccounts").is_err());
+    assert!(parse_method_concurrency("getProgramAccounts=0").is_err());
+
+    // 突发额度用完后按速率排队
+    let limiter = Limiter::new(LimiterConfig {
+        rate: 10.0,
+        burst: 2.0,
+        ..Default::default()
+    });
+    let now = Instant::now();
+    assert_eq!(limiter.reserve(now), Duration::ZERO);
+    assert_eq!(limiter.reserve(now), Duration::ZERO);
+    assert_eq!(limiter.reserve(now), Duration::from_millis(100));
+    assert_eq!(limiter.reserve(now), Duration::from_millis(200));
+    let later = now + Duration::from_secs(1);
+    assert_eq!(limiter.reserve(later), Duration::ZERO);
+
+    // 429 后整个端点暂停，退避翻倍直到上限
+    assert_eq!(limiter.rate_limited(later), BASE_BACKOFF);
+    assert_eq!(limiter.reserve(later), BASE_BACKOFF);
+    assert_eq!(limiter.rate_limited(later), BASE_BACKOFF * 2);
+    for _ in 0..10 {
+        limiter.rate_limited(later);
+    }
+    assert_eq!(limiter.rate_limited(later), DEFAULT_MAX_BACKOFF);
+    limiter.succeeded();
+    assert_eq!(limiter.rate_limited(later), BASE_BACKOFF);
+
+    let limiter = Arc::new(Limiter::new(LimiterConfig::default()));
+    let client = RpcClient::new_sender(
+        LimitedSender::new(MockSender::new("succeeds"), limiter),
+        RpcClientConfig::default(),
+    );
+    assert!(client.get_slot().await.is_ok());
+    assert!(!is_rate_limited(&ClientError::from(std::io::Error::other(
+        "timeout"
+    ))));
+}
@@ -0,0 +1,5 @@
+pub mod cache;
+pub mod keepalive;
+pub mod limiter;
+pub mod multi;
+pub mod retry;
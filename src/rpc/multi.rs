@@ -0,0 +1,317 @@
+//! Several rpc endpoints used as one.
+//!
+//! [`MultiClient`] sends every transaction to all healthy endpoints at once
+//! and returns the first signature accepted, and serves reads from the
+//! healthy endpoint that answered fastest. A background health check polls
+//! each endpoint's slot; endpoints that error or fall more than
+//! `RPC_MAX_SLOT_LAG` slots (default 25) behind the best one are left out
+//! until they catch up.
+//!
+//! `RPC_URLS` lists the endpoints, comma separated, in addition to the
+//! configured `rpc_url`. Without it the bot uses the single `rpc_url` client.
+//! `RPC_HEALTH_CHECK_SECS` sets the health check interval (default 5).
+//!
+//! The health checks, and the keepalive requests when enabled, are recorded
+//! into each endpoint's round trip times, see [`crate::rpc::keepalive`];
+//! reads go to the healthy endpoint with the lowest moving average.
+
+use std::{
+    env,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use futures_util::future::{join_all, select_ok};
+use solana_client::{
+    client_error::ClientError, nonblocking::rpc_client::RpcClient,
+    rpc_config::RpcSendTransactionConfig,
+};
+use solana_sdk::{signature::Signature, transaction::Transaction};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::{
+    config::bot_config,
+    get_rpc_commitment, get_rpc_timeout,
+    rpc::{keepalive::RttStats, limiter::new_rpc_client},
+    tx::leader_tracker,
+};
+
+pub const DEFAULT_MAX_SLOT_LAG: u64 = 25;
+pub const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+static GLOBAL_MULTI_CLIENT: OnceLock<Arc<MultiClient>> = OnceLock::new();
+
+pub struct Endpoint {
+    pub client: Arc<RpcClient>,
+    healthy: AtomicBool,
+    rtt: RttStats,
+    last_slot: AtomicU64,
+}
+
+impl Endpoint {
+    fn new(client: Arc<RpcClient>) -> Self {
+        let rtt = RttStats::new(&client.url());
+        Self {
+            client,
+            healthy: AtomicBool::new(true),
+            rtt,
+            last_slot: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    pub fn last_slot(&self) -> u64 {
+        self.last_slot.load(Ordering::Relaxed)
+    }
+
+    /// Round trip times of the health checks and keepalive requests
+    pub fn rtt(&self) -> &RttStats {
+        &self.rtt
+    }
+}
+
+pub struct MultiClient {
+    endpoints: Vec<Endpoint>,
+    max_slot_lag: u64,
+}
+
+impl MultiClient {
+    pub fn new(clients: Vec<Arc<RpcClient>>, max_slot_lag: u64) -> Result<Self> {
+        if clients.is_empty() {
+            return Err(anyhow!("no rpc endpoints"));
+        }
+        Ok(Self {
+            endpoints: clients.into_iter().map(Endpoint::new).collect(),
+            max_slot_lag,
+        })
+    }
+
+    /// Uses `rpc_url` plus `RPC_URLS`, `None` if `RPC_URLS` is unset
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        let Ok(urls) = env::var("RPC_URLS") else {
+            return Ok(None);
+        };
+        let mut all = vec![bot_config().rpc_url.clone()];
+        for url in urls.split(',').map(str::trim).filter(|url| !url.is_empty()) {
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                return Err(anyhow!("RPC_URLS must be http(s) urls, got {:?}", url));
+            }
+            if !all.iter().any(|u| u == url) {
+                all.push(url.to_string());
+            }
+        }
+        let max_slot_lag = match env::var("RPC_MAX_SLOT_LAG") {
+            Ok(v) => v
+                .parse()
+                .map_err(|_| anyhow!("invalid RPC_MAX_SLOT_LAG {:?}", v))?,
+            Err(_) => DEFAULT_MAX_SLOT_LAG,
+        };
+        let clients = all
+            .into_iter()
+            .map(|url| Arc::new(new_rpc_client(url, get_rpc_timeout(), get_rpc_commitment())))
+            .collect();
+        Ok(Some(Self::new(clients, max_slot_lag)?))
+    }
+
+    pub fn endpoints(&self) -> &[Endpoint] {
+        &self.endpoints
+    }
+
+    fn healthy(&self) -> impl Iterator<Item = &Endpoint> {
+        self.endpoints
+            .iter()
+            .filter(|endpoint| endpoint.is_healthy())
+    }
+
+    /// Client of the fastest healthy endpoint on average, or the first one if
+    /// none is healthy
+    pub fn read_client(&self) -> Arc<RpcClient> {
+        self.healthy()
+            .min_by_key(|endpoint| endpoint.rtt.smoothed_us())
+            .unwrap_or(&self.endpoints[0])
+            .client
+            .clone()
+    }
+
+    /// Polls every endpoint's slot and updates which ones are healthy
+    pub async fn check_health(&self) {
+        let results = join_all(self.endpoints.iter().map(|endpoint| async move {
+            let start = Instant::now();
+            endpoint
+                .client
+                .get_slot()
+                .await
+                .map(|slot| (slot, start.elapsed()))
+        }))
+        .await;
+        self.update_health(results);
+    }
+
+    fn update_health(&self, results: Vec<Result<(u64, Duration), ClientError>>) {
+        let best_slot = results
+            .iter()
+            .filter_map(|res| res.as_ref().ok().map(|(slot, _)| *slot))
+            .max()
+            .unwrap_or(0);
+        for (endpoint, res) in self.endpoints.iter().zip(results) {
+            let url = endpoint.client.url();
+            let healthy = match res {
+                Ok((slot, latency)) => {
+                    endpoint.last_slot.store(slot, Ordering::Relaxed);
+                    endpoint.rtt.record(latency);
+                    let lag = best_slot - slot;
+                    if lag > self.max_slot_lag {
+                        warn!("rpc {} is {} slots behind", url, lag);
+                    }
+                    lag <= self.max_slot_lag
+                }
+                Err(e) => {
+                    endpoint.rtt.record_failure();
+                    warn!("rpc {} health check failed {:?}", url, e);
+                    false
+                }
+            };
+            if endpoint.healthy.swap(healthy, Ordering::Relaxed) != healthy {
+                info!(
+                    "rpc {} is now {}",
+                    url,
+                    if healthy { "healthy" } else { "unhealthy" }
+                );
+            }
+        }
+    }
+
+    /// Spawns the task checking the endpoints every `interval`
+    pub fn spawn_health_check(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                self.check_health().await;
+            }
+        })
+    }
+
+    /// Sends `txn` to every healthy endpoint, returning the first signature
+    /// accepted, or the last error if all of them failed
+    pub async fn send_transaction(
+        &self,
+        txn: &Transaction,
+        config: RpcSendTransactionConfig,
+    ) -> Result<Signature, ClientError> {
+        let mut endpoints: Vec<&Endpoint> = self.healthy().collect();
+        if endpoints.is_empty() {
+            endpoints = self.endpoints.iter().collect();
+        }
+        let sends = endpoints
+            .into_iter()
+            .map(|endpoint| Box::pin(endpoint.client.send_transaction_with_config(txn, config)));
+        select_ok(sends).await.map(|(signature, _)| signature)
+    }
+}
+
+/// Starts the health check and installs the multi client process wide if
+/// `RPC_URLS` is set
+pub async fn start_from_env() -> Result<Option<JoinHandle<()>>> {
+    let Some(multi) = MultiClient::from_env()? else {
+        return Ok(None);
+    };
+    let interval = match env::var("RPC_HEALTH_CHECK_SECS") {
+        Ok(v) => Duration::from_secs(
+            v.parse()
+                .map_err(|_| anyhow!("invalid RPC_HEALTH_CHECK_SECS {:?}", v))?,
+        ),
+        Err(_) => DEFAULT_HEALTH_CHECK_INTERVAL,
+    };
+    let multi = Arc::new(multi);
+    multi.check_health().await;
+    GLOBAL_MULTI_CLIENT
+        .set(multi.clone())
+        .map_err(|_| anyhow!("multi rpc client already started"))?;
+    info!("using {} rpc endpoints", multi.endpoints.len());
+    Ok(Some(multi.spawn_health_check(interval)))
+}
+
+/// Returns the process wide multi client, if one was started
+pub fn global_multi_client() -> Option<&'static Arc<MultiClient>> {
+    GLOBAL_MULTI_CLIENT.get()
+}
+
+/// Sends `txn` through the multi client if one was started, through
+/// `client` otherwise
+///
+/// With the leader tracker started, `txn` also goes to the upcoming leaders
+/// in the background, see [`leader_tracker`].
+pub async fn send_transaction(
+    client: &RpcClient,
+    txn: &Transaction,
+    config: RpcSendTransactionConfig,
+) -> Result<Signature, ClientError> {
+    leader_tracker::forward(txn);
+    match global_multi_client() {
+        Some(multi) => multi.send_transaction(txn, config).await,
+        None => client.send_transaction_with_config(txn, config).await,
+    }
+}
+
+#[tokio::test]
+async fn test_multi_client_health_and_racing() {
+    use solana_client::rpc_request::RpcError;
+    use solana_sdk::{hash::Hash, signature::Keypair, signer::Signer, system_instruction};
+
+    let mock = |url: &str| Arc::new(RpcClient::new_mock(url.to_string()));
+    let multi =
+        MultiClient::new(vec![mock("fails"), mock("succeeds"), mock("succeeds")], 10).unwrap();
+
+    multi.update_health(vec![
+        Ok((1000, Duration::from_millis(50))),
+        Ok((995, Duration::from_millis(5))),
+        Ok((980, Duration::from_millis(1))),
+    ]);
+    // 落后太多的节点被排除，读取用最快的健康节点
+    assert!(multi.endpoints[0].is_healthy());
+    assert!(multi.endpoints[1].is_healthy());
+    assert!(!multi.endpoints[2].is_healthy());
+    assert!(Arc::ptr_eq(
+        &multi.read_client(),
+        &multi.endpoints[1].client
+    ));
+
+    multi.update_health(vec![
+        Ok((1000, Duration::from_millis(50))),
+        Err(RpcError::ForUser("down".to_string()).into()),
+        Ok((1000, Duration::from_millis(1))),
+    ]);
+    assert!(!multi.endpoints[1].is_healthy());
+    assert!(Arc::ptr_eq(
+        &multi.read_client(),
+        &multi.endpoints[2].client
+    ));
+
+    // 一个节点失败时仍然发送成功
+    let payer = Keypair::new();
+    let txn = Transaction::new_signed_with_payer(
+        &[system_instruction::transfer(
+            &payer.pubkey(),
+            &payer.pubkey(),
+            1,
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        Hash::default(),
+    );
+    let config = RpcSendTransactionConfig::default();
+    assert!(multi.send_transaction(&txn, config).await.is_ok());
+
+    let failing = MultiClient::new(vec![mock("fails")], 10).unwrap();
+    assert!(failing.send_transaction(&txn, config).await.is_err());
+}
@@ -0,0 +1,89 @@
+//! Bounded retry for transient rpc failures.
+//!
+//! Public nodes regularly rate-limit or time out; those errors are retried
+//! with a doubling backoff, anything else (including a valid "not found"
+//! answer) is returned to the caller right away.
+
+use std::{env, future::Future, time::Duration};
+
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    rpc_custom_error::{
+        JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED, JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+    },
+    rpc_request::RpcError,
+};
+use tracing::warn;
+
+/// Attempts made when `RPC_RETRY_ATTEMPTS` is unset
+pub const DEFAULT_RETRY_ATTEMPTS: u32 = 3;
+
+/// Backoff before the first retry, doubled after each attempt
+const BASE_BACKOFF: Duration = Duration::from_millis(100);
+
+fn get_retry_attempts() -> u32 {
+    env::var("RPC_RETRY_ATTEMPTS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_RETRY_ATTEMPTS)
+        .max(1)
+}
+
+/// Whether `err` is worth retrying
+pub fn is_transient(err: &ClientError) -> bool {
+    match err.kind() {
+        ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) => true,
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => matches!(
+            *code,
+            JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY | JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED
+        ),
+        _ => false,
+    }
+}
+
+/// Runs `call` until it succeeds, fails with a non transient error, or
+/// `RPC_RETRY_ATTEMPTS` attempts have been made
+pub async fn with_retry<T, F, Fut>(mut call: F) -> Result<T, ClientError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, ClientError>>,
+{
+    let attempts = get_retry_attempts();
+    let mut backoff = BASE_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match call().await {
+            Err(e) if attempt < attempts && is_transient(&e) => {
+                warn!("rpc attempt {}/{} failed, retrying {:?}", attempt, attempts, e);
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_with_retry_only_retries_transient_errors() {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    let calls = AtomicU32::new(0);
+    let res = with_retry(|| async {
+        match calls.fetch_add(1, Ordering::SeqCst) {
+            0 => Err(ClientError::from(std::io::Error::other("timeout"))),
+            n => Ok(n),
+        }
+    })
+    .await;
+    assert_eq!(res.unwrap(), 1);
+
+    calls.store(0, Ordering::SeqCst);
+    let res: Result<(), _> = with_retry(|| async {
+        calls.fetch_add(1, Ordering::SeqCst);
+        Err(ClientError::from(RpcError::ForUser("AccountNotFound".to_string())))
+    })
+    .await;
+    assert!(res.is_err());
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
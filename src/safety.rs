@@ -0,0 +1,501 @@
+//! Rug-pull checks of a token before buying it.
+//!
+//! [`check`] scores a mint out of 100, every issue found takes its penalty
+//! off the score:
+//!
+//! - mint or freeze authority not revoked
+//! - Token-2022 transfer fee, permanent delegate, transfer hook or
+//!   non-transferable extensions
+//! - the largest holders owning too much of the supply, the bonding curve or
+//!   pool vault excluded, see [`crate::analysis::holders`]
+//! - the creator wallet owning too much of the supply, or too few holders,
+//!   when configured
+//! - too little of the pool's LP burned, once the curve has migrated
+//! - a creator wallet younger than the configured age, when the creator is known
+//! - a honeypot flag or too little liquidity reported by the
+//!   [`crate::datasources`], when asked to consult them
+//!
+//! The sniper and copy-trade strategies skip buys scoring below the minimum
+//! when `SAFETY_CHECKS_ENABLED=true`.
+//!
+//! - `SAFETY_MIN_SCORE`: lowest score bought (default 70)
+//! - `SAFETY_MAX_TOP_HOLDERS_PCT`: share of supply the top 10 holders may own (default 30)
+//! - `SAFETY_MAX_DEV_PCT`: share of supply the creator may own, unchecked by default
+//! - `SAFETY_MIN_HOLDERS`: fewest holders accepted, unchecked by default as
+//!   counting them is a `getProgramAccounts` request
+//! - `SAFETY_MIN_LP_BURNED_PCT`: share of LP that must be burned (default 90)
+//! - `SAFETY_MIN_CREATOR_AGE_HOURS`: youngest creator wallet accepted (default 24)
+//! - `SAFETY_DATASOURCES`: also consult the datasources, off by default; a
+//!   lookup failing adds no issue
+//! - `SAFETY_MIN_LIQUIDITY_USD`: lowest liquidity the datasources may report
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient, rpc_client::GetConfirmedSignaturesForAddress2Config,
+};
+use solana_sdk::pubkey::Pubkey;
+use spl_associated_token_account::get_associated_token_address;
+use spl_token_2022::{
+    extension::{
+        transfer_fee::TransferFeeConfig, BaseStateWithExtensions, ExtensionType,
+        StateWithExtensions,
+    },
+    state::Mint,
+};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::{
+    analysis::holders::{self, HolderStats, TOP_HOLDERS},
+    datasources::{self, TokenInfo},
+    pumpfun::utils::{get_bonding_curve_account, get_bonding_curve_pda},
+    pumpswap,
+    raydium::{self, getter::get_pool_state},
+    strategy::parse_env,
+};
+
+const DEFAULT_MIN_SCORE: u8 = 70;
+const DEFAULT_MAX_TOP_HOLDERS_PCT: f64 = 30.0;
+const DEFAULT_MIN_LP_BURNED_PCT: f64 = 90.0;
+const DEFAULT_MIN_CREATOR_AGE: Duration = Duration::from_secs(24 * 3600);
+
+/// Signatures fetched to date the creator wallet
+const CREATOR_HISTORY_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum SafetyIssue {
+    #[error("mint authority {0} not revoked")]
+    MintAuthority(Pubkey),
+    #[error("freeze authority {0} not revoked")]
+    FreezeAuthority(Pubkey),
+    #[error("transfer fee of {0} bps")]
+    TransferFee(u16),
+    #[error("{0:?} extension")]
+    Extension(ExtensionType),
+    #[error("top {TOP_HOLDERS} holders own {pct:.1}% of supply, above {max}%")]
+    TopHolders { pct: f64, max: f64 },
+    #[error("creator owns {pct:.1}% of supply, above {max}%")]
+    DevShare { pct: f64, max: f64 },
+    #[error("only {holders} holders, below {min}")]
+    FewHolders { holders: u64, min: u64 },
+    #[error("only {pct:.1}% of LP burned, below {min}%")]
+    LpNotBurned { pct: f64, min: f64 },
+    #[error("creator wallet is only {age:?} old")]
+    NewCreator { age: Duration },
+    #[error("flagged as honeypot")]
+    Honeypot,
+    #[error("liquidity of ${usd:.0}, below ${min:.0}")]
+    LowLiquidity { usd: f64, min: f64 },
+}
+
+impl SafetyIssue {
+    /// Points the issue takes off the score
+    pub fn penalty(&self) -> u8 {
+        match self {
+            SafetyIssue::MintAuthority(_) | SafetyIssue::FreezeAuthority(_) => 40,
+            SafetyIssue::Extension(_) => 40,
+            SafetyIssue::TransferFee(_) => 20,
+            SafetyIssue::TopHolders { .. } | SafetyIssue::LpNotBurned { .. } => 20,
+            SafetyIssue::DevShare { .. } => 20,
+            SafetyIssue::FewHolders { .. } => 10,
+            SafetyIssue::NewCreator { .. } => 10,
+            SafetyIssue::Honeypot => 100,
+            SafetyIssue::LowLiquidity { .. } => 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SafetyReport {
+    pub mint: Pubkey,
+    /// 100 minus the penalties of the issues, at least 0
+    pub score: u8,
+    pub issues: Vec<SafetyIssue>,
+    /// Share of supply owned by the top holders, in percent
+    pub top_holders_pct: Option<f64>,
+    /// Holders with a balance, `None` unless counted
+    pub holders: Option<u64>,
+    /// Share of supply owned by the creator, in percent, `None` if unknown
+    pub dev_pct: Option<f64>,
+    /// Share of LP burned, in percent, `None` while on the bonding curve
+    pub lp_burned_pct: Option<f64>,
+    pub creator_age: Option<Duration>,
+}
+
+impl SafetyReport {
+    pub fn new(mint: Pubkey, issues: Vec<SafetyIssue>) -> Self {
+        let penalty: u32 = issues.iter().map(|issue| issue.penalty() as u32).sum();
+        Self {
+            mint,
+            score: 100u32.saturating_sub(penalty) as u8,
+            issues,
+            top_holders_pct: None,
+            holders: None,
+            dev_pct: None,
+            lp_burned_pct: None,
+            creator_age: None,
+        }
+    }
+}
+
+impl fmt::Display for SafetyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} scored {}/100", self.mint, self.score)?;
+        for issue in &self.issues {
+            write!(f, ", {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SafetyConfig {
+    pub min_score: u8,
+    pub max_top_holders_pct: f64,
+    /// Largest share of supply the creator may own, in percent
+    pub max_dev_pct: Option<f64>,
+    /// Fewest holders accepted, holders are only counted when set
+    pub min_holders: Option<u64>,
+    pub min_lp_burned_pct: f64,
+    pub min_creator_age: Duration,
+    /// Whether the datasources are consulted too
+    pub datasources: bool,
+    /// Lowest liquidity the datasources may report, in USD
+    pub min_liquidity_usd: Option<f64>,
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        Self {
+            min_score: DEFAULT_MIN_SCORE,
+            max_top_holders_pct: DEFAULT_MAX_TOP_HOLDERS_PCT,
+            max_dev_pct: None,
+            min_holders: None,
+            min_lp_burned_pct: DEFAULT_MIN_LP_BURNED_PCT,
+            min_creator_age: DEFAULT_MIN_CREATOR_AGE,
+            datasources: false,
+            min_liquidity_usd: None,
+        }
+    }
+}
+
+impl SafetyConfig {
+    /// Reads the `SAFETY_*` variables, `None` unless `SAFETY_CHECKS_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("SAFETY_CHECKS_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        let default = Self::default();
+        Ok(Some(Self {
+            min_score: parse_env("SAFETY_MIN_SCORE")?.unwrap_or(default.min_score),
+            max_top_holders_pct: parse_env("SAFETY_MAX_TOP_HOLDERS_PCT")?
+                .unwrap_or(default.max_top_holders_pct),
+            max_dev_pct: parse_env("SAFETY_MAX_DEV_PCT")?,
+            min_holders: parse_env("SAFETY_MIN_HOLDERS")?,
+            min_lp_burned_pct: parse_env("SAFETY_MIN_LP_BURNED_PCT")?
+                .unwrap_or(default.min_lp_burned_pct),
+            min_creator_age: parse_env::<f64>("SAFETY_MIN_CREATOR_AGE_HOURS")?
+                .map(|hours| Duration::from_secs_f64(hours * 3600.0))
+                .unwrap_or(default.min_creator_age),
+            datasources: parse_env("SAFETY_DATASOURCES")?.unwrap_or(default.datasources),
+            min_liquidity_usd: parse_env("SAFETY_MIN_LIQUIDITY_USD")?,
+        }))
+    }
+
+    /// Whether `report` scores high enough to buy
+    pub fn passes(&self, report: &SafetyReport) -> bool {
+        report.score >= self.min_score
+    }
+}
+
+/// Issues of the mint account itself, and its supply
+pub fn check_mint(data: &[u8]) -> Result<(u64, Vec<SafetyIssue>)> {
+    let mint = StateWithExtensions::<Mint>::unpack(data)?;
+    let mut issues = vec![];
+    if let Some(authority) = Option::<Pubkey>::from(mint.base.mint_authority) {
+        issues.push(SafetyIssue::MintAuthority(authority));
+    }
+    if let Some(authority) = Option::<Pubkey>::from(mint.base.freeze_authority) {
+        issues.push(SafetyIssue::FreezeAuthority(authority));
+    }
+    for extension in mint.get_extension_types()? {
+        match extension {
+            ExtensionType::TransferFeeConfig => {
+                let config = mint.get_extension::<TransferFeeConfig>()?;
+                let bps = u16::from(config.newer_transfer_fee.transfer_fee_basis_points);
+                if bps > 0 {
+                    issues.push(SafetyIssue::TransferFee(bps));
+                }
+            }
+            // 可以转走、冻结或拦截持有者的代币
+            ExtensionType::PermanentDelegate
+            | ExtensionType::TransferHook
+            | ExtensionType::NonTransferable => issues.push(SafetyIssue::Extension(extension)),
+            _ => {}
+        }
+    }
+    Ok((mint.base.supply, issues))
+}
+
+/// Where the token's liquidity sits
+enum Liquidity {
+    /// Still on the Pump.fun bonding curve
+    Curve { vault: Pubkey },
+    /// In an AMM pool, with the share of LP burned in percent
+    Pool { vault: Pubkey, lp_burned_pct: f64 },
+    /// No curve or pool found
+    Unknown,
+}
+
+fn burned_pct(lp_supply: u64, lp_minted: u64) -> f64 {
+    if lp_minted == 0 {
+        return 0.0;
+    }
+    (1.0 - lp_supply as f64 / lp_minted as f64).clamp(0.0, 1.0) * 100.0
+}
+
+async fn liquidity(client: &Arc<RpcClient>, mint: &Pubkey) -> Result<Liquidity> {
+    if let Ok(curve) = get_bonding_curve_account(client.clone(), mint).await {
+        if !curve.complete {
+            let curve_pda = get_bonding_curve_pda(mint).unwrap_or_default();
+            return Ok(Liquidity::Curve {
+                vault: get_associated_token_address(&curve_pda, mint),
+            });
+        }
+    }
+
+    // 池子记录的是已发行的LP，销毁的部分不再计入mint供应量
+    if let Ok((_, pool)) = pumpswap::pools::find_sol_pool(client.clone(), mint).await {
+        let supply = client.get_token_supply(&pool.lp_mint).await?;
+        return Ok(Liquidity::Pool {
+            vault: pool.pool_base_token_account,
+            lp_burned_pct: burned_pct(supply.amount.parse()?, pool.lp_supply),
+        });
+    }
+    if let Ok(pool_id) = raydium::pools::find_sol_pool(client.clone(), mint).await {
+        let (_, pool) = get_pool_state(client.clone(), &pool_id.to_string()).await?;
+        let supply = client.get_token_supply(&pool.lp_mint).await?;
+        let vault = if pool.coin_vault_mint == *mint {
+            pool.coin_vault
+        } else {
+            pool.pc_vault
+        };
+        return Ok(Liquidity::Pool {
+            vault,
+            lp_burned_pct: burned_pct(supply.amount.parse()?, pool.lp_amount),
+        });
+    }
+    Ok(Liquidity::Unknown)
+}
+
+/// Issues of the holder distribution
+pub fn check_holders(config: &SafetyConfig, stats: &HolderStats) -> Vec<SafetyIssue> {
+    let mut issues = vec![];
+    if stats.top_holders_pct > config.max_top_holders_pct {
+        issues.push(SafetyIssue::TopHolders {
+            pct: stats.top_holders_pct,
+            max: config.max_top_holders_pct,
+        });
+    }
+    if let (Some(pct), Some(max)) = (stats.dev_pct, config.max_dev_pct) {
+        if pct > max {
+            issues.push(SafetyIssue::DevShare { pct, max });
+        }
+    }
+    if let (Some(holders), Some(min)) = (stats.holders, config.min_holders) {
+        if holders < min {
+            issues.push(SafetyIssue::FewHolders { holders, min });
+        }
+    }
+    issues
+}
+
+/// Liquidity vault of `mint`, the bonding curve's or the pool's, left out of
+/// the holders
+pub async fn liquidity_vault(client: &Arc<RpcClient>, mint: &Pubkey) -> Result<Option<Pubkey>> {
+    Ok(match liquidity(client, mint).await? {
+        Liquidity::Curve { vault } | Liquidity::Pool { vault, .. } => Some(vault),
+        Liquidity::Unknown => None,
+    })
+}
+
+/// Issues of what the datasources report on the token
+pub fn check_token_info(config: &SafetyConfig, info: &TokenInfo) -> Vec<SafetyIssue> {
+    let mut issues = vec![];
+    if info.security.honeypot == Some(true) {
+        issues.push(SafetyIssue::Honeypot);
+    }
+    if let (Some(usd), Some(min)) = (info.liquidity_usd, config.min_liquidity_usd) {
+        if usd < min {
+            issues.push(SafetyIssue::LowLiquidity { usd, min });
+        }
+    }
+    issues
+}
+
+/// Age of `wallet`'s oldest transaction among its latest ones
+async fn wallet_age(client: &RpcClient, wallet: &Pubkey) -> Result<Option<Duration>> {
+    let config = GetConfirmedSignaturesForAddress2Config {
+        limit: Some(CREATOR_HISTORY_LIMIT),
+        ..GetConfirmedSignaturesForAddress2Config::default()
+    };
+    let signatures = client
+        .get_signatures_for_address_with_config(wallet, config)
+        .await?;
+    let Some(oldest) = signatures.last().and_then(|signature| signature.block_time) else {
+        return Ok(None);
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    Ok(Some(Duration::from_secs((now - oldest).max(0) as u64)))
+}
+
+/// Runs every check on `mint`, dating `creator` when it's known
+pub async fn check(
+    client: Arc<RpcClient>,
+    config: &SafetyConfig,
+    mint: &Pubkey,
+    creator: Option<&Pubkey>,
+) -> Result<SafetyReport> {
+    let account = client.get_account(mint).await?;
+    let (_, mut issues) = check_mint(&account.data)?;
+
+    let (vault, lp_burned_pct) = match liquidity(&client, mint).await? {
+        Liquidity::Curve { vault } => (Some(vault), None),
+        Liquidity::Pool {
+            vault,
+            lp_burned_pct,
+        } => (Some(vault), Some(lp_burned_pct)),
+        Liquidity::Unknown => (None, None),
+    };
+    if let Some(pct) = lp_burned_pct {
+        if pct < config.min_lp_burned_pct {
+            issues.push(SafetyIssue::LpNotBurned {
+                pct,
+                min: config.min_lp_burned_pct,
+            });
+        }
+    }
+
+    let stats =
+        holders::analyze(&client, mint, vault, creator, config.min_holders.is_some()).await?;
+    issues.extend(check_holders(config, &stats));
+
+    let creator_age = match creator {
+        Some(creator) => wallet_age(&client, creator).await?,
+        None => None,
+    };
+    if let Some(age) = creator_age {
+        if age < config.min_creator_age {
+            issues.push(SafetyIssue::NewCreator { age });
+        }
+    }
+
+    if config.datasources {
+        match datasources::token_info(mint).await {
+            Ok(info) => issues.extend(check_token_info(config, &info)),
+            Err(e) => warn!("no datasource checks of {} {:?}", mint, e),
+        }
+    }
+
+    let mut report = SafetyReport::new(*mint, issues);
+    report.top_holders_pct = Some(stats.top_holders_pct);
+    report.holders = stats.holders;
+    report.dev_pct = stats.dev_pct;
+    report.lp_burned_pct = lp_burned_pct;
+    report.creator_age = creator_age;
+    Ok(report)
+}
+
+#[test]
+fn test_check_mint_and_score() {
+    use solana_sdk::{program_option::COption, program_pack::Pack};
+
+    let authority = Pubkey::new_unique();
+    let mut mint = spl_token::state::Mint {
+        mint_authority: COption::Some(authority),
+        supply: 1_000_000,
+        decimals: 6,
+        is_initialized: true,
+        freeze_authority: COption::None,
+    };
+    let mut data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint::pack(mint, &mut data).unwrap();
+    let (supply, issues) = check_mint(&data).unwrap();
+    assert_eq!(supply, 1_000_000);
+    assert_eq!(issues, vec![SafetyIssue::MintAuthority(authority)]);
+
+    mint.mint_authority = COption::None;
+    spl_token::state::Mint::pack(mint, &mut data).unwrap();
+    assert!(check_mint(&data).unwrap().1.is_empty());
+
+    let report = SafetyReport::new(
+        Pubkey::new_unique(),
+        vec![
+            SafetyIssue::MintAuthority(authority),
+            SafetyIssue::TopHolders {
+                pct: 50.0,
+                max: 30.0,
+            },
+        ],
+    );
+    assert_eq!(report.score, 40);
+    assert!(!SafetyConfig::default().passes(&report));
+    assert_eq!(burned_pct(0, 100), 100.0);
+
+    let config = SafetyConfig {
+        min_liquidity_usd: Some(10_000.0),
+        ..SafetyConfig::default()
+    };
+    let mut info = TokenInfo {
+        liquidity_usd: Some(2_000.0),
+        ..TokenInfo::default()
+    };
+    assert_eq!(
+        check_token_info(&config, &info),
+        vec![SafetyIssue::LowLiquidity {
+            usd: 2_000.0,
+            min: 10_000.0
+        }]
+    );
+    info.security.honeypot = Some(true);
+    let issues = check_token_info(&SafetyConfig::default(), &info);
+    assert_eq!(SafetyReport::new(Pubkey::new_unique(), issues).score, 0);
+    assert_eq!(burned_pct(25, 100), 75.0);
+
+    // 没配置的持有者检查不算问题
+    let stats = HolderStats {
+        top_holders_pct: 20.0,
+        holders: Some(40),
+        dev_pct: Some(15.0),
+        ..HolderStats::default()
+    };
+    assert!(check_holders(&SafetyConfig::default(), &stats).is_empty());
+    let config = SafetyConfig {
+        max_dev_pct: Some(10.0),
+        min_holders: Some(50),
+        ..SafetyConfig::default()
+    };
+    assert_eq!(
+        check_holders(&config, &stats),
+        vec![
+            SafetyIssue::DevShare {
+                pct: 15.0,
+                max: 10.0
+            },
+            SafetyIssue::FewHolders {
+                holders: 40,
+                min: 50
+            },
+        ]
+    );
+}
@@ -0,0 +1,734 @@
+//! History of detections, transactions and fills in SQLite.
+//!
+//! With `STORAGE_ENABLED=true`, [`record_events`] stores the creates and
+//! migrations detected and the outcome of the transactions sent, and the
+//! portfolio stores its fills here instead of `PORTFOLIO_PATH`, replaying
+//! them on startup. The database can be queried directly for analysis, and
+//! `/stats` reports from it. Closed candles are stored too with
+//! `MARKETDATA_PERSIST=true`, see [`crate::marketdata`]. The tweets the
+//! twitter strategy processed are kept here as well, see
+//! [`crate::monitor::twitter::seen`].
+//!
+//! The fills can be exported as a CSV or JSON trade journal with
+//! [`write_journal`], from the `export` command or Telegram's `/export`.
+//!
+//! - `STORAGE_PATH`: database file, default `bot.sqlite`
+
+use std::{
+    env, fmt,
+    path::Path,
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use solana_sdk::{native_token::lamports_to_sol, pubkey::Pubkey};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, warn};
+
+use crate::{
+    marketdata::{Candle, Resolution},
+    monitor::events::{self, MigrationEvent, MonitorEvent, TokenCreateEvent},
+    portfolio::{Fill, Side},
+    strategy::parse_env,
+};
+
+const DEFAULT_STORAGE_PATH: &str = "bot.sqlite";
+
+static GLOBAL_STORAGE: OnceLock<Option<Storage>> = OnceLock::new();
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS creates (
+    mint TEXT PRIMARY KEY,
+    signature TEXT NOT NULL,
+    name TEXT NOT NULL,
+    symbol TEXT NOT NULL,
+    uri TEXT NOT NULL,
+    creator TEXT NOT NULL,
+    dev_buy_lamports INTEGER,
+    detected_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS migrations (
+    pool TEXT PRIMARY KEY,
+    signature TEXT NOT NULL,
+    venue TEXT NOT NULL,
+    coin_token TEXT NOT NULL,
+    pc_token TEXT NOT NULL,
+    detected_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS transactions (
+    signature TEXT PRIMARY KEY,
+    status TEXT NOT NULL,
+    slot INTEGER,
+    error TEXT,
+    attempts INTEGER,
+    sent_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS fills (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    mint TEXT NOT NULL,
+    side TEXT NOT NULL,
+    venue TEXT NOT NULL,
+    strategy TEXT NOT NULL,
+    token_amount INTEGER NOT NULL,
+    sol_amount INTEGER NOT NULL,
+    fee INTEGER NOT NULL,
+    signature TEXT NOT NULL,
+    timestamp INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS candles (
+    mint TEXT NOT NULL,
+    resolution TEXT NOT NULL,
+    start INTEGER NOT NULL,
+    open REAL NOT NULL,
+    high REAL NOT NULL,
+    low REAL NOT NULL,
+    close REAL NOT NULL,
+    volume INTEGER NOT NULL,
+    trades INTEGER NOT NULL,
+    PRIMARY KEY (mint, resolution, start)
+);
+CREATE TABLE IF NOT EXISTS tweet_cursors (
+    user_id INTEGER PRIMARY KEY,
+    since_id INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS seen_tweets (
+    id INTEGER PRIMARY KEY,
+    seen_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS creates_detected_at ON creates (detected_at);
+CREATE INDEX IF NOT EXISTS fills_timestamp ON fills (timestamp);
+";
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::Buy => "buy",
+        Side::Sell => "sell",
+    }
+}
+
+/// Counts over a period
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub creates: u64,
+    pub migrations: u64,
+    pub tx_landed: u64,
+    pub tx_failed: u64,
+    pub buys: u64,
+    pub sells: u64,
+    /// SOL traded, in lamports
+    pub volume: u64,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} creates, {} migrations, {} txs landed, {} failed, {} buys, {} sells, volume {:.4} SOL",
+            self.creates,
+            self.migrations,
+            self.tx_landed,
+            self.tx_failed,
+            self.buys,
+            self.sells,
+            lamports_to_sol(self.volume)
+        )
+    }
+}
+
+pub struct Storage {
+    conn: Mutex<Connection>,
+}
+
+impl Storage {
+    fn new(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Opens the database at `path`, creating the tables if needed
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let conn = Connection::open(path)
+            .map_err(|e| anyhow!("failed to open {}: {}", path.display(), e))?;
+        Self::new(conn)
+    }
+
+    pub fn in_memory() -> Result<Self> {
+        Self::new(Connection::open_in_memory()?)
+    }
+
+    /// Uses `STORAGE_PATH`, `None` unless `STORAGE_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("STORAGE_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        let path = env::var("STORAGE_PATH").unwrap_or_else(|_| DEFAULT_STORAGE_PATH.to_string());
+        Self::open(path).map(Some)
+    }
+
+    pub fn insert_create(&self, event: &TokenCreateEvent) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR IGNORE INTO creates
+             (mint, signature, name, symbol, uri, creator, dev_buy_lamports, detected_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                event.mint,
+                event.signature,
+                event.name,
+                event.symbol,
+                event.uri,
+                event.creator,
+                event
+                    .dev_buy
+                    .as_ref()
+                    .map(|dev_buy| dev_buy.sol_cost as i64),
+                now()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn insert_migration(&self, event: &MigrationEvent) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR IGNORE INTO migrations
+             (pool, signature, venue, coin_token, pc_token, detected_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                event.pool_id,
+                event.signature,
+                event.venue.to_string(),
+                event.coin_mint,
+                event.pc_mint,
+                now()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Records `signature` with `status`, keeping the send time of a known one
+    fn upsert_transaction(
+        &self,
+        signature: &str,
+        status: &str,
+        slot: Option<u64>,
+        error: Option<&str>,
+        attempts: Option<u32>,
+    ) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO transactions (signature, status, slot, error, attempts, sent_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)
+             ON CONFLICT (signature) DO UPDATE SET
+                status = excluded.status,
+                slot = COALESCE(excluded.slot, slot),
+                error = COALESCE(excluded.error, error),
+                attempts = COALESCE(excluded.attempts, attempts),
+                updated_at = excluded.updated_at",
+            params![
+                signature,
+                status,
+                slot.map(|slot| slot as i64),
+                error,
+                attempts,
+                now()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Stores the monitor and transaction events, ignoring the others
+    pub fn record_event(&self, event: &MonitorEvent) -> Result<()> {
+        match event {
+            MonitorEvent::Create(event) => self.insert_create(event),
+            MonitorEvent::Migration(event) => self.insert_migration(event),
+            MonitorEvent::TxSent(event) => {
+                self.upsert_transaction(&event.signature, "sent", None, None, None)
+            }
+            MonitorEvent::TxLanded(event) => self.upsert_transaction(
+                &event.signature,
+                "landed",
+                Some(event.slot),
+                None,
+                Some(event.attempts),
+            ),
+            MonitorEvent::TxFailed(event) => self.upsert_transaction(
+                &event.signature,
+                "failed",
+                None,
+                Some(&event.error),
+                Some(event.attempts),
+            ),
+            MonitorEvent::PendingSwap(_)
+            | MonitorEvent::CurveProgress(_)
+            | MonitorEvent::CurveComplete(_)
+            | MonitorEvent::PumpTrade(_)
+            | MonitorEvent::Signal(_)
+            | MonitorEvent::Frozen(_) => Ok(()),
+        }
+    }
+
+    pub fn insert_fill(&self, fill: &Fill) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO fills
+             (mint, side, venue, strategy, token_amount, sol_amount, fee, signature, timestamp)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                fill.mint,
+                side_name(fill.side),
+                fill.venue,
+                fill.strategy,
+                fill.token_amount as i64,
+                fill.sol_amount as i64,
+                fill.fee as i64,
+                fill.signature,
+                fill.timestamp as i64
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Stores a closed candle, replacing an earlier version of it
+    pub fn insert_candle(
+        &self,
+        mint: &Pubkey,
+        resolution: Resolution,
+        candle: &Candle,
+    ) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO candles
+             (mint, resolution, start, open, high, low, close, volume, trades)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                mint.to_string(),
+                resolution.to_string(),
+                candle.start as i64,
+                candle.open,
+                candle.high,
+                candle.low,
+                candle.close,
+                candle.volume as i64,
+                candle.trades
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Records tweet `id` as processed and `since_id` as the newest tweet of
+    /// its author, returning `false` if it already was
+    pub fn insert_seen_tweet(&self, id: u64, author: Option<u64>) -> Result<bool> {
+        let mut conn = self.conn.lock().unwrap();
+        let db = conn.transaction()?;
+        let inserted = db.execute(
+            "INSERT OR IGNORE INTO seen_tweets (id, seen_at) VALUES (?1, ?2)",
+            params![id as i64, now()],
+        )? == 1;
+        if let Some(author) = author {
+            db.execute(
+                "INSERT INTO tweet_cursors (user_id, since_id) VALUES (?1, ?2)
+                 ON CONFLICT (user_id) DO UPDATE SET since_id = MAX(since_id, excluded.since_id)",
+                params![author as i64, id as i64],
+            )?;
+        }
+        db.commit()?;
+        Ok(inserted)
+    }
+
+    /// The newest `limit` processed tweet ids
+    pub fn seen_tweets(&self, limit: usize) -> Result<Vec<u64>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare("SELECT id FROM seen_tweets ORDER BY id DESC LIMIT ?1")?;
+        let ids = statement
+            .query_map([limit as i64], |row| Ok(row.get::<_, i64>(0)? as u64))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(ids)
+    }
+
+    /// Newest processed tweet id of each user
+    pub fn tweet_cursors(&self) -> Result<Vec<(u64, u64)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare("SELECT user_id, since_id FROM tweet_cursors")?;
+        let cursors = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(cursors)
+    }
+
+    /// Every fill, in the order recorded
+    pub fn fills(&self) -> Result<Vec<Fill>> {
+        self.fills_since(0)
+    }
+
+    /// Fills since the unix timestamp `since`, in the order recorded
+    pub fn fills_since(&self, since: u64) -> Result<Vec<Fill>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(
+            "SELECT mint, side, venue, strategy, token_amount, sol_amount, fee, signature, timestamp
+             FROM fills WHERE timestamp >= ?1 ORDER BY id",
+        )?;
+        let fills = statement
+            .query_map([since as i64], |row| {
+                let side: String = row.get(1)?;
+                Ok(Fill {
+                    mint: row.get(0)?,
+                    side: if side == "sell" {
+                        Side::Sell
+                    } else {
+                        Side::Buy
+                    },
+                    venue: row.get(2)?,
+                    strategy: row.get(3)?,
+                    token_amount: row.get::<_, i64>(4)? as u64,
+                    sol_amount: row.get::<_, i64>(5)? as u64,
+                    fee: row.get::<_, i64>(6)? as u64,
+                    signature: row.get(7)?,
+                    timestamp: row.get::<_, i64>(8)? as u64,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(fills)
+    }
+
+    /// Creator of `mint`, if its create was recorded
+    pub fn creator(&self, mint: &str) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT creator FROM creates WHERE mint = ?1",
+                [mint],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Status of `signature`, if it was recorded
+    pub fn transaction_status(&self, signature: &str) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT status FROM transactions WHERE signature = ?1",
+                [signature],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Counts since the unix timestamp `since`
+    pub fn stats(&self, since: u64) -> Result<Stats> {
+        let conn = self.conn.lock().unwrap();
+        let since = since as i64;
+        let count = |sql: &str| -> Result<u64> {
+            Ok(conn.query_row(sql, [since], |row| row.get::<_, i64>(0))? as u64)
+        };
+        Ok(Stats {
+            creates: count("SELECT COUNT(*) FROM creates WHERE detected_at >= ?1")?,
+            migrations: count("SELECT COUNT(*) FROM migrations WHERE detected_at >= ?1")?,
+            tx_landed: count(
+                "SELECT COUNT(*) FROM transactions WHERE status = 'landed' AND updated_at >= ?1",
+            )?,
+            tx_failed: count(
+                "SELECT COUNT(*) FROM transactions WHERE status = 'failed' AND updated_at >= ?1",
+            )?,
+            buys: count("SELECT COUNT(*) FROM fills WHERE side = 'buy' AND timestamp >= ?1")?,
+            sells: count("SELECT COUNT(*) FROM fills WHERE side = 'sell' AND timestamp >= ?1")?,
+            volume: count("SELECT COALESCE(SUM(sol_amount), 0) FROM fills WHERE timestamp >= ?1")?,
+        })
+    }
+}
+
+/// Format of a trade journal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JournalFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl FromStr for JournalFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow!("unknown journal format {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for JournalFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+        })
+    }
+}
+
+/// A fill as written to the trade journal
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JournalEntry {
+    /// Unix timestamp in seconds
+    pub timestamp: u64,
+    pub mint: String,
+    pub side: Side,
+    /// Tokens bought or sold, in raw units
+    pub token_amount: u64,
+    /// SOL paid or received, in lamports before fees
+    pub sol_amount: u64,
+    /// Lamports per raw token unit
+    pub price: f64,
+    /// Network fees, in lamports
+    pub fee: u64,
+    pub signature: String,
+    pub strategy: String,
+    pub venue: String,
+}
+
+impl From<&Fill> for JournalEntry {
+    fn from(fill: &Fill) -> Self {
+        let price = if fill.token_amount == 0 {
+            0.0
+        } else {
+            fill.sol_amount as f64 / fill.token_amount as f64
+        };
+        Self {
+            timestamp: fill.timestamp,
+            mint: fill.mint.clone(),
+            side: fill.side,
+            token_amount: fill.token_amount,
+            sol_amount: fill.sol_amount,
+            price,
+            fee: fill.fee,
+            signature: fill.signature.clone(),
+            strategy: fill.strategy.clone(),
+            venue: fill.venue.clone(),
+        }
+    }
+}
+
+/// Quotes a CSV field if it needs it
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// The trade journal of `fills` in `format`, one entry per fill
+pub fn write_journal(fills: &[Fill], format: JournalFormat) -> Result<String> {
+    let entries: Vec<JournalEntry> = fills.iter().map(JournalEntry::from).collect();
+    match format {
+        JournalFormat::Json => Ok(serde_json::to_string_pretty(&entries)?),
+        JournalFormat::Csv => {
+            let mut csv = String::from(
+                "timestamp,mint,side,token_amount,sol_amount,price,fee,signature,strategy,venue\n",
+            );
+            for entry in entries {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{},{},{},{},{}\n",
+                    entry.timestamp,
+                    csv_field(&entry.mint),
+                    side_name(entry.side),
+                    entry.token_amount,
+                    entry.sol_amount,
+                    entry.price,
+                    entry.fee,
+                    csv_field(&entry.signature),
+                    csv_field(&entry.strategy),
+                    csv_field(&entry.venue),
+                ));
+            }
+            Ok(csv)
+        }
+    }
+}
+
+/// Process-wide storage, `None` unless enabled or if it can't be opened
+pub fn storage() -> Option<&'static Storage> {
+    GLOBAL_STORAGE
+        .get_or_init(|| {
+            Storage::from_env().unwrap_or_else(|e| {
+                error!("failed to open storage, not storing history {:?}", e);
+                None
+            })
+        })
+        .as_ref()
+}
+
+/// Stores every event published from now on, until the channel closes
+pub async fn record_events(storage: &'static Storage) {
+    let mut receiver = events::subscribe();
+    loop {
+        match receiver.recv().await {
+            Ok(event) => {
+                if let Err(e) = storage.record_event(&event) {
+                    error!("failed to store {} event {:?}", event.event_type(), e);
+                }
+            }
+            Err(RecvError::Lagged(n)) => warn!("storage lagged, skipped {} events", n),
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+#[test]
+fn test_storage_records_and_counts() {
+    use crate::monitor::events::{MigrationVenue, TxLandedEvent, TxSentEvent};
+
+    let storage = Storage::in_memory().unwrap();
+    let create = TokenCreateEvent {
+        signature: "create".to_string(),
+        name: "Moon Cat".to_string(),
+        symbol: "MCAT".to_string(),
+        uri: String::new(),
+        mint: "mint".to_string(),
+        bonding_curve: String::new(),
+        associated_bonding_curve: String::new(),
+        creator: "creator".to_string(),
+        dev_buy: None,
+        dev_alert: false,
+        creator_profile: None,
+        received_at: None,
+    };
+    storage
+        .record_event(&MonitorEvent::Create(create.clone()))
+        .unwrap();
+    // 重复的事件只记录一次
+    storage.record_event(&MonitorEvent::Create(create)).unwrap();
+    assert_eq!(storage.creator("mint").unwrap().as_deref(), Some("creator"));
+    assert_eq!(storage.creator("other").unwrap(), None);
+    storage
+        .record_event(&MonitorEvent::Migration(MigrationEvent {
+            venue: MigrationVenue::PumpSwap,
+            signature: "migration".to_string(),
+            coin_mint: "mint".to_string(),
+            pc_mint: "wsol".to_string(),
+            pool_id: "pool".to_string(),
+            received_at: None,
+        }))
+        .unwrap();
+    storage
+        .record_event(&MonitorEvent::TxSent(TxSentEvent {
+            signature: "tx".to_string(),
+        }))
+        .unwrap();
+    assert_eq!(
+        storage.transaction_status("tx").unwrap().as_deref(),
+        Some("sent")
+    );
+    storage
+        .record_event(&MonitorEvent::TxLanded(TxLandedEvent {
+            signature: "tx".to_string(),
+            slot: 10,
+            attempts: 2,
+        }))
+        .unwrap();
+    assert_eq!(
+        storage.transaction_status("tx").unwrap().as_deref(),
+        Some("landed")
+    );
+
+    let fill = |side, sol_amount, timestamp| Fill {
+        mint: "mint".to_string(),
+        side,
+        venue: "pumpfun".to_string(),
+        strategy: "sniper".to_string(),
+        token_amount: 1000,
+        sol_amount,
+        fee: 5000,
+        signature: "fill".to_string(),
+        timestamp,
+    };
+    storage.insert_fill(&fill(Side::Buy, 100, 0)).unwrap();
+    storage
+        .insert_fill(&fill(Side::Sell, 150, now() as u64))
+        .unwrap();
+    let fills = storage.fills().unwrap();
+    assert_eq!(fills.len(), 2);
+    assert_eq!(fills[1].side, Side::Sell);
+    assert_eq!(fills[1].strategy, "sniper");
+
+    let stats = storage.stats(0).unwrap();
+    assert_eq!(
+        stats,
+        Stats {
+            creates: 1,
+            migrations: 1,
+            tx_landed: 1,
+            tx_failed: 0,
+            buys: 1,
+            sells: 1,
+            volume: 250,
+        }
+    );
+    assert_eq!(storage.stats(now() as u64 - 60).unwrap().buys, 0);
+}
+
+#[test]
+fn test_write_journal() {
+    let fills = vec![
+        Fill {
+            mint: "mint".to_string(),
+            side: Side::Buy,
+            venue: "pumpfun".to_string(),
+            strategy: "sniper".to_string(),
+            token_amount: 4000,
+            sol_amount: 100,
+            fee: 5000,
+            signature: "sig".to_string(),
+            timestamp: 10,
+        },
+        Fill {
+            mint: "mint".to_string(),
+            side: Side::Sell,
+            venue: String::new(),
+            strategy: "a,\"b\"".to_string(),
+            token_amount: 0,
+            sol_amount: 0,
+            fee: 0,
+            signature: String::new(),
+            timestamp: 20,
+        },
+    ];
+    let csv = write_journal(&fills, JournalFormat::Csv).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(
+        lines[1],
+        "10,mint,buy,4000,100,0.025,5000,sig,sniper,pumpfun"
+    );
+    // 含逗号和引号的字段加引号
+    assert!(lines[2].ends_with(",\"a,\"\"b\"\"\","));
+
+    let json: serde_json::Value =
+        serde_json::from_str(&write_journal(&fills, JournalFormat::Json).unwrap()).unwrap();
+    assert_eq!(json[0]["side"], "buy");
+    assert_eq!(json[0]["price"], 0.025);
+    assert_eq!(json[1]["price"], 0.0);
+    assert_eq!(
+        "json".parse::<JournalFormat>().unwrap(),
+        JournalFormat::Json
+    );
+    assert!("xml".parse::<JournalFormat>().is_err());
+}
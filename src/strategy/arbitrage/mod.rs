@@ -0,0 +1,513 @@
+//! Arbitrage of one mint across venues.
+//!
+//! Each poll every registered [`Dex`] is asked how many tokens the
+//! configured SOL amount buys, and every other source how much SOL selling
+//! those tokens returns. When the best round trip beats the configured
+//! profit after fees, both legs are sent in one transaction; the sell leg's
+//! minimum output makes the whole transaction fail unless it at least covers
+//! the SOL spent and the fees. It is off unless `ARBITRAGE_ENABLED=true`.
+//!
+//! Before a live round trip is sent it is simulated against the current
+//! state as a pre-flight: the SOL the simulation credits the wallet, minus
+//! the network fees and tip, must still clear `ARB_PREFLIGHT_MIN_PROFIT_SOL`,
+//! else nothing is sent. This catches quotes that went stale between the
+//! poll and the send, and costs one `simulateTransaction` per opportunity.
+//!
+//! The venues of [`dex::default_venues`] are registered by default: the
+//! Pump.fun curve, Raydium AMM v4, PumpSwap and Orca whirlpools, the
+//! concentrated liquidity venue. Other venues can be added with
+//! [`Arbitrage::register`].
+//!
+//! - `ARB_MINTS`: comma separated mints to watch
+//! - `ARB_AMOUNT_SOL`: SOL spent on the buy leg (default 0.1)
+//! - `ARB_MIN_PROFIT_SOL`: profit after fees required (default 0.001)
+//! - `ARB_SLIPPAGE_BPS`: slippage on the buy leg in basis points (default 50)
+//! - `ARB_POLL_MS`: milliseconds between polls (default 1000)
+//! - `ARB_USE_JITO`: send as a Jito bundle instead of with a priority fee
+//! - `ARB_SIMULATE`: only simulate the transactions
+//! - `ARB_PREFLIGHT`: simulate live round trips before sending them (default
+//!   true)
+//! - `ARB_PREFLIGHT_MIN_PROFIT_SOL`: simulated profit after fees required to
+//!   send (default `ARB_MIN_PROFIT_SOL`)
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use futures_util::future::join_all;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    fee::FeeStructure,
+    instruction::Instruction,
+    native_token::{lamports_to_sol, sol_to_lamports},
+    pubkey::Pubkey,
+    signature::Keypair,
+    signer::Signer,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info};
+
+use crate::{
+    config::bot_config,
+    dex::{self, Dex},
+    engine::is_paused,
+    fees::jito_tips::jito_tip,
+    math::slippage::Slippage,
+    portfolio::Side,
+    raydium::tx::{build_transaction, new_signed_and_send, send_bundle},
+    risk, timeline,
+    tx::{
+        blockhash::recent_blockhash,
+        mode::ExecutionMode,
+        simulate::{simulated_balance_change, TxOutcome},
+    },
+};
+
+use super::parse_env;
+
+const DEFAULT_AMOUNT_SOL: f64 = 0.1;
+const DEFAULT_MIN_PROFIT_SOL: f64 = 0.001;
+const DEFAULT_SLIPPAGE_BPS: u64 = 50;
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(1000);
+
+#[derive(Debug, Clone)]
+pub struct ArbitrageConfig {
+    pub mints: Vec<Pubkey>,
+    /// Lamports spent on the buy leg
+    pub amount: u64,
+    /// Profit after fees required, in lamports
+    pub min_profit: u64,
+    pub slippage_bps: u64,
+    pub poll_interval: Duration,
+    pub use_jito: bool,
+    pub simulate: bool,
+    /// Whether live round trips are simulated before they're sent
+    pub preflight: bool,
+    /// Simulated profit after fees required to send, in lamports
+    pub preflight_min_profit: u64,
+}
+
+impl Default for ArbitrageConfig {
+    fn default() -> Self {
+        Self {
+            mints: vec![],
+            amount: sol_to_lamports(DEFAULT_AMOUNT_SOL),
+            min_profit: sol_to_lamports(DEFAULT_MIN_PROFIT_SOL),
+            slippage_bps: DEFAULT_SLIPPAGE_BPS,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            use_jito: false,
+            simulate: false,
+            preflight: true,
+            preflight_min_profit: sol_to_lamports(DEFAULT_MIN_PROFIT_SOL),
+        }
+    }
+}
+
+impl ArbitrageConfig {
+    /// Reads the `ARB_*` variables, `None` unless `ARBITRAGE_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("ARBITRAGE_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        let default = Self::default();
+        let mints = parse_env::<String>("ARB_MINTS")?
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|mint| !mint.is_empty())
+            .map(|mint| {
+                mint.parse()
+                    .map_err(|e| anyhow!("invalid ARB_MINTS {:?}: {}", mint, e))
+            })
+            .collect::<Result<Vec<Pubkey>>>()?;
+        if mints.is_empty() {
+            return Err(anyhow!("ARBITRAGE_ENABLED needs ARB_MINTS"));
+        }
+        let min_profit = parse_env::<f64>("ARB_MIN_PROFIT_SOL")?
+            .map(sol_to_lamports)
+            .unwrap_or(default.min_profit);
+        Ok(Some(Self {
+            mints,
+            amount: parse_env::<f64>("ARB_AMOUNT_SOL")?
+                .map(sol_to_lamports)
+                .unwrap_or(default.amount),
+            min_profit,
+            slippage_bps: parse_env("ARB_SLIPPAGE_BPS")?.unwrap_or(default.slippage_bps),
+            poll_interval: parse_env("ARB_POLL_MS")?
+                .map(Duration::from_millis)
+                .unwrap_or(default.poll_interval),
+            use_jito: parse_env("ARB_USE_JITO")?.unwrap_or(default.use_jito),
+            simulate: parse_env("ARB_SIMULATE")?.unwrap_or(default.simulate),
+            preflight: parse_env("ARB_PREFLIGHT")?.unwrap_or(default.preflight),
+            preflight_min_profit: parse_env::<f64>("ARB_PREFLIGHT_MIN_PROFIT_SOL")?
+                .map(sol_to_lamports)
+                .unwrap_or(min_profit),
+        }))
+    }
+}
+
+/// A profitable round trip
+#[derive(Debug, Clone)]
+pub struct Opportunity {
+    pub mint: Pubkey,
+    /// Index of the venue bought on
+    pub buy: usize,
+    /// Index of the venue sold on
+    pub sell: usize,
+    pub buy_venue: &'static str,
+    pub sell_venue: &'static str,
+    pub lamports_in: u64,
+    pub tokens: u64,
+    pub lamports_out: u64,
+    /// Network fees and tip, in lamports
+    pub fees: u64,
+    /// `lamports_out` minus `lamports_in` and `fees`
+    pub profit: i64,
+}
+
+impl fmt::Display for Opportunity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: buy on {} for {:.4} SOL, sell on {} for {:.4} SOL, profit {:.6} SOL after {} lamports fees",
+            self.mint,
+            self.buy_venue,
+            lamports_to_sol(self.lamports_in),
+            self.sell_venue,
+            lamports_to_sol(self.lamports_out),
+            self.profit as f64 / 1e9,
+            self.fees,
+        )
+    }
+}
+
+pub struct Arbitrage {
+    config: ArbitrageConfig,
+    sources: Vec<Arc<dyn Dex>>,
+}
+
+impl Arbitrage {
+    /// An engine without any venue
+    pub fn new(config: ArbitrageConfig) -> Self {
+        Self {
+            config,
+            sources: vec![],
+        }
+    }
+
+    /// An engine quoting the default venues
+    pub fn with_default_sources(config: ArbitrageConfig) -> Self {
+        Self {
+            config,
+            sources: dex::default_venues(),
+        }
+    }
+
+    /// Adds a venue to quote
+    pub fn register(&mut self, source: Arc<dyn Dex>) {
+        self.sources.push(source);
+    }
+
+    /// Fees of sending the round trip, in lamports
+    pub fn fees(&self) -> u64 {
+        let signature_fee = FeeStructure::default().lamports_per_signature;
+        if self.config.use_jito {
+            signature_fee + jito_tip()
+        } else {
+            let config = bot_config();
+            signature_fee + config.unit_limit as u64 * config.unit_price / 1_000_000
+        }
+    }
+
+    /// Best round trip of `mint` clearing the profit threshold, if any
+    pub async fn find_opportunity(
+        &self,
+        client: &Arc<RpcClient>,
+        mint: &Pubkey,
+        fees: u64,
+    ) -> Option<Opportunity> {
+        let lamports_in = self.config.amount;
+        let buys = join_all(
+            self.sources
+                .iter()
+                .map(|source| source.quote(client, mint, Side::Buy, lamports_in)),
+        )
+        .await;
+
+        let mut best: Option<Opportunity> = None;
+        for (buy, tokens) in buys.into_iter().enumerate() {
+            let tokens = match tokens {
+                Ok(tokens) if tokens > 0 => tokens,
+                Ok(_) => continue,
+                Err(e) => {
+                    // 很多代币只在部分市场上有池子
+                    debug!(
+                        "no {} buy quote for {} {:?}",
+                        self.sources[buy].name(),
+                        mint,
+                        e
+                    );
+                    continue;
+                }
+            };
+            for (sell, source) in self.sources.iter().enumerate() {
+                if sell == buy {
+                    continue;
+                }
+                let lamports_out = match source.quote(client, mint, Side::Sell, tokens).await {
+                    Ok(lamports_out) => lamports_out,
+                    Err(e) => {
+                        debug!("no {} sell quote for {} {:?}", source.name(), mint, e);
+                        continue;
+                    }
+                };
+                let profit = lamports_out as i64 - lamports_in as i64 - fees as i64;
+                if profit < self.config.min_profit as i64
+                    || best.as_ref().is_some_and(|best| best.profit >= profit)
+                {
+                    continue;
+                }
+                best = Some(Opportunity {
+                    mint: *mint,
+                    buy,
+                    sell,
+                    buy_venue: self.sources[buy].name(),
+                    sell_venue: source.name(),
+                    lamports_in,
+                    tokens,
+                    lamports_out,
+                    fees,
+                    profit,
+                });
+            }
+        }
+        best
+    }
+
+    /// Simulates the round trip `instructions` against the current state,
+    /// failing unless its profit after `opportunity`'s fees clears
+    /// the pre-flight threshold
+    async fn preflight(
+        &self,
+        client: &Arc<RpcClient>,
+        payer: &Keypair,
+        instructions: &[Instruction],
+        opportunity: &Opportunity,
+    ) -> Result<i64> {
+        let txn = build_transaction(payer, instructions, recent_blockhash(client).await?)?;
+        let change = simulated_balance_change(client, &txn, &payer.pubkey())
+            .await
+            .map_err(|e| anyhow!("arbitrage pre-flight of {} failed: {}", opportunity.mint, e))?;
+        // 模拟里没有小费和优先费，按报价时的手续费扣除
+        let profit = change - opportunity.fees as i64;
+        if profit < self.config.preflight_min_profit as i64 {
+            return Err(anyhow!(
+                "arbitrage of {} aborted, simulated profit {:.6} SOL below {:.6} SOL",
+                opportunity.mint,
+                profit as f64 / 1e9,
+                lamports_to_sol(self.config.preflight_min_profit),
+            ));
+        }
+        debug!(
+            "arbitrage pre-flight of {}: {:.6} SOL profit",
+            opportunity.mint,
+            profit as f64 / 1e9
+        );
+        Ok(profit)
+    }
+
+    /// Sends both legs of `opportunity` in one transaction
+    pub async fn execute(
+        &self,
+        client: &Arc<RpcClient>,
+        payer: Arc<Keypair>,
+        opportunity: &Opportunity,
+    ) -> Result<TxOutcome> {
+        let min_tokens = Slippage::Bps(self.config.slippage_bps).min_out(opportunity.tokens)?;
+        // 卖出至少收回成本和手续费，否则整笔交易失败
+        let min_lamports_out = opportunity.lamports_in + opportunity.fees;
+        let mode = ExecutionMode::resolve(self.config.simulate);
+        if !mode.simulates() {
+            risk::check_buy(&opportunity.mint, opportunity.lamports_in)?;
+        }
+
+        let mut instructions = self.sources[opportunity.buy]
+            .build_swap_ix(
+                client,
+                &payer,
+                &opportunity.mint,
+                Side::Buy,
+                opportunity.lamports_in,
+                min_tokens,
+            )
+            .await?;
+        instructions.extend(
+            self.sources[opportunity.sell]
+                .build_swap_ix(
+                    client,
+                    &payer,
+                    &opportunity.mint,
+                    Side::Sell,
+                    min_tokens,
+                    min_lamports_out,
+                )
+                .await?,
+        );
+
+        if mode == ExecutionMode::Live && self.config.preflight {
+            self.preflight(client, &payer, &instructions, opportunity)
+                .await?;
+        }
+
+        if self.config.use_jito {
+            send_bundle(
+                client.clone(),
+                payer,
+                instructions,
+                self.config.simulate,
+                None,
+                "arbitrage",
+                "round_trip",
+            )
+            .await
+        } else {
+            new_signed_and_send(
+                client.clone(),
+                payer,
+                instructions,
+                self.config.simulate,
+                None,
+                "arbitrage",
+                "round_trip",
+            )
+            .await
+        }
+    }
+}
+
+/// Polls the configured mints and sends every opportunity found, until
+/// `shutdown` is cancelled
+pub async fn run(
+    arbitrage: Arbitrage,
+    client: Arc<RpcClient>,
+    payer: Arc<Keypair>,
+    shutdown: CancellationToken,
+) {
+    let mut interval = tokio::time::interval(arbitrage.config.poll_interval);
+    while shutdown
+        .run_until_cancelled(interval.tick())
+        .await
+        .is_some()
+    {
+        if is_paused() {
+            continue;
+        }
+        let fees = arbitrage.fees();
+        for mint in &arbitrage.config.mints {
+            let Some(opportunity) = arbitrage.find_opportunity(&client, mint, fees).await else {
+                continue;
+            };
+            info!("arbitrage {}", opportunity);
+            let execute = arbitrage.execute(&client, payer.clone(), &opportunity);
+            match timeline::triggered("arbitrage", Instant::now(), execute).await {
+                Ok(outcome) => info!("arbitrage sent {:?}", outcome.signatures()),
+                Err(e) => error!("arbitrage of {} failed {:?}", mint, e),
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_find_opportunity_picks_best_pair() {
+    /// Trades at fixed rates, tokens per lamport
+    struct FixedRate {
+        name: &'static str,
+        buy_rate: Option<u64>,
+        sell_rate: u64,
+    }
+
+    #[async_trait::async_trait]
+    impl Dex for FixedRate {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        async fn pool_for_mint(&self, _: &Arc<RpcClient>, mint: &Pubkey) -> Result<Pubkey> {
+            Ok(*mint)
+        }
+
+        async fn quote(
+            &self,
+            _: &Arc<RpcClient>,
+            _: &Pubkey,
+            side: Side,
+            amount_in: u64,
+        ) -> Result<u64> {
+            match side {
+                Side::Buy => self
+                    .buy_rate
+                    .map(|rate| amount_in * rate)
+                    .ok_or_else(|| anyhow!("no pool")),
+                Side::Sell => Ok(amount_in / self.sell_rate),
+            }
+        }
+
+        async fn build_swap_ix(
+            &self,
+            _: &Arc<RpcClient>,
+            _: &Keypair,
+            _: &Pubkey,
+            _: Side,
+            _: u64,
+            _: u64,
+        ) -> Result<Vec<Instruction>> {
+            Ok(vec![])
+        }
+    }
+
+    let client = Arc::new(RpcClient::new_mock("succeeds".to_string()));
+    let mint = Pubkey::new_unique();
+    let config = ArbitrageConfig {
+        amount: 1000,
+        min_profit: 50,
+        ..ArbitrageConfig::default()
+    };
+    let mut arbitrage = Arbitrage::new(config);
+    for (name, buy_rate, sell_rate) in [("a", Some(10), 10), ("b", Some(12), 8), ("c", None, 9)] {
+        arbitrage.register(Arc::new(FixedRate {
+            name,
+            buy_rate,
+            sell_rate,
+        }));
+    }
+
+    // b 买入 12000 个，在 a 卖出 1200，在 c 卖出 1333
+    let best = arbitrage
+        .find_opportunity(&client, &mint, 10)
+        .await
+        .unwrap();
+    assert_eq!((best.buy_venue, best.sell_venue), ("b", "c"));
+    assert_eq!(best.lamports_out, 1333);
+    assert_eq!(best.profit, 323);
+
+    // 手续费太高时没有机会
+    assert!(arbitrage
+        .find_opportunity(&client, &mint, 300)
+        .await
+        .is_none());
+}
+
+#[test]
+fn test_default_sources_quote_every_venue() {
+    let arbitrage = Arbitrage::with_default_sources(ArbitrageConfig::default());
+    let names: Vec<_> = arbitrage
+        .sources
+        .iter()
+        .map(|source| source.name())
+        .collect();
+    assert_eq!(names, ["pumpfun", "raydium", "pumpswap", "orca"]);
+}
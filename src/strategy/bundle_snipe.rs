@@ -0,0 +1,337 @@
+//! Buys of a Pump.fun token from several wallets in one Jito bundle.
+//!
+//! On contested launches a single buy often loses the race to the curve.
+//! Signing the same buy from several payers and submitting them as one bundle
+//! raises the odds of a fill, and since a bundle lands whole in one block or
+//! not at all, the wallets never pay for half a snipe. It is off unless
+//! `BUNDLE_SNIPE_ENABLED=true`, in which case the sniper's buys are bundled.
+//!
+//! - `BUNDLE_SNIPE_SPLIT`: comma separated weights of each wallet's share of
+//!   the buy, the first weight for the first wallet of [`crate::wallet`] and
+//!   so on, e.g. `50,30,20` (default `1,1,1`). A bundle holds at most
+//!   [`MAX_BUNDLE_TRANSACTIONS`] transactions, so as many wallets.
+//!
+//! Bundles always go to the Jito block engine whatever the strategy's sender,
+//! the last wallet paying the tip in the last transaction.
+
+use std::{env, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    hash::Hash, instruction::Instruction, pubkey::Pubkey, signature::Keypair, signer::Signer,
+    transaction::Transaction,
+};
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use tracing::info;
+
+use crate::{
+    constants::{accounts::TOKEN_PROGRAM, jito::MAX_BUNDLE_TRANSACTIONS},
+    math::slippage::Slippage,
+    metrics,
+    portfolio::{record_trade, Side},
+    pumpfun::{
+        accounts::BondingCurveAccount,
+        instructions::create_buy_instruction,
+        utils::{get_bonding_curve_account, get_global_account},
+    },
+    raydium::tx::paper,
+    risk, timeline,
+    tx::{
+        blockhash::recent_blockhash,
+        budget::global_guard,
+        mode::ExecutionMode,
+        sender::{send_jito_transactions, Sender},
+        simulate::{simulate, TxOutcome},
+    },
+    wallet::Wallets,
+};
+
+use super::parse_env;
+
+const DEFAULT_SPLIT: [u64; 3] = [1, 1, 1];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BundleSnipeConfig {
+    /// Weight of each wallet's share of a buy, one per wallet
+    pub weights: Vec<u64>,
+}
+
+impl Default for BundleSnipeConfig {
+    fn default() -> Self {
+        Self {
+            weights: DEFAULT_SPLIT.to_vec(),
+        }
+    }
+}
+
+impl BundleSnipeConfig {
+    pub fn new(weights: Vec<u64>) -> Result<Self> {
+        if weights.is_empty() || weights.len() > MAX_BUNDLE_TRANSACTIONS {
+            return Err(anyhow!(
+                "bundle split needs 1 to {} weights, not {}",
+                MAX_BUNDLE_TRANSACTIONS,
+                weights.len()
+            ));
+        }
+        if weights.contains(&0) {
+            return Err(anyhow!("bundle split weights must be positive"));
+        }
+        Ok(Self { weights })
+    }
+
+    /// Reads the `BUNDLE_SNIPE_*` variables, `None` unless
+    /// `BUNDLE_SNIPE_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("BUNDLE_SNIPE_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        let Ok(split) = env::var("BUNDLE_SNIPE_SPLIT") else {
+            return Ok(Some(Self::default()));
+        };
+        let weights = split
+            .split(',')
+            .map(|weight| {
+                weight
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow!("invalid BUNDLE_SNIPE_SPLIT {:?}", split))
+            })
+            .collect::<Result<Vec<u64>>>()?;
+        Self::new(weights)
+            .map(Some)
+            .map_err(|e| anyhow!("invalid BUNDLE_SNIPE_SPLIT: {}", e))
+    }
+
+    /// Shares of `lamports` of each wallet
+    pub fn split(&self, lamports: u64) -> Vec<u64> {
+        split_amounts(lamports, &self.weights)
+    }
+}
+
+/// Splits `total` in proportion to `weights`, the rounding going to the
+/// heaviest one so the shares add up to `total`
+pub fn split_amounts(total: u64, weights: &[u64]) -> Vec<u64> {
+    let sum: u128 = weights.iter().map(|&weight| weight as u128).sum();
+    if sum == 0 {
+        return vec![0; weights.len()];
+    }
+    let mut amounts: Vec<u64> = weights
+        .iter()
+        .map(|&weight| (total as u128 * weight as u128 / sum) as u64)
+        .collect();
+    let rest = total - amounts.iter().sum::<u64>();
+    if let Some(heaviest) = (0..weights.len()).max_by_key(|&i| (weights[i], usize::MAX - i)) {
+        amounts[heaviest] += rest;
+    }
+    amounts
+}
+
+/// The signed buys of a bundle
+pub struct Bundle {
+    pub transactions: Vec<Transaction>,
+    /// Tokens bought by all the wallets
+    pub token_amount: u64,
+}
+
+/// Signs a buy of `mint` for `amounts[i]` lamports from each `payers[i]`
+///
+/// Each buy is priced on the curve the buys before it in the bundle moved,
+/// `amounts[i]` paying the `fee_bps` fee too, and `tip` goes last in the last
+/// transaction.
+#[allow(clippy::too_many_arguments)]
+pub fn build_bundle(
+    payers: &[&Keypair],
+    mint: &Pubkey,
+    curve: &BondingCurveAccount,
+    fee_bps: u64,
+    amounts: &[u64],
+    slippage: u64,
+    tip: Option<Instruction>,
+    recent_blockhash: Hash,
+) -> Result<Bundle> {
+    if payers.is_empty() || payers.len() != amounts.len() {
+        return Err(anyhow!(
+            "{} payers for {} amounts",
+            payers.len(),
+            amounts.len()
+        ));
+    }
+    let mut curve = curve.clone();
+    let mut transactions = vec![];
+    let mut token_amount = 0;
+    for (i, (payer, &amount)) in payers.iter().zip(amounts).enumerate() {
+        // 前面的买入推高了价格
+        let tokens = curve
+            .get_buy_price_with_fee(amount, fee_bps)
+            .and_then(|tokens| curve.apply_buy(tokens).map(|_| tokens))
+            .map_err(|e| anyhow!("can't buy {} for {} lamports: {}", mint, amount, e))?;
+        let max_sol_cost = Slippage::Percent(slippage).max_in(amount)?;
+        let mut instructions = vec![
+            create_associated_token_account_idempotent(
+                &payer.pubkey(),
+                &payer.pubkey(),
+                mint,
+                &TOKEN_PROGRAM,
+            ),
+            create_buy_instruction(payer, mint, tokens, max_sol_cost),
+        ];
+        if i == payers.len() - 1 {
+            instructions.extend(tip.clone());
+        }
+        transactions.push(Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&payer.pubkey()),
+            &[*payer],
+            recent_blockhash,
+        ));
+        token_amount += tokens;
+    }
+    Ok(Bundle {
+        transactions,
+        token_amount,
+    })
+}
+
+/// Buys `mint` for `amounts[i]` lamports from each of the first wallets of
+/// `wallets`, in one Jito bundle
+pub async fn bundle_buy(
+    client: Arc<RpcClient>,
+    wallets: &Wallets,
+    mint: &Pubkey,
+    amounts: &[u64],
+    slippage: u64,
+    is_simulate: bool,
+) -> Result<TxOutcome> {
+    if wallets.len() < amounts.len() {
+        return Err(anyhow!(
+            "bundle of {} buys needs as many wallets, {} configured",
+            amounts.len(),
+            wallets.len()
+        ));
+    }
+    let payers: Vec<&Keypair> = wallets.keypairs()[..amounts.len()]
+        .iter()
+        .map(Arc::as_ref)
+        .collect();
+    let total: u64 = amounts.iter().sum();
+    let curve = get_bonding_curve_account(client.clone(), mint).await?;
+    let global = get_global_account(client.clone()).await?;
+
+    // 风控、预算和冷却按总额检查
+    let mode = ExecutionMode::resolve(is_simulate);
+    let reservation = if !mode.simulates() {
+        risk::check_buy(mint, total)?;
+        Some(global_guard().reserve(mint, total)?)
+    } else {
+        None
+    };
+
+    timeline::mark_built();
+    let tip = Sender::Jito.tip(&payers[payers.len() - 1].pubkey());
+    let bundle = build_bundle(
+        &payers,
+        mint,
+        &curve,
+        global.fee_basis_points,
+        amounts,
+        slippage,
+        tip,
+        recent_blockhash(&client).await?,
+    )?;
+
+    let outcome = match mode {
+        ExecutionMode::Simulate => {
+            // 逐笔模拟，不计前面买入对曲线的影响
+            let mut summary = None;
+            for txn in &bundle.transactions {
+                summary = Some(simulate(&client, txn, None).await?);
+            }
+            TxOutcome::Simulated(summary.expect("bundle has transactions"))
+        }
+        ExecutionMode::Paper => paper(&bundle.transactions[0]),
+        ExecutionMode::Live => {
+            metrics::record_trade_attempt("pumpfun", "buy");
+            let bundle_id = send_jito_transactions(&bundle.transactions).await?;
+            info!(
+                "bundle id: {}, {} buys of {}",
+                bundle_id,
+                bundle.transactions.len(),
+                mint
+            );
+            timeline::mark_sent();
+            metrics::record_trade_success("pumpfun", "buy");
+            TxOutcome::Sent(
+                bundle
+                    .transactions
+                    .iter()
+                    .map(|txn| txn.signatures[0])
+                    .collect(),
+            )
+        }
+    };
+    if let Some(reservation) = reservation {
+        reservation.commit();
+    }
+    record_trade(
+        "pumpfun",
+        Side::Buy,
+        mint,
+        bundle.token_amount,
+        total,
+        &outcome,
+    );
+    Ok(outcome)
+}
+
+#[test]
+fn test_bundle_split_and_build() {
+    assert_eq!(split_amounts(100, &[1, 1, 1]), vec![34, 33, 33]);
+    assert_eq!(split_amounts(1_000, &[50, 30, 20]), vec![500, 300, 200]);
+    assert_eq!(split_amounts(10, &[1, 3]), vec![2, 8]);
+    assert!(BundleSnipeConfig::new(vec![1; MAX_BUNDLE_TRANSACTIONS + 1]).is_err());
+    assert!(BundleSnipeConfig::new(vec![1, 0]).is_err());
+
+    let payers = [Keypair::new(), Keypair::new(), Keypair::new()];
+    let payers: Vec<&Keypair> = payers.iter().collect();
+    let mint = Pubkey::new_unique();
+    let tip_account = Pubkey::new_unique();
+    let tip = solana_sdk::system_instruction::transfer(&payers[2].pubkey(), &tip_account, 1_000);
+    let amounts = BundleSnipeConfig::default().split(30_000_000);
+    let curve = BondingCurveAccount::fresh();
+    let bundle = build_bundle(
+        &payers,
+        &mint,
+        &curve,
+        100,
+        &amounts,
+        10,
+        Some(tip),
+        Hash::default(),
+    )
+    .unwrap();
+
+    assert_eq!(bundle.transactions.len(), 3);
+    for (txn, payer) in bundle.transactions.iter().zip(&payers) {
+        assert_eq!(txn.message.account_keys[0], payer.pubkey());
+        txn.verify().unwrap();
+    }
+    // 只有最后一笔付小费
+    let tips = |txn: &Transaction| txn.message.account_keys.contains(&tip_account);
+    assert!(!tips(&bundle.transactions[0]) && tips(&bundle.transactions[2]));
+    // 同样的金额，后面的买入得到更少的代币
+    let alone = curve.get_buy_price_with_fee(amounts[0], 100).unwrap();
+    assert!(bundle.token_amount < alone * 3);
+    assert!(build_bundle(
+        &payers,
+        &mint,
+        &curve,
+        100,
+        &amounts[..2],
+        10,
+        None,
+        Hash::default()
+    )
+    .is_err());
+}
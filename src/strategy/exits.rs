@@ -0,0 +1,274 @@
+//! Take-profit and stop-loss exits for open positions.
+//!
+//! Every poll the open positions of the portfolio are quoted at what selling
+//! them would return now, and a position whose value crossed a threshold is
+//! sold in full with `sell_auto`. It is off unless `EXITS_ENABLED=true`.
+//!
+//! - `EXIT_TAKE_PROFIT_PCT`: sell once the value is this much above the cost
+//! - `EXIT_STOP_LOSS_PCT`: sell once the value is this much below the cost
+//! - `EXIT_TRAILING_STOP`: measure the stop loss from the highest value seen
+//!   instead of the cost
+//! - `EXIT_POLL_SECS`: seconds between polls (default 5)
+//! - `EXIT_RETRY_SECS`: seconds after which a position still open is sold
+//!   again, in case the sell didn't land (default 90)
+//! - `EXIT_SLIPPAGE`: slippage in percent (default 10)
+//! - `EXIT_SIMULATE`: only simulate the sells
+//!
+//! The thresholds and slippage can be changed while running, see
+//! [`super::params`].
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+
+use crate::{
+    portfolio::{portfolio, quote::position_value, Position},
+    pumpfun::operation::sell_auto,
+    timeline,
+    wallet::Wallets,
+};
+
+use super::{
+    params::{parse_option, parse_value, Live},
+    parse_env,
+};
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_SLIPPAGE: u64 = 10;
+/// 比 blockhash 的有效期长，重试时之前的卖单已不会落地
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(90);
+
+/// Why a position is sold, changes in percent
+#[derive(Debug, Error, PartialEq)]
+pub enum ExitReason {
+    #[error("take profit at {change:+.2}%")]
+    TakeProfit { change: f64 },
+    #[error("stop loss at {change:+.2}%")]
+    StopLoss { change: f64 },
+    #[error("trailing stop at {drawdown:.2}% below the peak")]
+    TrailingStop { drawdown: f64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct ExitConfig {
+    /// Gain over the cost that triggers a sell, in percent
+    pub take_profit_pct: Option<f64>,
+    /// Loss that triggers a sell, in percent
+    pub stop_loss_pct: Option<f64>,
+    /// Whether the stop loss is measured from the peak value
+    pub trailing_stop: bool,
+    pub poll_interval: Duration,
+    /// Time after a sell before the position is sold again if still open
+    pub retry_after: Duration,
+    /// Slippage in percent
+    pub slippage: u64,
+    pub simulate: bool,
+}
+
+impl Default for ExitConfig {
+    fn default() -> Self {
+        Self {
+            take_profit_pct: None,
+            stop_loss_pct: None,
+            trailing_stop: false,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            retry_after: DEFAULT_RETRY_AFTER,
+            slippage: DEFAULT_SLIPPAGE,
+            simulate: false,
+        }
+    }
+}
+
+impl ExitConfig {
+    /// Reads the `EXIT_*` variables, `None` unless `EXITS_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("EXITS_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        let default = Self::default();
+        let config = Self {
+            take_profit_pct: parse_env("EXIT_TAKE_PROFIT_PCT")?,
+            stop_loss_pct: parse_env("EXIT_STOP_LOSS_PCT")?,
+            trailing_stop: parse_env("EXIT_TRAILING_STOP")?.unwrap_or(default.trailing_stop),
+            poll_interval: parse_env("EXIT_POLL_SECS")?
+                .map(Duration::from_secs)
+                .unwrap_or(default.poll_interval),
+            retry_after: parse_env("EXIT_RETRY_SECS")?
+                .map(Duration::from_secs)
+                .unwrap_or(default.retry_after),
+            slippage: parse_env("EXIT_SLIPPAGE")?.unwrap_or(default.slippage),
+            simulate: parse_env("EXIT_SIMULATE")?.unwrap_or(default.simulate),
+        };
+        config.validate()?;
+        Ok(Some(config))
+    }
+
+    /// Checks there is a threshold to exit at
+    pub fn validate(&self) -> Result<()> {
+        if self.take_profit_pct.is_none() && self.stop_loss_pct.is_none() {
+            return Err(anyhow!(
+                "EXITS_ENABLED needs EXIT_TAKE_PROFIT_PCT or EXIT_STOP_LOSS_PCT"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Sets the runtime parameter `key`, see [`super::params`]
+    ///
+    /// The poll interval and retry delay are read once.
+    pub fn set_param(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "take_profit_pct" => self.take_profit_pct = parse_option(key, value)?,
+            "stop_loss_pct" => self.stop_loss_pct = parse_option(key, value)?,
+            "trailing_stop" => self.trailing_stop = parse_value(key, value)?,
+            "slippage" => self.slippage = parse_value(key, value)?,
+            _ => return Err(anyhow!("unknown exits parameter {:?}", key)),
+        }
+        Ok(())
+    }
+
+    /// Checks a position that cost `cost` and is worth `value`, `peak` being
+    /// the highest value seen since it was opened
+    pub fn check(&self, cost: u64, value: u64, peak: u64) -> Option<ExitReason> {
+        if cost == 0 {
+            return None;
+        }
+        let change = (value as f64 / cost as f64 - 1.0) * 100.0;
+        if let Some(take_profit) = self.take_profit_pct {
+            if change >= take_profit {
+                return Some(ExitReason::TakeProfit { change });
+            }
+        }
+        let stop_loss = self.stop_loss_pct?;
+        if self.trailing_stop {
+            let peak = peak.max(cost);
+            let drawdown = (1.0 - value as f64 / peak as f64) * 100.0;
+            (drawdown >= stop_loss).then_some(ExitReason::TrailingStop { drawdown })
+        } else {
+            (change <= -stop_loss).then_some(ExitReason::StopLoss { change })
+        }
+    }
+}
+
+/// Polls the open positions and sells those crossing a threshold, until
+/// `shutdown` is cancelled
+///
+/// Each poll uses the parameters current when it starts, and finishes its
+/// sells once cancelled.
+pub async fn run(
+    config: Live<ExitConfig>,
+    client: Arc<RpcClient>,
+    wallets: Arc<Wallets>,
+    shutdown: CancellationToken,
+) {
+    let mut interval = tokio::time::interval(config.load().poll_interval);
+    // mint -> 最高估值
+    let mut peaks: HashMap<String, u64> = HashMap::new();
+    // 已经发出卖单的mint -> 发出时间，避免重复卖出
+    let mut exiting: HashMap<String, Instant> = HashMap::new();
+    let retry_after = config.load().retry_after;
+    while shutdown
+        .run_until_cancelled(interval.tick())
+        .await
+        .is_some()
+    {
+        let current = config.load_full();
+        let positions = portfolio().open_positions();
+        let open: HashSet<&String> = positions.iter().map(|p| &p.mint).collect();
+        peaks.retain(|mint, _| open.contains(mint));
+        // 卖单失败或过期时仓位仍在，超时后重新卖出
+        exiting.retain(|mint, sent_at| open.contains(mint) && sent_at.elapsed() < retry_after);
+
+        for position in &positions {
+            if exiting.contains_key(&position.mint) {
+                continue;
+            }
+            match check_position(&current, &client, &wallets, position, &mut peaks).await {
+                Ok(true) => {
+                    peaks.remove(&position.mint);
+                    exiting.insert(position.mint.clone(), Instant::now());
+                }
+                Ok(false) => {}
+                Err(e) => error!("exit of {} failed {:?}", position.mint, e),
+            }
+        }
+    }
+}
+
+/// Quotes `position` and sells it if it crossed a threshold, returning
+/// whether a sell was sent or simulated
+async fn check_position(
+    config: &ExitConfig,
+    client: &Arc<RpcClient>,
+    wallets: &Wallets,
+    position: &Position,
+    peaks: &mut HashMap<String, u64>,
+) -> Result<bool> {
+    let mint: Pubkey = position.mint.parse()?;
+    let (_, value) = position_value(client.clone(), position).await?;
+    let received_at = Instant::now();
+    let peak = peaks.entry(position.mint.clone()).or_insert(0);
+    *peak = (*peak).max(value);
+
+    let Some(reason) = config.check(position.cost_basis, value, *peak) else {
+        return Ok(false);
+    };
+    info!("exiting {}: {}", mint, reason);
+    let payer = wallets.for_sell(&mint);
+    let sell = sell_auto(
+        client.clone(),
+        &payer,
+        &mint,
+        position.token_amount,
+        config.slippage,
+        config.simulate,
+    );
+    let outcome = timeline::triggered("exits", received_at, sell).await?;
+    info!("sold {} {:?}", mint, outcome.signatures());
+    Ok(true)
+}
+
+#[test]
+fn test_exit_thresholds() {
+    let mut config = ExitConfig {
+        take_profit_pct: Some(100.0),
+        stop_loss_pct: Some(30.0),
+        ..ExitConfig::default()
+    };
+    assert_eq!(config.check(1000, 1500, 1500), None);
+    assert_eq!(
+        config.check(1000, 2000, 2000),
+        Some(ExitReason::TakeProfit { change: 100.0 })
+    );
+    assert!(matches!(
+        config.check(1000, 700, 1800),
+        Some(ExitReason::StopLoss { .. })
+    ));
+    // 固定止损不看最高估值
+    assert_eq!(config.check(1000, 1200, 1800), None);
+
+    config.trailing_stop = true;
+    assert!(matches!(
+        config.check(1000, 1260, 1800),
+        Some(ExitReason::TrailingStop { .. })
+    ));
+    assert_eq!(config.check(1000, 1300, 1800), None);
+    // 还没涨过成本时从成本算起
+    assert!(matches!(
+        config.check(1000, 700, 900),
+        Some(ExitReason::TrailingStop { .. })
+    ));
+
+    config.stop_loss_pct = None;
+    assert_eq!(config.check(1000, 100, 1800), None);
+}
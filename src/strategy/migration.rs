@@ -0,0 +1,244 @@
+//! Open Pump.fun positions whose curve migrated to PumpSwap or Raydium.
+//!
+//! Listens to the events of `listen_rayidum_migration` and, when the migrated
+//! mint is an open position, marks it migrated in the portfolio so it's
+//! quoted in the new pool, see [`crate::portfolio::quote`]. It is off unless
+//! `MIGRATION_EXIT_ENABLED=true`.
+//!
+//! - `MIGRATION_EXIT_MODE`: `track` (default) only marks the position, `sell`
+//!   also sells all of it into the pool
+//! - `MIGRATION_SELL_DELAY_SECS`: seconds waited before selling, letting the
+//!   pool's first trades settle (default 0)
+//! - `MIGRATION_SLIPPAGE`: slippage in percent (default 10)
+//! - `MIGRATION_SIMULATE`: only simulate the sells
+
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::{error, info, warn};
+
+use crate::{
+    monitor::events::{MigrationEvent, MigrationVenue, MonitorEvent},
+    portfolio::{portfolio, Portfolio},
+    pumpfun::operation::sell_auto,
+    pumpswap::pools,
+    timeline,
+    wallet::Wallets,
+};
+
+use super::parse_env;
+
+const DEFAULT_SLIPPAGE: u64 = 10;
+
+/// What is done with a position once its token migrated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MigrationMode {
+    /// Quote it in the new pool
+    #[default]
+    Track,
+    /// Sell it into the new pool
+    Sell,
+}
+
+impl FromStr for MigrationMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "track" => Ok(MigrationMode::Track),
+            "sell" => Ok(MigrationMode::Sell),
+            _ => Err(anyhow!("unknown migration mode {:?}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MigrationConfig {
+    pub mode: MigrationMode,
+    /// Wait before selling
+    pub sell_delay: Duration,
+    /// Slippage in percent
+    pub slippage: u64,
+    pub simulate: bool,
+}
+
+impl MigrationConfig {
+    /// Reads the `MIGRATION_*` variables, `None` unless
+    /// `MIGRATION_EXIT_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("MIGRATION_EXIT_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            mode: parse_env("MIGRATION_EXIT_MODE")?.unwrap_or_default(),
+            sell_delay: parse_env("MIGRATION_SELL_DELAY_SECS")?
+                .map(Duration::from_secs)
+                .unwrap_or_default(),
+            slippage: parse_env("MIGRATION_SLIPPAGE")?.unwrap_or(DEFAULT_SLIPPAGE),
+            simulate: parse_env("MIGRATION_SIMULATE")?.unwrap_or(false),
+        }))
+    }
+}
+
+/// The migrated mint and its WSOL pool, `None` unless the pool pairs a token
+/// with WSOL
+pub fn migrated_mint(event: &MigrationEvent) -> Option<(Pubkey, Pubkey)> {
+    let coin: Pubkey = event.coin_mint.parse().ok()?;
+    let pc: Pubkey = event.pc_mint.parse().ok()?;
+    let pool: Pubkey = event.pool_id.parse().ok()?;
+    let native_mint = spl_token::native_mint::ID;
+    match (coin == native_mint, pc == native_mint) {
+        (false, true) => Some((coin, pool)),
+        (true, false) => Some((pc, pool)),
+        _ => None,
+    }
+}
+
+/// Marks the position in the migrated mint of `event`, returning the mint if
+/// one was open
+pub fn mark_migrated(portfolio: &Portfolio, event: &MigrationEvent) -> Option<Pubkey> {
+    let (mint, pool) = migrated_mint(event)?;
+    // 之后的报价和卖出直接用这个池子，不用再搜索
+    if event.venue == MigrationVenue::PumpSwap {
+        pools::record_pool(mint, pool);
+    }
+    portfolio
+        .mark_migrated(&mint.to_string(), &pool)
+        .then_some(mint)
+}
+
+/// Handles the migrations on `events` until the channel closes or `shutdown`
+/// is cancelled, the sells running on `actions`
+pub async fn run(
+    config: MigrationConfig,
+    mut events: broadcast::Receiver<MonitorEvent>,
+    client: Arc<RpcClient>,
+    wallets: Arc<Wallets>,
+    shutdown: CancellationToken,
+    actions: TaskTracker,
+) {
+    while let Some(event) = shutdown.run_until_cancelled(events.recv()).await {
+        let event = match event {
+            Ok(MonitorEvent::Migration(event)) => event,
+            Ok(_) => continue,
+            Err(RecvError::Lagged(n)) => {
+                warn!("migration exits lagged, skipped {} events", n);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        let Some(mint) = mark_migrated(portfolio(), &event) else {
+            continue;
+        };
+        info!("position in {} migrated to {}", mint, event.pool_id);
+        if config.mode == MigrationMode::Sell {
+            let (config, client, wallets) = (config.clone(), client.clone(), wallets.clone());
+            let shutdown = shutdown.clone();
+            // 每个卖单单独等待，不阻塞后续迁移；等待期间停止时不再卖出
+            actions.spawn(async move {
+                let delay = tokio::time::sleep(config.sell_delay);
+                if shutdown.run_until_cancelled(delay).await.is_none() {
+                    return;
+                }
+                if let Err(e) = sell_migrated(&config, client, &wallets, &mint, &event).await {
+                    error!("migration sell of {} failed {:?}", mint, e);
+                }
+            });
+        }
+    }
+}
+
+/// Sells the whole position in `mint`
+async fn sell_migrated(
+    config: &MigrationConfig,
+    client: Arc<RpcClient>,
+    wallets: &Wallets,
+    mint: &Pubkey,
+    event: &MigrationEvent,
+) -> Result<()> {
+    // 等待期间可能已经被其他策略卖出
+    let Some(position) = portfolio()
+        .position(&mint.to_string())
+        .filter(|position| position.is_open())
+    else {
+        return Ok(());
+    };
+    let payer = wallets.for_sell(mint);
+    let sell = sell_auto(
+        client,
+        &payer,
+        mint,
+        position.token_amount,
+        config.slippage,
+        config.simulate,
+    );
+    let received_at = event.received_at.unwrap_or_else(Instant::now);
+    let outcome = timeline::triggered("migration", received_at, sell).await?;
+    info!("sold migrated {} {:?}", mint, outcome.signatures());
+    Ok(())
+}
+
+#[test]
+fn test_migrated_positions() {
+    use crate::portfolio::{Fill, Side};
+
+    let mint = Pubkey::new_unique();
+    let pool = Pubkey::new_unique();
+    let event = |coin: Pubkey, pc: Pubkey, venue| MigrationEvent {
+        venue,
+        signature: String::new(),
+        coin_mint: coin.to_string(),
+        pc_mint: pc.to_string(),
+        pool_id: pool.to_string(),
+        received_at: None,
+    };
+    let native_mint = spl_token::native_mint::ID;
+    let raydium = event(mint, native_mint, MigrationVenue::Raydium);
+    assert_eq!(migrated_mint(&raydium), Some((mint, pool)));
+    assert_eq!(
+        migrated_mint(&event(native_mint, mint, MigrationVenue::Raydium)),
+        Some((mint, pool))
+    );
+    assert_eq!(
+        migrated_mint(&event(mint, native_mint, MigrationVenue::PumpSwap)),
+        Some((mint, pool))
+    );
+    assert_eq!(
+        migrated_mint(&event(mint, Pubkey::new_unique(), MigrationVenue::PumpSwap)),
+        None
+    );
+    assert_eq!(
+        "sell".parse::<MigrationMode>().unwrap(),
+        MigrationMode::Sell
+    );
+    assert!("hold".parse::<MigrationMode>().is_err());
+
+    // 没有持仓时不处理
+    let portfolio = Portfolio::in_memory();
+    assert_eq!(mark_migrated(&portfolio, &raydium), None);
+    portfolio
+        .record(Fill {
+            mint: mint.to_string(),
+            side: Side::Buy,
+            venue: "pumpfun".to_string(),
+            strategy: "sniper".to_string(),
+            token_amount: 1000,
+            sol_amount: 1000,
+            fee: 0,
+            signature: String::new(),
+            timestamp: 0,
+        })
+        .unwrap();
+    assert_eq!(mark_migrated(&portfolio, &raydium), Some(mint));
+    let position = portfolio.position(&mint.to_string()).unwrap();
+    assert_eq!(position.pool, Some(pool.to_string()));
+}
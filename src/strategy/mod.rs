@@ -1,8 +1,74 @@
-#[derive(Debug, Clone, Copy)]
-pub enum Strategy {
+use std::{env, str::FromStr};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    engine::{Action, ActionConfig},
+    monitor::events::MonitorEvent,
+    safety::SafetyConfig,
+};
+
+pub mod arbitrage;
+pub mod bundle_snipe;
+pub mod exits;
+pub mod migration;
+pub mod params;
+pub mod signals;
+pub mod sniper;
+
+/// Parses the env var `key`, `None` if it isn't set
+pub(crate) fn parse_env<T: FromStr>(key: &str) -> Result<Option<T>> {
+    match env::var(key) {
+        Ok(v) => v
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| anyhow!("invalid {} {:?}", key, v)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// A strategy trading on the monitor's events
+///
+/// Strategies are registered in an [`crate::engine::StrategyRegistry`], which
+/// executes the actions they return. Strategies polling or subscribing on their
+/// own, like exits and copy trading, run as their own tasks instead.
+pub trait Strategy: Send + Sync {
+    /// Name the trades are attributed to, see [`crate::timeline`]
+    fn name(&self) -> &'static str;
+
+    /// Actions to take for `event`, usually none
+    fn on_event(&self, event: &MonitorEvent) -> Vec<Action>;
+
+    /// Checks the strategy's buys must pass before being executed
+    fn safety(&self) -> Option<&SafetyConfig> {
+        None
+    }
+
+    /// How the actions are executed, overriding the config the strategy was
+    /// registered with, for strategies changing it at runtime
+    fn action_config(&self) -> Option<ActionConfig> {
+        None
+    }
+}
+
+/// Risk appetite of the twitter strategy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RiskProfile {
     Conservative,
     Medium,
     Radical,
 }
 
-impl Strategy {}
+impl FromStr for RiskProfile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "conservative" => Ok(RiskProfile::Conservative),
+            "medium" => Ok(RiskProfile::Medium),
+            "radical" => Ok(RiskProfile::Radical),
+            _ => Err(anyhow!("unknown risk profile {:?}", s)),
+        }
+    }
+}
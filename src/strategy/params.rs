@@ -0,0 +1,334 @@
+//! Strategy parameters changed at runtime.
+//!
+//! The sniper and exits start from their `SNIPER_*` / `EXIT_*` variables,
+//! then take the values of the TOML file at `STRATEGY_PARAMS` (default
+//! `params.toml`, see `params.example.toml`), one table per strategy, and
+//! last the values set with the Telegram `/set <strategy>.<param> <value>`
+//! command. The file is checked for changes every [`RELOAD_INTERVAL`], like
+//! the risk limits.
+//!
+//! A change is applied to a copy of the starting config and swapped in
+//! whole, so the monitors keep running and a strategy never sees half an
+//! update. An invalid file or `/set` is rejected and the previous parameters
+//! kept. `none` unsets an optional parameter.
+//!
+//! - `sniper`: `buy_sol`, `buy_supply_pct`, `slippage`, `name_regex`,
+//!   `symbol_regex`, `min_dev_buy_sol`, `max_dev_buy_sol`, `max_curve_pct`,
+//!   `max_price_impact_bps`, `max_prior_rugs`, `max_bundled_buyers`
+//! - `exits`: `take_profit_pct`, `stop_loss_pct`, `trailing_stop`, `slippage`
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Result};
+use arc_swap::ArcSwap;
+use tracing::{error, info};
+
+use crate::{
+    engine::{Action, ActionConfig},
+    monitor::events::MonitorEvent,
+    risk::RELOAD_INTERVAL,
+    safety::SafetyConfig,
+};
+
+use super::{exits::ExitConfig, sniper::SniperConfig, Strategy};
+
+const DEFAULT_PARAMS_PATH: &str = "params.toml";
+
+static LIVE_PARAMS: OnceLock<Arc<LiveParams>> = OnceLock::new();
+
+/// A config swapped whole at runtime
+pub type Live<T> = Arc<ArcSwap<T>>;
+
+/// Parses `value` of the parameter `key`
+pub(crate) fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("invalid {} {:?}", key, value))
+}
+
+/// Parses `value` of the optional parameter `key`, `none` unsetting it
+pub(crate) fn parse_option<T: FromStr>(key: &str, value: &str) -> Result<Option<T>> {
+    if value.trim().eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+    parse_value(key, value).map(Some)
+}
+
+/// `<strategy>.<param>` -> value of every table of the TOML `text`
+fn parse_file(text: &str) -> Result<BTreeMap<String, String>> {
+    let tables: BTreeMap<String, toml::value::Table> = toml::from_str(text)?;
+    let mut values = BTreeMap::new();
+    for (strategy, table) in tables {
+        for (param, value) in table {
+            let value = match value {
+                toml::Value::String(s) => s,
+                value => value.to_string(),
+            };
+            values.insert(format!("{}.{}", strategy, param), value);
+        }
+    }
+    Ok(values)
+}
+
+/// Values from the file and `/set`
+#[derive(Default)]
+struct Values {
+    modified: Option<SystemTime>,
+    file: BTreeMap<String, String>,
+    set: BTreeMap<String, String>,
+}
+
+/// The runtime parameters of the running strategies
+pub struct LiveParams {
+    path: PathBuf,
+    sniper: Option<(SniperConfig, Live<SniperConfig>)>,
+    exits: Option<(ExitConfig, Live<ExitConfig>)>,
+    values: Mutex<Values>,
+}
+
+impl LiveParams {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            sniper: None,
+            exits: None,
+            values: Mutex::new(Values::default()),
+        }
+    }
+
+    /// Parameters of the file at `STRATEGY_PARAMS`
+    pub fn from_env() -> Self {
+        dotenv::dotenv().ok();
+        let path = env::var("STRATEGY_PARAMS")
+            .map(PathBuf::from)
+            .unwrap_or(PathBuf::from(DEFAULT_PARAMS_PATH));
+        Self::new(path)
+    }
+
+    /// Makes the sniper's parameters live, starting from `base`
+    pub fn sniper(&mut self, base: SniperConfig) -> Live<SniperConfig> {
+        let live = Arc::new(ArcSwap::from_pointee(base.clone()));
+        self.sniper = Some((base, live.clone()));
+        live
+    }
+
+    /// Makes the exits' parameters live, starting from `base`
+    pub fn exits(&mut self, base: ExitConfig) -> Live<ExitConfig> {
+        let live = Arc::new(ArcSwap::from_pointee(base.clone()));
+        self.exits = Some((base, live.clone()));
+        live
+    }
+
+    /// The starting configs with `values` applied in order
+    fn build<'a>(
+        &self,
+        values: impl Iterator<Item = (&'a String, &'a String)>,
+    ) -> Result<(Option<SniperConfig>, Option<ExitConfig>)> {
+        let mut sniper = self.sniper.as_ref().map(|(base, _)| base.clone());
+        let mut exits = self.exits.as_ref().map(|(base, _)| base.clone());
+        for (key, value) in values {
+            let (strategy, param) = key
+                .split_once('.')
+                .ok_or(anyhow!("{:?} is not <strategy>.<param>", key))?;
+            // 没有运行的策略忽略其参数
+            match strategy {
+                "sniper" => {
+                    if let Some(config) = &mut sniper {
+                        config.set_param(param, value)?;
+                    }
+                }
+                "exits" => {
+                    if let Some(config) = &mut exits {
+                        config.set_param(param, value)?;
+                    }
+                }
+                _ => return Err(anyhow!("unknown strategy {:?}", strategy)),
+            }
+        }
+        if let Some(config) = &exits {
+            config.validate()?;
+        }
+        Ok((sniper, exits))
+    }
+
+    /// Builds the configs from `values` and swaps them in
+    fn apply(&self, values: &Values) -> Result<()> {
+        let (sniper, exits) = self.build(values.file.iter().chain(values.set.iter()))?;
+        if let (Some(config), Some((_, live))) = (sniper, &self.sniper) {
+            live.store(Arc::new(config));
+        }
+        if let (Some(config), Some((_, live))) = (exits, &self.exits) {
+            live.store(Arc::new(config));
+        }
+        Ok(())
+    }
+
+    /// Sets `key`, `<strategy>.<param>`, to `value` until it's set again
+    pub fn set(&self, key: &str, value: &str) -> Result<()> {
+        let mut values = self.values.lock().unwrap();
+        let previous = values.set.insert(key.to_string(), value.to_string());
+        let result = self.apply(&values);
+        if result.is_err() {
+            match previous {
+                Some(previous) => values.set.insert(key.to_string(), previous),
+                None => values.set.remove(key),
+            };
+        } else {
+            info!("strategy parameter {} set to {}", key, value);
+        }
+        result
+    }
+
+    /// Reloads the file if it changed since the last check
+    fn reload(&self, first: bool) {
+        let mut values = self.values.lock().unwrap();
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        if !first && modified == values.modified {
+            return;
+        }
+        // 出错时也记下修改时间，不重复报错
+        values.modified = modified;
+        let file = match load(&self.path) {
+            Ok(file) => file,
+            Err(e) => {
+                error!("keeping the previous strategy parameters {:?}", e);
+                return;
+            }
+        };
+        let previous = std::mem::replace(&mut values.file, file);
+        match self.apply(&values) {
+            Ok(()) => info!("strategy parameters loaded from {}", self.path.display()),
+            Err(e) => {
+                error!(
+                    "keeping the previous strategy parameters, invalid {}: {:?}",
+                    self.path.display(),
+                    e
+                );
+                values.file = previous;
+            }
+        }
+    }
+}
+
+/// Values of the file at `path`, none if it doesn't exist
+fn load(path: &Path) -> Result<BTreeMap<String, String>> {
+    match fs::read_to_string(path) {
+        Ok(text) => parse_file(&text)
+            .map_err(|e| anyhow!("invalid strategy params {}: {}", path.display(), e)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(anyhow!("failed to read {}: {}", path.display(), e)),
+    }
+}
+
+/// Loads the file and makes `params` the ones `/set` changes
+///
+/// Call once, after making the strategies live.
+pub fn install(params: LiveParams) -> Arc<LiveParams> {
+    let params = Arc::new(params);
+    params.reload(true);
+    LIVE_PARAMS.get_or_init(|| params).clone()
+}
+
+/// The installed parameters, `None` before [`install`]
+pub fn live_params() -> Option<Arc<LiveParams>> {
+    LIVE_PARAMS.get().cloned()
+}
+
+/// Reloads the file whenever it changes, forever
+pub async fn watch(params: Arc<LiveParams>) {
+    let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+    loop {
+        interval.tick().await;
+        params.reload(false);
+    }
+}
+
+/// The sniper with its live parameters
+///
+/// The safety checks aren't live, they're read once.
+pub struct LiveSniper {
+    config: Live<SniperConfig>,
+    safety: Option<SafetyConfig>,
+}
+
+impl LiveSniper {
+    pub fn new(config: Live<SniperConfig>) -> Self {
+        let safety = config.load().safety.clone();
+        Self { config, safety }
+    }
+}
+
+impl Strategy for LiveSniper {
+    fn name(&self) -> &'static str {
+        self.config.load().name()
+    }
+
+    fn on_event(&self, event: &MonitorEvent) -> Vec<Action> {
+        self.config.load().on_event(event)
+    }
+
+    fn safety(&self) -> Option<&SafetyConfig> {
+        self.safety.as_ref()
+    }
+
+    fn action_config(&self) -> Option<ActionConfig> {
+        Some(self.config.load().action_config())
+    }
+}
+
+#[test]
+fn test_live_params() {
+    let mut params = LiveParams::new(PathBuf::from("missing_params.toml"));
+    let sniper = params.sniper(SniperConfig::default());
+    let exits = params.exits(ExitConfig {
+        stop_loss_pct: Some(30.0),
+        ..ExitConfig::default()
+    });
+
+    let file = parse_file(
+        r#"
+        [sniper]
+        buy_sol = 0.05
+        name_regex = "(?i)cat"
+
+        [exits]
+        take_profit_pct = 100
+        "#,
+    )
+    .unwrap();
+    assert_eq!(file["sniper.name_regex"], "(?i)cat");
+    params.values.lock().unwrap().file = file;
+    params.apply(&params.values.lock().unwrap()).unwrap();
+    assert_eq!(sniper.load().buy_amount, 50_000_000);
+    assert!(sniper.load().name_pattern.is_some());
+    assert_eq!(exits.load().take_profit_pct, Some(100.0));
+
+    // /set 覆盖文件里的值
+    params.set("sniper.buy_sol", "0.1").unwrap();
+    params.set("exits.stop_loss_pct", "none").unwrap();
+    assert_eq!(sniper.load().buy_amount, 100_000_000);
+    assert_eq!(exits.load().stop_loss_pct, None);
+
+    // 无效的修改不生效
+    assert!(params.set("sniper.slippage", "abc").is_err());
+    assert!(params.set("sniper.unknown", "1").is_err());
+    assert!(params.set("other.buy_sol", "1").is_err());
+    assert!(params.set("exits.take_profit_pct", "none").is_err());
+    assert_eq!(sniper.load().slippage, SniperConfig::default().slippage);
+    assert_eq!(exits.load().take_profit_pct, Some(100.0));
+    assert!(!params
+        .values
+        .lock()
+        .unwrap()
+        .set
+        .contains_key("sniper.slippage"));
+}
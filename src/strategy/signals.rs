@@ -0,0 +1,164 @@
+//! Buys of the mints called in the watched Discord and Telegram channels.
+//!
+//! A [`Strategy`] on the signal events of [`crate::monitor::discord`] and
+//! [`crate::monitor::telegram_channels`], buying the mint of every call from
+//! an enabled source. It is off unless `SIGNAL_BUY_ENABLED=true`.
+//!
+//! - `SIGNAL_SOURCES`: comma separated sources traded on, `discord` and / or
+//!   `telegram` (default both)
+//! - `SIGNAL_BUY_SOL`: SOL spent per buy (default 0.01)
+//! - `SIGNAL_SLIPPAGE`: slippage in percent (default 10)
+//! - `SIGNAL_SIMULATE`: only simulate the buys
+//! - `SIGNAL_SENDER`: path the buys are submitted through, see
+//!   [`crate::tx::sender`] (default rpc)
+//!
+//! With `SAFETY_CHECKS_ENABLED=true` tokens failing the [`crate::safety`]
+//! checks are skipped. The budget guard buys a mint at most once per
+//! cooldown, so a call reposted in several channels buys once.
+
+use std::env;
+
+use anyhow::{anyhow, Result};
+use solana_sdk::{native_token::sol_to_lamports, pubkey::Pubkey};
+use tracing::{error, info};
+
+use crate::{
+    engine::{Action, ActionConfig},
+    monitor::events::{MonitorEvent, SignalSource},
+    safety::SafetyConfig,
+    tx::sender::Sender,
+};
+
+use super::{parse_env, Strategy};
+
+const DEFAULT_BUY_SOL: f64 = 0.01;
+const DEFAULT_SLIPPAGE: u64 = 10;
+
+#[derive(Debug, Clone)]
+pub struct SignalConfig {
+    pub sources: Vec<SignalSource>,
+    /// Lamports spent per buy
+    pub buy_amount: u64,
+    /// Slippage in percent
+    pub slippage: u64,
+    pub simulate: bool,
+    pub sender: Sender,
+    /// Skips tokens failing the safety checks when set
+    pub safety: Option<SafetyConfig>,
+}
+
+impl Default for SignalConfig {
+    fn default() -> Self {
+        Self {
+            sources: vec![SignalSource::Discord, SignalSource::Telegram],
+            buy_amount: sol_to_lamports(DEFAULT_BUY_SOL),
+            slippage: DEFAULT_SLIPPAGE,
+            simulate: false,
+            sender: Sender::Rpc,
+            safety: None,
+        }
+    }
+}
+
+impl SignalConfig {
+    /// Reads the `SIGNAL_*` variables, `None` unless `SIGNAL_BUY_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("SIGNAL_BUY_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        let default = Self::default();
+        let sources = match env::var("SIGNAL_SOURCES") {
+            Ok(sources) => sources
+                .split(',')
+                .map(|source| source.trim().parse())
+                .collect::<Result<Vec<_>>>()
+                .map_err(|e| anyhow!("invalid SIGNAL_SOURCES: {}", e))?,
+            Err(_) => default.sources,
+        };
+        Ok(Some(Self {
+            sources,
+            buy_amount: parse_env::<f64>("SIGNAL_BUY_SOL")?
+                .map(sol_to_lamports)
+                .unwrap_or(default.buy_amount),
+            slippage: parse_env("SIGNAL_SLIPPAGE")?.unwrap_or(default.slippage),
+            simulate: parse_env("SIGNAL_SIMULATE")?.unwrap_or(default.simulate),
+            sender: parse_env("SIGNAL_SENDER")?.unwrap_or(default.sender),
+            safety: SafetyConfig::from_env()?,
+        }))
+    }
+
+    /// How the engine executes the buys
+    pub fn action_config(&self) -> ActionConfig {
+        ActionConfig {
+            slippage: self.slippage,
+            simulate: self.simulate,
+            sender: self.sender,
+        }
+    }
+}
+
+impl Strategy for SignalConfig {
+    fn name(&self) -> &'static str {
+        "signals"
+    }
+
+    fn on_event(&self, event: &MonitorEvent) -> Vec<Action> {
+        let MonitorEvent::Signal(event) = event else {
+            return vec![];
+        };
+        if !self.sources.contains(&event.source) {
+            return vec![];
+        }
+        match event.mint.parse::<Pubkey>() {
+            Ok(mint) => {
+                info!(
+                    "buying {} called by {} in {} {}",
+                    mint, event.author, event.source, event.channel
+                );
+                vec![Action::Buy {
+                    mint,
+                    lamports: self.buy_amount,
+                }]
+            }
+            Err(e) => {
+                error!("invalid mint {} {:?}", event.mint, e);
+                vec![]
+            }
+        }
+    }
+
+    fn safety(&self) -> Option<&SafetyConfig> {
+        self.safety.as_ref()
+    }
+}
+
+#[test]
+fn test_signal_buys() {
+    use crate::monitor::events::SignalEvent;
+
+    let mint = Pubkey::new_unique();
+    let signal = |source| {
+        MonitorEvent::Signal(SignalEvent {
+            source,
+            channel: "calls".to_string(),
+            message_id: "1".to_string(),
+            author: "caller".to_string(),
+            text: format!("CA {}", mint),
+            mint: mint.to_string(),
+            received_at: None,
+        })
+    };
+    let config = SignalConfig {
+        sources: vec![SignalSource::Telegram],
+        ..SignalConfig::default()
+    };
+    assert!(config.on_event(&signal(SignalSource::Discord)).is_empty());
+    let actions = config.on_event(&signal(SignalSource::Telegram));
+    assert!(matches!(
+        actions.as_slice(),
+        [Action::Buy { mint: bought, lamports }]
+            if *bought == mint && *lamports == config.buy_amount
+    ));
+    assert!("twitter".parse::<SignalSource>().is_err());
+}
@@ -0,0 +1,478 @@
+//! Automatic buys of freshly created Pump.fun tokens.
+//!
+//! The sniper is a [`Strategy`] on the create events of
+//! `listen_pumpfun_create`, buying every token that passes the filters. It is
+//! off unless `SNIPER_ENABLED=true`.
+//!
+//! - `SNIPER_NAME_REGEX` / `SNIPER_SYMBOL_REGEX`: only tokens matching these
+//! - `SNIPER_CREATOR_BLOCKLIST`: comma separated creators to ignore
+//! - `SNIPER_MIN_DEV_BUY_SOL` / `SNIPER_MAX_DEV_BUY_SOL`: bounds on the
+//!   creator's initial buy, a create without one counts as 0
+//! - `SNIPER_MAX_CURVE_PCT`: skip tokens whose curve the creator's buy already
+//!   filled past this percentage
+//! - `SNIPER_MAX_PRICE_IMPACT_BPS`: skip tokens where our buy would move the
+//!   price more than this, in basis points
+//! - `SNIPER_MAX_PRIOR_RUGS` / `SNIPER_MAX_BUNDLED_BUYERS`: bounds on the
+//!   creator's profile, see [`crate::creator`]; creates without a profile
+//!   pass
+//! - `SNIPER_BUY_SOL`: SOL spent per buy (default 0.01)
+//! - `SNIPER_BUY_SUPPLY_PCT`: buy this share of the supply instead, in
+//!   percent, paying at most `SNIPER_BUY_SOL`
+//! - `SNIPER_SLIPPAGE`: slippage in percent (default 10)
+//! - `SNIPER_SIMULATE`: only simulate the buys
+//! - `SNIPER_SENDER`: path the buys are submitted through, see
+//!   [`crate::tx::sender`] (default rpc)
+//!
+//! With `BUNDLE_SNIPE_ENABLED=true` each buy is split across several wallets
+//! and submitted as one Jito bundle, see [`super::bundle_snipe`].
+//!
+//! With `SAFETY_CHECKS_ENABLED=true` tokens failing the [`crate::safety`]
+//! checks are skipped too.
+//!
+//! Buys still go through the budget guard, so a mint is bought at most once
+//! per cooldown.
+//!
+//! The buy size, slippage and filters can be changed while running, see
+//! [`super::params`].
+
+use std::{collections::HashSet, env};
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use solana_sdk::{native_token::sol_to_lamports, pubkey::Pubkey};
+use thiserror::Error;
+use tracing::{error, info};
+
+use crate::{
+    engine::{Action, ActionConfig},
+    monitor::events::{MonitorEvent, TokenCreateEvent},
+    pumpfun::{accounts::BondingCurveAccount, operation::supply_tokens},
+    safety::SafetyConfig,
+    tx::sender::Sender,
+};
+
+use super::{
+    bundle_snipe::BundleSnipeConfig,
+    params::{parse_option, parse_value},
+    parse_env, Strategy,
+};
+
+const DEFAULT_BUY_SOL: f64 = 0.01;
+const DEFAULT_SLIPPAGE: u64 = 10;
+
+/// Why a create event wasn't sniped
+#[derive(Debug, Error, PartialEq)]
+pub enum SkipReason {
+    #[error("name {0:?} doesn't match")]
+    Name(String),
+    #[error("symbol {0:?} doesn't match")]
+    Symbol(String),
+    #[error("creator {0} is blocklisted")]
+    BlockedCreator(String),
+    #[error("dev buy of {sol_cost} lamports is below {min}")]
+    DevBuyTooSmall { sol_cost: u64, min: u64 },
+    #[error("dev buy of {sol_cost} lamports is above {max}")]
+    DevBuyTooLarge { sol_cost: u64, max: u64 },
+    #[error("curve is {pct:.1}% complete, above {max}%")]
+    CurveTooAdvanced { pct: f64, max: f64 },
+    #[error("price impact of {bps} bps is above {max}")]
+    PriceImpactTooHigh { bps: u64, max: u64 },
+    #[error("creator sold {rugs} prior launches, above {max}")]
+    CreatorRugs { rugs: u32, max: u32 },
+    #[error("{buyers} bundled buyers, above {max}")]
+    Bundled { buyers: usize, max: usize },
+}
+
+#[derive(Debug, Clone)]
+pub struct SniperConfig {
+    pub name_pattern: Option<Regex>,
+    pub symbol_pattern: Option<Regex>,
+    pub creator_blocklist: HashSet<String>,
+    /// Smallest creator buy accepted, in lamports
+    pub min_dev_buy: u64,
+    /// Largest creator buy accepted, in lamports
+    pub max_dev_buy: Option<u64>,
+    /// Largest share of the curve sold at creation, in percent
+    pub max_curve_pct: Option<f64>,
+    /// Largest price impact of our buy, in basis points
+    pub max_price_impact_bps: Option<u64>,
+    /// Most prior launches the creator may have sold
+    pub max_prior_rugs: Option<u32>,
+    /// Most buyers of the creation block the creator may have funded
+    pub max_bundled_buyers: Option<usize>,
+    /// Lamports spent per buy, the most spent with `buy_tokens`
+    pub buy_amount: u64,
+    /// Raw tokens bought per buy when set, instead of spending `buy_amount`
+    pub buy_tokens: Option<u64>,
+    /// Slippage in percent
+    pub slippage: u64,
+    pub simulate: bool,
+    pub sender: Sender,
+    /// Skips tokens failing the safety checks when set
+    pub safety: Option<SafetyConfig>,
+    /// Splits the buys across several wallets in a bundle when set
+    pub bundle: Option<BundleSnipeConfig>,
+}
+
+impl Default for SniperConfig {
+    fn default() -> Self {
+        Self {
+            name_pattern: None,
+            symbol_pattern: None,
+            creator_blocklist: HashSet::new(),
+            min_dev_buy: 0,
+            max_dev_buy: None,
+            max_curve_pct: None,
+            max_price_impact_bps: None,
+            max_prior_rugs: None,
+            max_bundled_buyers: None,
+            buy_amount: sol_to_lamports(DEFAULT_BUY_SOL),
+            buy_tokens: None,
+            slippage: DEFAULT_SLIPPAGE,
+            simulate: false,
+            sender: Sender::Rpc,
+            safety: None,
+            bundle: None,
+        }
+    }
+}
+
+fn regex_env(key: &str) -> Result<Option<Regex>> {
+    match env::var(key) {
+        Ok(v) => Ok(Some(
+            Regex::new(&v).map_err(|e| anyhow!("invalid {}: {}", key, e))?,
+        )),
+        Err(_) => Ok(None),
+    }
+}
+
+impl SniperConfig {
+    /// Reads the `SNIPER_*` variables, `None` unless `SNIPER_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        dotenv::dotenv().ok();
+        if !parse_env::<bool>("SNIPER_ENABLED")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        Self::read_env().map(Some)
+    }
+
+    /// Reads the `SNIPER_*` variables whether or not the sniper is enabled
+    pub fn read_env() -> Result<Self> {
+        dotenv::dotenv().ok();
+        let default = Self::default();
+        let mut creator_blocklist = HashSet::new();
+        for creator in env::var("SNIPER_CREATOR_BLOCKLIST")
+            .unwrap_or_default()
+            .split(',')
+        {
+            let creator = creator.trim();
+            if !creator.is_empty() {
+                creator.parse::<Pubkey>().map_err(|e| {
+                    anyhow!("invalid SNIPER_CREATOR_BLOCKLIST {:?}: {}", creator, e)
+                })?;
+                creator_blocklist.insert(creator.to_string());
+            }
+        }
+        Ok(Self {
+            name_pattern: regex_env("SNIPER_NAME_REGEX")?,
+            symbol_pattern: regex_env("SNIPER_SYMBOL_REGEX")?,
+            creator_blocklist,
+            min_dev_buy: parse_env::<f64>("SNIPER_MIN_DEV_BUY_SOL")?
+                .map(sol_to_lamports)
+                .unwrap_or(default.min_dev_buy),
+            max_dev_buy: parse_env::<f64>("SNIPER_MAX_DEV_BUY_SOL")?.map(sol_to_lamports),
+            max_curve_pct: parse_env("SNIPER_MAX_CURVE_PCT")?,
+            max_price_impact_bps: parse_env("SNIPER_MAX_PRICE_IMPACT_BPS")?,
+            max_prior_rugs: parse_env("SNIPER_MAX_PRIOR_RUGS")?,
+            max_bundled_buyers: parse_env("SNIPER_MAX_BUNDLED_BUYERS")?,
+            buy_amount: parse_env::<f64>("SNIPER_BUY_SOL")?
+                .map(sol_to_lamports)
+                .unwrap_or(default.buy_amount),
+            buy_tokens: parse_env::<f64>("SNIPER_BUY_SUPPLY_PCT")?
+                .map(supply_tokens)
+                .transpose()
+                .map_err(|e| anyhow!("invalid SNIPER_BUY_SUPPLY_PCT: {}", e))?,
+            slippage: parse_env("SNIPER_SLIPPAGE")?.unwrap_or(default.slippage),
+            simulate: parse_env("SNIPER_SIMULATE")?.unwrap_or(default.simulate),
+            sender: parse_env("SNIPER_SENDER")?.unwrap_or(default.sender),
+            safety: SafetyConfig::from_env()?,
+            bundle: BundleSnipeConfig::from_env()?,
+        })
+    }
+
+    /// Sets the runtime parameter `key`, see [`super::params`]
+    pub fn set_param(&mut self, key: &str, value: &str) -> Result<()> {
+        let regex = |value: &str| -> Result<Option<Regex>> {
+            parse_option::<String>(key, value)?
+                .map(|pattern| Regex::new(&pattern).map_err(|e| anyhow!("invalid {}: {}", key, e)))
+                .transpose()
+        };
+        match key {
+            "buy_sol" => self.buy_amount = sol_to_lamports(parse_value(key, value)?),
+            "buy_supply_pct" => {
+                self.buy_tokens = parse_option::<f64>(key, value)?
+                    .map(supply_tokens)
+                    .transpose()?
+            }
+            "slippage" => self.slippage = parse_value(key, value)?,
+            "name_regex" => self.name_pattern = regex(value)?,
+            "symbol_regex" => self.symbol_pattern = regex(value)?,
+            "min_dev_buy_sol" => {
+                self.min_dev_buy = parse_option::<f64>(key, value)?.map_or(0, sol_to_lamports)
+            }
+            "max_dev_buy_sol" => {
+                self.max_dev_buy = parse_option::<f64>(key, value)?.map(sol_to_lamports)
+            }
+            "max_curve_pct" => self.max_curve_pct = parse_option(key, value)?,
+            "max_price_impact_bps" => self.max_price_impact_bps = parse_option(key, value)?,
+            "max_prior_rugs" => self.max_prior_rugs = parse_option(key, value)?,
+            "max_bundled_buyers" => self.max_bundled_buyers = parse_option(key, value)?,
+            _ => return Err(anyhow!("unknown sniper parameter {:?}", key)),
+        }
+        Ok(())
+    }
+
+    /// Applies the filters to `event`
+    pub fn check(&self, event: &TokenCreateEvent) -> Result<(), SkipReason> {
+        if let Some(pattern) = &self.name_pattern {
+            if !pattern.is_match(&event.name) {
+                return Err(SkipReason::Name(event.name.clone()));
+            }
+        }
+        if let Some(pattern) = &self.symbol_pattern {
+            if !pattern.is_match(&event.symbol) {
+                return Err(SkipReason::Symbol(event.symbol.clone()));
+            }
+        }
+        if self.creator_blocklist.contains(&event.creator) {
+            return Err(SkipReason::BlockedCreator(event.creator.clone()));
+        }
+        let sol_cost = event.dev_buy.map_or(0, |dev_buy| dev_buy.sol_cost);
+        if sol_cost < self.min_dev_buy {
+            return Err(SkipReason::DevBuyTooSmall {
+                sol_cost,
+                min: self.min_dev_buy,
+            });
+        }
+        if let Some(max) = self.max_dev_buy {
+            if sol_cost > max {
+                return Err(SkipReason::DevBuyTooLarge { sol_cost, max });
+            }
+        }
+        // 创建时曲线只有创建者的买入
+        let mut curve = BondingCurveAccount::fresh();
+        if let Some(dev_buy) = event.dev_buy {
+            let _ = curve.apply_buy(dev_buy.token_amount.min(curve.real_token_reserves));
+        }
+        if let Some(max) = self.max_curve_pct {
+            let pct = curve.get_progress_pct();
+            if pct > max {
+                return Err(SkipReason::CurveTooAdvanced { pct, max });
+            }
+        }
+        if let Some(max) = self.max_price_impact_bps {
+            // 曲线已完成时无法买入，视为最大冲击
+            let bps = curve
+                .get_price_impact_bps(self.buy_amount)
+                .unwrap_or(10_000);
+            if bps > max {
+                return Err(SkipReason::PriceImpactTooHigh { bps, max });
+            }
+        }
+        if let Some(creator) = &event.creator_profile {
+            if let Some(max) = self.max_prior_rugs {
+                if creator.prior_rugs > max {
+                    return Err(SkipReason::CreatorRugs {
+                        rugs: creator.prior_rugs,
+                        max,
+                    });
+                }
+            }
+            if let Some(max) = self.max_bundled_buyers {
+                let buyers = creator.bundled_buyers.len();
+                if buyers > max {
+                    return Err(SkipReason::Bundled { buyers, max });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SniperConfig {
+    /// How the engine executes the sniper's buys
+    pub fn action_config(&self) -> ActionConfig {
+        ActionConfig {
+            slippage: self.slippage,
+            simulate: self.simulate,
+            sender: self.sender,
+        }
+    }
+}
+
+impl Strategy for SniperConfig {
+    fn name(&self) -> &'static str {
+        "sniper"
+    }
+
+    fn on_event(&self, event: &MonitorEvent) -> Vec<Action> {
+        let MonitorEvent::Create(event) = event else {
+            return vec![];
+        };
+        if let Err(reason) = self.check(event) {
+            info!("not sniping {}: {}", event.mint, reason);
+            return vec![];
+        }
+        match event.mint.parse::<Pubkey>() {
+            Ok(mint) => {
+                info!("sniping {} ({})", event.symbol, mint);
+                match &self.bundle {
+                    Some(bundle) => vec![Action::BundleBuy {
+                        mint,
+                        amounts: bundle.split(self.buy_amount),
+                    }],
+                    None => match self.buy_tokens {
+                        Some(token_amount) => vec![Action::BuyTokens {
+                            mint,
+                            token_amount,
+                            max_lamports: self.buy_amount,
+                        }],
+                        None => vec![Action::Buy {
+                            mint,
+                            lamports: self.buy_amount,
+                        }],
+                    },
+                }
+            }
+            Err(e) => {
+                error!("invalid mint {} {:?}", event.mint, e);
+                vec![]
+            }
+        }
+    }
+
+    fn safety(&self) -> Option<&SafetyConfig> {
+        self.safety.as_ref()
+    }
+}
+
+#[test]
+fn test_sniper_filters() {
+    use crate::monitor::events::{CreatorProfile, DevBuy};
+
+    let event = TokenCreateEvent {
+        signature: String::new(),
+        name: "Moon Cat".to_string(),
+        symbol: "MCAT".to_string(),
+        uri: String::new(),
+        mint: Pubkey::new_unique().to_string(),
+        bonding_curve: String::new(),
+        associated_bonding_curve: String::new(),
+        creator: "creator".to_string(),
+        dev_buy: Some(DevBuy {
+            token_amount: 1,
+            sol_cost: 2_000_000_000,
+            supply_pct: 1.0,
+        }),
+        dev_alert: false,
+        creator_profile: None,
+        received_at: None,
+    };
+    let mut config = SniperConfig {
+        name_pattern: Some(Regex::new("(?i)cat").unwrap()),
+        symbol_pattern: Some(Regex::new("^M").unwrap()),
+        min_dev_buy: 1_000_000_000,
+        max_dev_buy: Some(3_000_000_000),
+        ..SniperConfig::default()
+    };
+    assert_eq!(config.check(&event), Ok(()));
+
+    assert_eq!(
+        config.on_event(&MonitorEvent::Create(event.clone())),
+        vec![Action::Buy {
+            mint: event.mint.parse().unwrap(),
+            lamports: config.buy_amount,
+        }]
+    );
+
+    // 按供应量比例买入时，buy_sol 是花费上限
+    let mut by_supply = config.clone();
+    by_supply.set_param("buy_supply_pct", "1").unwrap();
+    assert_eq!(
+        by_supply.on_event(&MonitorEvent::Create(event.clone())),
+        vec![Action::BuyTokens {
+            mint: event.mint.parse().unwrap(),
+            token_amount: 10_000_000_000_000,
+            max_lamports: config.buy_amount,
+        }]
+    );
+    assert!(by_supply.set_param("buy_supply_pct", "0").is_err());
+
+    config.max_dev_buy = Some(1_500_000_000);
+    assert!(matches!(
+        config.check(&event),
+        Err(SkipReason::DevBuyTooLarge { .. })
+    ));
+    config.max_dev_buy = None;
+
+    // 约 2 SOL 的创建者买入占曲线 7.6%
+    let late = TokenCreateEvent {
+        dev_buy: Some(DevBuy {
+            token_amount: 60_000_000_000_000,
+            sol_cost: 2_000_000_000,
+            supply_pct: 6.0,
+        }),
+        ..event.clone()
+    };
+    config.max_curve_pct = Some(5.0);
+    assert!(matches!(
+        config.check(&late),
+        Err(SkipReason::CurveTooAdvanced { .. })
+    ));
+    config.max_curve_pct = Some(10.0);
+    config.max_price_impact_bps = Some(100);
+    assert_eq!(config.check(&late), Ok(()));
+    config.buy_amount = 5_000_000_000;
+    assert!(matches!(
+        config.check(&late),
+        Err(SkipReason::PriceImpactTooHigh { .. })
+    ));
+    config.max_price_impact_bps = None;
+
+    let no_dev_buy = TokenCreateEvent {
+        dev_buy: None,
+        ..event.clone()
+    };
+    assert!(matches!(
+        config.check(&no_dev_buy),
+        Err(SkipReason::DevBuyTooSmall { sol_cost: 0, .. })
+    ));
+
+    // 没有创建者画像时不过滤
+    config.max_bundled_buyers = Some(1);
+    assert_eq!(config.check(&event), Ok(()));
+    let bundled = TokenCreateEvent {
+        creator_profile: Some(CreatorProfile {
+            bundled_buyers: vec!["a".to_string(), "b".to_string()],
+            ..CreatorProfile::default()
+        }),
+        ..event.clone()
+    };
+    assert_eq!(
+        config.check(&bundled),
+        Err(SkipReason::Bundled { buyers: 2, max: 1 })
+    );
+    config.max_bundled_buyers = None;
+
+    config.creator_blocklist.insert("creator".to_string());
+    assert_eq!(
+        config.check(&event),
+        Err(SkipReason::BlockedCreator("creator".to_string()))
+    );
+
+    config.symbol_pattern = Some(Regex::new("^X").unwrap());
+    assert_eq!(
+        config.check(&event),
+        Err(SkipReason::Symbol("MCAT".to_string()))
+    );
+}
@@ -0,0 +1,144 @@
+//! Timeline of a trade, from the data that triggered it to the send.
+//!
+//! A strategy runs each trade inside [`triggered`], in a `trade` tracing span,
+//! with the instant the triggering block or transaction arrived in the
+//! monitor. The trade is then stamped when the strategy decides
+//! ([`mark_decided`], by default when the scope starts), when the send path
+//! has the instructions built ([`mark_built`]), and when the RPC accepts the
+//! first transaction ([`mark_sent`]). The deltas are logged and exported as
+//! `bot_trade_stage_seconds`, the total as `bot_detection_to_send_seconds`.
+//!
+//! Trades outside of a scope count as [`MANUAL`] and are not timed.
+
+use std::{cell::Cell, future::Future, time::Instant};
+
+use tracing::{info, info_span, Instrument};
+
+use crate::metrics;
+
+/// Strategy of trades made outside of [`triggered`]
+pub const MANUAL: &str = "manual";
+
+struct Timeline {
+    strategy: &'static str,
+    received_at: Instant,
+    decided_at: Cell<Instant>,
+    built_at: Cell<Option<Instant>>,
+    sent_at: Cell<Option<Instant>>,
+}
+
+tokio::task_local! {
+    static TIMELINE: Timeline;
+}
+
+/// Runs `fut` as the trade of `strategy` reacting to data received at
+/// `received_at`
+///
+/// Spawned tasks don't inherit it, they need their own scope.
+pub async fn triggered<F: Future>(
+    strategy: &'static str,
+    received_at: Instant,
+    fut: F,
+) -> F::Output {
+    let timeline = Timeline {
+        strategy,
+        received_at,
+        decided_at: Cell::new(Instant::now()),
+        built_at: Cell::new(None),
+        sent_at: Cell::new(None),
+    };
+    TIMELINE
+        .scope(timeline, fut)
+        .instrument(info_span!("trade", strategy))
+        .await
+}
+
+/// Strategy of the current task, [`MANUAL`] outside of [`triggered`]
+pub fn strategy() -> &'static str {
+    TIMELINE
+        .try_with(|timeline| timeline.strategy)
+        .unwrap_or(MANUAL)
+}
+
+/// Stamps the strategy's decision to trade, e.g. after its checks passed
+pub fn mark_decided() {
+    let _ = TIMELINE.try_with(|timeline| timeline.decided_at.set(Instant::now()));
+}
+
+/// Stamps the instructions of the trade being built, only the first time
+pub fn mark_built() {
+    let _ = TIMELINE.try_with(|timeline| {
+        if timeline.built_at.get().is_none() {
+            timeline.built_at.set(Some(Instant::now()));
+        }
+    });
+}
+
+/// Stamps the RPC accepting the trade's first transaction, logging and
+/// exporting the stages
+pub fn mark_sent() {
+    let _ = TIMELINE.try_with(|timeline| {
+        if timeline.sent_at.get().is_some() {
+            return;
+        }
+        let sent_at = Instant::now();
+        timeline.sent_at.set(Some(sent_at));
+        // 没有标记构建时，归入发送阶段
+        let decided_at = timeline.decided_at.get().max(timeline.received_at);
+        let built_at = timeline
+            .built_at
+            .get()
+            .unwrap_or(decided_at)
+            .max(decided_at);
+        let stages = [
+            ("decide", decided_at - timeline.received_at),
+            ("build", built_at - decided_at),
+            ("send", sent_at - built_at),
+        ];
+        for (stage, elapsed) in stages {
+            metrics::TRADE_STAGE_SECONDS
+                .with_label_values(&[timeline.strategy, stage])
+                .observe(elapsed.as_secs_f64());
+        }
+        let total = sent_at - timeline.received_at;
+        metrics::DETECTION_TO_SEND_SECONDS
+            .with_label_values(&[timeline.strategy])
+            .observe(total.as_secs_f64());
+        info!(
+            "latency: decide {:?}, build {:?}, send {:?}, total {:?}",
+            stages[0].1, stages[1].1, stages[2].1, total
+        );
+    });
+}
+
+#[tokio::test]
+async fn test_trade_timeline() {
+    assert_eq!(strategy(), MANUAL);
+    // 作用域外不记录
+    mark_sent();
+
+    let received_at = Instant::now();
+    let strategy = triggered("timeline_test", received_at, async {
+        mark_decided();
+        mark_built();
+        mark_sent();
+        mark_sent();
+        strategy()
+    })
+    .await;
+    assert_eq!(strategy, "timeline_test");
+    for stage in ["decide", "build", "send"] {
+        assert_eq!(
+            metrics::TRADE_STAGE_SECONDS
+                .with_label_values(&["timeline_test", stage])
+                .get_sample_count(),
+            1
+        );
+    }
+    assert_eq!(
+        metrics::DETECTION_TO_SEND_SECONDS
+            .with_label_values(&["timeline_test"])
+            .get_sample_count(),
+        1
+    );
+}
@@ -0,0 +1,146 @@
+//! Recent blockhash cache.
+//!
+//! A background task keeps the latest blockhash in memory so the send paths
+//! don't pay an RPC round trip right before submission. It refreshes about
+//! every slot, and readers load it lock free. Callers go through
+//! [`recent_blockhash`], which falls back to a live fetch when no cache is
+//! installed or the cached value is older than the configured max age.
+
+use std::{
+    str::FromStr,
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use arc_swap::ArcSwapOption;
+use serde_json::json;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_request::RpcRequest,
+    rpc_response::{Response, RpcBlockhash},
+};
+use solana_sdk::hash::Hash;
+use tokio::{task::JoinHandle, time::MissedTickBehavior};
+use tracing::warn;
+
+/// How often the background task refreshes the blockhash
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_millis(400);
+
+/// Cached blockhashes older than this are not served
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(10);
+
+static GLOBAL_CACHE: OnceLock<Arc<BlockhashCache>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy)]
+pub struct CachedBlockhash {
+    pub blockhash: Hash,
+    /// Slot of the RPC response the blockhash came from
+    pub slot: u64,
+    pub fetched_at: Instant,
+}
+
+pub struct BlockhashCache {
+    client: Arc<RpcClient>,
+    max_age: Duration,
+    latest: ArcSwapOption<CachedBlockhash>,
+}
+
+impl BlockhashCache {
+    pub fn new(client: Arc<RpcClient>, max_age: Duration) -> Self {
+        Self {
+            client,
+            max_age,
+            latest: ArcSwapOption::empty(),
+        }
+    }
+
+    /// Fetches the latest blockhash and stores it in the cache
+    pub async fn refresh(&self) -> Result<CachedBlockhash> {
+        let response: Response<RpcBlockhash> = self
+            .client
+            .send(
+                RpcRequest::GetLatestBlockhash,
+                json!([self.client.commitment()]),
+            )
+            .await?;
+        let cached = CachedBlockhash {
+            blockhash: Hash::from_str(&response.value.blockhash)
+                .map_err(|e| anyhow!("invalid blockhash {:?}", e))?,
+            slot: response.context.slot,
+            fetched_at: Instant::now(),
+        };
+        self.latest.store(Some(Arc::new(cached)));
+        Ok(cached)
+    }
+
+    /// Returns the cached blockhash if it is younger than the max age
+    pub fn get(&self) -> Option<CachedBlockhash> {
+        self.latest
+            .load()
+            .as_deref()
+            .copied()
+            .filter(|cached| cached.fetched_at.elapsed() <= self.max_age)
+    }
+
+    /// Returns the cached blockhash, fetching a fresh one when stale
+    pub async fn get_or_refresh(&self) -> Result<Hash> {
+        match self.get() {
+            Some(cached) => Ok(cached.blockhash),
+            None => Ok(self.refresh().await?.blockhash),
+        }
+    }
+
+    /// Spawns the task refreshing the cache every `interval`
+    pub fn spawn_refresh(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // 请求慢于间隔时不补发
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.refresh().await {
+                    warn!("failed to refresh blockhash {:?}", e);
+                }
+            }
+        })
+    }
+}
+
+/// Starts a refreshing cache for `client` and installs it process wide
+pub async fn start(client: Arc<RpcClient>, interval: Duration) -> Result<JoinHandle<()>> {
+    let cache = Arc::new(BlockhashCache::new(client, DEFAULT_MAX_AGE));
+    cache.refresh().await?;
+    GLOBAL_CACHE
+        .set(cache.clone())
+        .map_err(|_| anyhow!("blockhash cache already started"))?;
+    Ok(cache.spawn_refresh(interval))
+}
+
+/// Returns the process wide cache, if one was started
+pub fn global_cache() -> Option<&'static Arc<BlockhashCache>> {
+    GLOBAL_CACHE.get()
+}
+
+/// Latest blockhash from the cache, or from `client` when the cache is
+/// missing or stale
+pub async fn recent_blockhash(client: &RpcClient) -> Result<Hash> {
+    if let Some(cached) = global_cache().and_then(|cache| cache.get()) {
+        return Ok(cached.blockhash);
+    }
+    Ok(client.get_latest_blockhash().await?)
+}
+
+#[tokio::test]
+async fn test_blockhash_cache_expires() {
+    let client = Arc::new(RpcClient::new_mock("succeeds".to_string()));
+
+    let cache = BlockhashCache::new(client.clone(), DEFAULT_MAX_AGE);
+    let cached = cache.refresh().await.unwrap();
+    assert_eq!(cache.get().unwrap().blockhash, cached.blockhash);
+
+    let expired = BlockhashCache::new(client, Duration::ZERO);
+    expired.refresh().await.unwrap();
+    std::thread::sleep(Duration::from_millis(1));
+    assert!(expired.get().is_none());
+}
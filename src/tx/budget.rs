@@ -0,0 +1,275 @@
+//! Spend budget and per-mint cooldown for buys.
+//!
+//! Every buy path consults the process-wide [`BudgetGuard`] before sending.
+//! Spend is tracked over a sliding window (an hour by default) and a mint
+//! can't be bought again until its cooldown has passed. Simulations are not
+//! counted, and neither are buys that fail before they are sent: the
+//! [`Reservation`] is released unless the caller commits it.
+//!
+//! - `BUDGET_SOL_PER_HOUR`: SOL that may be spent per window, default 1
+//! - `MINT_COOLDOWN_SECS`: seconds between buys of the same mint, default 300
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use solana_sdk::{native_token::sol_to_lamports, pubkey::Pubkey};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::strategy::parse_env;
+
+const DEFAULT_BUDGET_SOL_PER_HOUR: f64 = 1.0;
+const DEFAULT_MINT_COOLDOWN: Duration = Duration::from_secs(300);
+const BUDGET_WINDOW: Duration = Duration::from_secs(3600);
+
+static GLOBAL_GUARD: OnceLock<BudgetGuard> = OnceLock::new();
+
+#[derive(Debug, Error)]
+pub enum BudgetError {
+    #[error("spending {requested} lamports exceeds the budget, {spent} of {max} spent in window")]
+    BudgetExceeded {
+        requested: u64,
+        spent: u64,
+        max: u64,
+    },
+    #[error("{mint} is on cooldown for another {remaining:?}")]
+    OnCooldown { mint: Pubkey, remaining: Duration },
+}
+
+#[derive(Default)]
+struct BudgetState {
+    /// Spends inside the window, oldest first
+    spends: VecDeque<(Instant, u64)>,
+    last_buy: HashMap<Pubkey, Instant>,
+}
+
+pub struct BudgetGuard {
+    /// Lamports that may be spent per window
+    max_spend: u64,
+    window: Duration,
+    cooldown: Duration,
+    state: Mutex<BudgetState>,
+}
+
+impl BudgetGuard {
+    pub fn new(max_spend: u64, window: Duration, cooldown: Duration) -> Self {
+        Self {
+            max_spend,
+            window,
+            cooldown,
+            state: Mutex::new(BudgetState::default()),
+        }
+    }
+
+    /// Reads `BUDGET_SOL_PER_HOUR` and `MINT_COOLDOWN_SECS`, failing on
+    /// invalid values rather than falling back to the defaults
+    pub fn from_env() -> Result<Self> {
+        dotenv::dotenv().ok();
+        let budget_sol: f64 =
+            parse_env("BUDGET_SOL_PER_HOUR")?.unwrap_or(DEFAULT_BUDGET_SOL_PER_HOUR);
+        if !budget_sol.is_finite() || budget_sol < 0.0 {
+            return Err(anyhow!("invalid BUDGET_SOL_PER_HOUR {}", budget_sol));
+        }
+        let cooldown = parse_env("MINT_COOLDOWN_SECS")?
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_MINT_COOLDOWN);
+        Ok(Self::new(
+            sol_to_lamports(budget_sol),
+            BUDGET_WINDOW,
+            cooldown,
+        ))
+    }
+
+    /// Lamports spent inside the current window
+    pub fn spent(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        self.expire(&mut state, Instant::now());
+        state.spends.iter().map(|(_, amount)| amount).sum()
+    }
+
+    /// Checks a buy of `mint` for `lamports` and records it if allowed
+    ///
+    /// The spend and cooldown are given back when the returned
+    /// [`Reservation`] drops without [`Reservation::commit`].
+    pub fn reserve(&self, mint: &Pubkey, lamports: u64) -> Result<Reservation<'_>, BudgetError> {
+        self.reserve_at(Instant::now(), mint, lamports)
+            .inspect_err(|e| warn!("buy rejected by budget guard: {}", e))
+    }
+
+    fn reserve_at(
+        &self,
+        now: Instant,
+        mint: &Pubkey,
+        lamports: u64,
+    ) -> Result<Reservation<'_>, BudgetError> {
+        let mut state = self.state.lock().unwrap();
+        self.expire(&mut state, now);
+
+        if let Some(last) = state.last_buy.get(mint) {
+            let elapsed = now.duration_since(*last);
+            if elapsed < self.cooldown {
+                return Err(BudgetError::OnCooldown {
+                    mint: *mint,
+                    remaining: self.cooldown - elapsed,
+                });
+            }
+        }
+
+        let spent: u64 = state.spends.iter().map(|(_, amount)| amount).sum();
+        if spent.saturating_add(lamports) > self.max_spend {
+            return Err(BudgetError::BudgetExceeded {
+                requested: lamports,
+                spent,
+                max: self.max_spend,
+            });
+        }
+
+        state.spends.push_back((now, lamports));
+        state.last_buy.insert(*mint, now);
+        Ok(Reservation {
+            guard: self,
+            mint: *mint,
+            lamports,
+            at: now,
+            committed: false,
+        })
+    }
+
+    /// Takes back the spend and cooldown of `reservation`
+    fn release(&self, reservation: &Reservation) {
+        let mut state = self.state.lock().unwrap();
+        let spend = (reservation.at, reservation.lamports);
+        if let Some(i) = state.spends.iter().position(|s| *s == spend) {
+            state.spends.remove(i);
+        }
+        if state.last_buy.get(&reservation.mint) == Some(&reservation.at) {
+            state.last_buy.remove(&reservation.mint);
+        }
+    }
+
+    /// Drops spends and cooldowns that are no longer relevant
+    fn expire(&self, state: &mut BudgetState, now: Instant) {
+        while let Some((at, _)) = state.spends.front() {
+            if now.duration_since(*at) < self.window {
+                break;
+            }
+            state.spends.pop_front();
+        }
+        state
+            .last_buy
+            .retain(|_, at| now.duration_since(*at) < self.cooldown);
+    }
+}
+
+/// A buy counted against a [`BudgetGuard`], released on drop unless
+/// committed
+#[must_use = "the reservation is released when dropped, commit it once the buy is sent"]
+pub struct Reservation<'a> {
+    guard: &'a BudgetGuard,
+    mint: Pubkey,
+    lamports: u64,
+    at: Instant,
+    committed: bool,
+}
+
+impl Reservation<'_> {
+    /// Keeps the spend and cooldown, once the buy was sent
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.guard.release(self);
+        }
+    }
+}
+
+/// Reads the guard's config, failing if it's invalid
+pub fn init() -> Result<()> {
+    let guard = BudgetGuard::from_env()?;
+    GLOBAL_GUARD.get_or_init(|| guard);
+    Ok(())
+}
+
+/// Process-wide guard shared by every buy path
+///
+/// Panics on an invalid config if `init` wasn't called first.
+pub fn global_guard() -> &'static BudgetGuard {
+    GLOBAL_GUARD.get_or_init(|| BudgetGuard::from_env().unwrap())
+}
+
+#[test]
+fn test_budget_guard_window_and_cooldown() {
+    let guard = BudgetGuard::new(100, Duration::from_secs(60), Duration::from_secs(10));
+    let (a, b, c) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let start = Instant::now();
+
+    guard.reserve_at(start, &a, 60).unwrap().commit();
+    assert!(matches!(
+        guard.reserve_at(start, &a, 10),
+        Err(BudgetError::OnCooldown { .. })
+    ));
+    assert!(matches!(
+        guard.reserve_at(start, &b, 50),
+        Err(BudgetError::BudgetExceeded { spent: 60, .. })
+    ));
+    guard.reserve_at(start, &b, 40).unwrap().commit();
+
+    // 冷却结束，但预算仍在窗口内
+    let later = start + Duration::from_secs(11);
+    assert!(matches!(
+        guard.reserve_at(later, &a, 1),
+        Err(BudgetError::BudgetExceeded { .. })
+    ));
+
+    // 窗口滑过之后预算恢复
+    let next_window = start + Duration::from_secs(61);
+    guard.reserve_at(next_window, &c, 100).unwrap().commit();
+}
+
+#[test]
+fn test_budget_guard_releases_uncommitted() {
+    let guard = BudgetGuard::new(100, Duration::from_secs(60), Duration::from_secs(10));
+    let mint = Pubkey::new_unique();
+    let start = Instant::now();
+
+    // 发送失败，预算和冷却都退回
+    let reservation = guard.reserve_at(start, &mint, 80).unwrap();
+    drop(reservation);
+    guard.reserve_at(start, &mint, 80).unwrap().commit();
+    assert!(matches!(
+        guard.reserve_at(start, &mint, 10),
+        Err(BudgetError::OnCooldown { .. })
+    ));
+    assert!(matches!(
+        guard.reserve_at(start, &Pubkey::new_unique(), 30),
+        Err(BudgetError::BudgetExceeded { spent: 80, .. })
+    ));
+}
+
+#[test]
+fn test_budget_guard_batch_rolls_back() {
+    let guard = BudgetGuard::new(100, Duration::from_secs(60), Duration::from_secs(10));
+    let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let start = Instant::now();
+
+    // 同 buy_many，后面的代币超预算时前面的预留一起退回
+    let batch = [(a, 60), (b, 50)]
+        .iter()
+        .map(|(mint, lamports)| guard.reserve_at(start, mint, *lamports))
+        .collect::<Result<Vec<_>, _>>();
+    assert!(matches!(batch, Err(BudgetError::BudgetExceeded { .. })));
+    assert_eq!(guard.spent(), 0);
+    guard.reserve_at(start, &a, 60).unwrap().commit();
+}
@@ -0,0 +1,251 @@
+//! Upcoming leaders and their TPU addresses.
+//!
+//! A [`LeaderTracker`] follows the current slot, the leaders of the slots
+//! ahead from `getSlotLeaders` and the validators' TPU QUIC addresses from
+//! `getClusterNodes`. With `LEADER_FORWARD_ENABLED=true` every transaction
+//! sent through the RPC, see [`crate::rpc::multi::send_transaction`], is
+//! also sent straight to the TPU of the leaders of the next
+//! `LEADER_FORWARD_SLOTS` slots (default 8, two leaders), which saves the hop
+//! through the RPC node's own forwarding. The RPC send stays: it's the one
+//! the signature and the confirmation come from, the copies to the leaders
+//! are best effort.
+//!
+//! The slot is polled every 400ms, the leaders fetched again before the known
+//! ones run out, and the cluster nodes every `NODES_REFRESH_INTERVAL`.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, LazyLock, OnceLock, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use futures_util::future::join_all;
+use solana_client::{connection_cache::ConnectionCache, nonblocking::rpc_client::RpcClient};
+use solana_connection_cache::nonblocking::client_connection::ClientConnection;
+use solana_sdk::{pubkey::Pubkey, transaction::Transaction};
+use tokio::{task::JoinHandle, time::MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+use crate::strategy::parse_env;
+
+pub const DEFAULT_FORWARD_SLOTS: u64 = 8;
+
+/// How often the current slot is polled
+const SLOT_REFRESH_INTERVAL: Duration = Duration::from_millis(400);
+
+/// How often the TPU addresses are fetched again
+const NODES_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Leaders fetched at once
+const LEADERS_FETCHED: u64 = 256;
+
+/// The leaders are fetched again when fewer slots than this are known ahead
+const LEADERS_MIN_AHEAD: u64 = 64;
+
+/// Connections to the leaders kept per address
+const CONNECTION_POOL_SIZE: usize = 2;
+
+static GLOBAL_TRACKER: OnceLock<Arc<LeaderTracker>> = OnceLock::new();
+
+static CONNECTIONS: LazyLock<ConnectionCache> =
+    LazyLock::new(|| ConnectionCache::new_quic("leader_tracker", CONNECTION_POOL_SIZE));
+
+#[derive(Default)]
+struct State {
+    slot: u64,
+    /// Slot of `leaders[0]`
+    leaders_start: u64,
+    leaders: Vec<Pubkey>,
+    tpu: HashMap<Pubkey, SocketAddr>,
+    nodes_fetched_at: Option<Instant>,
+}
+
+pub struct LeaderTracker {
+    client: Arc<RpcClient>,
+    /// Slots ahead, from the current one, whose leaders get the transactions
+    forward_slots: u64,
+    state: RwLock<State>,
+}
+
+impl LeaderTracker {
+    pub fn new(client: Arc<RpcClient>, forward_slots: u64) -> Self {
+        Self {
+            client,
+            forward_slots,
+            state: RwLock::new(State::default()),
+        }
+    }
+
+    /// Last slot polled
+    pub fn slot(&self) -> u64 {
+        self.state.read().unwrap().slot
+    }
+
+    /// Distinct leaders of the current slot and the `forward_slots` after
+    /// it, in order
+    pub fn upcoming_leaders(&self) -> Vec<Pubkey> {
+        let state = self.state.read().unwrap();
+        upcoming(
+            state.leaders_start,
+            &state.leaders,
+            state.slot,
+            self.forward_slots,
+        )
+    }
+
+    /// TPU QUIC addresses of the upcoming leaders that have one
+    pub fn leader_tpus(&self) -> Vec<SocketAddr> {
+        let leaders = self.upcoming_leaders();
+        let state = self.state.read().unwrap();
+        leaders
+            .iter()
+            .filter_map(|leader| state.tpu.get(leader).copied())
+            .collect()
+    }
+
+    /// Polls the slot, and the leaders and nodes when they're due
+    pub async fn refresh(&self) -> Result<()> {
+        let slot = self.client.get_slot().await?;
+        let (known_until, nodes_due) = {
+            let mut state = self.state.write().unwrap();
+            state.slot = slot;
+            (
+                state.leaders_start + state.leaders.len() as u64,
+                state
+                    .nodes_fetched_at
+                    .is_none_or(|at| at.elapsed() >= NODES_REFRESH_INTERVAL),
+            )
+        };
+        if known_until < slot + LEADERS_MIN_AHEAD {
+            let leaders = self.client.get_slot_leaders(slot, LEADERS_FETCHED).await?;
+            let mut state = self.state.write().unwrap();
+            state.leaders_start = slot;
+            state.leaders = leaders;
+        }
+        if nodes_due {
+            let tpu: HashMap<_, _> = self
+                .client
+                .get_cluster_nodes()
+                .await?
+                .into_iter()
+                .filter_map(|node| Some((node.pubkey.parse().ok()?, node.tpu_quic?)))
+                .collect();
+            let mut state = self.state.write().unwrap();
+            state.tpu = tpu;
+            state.nodes_fetched_at = Some(Instant::now());
+        }
+        Ok(())
+    }
+
+    /// Spawns the task refreshing the tracker about every slot
+    pub fn spawn_refresh(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(SLOT_REFRESH_INTERVAL);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.refresh().await {
+                    warn!("failed to refresh the leaders {:?}", e);
+                }
+            }
+        })
+    }
+
+    /// Sends `txn` to the TPU of every upcoming leader at once
+    pub async fn forward(&self, txn: &Transaction) -> Result<()> {
+        let tpus = self.leader_tpus();
+        if tpus.is_empty() {
+            return Err(anyhow!("no TPU address known for the upcoming leaders"));
+        }
+        let wire = bincode::serialize(txn)?;
+        let results = join_all(tpus.iter().map(|tpu| {
+            let wire = &wire;
+            async move {
+                let connection = CONNECTIONS.get_nonblocking_connection(tpu);
+                connection.send_data(wire).await
+            }
+        }))
+        .await;
+        for (tpu, result) in tpus.iter().zip(results) {
+            if let Err(e) = result {
+                debug!("failed to forward {} to {} {:?}", txn.signatures[0], tpu, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Distinct leaders of `slot` to `slot + forward_slots`, from the leaders of
+/// the slots from `leaders_start` on
+fn upcoming(leaders_start: u64, leaders: &[Pubkey], slot: u64, forward_slots: u64) -> Vec<Pubkey> {
+    let mut upcoming = vec![];
+    for slot in slot..=slot + forward_slots {
+        let Some(leader) = slot
+            .checked_sub(leaders_start)
+            .and_then(|index| leaders.get(index as usize))
+        else {
+            continue;
+        };
+        // 每个领导者连续出4个块
+        if !upcoming.contains(leader) {
+            upcoming.push(*leader);
+        }
+    }
+    upcoming
+}
+
+/// Starts the tracker and installs it process wide if
+/// `LEADER_FORWARD_ENABLED=true`
+pub async fn start_from_env(client: Arc<RpcClient>) -> Result<Option<JoinHandle<()>>> {
+    dotenv::dotenv().ok();
+    if !parse_env::<bool>("LEADER_FORWARD_ENABLED")?.unwrap_or(false) {
+        return Ok(None);
+    }
+    let forward_slots = parse_env("LEADER_FORWARD_SLOTS")?.unwrap_or(DEFAULT_FORWARD_SLOTS);
+    let tracker = Arc::new(LeaderTracker::new(client, forward_slots));
+    tracker.refresh().await?;
+    GLOBAL_TRACKER
+        .set(tracker.clone())
+        .map_err(|_| anyhow!("leader tracker already started"))?;
+    info!(
+        "forwarding transactions to the leaders of the next {} slots",
+        forward_slots
+    );
+    Ok(Some(tracker.spawn_refresh()))
+}
+
+/// Returns the process wide tracker, if one was started
+pub fn global_leader_tracker() -> Option<&'static Arc<LeaderTracker>> {
+    GLOBAL_TRACKER.get()
+}
+
+/// Sends `txn` to the upcoming leaders in the background if the tracker
+/// was started
+pub fn forward(txn: &Transaction) {
+    let Some(tracker) = global_leader_tracker() else {
+        return;
+    };
+    let txn = txn.clone();
+    tokio::spawn(async move {
+        if let Err(e) = tracker.forward(&txn).await {
+            debug!(
+                "didn't forward {} to the leaders {:?}",
+                txn.signatures[0], e
+            );
+        }
+    });
+}
+
+#[test]
+fn test_upcoming_leaders() {
+    let [a, b, c] = [(); 3].map(|_| Pubkey::new_unique());
+    let leaders = [a, a, a, a, b, b, b, b, c, c, c, c];
+
+    assert_eq!(upcoming(100, &leaders, 102, 4), vec![a, b]);
+    assert_eq!(upcoming(100, &leaders, 100, 8), vec![a, b, c]);
+    // 超出已知范围的槽位没有领导者
+    assert_eq!(upcoming(100, &leaders, 110, 8), vec![c]);
+    assert!(upcoming(100, &leaders, 99, 0).is_empty());
+}
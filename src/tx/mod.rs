@@ -0,0 +1,8 @@
+pub mod blockhash;
+pub mod budget;
+pub mod leader_tracker;
+pub mod mode;
+pub mod nonce;
+pub mod sender;
+pub mod simulate;
+pub mod tracker;
@@ -0,0 +1,122 @@
+//! Whether the engine sends its transactions at all.
+//!
+//! The process-wide [`ExecutionMode`] is read from `EXECUTION_MODE` (default
+//! `live`), or set once from `--mode` before the first trade:
+//!
+//! - `live`: transactions are sent
+//! - `simulate`: every built transaction goes through `simulateTransaction`
+//!   instead, see [`crate::tx::simulate`]
+//! - `paper`: transactions are built and signed but never sent, and the swaps
+//!   are recorded as fills at the quoted amounts in a portfolio of their own,
+//!   see [`crate::portfolio`]
+//!
+//! A trade asking to simulate, e.g. with `SNIPER_SIMULATE` or `--simulate`,
+//! is simulated in live mode too. Outside of live mode the raw send paths
+//! refuse to send, so WSOL and nonce account management fail rather than
+//! touch the chain.
+
+use std::{fmt, str::FromStr, sync::OnceLock};
+
+use anyhow::{anyhow, Result};
+use tracing::warn;
+
+use crate::strategy::parse_env;
+
+static EXECUTION_MODE: OnceLock<ExecutionMode> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExecutionMode {
+    #[default]
+    Live,
+    Simulate,
+    Paper,
+}
+
+impl ExecutionMode {
+    /// Mode of a trade that asked to simulate or not
+    pub fn resolve(is_simulate: bool) -> Self {
+        match execution_mode() {
+            ExecutionMode::Live if is_simulate => ExecutionMode::Simulate,
+            mode => mode,
+        }
+    }
+
+    /// Whether trades in this mode go through `simulateTransaction`, and so
+    /// skip the risk and budget checks
+    pub fn simulates(self) -> bool {
+        self == ExecutionMode::Simulate
+    }
+}
+
+impl FromStr for ExecutionMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "live" => Ok(Self::Live),
+            "simulate" => Ok(Self::Simulate),
+            "paper" => Ok(Self::Paper),
+            _ => Err(anyhow!("unknown execution mode {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ExecutionMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Live => "live",
+            Self::Simulate => "simulate",
+            Self::Paper => "paper",
+        })
+    }
+}
+
+/// The process-wide mode, `EXECUTION_MODE` unless [`set_execution_mode`] ran
+/// first
+pub fn execution_mode() -> ExecutionMode {
+    *EXECUTION_MODE.get_or_init(|| {
+        dotenv::dotenv().ok();
+        // 配置错误时不能退回到实盘
+        parse_env("EXECUTION_MODE")
+            .unwrap_or_else(|e| {
+                warn!("{:?}, simulating", e);
+                Some(ExecutionMode::Simulate)
+            })
+            .unwrap_or_default()
+    })
+}
+
+/// Sets the process-wide mode, failing if a different one is already in use
+pub fn set_execution_mode(mode: ExecutionMode) -> Result<()> {
+    let current = *EXECUTION_MODE.get_or_init(|| mode);
+    if current != mode {
+        return Err(anyhow!("execution mode is already {}", current));
+    }
+    Ok(())
+}
+
+/// Fails unless transactions may be sent
+pub fn ensure_live() -> Result<()> {
+    match execution_mode() {
+        ExecutionMode::Live => Ok(()),
+        mode => Err(anyhow!("not sending in {} mode", mode)),
+    }
+}
+
+#[test]
+fn test_execution_modes() {
+    for mode in [
+        ExecutionMode::Live,
+        ExecutionMode::Simulate,
+        ExecutionMode::Paper,
+    ] {
+        assert_eq!(mode.to_string().parse::<ExecutionMode>().unwrap(), mode);
+    }
+    assert!("dry".parse::<ExecutionMode>().is_err());
+
+    set_execution_mode(ExecutionMode::Live).unwrap();
+    assert!(set_execution_mode(ExecutionMode::Paper).is_err());
+    assert_eq!(ExecutionMode::resolve(true), ExecutionMode::Simulate);
+    assert_eq!(ExecutionMode::resolve(false), ExecutionMode::Live);
+    assert!(ensure_live().is_ok());
+}
@@ -0,0 +1,162 @@
+//! Durable nonce transactions.
+//!
+//! With `DURABLE_NONCE_ENABLED=true`, `new_signed_and_send` signs against the
+//! wallet's nonce account instead of a recent blockhash, so a transaction
+//! signed ahead of time (e.g. a stop-loss) doesn't expire. The nonce account
+//! is derived from the wallet with a seed and created on first use, the
+//! wallet being its authority.
+//!
+//! A nonce is consumed by the first transaction landing with it, so the sends
+//! of a wallet through the same nonce account are serialized with [`lock`].
+//! Transactions held for later should use their own seed.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex, OnceLock},
+};
+
+use anyhow::{anyhow, Result};
+use solana_client::{
+    nonblocking::rpc_client::RpcClient, nonce_utils::nonblocking::data_from_account,
+};
+use solana_sdk::{
+    hash::Hash, instruction::Instruction, nonce::State, pubkey::Pubkey, signature::Keypair,
+    signer::Signer, system_instruction, system_program, transaction::Transaction,
+};
+use tokio::sync::OwnedMutexGuard;
+use tracing::info;
+
+use crate::{
+    raydium::tx::{build_transaction, send_txn},
+    rpc::retry::with_retry,
+    strategy::parse_env,
+    tx::blockhash::recent_blockhash,
+};
+
+/// Seed of the nonce account `new_signed_and_send` uses
+pub const DEFAULT_SEED: &str = "nonce";
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+/// wallet -> 发送锁
+static LOCKS: LazyLock<Mutex<HashMap<Pubkey, Arc<tokio::sync::Mutex<()>>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Whether `DURABLE_NONCE_ENABLED=true`, read once
+pub fn enabled() -> bool {
+    *ENABLED.get_or_init(|| {
+        dotenv::dotenv().ok();
+        parse_env("DURABLE_NONCE_ENABLED")
+            .ok()
+            .flatten()
+            .unwrap_or(false)
+    })
+}
+
+/// Nonce account of `wallet` for `seed`
+pub fn nonce_address(wallet: &Pubkey, seed: &str) -> Result<Pubkey> {
+    Pubkey::create_with_seed(wallet, seed, &system_program::ID)
+        .map_err(|e| anyhow!("invalid nonce seed {:?}: {}", seed, e))
+}
+
+/// Held while a transaction of `wallet` using its nonce is in flight
+pub async fn lock(wallet: &Pubkey) -> OwnedMutexGuard<()> {
+    let lock = LOCKS.lock().unwrap().entry(*wallet).or_default().clone();
+    lock.lock_owned().await
+}
+
+/// Creates the nonce account of `payer` for `seed`, returning its address
+pub async fn create_nonce_account(
+    client: &RpcClient,
+    payer: &Keypair,
+    seed: &str,
+) -> Result<Pubkey> {
+    let wallet = payer.pubkey();
+    let nonce = nonce_address(&wallet, seed)?;
+    let rent = client
+        .get_minimum_balance_for_rent_exemption(State::size())
+        .await?;
+    let instructions = system_instruction::create_nonce_account_with_seed(
+        &wallet, &nonce, &wallet, seed, &wallet, rent,
+    );
+    let txn = build_transaction(payer, &instructions, recent_blockhash(client).await?)?;
+    let sig = send_txn(client, &txn, false).await?;
+    info!("created nonce account {} signature: {:?}", nonce, sig);
+    Ok(nonce)
+}
+
+/// Current nonce of `payer`'s nonce account for `seed`, creating the
+/// account if it doesn't exist
+pub async fn current_nonce(
+    client: &RpcClient,
+    payer: &Keypair,
+    seed: &str,
+) -> Result<(Pubkey, Hash)> {
+    let nonce = nonce_address(&payer.pubkey(), seed)?;
+    let fetch = || client.get_account_with_commitment(&nonce, client.commitment());
+    let account = match with_retry(fetch).await?.value {
+        Some(account) => account,
+        None => {
+            create_nonce_account(client, payer, seed).await?;
+            with_retry(fetch)
+                .await?
+                .value
+                .ok_or(anyhow!("nonce account {} not found", nonce))?
+        }
+    };
+    let data = data_from_account(&account)?;
+    if data.authority != payer.pubkey() {
+        return Err(anyhow!(
+            "nonce account {} is not authorized by {}",
+            nonce,
+            payer.pubkey()
+        ));
+    }
+    Ok((nonce, data.blockhash()))
+}
+
+/// Prepends the nonce advance, which must be the first instruction
+fn with_advance(
+    nonce: &Pubkey,
+    authority: &Pubkey,
+    instructions: &[Instruction],
+) -> Vec<Instruction> {
+    let mut with_advance = vec![system_instruction::advance_nonce_account(nonce, authority)];
+    with_advance.extend_from_slice(instructions);
+    with_advance
+}
+
+/// Signs `instructions` against `payer`'s nonce account for `seed`
+///
+/// The transaction stays valid until the nonce is advanced, by it or another
+/// transaction using the same account.
+pub async fn sign_with_nonce(
+    client: &RpcClient,
+    payer: &Keypair,
+    seed: &str,
+    instructions: &[Instruction],
+) -> Result<Transaction> {
+    let (nonce, nonce_hash) = current_nonce(client, payer, seed).await?;
+    build_transaction(
+        payer,
+        &with_advance(&nonce, &payer.pubkey(), instructions),
+        nonce_hash,
+    )
+}
+
+#[test]
+fn test_nonce_transaction_layout() {
+    use solana_client::rpc_client::SerializableTransaction;
+
+    let payer = Keypair::new();
+    let nonce = nonce_address(&payer.pubkey(), DEFAULT_SEED).unwrap();
+    assert_eq!(nonce, nonce_address(&payer.pubkey(), DEFAULT_SEED).unwrap());
+    assert_ne!(nonce, nonce_address(&payer.pubkey(), "stop-loss").unwrap());
+    assert!(nonce_address(&payer.pubkey(), &"x".repeat(33)).is_err());
+
+    let transfer = system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1);
+    let instructions = with_advance(&nonce, &payer.pubkey(), &[transfer]);
+    let txn = build_transaction(&payer, &instructions, Hash::new_unique()).unwrap();
+    assert!(txn.uses_durable_nonce());
+    assert_eq!(txn.message.instructions.len(), 2);
+}
@@ -0,0 +1,363 @@
+//! Submission paths of the swap transactions.
+//!
+//! `new_signed_and_send` submits through the [`Sender`] of the current task,
+//! which the engine sets from each strategy's `ActionConfig::sender` with
+//! [`with_sender`], and through the RPC outside of one:
+//!
+//! - `rpc`: the RPC nodes, see [`crate::rpc::multi`]
+//! - `jito`: a bundle of the transaction to the Jito block engine at
+//!   `JITO_BLOCK_ENGINE_URL`, authenticated with `JITO_UUID` if set
+//! - `helius`: Helius Sender at `HELIUS_SENDER_URL`, which forwards to the
+//!   validators and to Jito at once
+//! - `bloxroute`: bloXroute's submit endpoint at `BLOXROUTE_URL`, authorized
+//!   with `BLOXROUTE_AUTH_HEADER`
+//! - `tpu`: straight to the TPU of the leaders of the next `TPU_FANOUT_SLOTS`
+//!   slots (default 12) over QUIC, through solana's TPU client, which follows
+//!   the leaders over `ws_rpc_url`. It sends single transactions, not
+//!   bundles.
+//!
+//! Besides the RPC and the TPU, every path only accepts transactions tipping
+//! one of its accounts, see [`Sender::tip`]. Helius Sender and bloXroute take
+//! at least [`MIN_TIP`].
+
+use std::{
+    env, fmt,
+    future::Future,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        LazyLock,
+    },
+};
+
+use anyhow::{anyhow, Result};
+use jito_sdk_rust::JitoJsonRpcSDK;
+use serde_json::{json, Value};
+use solana_client::{
+    nonblocking::{rpc_client::RpcClient, tpu_client::TpuClient},
+    tpu_client::{TpuClientConfig, DEFAULT_FANOUT_SLOTS, MAX_FANOUT_SLOTS},
+};
+use solana_quic_client::{QuicConfig, QuicConnectionManager, QuicPool};
+use solana_sdk::{
+    commitment_config::CommitmentConfig, instruction::Instruction, pubkey::Pubkey,
+    signature::Signature, system_instruction, transaction::Transaction,
+};
+use tokio::sync::OnceCell;
+use tracing::info;
+
+use crate::{
+    config::bot_config,
+    constants::{
+        bloxroute::{self, TIP_ACCOUNT as BLOXROUTE_TIP_ACCOUNT},
+        helius::{self, TIP_ACCOUNTS as HELIUS_TIP_ACCOUNTS},
+        jito::{BLOCK_ENGINE_URL, MAX_BUNDLE_TRANSACTIONS, TIP_ACCOUNTS as JITO_TIP_ACCOUNTS},
+    },
+    fees::jito_tips::jito_tip,
+    metrics, new_client,
+    raydium::tx::send_txn,
+    strategy::parse_env,
+    timeline,
+    tx::mode::ensure_live,
+};
+
+/// Least tip Helius Sender and bloXroute accept, 0.001 SOL
+pub const MIN_TIP: u64 = 1_000_000;
+
+static JITO_CLIENT: LazyLock<JitoJsonRpcSDK> =
+    LazyLock::new(|| JitoJsonRpcSDK::new(&jito_url(), env::var("JITO_UUID").ok()));
+
+static HTTP: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+/// Started on the first transaction sent to the TPU
+static TPU_CLIENT: OnceCell<TpuClient<QuicPool, QuicConnectionManager, QuicConfig>> =
+    OnceCell::const_new();
+
+static NEXT_TIP_ACCOUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Path a transaction is submitted through
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Sender {
+    #[default]
+    Rpc,
+    Jito,
+    Helius,
+    Bloxroute,
+    Tpu,
+}
+
+impl FromStr for Sender {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rpc" => Ok(Self::Rpc),
+            "jito" => Ok(Self::Jito),
+            "helius" => Ok(Self::Helius),
+            "bloxroute" => Ok(Self::Bloxroute),
+            "tpu" => Ok(Self::Tpu),
+            _ => Err(anyhow!("unknown sender {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Sender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Rpc => "rpc",
+            Self::Jito => "jito",
+            Self::Helius => "helius",
+            Self::Bloxroute => "bloxroute",
+            Self::Tpu => "tpu",
+        })
+    }
+}
+
+tokio::task_local! {
+    static SENDER: Sender;
+}
+
+/// Runs `fut` submitting its transactions through `sender`
+///
+/// Spawned tasks don't inherit it, they need their own scope.
+pub async fn with_sender<F: Future>(sender: Sender, fut: F) -> F::Output {
+    SENDER.scope(sender, fut).await
+}
+
+/// Sender of the current task, [`Sender::Rpc`] outside of [`with_sender`]
+pub fn current() -> Sender {
+    SENDER.try_with(|sender| *sender).unwrap_or_default()
+}
+
+/// Whether `account` receives the tips of one of the paths
+pub(crate) fn is_tip_account(account: &Pubkey) -> bool {
+    JITO_TIP_ACCOUNTS.contains(account)
+        || HELIUS_TIP_ACCOUNTS.contains(account)
+        || *account == BLOXROUTE_TIP_ACCOUNT
+}
+
+/// Next of `accounts`, rotating so transactions don't all write-lock the same
+/// tip account
+fn next_tip_account(accounts: &[Pubkey]) -> Pubkey {
+    accounts[NEXT_TIP_ACCOUNT.fetch_add(1, Ordering::Relaxed) % accounts.len()]
+}
+
+impl Sender {
+    /// Whether the transaction also pays a priority fee, the Jito tip
+    /// replaces it
+    pub fn pays_priority_fee(&self) -> bool {
+        *self != Sender::Jito
+    }
+
+    /// Tip transfer from `payer` the path requires, `None` for the RPC and
+    /// the TPU
+    ///
+    /// It goes last, so failed transactions don't tip.
+    pub fn tip(&self, payer: &Pubkey) -> Option<Instruction> {
+        let lamports = self.tip_lamports()?;
+        let account = match self {
+            Sender::Rpc | Sender::Tpu => return None,
+            Sender::Jito => next_tip_account(&JITO_TIP_ACCOUNTS),
+            Sender::Helius => next_tip_account(&HELIUS_TIP_ACCOUNTS),
+            Sender::Bloxroute => BLOXROUTE_TIP_ACCOUNT,
+        };
+        Some(system_instruction::transfer(payer, &account, lamports))
+    }
+
+    /// Lamports [`Sender::tip`] transfers
+    pub fn tip_lamports(&self) -> Option<u64> {
+        match self {
+            Sender::Rpc | Sender::Tpu => None,
+            Sender::Jito => Some(jito_tip()),
+            Sender::Helius | Sender::Bloxroute => Some(jito_tip().max(MIN_TIP)),
+        }
+    }
+
+    /// Submits `txn` and waits for it to be confirmed
+    pub async fn submit(&self, client: &RpcClient, txn: &Transaction) -> Result<Signature> {
+        ensure_live()?;
+        let sig = txn.signatures[0];
+        match self {
+            Sender::Rpc => return send_txn(client, txn, true).await,
+            Sender::Jito => {
+                let bundle_id = send_jito_bundle(txn).await?;
+                info!("bundle id: {}", bundle_id);
+            }
+            Sender::Helius => send_helius(txn).await?,
+            Sender::Bloxroute => send_bloxroute(txn).await?,
+            Sender::Tpu => send_tpu(txn).await?,
+        }
+        timeline::mark_sent();
+        let result = client
+            .poll_for_signature_with_commitment(&sig, CommitmentConfig::confirmed())
+            .await;
+        metrics::record_confirmation(if result.is_ok() { "landed" } else { "failed" });
+        result?;
+        Ok(sig)
+    }
+}
+
+/// Url of the Jito block engine, `JITO_BLOCK_ENGINE_URL` if set
+pub fn jito_url() -> String {
+    dotenv::dotenv().ok();
+    env::var("JITO_BLOCK_ENGINE_URL").unwrap_or(BLOCK_ENGINE_URL.to_string())
+}
+
+/// Asks the Jito block engine for its tip accounts, the lightest request it
+/// answers, to keep the bundle client's connection open
+pub async fn ping_jito() -> Result<()> {
+    JITO_CLIENT.get_tip_accounts().await?;
+    Ok(())
+}
+
+/// JSON-RPC `sendBundle` request of `txns`
+fn bundle_request(txns: &[Transaction]) -> Result<Value> {
+    let encoded = txns
+        .iter()
+        .map(|txn| Ok(bs64::encode(&bincode::serialize(txn)?)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "sendBundle",
+        // 区块引擎默认按base58解码
+        "params": [encoded, {"encoding": "base64"}],
+    }))
+}
+
+/// Sends `txn` as a bundle of its own to the Jito block engine, returning the
+/// bundle id
+pub async fn send_jito_bundle(txn: &Transaction) -> Result<String> {
+    send_jito_transactions(std::slice::from_ref(txn)).await
+}
+
+/// Sends `txns` as one bundle to the Jito block engine, returning the bundle id
+///
+/// The transactions land in order in the same block, or none of them does.
+pub async fn send_jito_transactions(txns: &[Transaction]) -> Result<String> {
+    ensure_live()?;
+    if txns.is_empty() || txns.len() > MAX_BUNDLE_TRANSACTIONS {
+        return Err(anyhow!(
+            "a bundle takes 1 to {} transactions, not {}",
+            MAX_BUNDLE_TRANSACTIONS,
+            txns.len()
+        ));
+    }
+    let mut request = HTTP
+        .post(format!("{}/bundles", jito_url()))
+        .json(&bundle_request(txns)?);
+    if let Ok(uuid) = env::var("JITO_UUID") {
+        request = request.query(&[("uuid", uuid)]);
+    }
+    let response: Value = request.send().await?.json().await?;
+    response["result"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("failed to send bundle {}", response))
+}
+
+async fn send_helius(txn: &Transaction) -> Result<()> {
+    let url = env::var("HELIUS_SENDER_URL").unwrap_or(helius::SENDER_URL.to_string());
+    let encoded = bs64::encode(&bincode::serialize(txn)?);
+    let body = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "sendTransaction",
+        // Sender 要求跳过预检，且不由节点重试
+        "params": [encoded, {"encoding": "base64", "skipPreflight": true, "maxRetries": 0}],
+    });
+    let response: Value = HTTP.post(url).json(&body).send().await?.json().await?;
+    if response["result"].is_null() {
+        return Err(anyhow!(
+            "helius sender rejected the transaction {}",
+            response
+        ));
+    }
+    Ok(())
+}
+
+async fn send_bloxroute(txn: &Transaction) -> Result<()> {
+    let url = env::var("BLOXROUTE_URL").unwrap_or(bloxroute::SUBMIT_URL.to_string());
+    let auth =
+        env::var("BLOXROUTE_AUTH_HEADER").map_err(|_| anyhow!("BLOXROUTE_AUTH_HEADER not set"))?;
+    let encoded = bs64::encode(&bincode::serialize(txn)?);
+    let body = json!({
+        "transaction": {"content": encoded},
+        "skipPreFlight": true,
+        "useStakedRPCs": true,
+    });
+    let response: Value = HTTP
+        .post(url)
+        .header("Authorization", auth)
+        .json(&body)
+        .send()
+        .await?
+        .json()
+        .await?;
+    if response["signature"].is_null() {
+        return Err(anyhow!("bloxroute rejected the transaction {}", response));
+    }
+    Ok(())
+}
+
+/// Solana's TPU client fanning out to the leaders of the next
+/// `TPU_FANOUT_SLOTS` slots
+async fn new_tpu_client() -> Result<TpuClient<QuicPool, QuicConnectionManager, QuicConfig>> {
+    dotenv::dotenv().ok();
+    let fanout_slots = parse_env("TPU_FANOUT_SLOTS")?.unwrap_or(DEFAULT_FANOUT_SLOTS);
+    if fanout_slots == 0 || fanout_slots > MAX_FANOUT_SLOTS {
+        return Err(anyhow!(
+            "TPU_FANOUT_SLOTS must be 1 to {}, got {}",
+            MAX_FANOUT_SLOTS,
+            fanout_slots
+        ));
+    }
+    let client = TpuClient::new(
+        "tpu_sender",
+        new_client(),
+        &bot_config().ws_rpc_url,
+        TpuClientConfig { fanout_slots },
+    )
+    .await?;
+    info!(
+        "sending to the TPU of the next {} slots' leaders",
+        fanout_slots
+    );
+    Ok(client)
+}
+
+async fn send_tpu(txn: &Transaction) -> Result<()> {
+    let client = TPU_CLIENT.get_or_try_init(new_tpu_client).await?;
+    client.try_send_transaction(txn).await?;
+    Ok(())
+}
+
+#[test]
+fn test_senders_and_tips() {
+    for sender in [
+        Sender::Rpc,
+        Sender::Jito,
+        Sender::Helius,
+        Sender::Bloxroute,
+        Sender::Tpu,
+    ] {
+        assert_eq!(sender.to_string().parse::<Sender>().unwrap(), sender);
+    }
+    assert!("triton".parse::<Sender>().is_err());
+
+    let payer = Pubkey::new_unique();
+    assert!(Sender::Rpc.tip(&payer).is_none() && Sender::Tpu.tip(&payer).is_none());
+    assert!(!Sender::Jito.pays_priority_fee() && Sender::Helius.pays_priority_fee());
+    let tip = Sender::Bloxroute.tip(&payer).unwrap();
+    assert_eq!(tip.accounts[1].pubkey, BLOXROUTE_TIP_ACCOUNT);
+
+    let txns = [Transaction::default(), Transaction::default()];
+    let request = bundle_request(&txns).unwrap();
+    assert_eq!(request["method"], "sendBundle");
+    assert_eq!(request["params"][0].as_array().unwrap().len(), 2);
+    assert_eq!(request["params"][1]["encoding"], "base64");
+
+    let first = next_tip_account(&JITO_TIP_ACCOUNTS);
+    let second = next_tip_account(&JITO_TIP_ACCOUNTS);
+    assert_ne!(first, second);
+    assert!(JITO_TIP_ACCOUNTS.contains(&first) && JITO_TIP_ACCOUNTS.contains(&second));
+}
@@ -0,0 +1,282 @@
+//! Simulation with an expected-output summary.
+//!
+//! Besides dumping the program logs, a simulation reports how much the
+//! transaction was expected to deliver, the minimum accepted after slippage,
+//! and what the simulated post-state actually credited to the output account.
+
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::{
+        RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig, RpcTransactionConfig,
+    },
+};
+use solana_sdk::{
+    account::Account, commitment_config::CommitmentConfig, program_pack::Pack, pubkey::Pubkey,
+    signature::Signature, transaction::Transaction,
+};
+use solana_transaction_status_client_types::{
+    option_serializer::OptionSerializer, UiTransactionEncoding, UiTransactionTokenBalance,
+};
+use tracing::{debug, info};
+
+use crate::{metrics, rpc::retry::with_retry};
+
+/// Account credited with the output of a swap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputAccount {
+    /// SPL token account, measured in raw token units
+    Token(Pubkey),
+    /// Wallet receiving SOL, measured in lamports with the tx fee added back
+    Lamports(Pubkey),
+}
+
+impl OutputAccount {
+    fn pubkey(&self) -> Pubkey {
+        match self {
+            OutputAccount::Token(pubkey) | OutputAccount::Lamports(pubkey) => *pubkey,
+        }
+    }
+
+    fn amount(&self, account: &Account) -> Result<u64> {
+        match self {
+            OutputAccount::Token(_) => Ok(spl_token::state::Account::unpack(&account.data)?.amount),
+            OutputAccount::Lamports(_) => Ok(account.lamports),
+        }
+    }
+}
+
+/// What the caller computed the transaction should deliver
+#[derive(Debug, Clone, Copy)]
+pub struct ExpectedOutput {
+    pub expected_out: u64,
+    /// Minimum accepted after slippage
+    pub min_out: u64,
+    pub account: OutputAccount,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SimulationSummary {
+    pub expected_out: Option<u64>,
+    pub min_out: Option<u64>,
+    /// Output credited in the simulated post-state
+    pub simulated_out: Option<u64>,
+    pub units_consumed: Option<u64>,
+    pub logs: Vec<String>,
+}
+
+impl SimulationSummary {
+    /// Whether the simulated output met the slippage threshold, if known
+    pub fn satisfied(&self) -> Option<bool> {
+        Some(self.simulated_out? >= self.min_out?)
+    }
+}
+
+impl fmt::Display for SimulationSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |v: Option<u64>| v.map_or("unknown".to_string(), |v| v.to_string());
+        let satisfied = match self.satisfied() {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "unknown",
+        };
+        write!(
+            f,
+            "expected out: {}, min out: {}, simulated out: {}, threshold satisfied: {}, units consumed: {}",
+            show(self.expected_out),
+            show(self.min_out),
+            show(self.simulated_out),
+            satisfied,
+            show(self.units_consumed),
+        )
+    }
+}
+
+/// Result of a send path that may only simulate
+#[derive(Debug, Clone)]
+pub enum TxOutcome {
+    Sent(Vec<Signature>),
+    Simulated(SimulationSummary),
+    /// Signed but not sent in paper mode, see [`crate::tx::mode`]
+    Paper(Signature),
+}
+
+impl TxOutcome {
+    /// Signatures of the sent transactions, empty for simulations and paper
+    /// trades
+    pub fn signatures(&self) -> &[Signature] {
+        match self {
+            TxOutcome::Sent(signatures) => signatures,
+            TxOutcome::Simulated(_) | TxOutcome::Paper(_) => &[],
+        }
+    }
+}
+
+async fn current_amount(client: &RpcClient, output: &OutputAccount) -> Result<u64> {
+    match client
+        .get_account_with_commitment(&output.pubkey(), client.commitment())
+        .await?
+        .value
+    {
+        Some(account) => output.amount(&account),
+        None => Ok(0),
+    }
+}
+
+/// Output credited to `output` by the confirmed transaction `signature`,
+/// `None` if it failed
+pub async fn landed_output(
+    client: &RpcClient,
+    signature: &Signature,
+    output: &OutputAccount,
+) -> Result<Option<u64>> {
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        commitment: Some(CommitmentConfig::confirmed()),
+        max_supported_transaction_version: Some(0),
+    };
+    let tx = with_retry(|| client.get_transaction_with_config(signature, config))
+        .await?
+        .transaction;
+    let meta = tx
+        .meta
+        .ok_or(anyhow!("transaction {} has no status meta", signature))?;
+    if meta.err.is_some() {
+        return Ok(None);
+    }
+    let decoded = tx
+        .transaction
+        .decode()
+        .ok_or(anyhow!("failed to decode transaction {}", signature))?;
+    let index = decoded
+        .message
+        .static_account_keys()
+        .iter()
+        .position(|key| *key == output.pubkey())
+        .ok_or(anyhow!(
+            "{} is not in transaction {}",
+            output.pubkey(),
+            signature
+        ))?;
+    match output {
+        OutputAccount::Token(_) => {
+            // 交易中新建的账户没有交易前余额
+            let amount = |balances: OptionSerializer<Vec<UiTransactionTokenBalance>>| {
+                Option::<Vec<_>>::from(balances)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .find(|balance| balance.account_index as usize == index)
+                    .and_then(|balance| balance.ui_token_amount.amount.parse::<u64>().ok())
+                    .unwrap_or(0)
+            };
+            let pre = amount(meta.pre_token_balances);
+            Ok(Some(amount(meta.post_token_balances).saturating_sub(pre)))
+        }
+        OutputAccount::Lamports(_) => {
+            let (pre, post) = (meta.pre_balances[index], meta.post_balances[index]);
+            Ok(Some((post + meta.fee).saturating_sub(pre)))
+        }
+    }
+}
+
+/// Simulates `txn`, printing its logs and a summary of the expected output
+///
+/// Returns an error if the simulation failed.
+pub async fn simulate(
+    client: &RpcClient,
+    txn: &Transaction,
+    expected: Option<ExpectedOutput>,
+) -> Result<SimulationSummary> {
+    let before = match &expected {
+        Some(expected) => Some(current_amount(client, &expected.account).await?),
+        None => None,
+    };
+
+    let config = RpcSimulateTransactionConfig {
+        commitment: Some(client.commitment()),
+        accounts: expected.map(|expected| RpcSimulateTransactionAccountsConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            addresses: vec![expected.account.pubkey().to_string()],
+        }),
+        ..RpcSimulateTransactionConfig::default()
+    };
+    let result = client
+        .simulate_transaction_with_config(txn, config)
+        .await?
+        .value;
+
+    let logs = result.logs.unwrap_or_default();
+    for log in &logs {
+        debug!("{}", log);
+    }
+    if let Some(err) = result.err {
+        return Err(anyhow!("{}", err));
+    }
+
+    let mut simulated_out = None;
+    if let (Some(expected), Some(before)) = (&expected, before) {
+        let after = result
+            .accounts
+            .and_then(|accounts| accounts.into_iter().next().flatten())
+            .and_then(|account| account.decode::<Account>());
+        if let Some(after) = after {
+            let mut after = expected.account.amount(&after)?;
+            if let OutputAccount::Lamports(_) = expected.account {
+                after += client.get_fee_for_message(&txn.message).await?;
+            }
+            simulated_out = Some(after.saturating_sub(before));
+        }
+    }
+    if let (Some(expected), Some(simulated_out)) = (&expected, simulated_out) {
+        metrics::record_slippage("simulated", expected.expected_out, simulated_out);
+    }
+
+    let summary = SimulationSummary {
+        expected_out: expected.map(|e| e.expected_out),
+        min_out: expected.map(|e| e.min_out),
+        simulated_out,
+        units_consumed: result.units_consumed,
+        logs,
+    };
+    info!("simulation: {}", summary);
+    Ok(summary)
+}
+
+/// Lamports simulating `txn` against the current state credits `wallet`,
+/// with the network fee added back, negative when it spends more than it
+/// gets back
+///
+/// Returns an error if the simulation failed or didn't return the wallet.
+pub async fn simulated_balance_change(
+    client: &RpcClient,
+    txn: &Transaction,
+    wallet: &Pubkey,
+) -> Result<i64> {
+    let before = client.get_balance(wallet).await?;
+    let config = RpcSimulateTransactionConfig {
+        commitment: Some(client.commitment()),
+        accounts: Some(RpcSimulateTransactionAccountsConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            addresses: vec![wallet.to_string()],
+        }),
+        ..RpcSimulateTransactionConfig::default()
+    };
+    let result = client
+        .simulate_transaction_with_config(txn, config)
+        .await?
+        .value;
+    if let Some(err) = result.err {
+        return Err(anyhow!("{}", err));
+    }
+    let after = result
+        .accounts
+        .and_then(|accounts| accounts.into_iter().next().flatten())
+        .and_then(|account| account.decode::<Account>())
+        .ok_or(anyhow!("simulation didn't return {}", wallet))?
+        .lamports;
+    let fee = client.get_fee_for_message(&txn.message).await?;
+    Ok((after + fee) as i64 - before as i64)
+}
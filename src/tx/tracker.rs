@@ -0,0 +1,488 @@
+//! Confirmation of sent transactions.
+//!
+//! [`TxTracker::send`] sends a transaction and polls `getSignatureStatuses`
+//! until it lands, fails on chain, or the [`EscalationPolicy`] gives up on
+//! it. An attempt that hasn't landed once its blockhash expired is signed
+//! again with a fresh blockhash and rebroadcast, its compute unit price and
+//! tip raised to the next step of the schedule. Rebroadcasting earlier would
+//! leave two live attempts that can both land. The policy gives up once the
+//! schedule runs out, or the next step would spend more than the cap. The
+//! final result is published as a `tx_landed` or `tx_failed` event and
+//! counted in the `bot_tx_confirmations_total` metric.
+//!
+//! - `TX_POLL_INTERVAL_MS`: status poll interval, default 500
+//! - `TX_ESCALATION_SCHEDULE`: comma separated unit price and tip of each
+//!   rebroadcast, in percent of the first attempt's, e.g. `150,225`; defaults
+//!   to `TX_MAX_ATTEMPTS - 1` steps of `TX_FEE_ESCALATION_PCT` each
+//! - `TX_MAX_ATTEMPTS`: sends before giving up, default 3
+//! - `TX_FEE_ESCALATION_PCT`: increase per rebroadcast, default 50
+//! - `TX_MAX_UNIT_PRICE`: cap on the escalated price, default 1_000_000
+//! - `TX_MAX_FEE_LAMPORTS`: cap on the priority fee plus tip of an attempt,
+//!   none by default
+
+use std::{env, fmt, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcSendTransactionConfig};
+use solana_sdk::{
+    commitment_config::CommitmentConfig, compute_budget, signature::Keypair, signature::Signature,
+    system_program, transaction::Transaction,
+};
+use tracing::{info, warn};
+
+use crate::{
+    metrics,
+    monitor::events::{self, MonitorEvent, TxFailedEvent, TxLandedEvent},
+    rpc::retry::with_retry,
+    strategy::parse_env,
+    timeline,
+    tx::{mode::ensure_live, sender::is_tip_account},
+};
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_FEE_ESCALATION_PCT: u64 = 50;
+const DEFAULT_MAX_UNIT_PRICE: u64 = 1_000_000;
+
+/// `ComputeBudgetInstruction` 的序号
+const SET_COMPUTE_UNIT_LIMIT: u8 = 2;
+const SET_COMPUTE_UNIT_PRICE: u8 = 3;
+/// `SystemInstruction::Transfer` 的序号
+const SYSTEM_TRANSFER: u32 = 2;
+/// 未设置上限时按默认的单条指令上限估算
+const DEFAULT_UNIT_LIMIT: u64 = 200_000;
+
+/// How a transaction that doesn't land is rebroadcast
+#[derive(Debug, Clone, PartialEq)]
+pub struct EscalationPolicy {
+    /// Compute unit price and tip of each rebroadcast, in percent of the
+    /// first attempt's
+    pub schedule: Vec<u64>,
+    /// Cap on the escalated compute unit price, in micro-lamports
+    pub max_unit_price: u64,
+    /// Cap on the priority fee plus tip of an attempt, in lamports
+    pub max_spend: Option<u64>,
+}
+
+impl Default for EscalationPolicy {
+    fn default() -> Self {
+        Self {
+            schedule: compounding_schedule(DEFAULT_MAX_ATTEMPTS, DEFAULT_FEE_ESCALATION_PCT),
+            max_unit_price: DEFAULT_MAX_UNIT_PRICE,
+            max_spend: None,
+        }
+    }
+}
+
+/// `max_attempts - 1` steps raising the fees by `pct` percent each
+fn compounding_schedule(max_attempts: u32, pct: u64) -> Vec<u64> {
+    let mut step = 100;
+    (1..max_attempts.max(1))
+        .map(|_| {
+            step = step * (100 + pct) / 100;
+            step
+        })
+        .collect()
+}
+
+impl EscalationPolicy {
+    /// Reads the `TX_*` escalation variables, defaults for those unset
+    pub fn from_env() -> Result<Self> {
+        dotenv::dotenv().ok();
+        let default = Self::default();
+        let schedule = match env::var("TX_ESCALATION_SCHEDULE") {
+            Ok(schedule) => schedule
+                .split(',')
+                .map(|pct| match pct.trim().parse::<u64>() {
+                    Ok(pct) if pct >= 100 => Ok(pct),
+                    _ => Err(anyhow!(
+                        "invalid TX_ESCALATION_SCHEDULE step {:?}, expected a percent of at least 100",
+                        pct
+                    )),
+                })
+                .collect::<Result<_>>()?,
+            Err(_) => compounding_schedule(
+                parse_env("TX_MAX_ATTEMPTS")?.unwrap_or(DEFAULT_MAX_ATTEMPTS),
+                parse_env("TX_FEE_ESCALATION_PCT")?.unwrap_or(DEFAULT_FEE_ESCALATION_PCT),
+            ),
+        };
+        Ok(Self {
+            schedule,
+            max_unit_price: parse_env("TX_MAX_UNIT_PRICE")?.unwrap_or(default.max_unit_price),
+            max_spend: parse_env("TX_MAX_FEE_LAMPORTS")?,
+        })
+    }
+
+    /// Fees of the rebroadcast after `attempts` sends, `None` once the
+    /// schedule or the spend cap is exhausted
+    fn fees(&self, first: &AttemptFees, attempts: u32) -> Option<AttemptFees> {
+        let pct = *self.schedule.get(attempts.checked_sub(1)? as usize)?;
+        let scale = |value: u64| value.saturating_mul(pct) / 100;
+        let fees = AttemptFees {
+            unit_price: first
+                .unit_price
+                .map(|price| scale(price).clamp(price, self.max_unit_price.max(price))),
+            tip: first.tip.map(scale),
+            ..*first
+        };
+        match self.max_spend {
+            Some(max) if fees.spend() > max => None,
+            _ => Some(fees),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackerConfig {
+    pub poll_interval: Duration,
+    pub escalation: EscalationPolicy,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            escalation: EscalationPolicy::default(),
+        }
+    }
+}
+
+impl TrackerConfig {
+    /// Reads the `TX_*` variables, defaults for those unset
+    pub fn from_env() -> Result<Self> {
+        dotenv::dotenv().ok();
+        let default = Self::default();
+        Ok(Self {
+            poll_interval: parse_env("TX_POLL_INTERVAL_MS")?
+                .map_or(default.poll_interval, Duration::from_millis),
+            escalation: EscalationPolicy::from_env()?,
+        })
+    }
+}
+
+/// What an attempt pays on top of the base fee
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct AttemptFees {
+    /// Compute unit price, in micro-lamports
+    unit_price: Option<u64>,
+    unit_limit: Option<u32>,
+    /// Tip to a sender's tip account, in lamports
+    tip: Option<u64>,
+}
+
+impl AttemptFees {
+    fn read(txn: &Transaction) -> Self {
+        let mut fees = Self::default();
+        let message = &txn.message;
+        for ix in &message.instructions {
+            let program = message.account_keys[ix.program_id_index as usize];
+            if program == compute_budget::id() {
+                match (ix.data.first(), ix.data.get(1..)) {
+                    (Some(&SET_COMPUTE_UNIT_LIMIT), Some(limit)) => {
+                        fees.unit_limit = limit.try_into().ok().map(u32::from_le_bytes);
+                    }
+                    (Some(&SET_COMPUTE_UNIT_PRICE), Some(price)) => {
+                        fees.unit_price = price.try_into().ok().map(u64::from_le_bytes);
+                    }
+                    _ => {}
+                }
+            } else if let Some(tip) = tip_lamports(txn, ix) {
+                fees.tip = Some(tip);
+            }
+        }
+        fees
+    }
+
+    /// Priority fee plus tip, in lamports
+    fn spend(&self) -> u64 {
+        let unit_limit = self.unit_limit.map_or(DEFAULT_UNIT_LIMIT, u64::from);
+        let priority_fee =
+            (self.unit_price.unwrap_or(0) as u128 * unit_limit as u128).div_ceil(1_000_000);
+        (priority_fee as u64).saturating_add(self.tip.unwrap_or(0))
+    }
+
+    /// Writes the unit price and tip into `txn`, which has to be signed
+    /// again afterwards
+    fn apply(&self, txn: &mut Transaction) {
+        let compute_budget_index = txn
+            .message
+            .account_keys
+            .iter()
+            .position(|key| *key == compute_budget::id());
+        for i in 0..txn.message.instructions.len() {
+            let ix = &txn.message.instructions[i];
+            if Some(ix.program_id_index as usize) == compute_budget_index {
+                if let (Some(price), [SET_COMPUTE_UNIT_PRICE, ..], 9) =
+                    (self.unit_price, ix.data.as_slice(), ix.data.len())
+                {
+                    txn.message.instructions[i].data[1..].copy_from_slice(&price.to_le_bytes());
+                }
+            } else if let (Some(tip), Some(_)) = (self.tip, tip_lamports(txn, ix)) {
+                txn.message.instructions[i].data[4..].copy_from_slice(&tip.to_le_bytes());
+            }
+        }
+    }
+}
+
+impl fmt::Display for AttemptFees {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |v: Option<u64>| v.map_or("none".to_string(), |v| v.to_string());
+        write!(
+            f,
+            "unit price {}, tip {}",
+            show(self.unit_price),
+            show(self.tip)
+        )
+    }
+}
+
+/// Lamports `ix` of `txn` tips, if it is a transfer to a tip account
+fn tip_lamports(
+    txn: &Transaction,
+    ix: &solana_sdk::instruction::CompiledInstruction,
+) -> Option<u64> {
+    let keys = &txn.message.account_keys;
+    if keys.get(ix.program_id_index as usize)? != &system_program::id() || ix.data.len() != 12 {
+        return None;
+    }
+    if u32::from_le_bytes(ix.data[..4].try_into().ok()?) != SYSTEM_TRANSFER {
+        return None;
+    }
+    let to = keys.get(*ix.accounts.get(1)? as usize)?;
+    is_tip_account(to).then(|| u64::from_le_bytes(ix.data[4..].try_into().unwrap()))
+}
+
+/// Final state of a tracked transaction
+#[derive(Debug, Clone, PartialEq)]
+pub enum TxStatus {
+    Landed {
+        signature: Signature,
+        slot: u64,
+    },
+    /// Executed with an error, not retried
+    Failed {
+        signature: Signature,
+        error: String,
+    },
+    /// Not landed after every attempt the policy allowed
+    Expired {
+        signature: Signature,
+    },
+}
+
+/// A tracked transaction once done
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackedTx {
+    pub status: TxStatus,
+    /// Times the transaction was sent
+    pub attempts: u32,
+}
+
+impl TrackedTx {
+    /// The `tx_landed` or `tx_failed` event of the transaction
+    pub fn event(&self) -> MonitorEvent {
+        let attempts = self.attempts;
+        match &self.status {
+            TxStatus::Landed { signature, slot } => MonitorEvent::TxLanded(TxLandedEvent {
+                signature: signature.to_string(),
+                slot: *slot,
+                attempts,
+            }),
+            TxStatus::Failed { signature, error } => MonitorEvent::TxFailed(TxFailedEvent {
+                signature: signature.to_string(),
+                error: error.clone(),
+                attempts,
+            }),
+            TxStatus::Expired { signature } => MonitorEvent::TxFailed(TxFailedEvent {
+                signature: signature.to_string(),
+                error: "not landed before the escalation gave up".to_string(),
+                attempts,
+            }),
+        }
+    }
+}
+
+/// Counts and publishes the final status of a transaction
+fn finish(status: TxStatus, attempts: u32) -> TrackedTx {
+    metrics::record_confirmation(match status {
+        TxStatus::Landed { .. } => "landed",
+        TxStatus::Failed { .. } => "failed",
+        TxStatus::Expired { .. } => "expired",
+    });
+    let tracked = TrackedTx { status, attempts };
+    events::publish(tracked.event());
+    tracked
+}
+
+pub struct TxTracker {
+    client: Arc<RpcClient>,
+    config: TrackerConfig,
+}
+
+impl TxTracker {
+    pub fn new(client: Arc<RpcClient>, config: TrackerConfig) -> Self {
+        Self { client, config }
+    }
+
+    /// Sends `txn` until it lands or the escalation policy gives up,
+    /// publishing the result
+    ///
+    /// `payer` must be the only signer, the transaction is signed again with
+    /// a fresh blockhash for every attempt.
+    pub async fn send(&self, mut txn: Transaction, payer: &Keypair) -> Result<TrackedTx> {
+        ensure_live()?;
+        timeline::mark_built();
+        let policy = &self.config.escalation;
+        let first = AttemptFees::read(&txn);
+        let mut signatures = vec![];
+        let mut attempts = 0;
+        let status = loop {
+            attempts += 1;
+            let (blockhash, last_valid_block_height) = with_retry(|| {
+                self.client
+                    .get_latest_blockhash_with_commitment(CommitmentConfig::confirmed())
+            })
+            .await?;
+            txn.try_sign(&[payer], blockhash)?;
+            let next = policy.fees(&first, attempts);
+            let config = RpcSendTransactionConfig {
+                skip_preflight: true,
+                ..RpcSendTransactionConfig::default()
+            };
+            let signature = match self.client.send_transaction_with_config(&txn, config).await {
+                Ok(signature) => signature,
+                Err(e) => match next {
+                    Some(fees) => {
+                        warn!("send attempt {} failed {:?}", attempts, e);
+                        fees.apply(&mut txn);
+                        continue;
+                    }
+                    None => {
+                        let status = TxStatus::Failed {
+                            signature: txn.signatures[0],
+                            error: format!("send failed: {}", e),
+                        };
+                        finish(status, attempts);
+                        return Err(e.into());
+                    }
+                },
+            };
+            timeline::mark_sent();
+            info!("attempt {} signature: {:?}", attempts, signature);
+            signatures.push(signature);
+
+            // 等到这次的 blockhash 过期再重发，之前的尝试不会再落地
+            if let Some(status) = self.poll(&signatures, last_valid_block_height).await? {
+                break status;
+            }
+            let Some(fees) = next else {
+                break TxStatus::Expired { signature };
+            };
+            info!("rebroadcasting with {}", fees);
+            fees.apply(&mut txn);
+        };
+        Ok(finish(status, attempts))
+    }
+
+    /// Polls `signatures` until one is confirmed or the block height passes
+    /// `last_valid_block_height`
+    async fn poll(
+        &self,
+        signatures: &[Signature],
+        last_valid_block_height: u64,
+    ) -> Result<Option<TxStatus>> {
+        loop {
+            tokio::time::sleep(self.config.poll_interval).await;
+            // 先取高度再查状态，过期前落地的交易不会漏掉
+            let block_height = with_retry(|| self.client.get_block_height()).await?;
+            let statuses = with_retry(|| self.client.get_signature_statuses(signatures))
+                .await?
+                .value;
+            for (signature, status) in signatures.iter().zip(statuses) {
+                let Some(status) = status else {
+                    continue;
+                };
+                if !status.satisfies_commitment(CommitmentConfig::confirmed()) {
+                    continue;
+                }
+                return Ok(Some(match status.err {
+                    Some(error) => TxStatus::Failed {
+                        signature: *signature,
+                        error: error.to_string(),
+                    },
+                    None => TxStatus::Landed {
+                        signature: *signature,
+                        slot: status.slot,
+                    },
+                }));
+            }
+            if block_height > last_valid_block_height {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_escalation_policy() {
+    use solana_sdk::{
+        compute_budget::ComputeBudgetInstruction, hash::Hash, pubkey::Pubkey, signer::Signer,
+        system_instruction,
+    };
+
+    let payer = Keypair::new();
+    let tip_account = crate::constants::jito::TIP_ACCOUNTS[0];
+    let instructions = [
+        ComputeBudgetInstruction::set_compute_unit_limit(100_000),
+        ComputeBudgetInstruction::set_compute_unit_price(10_000),
+        system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1),
+        system_instruction::transfer(&payer.pubkey(), &tip_account, 1000),
+    ];
+    let mut txn = Transaction::new_signed_with_payer(
+        &instructions,
+        Some(&payer.pubkey()),
+        &[&payer],
+        Hash::new_unique(),
+    );
+    let first = AttemptFees::read(&txn);
+    assert_eq!(
+        first,
+        AttemptFees {
+            unit_price: Some(10_000),
+            unit_limit: Some(100_000),
+            tip: Some(1000),
+        }
+    );
+    assert_eq!(first.spend(), 2000);
+
+    let policy = EscalationPolicy {
+        schedule: vec![150, 300],
+        max_unit_price: 20_000,
+        max_spend: Some(4000),
+    };
+    assert_eq!(policy.fees(&first, 0), None);
+    let second = policy.fees(&first, 1).unwrap();
+    assert_eq!((second.unit_price, second.tip), (Some(15_000), Some(1500)));
+    // 单价封顶，但 tip 超出花费上限后放弃
+    assert_eq!(policy.fees(&first, 2), None);
+    let uncapped = EscalationPolicy {
+        max_spend: None,
+        ..policy.clone()
+    };
+    let third = uncapped.fees(&first, 2).unwrap();
+    assert_eq!((third.unit_price, third.tip), (Some(20_000), Some(3000)));
+    assert_eq!(uncapped.fees(&first, 3), None);
+
+    second.apply(&mut txn);
+    assert_eq!(AttemptFees::read(&txn), second);
+    txn.try_sign(&[&payer], Hash::new_unique()).unwrap();
+    assert!(txn.verify().is_ok());
+
+    let no_fees = Transaction::new_with_payer(&instructions[2..3], Some(&payer.pubkey()));
+    let fees = AttemptFees::read(&no_fees);
+    assert_eq!(fees, AttemptFees::default());
+    assert_eq!(policy.fees(&fees, 1), Some(fees));
+
+    assert_eq!(compounding_schedule(3, 50), vec![150, 225]);
+    assert!(compounding_schedule(1, 50).is_empty());
+}
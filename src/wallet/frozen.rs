@@ -0,0 +1,131 @@
+//! Frozen token accounts.
+//!
+//! A mint whose freeze authority isn't revoked can freeze any holder's token
+//! account, the usual honeypot: the buys go through and every sell fails.
+//! The sells call [`check_sellable`] first, which fails with [`Frozen`]
+//! instead of sending a transaction bound to fail, and publishes a
+//! [`FrozenEvent`] the notifiers alert on, once per account.
+
+use std::{
+    collections::HashSet,
+    sync::{LazyLock, Mutex},
+};
+
+use anyhow::Result;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::{
+    extension::StateWithExtensions,
+    state::{Account, AccountState, Mint},
+};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::monitor::events::{publish, FrozenEvent, MonitorEvent};
+
+/// Accounts already alerted on
+static ALERTED: LazyLock<Mutex<HashSet<Pubkey>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("token account {account} of {mint} is frozen, it can't be sold")]
+pub struct Frozen {
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub account: Pubkey,
+    /// Freeze authority of the mint, `None` once revoked
+    pub freeze_authority: Option<Pubkey>,
+}
+
+/// Freeze authority of the mint account `data`
+pub fn freeze_authority(data: &[u8]) -> Result<Option<Pubkey>> {
+    let mint = StateWithExtensions::<Mint>::unpack(data)?;
+    Ok(mint.base.freeze_authority.into())
+}
+
+/// Whether the token account `data` is frozen
+pub fn is_frozen(data: &[u8]) -> Result<bool> {
+    let account = StateWithExtensions::<Account>::unpack(data)?;
+    Ok(account.base.state == AccountState::Frozen)
+}
+
+/// Fails with [`Frozen`] if `owner`'s token account of `mint` is frozen
+///
+/// Without a token account there's nothing to check, the sell fails on the
+/// balance.
+pub async fn check_sellable(client: &RpcClient, owner: &Pubkey, mint: &Pubkey) -> Result<()> {
+    let mint_account = client.get_account(mint).await?;
+    let freeze_authority = freeze_authority(&mint_account.data)?;
+    let account = get_associated_token_address_with_program_id(owner, mint, &mint_account.owner);
+    let Some(token_account) = client
+        .get_account_with_commitment(&account, client.commitment())
+        .await?
+        .value
+    else {
+        return Ok(());
+    };
+    if !is_frozen(&token_account.data)? {
+        if let Some(authority) = freeze_authority {
+            debug!("{} can still be frozen by {}", mint, authority);
+        }
+        return Ok(());
+    }
+
+    let frozen = Frozen {
+        mint: *mint,
+        owner: *owner,
+        account,
+        freeze_authority,
+    };
+    warn!("{}", frozen);
+    if ALERTED.lock().unwrap().insert(account) {
+        publish(MonitorEvent::Frozen(FrozenEvent {
+            mint: mint.to_string(),
+            owner: owner.to_string(),
+            account: account.to_string(),
+            freeze_authority: freeze_authority.map(|authority| authority.to_string()),
+        }));
+    }
+    Err(frozen.into())
+}
+
+#[test]
+fn test_frozen_accounts() {
+    use solana_sdk::{program_option::COption, program_pack::Pack};
+
+    let authority = Pubkey::new_unique();
+    let mint = spl_token::state::Mint {
+        mint_authority: COption::None,
+        supply: 1_000_000,
+        decimals: 6,
+        is_initialized: true,
+        freeze_authority: COption::Some(authority),
+    };
+    let mut data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint::pack(mint, &mut data).unwrap();
+    assert_eq!(freeze_authority(&data).unwrap(), Some(authority));
+
+    let mut account = spl_token::state::Account {
+        mint: Pubkey::new_unique(),
+        owner: Pubkey::new_unique(),
+        amount: 1000,
+        state: spl_token::state::AccountState::Initialized,
+        ..spl_token::state::Account::default()
+    };
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account::pack(account, &mut data).unwrap();
+    assert!(!is_frozen(&data).unwrap());
+
+    account.state = spl_token::state::AccountState::Frozen;
+    spl_token::state::Account::pack(account, &mut data).unwrap();
+    assert!(is_frozen(&data).unwrap());
+
+    let error = anyhow::Error::from(Frozen {
+        mint: Pubkey::new_unique(),
+        owner: Pubkey::new_unique(),
+        account: Pubkey::new_unique(),
+        freeze_authority: Some(authority),
+    });
+    // 调用方可以区分冻结和普通的交易失败
+    assert!(error.downcast_ref::<Frozen>().is_some());
+}
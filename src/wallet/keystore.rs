@@ -0,0 +1,91 @@
+//! Keypairs encrypted with a password.
+//!
+//! The secret key is sealed with AES-256-GCM-SIV, the key derived from the
+//! password with PBKDF2-HMAC-SHA256. Binary fields are base58 in the JSON
+//! file, written by `encrypt` (the `encrypt-key` subcommand).
+
+use aes_gcm_siv::{
+    aead::{Aead, KeyInit},
+    Aes256GcmSiv, Nonce,
+};
+use anyhow::{anyhow, Result};
+use hmac::Hmac;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use solana_sdk::{bs58, signature::Keypair};
+
+/// PBKDF2 rounds of new keystores
+pub const DEFAULT_ITERATIONS: u32 = 600_000;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// An encrypted keypair file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keystore {
+    pub iterations: u32,
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+fn cipher(password: &str, salt: &[u8], iterations: u32) -> Aes256GcmSiv {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, iterations, &mut key);
+    Aes256GcmSiv::new(&key.into())
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>> {
+    bs58::decode(value)
+        .into_vec()
+        .map_err(|e| anyhow!("keystore {} is not valid base58: {}", field, e))
+}
+
+/// Encrypts `keypair` with `password`
+pub fn encrypt(keypair: &Keypair, password: &str, iterations: u32) -> Result<Keystore> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = cipher(password, &salt, iterations)
+        .encrypt(Nonce::from_slice(&nonce), keypair.to_bytes().as_ref())
+        .map_err(|_| anyhow!("failed to encrypt keypair"))?;
+    Ok(Keystore {
+        iterations,
+        salt: bs58::encode(salt).into_string(),
+        nonce: bs58::encode(nonce).into_string(),
+        ciphertext: bs58::encode(ciphertext).into_string(),
+    })
+}
+
+/// Decrypts `keystore` with `password`
+pub fn decrypt(keystore: &Keystore, password: &str) -> Result<Keypair> {
+    let salt = decode("salt", &keystore.salt)?;
+    let nonce = decode("nonce", &keystore.nonce)?;
+    if nonce.len() != NONCE_LEN {
+        return Err(anyhow!("keystore nonce is {} bytes", nonce.len()));
+    }
+    let bytes = cipher(password, &salt, keystore.iterations)
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            decode("ciphertext", &keystore.ciphertext)?.as_ref(),
+        )
+        .map_err(|_| anyhow!("wrong password or corrupted keystore"))?;
+    Keypair::from_bytes(&bytes).map_err(|e| anyhow!("keystore is not a valid keypair: {}", e))
+}
+
+#[test]
+fn test_keystore_round_trip() {
+    use solana_sdk::signer::Signer;
+
+    let keypair = Keypair::new();
+    let keystore = encrypt(&keypair, "hunter2", 1000).unwrap();
+    let json = serde_json::to_string(&keystore).unwrap();
+    let keystore: Keystore = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        decrypt(&keystore, "hunter2").unwrap().pubkey(),
+        keypair.pubkey()
+    );
+    assert!(decrypt(&keystore, "hunter3").is_err());
+}
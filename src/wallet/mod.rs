@@ -0,0 +1,357 @@
+//! Trading wallets.
+//!
+//! Besides the configured `pk`, more keypairs can be loaded from the
+//! environment. Buys of a mint are spread across the wallets, and later
+//! trades of that mint go to the wallet holding it, so the strategies don't
+//! contend on one wallet nor trade all from the same address.
+//!
+//! - `WALLET_KEYS`: comma separated base58 secret keys
+//! - `WALLET_KEYPAIR_FILES`: comma separated `solana-keygen` keypair files
+//! - `WALLET_KEYSTORES`: comma separated encrypted keystores, see `keystore`
+//! - `WALLET_KEYSTORE_PASSWORD`: password of the keystores
+//! - `WALLET_SELECTION`: `round_robin` (default) or `shard`, sharding picks
+//!   the wallet of a mint from its address
+//! - `WALLET_BALANCE_INTERVAL_MS`: balance refresh interval, default 30000
+
+pub mod frozen;
+pub mod keystore;
+pub mod wsol;
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    env, fs,
+    hash::{Hash, Hasher},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    bs58,
+    instruction::Instruction,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair},
+    signer::Signer,
+};
+use spl_associated_token_account::{
+    get_associated_token_address, get_associated_token_address_with_program_id,
+    instruction::{create_associated_token_account, create_associated_token_account_idempotent},
+};
+use tracing::{error, info};
+
+use crate::{config::bot_config, portfolio::portfolio, strategy::parse_env};
+
+const DEFAULT_BALANCE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How the wallet of a mint's first buy is picked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Selection {
+    /// The next wallet in turn
+    #[default]
+    RoundRobin,
+    /// Always the same wallet for a mint
+    Shard,
+}
+
+impl FromStr for Selection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "round_robin" => Ok(Self::RoundRobin),
+            "shard" => Ok(Self::Shard),
+            _ => Err(anyhow!("unknown wallet selection {:?}", s)),
+        }
+    }
+}
+
+/// Last known balances of a wallet, in raw units
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Balances {
+    pub sol: u64,
+    pub wsol: u64,
+    /// Balances of the tracked mints
+    pub tokens: HashMap<Pubkey, u64>,
+}
+
+/// The trading wallets, the configured `pk` first
+pub struct Wallets {
+    keypairs: Vec<Arc<Keypair>>,
+    selection: Selection,
+    next: AtomicUsize,
+    /// mint -> 持有它的钱包下标
+    holders: RwLock<HashMap<Pubkey, usize>>,
+    balances: RwLock<HashMap<Pubkey, Balances>>,
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+fn parse_secret_key(key: &str) -> Result<Keypair> {
+    let bytes = bs58::decode(key)
+        .into_vec()
+        .map_err(|e| anyhow!("wallet key is not valid base58: {}", e))?;
+    Keypair::from_bytes(&bytes).map_err(|e| anyhow!("wallet key is not a valid keypair: {}", e))
+}
+
+fn read_keystore(path: &str, password: &str) -> Result<Keypair> {
+    let text = fs::read_to_string(path).map_err(|e| anyhow!("failed to read {}: {}", path, e))?;
+    let keystore =
+        serde_json::from_str(&text).map_err(|e| anyhow!("invalid keystore {}: {}", path, e))?;
+    keystore::decrypt(&keystore, password).map_err(|e| anyhow!("{}: {}", path, e))
+}
+
+impl Wallets {
+    /// Wallets of `keypairs`, the first one being the main wallet
+    ///
+    /// Duplicate keypairs are dropped.
+    pub fn new(keypairs: Vec<Keypair>, selection: Selection) -> Result<Self> {
+        let mut seen = HashSet::new();
+        let keypairs: Vec<Arc<Keypair>> = keypairs
+            .into_iter()
+            .filter(|keypair| seen.insert(keypair.pubkey()))
+            .map(Arc::new)
+            .collect();
+        if keypairs.is_empty() {
+            return Err(anyhow!("no wallet configured"));
+        }
+        Ok(Self {
+            keypairs,
+            selection,
+            next: AtomicUsize::new(0),
+            holders: RwLock::new(HashMap::new()),
+            balances: RwLock::new(HashMap::new()),
+        })
+    }
+
+    /// `main` followed by the wallets of the environment
+    pub fn from_env(main: Keypair) -> Result<Self> {
+        dotenv::dotenv().ok();
+        let mut keypairs = vec![main];
+        for key in split_list(&env::var("WALLET_KEYS").unwrap_or_default()) {
+            keypairs.push(parse_secret_key(key)?);
+        }
+        for path in split_list(&env::var("WALLET_KEYPAIR_FILES").unwrap_or_default()) {
+            let keypair =
+                read_keypair_file(path).map_err(|e| anyhow!("failed to read {}: {}", path, e))?;
+            keypairs.push(keypair);
+        }
+        let keystores = env::var("WALLET_KEYSTORES").unwrap_or_default();
+        if split_list(&keystores).next().is_some() {
+            let password = env::var("WALLET_KEYSTORE_PASSWORD")
+                .map_err(|_| anyhow!("WALLET_KEYSTORES needs WALLET_KEYSTORE_PASSWORD"))?;
+            for path in split_list(&keystores) {
+                keypairs.push(read_keystore(path, &password)?);
+            }
+        }
+        let selection = parse_env("WALLET_SELECTION")?.unwrap_or_default();
+        let wallets = Self::new(keypairs, selection)?;
+        info!("{} wallets, {:?}", wallets.len(), selection);
+        Ok(wallets)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keypairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keypairs.is_empty()
+    }
+
+    /// The configured `pk`
+    pub fn main(&self) -> Arc<Keypair> {
+        self.keypairs[0].clone()
+    }
+
+    pub fn keypairs(&self) -> &[Arc<Keypair>] {
+        &self.keypairs
+    }
+
+    fn shard(&self, mint: &Pubkey) -> usize {
+        let mut hasher = DefaultHasher::new();
+        mint.hash(&mut hasher);
+        (hasher.finish() % self.keypairs.len() as u64) as usize
+    }
+
+    /// Wallet to buy `mint` with, the one already holding it if any
+    pub fn for_buy(&self, mint: &Pubkey) -> Arc<Keypair> {
+        let mut holders = self.holders.write().unwrap();
+        let index = *holders.entry(*mint).or_insert_with(|| {
+            self.holder_by_balance(mint)
+                .unwrap_or_else(|| match self.selection {
+                    Selection::RoundRobin => {
+                        self.next.fetch_add(1, Ordering::Relaxed) % self.keypairs.len()
+                    }
+                    Selection::Shard => self.shard(mint),
+                })
+        });
+        self.keypairs[index].clone()
+    }
+
+    /// Wallet holding `mint`, to sell from
+    ///
+    /// Falls back to the last known balances, e.g. after a restart, then to
+    /// the wallet a buy would pick when sharding, or the main wallet.
+    pub fn for_sell(&self, mint: &Pubkey) -> Arc<Keypair> {
+        let index = self
+            .holders
+            .read()
+            .unwrap()
+            .get(mint)
+            .copied()
+            .or_else(|| self.holder_by_balance(mint))
+            .unwrap_or(match self.selection {
+                Selection::RoundRobin => 0,
+                Selection::Shard => self.shard(mint),
+            });
+        self.keypairs[index].clone()
+    }
+
+    fn holder_by_balance(&self, mint: &Pubkey) -> Option<usize> {
+        let balances = self.balances.read().unwrap();
+        self.keypairs.iter().position(|keypair| {
+            balances
+                .get(&keypair.pubkey())
+                .and_then(|b| b.tokens.get(mint))
+                .is_some_and(|amount| *amount > 0)
+        })
+    }
+
+    /// Last known balances of `wallet`
+    pub fn balances(&self, wallet: &Pubkey) -> Option<Balances> {
+        self.balances.read().unwrap().get(wallet).cloned()
+    }
+
+    /// Reads the SOL, WSOL and `mints` balances of every wallet
+    pub async fn refresh_balances(&self, client: Arc<RpcClient>, mints: &[Pubkey]) -> Result<()> {
+        let wsol = spl_token::native_mint::ID;
+        for keypair in &self.keypairs {
+            let owner = keypair.pubkey();
+            let mut pubkeys = vec![owner, get_associated_token_address(&owner, &wsol)];
+            pubkeys.extend(
+                mints
+                    .iter()
+                    .map(|mint| get_associated_token_address(&owner, mint)),
+            );
+            // 按单次请求的账户上限分批
+            let mut accounts = Vec::with_capacity(pubkeys.len());
+            for chunk in pubkeys.chunks(100) {
+                accounts.extend(client.get_multiple_accounts(chunk).await?);
+            }
+            let token_amount = |index: usize| {
+                accounts[index]
+                    .as_ref()
+                    .and_then(|account| {
+                        spl_token::state::Account::unpack_from_slice(
+                            account.data.get(..spl_token::state::Account::LEN)?,
+                        )
+                        .ok()
+                    })
+                    .map_or(0, |account| account.amount)
+            };
+            let balances = Balances {
+                sol: accounts[0].as_ref().map_or(0, |account| account.lamports),
+                wsol: token_amount(1),
+                tokens: mints
+                    .iter()
+                    .enumerate()
+                    .map(|(i, mint)| (*mint, token_amount(i + 2)))
+                    .collect(),
+            };
+            self.balances.write().unwrap().insert(owner, balances);
+        }
+        Ok(())
+    }
+}
+
+/// Refreshes the balances of the wallets and the portfolio's open positions,
+/// forever
+pub async fn track_balances(wallets: Arc<Wallets>, client: Arc<RpcClient>) {
+    let interval = match parse_env::<u64>("WALLET_BALANCE_INTERVAL_MS") {
+        Ok(ms) => ms.map_or(DEFAULT_BALANCE_INTERVAL, Duration::from_millis),
+        Err(e) => {
+            error!("{:?}", e);
+            DEFAULT_BALANCE_INTERVAL
+        }
+    };
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        let mints: Vec<Pubkey> = portfolio()
+            .open_positions()
+            .iter()
+            .filter_map(|position| position.mint.parse().ok())
+            .collect();
+        if let Err(e) = wallets.refresh_balances(client.clone(), &mints).await {
+            error!("failed to refresh wallet balances {:?}", e);
+        }
+    }
+}
+
+/// Instruction creating `owner`'s token account for `mint`
+///
+/// The idempotent create, which succeeds if the account exists, saves a
+/// lookup and can't race another transaction creating it. With
+/// `ata_precheck` the account is looked up instead, and `None` returned if it
+/// exists.
+pub async fn create_ata(
+    client: &RpcClient,
+    owner: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Option<Instruction> {
+    if !bot_config().ata_precheck {
+        return Some(create_associated_token_account_idempotent(
+            owner,
+            owner,
+            mint,
+            token_program,
+        ));
+    }
+    let ata = get_associated_token_address_with_program_id(owner, mint, token_program);
+    match client.get_account(&ata).await {
+        Ok(_) => None,
+        Err(_) => Some(create_associated_token_account(
+            owner,
+            owner,
+            mint,
+            token_program,
+        )),
+    }
+}
+
+#[test]
+fn test_wallet_selection() {
+    let keypairs = || (0..3).map(|_| Keypair::new()).collect::<Vec<_>>();
+    let wallets = Wallets::new(keypairs(), Selection::RoundRobin).unwrap();
+    let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let first = wallets.for_buy(&a).pubkey();
+    assert_eq!(first, wallets.main().pubkey());
+    assert_ne!(wallets.for_buy(&b).pubkey(), first);
+    // 同一个mint一直用持有它的钱包
+    assert_eq!(wallets.for_buy(&a).pubkey(), first);
+    assert_eq!(wallets.for_sell(&a).pubkey(), first);
+
+    let wallets = Wallets::new(keypairs(), Selection::Shard).unwrap();
+    assert_eq!(wallets.for_sell(&a).pubkey(), wallets.for_buy(&a).pubkey());
+
+    let keypair = Keypair::new();
+    let duplicate = Keypair::from_bytes(&keypair.to_bytes()).unwrap();
+    assert_eq!(
+        Wallets::new(vec![keypair, duplicate], Selection::Shard)
+            .unwrap()
+            .len(),
+        1
+    );
+    assert!(Wallets::new(vec![], Selection::Shard).is_err());
+    assert!("random".parse::<Selection>().is_err());
+}
@@ -0,0 +1,206 @@
+//! Wrapped SOL accounts of the swaps from and to SOL.
+//!
+//! By default every swap wraps its SOL in a temporary account, created with a
+//! random seed and closed again at the end of the transaction. With
+//! `WSOL_PERSISTENT=true` swaps use the wallet's WSOL ATA instead and leave it
+//! open: buys top it up, sells leave their proceeds wrapped. Once the ATA is
+//! known to exist this saves the create and close instructions of every swap.
+//! [`close`] unwraps it, e.g. on shutdown.
+
+use std::{
+    collections::HashSet,
+    sync::{LazyLock, Mutex, OnceLock},
+};
+
+use anyhow::Result;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::Instruction,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    system_instruction,
+};
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+};
+use spl_token::{native_mint, state::Account};
+use tracing::{error, info};
+
+use crate::{
+    raydium::tx::{build_transaction, send_txn},
+    rpc::retry::with_retry,
+    strategy::parse_env,
+    tx::{blockhash::recent_blockhash, simulate::OutputAccount},
+};
+
+static PERSISTENT: OnceLock<bool> = OnceLock::new();
+
+/// 已确认存在的 WSOL ATA 的所有者
+static OPEN_ATAS: LazyLock<Mutex<HashSet<Pubkey>>> = LazyLock::new(Default::default);
+
+/// Whether swaps keep the wallet's WSOL ATA open, `WSOL_PERSISTENT`
+pub fn persistent() -> bool {
+    *PERSISTENT.get_or_init(|| {
+        parse_env("WSOL_PERSISTENT")
+            .unwrap_or_else(|e| {
+                error!("{:?}", e);
+                None
+            })
+            .unwrap_or(false)
+    })
+}
+
+/// WSOL account of a swap, with the instructions around the swap
+#[derive(Debug, Clone)]
+pub struct WsolAccount {
+    pub address: Pubkey,
+    /// Creates and funds the account, before the swap
+    pub setup: Vec<Instruction>,
+    /// Closes the account after the swap, empty for the persistent ATA
+    pub cleanup: Vec<Instruction>,
+}
+
+impl WsolAccount {
+    /// Where the SOL of a swap to SOL ends up
+    pub fn output(&self, owner: &Pubkey) -> OutputAccount {
+        if self.cleanup.is_empty() {
+            OutputAccount::Token(self.address)
+        } else {
+            OutputAccount::Lamports(*owner)
+        }
+    }
+}
+
+/// Moves `lamports` into the WSOL `account` and syncs its token balance
+pub fn fund(owner: &Pubkey, account: &Pubkey, lamports: u64) -> Result<Vec<Instruction>> {
+    Ok(vec![
+        system_instruction::transfer(owner, account, lamports),
+        spl_token::instruction::sync_native(&spl_token::ID, account)?,
+    ])
+}
+
+/// Closes the WSOL `account`, returning its balance to `owner` as SOL
+pub fn close_instruction(owner: &Pubkey, account: &Pubkey) -> Result<Instruction> {
+    Ok(spl_token::instruction::close_account(
+        &spl_token::ID,
+        account,
+        owner,
+        owner,
+        &[owner],
+    )?)
+}
+
+/// Temporary account holding `lamports` on top of its `rent`, closed after
+/// the swap
+pub fn temporary(owner: &Pubkey, rent: u64, lamports: u64) -> Result<WsolAccount> {
+    let seed = &Keypair::new().pubkey().to_string()[..32];
+    let address = Pubkey::create_with_seed(owner, seed, &spl_token::ID)?;
+    let setup = vec![
+        system_instruction::create_account_with_seed(
+            owner,
+            &address,
+            owner,
+            seed,
+            rent + lamports,
+            Account::LEN as u64,
+            &spl_token::ID,
+        ),
+        spl_token::instruction::initialize_account(
+            &spl_token::ID,
+            &address,
+            &native_mint::ID,
+            owner,
+        )?,
+    ];
+    Ok(WsolAccount {
+        address,
+        setup,
+        cleanup: vec![close_instruction(owner, &address)?],
+    })
+}
+
+/// The wallet's WSOL ATA topped up with `lamports`, created unless `exists`
+pub fn ata(owner: &Pubkey, lamports: u64, exists: bool) -> Result<WsolAccount> {
+    let address = get_associated_token_address(owner, &native_mint::ID);
+    let mut setup = vec![];
+    if !exists {
+        setup.push(create_associated_token_account_idempotent(
+            owner,
+            owner,
+            &native_mint::ID,
+            &spl_token::ID,
+        ));
+    }
+    if lamports > 0 {
+        setup.extend(fund(owner, &address, lamports)?);
+    }
+    Ok(WsolAccount {
+        address,
+        setup,
+        cleanup: vec![],
+    })
+}
+
+/// Whether `owner`'s WSOL ATA exists, remembered once it does
+async fn ata_exists(client: &RpcClient, owner: &Pubkey) -> Result<bool> {
+    if OPEN_ATAS.lock().unwrap().contains(owner) {
+        return Ok(true);
+    }
+    let address = get_associated_token_address(owner, &native_mint::ID);
+    let fetch = || client.get_account_with_commitment(&address, client.commitment());
+    let exists = with_retry(fetch).await?.value.is_some();
+    if exists {
+        OPEN_ATAS.lock().unwrap().insert(*owner);
+    }
+    Ok(exists)
+}
+
+/// WSOL account for a swap spending `lamports` of SOL, 0 for a swap to SOL
+pub async fn prepare(client: &RpcClient, owner: &Pubkey, lamports: u64) -> Result<WsolAccount> {
+    if persistent() {
+        return ata(owner, lamports, ata_exists(client, owner).await?);
+    }
+    let rent = client
+        .get_minimum_balance_for_rent_exemption(Account::LEN)
+        .await?;
+    temporary(owner, rent, lamports)
+}
+
+/// Closes `payer`'s WSOL ATA, unwrapping its balance to SOL
+///
+/// `None` if the wallet has no WSOL ATA.
+pub async fn close(client: &RpcClient, payer: &Keypair) -> Result<Option<Signature>> {
+    let owner = payer.pubkey();
+    OPEN_ATAS.lock().unwrap().remove(&owner);
+    let address = get_associated_token_address(&owner, &native_mint::ID);
+    let fetch = || client.get_account_with_commitment(&address, client.commitment());
+    if with_retry(fetch).await?.value.is_none() {
+        return Ok(None);
+    }
+    let instruction = close_instruction(&owner, &address)?;
+    let txn = build_transaction(payer, &[instruction], recent_blockhash(client).await?)?;
+    let sig = send_txn(client, &txn, false).await?;
+    info!("closed WSOL account {} signature: {:?}", address, sig);
+    Ok(Some(sig))
+}
+
+#[test]
+fn test_wsol_accounts() {
+    let owner = Pubkey::new_unique();
+
+    let buy = temporary(&owner, 2_039_280, 1_000).unwrap();
+    assert_eq!(buy.setup.len(), 2);
+    assert_eq!(buy.cleanup.len(), 1);
+    assert_eq!(buy.output(&owner), OutputAccount::Lamports(owner));
+    assert_ne!(temporary(&owner, 0, 0).unwrap().address, buy.address);
+
+    let address = get_associated_token_address(&owner, &native_mint::ID);
+    // 首次使用时创建，之后只需转入并同步
+    assert_eq!(ata(&owner, 1_000, false).unwrap().setup.len(), 3);
+    assert_eq!(ata(&owner, 1_000, true).unwrap().setup.len(), 2);
+    let sell = ata(&owner, 0, true).unwrap();
+    assert!(sell.setup.is_empty() && sell.cleanup.is_empty());
+    assert_eq!(sell.output(&owner), OutputAccount::Token(address));
+}